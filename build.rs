@@ -0,0 +1,26 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // The gRPC service is generated from the published .proto only when the feature is enabled
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/semtools.proto");
+        tonic_build::compile_protos("proto/semtools.proto")?;
+    }
+    // Link flags for loading the library as a Node.js addon
+    #[cfg(feature = "node")]
+    napi_build::setup();
+    // The C header is generated from the `extern "C"` functions in src/ffi.rs into OUT_DIR;
+    // a test checks that the copy in include/ matches it
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR")?;
+        let out_dir = std::env::var("OUT_DIR")?;
+        cbindgen::generate_with_config(
+            &crate_dir,
+            cbindgen::Config::from_file(format!("{crate_dir}/cbindgen.toml"))?,
+        )?
+        .write_to_file(format!("{out_dir}/semtools.h"));
+    }
+    Ok(())
+}
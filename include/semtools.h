@@ -0,0 +1,94 @@
+/* C API of semtools, generated by cbindgen from src/ffi.rs into OUT_DIR when building with `--features ffi`. Do not edit; copy the generated header over this one. */
+
+#ifndef SEMTOOLS_H
+#define SEMTOOLS_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+// Search options; start from `semtools_search_options_default`
+typedef struct SemtoolsSearchOptions {
+  // Lines of context before and after each match
+  size_t n_lines;
+  // Number of results
+  size_t top_k;
+  // Return every match under this distance instead of the top `top_k` (NaN for none)
+  double max_distance;
+  bool ignore_case;
+  // Workspace to search through, or NULL
+  const char *workspace;
+} SemtoolsSearchOptions;
+
+typedef struct SemtoolsSearchResult {
+  char *filename;
+  size_t start_line_number;
+  size_t end_line_number;
+  size_t match_line_number;
+  double distance;
+  double score;
+  // The matching line with its context
+  char *content;
+} SemtoolsSearchResult;
+
+typedef struct SemtoolsParseResult {
+  char *input_path;
+  // Markdown output of the parse
+  char *output_path;
+  bool was_cached;
+  // Backend that produced the output, or NULL for readable files passed through as-is
+  char *backend;
+} SemtoolsParseResult;
+
+// The outcome of a call: an array of results, or an error
+typedef struct SemtoolsResults {
+  // Search results, best first (NULL for parse calls and errors)
+  struct SemtoolsSearchResult *search_results;
+  // Parse results, in input order (NULL for search calls and errors)
+  struct SemtoolsParseResult *parse_results;
+  // Number of entries in the array that is set
+  size_t len;
+  // Error message, or NULL on success
+  char *error;
+} SemtoolsResults;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// The options `search` uses by default
+struct SemtoolsSearchOptions semtools_search_options_default(void);
+
+// Search `files` for lines semantically similar to `query`. `options` may be NULL for the
+// defaults. The embedding model is loaded on the first call and kept for later ones.
+//
+// # Safety
+//
+// `query` must be a NUL-terminated string, `files` must point to `n_files` of them, and
+// `options`, if not NULL, must point to valid options.
+struct SemtoolsResults *semtools_search(const char *query,
+                                        const char *const *files,
+                                        size_t n_files,
+                                        const struct SemtoolsSearchOptions *options);
+
+// Parse `files` to markdown with `backend` (a `--backend` chain such as "pdf-text,llama-parse"),
+// or the config file's chain when it is NULL.
+//
+// # Safety
+//
+// `files` must point to `n_files` NUL-terminated strings, and `backend` must be NULL or one.
+struct SemtoolsResults *semtools_parse(const char *const *files,
+                                       size_t n_files,
+                                       const char *backend);
+
+// Release what `semtools_search` or `semtools_parse` returned, including every string in it
+//
+// # Safety
+//
+// `results` must be NULL or a pointer returned by this library that was not freed yet.
+void semtools_free_results(struct SemtoolsResults *results);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SEMTOOLS_H */
@@ -0,0 +1,21 @@
+/*
+  In-process bindings to semtools search and parse.
+  The addon is built with `cargo build --release --features node` and copied to dist/semtools.node.
+*/
+
+const { existsSync } = require('node:fs');
+const { join } = require('node:path');
+
+const addonPath = join(__dirname, 'dist', 'semtools.node');
+
+if (!existsSync(addonPath)) {
+  throw new Error(
+    '@llamaindex/semtools: the Node.js addon was not found at dist/semtools.node. ' +
+    'Build it with `cargo build --release --features node` and copy the library from target/release ' +
+    '(libsemtools.so, libsemtools.dylib or semtools.dll) to dist/semtools.node.'
+  );
+}
+
+const { search, parse } = require(addonPath);
+
+module.exports = { search, parse };
@@ -0,0 +1,151 @@
+//! In-process search and parse, shared by the language bindings (`node`, `ffi`). Callers get
+//! the same results as `search --json` and `parse --json` without spawning the binary.
+//!
+//! Errors are `anyhow::Error`s; the failures worth handling carry a typed error to downcast to,
+//! such as `SearchError`, `WorkspaceError` or `JobError`.
+use anyhow::Result;
+use model2vec_rs::model::StaticModel;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use crate::cmds::search::{ranked_line_to_json, search_result_to_json};
+use crate::json_mode::{ParseResultJSON, SearchResultJSON};
+use crate::parse::local::parse_backend_chain;
+use crate::search::error::SearchError;
+use crate::search::model::{ModelSource, resolve_embed_preset};
+use crate::search::{
+    DEFAULT_NEGATIVE_WEIGHT, QueryCombination, SearchConfig, search_files, search_with_workspace,
+};
+use crate::workspace::Workspace;
+use crate::workspace::context::ResultContext;
+use crate::{LlamaParseBackend, SemtoolsConfig};
+
+/// Embedding models by name, loaded on first use and kept for the life of the process
+static MODELS: LazyLock<Mutex<HashMap<String, Arc<StaticModel>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn load_model(source: &ModelSource) -> Result<Arc<StaticModel>> {
+    let mut models = MODELS.lock().unwrap();
+    if let Some(model) = models.get(&source.name) {
+        return Ok(model.clone());
+    }
+    let model = Arc::new(source.load(false)?);
+    models.insert(source.name.clone(), model.clone());
+    Ok(model)
+}
+
+/// Options of an in-process search, defaulting like the `search` flags
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub n_lines: usize,
+    pub top_k: usize,
+    pub max_distance: Option<f64>,
+    pub ignore_case: bool,
+    /// Workspace to search through (defaults to `SEMTOOLS_WORKSPACE`, if set)
+    pub workspace: Option<String>,
+    pub embed_preset: Option<String>,
+    /// Config file (defaults to `~/.semtools_config.json`)
+    pub config: Option<String>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            n_lines: 3,
+            top_k: 3,
+            max_distance: None,
+            ignore_case: false,
+            workspace: None,
+            embed_preset: None,
+            config: None,
+        }
+    }
+}
+
+/// Search `files` for lines semantically similar to `query`, best first
+pub async fn search(
+    query: String,
+    files: Vec<String>,
+    options: SearchOptions,
+) -> Result<Vec<SearchResultJSON>> {
+    if files.is_empty() {
+        return Err(SearchError::NoInput("No files provided".to_string()).into());
+    }
+    let workspace_name = options.workspace.as_deref();
+    let embed_preset = resolve_embed_preset(options.embed_preset.as_deref(), workspace_name)?;
+    let source = ModelSource::from_config_file(options.config.as_deref(), embed_preset)?;
+    let model = tokio::task::spawn_blocking(move || load_model(&source)).await??;
+
+    let config = SearchConfig {
+        n_lines: options.n_lines,
+        top_k: options.top_k,
+        max_distance: options.max_distance,
+        ignore_case: options.ignore_case,
+        recency_boost: 0.0,
+        mmr_lambda: None,
+        negative_embeddings: Vec::new(),
+        negative_weight: DEFAULT_NEGATIVE_WEIGHT,
+        extra_query_embeddings: Vec::new(),
+        query_combination: QueryCombination::All,
+        tag_filters: Vec::new(),
+        metadata_filters: Vec::new(),
+        boosts: Vec::new(),
+    };
+    let query = if options.ignore_case {
+        query.to_lowercase()
+    } else {
+        query
+    };
+
+    if Workspace::active(workspace_name).is_ok() {
+        let ranked_lines =
+            search_with_workspace(&files, &query, &model, &config, workspace_name).await?;
+        let context = ResultContext::new(workspace_name);
+        context.prefetch(ranked_lines.iter().map(|line| line.path.as_str()));
+        return Ok(ranked_lines
+            .iter()
+            .map(|ranked_line| ranked_line_to_json(ranked_line, options.n_lines, &context))
+            .collect());
+    }
+
+    // Embedding is CPU-bound: keep it off the runtime's worker threads
+    tokio::task::spawn_blocking(move || {
+        Ok(search_files(&files, &query, &model, &config)?
+            .iter()
+            .map(search_result_to_json)
+            .collect())
+    })
+    .await?
+}
+
+/// Parse documents to markdown with `backend` (a `--backend` chain), or the config file's
+/// chain when it is `None`
+pub async fn parse(
+    files: Vec<String>,
+    backend: Option<String>,
+    config: Option<String>,
+) -> Result<Vec<ParseResultJSON>> {
+    let config_path = config.unwrap_or_else(SemtoolsConfig::default_config_path);
+    let parse_config = SemtoolsConfig::from_config_file(&config_path)?
+        .parse
+        .unwrap_or_default();
+
+    // Resolve backend chain with priority: argument > config file > llama-parse
+    let chain = match backend {
+        Some(b) => parse_backend_chain(&[b]),
+        None => parse_backend_chain(&parse_config.backends),
+    }?;
+
+    let backend = LlamaParseBackend::new(parse_config, false)?.with_chain(chain);
+    let results = backend.parse_files(files).await?;
+    Ok(results
+        .into_iter()
+        .map(|r| ParseResultJSON {
+            input_path: r.input_path,
+            output_path: r.output_path,
+            was_cached: r.was_cached,
+            backend: r.backend,
+            assets: r.assets,
+        })
+        .collect())
+}
@@ -0,0 +1,269 @@
+//! Archives (`.zip`, `.tar`, `.tar.gz`, `.tgz`) given as inputs are expanded into
+//! `$XDG_CACHE_HOME/semtools/archives`, and their files are searched, parsed and indexed like
+//! any other. A file inside an archive is named by a pseudo-path such as
+//! `dump.zip!reports/q3.pdf`, which is what results cite and workspaces store; it is read from
+//! where the archive was extracted to (see `local_path`).
+//!
+//! An archive is extracted again only when its size or modification time changes. Entries
+//! that would land outside the extraction directory, links and `__MACOSX` metadata are skipped.
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// Separates an archive from the file inside it in a pseudo-path
+pub const MEMBER_SEPARATOR: char = '!';
+
+/// Whether `path` names an archive whose files are expanded
+pub fn is_archive(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    [".zip", ".tar", ".tar.gz", ".tgz"]
+        .iter()
+        .any(|extension| lower.ends_with(extension))
+}
+
+/// The pseudo-path of `member` inside `archive`
+pub fn member_path(archive: &str, member: &str) -> String {
+    format!("{archive}{MEMBER_SEPARATOR}{member}")
+}
+
+/// The archive and member of a pseudo-path, or `None` for a plain path
+pub fn split_member(path: &str) -> Option<(&str, &str)> {
+    path.match_indices(MEMBER_SEPARATOR)
+        .map(|(i, _)| (&path[..i], &path[i + 1..]))
+        .find(|(archive, member)| is_archive(archive) && !member.is_empty())
+}
+
+/// Directory under `root` that `archive` is extracted to. Its name is the archive's absolute
+/// path with every byte but `[A-Za-z0-9._-]` percent-encoded, so each archive gets its own.
+fn extraction_dir(root: &Path, archive: &str) -> Result<PathBuf> {
+    let absolute = std::path::absolute(archive)?;
+    let mut name = String::new();
+    for byte in absolute.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"._-".contains(&byte) {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    Ok(root.join(name))
+}
+
+fn archives_root() -> Result<PathBuf> {
+    crate::paths::cache_path("archives")
+}
+
+/// Where to read `path` from: a file inside an archive is read from where the archive was
+/// extracted to, any other path as it is
+pub fn local_path(path: &str) -> PathBuf {
+    let extracted = |(archive, member): (&str, &str)| {
+        Some(
+            extraction_dir(&archives_root().ok()?, archive)
+                .ok()?
+                .join(member),
+        )
+    };
+    split_member(path)
+        .and_then(extracted)
+        .unwrap_or_else(|| PathBuf::from(path))
+}
+
+/// Extract `archive` unless its current version already is, and list its files as
+/// (extracted path, pseudo-path), sorted by pseudo-path
+#[cfg(feature = "fetch")]
+pub fn expand(archive: &str) -> Result<Vec<(String, String)>> {
+    expand_into(&archives_root()?, archive)
+}
+
+#[cfg(feature = "fetch")]
+fn expand_into(root: &Path, archive: &str) -> Result<Vec<(String, String)>> {
+    use std::fs;
+    use std::time::UNIX_EPOCH;
+
+    let dir = extraction_dir(root, archive)?;
+    let metadata = fs::metadata(archive)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let version = format!("{} {mtime}", metadata.len());
+    let stamp = root.join(format!(
+        "{}.stamp",
+        dir.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    if fs::read_to_string(&stamp).ok().as_deref() != Some(version.as_str()) {
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir)?;
+        let lower = archive.to_lowercase();
+        if lower.ends_with(".zip") {
+            extract_zip(archive, &dir)?;
+        } else {
+            extract_tar(archive, &dir, !lower.ends_with(".tar"))?;
+        }
+        fs::write(&stamp, version)?;
+    }
+
+    let mut members = Vec::new();
+    list_files(&dir, &dir, &mut members)?;
+    members.sort();
+    Ok(members
+        .into_iter()
+        .map(|member| {
+            let extracted = dir.join(&member).to_string_lossy().to_string();
+            (extracted, member_path(archive, &member))
+        })
+        .collect())
+}
+
+/// Whether an entry is metadata a macOS archiver adds rather than a file of the archive
+#[cfg(feature = "fetch")]
+fn is_junk(name: &Path) -> bool {
+    name.components()
+        .next()
+        .is_some_and(|first| first.as_os_str() == "__MACOSX")
+}
+
+#[cfg(feature = "fetch")]
+fn extract_zip(archive: &str, dir: &Path) -> Result<()> {
+    use std::fs::{self, File};
+
+    let mut zip = zip::ZipArchive::new(File::open(archive)?)?;
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        // `enclosed_name` is `None` for names escaping the directory, like `../x` or `/x`
+        let Some(name) = entry.enclosed_name().filter(|name| !is_junk(name)) else {
+            continue;
+        };
+        if entry.is_dir() {
+            continue;
+        }
+        let target = dir.join(name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        std::io::copy(&mut entry, &mut File::create(target)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(feature = "fetch")]
+fn extract_tar(archive: &str, dir: &Path, gzipped: bool) -> Result<()> {
+    use std::fs::File;
+    use std::io::Read;
+
+    let file = File::open(archive)?;
+    let reader: Box<dyn Read> = if gzipped {
+        Box::new(flate2::read::GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    let mut tar = tar::Archive::new(reader);
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() || is_junk(&entry.path()?) {
+            continue;
+        }
+        // Refuses, and skips, paths that would land outside `dir`
+        entry.unpack_in(dir)?;
+    }
+    Ok(())
+}
+
+/// Paths of the files under `dir`, relative to `root` and `/`-separated
+#[cfg(feature = "fetch")]
+fn list_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            list_files(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let parts: Vec<String> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect();
+            files.push(parts.join("/"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_member() {
+        assert_eq!(
+            split_member("dumps/q3.zip!reports/a.pdf"),
+            Some(("dumps/q3.zip", "reports/a.pdf"))
+        );
+        assert_eq!(
+            split_member("wow!.tgz!notes.md"),
+            Some(("wow!.tgz", "notes.md"))
+        );
+        assert_eq!(split_member("hello!.md"), None);
+        assert_eq!(split_member("dump.zip!"), None);
+        assert!(is_archive("Backup.TAR.GZ"));
+        assert!(!is_archive("notes.gz"));
+    }
+
+    #[test]
+    fn test_extraction_dir_is_unique_per_archive() {
+        let root = Path::new("/cache");
+        let a = extraction_dir(root, "/data/a_b/c.zip").unwrap();
+        let b = extraction_dir(root, "/data/a/b_c.zip").unwrap();
+        assert_ne!(a, b);
+        assert_eq!(a, root.join("%2Fdata%2Fa_b%2Fc.zip"));
+    }
+
+    #[cfg(feature = "fetch")]
+    #[test]
+    fn test_expand_zip_and_tar_gz() {
+        use std::io::Write;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let root = temp_dir.path().join("archives");
+
+        let zip_path = temp_dir.path().join("dump.zip");
+        let mut writer = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+        for (name, content) in [
+            ("reports/q3.md", "revenue grew"),
+            ("../escape.md", "outside"),
+            ("__MACOSX/._q3.md", "junk"),
+        ] {
+            writer
+                .start_file(name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let zip_path = zip_path.to_string_lossy().to_string();
+        let members = expand_into(&root, &zip_path).unwrap();
+        assert_eq!(members.len(), 1);
+        let (extracted, pseudo) = &members[0];
+        assert_eq!(pseudo, &format!("{zip_path}!reports/q3.md"));
+        assert_eq!(std::fs::read_to_string(extracted).unwrap(), "revenue grew");
+        assert!(!temp_dir.path().join("escape.md").exists());
+
+        let tar_path = temp_dir.path().join("notes.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&tar_path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(5);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "a/notes.txt", "hello".as_bytes())
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let tar_path = tar_path.to_string_lossy().to_string();
+        let members = expand_into(&root, &tar_path).unwrap();
+        assert_eq!(members[0].1, format!("{tar_path}!a/notes.txt"));
+        // Expanding an unchanged archive again reuses the extracted files
+        assert_eq!(expand_into(&root, &tar_path).unwrap(), members);
+    }
+}
@@ -0,0 +1,142 @@
+//! Opt-in cache of `ask` answers (`--cache`, or `cache_answers` in the config file).
+//!
+//! Answers are kept in ~/.cache/semtools/answer_cache/, keyed on the question, the content of
+//! every input and the model, so re-running a question over an unchanged corpus (e.g.
+//! regenerating a report) is instant, while any edit to the corpus asks the model again.
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::json_mode::AskOutput;
+
+const ANSWER_CACHE_DIR: &str = "answer_cache";
+
+/// Hash of everything the agent could read: each input's path and content, and piped stdin
+pub fn corpus_hash(files: &[String], stdin_content: Option<&str>) -> Result<String> {
+    let mut files: Vec<&String> = files.iter().collect();
+    files.sort();
+    files.dedup();
+
+    let mut hasher = Sha256::new();
+    for file in files {
+        let content = fs::read(crate::archive::local_path(file))
+            .with_context(|| format!("Failed to read {file}"))?;
+        hasher.update((file.len() as u64).to_le_bytes());
+        hasher.update(file.as_bytes());
+        hasher.update(Sha256::digest(&content));
+    }
+    if let Some(stdin_content) = stdin_content {
+        hasher.update(b"stdin");
+        hasher.update(Sha256::digest(stdin_content.as_bytes()));
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Key of an answer: the question, the corpus, the model, and the settings that shape the
+/// answer (e.g. answer format, self-check)
+pub fn answer_key(question: &str, corpus_hash: &str, model: &str, settings: &str) -> String {
+    let mut hasher = Sha256::new();
+    for field in [question, corpus_hash, model, settings] {
+        hasher.update((field.len() as u64).to_le_bytes());
+        hasher.update(field.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Answers stored on disk, one JSON file per key
+pub struct AnswerCache {
+    dir: PathBuf,
+}
+
+impl AnswerCache {
+    pub fn default_dir() -> Result<PathBuf> {
+        crate::paths::cache_path(ANSWER_CACHE_DIR)
+    }
+
+    pub fn open() -> Result<Self> {
+        Ok(Self::at(Self::default_dir()?))
+    }
+
+    pub fn at(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.json"))
+    }
+
+    /// The cached answer for `key`, marked as cached. Unreadable entries count as misses.
+    pub fn get(&self, key: &str) -> Option<AskOutput> {
+        let content = fs::read_to_string(self.path(key)).ok()?;
+        let mut output: AskOutput = serde_json::from_str(&content).ok()?;
+        output.cached = true;
+        Some(output)
+    }
+
+    pub fn put(&self, key: &str, output: &AskOutput) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // Written aside and renamed, so a concurrent run never reads half an answer
+        let path = self.path(key);
+        let partial = path.with_extension("json.partial");
+        fs::write(&partial, serde_json::to_string_pretty(output)?)?;
+        fs::rename(&partial, &path).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_corpus_hash_tracks_content() {
+        let temp_dir = TempDir::new().unwrap();
+        let a = temp_dir.path().join("a.md");
+        let b = temp_dir.path().join("b.md");
+        fs::write(&a, "pro plan: $20").unwrap();
+        fs::write(&b, "team plan: $50").unwrap();
+        let files = vec![
+            a.to_string_lossy().to_string(),
+            b.to_string_lossy().to_string(),
+        ];
+
+        let hash = corpus_hash(&files, None).unwrap();
+        // Input order does not matter
+        let reversed: Vec<String> = files.iter().rev().cloned().collect();
+        assert_eq!(hash, corpus_hash(&reversed, None).unwrap());
+        assert_ne!(hash, corpus_hash(&files, Some("notes")).unwrap());
+
+        fs::write(&a, "pro plan: $25").unwrap();
+        assert_ne!(hash, corpus_hash(&files, None).unwrap());
+    }
+
+    #[test]
+    fn test_put_and_get_answer() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache = AnswerCache::at(temp_dir.path().join(ANSWER_CACHE_DIR));
+        let key = answer_key("What does pro cost?", "corpus", "gpt-4o-mini", "");
+        assert_ne!(
+            key,
+            answer_key("What does pro cost?", "corpus", "gpt-4o", "")
+        );
+        assert!(cache.get(&key).is_none());
+
+        let output = AskOutput {
+            query: "What does pro cost?".to_string(),
+            response: "$20 per month [1]".to_string(),
+            files_searched: vec!["docs/pricing.md".to_string()],
+            self_check: None,
+            cached: false,
+            evidence: vec![],
+            injections: vec![],
+            tool_calls: vec![],
+        };
+        cache.put(&key, &output).unwrap();
+
+        let cached = cache.get(&key).unwrap();
+        assert!(cached.cached);
+        assert_eq!(cached.response, output.response);
+        assert_eq!(cached.files_searched, output.files_searched);
+    }
+}
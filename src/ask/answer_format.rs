@@ -0,0 +1,184 @@
+use regex::Regex;
+use std::str::FromStr;
+
+use crate::ask::error::AskError;
+
+/// How the final answer of `ask` is shaped, for output that is piped into other tools
+///
+/// Parsed from a comma-separated list such as `plain,no-citations,max-chars=800`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnswerFormat {
+    /// Numbered citations and a references section
+    pub citations: bool,
+    /// Plain text instead of markdown
+    pub plain: bool,
+    /// Hard cap on the answer length, in characters
+    pub max_chars: Option<usize>,
+    /// Language to answer in, whatever the language of the question and the documents
+    /// (`--answer-language`, or `answer_language` in the config file)
+    pub language: Option<String>,
+    /// No answer at all: the agent only retrieves context (`ask --dry-run`)
+    pub retrieval_only: bool,
+}
+
+impl Default for AnswerFormat {
+    fn default() -> Self {
+        Self {
+            citations: true,
+            plain: false,
+            max_chars: None,
+            language: None,
+            retrieval_only: false,
+        }
+    }
+}
+
+impl FromStr for AnswerFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut format = AnswerFormat::default();
+        for option in s.split(',').map(str::trim).filter(|o| !o.is_empty()) {
+            match option {
+                "citations" => format.citations = true,
+                "no-citations" => format.citations = false,
+                "markdown" => format.plain = false,
+                "plain" => format.plain = true,
+                other => {
+                    let Some(value) = other.strip_prefix("max-chars=") else {
+                        return Err(AskError::InvalidArgument(format!(
+                            "Unknown answer format option '{other}'. Supported options: citations, no-citations, markdown, plain, max-chars=N"
+                        ))
+                        .into());
+                    };
+                    let max_chars: usize = value.parse().map_err(|_| {
+                        AskError::InvalidArgument(format!("Invalid max-chars value '{value}'"))
+                    })?;
+                    if max_chars == 0 {
+                        return Err(AskError::InvalidArgument(
+                            "max-chars must be greater than 0".to_string(),
+                        )
+                        .into());
+                    }
+                    format.max_chars = Some(max_chars);
+                }
+            }
+        }
+        Ok(format)
+    }
+}
+
+impl AnswerFormat {
+    /// Enforce the format on the model's final message. The system prompt asks for the same
+    /// thing, but models do not always comply.
+    pub fn apply(&self, response: &str) -> String {
+        if self.retrieval_only {
+            return String::new();
+        }
+        let mut text = response.to_string();
+        if !self.citations {
+            text = strip_citations(&text);
+        }
+        if self.plain {
+            text = strip_markdown(&text);
+        }
+        if let Some(max_chars) = self.max_chars {
+            text = truncate_chars(&text, max_chars);
+        }
+        text
+    }
+}
+
+/// Remove a trailing references section and inline markers like `[1]` or `[2, 3]`
+pub(crate) fn strip_citations(text: &str) -> String {
+    let references = Regex::new(r"(?im)^\s*(#+\s*)?references:?\s*$").unwrap();
+    let body = match references.find(text) {
+        Some(m) => &text[..m.start()],
+        None => text,
+    };
+
+    let markers = Regex::new(r"[ \t]*\[\d+(?:\s*[,\-–]\s*\d+)*\]").unwrap();
+    markers.replace_all(body, "").trim_end().to_string()
+}
+
+/// Reduce markdown to plain text, keeping the words and line structure
+fn strip_markdown(text: &str) -> String {
+    let heading = Regex::new(r"^\s{0,3}#{1,6}\s+").unwrap();
+    let bullet = Regex::new(r"^(\s*)[-*+]\s+").unwrap();
+    let link = Regex::new(r"\[([^\]]+)\]\(([^)]+)\)").unwrap();
+    let emphasis = Regex::new(r"(\*\*|__|\*|`)").unwrap();
+
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .map(|line| {
+            let line = heading.replace(line, "");
+            let line = bullet.replace(&line, "$1");
+            let line = link.replace_all(&line, "$1 ($2)");
+            emphasis.replace_all(&line, "").to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Cut to at most `max_chars` characters, preferring a word boundary, and mark the cut
+pub(crate) fn truncate_chars(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.to_string();
+    }
+
+    let keep = max_chars.saturating_sub(1);
+    let cut: String = text.chars().take(keep).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(idx) if idx > keep / 2 => &cut[..idx],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_answer_format() {
+        assert_eq!("".parse::<AnswerFormat>().unwrap(), AnswerFormat::default());
+        let format: AnswerFormat = "plain, no-citations, max-chars=200".parse().unwrap();
+        assert_eq!(
+            format,
+            AnswerFormat {
+                citations: false,
+                plain: true,
+                max_chars: Some(200),
+                language: None,
+                retrieval_only: false,
+            }
+        );
+        assert!("max-chars=0".parse::<AnswerFormat>().is_err());
+        assert!("html".parse::<AnswerFormat>().is_err());
+    }
+
+    #[test]
+    fn test_strip_citations() {
+        let answer = "GCNs classify nodes [1]. GraphSAGE is inductive [2, 3].\n\n## References\n[1] a.txt:1\n[2] b.txt:2";
+        assert_eq!(
+            strip_citations(answer),
+            "GCNs classify nodes. GraphSAGE is inductive."
+        );
+    }
+
+    #[test]
+    fn test_strip_markdown() {
+        let answer = "## Summary\n- **Fast** and `small`\n```\ncode\n```\nSee [docs](https://x.io)";
+        assert_eq!(
+            strip_markdown(answer),
+            "Summary\nFast and small\ncode\nSee docs (https://x.io)"
+        );
+    }
+
+    #[test]
+    fn test_truncate_chars() {
+        assert_eq!(truncate_chars("short", 10), "short");
+        assert_eq!(truncate_chars("one two three four", 10), "one two…");
+        assert!(truncate_chars("ééééééééééé", 5).chars().count() <= 5);
+    }
+}
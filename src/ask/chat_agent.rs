@@ -8,53 +8,107 @@ use async_openai::types::chat::{
 use async_openai::{Client, types::chat::CreateChatCompletionRequestArgs};
 use model2vec_rs::model::StaticModel;
 
-use crate::ask::system_prompt::{STDIN_SYSTEM_PROMPT, SYSTEM_PROMPT};
-use crate::ask::tool_calling::{call_tool, print_tool_summary};
-use crate::ask::tools::{AgentTool, GrepTool, ReadTool, SearchTool};
-use crate::json_mode::AskOutput;
+use crate::ask::answer_format::AnswerFormat;
+use crate::ask::error::AskError;
+use crate::ask::events::{AgentEvent, AgentEventSender, emit};
+use crate::ask::injection::{InjectionCheck, InjectionGuard};
+use crate::ask::memory::AgentMemory;
+use crate::ask::redaction;
+use crate::ask::sampling::WithSampling;
+use crate::ask::sandbox::PathSandbox;
+use crate::ask::system_prompt::{stdin_system_prompt, system_prompt, with_stdin_content};
+use crate::ask::tool_calling::{call_tools, print_tool_summary, tool_response_stats};
+use crate::ask::tools::{
+    AgentTool, GrepTool, MemoryLookupTool, ReadTool, SearchInFileTool, SearchTool, ViewImageTool,
+};
+use crate::ask::vision::{ImageViewer, offers_images};
+use crate::config::ApiMode;
+use crate::json_mode::{AskOutput, ToolCallJSON};
+use crate::telemetry::{Operation, measure};
+use crate::usage::record_tokens;
 
 /// Run an agent loop with the search and read tools
 ///
 /// # Arguments
 /// * `files` - List of file paths to search through
 /// * `user_message` - The user's query/message
+/// * `stdin_content` - Content piped via stdin to answer about, alongside the files
 /// * `model` - The embedding model for semantic search
 /// * `client` - OpenAI API client
 /// * `api_model` - The LLM model to use (e.g., "gpt-4o-mini")
 /// * `max_iterations` - Maximum number of agent loop iterations (default: 10)
+/// * `workspace_name` - Workspace to search instead of re-embedding files
+/// * `answer_format` - Citation, markup, and length constraints for the final answer
+/// * `memory` - Earlier findings to offer through the `memory_lookup` tool, if enabled
+/// * `allow_paths` - Files and directories the tools may open besides `files`
+/// * `injection_check` - How tool responses are checked for injected instructions
+/// * `events` - Optional channel that receives tool events as they happen
 ///
 /// # Returns
 /// The final response from the agent as a String
+#[allow(clippy::too_many_arguments)]
 pub async fn ask_agent(
     files: Vec<String>,
     user_message: &str,
+    stdin_content: Option<&str>,
     model: &StaticModel,
     client: &Client<OpenAIConfig>,
     api_model: &str,
     max_iterations: Option<usize>,
     workspace_name: Option<&str>,
+    answer_format: &AnswerFormat,
+    memory: Option<&AgentMemory>,
+    allow_paths: &[String],
+    injection_check: InjectionCheck,
+    events: Option<&AgentEventSender>,
 ) -> Result<AskOutput> {
+    let sandbox = PathSandbox::new(&files, allow_paths)?;
     let max_iterations = max_iterations.unwrap_or(20);
+    let guard = InjectionGuard::new(injection_check, client, api_model, ApiMode::Chat);
     let mut result = AskOutput {
         query: user_message.to_string(),
         response: String::new(),
         files_searched: vec![],
+        self_check: None,
+        cached: false,
+        evidence: vec![],
+        injections: vec![],
+        tool_calls: vec![],
     };
 
     // Build the tools
-    let tools: Vec<ChatCompletionTools> = vec![
+    let mut tools: Vec<ChatCompletionTools> = vec![
         GrepTool::chat_definition()?,
         SearchTool::chat_definition()?,
+        SearchInFileTool::chat_definition()?,
         ReadTool::chat_definition()?,
     ];
+    if memory.is_some() {
+        tools.push(MemoryLookupTool::chat_definition()?);
+    }
+    let viewer = offers_images(&files).then(|| ImageViewer::new(client, api_model));
+    if viewer.is_some() {
+        tools.push(ViewImageTool::chat_definition()?);
+    }
+
+    // Piped content goes in front of the query; the tools still cover the files
+    let message = match stdin_content {
+        Some(content) => with_stdin_content(content, user_message),
+        None => user_message.to_string(),
+    };
 
     // Initialize messages with system prompt and user message
     let mut messages: Vec<ChatCompletionRequestMessage> = vec![
         ChatCompletionRequestSystemMessageArgs::default()
-            .content(SYSTEM_PROMPT)
+            .content(system_prompt(
+                answer_format,
+                memory.is_some(),
+                stdin_content.is_some(),
+                viewer.is_some(),
+            ))
             .build()?
             .into(),
-        ChatCompletionRequestUserMessage::from(user_message).into(),
+        ChatCompletionRequestUserMessage::from(redaction::redact(&message).as_str()).into(),
     ];
 
     // Agent loop
@@ -64,38 +118,104 @@ pub async fn ask_agent(
             .model(api_model)
             .messages(messages.clone())
             .tools(tools.clone())
+            .with_sampling()
             .build()?;
 
         // Get response from LLM
-        let response_message = client
-            .chat()
-            .create(request)
-            .await?
+        let attributes = [("model", api_model), ("api", "chat")];
+        let response = measure(
+            Operation::LlmRequest,
+            &attributes,
+            client.chat().create(request),
+        )
+        .await
+        .map_err(AskError::from)?;
+        if let Some(usage) = &response.usage {
+            record_tokens(
+                api_model,
+                usage.prompt_tokens.into(),
+                usage.completion_tokens.into(),
+            );
+        }
+        let response_message = response
             .choices
             .first()
-            .ok_or_else(|| anyhow::anyhow!("No choices in response"))?
+            .ok_or(AskError::EmptyResponse)?
             .message
             .clone();
 
         // Check if there are tool calls
         if let Some(tool_calls) = response_message.tool_calls.clone() {
-            // Process tool calls
-            let mut function_responses = Vec::new();
+            // Run the function calls of this turn concurrently
+            let function_calls: Vec<_> = tool_calls
+                .iter()
+                .filter_map(|tool_call_enum| match tool_call_enum {
+                    ChatCompletionMessageToolCalls::Function(tool_call) => Some(tool_call),
+                    _ => None,
+                })
+                .collect();
+            let calls: Vec<(String, String)> = function_calls
+                .iter()
+                .map(|tool_call| {
+                    (
+                        tool_call.function.name.clone(),
+                        tool_call.function.arguments.clone(),
+                    )
+                })
+                .collect();
 
-            for tool_call_enum in tool_calls.iter() {
-                if let ChatCompletionMessageToolCalls::Function(tool_call) = tool_call_enum {
-                    let name = &tool_call.function.name;
-                    let args = &tool_call.function.arguments;
+            for (name, args) in calls.iter() {
+                emit(
+                    events,
+                    AgentEvent::ToolCall {
+                        name: name.clone(),
+                        arguments: args.clone(),
+                    },
+                );
+            }
 
-                    // Call the appropriate tool
-                    let response_content =
-                        call_tool(name, args, &files, model, &mut result, workspace_name).await?;
+            let responses = call_tools(
+                &calls,
+                &files,
+                &sandbox,
+                model,
+                &mut result,
+                workspace_name,
+                memory,
+                viewer.as_ref(),
+            )
+            .await?;
 
-                    // Print summary of the tool response
-                    print_tool_summary(&response_content);
+            let mut function_responses = Vec::new();
+            for (tool_call, response_content) in function_calls.into_iter().zip(responses) {
+                // Print summary of the tool response
+                print_tool_summary(&response_content);
+                let (chunks, lines) = tool_response_stats(&response_content);
+                emit(
+                    events,
+                    AgentEvent::ToolResult {
+                        name: tool_call.function.name.clone(),
+                        chunks,
+                        lines,
+                    },
+                );
 
-                    function_responses.push((tool_call.clone(), response_content));
-                }
+                // The model only ever sees tool responses marked as untrusted data
+                let wrapped = guard
+                    .guard(
+                        &tool_call.function.name,
+                        &response_content,
+                        &mut result,
+                        events,
+                    )
+                    .await;
+                result.tool_calls.push(ToolCallJSON {
+                    tool: tool_call.function.name.clone(),
+                    arguments: tool_call.function.arguments.clone(),
+                    response: response_content.clone(),
+                });
+                result.evidence.push(response_content);
+                function_responses.push((tool_call.clone(), wrapped));
             }
 
             // Add assistant message with tool calls to history
@@ -120,7 +240,7 @@ pub async fn ask_agent(
         } else {
             // No tool calls - we have a final response
             if let Some(content) = response_message.content {
-                result.response = content.clone();
+                result.response = answer_format.apply(&redaction::restore(&content));
             } else {
                 result.response = "<No response>".to_string();
             }
@@ -143,6 +263,7 @@ pub async fn ask_agent(
 /// * `user_message` - The user's query/message
 /// * `client` - OpenAI API client
 /// * `api_model` - The LLM model to use (e.g., "gpt-4o-mini")
+/// * `answer_format` - Citation, markup, and length constraints for the answer
 ///
 /// # Returns
 /// The response from the agent as a String
@@ -151,49 +272,65 @@ pub async fn ask_agent_with_stdin(
     user_message: &str,
     client: &Client<OpenAIConfig>,
     api_model: &str,
+    answer_format: &AnswerFormat,
 ) -> Result<AskOutput> {
     // Construct the user message with stdin content
-    let full_message = format!(
-        "<stdin_content>\n{}\n</stdin_content>\n\n{}",
-        stdin_content, user_message
-    );
+    let full_message = with_stdin_content(stdin_content, user_message);
     let mut result = AskOutput {
         query: user_message.to_string(),
         response: String::new(),
         files_searched: vec!["<stdin>".to_string()],
+        self_check: None,
+        cached: false,
+        evidence: vec![stdin_content.to_string()],
+        injections: vec![],
+        tool_calls: vec![],
     };
 
     // Initialize messages with system prompt and user message (no tools)
     let messages: Vec<ChatCompletionRequestMessage> = vec![
         ChatCompletionRequestSystemMessageArgs::default()
-            .content(STDIN_SYSTEM_PROMPT)
+            .content(stdin_system_prompt(answer_format))
             .build()?
             .into(),
-        ChatCompletionRequestUserMessage::from(full_message.as_str()).into(),
+        ChatCompletionRequestUserMessage::from(redaction::redact(&full_message).as_str()).into(),
     ];
 
     // Create request without tools
     let request = CreateChatCompletionRequestArgs::default()
         .model(api_model)
         .messages(messages)
+        .with_sampling()
         .build()?;
 
     // Get response from LLM
-    let response_message = client
-        .chat()
-        .create(request)
-        .await?
+    let attributes = [("model", api_model), ("api", "chat")];
+    let response = measure(
+        Operation::LlmRequest,
+        &attributes,
+        client.chat().create(request),
+    )
+    .await
+    .map_err(AskError::from)?;
+    if let Some(usage) = &response.usage {
+        record_tokens(
+            api_model,
+            usage.prompt_tokens.into(),
+            usage.completion_tokens.into(),
+        );
+    }
+    let response_message = response
         .choices
         .first()
-        .ok_or_else(|| anyhow::anyhow!("No choices in response"))?
+        .ok_or(AskError::EmptyResponse)?
         .message
         .clone();
 
     // Return the content
     if let Some(content) = response_message.content {
-        result.response = content;
+        result.response = answer_format.apply(&redaction::restore(&content));
         Ok(result)
     } else {
-        Err(anyhow::anyhow!("No content in response"))
+        Err(AskError::EmptyResponse.into())
     }
 }
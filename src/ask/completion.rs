@@ -0,0 +1,114 @@
+//! Single-turn LLM calls without tools, shared by the commands that are not agent loops
+use anyhow::Result;
+use async_openai::Client;
+use async_openai::config::OpenAIConfig;
+use async_openai::types::chat::{
+    ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs,
+    ChatCompletionRequestUserMessage, CreateChatCompletionRequestArgs,
+};
+use async_openai::types::responses::{
+    CreateResponseArgs, EasyInputContent, EasyInputMessage, InputItem, InputParam, MessageType,
+    Role,
+};
+
+use crate::ask::error::AskError;
+use crate::ask::redaction;
+use crate::ask::sampling::WithSampling;
+use crate::config::ApiMode;
+use crate::telemetry::{Operation, measure};
+use crate::usage::record_tokens;
+
+/// Send `instructions` and one user `message`, and return the model's text reply. The message
+/// is redacted and the reply restored when redaction is on.
+pub async fn complete(
+    client: &Client<OpenAIConfig>,
+    api_model: &str,
+    api_mode: ApiMode,
+    instructions: &str,
+    message: &str,
+) -> Result<String> {
+    let message = redaction::redact(message);
+    let reply = complete_raw(client, api_model, api_mode, instructions, &message).await?;
+    Ok(redaction::restore(&reply))
+}
+
+async fn complete_raw(
+    client: &Client<OpenAIConfig>,
+    api_model: &str,
+    api_mode: ApiMode,
+    instructions: &str,
+    message: &str,
+) -> Result<String> {
+    match api_mode {
+        ApiMode::Chat => {
+            let messages: Vec<ChatCompletionRequestMessage> = vec![
+                ChatCompletionRequestSystemMessageArgs::default()
+                    .content(instructions)
+                    .build()?
+                    .into(),
+                ChatCompletionRequestUserMessage::from(message).into(),
+            ];
+            let request = CreateChatCompletionRequestArgs::default()
+                .model(api_model)
+                .messages(messages)
+                .with_sampling()
+                .build()?;
+
+            let attributes = [("model", api_model), ("api", "chat")];
+            let response = measure(
+                Operation::LlmRequest,
+                &attributes,
+                client.chat().create(request),
+            )
+            .await
+            .map_err(AskError::from)?;
+            if let Some(usage) = &response.usage {
+                record_tokens(
+                    api_model,
+                    usage.prompt_tokens.into(),
+                    usage.completion_tokens.into(),
+                );
+            }
+            response
+                .choices
+                .first()
+                .and_then(|choice| choice.message.content.clone())
+                .ok_or_else(|| AskError::EmptyResponse.into())
+        }
+        ApiMode::Responses => {
+            let request = CreateResponseArgs::default()
+                .max_output_tokens(4096u32)
+                .model(api_model)
+                .input(InputParam::Items(vec![InputItem::EasyMessage(
+                    EasyInputMessage {
+                        r#type: MessageType::Message,
+                        role: Role::User,
+                        content: EasyInputContent::Text(message.to_string()),
+                    },
+                )]))
+                .instructions(instructions)
+                .store(false)
+                .with_sampling()
+                .build()?;
+
+            let attributes = [("model", api_model), ("api", "responses")];
+            let response = measure(
+                Operation::LlmRequest,
+                &attributes,
+                client.responses().create(request),
+            )
+            .await
+            .map_err(AskError::from)?;
+            if let Some(usage) = &response.usage {
+                record_tokens(
+                    api_model,
+                    usage.input_tokens.into(),
+                    usage.output_tokens.into(),
+                );
+            }
+            response
+                .output_text()
+                .ok_or_else(|| AskError::EmptyResponse.into())
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! `ask --dry-run`: the agent plans and runs its tool calls as usual, but is told to stop once
+//! it has retrieved enough instead of writing an answer. What it retrieved is printed as JSON,
+//! to audit retrieval, build datasets, or hand the context to another model.
+use std::collections::HashSet;
+
+use crate::json_mode::{
+    AskDryRunOutput, AskOutput, RetrievalCallJSON, RetrievedChunkJSON, ToolCallJSON,
+};
+
+/// The retrieval of an agent run, with each chunk listed once
+pub fn dry_run_output(output: &AskOutput) -> AskDryRunOutput {
+    let mut seen = HashSet::new();
+    let mut chunks = Vec::new();
+    let mut tool_calls = Vec::with_capacity(output.tool_calls.len());
+    for call in &output.tool_calls {
+        let retrieved = response_chunks(call);
+        tool_calls.push(RetrievalCallJSON {
+            tool: call.tool.clone(),
+            arguments: serde_json::from_str(&call.arguments)
+                .unwrap_or_else(|_| serde_json::Value::String(call.arguments.clone())),
+            chunks: retrieved.len(),
+        });
+        for chunk in retrieved {
+            if seen.insert((chunk.file.clone(), chunk.start, chunk.end)) {
+                chunks.push(chunk);
+            }
+        }
+    }
+    AskDryRunOutput {
+        query: output.query.clone(),
+        files_searched: output.files_searched.clone(),
+        tool_calls,
+        chunks,
+    }
+}
+
+/// The `<chunk>` blocks of a tool response
+fn response_chunks(call: &ToolCallJSON) -> Vec<RetrievedChunkJSON> {
+    let mut chunks = Vec::new();
+    let mut current: Option<(RetrievedChunkJSON, Vec<&str>)> = None;
+    for line in call.response.lines() {
+        if current.is_none() {
+            current = line
+                .strip_prefix("<chunk ")
+                .and_then(|header| header.strip_suffix('>'))
+                .and_then(|header| chunk_header(header, &call.tool))
+                .map(|chunk| (chunk, Vec::new()));
+            continue;
+        }
+        // `read` ends its last line with the closing tag
+        let (text, closed) = match line.strip_suffix("</chunk>") {
+            Some(text) => (text, true),
+            None => (line, false),
+        };
+        if let Some((_, lines)) = &mut current
+            && (!closed || !text.is_empty())
+        {
+            lines.push(text);
+        }
+        if closed && let Some((mut chunk, lines)) = current.take() {
+            chunk.text = lines.join("\n");
+            chunks.push(chunk);
+        }
+    }
+    chunks
+}
+
+/// A chunk from the attributes of its tag: `file=PATH start=N end=N`, then optional ones such
+/// as `distance=D`. The path runs up to ` start=`, since it may hold spaces.
+fn chunk_header(header: &str, tool: &str) -> Option<RetrievedChunkJSON> {
+    let (file, rest) = header.strip_prefix("file=")?.split_once(" start=")?;
+    let mut start = None;
+    let mut end = None;
+    let mut distance = None;
+    for (i, attribute) in rest.split(' ').enumerate() {
+        let (key, value) = if i == 0 {
+            ("start", attribute)
+        } else {
+            attribute.split_once('=')?
+        };
+        match key {
+            "start" => start = value.parse().ok(),
+            "end" => end = value.parse().ok(),
+            "distance" => distance = value.parse().ok(),
+            _ => {}
+        }
+    }
+    Some(RetrievedChunkJSON {
+        file: file.to_string(),
+        start: start?,
+        end: end?,
+        distance,
+        tool: tool.to_string(),
+        text: String::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(tool: &str, arguments: &str, response: &str) -> ToolCallJSON {
+        ToolCallJSON {
+            tool: tool.to_string(),
+            arguments: arguments.to_string(),
+            response: response.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_dry_run_output() {
+        let output = AskOutput {
+            query: "What does pro cost?".to_string(),
+            response: String::new(),
+            files_searched: vec!["docs/pricing page.md".to_string()],
+            self_check: None,
+            cached: false,
+            evidence: vec![],
+            injections: vec![],
+            tool_calls: vec![
+                call(
+                    "search",
+                    r#"{"query":"pro price"}"#,
+                    "<chunk file=docs/pricing page.md start=2 end=4 distance=0.31>\n\
+                     ## Pro\nPro: $20\n</chunk>\n",
+                ),
+                // The same lines again, then one more chunk with the tag closed on its last line
+                call(
+                    "read",
+                    "not json",
+                    "<chunk file=docs/pricing page.md start=2 end=4>\n## Pro\nPro: $20\n</chunk>\n\
+                     <chunk file=docs/faq.md start=0 end=1 total_lines=9>\nRefunds: 30 days</chunk>\n",
+                ),
+            ],
+        };
+        let dry_run = dry_run_output(&output);
+        assert_eq!(dry_run.tool_calls[0].arguments["query"], "pro price");
+        assert_eq!(dry_run.tool_calls[1].arguments, "not json");
+        assert_eq!(dry_run.tool_calls[1].chunks, 2);
+        assert_eq!(
+            dry_run.chunks,
+            vec![
+                RetrievedChunkJSON {
+                    file: "docs/pricing page.md".to_string(),
+                    start: 2,
+                    end: 4,
+                    distance: Some(0.31),
+                    tool: "search".to_string(),
+                    text: "## Pro\nPro: $20".to_string(),
+                },
+                RetrievedChunkJSON {
+                    file: "docs/faq.md".to_string(),
+                    start: 0,
+                    end: 1,
+                    distance: None,
+                    tool: "read".to_string(),
+                    text: "Refunds: 30 days".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use async_openai::error::OpenAIError;
+
+/// `ask` failures a caller can act on. They reach callers inside `anyhow::Error`; match on
+/// them with `error.downcast_ref::<AskError>()`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AskError {
+    /// Nothing to answer from: no files were given and nothing was piped in
+    NoInput,
+    /// No API key in `--api-key`, the config file or `OPENAI_API_KEY`
+    MissingApiKey,
+    /// An option has a value that is not allowed
+    InvalidArgument(String),
+    /// `--memory` was given without an active workspace to keep the memory in
+    MemoryNeedsWorkspace,
+    /// The LLM API request failed
+    Api(OpenAIError),
+    /// The LLM API replied without an answer
+    EmptyResponse,
+}
+
+impl AskError {
+    /// Stable name of the error, reported as `error_type` by `--json`
+    pub fn error_type(&self) -> &'static str {
+        match self {
+            AskError::NoInput => "NoInput",
+            AskError::MissingApiKey => "MissingApiKey",
+            AskError::InvalidArgument(_) => "InvalidArgument",
+            AskError::MemoryNeedsWorkspace => "NoActiveWorkspace",
+            AskError::Api(_) => "ApiError",
+            AskError::EmptyResponse => "EmptyResponse",
+        }
+    }
+}
+
+impl From<OpenAIError> for AskError {
+    fn from(error: OpenAIError) -> Self {
+        AskError::Api(error)
+    }
+}
+
+impl std::fmt::Display for AskError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AskError::NoInput => f.write_str(
+                "No input provided. Either specify files as arguments or pipe input to stdin.",
+            ),
+            AskError::MissingApiKey => f.write_str(
+                "OpenAI API key not found. Set via --api-key, config file, or OPENAI_API_KEY env var",
+            ),
+            AskError::InvalidArgument(msg) => f.write_str(msg),
+            AskError::MemoryNeedsWorkspace => f.write_str(
+                "--memory needs an active workspace. Set SEMTOOLS_WORKSPACE or pass --workspace.",
+            ),
+            AskError::Api(e) => write!(f, "LLM API request failed: {e}"),
+            AskError::EmptyResponse => f.write_str("No content in response"),
+        }
+    }
+}
+
+impl std::error::Error for AskError {}
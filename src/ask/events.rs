@@ -0,0 +1,29 @@
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Incremental progress of an agent run, for callers that stream it (e.g. the gRPC `Ask` RPC)
+#[derive(Debug, Clone, PartialEq)]
+pub enum AgentEvent {
+    /// The model requested a tool call (`arguments` is the raw JSON from the model)
+    ToolCall { name: String, arguments: String },
+    /// A tool call finished, with the size of what it returned
+    ToolResult {
+        name: String,
+        chunks: usize,
+        lines: usize,
+    },
+    /// A tool response looked like it carried instructions for the agent
+    InjectionDetected {
+        name: String,
+        reason: String,
+        file: Option<String>,
+    },
+}
+
+pub type AgentEventSender = UnboundedSender<AgentEvent>;
+
+/// Send an event if anyone is listening. A dropped receiver never fails the agent run.
+pub(crate) fn emit(events: Option<&AgentEventSender>, event: AgentEvent) {
+    if let Some(sender) = events {
+        let _ = sender.send(event);
+    }
+}
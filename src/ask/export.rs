@@ -0,0 +1,273 @@
+//! Reports of `ask` runs for `--export-markdown` and `--export-html`: every question and answer
+//! with the agent's tool calls, what each one retrieved and the files it consulted, so a
+//! research session can be archived and reviewed without running it again.
+//!
+//! Tool calls and responses appear as the model saw them, so redacted values stay masked. An
+//! answer taken from the answer cache has no tool calls to report.
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+use crate::ask::tool_calling::tool_response_stats;
+use crate::json_mode::{AskOutput, ToolCallJSON};
+
+const TITLE: &str = "semtools ask report";
+
+const STYLE: &str = "body { font-family: system-ui, sans-serif; max-width: 60rem; margin: 2rem auto; padding: 0 1rem; line-height: 1.5; }
+.meta { color: #666; }
+.answer { white-space: pre-wrap; }
+pre { background: #f6f8fa; padding: 0.75rem; overflow-x: auto; white-space: pre-wrap; }
+section { border-top: 1px solid #ddd; margin-top: 2rem; }";
+
+/// Where to write the report of a run, in each format
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Exports {
+    pub markdown: Option<String>,
+    pub html: Option<String>,
+}
+
+impl Exports {
+    /// Write the report of `turns`, answered by `model`, to each requested path
+    pub fn write(&self, model: &str, turns: &[AskOutput]) -> Result<()> {
+        if let Some(path) = &self.markdown {
+            fs::write(Path::new(path), markdown(model, turns))?;
+            eprintln!("Report written to {path}");
+        }
+        if let Some(path) = &self.html {
+            fs::write(Path::new(path), html(model, turns))?;
+            eprintln!("Report written to {path}");
+        }
+        Ok(())
+    }
+}
+
+pub fn markdown(model: &str, turns: &[AskOutput]) -> String {
+    let mut report = format!("# {TITLE}\n\n{}\n", meta(model, turns));
+    for turn in turns {
+        report.push_str(&format!(
+            "\n## {}\n\n{}\n",
+            one_line(&turn.query),
+            turn.response.trim_end()
+        ));
+
+        if !turn.files_searched.is_empty() {
+            report.push_str("\n### Files consulted\n\n");
+            for file in &turn.files_searched {
+                report.push_str(&format!("- `{file}`\n"));
+            }
+        }
+
+        report.push_str("\n### Tool calls\n\n");
+        match no_tool_calls(turn) {
+            Some(note) => report.push_str(&format!("{note}\n")),
+            None => {
+                for (i, call) in turn.tool_calls.iter().enumerate() {
+                    report.push_str(&format!(
+                        "**{}. {}** ({})\n\n{}\n<details>\n<summary>Retrieved</summary>\n\n{}\n</details>\n\n",
+                        i + 1,
+                        call.tool,
+                        stats(call),
+                        fenced(&arguments(call), "json"),
+                        fenced(call.response.trim_end(), "text")
+                    ));
+                }
+            }
+        }
+
+        if let Some(check) = &turn.self_check {
+            report.push_str(&format!(
+                "\n### Self-check\n\n{} revision(s)\n",
+                check.revisions
+            ));
+            for claim in &check.dropped_claims {
+                report.push_str(&format!("- Dropped: {claim}\n"));
+            }
+        }
+        if !turn.injections.is_empty() {
+            report.push_str("\n### Suspected injections\n\n");
+            for injection in &turn.injections {
+                report.push_str(&format!(
+                    "- {}{}: {}\n",
+                    injection.tool,
+                    injection
+                        .file
+                        .as_deref()
+                        .map(|file| format!(" in `{file}`"))
+                        .unwrap_or_default(),
+                    injection.reason
+                ));
+            }
+        }
+    }
+    report
+}
+
+pub fn html(model: &str, turns: &[AskOutput]) -> String {
+    let mut body = format!(
+        "<h1>{TITLE}</h1>\n<p class=\"meta\">{}</p>\n",
+        escape(&meta(model, turns))
+    );
+    for turn in turns {
+        body.push_str(&format!(
+            "<section>\n<h2>{}</h2>\n<div class=\"answer\">{}</div>\n",
+            escape(&turn.query),
+            escape(turn.response.trim_end())
+        ));
+
+        if !turn.files_searched.is_empty() {
+            body.push_str("<h3>Files consulted</h3>\n<ul>\n");
+            for file in &turn.files_searched {
+                body.push_str(&format!("<li><code>{}</code></li>\n", escape(file)));
+            }
+            body.push_str("</ul>\n");
+        }
+
+        body.push_str("<h3>Tool calls</h3>\n");
+        match no_tool_calls(turn) {
+            Some(note) => body.push_str(&format!("<p>{}</p>\n", escape(note))),
+            None => {
+                body.push_str("<ol>\n");
+                for call in &turn.tool_calls {
+                    body.push_str(&format!(
+                        "<li><strong>{}</strong> ({})\n<pre>{}</pre>\n<details><summary>Retrieved</summary><pre>{}</pre></details></li>\n",
+                        escape(&call.tool),
+                        stats(call),
+                        escape(&arguments(call)),
+                        escape(call.response.trim_end())
+                    ));
+                }
+                body.push_str("</ol>\n");
+            }
+        }
+
+        if let Some(check) = &turn.self_check {
+            body.push_str(&format!(
+                "<h3>Self-check</h3>\n<p>{} revision(s)</p>\n<ul>\n",
+                check.revisions
+            ));
+            for claim in &check.dropped_claims {
+                body.push_str(&format!("<li>Dropped: {}</li>\n", escape(claim)));
+            }
+            body.push_str("</ul>\n");
+        }
+        if !turn.injections.is_empty() {
+            body.push_str("<h3>Suspected injections</h3>\n<ul>\n");
+            for injection in &turn.injections {
+                body.push_str(&format!(
+                    "<li>{}{}: {}</li>\n",
+                    escape(&injection.tool),
+                    injection
+                        .file
+                        .as_deref()
+                        .map(|file| format!(" in <code>{}</code>", escape(file)))
+                        .unwrap_or_default(),
+                    escape(&injection.reason)
+                ));
+            }
+            body.push_str("</ul>\n");
+        }
+        body.push_str("</section>\n");
+    }
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{TITLE}</title>\n<style>\n{STYLE}\n</style>\n</head>\n<body>\n{body}</body>\n</html>\n"
+    )
+}
+
+fn meta(model: &str, turns: &[AskOutput]) -> String {
+    format!("Answered by {model} · {} question(s)", turns.len())
+}
+
+/// Why a turn has no tool calls to show, if it has none
+fn no_tool_calls(turn: &AskOutput) -> Option<&'static str> {
+    if !turn.tool_calls.is_empty() {
+        None
+    } else if turn.cached {
+        Some("Answered from the answer cache; the tool calls of the original run are not recorded.")
+    } else {
+        Some("The agent answered without calling any tools.")
+    }
+}
+
+fn stats(call: &ToolCallJSON) -> String {
+    let (chunks, lines) = tool_response_stats(&call.response);
+    if chunks > 0 {
+        format!("{chunks} chunk(s), {lines} line(s)")
+    } else {
+        format!("{lines} line(s)")
+    }
+}
+
+/// The arguments of a tool call, pretty-printed when they are valid JSON
+fn arguments(call: &ToolCallJSON) -> String {
+    serde_json::from_str::<serde_json::Value>(&call.arguments)
+        .and_then(|value| serde_json::to_string_pretty(&value))
+        .unwrap_or_else(|_| call.arguments.clone())
+}
+
+fn one_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `text` in a markdown code block, fenced with more backticks than it contains in a row
+fn fenced(text: &str, lang: &str) -> String {
+    let longest = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{fence}{lang}\n{text}\n{fence}\n")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn() -> AskOutput {
+        AskOutput {
+            query: "What does pro cost?".to_string(),
+            response: "$20 per month [1]\n\n[1] docs/pricing.md:3".to_string(),
+            files_searched: vec!["docs/pricing.md".to_string()],
+            self_check: None,
+            cached: false,
+            evidence: vec![],
+            injections: vec![],
+            tool_calls: vec![ToolCallJSON {
+                tool: "search".to_string(),
+                arguments: r#"{"query":"pro price"}"#.to_string(),
+                response: "<chunk file=\"docs/pricing.md\">\nPro: $20 ```\n</chunk>".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_markdown_report() {
+        let report = markdown("gpt-4o-mini", &[turn()]);
+        assert!(report.starts_with("# semtools ask report\n\nAnswered by gpt-4o-mini"));
+        assert!(report.contains("## What does pro cost?\n\n$20 per month [1]"));
+        assert!(report.contains("- `docs/pricing.md`"));
+        assert!(report.contains("**1. search** (1 chunk(s)"));
+        assert!(report.contains("\"query\": \"pro price\""));
+        // The response's own fence does not close the block holding it
+        assert!(report.contains("````text\n<chunk"));
+
+        let cached = AskOutput {
+            cached: true,
+            tool_calls: vec![],
+            ..turn()
+        };
+        assert!(markdown("m", &[cached]).contains("answer cache"));
+    }
+
+    #[test]
+    fn test_html_report() {
+        let report = html("gpt-4o-mini", &[turn()]);
+        assert!(report.starts_with("<!DOCTYPE html>"));
+        assert!(report.contains("<h2>What does pro cost?</h2>"));
+        assert!(report.contains("&lt;chunk file=&quot;docs/pricing.md&quot;&gt;"));
+        assert!(!report.contains("<chunk"));
+    }
+}
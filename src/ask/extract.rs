@@ -0,0 +1,164 @@
+//! Structured extraction: pull fields described by a JSON Schema out of documents with the LLM
+use anyhow::{Result, anyhow, bail};
+use async_openai::Client;
+use async_openai::config::OpenAIConfig;
+use jsonschema::Validator;
+use serde_json::Value;
+
+use crate::ask::completion::complete;
+use crate::config::ApiMode;
+
+/// How many times the model is asked before giving up on a document. Every retry includes
+/// the validation errors of the previous reply.
+const MAX_ATTEMPTS: usize = 2;
+
+/// Documents longer than this (in characters) are cut before being sent to the model
+pub const MAX_DOCUMENT_CHARS: usize = 100_000;
+
+const EXTRACT_PROMPT: &str = "You extract structured data from documents.
+
+Reply with a single JSON object that validates against the JSON Schema below, and nothing else: no prose and no code fences. Only use values stated in the document. When a field is not in the document, use null if the schema allows it, or leave the field out if it is optional.
+
+JSON Schema:";
+
+/// Extracts JSON matching a schema from document text
+pub struct Extractor<'a> {
+    client: &'a Client<OpenAIConfig>,
+    api_model: &'a str,
+    api_mode: ApiMode,
+    instructions: String,
+    validator: Validator,
+}
+
+impl<'a> Extractor<'a> {
+    pub fn new(
+        client: &'a Client<OpenAIConfig>,
+        api_model: &'a str,
+        api_mode: ApiMode,
+        schema: &Value,
+    ) -> Result<Self> {
+        let validator =
+            jsonschema::validator_for(schema).map_err(|e| anyhow!("Invalid JSON Schema: {e}"))?;
+        Ok(Self {
+            client,
+            api_model,
+            api_mode,
+            instructions: format!(
+                "{EXTRACT_PROMPT}\n{}",
+                serde_json::to_string_pretty(schema)?
+            ),
+            validator,
+        })
+    }
+
+    /// Extract one JSON object from `content`, validated against the schema
+    pub async fn extract(&self, content: &str) -> Result<Value> {
+        let mut message = format!("<document>\n{content}\n</document>");
+        let mut problems = Vec::new();
+
+        for _attempt in 0..MAX_ATTEMPTS {
+            let reply = self.complete(&message).await?;
+            problems = match parse_json_reply(&reply) {
+                Ok(value) => {
+                    let errors = validation_errors(&self.validator, &value);
+                    if errors.is_empty() {
+                        return Ok(value);
+                    }
+                    errors
+                }
+                Err(e) => vec![e.to_string()],
+            };
+
+            message.push_str(&format!(
+                "\n\nYour previous reply was:\n{reply}\n\nIt does not match the schema:\n- {}\n\nReply with the corrected JSON object only.",
+                problems.join("\n- ")
+            ));
+        }
+
+        bail!(
+            "No valid result after {MAX_ATTEMPTS} attempts: {}",
+            problems.join("; ")
+        )
+    }
+
+    async fn complete(&self, message: &str) -> Result<String> {
+        complete(
+            self.client,
+            self.api_model,
+            self.api_mode,
+            &self.instructions,
+            message,
+        )
+        .await
+    }
+}
+
+/// Parse the model's reply as JSON, tolerating code fences and text around the object
+pub(crate) fn parse_json_reply(reply: &str) -> Result<Value> {
+    let trimmed = reply.trim();
+    if let Ok(value) = serde_json::from_str(trimmed) {
+        return Ok(value);
+    }
+
+    let object = match (trimmed.find('{'), trimmed.rfind('}')) {
+        (Some(start), Some(end)) if start < end => &trimmed[start..=end],
+        _ => bail!("Reply is not a JSON object"),
+    };
+    serde_json::from_str(object).map_err(|e| anyhow!("Reply is not valid JSON: {e}"))
+}
+
+/// Every way `value` breaks the schema, as `<path>: <error>` (the path is empty at the root)
+fn validation_errors(validator: &Validator, value: &Value) -> Vec<String> {
+    validator
+        .iter_errors(value)
+        .map(|e| format!("{}: {e}", e.instance_path))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_json_reply() {
+        assert_eq!(
+            parse_json_reply("{\"total\": 12}").unwrap(),
+            json!({"total": 12})
+        );
+        assert_eq!(
+            parse_json_reply("Here you go:\n```json\n{\"total\": 12}\n```").unwrap(),
+            json!({"total": 12})
+        );
+        assert!(parse_json_reply("no data found").is_err());
+        assert!(parse_json_reply("{total: 12}").is_err());
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "parties": {"type": "array", "items": {"type": "string"}},
+                "effective_date": {"type": "string"}
+            },
+            "required": ["parties"]
+        });
+        let validator = jsonschema::validator_for(&schema).unwrap();
+
+        assert!(validation_errors(&validator, &json!({"parties": ["Acme", "Globex"]})).is_empty());
+
+        let errors = validation_errors(&validator, &json!({"parties": ["Acme", 3]}));
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("/parties/1:"));
+
+        assert_eq!(validation_errors(&validator, &json!({})).len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let client = Client::new();
+        let schema = json!({"type": "not-a-type"});
+        assert!(Extractor::new(&client, "gpt-4o-mini", ApiMode::Chat, &schema).is_err());
+    }
+}
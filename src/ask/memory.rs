@@ -0,0 +1,192 @@
+//! Opt-in memory of earlier `ask` runs, so the agent can reuse previous findings instead of
+//! searching the same corpus again
+use anyhow::Result;
+use model2vec_rs::model::StaticModel;
+use serde::{Deserialize, Serialize};
+use simsimd::SpatialSimilarity;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::ask::answer_format::{strip_citations, truncate_chars};
+use crate::json_mode::AskOutput;
+
+/// Maximum length, in characters, of the answer summary kept for each question
+const SUMMARY_MAX_CHARS: usize = 600;
+
+/// Previous questions further than this from the lookup query are not returned
+const LOOKUP_MAX_DISTANCE: f32 = 0.5;
+
+/// Number of previous questions returned by a lookup
+const LOOKUP_TOP_K: usize = 3;
+
+/// One answered question
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryEntry {
+    /// Unix timestamp (seconds) of when the question was answered
+    pub asked_at: u64,
+    pub question: String,
+    /// Files the agent searched or read while answering
+    pub files: Vec<String>,
+    /// The start of the answer, without citations
+    pub summary: String,
+}
+
+impl MemoryEntry {
+    pub fn from_output(output: &AskOutput) -> Result<Self> {
+        Ok(Self {
+            asked_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            question: output.query.clone(),
+            files: output.files_searched.clone(),
+            summary: truncate_chars(&strip_citations(&output.response), SUMMARY_MAX_CHARS),
+        })
+    }
+}
+
+/// Questions answered in earlier runs, stored one JSON entry per line
+#[derive(Debug, Clone, Default)]
+pub struct AgentMemory {
+    path: PathBuf,
+    entries: Vec<MemoryEntry>,
+}
+
+impl AgentMemory {
+    /// Load the memory file at `path`. A missing file is an empty memory, and lines that fail
+    /// to parse are skipped.
+    pub fn load(path: &Path) -> Result<Self> {
+        let entries = match fs::read_to_string(path) {
+            Ok(content) => content
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> &[MemoryEntry] {
+        &self.entries
+    }
+
+    /// Append an entry to the memory file
+    pub fn record(&mut self, entry: MemoryEntry) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Previous questions closest to `query`, best first, with their cosine distance
+    pub fn lookup(&self, query: &str, model: &StaticModel) -> Vec<(&MemoryEntry, f32)> {
+        if self.entries.is_empty() {
+            return Vec::new();
+        }
+
+        let query_embedding = model.encode_single(query);
+        let questions: Vec<String> = self.entries.iter().map(|e| e.question.clone()).collect();
+        let embeddings = model.encode_with_args(&questions, Some(2048), 1024);
+
+        let mut matches: Vec<(&MemoryEntry, f32)> = self
+            .entries
+            .iter()
+            .zip(embeddings.iter())
+            .filter_map(|(entry, embedding)| {
+                f32::cosine(&query_embedding, embedding).map(|d| (entry, d as f32))
+            })
+            .filter(|(_, distance)| *distance <= LOOKUP_MAX_DISTANCE)
+            .collect();
+        // Newer answers win ties, since the files may have changed since
+        matches.sort_by(|a, b| {
+            a.1.partial_cmp(&b.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| b.0.asked_at.cmp(&a.0.asked_at))
+        });
+        matches.truncate(LOOKUP_TOP_K);
+        matches
+    }
+}
+
+/// Format lookup results for the agent
+pub fn format_memory_matches(matches: &[(&MemoryEntry, f32)]) -> String {
+    if matches.is_empty() {
+        return "No previous findings match this question.".to_string();
+    }
+
+    let mut response = String::new();
+    for (entry, distance) in matches {
+        response.push_str(&format!(
+            "<memory asked_at={} distance={distance}>\n",
+            entry.asked_at
+        ));
+        response.push_str(&format!("Question: {}\n", entry.question));
+        response.push_str(&format!("Files: {}\n", entry.files.join(", ")));
+        response.push_str(&format!("Summary: {}\n", entry.summary));
+        response.push_str("</memory>\n");
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn output(query: &str, response: &str) -> AskOutput {
+        AskOutput {
+            query: query.to_string(),
+            response: response.to_string(),
+            files_searched: vec!["docs/pricing.md".to_string()],
+            self_check: None,
+            cached: false,
+            evidence: vec![],
+            injections: vec![],
+            tool_calls: vec![],
+        }
+    }
+
+    #[test]
+    fn test_record_and_reload() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("memory.jsonl");
+
+        let mut memory = AgentMemory::load(&path).unwrap();
+        assert!(memory.entries().is_empty());
+
+        let entry = MemoryEntry::from_output(&output(
+            "What does the pro plan cost?",
+            "The pro plan costs $20 per month [1].\n\n## References\n[1] docs/pricing.md:12",
+        ))
+        .unwrap();
+        assert_eq!(entry.summary, "The pro plan costs $20 per month.");
+        memory.record(entry.clone()).unwrap();
+
+        // A corrupted line does not lose the other entries
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        writeln!(file, "{{not json").unwrap();
+
+        let reloaded = AgentMemory::load(&path).unwrap();
+        assert_eq!(reloaded.entries(), &[entry]);
+    }
+
+    #[test]
+    fn test_format_memory_matches() {
+        assert!(format_memory_matches(&[]).contains("No previous findings"));
+
+        let entry = MemoryEntry::from_output(&output("Pricing?", "$20 per month")).unwrap();
+        let formatted = format_memory_matches(&[(&entry, 0.1)]);
+        assert!(formatted.contains("Question: Pricing?"));
+        assert!(formatted.contains("Files: docs/pricing.md"));
+        assert!(formatted.contains("Summary: $20 per month"));
+    }
+}
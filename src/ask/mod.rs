@@ -1,5 +1,24 @@
+pub mod answer_cache;
+pub mod answer_format;
 pub mod chat_agent;
+pub mod completion;
+pub mod dry_run;
+pub mod error;
+pub mod events;
+pub mod export;
+pub mod extract;
+pub mod injection;
+pub mod memory;
+pub mod piped_results;
+pub mod redaction;
+pub mod repl;
 pub mod responses_agent;
+pub mod sampling;
+pub mod sandbox;
+pub mod self_check;
+pub mod session_cache;
+pub mod summarize;
 mod system_prompt;
 mod tool_calling;
 pub mod tools;
+pub mod vision;
@@ -0,0 +1,112 @@
+//! `search --json ... | ask "..."`: when the input piped into `ask` is the JSON output of
+//! `search`, the agent gets the cited line ranges, read afresh from their files, instead of
+//! the raw JSON. The cited files stay available to its tools, so it can read around a match
+//! whose context is too short.
+use std::collections::HashMap;
+use std::fs::read_to_string;
+
+use crate::archive;
+use crate::json_mode::{SearchOutput, SearchResultJSON};
+
+/// Search results piped into `ask`
+#[derive(Debug)]
+pub struct PipedResults {
+    /// Each cited range as `file:start::end`, followed by its numbered lines
+    pub context: String,
+    /// The distinct cited files that exist on disk, in order of first citation
+    pub files: Vec<String>,
+}
+
+/// Parse piped input as the JSON output of `search`. Anything else, including results
+/// without a single match, returns `None` and is answered about as plain text.
+pub fn parse_search_output(input: &str) -> Option<PipedResults> {
+    let output: SearchOutput = serde_json::from_str(input).ok()?;
+    if output.results.is_empty() {
+        return None;
+    }
+
+    let mut contents: HashMap<&str, Option<String>> = HashMap::new();
+    let mut files = Vec::new();
+    let mut chunks = Vec::new();
+    for result in &output.results {
+        let content = contents
+            .entry(result.filename.as_str())
+            .or_insert_with(|| read_to_string(archive::local_path(&result.filename)).ok());
+        if content.is_some() && !files.contains(&result.filename) {
+            files.push(result.filename.clone());
+        }
+        chunks.push(cited_range(result, content.as_deref()));
+    }
+    Some(PipedResults {
+        context: chunks.join("\n\n"),
+        files,
+    })
+}
+
+/// The lines a result cites, numbered from 1. They are read from `file_content` when the file
+/// could be read, and from the result's own `content` otherwise (e.g. for `<stdin>`).
+fn cited_range(result: &SearchResultJSON, file_content: Option<&str>) -> String {
+    let start = result.start_line_number;
+    let lines: Vec<&str> = match file_content {
+        Some(content) => content
+            .lines()
+            .skip(start)
+            .take(result.end_line_number.saturating_sub(start))
+            .collect(),
+        None => result.content.lines().collect(),
+    };
+    let mut chunk = format!("{}:{}::{}", result.filename, start, start + lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        chunk.push_str(&format!("\n{:4}: {line}", start + i + 1));
+    }
+    chunk
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_search_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let notes = temp_dir.path().join("notes.md");
+        std::fs::write(&notes, "a\nb\nrefunds take 5 days\nc\nd\n").unwrap();
+        let notes = notes.to_string_lossy().to_string();
+
+        let output = serde_json::json!({
+            "results": [
+                {
+                    "filename": notes,
+                    "start_line_number": 1,
+                    "end_line_number": 4,
+                    "match_line_number": 2,
+                    "distance": 0.2,
+                    "score": 0.2,
+                    "content": "stale"
+                },
+                {
+                    "filename": "<stdin>",
+                    "start_line_number": 0,
+                    "end_line_number": 1,
+                    "match_line_number": 0,
+                    "distance": 0.3,
+                    "score": 0.3,
+                    "content": "piped line"
+                }
+            ]
+        });
+        let piped = parse_search_output(&output.to_string()).unwrap();
+        // Files are read afresh; what only exists in the results is kept as is
+        assert_eq!(piped.files, vec![notes.clone()]);
+        assert_eq!(
+            piped.context,
+            format!(
+                "{notes}:1::4\n   2: b\n   3: refunds take 5 days\n   4: c\n\n<stdin>:0::1\n   1: piped line"
+            )
+        );
+
+        assert!(parse_search_output("just some text").is_none());
+        assert!(parse_search_output(r#"{"results": []}"#).is_none());
+    }
+}
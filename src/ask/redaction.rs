@@ -0,0 +1,273 @@
+//! Masking of sensitive values in text sent to LLM APIs (`--redact`, or the `redaction`
+//! section of the config file).
+//!
+//! Emails, SSNs, API keys and any configured patterns are replaced with placeholders such as
+//! `[EMAIL_1]` before a request leaves the machine. The same value always gets the same
+//! placeholder, so the model can still reason about it, and the placeholders in its replies
+//! are swapped back for the original text. The mapping only ever lives in memory. Redaction
+//! never adds or removes lines, so `file:line` citations still point at the original text.
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, OnceLock};
+
+use crate::config::RedactionConfig;
+
+/// Detectors that can be named in `redaction.detectors`
+pub const BUILTIN_DETECTORS: [&str; 3] = ["email", "ssn", "api_key"];
+
+fn builtin_pattern(name: &str) -> Option<&'static str> {
+    match name {
+        "email" => Some(r"\b[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}\b"),
+        "ssn" => Some(r"\b\d{3}-\d{2}-\d{4}\b"),
+        // OpenAI, LlamaCloud, AWS, GitHub and Slack keys
+        "api_key" => Some(
+            r"\b(sk-[A-Za-z0-9_-]{20,}|llx-[A-Za-z0-9]{20,}|AKIA[0-9A-Z]{16}|gh[pousr]_[A-Za-z0-9]{36,}|xox[abprs]-[A-Za-z0-9-]{10,})\b",
+        ),
+        _ => None,
+    }
+}
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\[[A-Z][A-Z0-9_]*_\d+\]").unwrap());
+
+/// Set by `--redact`, turning redaction on even if the config file does not
+static REDACT_OVERRIDE: OnceLock<bool> = OnceLock::new();
+
+pub fn set_redact_override() {
+    let _ = REDACT_OVERRIDE.set(true);
+}
+
+/// The redactor of this process, installed by `init` when redaction is on
+static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+
+/// Turn redaction on for the rest of the process if `config` or `--redact` asks for it
+pub fn init(config: &RedactionConfig) -> Result<()> {
+    if !config.enabled && REDACT_OVERRIDE.get().is_none() {
+        return Ok(());
+    }
+    if REDACTOR.get().is_none() {
+        let _ = REDACTOR.set(Redactor::from_config(config)?);
+    }
+    Ok(())
+}
+
+/// Whether text sent to LLM APIs is being redacted
+pub fn is_active() -> bool {
+    REDACTOR.get().is_some()
+}
+
+/// `text` with sensitive values replaced by placeholders, or unchanged when redaction is off
+pub fn redact(text: &str) -> String {
+    match REDACTOR.get() {
+        Some(redactor) => redactor.redact(text),
+        None => text.to_string(),
+    }
+}
+
+/// `text` with placeholders swapped back for the values they replaced
+pub fn restore(text: &str) -> String {
+    match REDACTOR.get() {
+        Some(redactor) => redactor.restore(text),
+        None => text.to_string(),
+    }
+}
+
+/// The arguments of a tool call with placeholders in their strings swapped back, so the tool
+/// searches and reads the original values
+pub fn restore_json(value: &Value) -> Value {
+    match REDACTOR.get() {
+        Some(redactor) => redactor.restore_json(value),
+        None => value.clone(),
+    }
+}
+
+/// Number of distinct values redacted so far
+pub fn redacted_count() -> usize {
+    REDACTOR.get().map_or(0, Redactor::len)
+}
+
+#[derive(Default)]
+struct Mapping {
+    placeholders: HashMap<String, String>,
+    originals: HashMap<String, String>,
+    counts: HashMap<String, usize>,
+}
+
+pub struct Redactor {
+    /// Placeholder label (e.g. `EMAIL`) and the pattern it replaces
+    detectors: Vec<(String, Regex)>,
+    mapping: Mutex<Mapping>,
+}
+
+impl Redactor {
+    pub fn from_config(config: &RedactionConfig) -> Result<Self> {
+        let mut detectors = Vec::new();
+        for name in &config.detectors {
+            let pattern = builtin_pattern(name).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown redaction detector '{name}'. Available: {}",
+                    BUILTIN_DETECTORS.join(", ")
+                )
+            })?;
+            detectors.push((name.to_ascii_uppercase(), Regex::new(pattern)?));
+        }
+        for (label, pattern) in &config.patterns {
+            let label = label.to_ascii_uppercase().replace(['-', ' '], "_");
+            if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                || !label.starts_with(|c: char| c.is_ascii_alphabetic())
+            {
+                bail!("Invalid redaction pattern name '{label}': use letters, digits and '_'");
+            }
+            let regex = Regex::new(pattern)
+                .with_context(|| format!("Invalid redaction pattern for '{label}'"))?;
+            detectors.push((label, regex));
+        }
+        Ok(Self {
+            detectors,
+            mapping: Mutex::new(Mapping::default()),
+        })
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut mapping = self.mapping.lock().unwrap();
+        let mut text = text.to_string();
+        for (label, regex) in &self.detectors {
+            text = regex
+                .replace_all(&text, |caps: &regex::Captures| {
+                    let original = &caps[0];
+                    if let Some(placeholder) = mapping.placeholders.get(original) {
+                        return placeholder.clone();
+                    }
+                    let count = mapping.counts.entry(label.clone()).or_default();
+                    *count += 1;
+                    let placeholder = format!("[{label}_{count}]");
+                    mapping
+                        .placeholders
+                        .insert(original.to_string(), placeholder.clone());
+                    mapping
+                        .originals
+                        .insert(placeholder.clone(), original.to_string());
+                    placeholder
+                })
+                .into_owned();
+        }
+        text
+    }
+
+    pub fn restore(&self, text: &str) -> String {
+        let mapping = self.mapping.lock().unwrap();
+        PLACEHOLDER
+            .replace_all(text, |caps: &regex::Captures| {
+                mapping
+                    .originals
+                    .get(&caps[0])
+                    .cloned()
+                    .unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    }
+
+    /// Restore each string in `value` on its own, since an original value may need escaping
+    /// in JSON where its placeholder did not
+    pub fn restore_json(&self, value: &Value) -> Value {
+        match value {
+            Value::String(text) => Value::String(self.restore(text)),
+            Value::Array(items) => {
+                Value::Array(items.iter().map(|v| self.restore_json(v)).collect())
+            }
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(key, v)| (key.clone(), self.restore_json(v)))
+                    .collect(),
+            ),
+            _ => value.clone(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.mapping.lock().unwrap().originals.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::BTreeMap;
+
+    fn redactor(patterns: &[(&str, &str)]) -> Redactor {
+        Redactor::from_config(&RedactionConfig {
+            patterns: patterns
+                .iter()
+                .map(|(label, pattern)| (label.to_string(), pattern.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_redact_and_restore() {
+        let redactor = redactor(&[("employee_id", r"\bE\d{6}\b")]);
+        let text = "Contact jane@example.com (SSN 123-45-6789, id E123456).\n\
+                    Key: sk-abcdefghijklmnopqrstuvwx. CC jane@example.com, bob@example.org";
+        let redacted = redactor.redact(text);
+        assert_eq!(
+            redacted,
+            "Contact [EMAIL_1] (SSN [SSN_1], id [EMPLOYEE_ID_1]).\n\
+             Key: [API_KEY_1]. CC [EMAIL_1], [EMAIL_2]"
+        );
+        assert_eq!(redacted.lines().count(), text.lines().count());
+        assert_eq!(redactor.len(), 5);
+
+        // Placeholders in a reply are swapped back; unknown ones are left alone
+        assert_eq!(
+            redactor.restore("Write to [EMAIL_2] about [EMPLOYEE_ID_1] and [EMAIL_9]"),
+            "Write to bob@example.org about E123456 and [EMAIL_9]"
+        );
+        // Redacting again is stable
+        assert_eq!(redactor.redact(&redacted), redacted);
+    }
+
+    #[test]
+    fn test_restore_tool_arguments() {
+        let redactor = redactor(&[("path", r"C:\\Users\\\w+")]);
+        let redacted = redactor.redact(r"See jane@example.com in C:\Users\jane");
+        assert_eq!(redacted, "See [EMAIL_1] in [PATH_1]");
+
+        // Arguments the model wrote with the placeholders it was shown. Restoring the raw JSON
+        // would leave the backslashes of the path unescaped.
+        let args: Value = serde_json::from_str(
+            r#"{"pattern": "[EMAIL_1]", "file_paths": ["[PATH_1]\\notes.md"], "context_lines": 2}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            redactor.restore_json(&args),
+            json!({
+                "pattern": "jane@example.com",
+                "file_paths": [r"C:\Users\jane\notes.md"],
+                "context_lines": 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let config = RedactionConfig {
+            detectors: vec!["phone".to_string()],
+            ..Default::default()
+        };
+        assert!(Redactor::from_config(&config).is_err());
+        let config = RedactionConfig {
+            patterns: BTreeMap::from([("id".to_string(), "(".to_string())]),
+            ..Default::default()
+        };
+        assert!(Redactor::from_config(&config).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! The conversation and slash commands of `ask --interactive`.
+//!
+//! Each question is sent with the previous questions and answers of the session, so follow-ups
+//! such as "and in the 2023 report?" work. The agent still searches the files afresh for every
+//! question; only the answers are carried over.
+use anyhow::{Result, bail};
+use std::fs;
+use std::path::Path;
+
+use crate::json_mode::AskOutput;
+
+/// Earlier turns sent along with a question; older ones are dropped to bound the prompt
+const MAX_CONTEXT_TURNS: usize = 10;
+
+pub const HELP: &str = "Ask a question, or use one of these commands:
+  /files [PATH...]      list the files being searched, or search PATHs instead
+  /workspace [NAME]     show the active workspace, or switch to NAME
+  /clear                forget the conversation so far
+  /save [PATH]          save the conversation (markdown, or JSON if PATH ends in .json)
+  /help                 show this help
+  /exit                 quit (or Ctrl-D)";
+
+/// A line typed at the prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplInput {
+    Question(String),
+    Files(Vec<String>),
+    Workspace(Option<String>),
+    Clear,
+    Save(Option<String>),
+    Help,
+    Exit,
+    Empty,
+}
+
+impl ReplInput {
+    pub fn parse(line: &str) -> Result<Self> {
+        let line = line.trim();
+        let Some(command) = line.strip_prefix('/') else {
+            return Ok(if line.is_empty() {
+                ReplInput::Empty
+            } else {
+                ReplInput::Question(line.to_string())
+            });
+        };
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<String> = words.map(str::to_string).collect();
+        let at_most_one = |args: Vec<String>| -> Result<Option<String>> {
+            if args.len() > 1 {
+                bail!("/{name} takes at most one argument");
+            }
+            Ok(args.into_iter().next())
+        };
+        Ok(match name {
+            "files" => ReplInput::Files(args),
+            "workspace" => ReplInput::Workspace(at_most_one(args)?),
+            "clear" => ReplInput::Clear,
+            "save" => ReplInput::Save(at_most_one(args)?),
+            "help" | "?" => ReplInput::Help,
+            "exit" | "quit" => ReplInput::Exit,
+            _ => bail!("Unknown command '/{name}'. Type /help for the list of commands"),
+        })
+    }
+}
+
+/// The questions and answers of an interactive session
+#[derive(Debug, Default)]
+pub struct Conversation {
+    turns: Vec<AskOutput>,
+}
+
+impl Conversation {
+    /// The message to send for `question`: the question itself, after the latest turns
+    pub fn message(&self, question: &str) -> String {
+        let start = self.turns.len().saturating_sub(MAX_CONTEXT_TURNS);
+        let turns = &self.turns[start..];
+        if turns.is_empty() {
+            return question.to_string();
+        }
+        let mut message = String::from("<conversation>\n");
+        for turn in turns {
+            message.push_str(&format!(
+                "User: {}\nAssistant: {}\n\n",
+                turn.query, turn.response
+            ));
+        }
+        message.push_str(&format!(
+            "</conversation>\n\nAnswer the user's follow-up question, using the conversation \
+             above for context:\n{question}"
+        ));
+        message
+    }
+
+    pub fn push(&mut self, output: AskOutput) {
+        self.turns.push(output);
+    }
+
+    pub fn clear(&mut self) {
+        self.turns.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.turns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.turns.is_empty()
+    }
+
+    pub fn turns(&self) -> &[AskOutput] {
+        &self.turns
+    }
+
+    /// Write the conversation to `path`: a JSON array of answers (as with `ask --json`) if it
+    /// ends in `.json`, markdown otherwise
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = if path.extension().is_some_and(|ext| ext == "json") {
+            serde_json::to_string_pretty(&self.turns)? + "\n"
+        } else {
+            self.turns
+                .iter()
+                .map(|turn| format!("## {}\n\n{}\n", turn.query, turn.response.trim_end()))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turn(query: &str, response: &str) -> AskOutput {
+        AskOutput {
+            query: query.to_string(),
+            response: response.to_string(),
+            files_searched: Vec::new(),
+            self_check: None,
+            cached: false,
+            evidence: Vec::new(),
+            injections: Vec::new(),
+            tool_calls: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(
+            ReplInput::parse("  what changed? ").unwrap(),
+            ReplInput::Question("what changed?".to_string())
+        );
+        assert_eq!(ReplInput::parse("").unwrap(), ReplInput::Empty);
+        assert_eq!(
+            ReplInput::parse("/files a.md b.md").unwrap(),
+            ReplInput::Files(vec!["a.md".to_string(), "b.md".to_string()])
+        );
+        assert_eq!(ReplInput::parse("/save").unwrap(), ReplInput::Save(None));
+        assert_eq!(ReplInput::parse("/quit").unwrap(), ReplInput::Exit);
+        assert!(ReplInput::parse("/workspace a b").is_err());
+        assert!(ReplInput::parse("/unknown").is_err());
+    }
+
+    #[test]
+    fn test_conversation() {
+        let mut conversation = Conversation::default();
+        assert_eq!(conversation.message("first?"), "first?");
+
+        conversation.push(turn("Who wrote the report?", "Alice [1]"));
+        let message = conversation.message("When?");
+        assert!(message.contains("User: Who wrote the report?\nAssistant: Alice [1]"));
+        assert!(message.ends_with("When?"));
+
+        for i in 0..MAX_CONTEXT_TURNS {
+            conversation.push(turn(&format!("q{i}"), "a"));
+        }
+        assert!(!conversation.message("next").contains("Who wrote"));
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let markdown = temp_dir.path().join("chat.md");
+        conversation.save(&markdown).unwrap();
+        assert!(
+            fs::read_to_string(&markdown)
+                .unwrap()
+                .starts_with("## Who wrote the report?\n\nAlice [1]\n")
+        );
+        let json = temp_dir.path().join("chat.json");
+        conversation.save(&json).unwrap();
+        let saved: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&json).unwrap()).unwrap();
+        assert_eq!(saved.as_array().unwrap().len(), conversation.len());
+
+        conversation.clear();
+        assert!(conversation.is_empty());
+    }
+}
@@ -8,52 +8,101 @@ use async_openai::types::responses::{
 };
 use model2vec_rs::model::StaticModel;
 
-use crate::ask::system_prompt::{STDIN_SYSTEM_PROMPT, SYSTEM_PROMPT};
-use crate::ask::tool_calling::{call_tool, print_tool_summary};
-use crate::ask::tools::{AgentTool, GrepTool, ReadTool, SearchTool};
-use crate::json_mode::AskOutput;
+use crate::ask::answer_format::AnswerFormat;
+use crate::ask::error::AskError;
+use crate::ask::events::{AgentEvent, AgentEventSender, emit};
+use crate::ask::injection::{InjectionCheck, InjectionGuard};
+use crate::ask::memory::AgentMemory;
+use crate::ask::redaction;
+use crate::ask::sampling::WithSampling;
+use crate::ask::sandbox::PathSandbox;
+use crate::ask::system_prompt::{stdin_system_prompt, system_prompt, with_stdin_content};
+use crate::ask::tool_calling::{call_tools, print_tool_summary, tool_response_stats};
+use crate::ask::tools::{
+    AgentTool, GrepTool, MemoryLookupTool, ReadTool, SearchInFileTool, SearchTool, ViewImageTool,
+};
+use crate::ask::vision::{ImageViewer, offers_images};
+use crate::config::ApiMode;
+use crate::json_mode::{AskOutput, ToolCallJSON};
+use crate::telemetry::{Operation, measure};
+use crate::usage::record_tokens;
 
 /// Run an agent loop with the search and read tools using the Responses API
 ///
 /// # Arguments
 /// * `files` - List of file paths to search through
 /// * `user_message` - The user's query/message
+/// * `stdin_content` - Content piped via stdin to answer about, alongside the files
 /// * `model` - The embedding model for semantic search
 /// * `client` - OpenAI API client
 /// * `api_model` - The LLM model to use (e.g., "gpt-4.1")
 /// * `max_iterations` - Maximum number of agent loop iterations (default: 20)
+/// * `workspace_name` - Workspace to search instead of re-embedding files
+/// * `answer_format` - Citation, markup, and length constraints for the final answer
+/// * `memory` - Earlier findings to offer through the `memory_lookup` tool, if enabled
+/// * `allow_paths` - Files and directories the tools may open besides `files`
+/// * `injection_check` - How tool responses are checked for injected instructions
+/// * `events` - Optional channel that receives tool events as they happen
 ///
 /// # Returns
 /// AskOutput containing the query, response, and files searched
+#[allow(clippy::too_many_arguments)]
 pub async fn ask_agent_responses(
     files: Vec<String>,
     user_message: &str,
+    stdin_content: Option<&str>,
     model: &StaticModel,
     client: &Client<OpenAIConfig>,
     api_model: &str,
     max_iterations: Option<usize>,
     workspace_name: Option<&str>,
+    answer_format: &AnswerFormat,
+    memory: Option<&AgentMemory>,
+    allow_paths: &[String],
+    injection_check: InjectionCheck,
+    events: Option<&AgentEventSender>,
 ) -> Result<AskOutput> {
+    let sandbox = PathSandbox::new(&files, allow_paths)?;
     let max_iterations = max_iterations.unwrap_or(20);
+    let guard = InjectionGuard::new(injection_check, client, api_model, ApiMode::Responses);
     let mut result = AskOutput {
         query: user_message.to_string(),
         response: String::new(),
         files_searched: vec![],
+        self_check: None,
+        cached: false,
+        evidence: vec![],
+        injections: vec![],
+        tool_calls: vec![],
     };
 
     // Build the tools using the responses API format
-    let tools: Vec<Tool> = vec![
+    let mut tools: Vec<Tool> = vec![
         GrepTool::responses_definition()?,
         SearchTool::responses_definition()?,
+        SearchInFileTool::responses_definition()?,
         ReadTool::responses_definition()?,
     ];
+    if memory.is_some() {
+        tools.push(MemoryLookupTool::responses_definition()?);
+    }
+    let viewer = offers_images(&files).then(|| ImageViewer::new(client, api_model));
+    if viewer.is_some() {
+        tools.push(ViewImageTool::responses_definition()?);
+    }
+
+    // Piped content goes in front of the query; the tools still cover the files
+    let message = match stdin_content {
+        Some(content) => with_stdin_content(content, user_message),
+        None => user_message.to_string(),
+    };
 
     // Initialize input items with user message
     // Note: For Responses API, we use the instructions parameter for the system prompt
     let mut input_items: Vec<InputItem> = vec![InputItem::EasyMessage(EasyInputMessage {
         r#type: MessageType::Message,
         role: Role::User,
-        content: EasyInputContent::Text(user_message.to_string()),
+        content: EasyInputContent::Text(redaction::redact(&message)),
     })];
 
     // Agent loop
@@ -63,13 +112,33 @@ pub async fn ask_agent_responses(
             .max_output_tokens(4096u32)
             .model(api_model)
             .input(InputParam::Items(input_items.clone()))
-            .instructions(SYSTEM_PROMPT)
+            .instructions(system_prompt(
+                answer_format,
+                memory.is_some(),
+                stdin_content.is_some(),
+                viewer.is_some(),
+            ))
             .tools(tools.clone())
             .store(false)
+            .with_sampling()
             .build()?;
 
         // Get response from LLM
-        let response = client.responses().create(request).await?;
+        let attributes = [("model", api_model), ("api", "responses")];
+        let response = measure(
+            Operation::LlmRequest,
+            &attributes,
+            client.responses().create(request),
+        )
+        .await
+        .map_err(AskError::from)?;
+        if let Some(usage) = &response.usage {
+            record_tokens(
+                api_model,
+                usage.input_tokens.into(),
+                usage.output_tokens.into(),
+            );
+        }
 
         // Convert OutputItem to InputItem for history tracking
         for output_item in response.output.iter() {
@@ -91,23 +160,63 @@ pub async fn ask_agent_responses(
             .collect();
 
         if !function_calls.is_empty() {
-            // Process tool calls
-            for function_call in function_calls.iter() {
-                let name = &function_call.name;
-                let args = &function_call.arguments;
+            // Run the function calls of this turn concurrently
+            let calls: Vec<(String, String)> = function_calls
+                .iter()
+                .map(|function_call| (function_call.name.clone(), function_call.arguments.clone()))
+                .collect();
 
-                // Call the appropriate tool
-                let response_content =
-                    call_tool(name, args, &files, model, &mut result, workspace_name).await?;
+            for (name, args) in calls.iter() {
+                emit(
+                    events,
+                    AgentEvent::ToolCall {
+                        name: name.clone(),
+                        arguments: args.clone(),
+                    },
+                );
+            }
+
+            let responses = call_tools(
+                &calls,
+                &files,
+                &sandbox,
+                model,
+                &mut result,
+                workspace_name,
+                memory,
+                viewer.as_ref(),
+            )
+            .await?;
 
+            for (function_call, response_content) in function_calls.iter().zip(responses) {
                 // Print summary of the tool response
                 print_tool_summary(&response_content);
+                let (chunks, lines) = tool_response_stats(&response_content);
+                emit(
+                    events,
+                    AgentEvent::ToolResult {
+                        name: function_call.name.clone(),
+                        chunks,
+                        lines,
+                    },
+                );
+
+                // The model only ever sees tool responses marked as untrusted data
+                let wrapped = guard
+                    .guard(&function_call.name, &response_content, &mut result, events)
+                    .await;
+                result.tool_calls.push(ToolCallJSON {
+                    tool: function_call.name.clone(),
+                    arguments: function_call.arguments.clone(),
+                    response: response_content.clone(),
+                });
+                result.evidence.push(response_content);
 
                 // Add the function call output to input items
                 input_items.push(InputItem::Item(Item::FunctionCallOutput(
                     FunctionCallOutputItemParam {
                         call_id: function_call.call_id.clone(),
-                        output: FunctionCallOutput::Text(response_content),
+                        output: FunctionCallOutput::Text(wrapped),
                         id: None,
                         status: None,
                     },
@@ -117,12 +226,18 @@ pub async fn ask_agent_responses(
             // No tool calls - we have a final response
             let response_text = response
                 .output_text()
+                .map(|text| answer_format.apply(&redaction::restore(&text)))
                 .unwrap_or("<No response>".to_string());
 
             return Ok(AskOutput {
                 query: user_message.to_string(),
                 response: response_text,
                 files_searched: result.files_searched,
+                self_check: None,
+                cached: false,
+                evidence: result.evidence,
+                injections: result.injections,
+                tool_calls: result.tool_calls,
             });
         }
     }
@@ -135,6 +250,11 @@ pub async fn ask_agent_responses(
             max_iterations
         ),
         files_searched: result.files_searched,
+        self_check: None,
+        cached: false,
+        evidence: result.evidence,
+        injections: result.injections,
+        tool_calls: result.tool_calls,
     })
 }
 
@@ -172,6 +292,7 @@ fn output_item_to_item(output_item: &OutputItem) -> Result<Item> {
 /// * `user_message` - The user's query/message
 /// * `client` - OpenAI API client
 /// * `api_model` - The LLM model to use (e.g., "gpt-4.1")
+/// * `answer_format` - Citation, markup, and length constraints for the answer
 ///
 /// # Returns
 /// AskOutput containing the query, response, and "<stdin>" as the file searched
@@ -180,18 +301,16 @@ pub async fn ask_agent_responses_with_stdin(
     user_message: &str,
     client: &Client<OpenAIConfig>,
     api_model: &str,
+    answer_format: &AnswerFormat,
 ) -> Result<AskOutput> {
     // Construct the user message with stdin content
-    let full_message = format!(
-        "<stdin_content>\n{}\n</stdin_content>\n\n{}",
-        stdin_content, user_message
-    );
+    let full_message = with_stdin_content(stdin_content, user_message);
 
     // Initialize input items with user message (no tools)
     let input_items: Vec<InputItem> = vec![InputItem::EasyMessage(EasyInputMessage {
         r#type: MessageType::Message,
         role: Role::User,
-        content: EasyInputContent::Text(full_message),
+        content: EasyInputContent::Text(redaction::redact(&full_message)),
     })];
 
     // Create request without tools
@@ -199,21 +318,42 @@ pub async fn ask_agent_responses_with_stdin(
         .max_output_tokens(4096u32)
         .model(api_model)
         .input(InputParam::Items(input_items))
-        .instructions(STDIN_SYSTEM_PROMPT)
+        .instructions(stdin_system_prompt(answer_format))
         .store(false)
+        .with_sampling()
         .build()?;
 
     // Get response from LLM
-    let response = client.responses().create(request).await?;
+    let attributes = [("model", api_model), ("api", "responses")];
+    let response = measure(
+        Operation::LlmRequest,
+        &attributes,
+        client.responses().create(request),
+    )
+    .await
+    .map_err(AskError::from)?;
+    if let Some(usage) = &response.usage {
+        record_tokens(
+            api_model,
+            usage.input_tokens.into(),
+            usage.output_tokens.into(),
+        );
+    }
 
     // Return AskOutput with stdin as the file searched
     let response_text = response
         .output_text()
+        .map(|text| answer_format.apply(&redaction::restore(&text)))
         .unwrap_or("<No response>".to_string());
 
     Ok(AskOutput {
         query: user_message.to_string(),
         response: response_text,
         files_searched: vec!["<stdin>".to_string()],
+        self_check: None,
+        cached: false,
+        evidence: vec![stdin_content.to_string()],
+        injections: vec![],
+        tool_calls: vec![],
     })
 }
@@ -0,0 +1,107 @@
+//! Sampling settings for every LLM request: `ask --temperature/--seed`, or `temperature` and
+//! `seed` in the config file. With a seed and temperature 0, repeated runs over the same files
+//! give the same answer as far as the provider allows, for snapshot tests of pipelines.
+//!
+//! The Responses API takes no seed: use `--api-mode chat` where answers must repeat exactly.
+use async_openai::types::chat::CreateChatCompletionRequestArgs;
+use async_openai::types::responses::CreateResponseArgs;
+use std::sync::OnceLock;
+
+use crate::ask::error::AskError;
+
+/// Highest temperature the OpenAI API accepts
+pub const MAX_TEMPERATURE: f32 = 2.0;
+
+static SAMPLING: OnceLock<Sampling> = OnceLock::new();
+
+/// Sampling parameters; those left unset are the provider's defaults
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Sampling {
+    pub temperature: Option<f32>,
+    pub seed: Option<i64>,
+}
+
+impl Sampling {
+    /// These settings, with the unset ones taken from `fallback`
+    pub fn or(self, fallback: Sampling) -> Sampling {
+        Sampling {
+            temperature: self.temperature.or(fallback.temperature),
+            seed: self.seed.or(fallback.seed),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), AskError> {
+        match self.temperature {
+            Some(t) if !(0.0..=MAX_TEMPERATURE).contains(&t) => Err(AskError::InvalidArgument(
+                format!("Invalid temperature {t}: must be between 0 and {MAX_TEMPERATURE}"),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Use `sampling` for the requests of this process; the first call wins
+pub fn set_sampling(sampling: Sampling) {
+    let _ = SAMPLING.set(sampling);
+}
+
+pub fn sampling() -> Sampling {
+    SAMPLING.get().copied().unwrap_or_default()
+}
+
+/// Apply the configured sampling to a request being built
+pub trait WithSampling {
+    fn with_sampling(&mut self) -> &mut Self;
+}
+
+impl WithSampling for CreateChatCompletionRequestArgs {
+    fn with_sampling(&mut self) -> &mut Self {
+        let sampling = sampling();
+        if let Some(temperature) = sampling.temperature {
+            self.temperature(temperature);
+        }
+        if let Some(seed) = sampling.seed {
+            self.seed(seed);
+        }
+        self
+    }
+}
+
+impl WithSampling for CreateResponseArgs {
+    fn with_sampling(&mut self) -> &mut Self {
+        if let Some(temperature) = sampling().temperature {
+            self.temperature(temperature);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sampling() {
+        let cli = Sampling {
+            temperature: Some(0.0),
+            seed: None,
+        };
+        let config = Sampling {
+            temperature: Some(0.7),
+            seed: Some(42),
+        };
+        assert_eq!(
+            cli.or(config),
+            Sampling {
+                temperature: Some(0.0),
+                seed: Some(42),
+            }
+        );
+        assert!(cli.validate().is_ok());
+        let too_hot = Sampling {
+            temperature: Some(2.5),
+            seed: None,
+        };
+        assert!(too_hot.validate().is_err());
+    }
+}
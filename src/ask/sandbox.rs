@@ -0,0 +1,136 @@
+//! The paths the agent's tools may open.
+//!
+//! Tools only see the files `ask` was given and whatever `--allow-path` adds. Requested paths
+//! are canonicalized before they are checked, so neither `..` nor a symlink pointing out of an
+//! allowed directory gets around the list. A denied path is reported back to the model as the
+//! tool's response rather than failing the run.
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+use crate::ask::vision;
+
+pub struct PathSandbox {
+    /// Canonical paths of the files being searched
+    files: Vec<PathBuf>,
+    /// Canonical `--allow-path` entries and the image directories of parsed files: a
+    /// directory allows everything below it
+    allowed: Vec<PathBuf>,
+}
+
+impl PathSandbox {
+    /// Allow `files`, the images saved next to them when they were parsed, and every
+    /// `allow_paths` entry. Files that cannot be resolved are left out, as the tools cannot
+    /// read them anyway; an `--allow-path` that does not exist is an error.
+    pub fn new(files: &[String], allow_paths: &[String]) -> Result<Self> {
+        let mut allowed: Vec<PathBuf> = allow_paths
+            .iter()
+            .map(|path| {
+                Path::new(path)
+                    .canonicalize()
+                    .with_context(|| format!("--allow-path {path} does not exist"))
+            })
+            .collect::<Result<_>>()?;
+        allowed.extend(
+            files
+                .iter()
+                .filter_map(|file| vision::assets_dir(file)?.canonicalize().ok()),
+        );
+        let files = files
+            .iter()
+            .filter_map(|file| archive::local_path(file).canonicalize().ok())
+            .collect();
+        Ok(Self { files, allowed })
+    }
+
+    /// Whether the tools may open `path`
+    pub fn allows(&self, path: &str) -> bool {
+        let Ok(path) = archive::local_path(path).canonicalize() else {
+            return false;
+        };
+        self.files.contains(&path) || self.allowed.iter().any(|root| path.starts_with(root))
+    }
+
+    /// The response a tool gives instead of opening `paths`, or `None` if all are allowed
+    pub fn deny(&self, paths: &[&str]) -> Option<String> {
+        let denied: Vec<&str> = paths
+            .iter()
+            .copied()
+            .filter(|path| !self.allows(path))
+            .collect();
+        if denied.is_empty() {
+            return None;
+        }
+        for path in &denied {
+            println!("  ✗ Access denied: {path}");
+        }
+        Some(format!(
+            "Access denied: {} outside the files you were given. Only use the files being \
+             searched (grep and search cover all of them), and do not retry other paths.",
+            match denied.as_slice() {
+                [path] => format!("'{path}' is"),
+                paths => format!("'{}' are", paths.join("', '")),
+            }
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sandbox() {
+        let temp_dir = TempDir::new().unwrap();
+        let docs = temp_dir.path().join("docs");
+        let extra = temp_dir.path().join("extra");
+        fs::create_dir_all(docs.join("sub")).unwrap();
+        fs::create_dir_all(&extra).unwrap();
+        for path in [
+            docs.join("a.md"),
+            docs.join("b.md"),
+            docs.join("sub/c.md"),
+            extra.join("d.md"),
+        ] {
+            fs::write(path, "text").unwrap();
+        }
+        let path = |p: &Path| p.to_str().unwrap().to_string();
+
+        let sandbox = PathSandbox::new(&[path(&docs.join("a.md"))], &[path(&extra)]).unwrap();
+        assert!(sandbox.allows(&path(&docs.join("a.md"))));
+        assert!(sandbox.allows(&path(&docs.join("sub/../a.md"))));
+        assert!(sandbox.allows(&path(&extra.join("d.md"))));
+        assert!(!sandbox.allows(&path(&docs.join("b.md"))));
+        assert!(!sandbox.allows(&path(&extra.join("../docs/b.md"))));
+        assert!(!sandbox.allows(&path(&docs.join("missing.md"))));
+
+        assert_eq!(sandbox.deny(&[&path(&docs.join("a.md"))]), None);
+        let denial = sandbox.deny(&[&path(&docs.join("b.md"))]).unwrap();
+        assert!(denial.starts_with("Access denied"));
+
+        assert!(PathSandbox::new(&[], &[path(&docs.join("missing"))]).is_err());
+
+        // The images saved when a file was parsed come with it
+        fs::create_dir_all(docs.join("a.assets")).unwrap();
+        fs::write(docs.join("a.assets/fig.png"), "png").unwrap();
+        let sandbox = PathSandbox::new(&[path(&docs.join("a.md"))], &[]).unwrap();
+        assert!(sandbox.allows(&path(&docs.join("a.assets/fig.png"))));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sandbox_follows_symlinks() {
+        let temp_dir = TempDir::new().unwrap();
+        let allowed = temp_dir.path().join("allowed");
+        fs::create_dir_all(&allowed).unwrap();
+        let secret = temp_dir.path().join("secret.txt");
+        fs::write(&secret, "secret").unwrap();
+        let link = allowed.join("link.txt");
+        std::os::unix::fs::symlink(&secret, &link).unwrap();
+
+        let sandbox = PathSandbox::new(&[], &[allowed.to_str().unwrap().to_string()]).unwrap();
+        assert!(!sandbox.allows(link.to_str().unwrap()));
+    }
+}
@@ -0,0 +1,106 @@
+//! Optional verification pass over a finished `ask` answer: the model checks each claim against
+//! the chunks the agent retrieved and revises statements they do not support
+use anyhow::{Result, anyhow};
+use async_openai::Client;
+use async_openai::config::OpenAIConfig;
+use serde::Deserialize;
+
+use crate::ask::answer_format::{AnswerFormat, truncate_chars};
+use crate::ask::completion::complete;
+use crate::ask::extract::parse_json_reply;
+use crate::config::ApiMode;
+use crate::json_mode::SelfCheckOutput;
+
+/// Verification turns run at most this many times; a turn that changes nothing ends the loop
+const MAX_SELF_CHECK_ROUNDS: usize = 2;
+
+/// Retrieved chunks longer than this (in characters, all together) are cut before being sent
+const MAX_EVIDENCE_CHARS: usize = 100_000;
+
+const SELF_CHECK_PROMPT: &str = "You verify answers against the evidence they were written from.
+
+Check every factual claim in the answer against the evidence. A claim is supported only if the evidence states it. Reply with a single JSON object and nothing else: no prose and no code fences.
+
+{\"revised\": <bool>, \"answer\": \"<the answer>\", \"dropped_claims\": [\"<claim>\", ...]}
+
+If every claim is supported, set revised to false, repeat the answer unchanged and leave dropped_claims empty. Otherwise set revised to true, rewrite the answer without the unsupported statements (correct them instead when the evidence says otherwise), keep the citations of the claims that remain, and list each removed or corrected claim in dropped_claims.";
+
+/// The model's reply to one verification turn
+#[derive(Debug, Deserialize, PartialEq)]
+struct Verdict {
+    revised: bool,
+    answer: String,
+    #[serde(default)]
+    dropped_claims: Vec<String>,
+}
+
+/// Verify `answer` to `question` against `evidence`, the tool responses the agent saw
+///
+/// Returns the (possibly revised) answer, with `answer_format` applied, and a record of what
+/// was changed.
+pub async fn self_check(
+    client: &Client<OpenAIConfig>,
+    api_model: &str,
+    api_mode: ApiMode,
+    question: &str,
+    answer: &str,
+    evidence: &[String],
+    answer_format: &AnswerFormat,
+) -> Result<(String, SelfCheckOutput)> {
+    let evidence = truncate_chars(&evidence.join("\n\n"), MAX_EVIDENCE_CHARS);
+    let mut answer = answer.to_string();
+    let prompt = match &answer_format.language {
+        Some(language) => format!("{SELF_CHECK_PROMPT}\n\nWrite the answer in {language}."),
+        None => SELF_CHECK_PROMPT.to_string(),
+    };
+    let mut output = SelfCheckOutput {
+        revisions: 0,
+        dropped_claims: Vec::new(),
+    };
+
+    for _round in 0..MAX_SELF_CHECK_ROUNDS {
+        let message = format!(
+            "<evidence>\n{evidence}\n</evidence>\n\n<question>\n{question}\n</question>\n\n<answer>\n{answer}\n</answer>"
+        );
+        let reply = complete(client, api_model, api_mode, &prompt, &message).await?;
+        let verdict = parse_verdict(&reply)?;
+        if !verdict.revised || verdict.answer.trim() == answer.trim() {
+            break;
+        }
+
+        answer = answer_format.apply(&verdict.answer);
+        output.revisions += 1;
+        output.dropped_claims.extend(verdict.dropped_claims);
+    }
+
+    Ok((answer, output))
+}
+
+fn parse_verdict(reply: &str) -> Result<Verdict> {
+    serde_json::from_value(parse_json_reply(reply)?)
+        .map_err(|e| anyhow!("Self-check reply is not a valid verdict: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_verdict() {
+        let reply = "```json\n{\"revised\": true, \"answer\": \"The pro plan costs $20 [1].\", \"dropped_claims\": [\"It includes SSO\"]}\n```";
+        assert_eq!(
+            parse_verdict(reply).unwrap(),
+            Verdict {
+                revised: true,
+                answer: "The pro plan costs $20 [1].".to_string(),
+                dropped_claims: vec!["It includes SSO".to_string()],
+            }
+        );
+
+        let unchanged = parse_verdict("{\"revised\": false, \"answer\": \"ok\"}").unwrap();
+        assert!(!unchanged.revised);
+        assert!(unchanged.dropped_claims.is_empty());
+
+        assert!(parse_verdict("{\"answer\": \"ok\"}").is_err());
+    }
+}
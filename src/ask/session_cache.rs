@@ -0,0 +1,195 @@
+//! Per-process cache for the agent's search tools.
+//!
+//! Agents often repeat or refine a search within one `ask` run. Results are kept by a hash of
+//! the query, files and settings, and line embeddings by a hash of each file's content, so a
+//! repeated search is free and a refined one only embeds the query.
+use anyhow::Result;
+use model2vec_rs::model::StaticModel;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::sync::{Arc, Mutex};
+
+use crate::search::{Document, SearchConfig, embed_lines, embed_table_rows};
+
+/// Cached results are dropped wholesale once there are this many
+const MAX_CACHED_RESULTS: usize = 256;
+
+/// Cached embeddings are dropped wholesale once they cover this many lines
+const MAX_CACHED_LINES: usize = 2_000_000;
+
+/// A search tool's output, with the files that had results
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedSearch {
+    pub output: String,
+    pub files: Vec<String>,
+}
+
+struct SessionCache {
+    results: BTreeMap<String, CachedSearch>,
+    /// Line embeddings by content hash (and case folding)
+    embeddings: BTreeMap<String, Arc<Vec<Vec<f32>>>>,
+    cached_lines: usize,
+}
+
+static CACHE: Mutex<SessionCache> = Mutex::new(SessionCache {
+    results: BTreeMap::new(),
+    embeddings: BTreeMap::new(),
+    cached_lines: 0,
+});
+
+fn cache() -> std::sync::MutexGuard<'static, SessionCache> {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Key of a search: everything that changes its output
+pub fn result_key(
+    files: &[String],
+    query: &str,
+    config: &SearchConfig,
+    workspace_name: Option<&str>,
+) -> String {
+    let mut hasher = Sha256::new();
+    let mut field = |bytes: &[u8]| {
+        hasher.update((bytes.len() as u64).to_le_bytes());
+        hasher.update(bytes);
+    };
+    for file in files {
+        field(file.as_bytes());
+    }
+    field(query.as_bytes());
+    field(
+        format!(
+            "{} {} {:?} {} {} {:?} {} {:?} {:?} {:?}",
+            config.n_lines,
+            config.top_k,
+            config.max_distance,
+            config.ignore_case,
+            config.recency_boost,
+            config.mmr_lambda,
+            config.negative_weight,
+            config.query_combination,
+            config.boosts,
+            workspace_name
+        )
+        .as_bytes(),
+    );
+    for embedding in config
+        .negative_embeddings
+        .iter()
+        .chain(&config.extra_query_embeddings)
+    {
+        let bytes: Vec<u8> = embedding.iter().flat_map(|x| x.to_le_bytes()).collect();
+        field(&bytes);
+    }
+    hex::encode(hasher.finalize())
+}
+
+pub fn cached_result(key: &str) -> Option<CachedSearch> {
+    cache().results.get(key).cloned()
+}
+
+pub fn store_result(key: String, result: CachedSearch) {
+    let mut cache = cache();
+    if cache.results.len() >= MAX_CACHED_RESULTS {
+        cache.results.clear();
+    }
+    cache.results.insert(key, result);
+}
+
+/// `load_documents`, reusing the embeddings of files whose content was already embedded in
+/// this process
+pub fn load_documents(
+    files: &[String],
+    model: &StaticModel,
+    ignore_case: bool,
+) -> Result<Vec<Document>> {
+    let mut documents = Vec::new();
+    for filename in files {
+        let content = read_to_string(crate::archive::local_path(filename))?;
+        let lines: Vec<String> = content.lines().map(str::to_string).collect();
+        if lines.is_empty() {
+            continue;
+        }
+
+        let key = format!(
+            "{}:{ignore_case}",
+            hex::encode(Sha256::digest(content.as_bytes()))
+        );
+        let cached = cache().embeddings.get(&key).cloned();
+        let embeddings = match cached {
+            Some(embeddings) => embeddings,
+            None => {
+                let embeddings = if ignore_case {
+                    let lowered: Vec<String> = lines.iter().map(|l| l.to_lowercase()).collect();
+                    embed_lines(model, &lowered)
+                } else {
+                    embed_lines(model, &lines)
+                };
+                let embeddings = Arc::new(embeddings);
+                store_embeddings(key, embeddings.clone());
+                embeddings
+            }
+        };
+
+        documents.push(Document {
+            filename: filename.clone(),
+            table_rows: embed_table_rows(model, &lines, ignore_case),
+            lines,
+            embeddings: embeddings.as_ref().clone(),
+        });
+    }
+    Ok(documents)
+}
+
+fn store_embeddings(key: String, embeddings: Arc<Vec<Vec<f32>>>) {
+    let mut cache = cache();
+    if cache.cached_lines + embeddings.len() > MAX_CACHED_LINES {
+        cache.embeddings.clear();
+        cache.cached_lines = 0;
+    }
+    cache.cached_lines += embeddings.len();
+    cache.embeddings.insert(key, embeddings);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_result_key_covers_settings() {
+        let files = vec!["a.txt".to_string()];
+        let config = SearchConfig {
+            top_k: 3,
+            ..Default::default()
+        };
+        let key = result_key(&files, "billing", &config, None);
+        assert_eq!(key, result_key(&files, "billing", &config, None));
+        assert_ne!(key, result_key(&files, "invoices", &config, None));
+        assert_ne!(key, result_key(&files, "billing", &config, Some("ws")));
+
+        let wider = SearchConfig {
+            top_k: 10,
+            ..Default::default()
+        };
+        assert_ne!(key, result_key(&files, "billing", &wider, None));
+        let negative = SearchConfig {
+            top_k: 3,
+            negative_embeddings: vec![vec![0.5, 0.5]],
+            ..Default::default()
+        };
+        assert_ne!(key, result_key(&files, "billing", &negative, None));
+    }
+
+    #[test]
+    fn test_store_and_lookup_result() {
+        let key = result_key(&[], "cache test", &SearchConfig::default(), None);
+        assert_eq!(cached_result(&key), None);
+        let result = CachedSearch {
+            output: "a.txt:1::3".to_string(),
+            files: vec!["a.txt".to_string()],
+        };
+        store_result(key.clone(), result.clone());
+        assert_eq!(cached_result(&key), Some(result));
+    }
+}
@@ -0,0 +1,251 @@
+//! Map-reduce summarization: summarize chunks of each document concurrently, then combine the
+//! partial summaries into one answer that cites the chunks it came from
+use anyhow::Result;
+use async_openai::Client;
+use async_openai::config::OpenAIConfig;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::ask::completion::complete;
+use crate::ask::error::AskError;
+use crate::ask::tools::format_chunk;
+use crate::config::ApiMode;
+
+/// Target size of a chunk, in characters. Chunks always end on a line boundary.
+const CHUNK_CHARS: usize = 12_000;
+
+/// Number of chunk summaries requested from the LLM at the same time
+const MAX_CONCURRENT_REQUESTS: usize = 4;
+
+const MAP_PROMPT: &str = "You summarize one section of a larger document. The section is wrapped in a <chunk> tag with its file path and line range.
+
+Write a dense summary of the section in a few sentences. Keep names, numbers, dates and conclusions. Do not add anything that is not in the section, and do not mention that it is a section.";
+
+const REDUCE_PROMPT: &str = "You write the final summary of one or more documents from summaries of their sections. Each section summary is wrapped in a <chunk> tag with the file path and line range it covers.
+
+Merge overlapping points, keep the most important facts, and organize the summary by topic rather than by section.
+
+CITATIONS:
+Cite the sections that support each point with numbered citations [1], [2], placed right after the claim. End with a '## References' section listing each citation as file_path:start-end, using the start and end of the cited chunk.";
+
+/// How long the final summary should be
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SummaryLength {
+    Short,
+    #[default]
+    Medium,
+    Long,
+}
+
+impl SummaryLength {
+    fn instructions(&self) -> &'static str {
+        match self {
+            SummaryLength::Short => "LENGTH:\nKeep the summary to 3-5 sentences.",
+            SummaryLength::Medium => "LENGTH:\nWrite 2-4 paragraphs.",
+            SummaryLength::Long => {
+                "LENGTH:\nWrite a detailed summary with a short section for each major topic."
+            }
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SummaryLength::Short => "short",
+            SummaryLength::Medium => "medium",
+            SummaryLength::Long => "long",
+        }
+    }
+}
+
+impl fmt::Display for SummaryLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SummaryLength {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "short" => Ok(SummaryLength::Short),
+            "medium" => Ok(SummaryLength::Medium),
+            "long" => Ok(SummaryLength::Long),
+            _ => Err(AskError::InvalidArgument(format!(
+                "Invalid summary length: '{s}'. Must be 'short', 'medium' or 'long'"
+            ))
+            .into()),
+        }
+    }
+}
+
+/// Lines `start..end` (0-based, end exclusive) of one document
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub file: String,
+    pub start: usize,
+    pub end: usize,
+    pub lines: Vec<String>,
+}
+
+impl Chunk {
+    fn format(&self) -> String {
+        format_chunk(&self.file, self.start, self.end, &self.lines)
+    }
+}
+
+/// Split a document into chunks of about `CHUNK_CHARS` characters, on line boundaries
+pub fn chunk_document(file: &str, content: &str) -> Vec<Chunk> {
+    chunk_lines(file, content, CHUNK_CHARS)
+}
+
+fn chunk_lines(file: &str, content: &str, max_chars: usize) -> Vec<Chunk> {
+    let mut chunks = Vec::new();
+    let mut current = Chunk {
+        file: file.to_string(),
+        start: 0,
+        end: 0,
+        lines: Vec::new(),
+    };
+    let mut chars = 0;
+
+    for (idx, line) in content.lines().enumerate() {
+        let line_chars = line.chars().count() + 1;
+        if chars + line_chars > max_chars && !current.lines.is_empty() {
+            let next = Chunk {
+                file: file.to_string(),
+                start: idx,
+                end: idx,
+                lines: Vec::new(),
+            };
+            chunks.push(std::mem::replace(&mut current, next));
+            chars = 0;
+        }
+        current.lines.push(line.to_string());
+        current.end = idx + 1;
+        chars += line_chars;
+    }
+
+    chunks.push(current);
+
+    // Whitespace-only chunks have nothing to summarize
+    chunks.retain(|c| c.lines.iter().any(|l| !l.trim().is_empty()));
+    chunks
+}
+
+/// Summarizes chunks with the LLM
+pub struct Summarizer<'a> {
+    client: &'a Client<OpenAIConfig>,
+    api_model: &'a str,
+    api_mode: ApiMode,
+    length: SummaryLength,
+}
+
+impl<'a> Summarizer<'a> {
+    pub fn new(
+        client: &'a Client<OpenAIConfig>,
+        api_model: &'a str,
+        api_mode: ApiMode,
+        length: SummaryLength,
+    ) -> Self {
+        Self {
+            client,
+            api_model,
+            api_mode,
+            length,
+        }
+    }
+
+    /// Summarize all chunks into one cited summary. A single chunk is summarized directly;
+    /// otherwise every chunk is summarized first and the summaries are combined.
+    pub async fn summarize(&self, chunks: &[Chunk]) -> Result<String> {
+        if chunks.is_empty() {
+            anyhow::bail!("Nothing to summarize: the input is empty");
+        }
+
+        let sections: Vec<String> = if chunks.len() == 1 {
+            vec![chunks[0].format()]
+        } else {
+            stream::iter(chunks)
+                .map(|chunk| self.summarize_chunk(chunk))
+                .buffered(MAX_CONCURRENT_REQUESTS)
+                .try_collect()
+                .await?
+        };
+
+        let instructions = format!("{REDUCE_PROMPT}\n\n{}", self.length.instructions());
+        complete(
+            self.client,
+            self.api_model,
+            self.api_mode,
+            &instructions,
+            &sections.concat(),
+        )
+        .await
+    }
+
+    /// Summarize one chunk, keeping its location so the final summary can cite it
+    async fn summarize_chunk(&self, chunk: &Chunk) -> Result<String> {
+        let summary = complete(
+            self.client,
+            self.api_model,
+            self.api_mode,
+            MAP_PROMPT,
+            &chunk.format(),
+        )
+        .await?;
+        Ok(format_chunk(
+            &chunk.file,
+            chunk.start,
+            chunk.end,
+            &[summary.trim()],
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_lines() {
+        let content = "alpha\nbravo\ncharlie\ndelta\n";
+        let chunks = chunk_lines("notes.txt", content, 15);
+
+        assert_eq!(chunks.len(), 2);
+        assert_eq!((chunks[0].start, chunks[0].end), (0, 2));
+        assert_eq!(chunks[0].lines, vec!["alpha", "bravo"]);
+        assert_eq!((chunks[1].start, chunks[1].end), (2, 4));
+
+        // A line longer than the limit still gets a chunk of its own
+        let chunks = chunk_lines("long.txt", &"x".repeat(50), 13);
+        assert_eq!(chunks.len(), 1);
+
+        assert!(chunk_lines("empty.txt", "\n  \n", 13).is_empty());
+    }
+
+    #[test]
+    fn test_chunk_format() {
+        let chunk = Chunk {
+            file: "notes.txt".to_string(),
+            start: 4,
+            end: 6,
+            lines: vec!["alpha".to_string(), "bravo".to_string()],
+        };
+        assert_eq!(
+            chunk.format(),
+            "<chunk file=notes.txt start=4 end=6>\nalpha\nbravo\n</chunk>\n"
+        );
+    }
+
+    #[test]
+    fn test_parse_summary_length() {
+        assert_eq!(
+            "Short".parse::<SummaryLength>().unwrap(),
+            SummaryLength::Short
+        );
+        assert_eq!(SummaryLength::default().as_str(), "medium");
+        assert!("tiny".parse::<SummaryLength>().is_err());
+    }
+}
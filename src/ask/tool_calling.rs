@@ -1,21 +1,107 @@
+use std::time::Duration;
+
 use anyhow::Result;
+use futures::stream::{self, StreamExt};
 use model2vec_rs::model::StaticModel;
-use serde_json::Value;
 
-use crate::ask::tools::{GrepTool, ReadTool, SearchTool};
+use crate::ask::memory::AgentMemory;
+use crate::ask::redaction;
+use crate::ask::sandbox::PathSandbox;
+use crate::ask::tools::{
+    GrepTool, MemoryLookupTool, ReadRange, ReadTool, SearchInFileTool, SearchTool, ViewImageTool,
+};
+use crate::ask::vision::{ImageViewer, resolve_image_path};
 use crate::json_mode::AskOutput;
-use crate::search::SearchConfig;
+use crate::search::{DEFAULT_NEGATIVE_WEIGHT, QueryCombination, SearchConfig, embed_queries};
+
+/// Maximum number of tool calls from one assistant turn that run at the same time
+const MAX_CONCURRENT_TOOL_CALLS: usize = 6;
+/// How long a single tool call may run before the model is told it timed out
+const TOOL_CALL_TIMEOUT: Duration = Duration::from_secs(120);
 
-/// Call a tool by name with the given arguments
+/// Run all tool calls from one assistant turn concurrently
+///
+/// `calls` holds `(name, arguments)` pairs. Responses come back in the same order as the calls,
+/// and files touched by each call are added to `cur_output` in that order too, so the
+/// transcript does not depend on which tool happened to finish first.
+#[allow(clippy::too_many_arguments)]
+pub async fn call_tools(
+    calls: &[(String, String)],
+    files: &[String],
+    sandbox: &PathSandbox,
+    model: &StaticModel,
+    cur_output: &mut AskOutput,
+    workspace_name: Option<&str>,
+    memory: Option<&AgentMemory>,
+    viewer: Option<&ImageViewer<'_>>,
+) -> Result<Vec<String>> {
+    // `buffered` polls each call in order before it first yields, so the call logs printed by
+    // `call_tool` are not interleaved
+    let results: Vec<(Result<String>, Vec<String>)> = stream::iter(calls)
+        .map(|(name, args)| async move {
+            let mut files_searched = Vec::new();
+            let response = match tokio::time::timeout(
+                TOOL_CALL_TIMEOUT,
+                call_tool(
+                    name,
+                    args,
+                    files,
+                    sandbox,
+                    model,
+                    &mut files_searched,
+                    workspace_name,
+                    memory,
+                    viewer,
+                ),
+            )
+            .await
+            {
+                Ok(response) => response,
+                Err(_) => Ok(format!(
+                    "Tool call timed out after {} seconds",
+                    TOOL_CALL_TIMEOUT.as_secs()
+                )),
+            };
+            (response, files_searched)
+        })
+        .buffered(MAX_CONCURRENT_TOOL_CALLS)
+        .collect()
+        .await;
+
+    let mut responses = Vec::with_capacity(results.len());
+    for (response, files_searched) in results {
+        merge_files_searched(&mut cur_output.files_searched, files_searched);
+        // Tool responses only reach the model (and the evidence) redacted
+        responses.push(redaction::redact(&response?));
+    }
+    Ok(responses)
+}
+
+/// Append newly searched files, skipping ones already recorded
+fn merge_files_searched(files_searched: &mut Vec<String>, new_files: Vec<String>) {
+    for path in new_files {
+        if !files_searched.contains(&path) {
+            files_searched.push(path);
+        }
+    }
+}
+
+/// Call a tool by name with the given arguments. Paths outside `sandbox` are refused with a
+/// response the model can act on.
+#[allow(clippy::too_many_arguments)]
 pub async fn call_tool(
     name: &str,
     args: &str,
     files: &[String],
+    sandbox: &PathSandbox,
     model: &StaticModel,
-    cur_output: &mut AskOutput,
+    files_searched: &mut Vec<String>,
     workspace_name: Option<&str>,
+    memory: Option<&AgentMemory>,
+    viewer: Option<&ImageViewer<'_>>,
 ) -> Result<String> {
-    let function_args: Value = serde_json::from_str(args)?;
+    // The model only saw redacted text, so its arguments may hold placeholders
+    let function_args = redaction::restore_json(&serde_json::from_str(args)?);
 
     match name {
         "grep" => {
@@ -30,15 +116,6 @@ pub async fn call_tool(
                         .collect()
                 });
 
-            // Update files_searched in cur_output
-            if let Some(paths) = file_paths.clone() {
-                for path in paths {
-                    if !cur_output.files_searched.contains(&path) {
-                        cur_output.files_searched.push(path);
-                    }
-                }
-            }
-
             let is_regex = function_args["is_regex"].as_bool().unwrap_or(false);
             let case_sensitive = function_args["case_sensitive"].as_bool().unwrap_or(true);
             let context_lines = function_args["context_lines"].as_u64().unwrap_or(3) as usize;
@@ -54,6 +131,17 @@ pub async fn call_tool(
             {
                 println!("  file_paths: {:?}", paths);
             }
+            if let Some(ref paths) = file_paths {
+                let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+                if let Some(denial) = sandbox.deny(&paths) {
+                    return Ok(denial);
+                }
+            }
+
+            // Update files_searched
+            if let Some(paths) = file_paths.clone() {
+                merge_files_searched(files_searched, paths);
+            }
 
             GrepTool::grep(
                 files,
@@ -75,12 +163,30 @@ pub async fn call_tool(
             let ignore_case = config_json["ignore_case"].as_bool().unwrap_or(false);
             let max_distance = config_json["max_distance"].as_f64();
             let top_k = config_json["top_k"].as_u64().unwrap_or(3) as usize;
+            let negative_queries: Vec<String> = config_json["not"]
+                .as_array()
+                .map(|queries| {
+                    queries
+                        .iter()
+                        .filter_map(|q| q.as_str().map(String::from))
+                        .collect()
+                })
+                .unwrap_or_default();
 
             let config = SearchConfig {
                 n_lines,
                 ignore_case,
                 max_distance,
                 top_k,
+                recency_boost: 0.0,
+                mmr_lambda: None,
+                negative_embeddings: embed_queries(model, &negative_queries, ignore_case),
+                negative_weight: DEFAULT_NEGATIVE_WEIGHT,
+                extra_query_embeddings: Vec::new(),
+                query_combination: QueryCombination::All,
+                tag_filters: Vec::new(),
+                metadata_filters: Vec::new(),
+                boosts: Vec::new(),
             };
 
             // Log the tool call with formatted parameters
@@ -96,13 +202,52 @@ pub async fn call_tool(
             } else {
                 println!("    top_k: {}", top_k);
             }
+            if !negative_queries.is_empty() {
+                println!("    not: {:?}", negative_queries);
+            }
 
-            SearchTool::search(
+            SearchTool::search(files, query, model, config, files_searched, workspace_name).await
+        }
+        "search_in_file" => {
+            let path = function_args["path"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Missing 'path' parameter"))?;
+            let query = function_args["query"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Missing 'query' parameter"))?;
+            let top_k = function_args["top_k"].as_u64().unwrap_or(3) as usize;
+            let n_lines = function_args["n_lines"].as_u64().unwrap_or(5) as usize;
+
+            let config = SearchConfig {
+                n_lines,
+                ignore_case: false,
+                max_distance: None,
+                top_k,
+                recency_boost: 0.0,
+                mmr_lambda: None,
+                negative_embeddings: Vec::new(),
+                negative_weight: DEFAULT_NEGATIVE_WEIGHT,
+                extra_query_embeddings: Vec::new(),
+                query_combination: QueryCombination::All,
+                tag_filters: Vec::new(),
+                metadata_filters: Vec::new(),
+                boosts: Vec::new(),
+            };
+
+            // Log the tool call with formatted parameters
+            println!("\n[Tool Call: search_in_file]");
+            println!("  path: {}", path);
+            println!("  query: \"{}\"", query);
+            println!("  top_k: {}", top_k);
+            println!("  n_lines: {}", n_lines);
+
+            SearchInFileTool::search(
                 files,
+                path,
                 query,
                 model,
                 config,
-                &mut cur_output.files_searched,
+                files_searched,
                 workspace_name,
             )
             .await
@@ -111,34 +256,74 @@ pub async fn call_tool(
             let path = function_args["path"]
                 .as_str()
                 .ok_or_else(|| anyhow::anyhow!("Missing 'path' parameter"))?;
-            let start_line = function_args["start_line"]
-                .as_u64()
-                .ok_or_else(|| anyhow::anyhow!("Missing 'start_line' parameter"))?
-                as usize;
-            let end_line = function_args["end_line"]
-                .as_u64()
-                .ok_or_else(|| anyhow::anyhow!("Missing 'end_line' parameter"))?
-                as usize;
+            let range = ReadRange::from_args(&function_args)?;
 
             // Log the tool call with formatted parameters
             println!("\n[Tool Call: read]");
             println!("  path: {}", path);
-            println!("  start_line: {}", start_line);
-            println!("  end_line: {}", end_line);
+            match range {
+                ReadRange::Lines { start, end } => {
+                    println!("  start_line: {}", start);
+                    println!("  end_line: {}", end);
+                }
+                ReadRange::Tail(n) => println!("  tail: {}", n),
+                ReadRange::WholeFile => println!("  whole_file: true"),
+            }
+            if let Some(denial) = sandbox.deny(&[path]) {
+                return Ok(denial);
+            }
 
-            // Update files_searched in cur_output
-            if !cur_output.files_searched.contains(&path.to_string()) {
-                cur_output.files_searched.push(path.to_string());
+            // Update files_searched
+            if !files_searched.contains(&path.to_string()) {
+                files_searched.push(path.to_string());
             }
 
-            ReadTool::read(path, start_line, end_line).await
+            ReadTool::read(path, range).await
+        }
+        "memory_lookup" => {
+            let memory =
+                memory.ok_or_else(|| anyhow::anyhow!("Memory is not enabled for this run"))?;
+            let query = function_args["query"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Missing 'query' parameter"))?;
+
+            println!("\n[Tool Call: memory_lookup]");
+            println!("  query: \"{}\"", query);
+
+            Ok(MemoryLookupTool::lookup(memory, query, model))
+        }
+        "view_image" => {
+            let viewer =
+                viewer.ok_or_else(|| anyhow::anyhow!("There are no images to view in this run"))?;
+            let path = function_args["path"]
+                .as_str()
+                .ok_or_else(|| anyhow::anyhow!("Missing 'path' parameter"))?;
+            let document = function_args["document"].as_str();
+            let question = function_args["question"].as_str();
+
+            println!("\n[Tool Call: view_image]");
+            println!("  path: {}", path);
+            if let Some(document) = document {
+                println!("  document: {}", document);
+            }
+            if let Some(question) = question {
+                println!("  question: \"{}\"", question);
+            }
+            let image = resolve_image_path(path, document);
+            let image_path = image.to_string_lossy().to_string();
+            if let Some(denial) = sandbox.deny(&[&image_path]) {
+                return Ok(denial);
+            }
+
+            merge_files_searched(files_searched, vec![image_path]);
+            ViewImageTool::view(viewer, &image, question).await
         }
         _ => Err(anyhow::anyhow!("Unknown tool: {}", name)),
     }
 }
 
-/// Print a summary of the tool response
-pub fn print_tool_summary(response: &str) {
+/// Count the `<chunk>` tags in a tool response and the lines they contain
+pub fn tool_response_stats(response: &str) -> (usize, usize) {
     // Count the number of <chunk> tags
     let chunk_count = response.matches("<chunk").count();
 
@@ -155,6 +340,17 @@ pub fn print_tool_summary(response: &str) {
         })
         .sum();
 
+    if chunk_count > 0 {
+        (chunk_count, total_lines)
+    } else {
+        (0, response.lines().count())
+    }
+}
+
+/// Print a summary of the tool response
+pub fn print_tool_summary(response: &str) {
+    let (chunk_count, total_lines) = tool_response_stats(response);
+
     if chunk_count > 0 {
         println!(
             "  → Returned {} chunk(s) with {} total lines",
@@ -163,6 +359,22 @@ pub fn print_tool_summary(response: &str) {
     } else if response.contains("No matches found") {
         println!("  → No matches found");
     } else {
-        println!("  → Returned {} lines", response.lines().count());
+        println!("  → Returned {} lines", total_lines);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_files_searched_keeps_first_occurrence_order() {
+        let mut files_searched = vec!["a.md".to_string()];
+        merge_files_searched(
+            &mut files_searched,
+            vec!["b.md".to_string(), "a.md".to_string(), "c.md".to_string()],
+        );
+        merge_files_searched(&mut files_searched, vec!["c.md".to_string()]);
+        assert_eq!(files_searched, vec!["a.md", "b.md", "c.md"]);
     }
 }
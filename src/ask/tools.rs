@@ -3,13 +3,23 @@ use async_openai::types::chat::{ChatCompletionTool, ChatCompletionTools, Functio
 use async_openai::types::responses::{FunctionTool, Tool};
 use model2vec_rs::model::StaticModel;
 use serde_json::json;
-
-use crate::search::{SearchConfig, SearchResult, search_files};
-
-#[cfg(feature = "workspace")]
-use crate::workspace::{Workspace, store::RankedLine};
-
-#[cfg(feature = "workspace")]
+use std::path::Path;
+
+use crate::ask::memory::{AgentMemory, format_memory_matches};
+use crate::ask::session_cache::{self, CachedSearch};
+use crate::ask::vision::ImageViewer;
+use crate::grep::{GrepOptions, grep_paths};
+use crate::search::{
+    DEFAULT_MAX_LINE_CHARS, SearchConfig, SearchResult, embed_query, locate_long_match_spans,
+    search_documents,
+};
+
+#[cfg(feature = "workspace-lite")]
+use crate::cmds::search::ranked_line_to_search_result;
+#[cfg(feature = "workspace-lite")]
+use crate::workspace::{Workspace, context::ResultContext};
+
+#[cfg(feature = "workspace-lite")]
 use crate::search::search_with_workspace;
 
 /// Trait for tools that can work with both Chat Completions and Responses API
@@ -31,6 +41,33 @@ fn create_function_tool(name: &str, description: &str, parameters: serde_json::V
     })
 }
 
+/// Wrap lines `start..end` of a file in the `<chunk>` tag the agent reads and cites from
+pub(crate) fn format_chunk<S: AsRef<str>>(
+    file: &str,
+    start: usize,
+    end: usize,
+    lines: &[S],
+) -> String {
+    let source = source_attribute(file);
+    let mut chunk = format!("<chunk file={file} start={start} end={end}{source}>\n");
+    for line in lines {
+        chunk.push_str(line.as_ref());
+        chunk.push('\n');
+    }
+    chunk.push_str("</chunk>\n");
+    chunk
+}
+
+/// The ` source=URL` attribute of the chunks of a parsed document that was downloaded, so the
+/// agent can cite the original; empty for other files
+fn source_attribute(file: &str) -> String {
+    crate::metadata::source_of(file)
+        .map(|url| format!(" source={url}"))
+        .unwrap_or_default()
+}
+
+/// Results as chunks for the agent, long lines cut to snippets around the match so they do
+/// not crowd its context
 fn format_search_results(results: &[SearchResult]) -> String {
     let mut response = String::new();
 
@@ -39,12 +76,13 @@ fn format_search_results(results: &[SearchResult]) -> String {
         let distance = search_result.distance;
         let start = search_result.start;
         let end = search_result.end;
+        let source = source_attribute(&filename);
 
         response.push_str(&format!(
-            "<chunk file={filename} start={start} end={end} distance={distance}>\n"
+            "<chunk file={filename} start={start} end={end} distance={distance}{source}>\n"
         ));
 
-        for line in search_result.lines.iter() {
+        for line in search_result.snippet_lines(DEFAULT_MAX_LINE_CHARS) {
             response.push_str(&format!("{line}\n"));
         }
 
@@ -54,45 +92,6 @@ fn format_search_results(results: &[SearchResult]) -> String {
     response
 }
 
-#[cfg(feature = "workspace")]
-fn format_ranked_lines(ranked_lines: &[RankedLine], n_lines: usize) -> String {
-    let mut response = String::new();
-
-    for ranked_line in ranked_lines {
-        let filename = &ranked_line.path;
-        let distance = ranked_line.distance;
-        // ranked_line.line_number is 0-based from database
-        let match_line_number = ranked_line.line_number as usize;
-
-        // Calculate context range (working with 0-based indices)
-        let start = match_line_number.saturating_sub(n_lines);
-        let end = match_line_number + n_lines + 1;
-
-        response.push_str(&format!(
-            "<chunk file={filename} start={start} end={end} distance={distance}>\n"
-        ));
-
-        // For workspace results, we need to read the file to get context lines
-        // This is acceptable since we're only doing this for the final results
-        if let Ok(content) = std::fs::read_to_string(filename) {
-            let lines: Vec<&str> = content.lines().collect();
-            let actual_start = start;
-            let actual_end = end.min(lines.len());
-
-            for line in lines[actual_start..actual_end].iter() {
-                response.push_str(&format!("{line}\n"));
-            }
-        } else {
-            // Fallback: indicate that the file couldn't be read
-            response.push_str("[Error: Could not read file content]");
-        }
-
-        response.push_str("</chunk>\n");
-    }
-
-    response
-}
-
 pub struct SearchTool;
 
 impl AgentTool for SearchTool {
@@ -125,6 +124,11 @@ impl AgentTool for SearchTool {
                                     "type": "integer",
                                     "description": "Number of top results to return",
                                     "default": 3
+                                },
+                                "not": {
+                                    "type": "array",
+                                    "items": { "type": "string" },
+                                    "description": "Topics to exclude: lines similar to any of these texts are ranked lower (e.g. [\"python the snake\"] when searching for the programming language)"
                                 }
                             },
                             "required": [],
@@ -175,6 +179,11 @@ impl AgentTool for SearchTool {
                             "type": "integer",
                             "description": "Number of top results to return",
                             "default": 3
+                        },
+                        "not": {
+                            "type": "array",
+                            "items": { "type": "string" },
+                            "description": "Topics to exclude: lines similar to any of these texts are ranked lower (e.g. [\"python the snake\"] when searching for the programming language)"
                         }
                     },
                     "required": [],
@@ -225,37 +234,172 @@ impl SearchTool {
             ));
         }
 
+        // Repeated searches within one run are answered from the session cache
+        let key = session_cache::result_key(files, &query, &config, workspace_name);
+        let search = match session_cache::cached_result(&key) {
+            Some(cached) => cached,
+            None => {
+                let search =
+                    Self::run_search(files, &query, model, &config, workspace_name).await?;
+                session_cache::store_result(key, search.clone());
+                search
+            }
+        };
+
+        // Track files that were searched (have results)
+        for file in search.files {
+            if !files_searched.contains(&file) {
+                files_searched.push(file);
+            }
+        }
+        Ok(search.output)
+    }
+
+    async fn run_search(
+        files: &[String],
+        query: &str,
+        model: &StaticModel,
+        config: &SearchConfig,
+        workspace_name: Option<&str>,
+    ) -> Result<CachedSearch> {
         // Handle file input with optional workspace integration
-        #[cfg(feature = "workspace")]
+        #[cfg(feature = "workspace-lite")]
         if Workspace::active(workspace_name).is_ok() {
             // Workspace mode: use persisted line embeddings for speed
             let ranked_lines =
-                search_with_workspace(files, &query, model, &config, workspace_name).await?;
+                search_with_workspace(files, query, model, config, workspace_name).await?;
+
+            // Convert results to SearchResult format, reading their context, and format
+            let context = ResultContext::new(workspace_name);
+            context.prefetch(ranked_lines.iter().map(|line| line.path.as_str()));
+            let mut search_results: Vec<SearchResult> = ranked_lines
+                .iter()
+                .filter_map(|ranked_line| {
+                    ranked_line_to_search_result(ranked_line, config.n_lines, &context)
+                })
+                .collect();
+            let query_embedding = embed_query(model, query);
+            locate_long_match_spans(
+                &mut search_results,
+                DEFAULT_MAX_LINE_CHARS,
+                query,
+                &query_embedding,
+                model,
+                config.ignore_case,
+            );
+            return Ok(CachedSearch {
+                output: format_search_results(&search_results),
+                files: ranked_lines.iter().map(|r| r.path.clone()).collect(),
+            });
+        }
+        #[cfg(not(feature = "workspace-lite"))]
+        let _ = workspace_name;
+
+        // Embeddings of files already searched in this run are reused
+        let documents = session_cache::load_documents(files, model, config.ignore_case)?;
+        let query_embedding = embed_query(model, query);
+        let mut search_results = search_documents(&documents, &query_embedding, config);
+        locate_long_match_spans(
+            &mut search_results,
+            DEFAULT_MAX_LINE_CHARS,
+            query,
+            &query_embedding,
+            model,
+            config.ignore_case,
+        );
+
+        Ok(CachedSearch {
+            output: format_search_results(&search_results),
+            files: search_results.iter().map(|r| r.filename.clone()).collect(),
+        })
+    }
+}
 
-            // Track files that were searched (have results)
-            for ranked_line in &ranked_lines {
-                if !files_searched.contains(&ranked_line.path) {
-                    files_searched.push(ranked_line.path.clone());
-                }
+pub struct SearchInFileTool;
+
+const SEARCH_IN_FILE_TOOL_DESCRIPTION: &str = "Semantic keyword search restricted to a single file. Use this to drill into one document once you know it is relevant, so matches from other files do not crowd out its results.";
+
+fn search_in_file_tool_parameters() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "path": {
+                "type": "string",
+                "description": "The file to search, one of the files being searched"
+            },
+            "query": {
+                "type": "string",
+                "description": "The search query. Works best as a comma separated list of keywords."
+            },
+            "top_k": {
+                "type": "integer",
+                "description": "Number of top results to return",
+                "default": 3
+            },
+            "n_lines": {
+                "type": "integer",
+                "description": "Number of context lines to include before and after each match",
+                "default": 5
             }
+        },
+        "required": ["path", "query"],
+        "additionalProperties": false
+    })
+}
 
-            // Convert results to SearchResult format and format
-            let formatted = format_ranked_lines(&ranked_lines, config.n_lines);
-            return Ok(formatted);
-        }
+impl AgentTool for SearchInFileTool {
+    fn chat_definition() -> Result<ChatCompletionTools> {
+        Ok(ChatCompletionTools::Function(ChatCompletionTool {
+            function: FunctionObjectArgs::default()
+                .name("search_in_file")
+                .description(SEARCH_IN_FILE_TOOL_DESCRIPTION)
+                .parameters(search_in_file_tool_parameters())
+                .strict(false)
+                .build()?,
+        }))
+    }
 
-        let search_results = search_files(files, &query, model, &config)?;
+    fn responses_definition() -> Result<Tool> {
+        Ok(create_function_tool(
+            "search_in_file",
+            SEARCH_IN_FILE_TOOL_DESCRIPTION,
+            search_in_file_tool_parameters(),
+        ))
+    }
+}
 
-        // Track files that were searched (have results)
-        for result in &search_results {
-            if !files_searched.contains(&result.filename) {
-                files_searched.push(result.filename.clone());
-            }
+impl SearchInFileTool {
+    /// Search only `path`, which must be one of `files`. In a workspace the stored embeddings
+    /// are filtered to the file, so nothing else is embedded or ranked.
+    pub async fn search(
+        files: &[String],
+        path: &str,
+        query: &str,
+        model: &StaticModel,
+        config: SearchConfig,
+        files_searched: &mut Vec<String>,
+        workspace_name: Option<&str>,
+    ) -> Result<String> {
+        if !files.iter().any(|f| f == path) {
+            return Err(anyhow::anyhow!(
+                "'{path}' is not one of the files being searched"
+            ));
         }
 
-        let formatted = format_search_results(&search_results);
-
-        Ok(formatted)
+        let response = SearchTool::search(
+            &[path.to_string()],
+            query,
+            model,
+            config,
+            files_searched,
+            workspace_name,
+        )
+        .await?;
+
+        if response.is_empty() {
+            return Ok("No matches found.".to_string());
+        }
+        Ok(response)
     }
 }
 
@@ -266,80 +410,272 @@ impl AgentTool for ReadTool {
         Ok(ChatCompletionTools::Function(ChatCompletionTool {
             function: FunctionObjectArgs::default()
                 .name("read")
-                .description("Read a specific range of lines from a file. Returns the content between start_line and end_line.")
-                .parameters(json!({
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "The file path to read from"
-                        },
-                        "start_line": {
-                            "type": "integer",
-                            "description": "The starting line number (0-based)"
-                        },
-                        "end_line": {
-                            "type": "integer",
-                            "description": "The ending line number (exclusive, 0-based)"
-                        }
-                    },
-                    "required": ["path", "start_line", "end_line"],
-                    "additionalProperties": false
-                }))
+                .description(READ_TOOL_DESCRIPTION)
+                .parameters(read_tool_parameters())
                 .strict(false)
                 .build()?,
         }))
     }
 
     fn responses_definition() -> Result<Tool> {
-        let parameters = json!({
-            "type": "object",
-            "properties": {
-                "path": {
-                    "type": "string",
-                    "description": "The file path to read from"
-                },
-                "start_line": {
-                    "type": "integer",
-                    "description": "The starting line number (0-based)"
-                },
-                "end_line": {
-                    "type": "integer",
-                    "description": "The ending line number (exclusive, 0-based)"
-                }
-            },
-            "required": ["path", "start_line", "end_line"],
-            "additionalProperties": false
-        });
-
         Ok(create_function_tool(
             "read",
-            "Read a specific range of lines from a file. Returns the content between start_line and end_line.",
-            parameters,
+            READ_TOOL_DESCRIPTION,
+            read_tool_parameters(),
         ))
     }
 }
 
+/// Maximum number of bytes returned by a single read, to keep whole-file reads bounded
+pub const READ_MAX_BYTES: usize = 64 * 1024;
+
+const READ_TOOL_DESCRIPTION: &str = "Read lines from a file. Pass start_line/end_line for a range, a negative start_line (e.g. -50) for the last N lines, or whole_file=true for the entire file (capped in size). The response includes the file's total line count so you can plan further reads.";
+
+fn read_tool_parameters() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "path": {
+                "type": "string",
+                "description": "The file path to read from"
+            },
+            "start_line": {
+                "type": "integer",
+                "description": "The starting line number (0-based). Negative values read the last N lines, e.g. -50"
+            },
+            "end_line": {
+                "type": "integer",
+                "description": "The ending line number (exclusive, 0-based). Ignored for tail and whole-file reads"
+            },
+            "whole_file": {
+                "type": "boolean",
+                "description": "Read the whole file (output is truncated if the file is too large)",
+                "default": false
+            }
+        },
+        "required": ["path"],
+        "additionalProperties": false
+    })
+}
+
+/// Which lines of a file a read should return
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadRange {
+    /// Lines `start..end` (0-based, end exclusive)
+    Lines { start: usize, end: usize },
+    /// The last N lines
+    Tail(usize),
+    /// The whole file
+    WholeFile,
+}
+
+impl ReadRange {
+    /// Build a range from the tool arguments. `start_line` may be an integer or a string such
+    /// as "-50"; a negative start means a tail read.
+    pub fn from_args(args: &serde_json::Value) -> Result<Self> {
+        if args["whole_file"].as_bool().unwrap_or(false) {
+            return Ok(ReadRange::WholeFile);
+        }
+
+        let start = parse_line_arg(&args["start_line"]);
+        let end = parse_line_arg(&args["end_line"]);
+
+        match (start, end) {
+            (Some(start), _) if start < 0 => Ok(ReadRange::Tail(start.unsigned_abs() as usize)),
+            (Some(start), Some(end)) => Ok(ReadRange::Lines {
+                start: start as usize,
+                end: end.max(0) as usize,
+            }),
+            (Some(_), None) => Err(anyhow::anyhow!("Missing 'end_line' parameter")),
+            (None, _) => Err(anyhow::anyhow!(
+                "Missing 'start_line' parameter (or set 'whole_file' to true)"
+            )),
+        }
+    }
+}
+
+fn parse_line_arg(value: &serde_json::Value) -> Option<i64> {
+    value
+        .as_i64()
+        .or_else(|| value.as_str().and_then(|s| s.trim().parse::<i64>().ok()))
+}
+
 impl ReadTool {
-    pub async fn read(path: &str, start_line: usize, end_line: usize) -> Result<String> {
-        let content = std::fs::read_to_string(path)?;
+    pub async fn read(path: &str, range: ReadRange) -> Result<String> {
+        let content = std::fs::read_to_string(crate::archive::local_path(path))?;
         let lines: Vec<&str> = content.lines().collect();
-        let actual_end = end_line.min(lines.len());
-        let selected_lines = &lines[start_line..actual_end];
+        let total_lines = lines.len();
+
+        // Clamp the requested range to the file so out-of-bounds reads never panic
+        let (start, end) = match range {
+            ReadRange::Lines { start, end } => {
+                let end = end.min(total_lines);
+                (start.min(end), end)
+            }
+            ReadRange::Tail(n) => (total_lines.saturating_sub(n), total_lines),
+            ReadRange::WholeFile => (0, total_lines),
+        };
+
+        // Cap the number of bytes returned, cutting at a line boundary
+        let mut actual_end = start;
+        let mut bytes = 0;
+        for line in &lines[start..end] {
+            if bytes + line.len() + 1 > READ_MAX_BYTES && actual_end > start {
+                break;
+            }
+            bytes += line.len() + 1;
+            actual_end += 1;
+        }
+        let truncated = actual_end < end;
+        let mut text = lines[start..actual_end].join("\n");
+
+        // A single line longer than the cap is cut at the cap itself
+        let line_truncated = text.len() > READ_MAX_BYTES;
+        if line_truncated {
+            let mut cut = READ_MAX_BYTES;
+            while !text.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            text.truncate(cut);
+        }
 
         // Build the response with the `<chunk>` tags
         let mut response = String::new();
         response.push_str(&format!(
-            "<chunk file={} start={} end={}>\n",
-            path, start_line, actual_end
+            "<chunk file={} start={} end={} total_lines={}{}>\n",
+            path,
+            start,
+            actual_end,
+            total_lines,
+            source_attribute(path)
         ));
-        response.push_str(&selected_lines.join("\n"));
+        response.push_str(&text);
         response.push_str("</chunk>\n");
+        if line_truncated {
+            response.push_str(&format!(
+                "[Line {} truncated at {} bytes.]\n",
+                start, READ_MAX_BYTES
+            ));
+        }
+        if truncated {
+            response.push_str(&format!(
+                "[Output truncated at {} bytes. Read from start_line={} to continue.]\n",
+                READ_MAX_BYTES, actual_end
+            ));
+        }
 
         Ok(response)
     }
 }
 
+pub struct MemoryLookupTool;
+
+const MEMORY_LOOKUP_TOOL_DESCRIPTION: &str = "Check what was found for similar questions in earlier sessions. Returns previous questions with the files that answered them and a short summary of each answer. Use this before searching, then read the listed files to confirm and cite.";
+
+fn memory_lookup_tool_parameters() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "query": {
+                "type": "string",
+                "description": "The question or topic to look up"
+            }
+        },
+        "required": ["query"],
+        "additionalProperties": false
+    })
+}
+
+impl AgentTool for MemoryLookupTool {
+    fn chat_definition() -> Result<ChatCompletionTools> {
+        Ok(ChatCompletionTools::Function(ChatCompletionTool {
+            function: FunctionObjectArgs::default()
+                .name("memory_lookup")
+                .description(MEMORY_LOOKUP_TOOL_DESCRIPTION)
+                .parameters(memory_lookup_tool_parameters())
+                .strict(false)
+                .build()?,
+        }))
+    }
+
+    fn responses_definition() -> Result<Tool> {
+        Ok(create_function_tool(
+            "memory_lookup",
+            MEMORY_LOOKUP_TOOL_DESCRIPTION,
+            memory_lookup_tool_parameters(),
+        ))
+    }
+}
+
+impl MemoryLookupTool {
+    pub fn lookup(memory: &AgentMemory, query: &str, model: &StaticModel) -> String {
+        format_memory_matches(&memory.lookup(query, model))
+    }
+}
+
+pub struct ViewImageTool;
+
+const VIEW_IMAGE_TOOL_DESCRIPTION: &str = "Look at an image: a figure, chart, diagram or scan. Use this when the answer may be in a picture, such as an image file among the files being searched or an image a parsed document links to with ![...](path). Returns a description of the image with its text transcribed.";
+
+fn view_image_tool_parameters() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "path": {
+                "type": "string",
+                "description": "The image file, or the link target of an image in a document"
+            },
+            "document": {
+                "type": "string",
+                "description": "The document linking to the image, when path is a link target relative to it"
+            },
+            "question": {
+                "type": "string",
+                "description": "What you need to know from the image"
+            }
+        },
+        "required": ["path"],
+        "additionalProperties": false
+    })
+}
+
+impl AgentTool for ViewImageTool {
+    fn chat_definition() -> Result<ChatCompletionTools> {
+        Ok(ChatCompletionTools::Function(ChatCompletionTool {
+            function: FunctionObjectArgs::default()
+                .name("view_image")
+                .description(VIEW_IMAGE_TOOL_DESCRIPTION)
+                .parameters(view_image_tool_parameters())
+                .strict(false)
+                .build()?,
+        }))
+    }
+
+    fn responses_definition() -> Result<Tool> {
+        Ok(create_function_tool(
+            "view_image",
+            VIEW_IMAGE_TOOL_DESCRIPTION,
+            view_image_tool_parameters(),
+        ))
+    }
+}
+
+impl ViewImageTool {
+    /// Describe the image at `path`, in an `<image>` tag naming it for citations
+    pub async fn view(
+        viewer: &ImageViewer<'_>,
+        path: &Path,
+        question: Option<&str>,
+    ) -> Result<String> {
+        let description = viewer.describe(path, question).await?;
+        Ok(format!(
+            "<image file={}>\n{}\n</image>\n",
+            path.display(),
+            description.trim()
+        ))
+    }
+}
+
 pub struct GrepTool;
 
 impl AgentTool for GrepTool {
@@ -360,7 +696,7 @@ impl AgentTool for GrepTool {
                             "items": {
                                 "type": "string"
                             },
-                            "description": "Optional list of specific file paths to search. If empty or not provided, searches all available files.",
+                            "description": "Optional list of specific file or directory paths to search (directories are searched recursively). If empty or not provided, searches all available files.",
                             "default": []
                         },
                         "is_regex": {
@@ -400,7 +736,7 @@ impl AgentTool for GrepTool {
                     "items": {
                         "type": "string"
                     },
-                    "description": "Optional list of specific file paths to search. If empty or not provided, searches all available files.",
+                    "description": "Optional list of specific file or directory paths to search (directories are searched recursively). If empty or not provided, searches all available files.",
                     "default": []
                 },
                 "is_regex": {
@@ -440,143 +776,115 @@ impl GrepTool {
         case_sensitive: bool,
         context_lines: usize,
     ) -> Result<String> {
-        use grep::regex::RegexMatcher;
-        use grep::searcher::{BinaryDetection, SearcherBuilder};
-        use regex;
-        use std::collections::HashMap;
-        use std::path::Path;
-
         // Determine which files to search
-        let files_to_search = if let Some(paths) = file_paths {
-            if paths.is_empty() {
-                all_files.to_vec()
-            } else {
-                paths
-            }
-        } else {
-            all_files.to_vec()
+        let files_to_search = match file_paths {
+            Some(paths) if !paths.is_empty() => paths,
+            _ => all_files.to_vec(),
         };
 
-        if files_to_search.is_empty() {
-            return Err(anyhow::anyhow!("No files to search"));
-        }
-
-        // Build the regex matcher
-        let pattern_with_flags = if is_regex {
-            if case_sensitive {
-                pattern.to_string()
-            } else {
-                format!("(?i){}", pattern)
-            }
-        } else {
-            // Escape special regex characters for literal search
-            let escaped = regex::escape(pattern);
-            if case_sensitive {
-                escaped
-            } else {
-                format!("(?i){}", escaped)
-            }
+        let options = GrepOptions {
+            fixed_strings: !is_regex,
+            case_sensitive,
+            context_lines,
+            ..Default::default()
         };
+        let pattern = pattern.to_string();
+        // The directory walk and search run on their own threads
+        let results =
+            tokio::task::spawn_blocking(move || grep_paths(&files_to_search, &pattern, &options))
+                .await??;
+
+        for error in &results.errors {
+            eprintln!("Warning: Could not search {error}");
+        }
+        if results.blocks.is_empty() {
+            return Ok("No matches found.".to_string());
+        }
 
-        let matcher = RegexMatcher::new(&pattern_with_flags)
-            .map_err(|e| anyhow::anyhow!("Invalid regex pattern: {}", e))?;
+        Ok(results
+            .blocks
+            .iter()
+            .map(|block| format_chunk(&block.file, block.start, block.end(), &block.lines))
+            .collect())
+    }
+}
 
-        // Build the searcher with context lines
-        let mut searcher = SearcherBuilder::new()
-            .binary_detection(BinaryDetection::quit(b'\x00'))
-            .line_number(true)
-            .build();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
 
-        // Store results per file
-        let mut all_results: HashMap<String, Vec<GrepMatch>> = HashMap::new();
+    fn write_lines(n: usize) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        for i in 0..n {
+            writeln!(file, "line {i}").unwrap();
+        }
+        file
+    }
 
-        // Search each file
-        for file_path in &files_to_search {
-            let path = Path::new(file_path);
+    #[test]
+    fn test_read_range_from_args() {
+        let range = ReadRange::from_args(&json!({"start_line": 2, "end_line": 5})).unwrap();
+        assert_eq!(range, ReadRange::Lines { start: 2, end: 5 });
 
-            // Skip if file doesn't exist or isn't a file
-            if !path.exists() || !path.is_file() {
-                continue;
-            }
+        let tail = ReadRange::from_args(&json!({"start_line": "-50"})).unwrap();
+        assert_eq!(tail, ReadRange::Tail(50));
 
-            let mut matches = Vec::new();
-            let mut sink = GrepSink::new(&mut matches);
+        let whole = ReadRange::from_args(&json!({"whole_file": true})).unwrap();
+        assert_eq!(whole, ReadRange::WholeFile);
 
-            // Perform the search
-            if let Err(e) = searcher.search_path(&matcher, path, &mut sink) {
-                // Skip files that can't be read (binary, permissions, etc.)
-                eprintln!("Warning: Could not search {}: {}", file_path, e);
-                continue;
-            }
+        assert!(ReadRange::from_args(&json!({})).is_err());
+    }
 
-            if !matches.is_empty() {
-                all_results.insert(file_path.clone(), matches);
-            }
-        }
+    #[tokio::test]
+    async fn test_read_clamps_past_eof() {
+        let file = write_lines(3);
+        let path = file.path().to_str().unwrap();
 
-        // Format the results
-        if all_results.is_empty() {
-            return Ok("No matches found.".to_string());
-        }
+        let response = ReadTool::read(path, ReadRange::Lines { start: 10, end: 20 })
+            .await
+            .unwrap();
 
-        let mut response = String::new();
+        assert!(response.contains("start=3 end=3 total_lines=3"));
+    }
 
-        for (file_path, matches) in all_results.iter() {
-            // Read the file to get context lines
-            let content = match std::fs::read_to_string(file_path) {
-                Ok(c) => c,
-                Err(_) => continue,
-            };
-            let lines: Vec<&str> = content.lines().collect();
-
-            for grep_match in matches {
-                // Calculate context range (0-based indexing)
-                let match_line_idx = grep_match.line_number - 1; // Convert to 0-based
-                let start = match_line_idx.saturating_sub(context_lines);
-                let end = (match_line_idx + context_lines + 1).min(lines.len());
-
-                response.push_str(&format!(
-                    "<chunk file={} start={} end={}>\n",
-                    file_path, start, end
-                ));
-
-                for line in &lines[start..end] {
-                    response.push_str(&format!("{}\n", line));
-                }
+    #[tokio::test]
+    async fn test_read_tail_and_whole_file() {
+        let file = write_lines(10);
+        let path = file.path().to_str().unwrap();
 
-                response.push_str("</chunk>\n");
-            }
-        }
+        let tail = ReadTool::read(path, ReadRange::Tail(2)).await.unwrap();
+        assert!(tail.contains("start=8 end=10 total_lines=10"));
+        assert!(tail.contains("line 9"));
+        assert!(!tail.contains("line 7"));
 
-        Ok(response)
+        let whole = ReadTool::read(path, ReadRange::WholeFile).await.unwrap();
+        assert!(whole.contains("start=0 end=10"));
     }
-}
 
-struct GrepMatch {
-    line_number: usize,
-}
+    #[tokio::test]
+    async fn test_read_whole_file_is_capped() {
+        let file = write_lines(20_000);
+        let path = file.path().to_str().unwrap();
 
-struct GrepSink<'a> {
-    matches: &'a mut Vec<GrepMatch>,
-}
+        let response = ReadTool::read(path, ReadRange::WholeFile).await.unwrap();
 
-impl<'a> GrepSink<'a> {
-    fn new(matches: &'a mut Vec<GrepMatch>) -> Self {
-        GrepSink { matches }
+        assert!(response.contains("Output truncated"));
+        assert!(response.len() < READ_MAX_BYTES + 512);
     }
-}
 
-impl<'a> grep_searcher::Sink for GrepSink<'a> {
-    type Error = std::io::Error;
+    #[tokio::test]
+    async fn test_read_oversized_line_is_capped() {
+        let mut file = tempfile::NamedTempFile::new().expect("Failed to create temp file");
+        writeln!(file, "{}", "あ".repeat(READ_MAX_BYTES)).unwrap();
+        writeln!(file, "next").unwrap();
+        let path = file.path().to_str().unwrap();
 
-    fn matched(
-        &mut self,
-        _searcher: &grep_searcher::Searcher,
-        mat: &grep_searcher::SinkMatch<'_>,
-    ) -> Result<bool, Self::Error> {
-        self.matches.push(GrepMatch {
-            line_number: mat.line_number().unwrap_or(0) as usize,
-        });
-        Ok(true)
+        let response = ReadTool::read(path, ReadRange::WholeFile).await.unwrap();
+
+        assert!(response.contains("start=0 end=1"));
+        assert!(response.contains("[Line 0 truncated"));
+        assert!(response.contains("Output truncated"));
+        assert!(response.len() < READ_MAX_BYTES + 512);
     }
 }
@@ -0,0 +1,195 @@
+//! The `view_image` tool: figures, charts and diagrams are sent to a vision-capable model,
+//! which describes them for the agent. The agent can look at the images among its files and
+//! those `parse --save-assets` saved next to a parsed document, which its markdown links to.
+//!
+//! Images cannot be redacted, so the tool is not offered while redaction is on.
+use anyhow::{Context, Result, bail};
+use async_openai::Client;
+use async_openai::config::OpenAIConfig;
+use async_openai::types::chat::{
+    ChatCompletionRequestMessageContentPartImage, ChatCompletionRequestMessageContentPartText,
+    ChatCompletionRequestUserMessageArgs, ChatCompletionRequestUserMessageContentPart,
+    CreateChatCompletionRequestArgs, ImageDetail, ImageUrl,
+};
+use base64::Engine;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::ask::error::AskError;
+use crate::ask::redaction;
+use crate::ask::sampling::WithSampling;
+use crate::telemetry::{Operation, measure};
+use crate::usage::record_tokens;
+
+/// Image formats vision models accept
+pub const IMAGE_EXTENSIONS: [&str; 5] = ["png", "jpg", "jpeg", "gif", "webp"];
+
+/// Largest image sent, the limit of the OpenAI API
+pub const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Suffix of the directory next to a parsed document's markdown that holds its images
+const ASSETS_DIR_SUFFIX: &str = ".assets";
+
+const VISION_PROMPT: &str = "You describe an image from a document for an assistant that \
+cannot see it. Transcribe any text, labels, axis titles, legends and numbers exactly, and say \
+what a chart, table or diagram shows: its kind, what is compared, and the trends or values that \
+stand out. If a question is given, answer it from the image first. Never guess at what is not \
+visible.";
+
+/// Set from `vision_model` in the config file, sending images to another model than the agent's
+static VISION_MODEL: OnceLock<String> = OnceLock::new();
+
+pub fn set_vision_model(model: String) {
+    let _ = VISION_MODEL.set(model);
+}
+
+/// Whether `path` names an image the tool can send
+pub fn is_image(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// The directory of images saved next to a parsed document's markdown, if it has one
+pub fn assets_dir(markdown: &str) -> Option<PathBuf> {
+    let prefix = markdown.strip_suffix(".md")?;
+    let dir = PathBuf::from(format!("{prefix}{ASSETS_DIR_SUFFIX}"));
+    dir.is_dir().then_some(dir)
+}
+
+/// Whether the agent gets the `view_image` tool: some of `files` are images or have saved
+/// images, and redaction is off
+pub fn offers_images(files: &[String]) -> bool {
+    !redaction::is_active() && files.iter().any(|f| is_image(f) || assets_dir(f).is_some())
+}
+
+/// Where the image `path` is. A relative path is taken from the directory of `document`, the
+/// file linking to the image, when one is given.
+pub fn resolve_image_path(path: &str, document: Option<&str>) -> PathBuf {
+    let path = Path::new(path);
+    match document.and_then(|d| Path::new(d).parent()) {
+        Some(dir) if path.is_relative() && !path.exists() => dir.join(path),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Describes images with a vision-capable model
+pub struct ImageViewer<'a> {
+    client: &'a Client<OpenAIConfig>,
+    model: &'a str,
+}
+
+impl<'a> ImageViewer<'a> {
+    /// A viewer sending images to `vision_model` from the config file, or else `api_model`
+    pub fn new(client: &'a Client<OpenAIConfig>, api_model: &'a str) -> Self {
+        Self {
+            client,
+            model: VISION_MODEL.get().map_or(api_model, String::as_str),
+        }
+    }
+
+    /// The model's description of the image at `path`, answering `question` if given
+    pub async fn describe(&self, path: &Path, question: Option<&str>) -> Result<String> {
+        let mut prompt = VISION_PROMPT.to_string();
+        if let Some(question) = question {
+            prompt.push_str(&format!("\n\nQuestion: {question}"));
+        }
+        let content = vec![
+            ChatCompletionRequestUserMessageContentPart::Text(
+                ChatCompletionRequestMessageContentPartText { text: prompt },
+            ),
+            ChatCompletionRequestUserMessageContentPart::ImageUrl(
+                ChatCompletionRequestMessageContentPartImage {
+                    image_url: ImageUrl {
+                        url: data_url(path)?,
+                        detail: Some(ImageDetail::High),
+                    },
+                },
+            ),
+        ];
+        let request = CreateChatCompletionRequestArgs::default()
+            .model(self.model)
+            .messages(vec![
+                ChatCompletionRequestUserMessageArgs::default()
+                    .content(content)
+                    .build()?
+                    .into(),
+            ])
+            .with_sampling()
+            .build()?;
+
+        let attributes = [("model", self.model), ("api", "chat")];
+        let response = measure(
+            Operation::LlmRequest,
+            &attributes,
+            self.client.chat().create(request),
+        )
+        .await
+        .map_err(AskError::from)?;
+        if let Some(usage) = &response.usage {
+            record_tokens(
+                self.model,
+                usage.prompt_tokens.into(),
+                usage.completion_tokens.into(),
+            );
+        }
+        response
+            .choices
+            .first()
+            .and_then(|choice| choice.message.content.clone())
+            .ok_or_else(|| AskError::EmptyResponse.into())
+    }
+}
+
+/// The image at `path` inlined as a `data:` URL
+fn data_url(path: &Path) -> Result<String> {
+    let name = path.to_string_lossy();
+    if !is_image(&name) {
+        bail!(
+            "'{name}' is not an image (supported: {})",
+            IMAGE_EXTENSIONS.join(", ")
+        );
+    }
+    let size = std::fs::metadata(path)
+        .with_context(|| format!("Cannot read {name}"))?
+        .len();
+    if size > MAX_IMAGE_BYTES {
+        bail!("'{name}' is {size} bytes, over the {MAX_IMAGE_BYTES} bytes an image may have");
+    }
+    let bytes = std::fs::read(path).with_context(|| format!("Cannot read {name}"))?;
+    let mime = mime_guess::from_path(path).first_or_octet_stream();
+    Ok(format!(
+        "data:{mime};base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_images_offered() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let markdown = dir.path().join("report.pdf.1a2b.md");
+        std::fs::write(&markdown, "![chart](report.pdf.1a2b.assets/img_p1_1.png)").unwrap();
+        let markdown = markdown.to_string_lossy().to_string();
+        assert!(!offers_images(std::slice::from_ref(&markdown)));
+
+        let assets = dir.path().join("report.pdf.1a2b.assets");
+        std::fs::create_dir(&assets).unwrap();
+        assert!(offers_images(std::slice::from_ref(&markdown)));
+        assert!(offers_images(&["scans/Figure 3.JPG".to_string()]));
+        assert!(!offers_images(&["notes.txt".to_string()]));
+
+        assert_eq!(
+            resolve_image_path("report.pdf.1a2b.assets/img_p1_1.png", Some(&markdown)),
+            assets.join("img_p1_1.png")
+        );
+        assert_eq!(
+            resolve_image_path("/tmp/chart.png", Some(&markdown)),
+            PathBuf::from("/tmp/chart.png")
+        );
+    }
+}
@@ -1,11 +1,94 @@
-use clap::{Parser, Subcommand};
-use semtools::cmds::ask::ask_cmd;
-use semtools::cmds::parse::parse_cmd;
-use semtools::cmds::search::search_cmd;
-use semtools::cmds::workspace::{workspace_prune_cmd, workspace_status_cmd, workspace_use_cmd};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use clap_complete::env::Shells;
+use clap_complete::{ArgValueCandidates, CompleteEnv, CompletionCandidate};
+use semtools::SemtoolsConfig;
+use semtools::ask::export::Exports;
+use semtools::ask::sampling::Sampling;
+use semtools::cmds::ask::{ask_cmd, ask_interactive_cmd};
+use semtools::cmds::bench::bench_cmd;
+use semtools::cmds::config::{
+    config_init_cmd, config_set_cmd, config_show_cmd, config_validate_cmd,
+};
+use semtools::cmds::dedupe::{DedupeAction, dedupe_cmd};
+use semtools::cmds::exit::{Outcome, classify};
+use semtools::cmds::extract::extract_cmd;
+use semtools::cmds::grep::grep_cmd;
+#[cfg(feature = "import")]
+use semtools::cmds::import::{ImportSource, import_cmd};
+use semtools::cmds::model::{model_download_cmd, model_path_cmd, model_status_cmd};
+use semtools::cmds::parse::{
+    parse_cache_clear_cmd, parse_cache_gc_cmd, parse_cache_list_cmd, parse_cache_path_cmd,
+    parse_cmd, parse_jobs_cancel_cmd, parse_jobs_list_cmd,
+};
+use semtools::cmds::pick::pick_cmd;
+use semtools::cmds::saved::{saved_add_cmd, saved_list_cmd, saved_remove_cmd, saved_search_argv};
+use semtools::cmds::search::{search_cmd, search_kv_cmd};
+use semtools::cmds::similar::similar_cmd;
+use semtools::cmds::summarize::summarize_cmd;
+use semtools::cmds::workspace::{
+    SnapshotAction, workspace_delete_cmd, workspace_fsck_cmd, workspace_kv_cmd, workspace_list_cmd,
+    workspace_optimize_cmd, workspace_prune_cmd, workspace_snapshot_cmd, workspace_status_cmd,
+    workspace_sync_cmd, workspace_use_cmd,
+};
+use semtools::config::SavedSearch;
+use semtools::grep::GrepOptions;
+use semtools::json_mode::ErrorOutput;
+use semtools::search::device::set_device_override;
+use semtools::search::pick::DEFAULT_LEXICAL_WEIGHT;
+use semtools::search::saved::search_argv;
+use semtools::search::{DEFAULT_MAX_LINE_CHARS, DEFAULT_NEGATIVE_WEIGHT};
+use semtools::usage::UsageRecord;
+use semtools::workspace::{Workspace, set_read_only_override};
+use std::process::ExitCode;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Environment variable that switches the binary into completion mode (see `completions`)
+const COMPLETE_VAR: &str = "COMPLETE";
+
+/// Existing workspaces, offered when completing workspace names
+fn workspace_candidates() -> Vec<CompletionCandidate> {
+    Workspace::list_names()
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
 
 #[derive(Parser, Debug)]
 struct SemtoolsArgs {
+    /// Never download the embedding model; fail if it is not cached (also set by HF_HUB_OFFLINE=1)
+    #[clap(long, global = true)]
+    offline: bool,
+
+    /// Where to embed documents: auto, cpu, metal or cuda (GPUs need the `gpu` feature; falls
+    /// back to the CPU when the device is unavailable)
+    #[clap(long, global = true)]
+    device: Option<String>,
+
+    /// Workspace to use, taking precedence over SEMTOOLS_WORKSPACE and the config's
+    /// `project.workspace`
+    #[clap(short, long, global = true, add = ArgValueCandidates::new(workspace_candidates))]
+    workspace: Option<String>,
+
+    /// Print no results, only set the exit status (search, grep, parse and dedupe)
+    #[clap(short, long, global = true)]
+    quiet: bool,
+
+    /// Print LLM token and LlamaParse page usage, with costs from the config file's `pricing`,
+    /// when the command finishes
+    #[clap(long, global = true)]
+    show_usage: bool,
+
+    /// Never write to the workspace index: new or changed files are searched in memory, and
+    /// commands that modify the index fail (also `read_only` in the workspace's config.json)
+    #[clap(long, global = true)]
+    read_only: bool,
+
+    /// Replace emails, SSNs, API keys and the config file's `redaction.patterns` with
+    /// placeholders in everything sent to LLM APIs (also `redaction.enabled` in the config)
+    #[cfg(feature = "ask")]
+    #[clap(long, global = true)]
+    redact: bool,
+
     #[command(subcommand)]
     cmd: Commands,
 }
@@ -13,31 +96,300 @@ struct SemtoolsArgs {
 #[derive(Subcommand, Debug)]
 enum WorkspaceCommands {
     /// Use or create a workspace (prints export command to run)
-    Use { name: String },
+    Use {
+        #[arg(add = ArgValueCandidates::new(workspace_candidates))]
+        name: String,
+
+        /// Encrypt the index at rest with the passphrase in SEMTOOLS_WORKSPACE_KEY
+        #[clap(long)]
+        encrypted: bool,
+
+        /// Keep each line's text in the index, so results are served even when the original
+        /// files moved or were deleted (the index grows by about the size of the text)
+        #[clap(long)]
+        store_text: bool,
+
+        /// Embedding model preset for a new workspace: 'fast', 'balanced' or 'quality' (default).
+        /// Fixed once the workspace is created
+        #[clap(long)]
+        embed_preset: Option<String>,
+    },
+    /// List workspaces with their document counts, sizes and last use
+    List,
+    /// Delete a workspace and its index. The active workspace is only deleted once confirmed,
+    /// or with --force
+    Delete {
+        #[arg(add = ArgValueCandidates::new(workspace_candidates))]
+        name: String,
+
+        /// Delete the active workspace without asking
+        #[clap(long)]
+        force: bool,
+    },
     /// Show active workspace and basic stats
     Status {
-        #[clap(default_value = None)]
+        #[clap(default_value = None, add = ArgValueCandidates::new(workspace_candidates))]
         name: Option<String>,
     },
     /// Remove stale or missing files from store
     Prune {
-        #[clap(default_value = None)]
+        #[clap(default_value = None, add = ArgValueCandidates::new(workspace_candidates))]
         name: Option<String>,
     },
+    /// Index files into the workspace without searching them, optionally tagging them
+    Sync {
+        /// Files to index; `s3://bucket/prefix` indexes the objects under the prefix
+        #[clap(required = true)]
+        files: Vec<String>,
+
+        /// Tag the files with `key=value`, to narrow searches with `search --filter
+        /// tag:key=value`; can be repeated
+        #[clap(long = "tag", value_name = "KEY=VALUE")]
+        tag: Vec<String>,
+
+        /// Also index the committed copy of each file of a git repository, kept for `search
+        /// --at <rev>`, and tag documents with the commit they hold (`tag:git_commit=<hash>`)
+        #[clap(long)]
+        git: bool,
+
+        /// Index into the workspace's embedding space for this preset: 'fast', 'balanced' or
+        /// 'quality' (defaults to the workspace's own preset)
+        #[clap(long)]
+        embed_preset: Option<String>,
+    },
+    /// Index question→answer pairs of CSV or JSONL files: the question is embedded and the
+    /// answer is what `search --kv` returns. Syncing a file again replaces its pairs
+    Kv {
+        /// CSV or JSONL files of pairs
+        #[clap(required = true)]
+        files: Vec<String>,
+
+        /// Field holding the question (a JSON key, dotted path or CSV column)
+        #[clap(long, value_name = "FIELD")]
+        key: String,
+
+        /// Field holding the answer
+        #[clap(long, value_name = "FIELD")]
+        value: String,
+
+        /// 'jsonl' or 'csv' (defaults to each file's extension)
+        #[arg(long = "input-format", value_name = "FORMAT")]
+        input_format: Option<String>,
+
+        /// Index into the workspace's embedding space for this preset: 'fast', 'balanced' or
+        /// 'quality' (defaults to the workspace's own preset)
+        #[clap(long)]
+        embed_preset: Option<String>,
+    },
+    /// Build payload indexes and flush pending writes to disk
+    Optimize {
+        #[clap(default_value = None, add = ArgValueCandidates::new(workspace_candidates))]
+        name: Option<String>,
+    },
+    /// Check the index for paths its tables disagree on (e.g. after an interrupted write)
+    Fsck {
+        #[clap(default_value = None, add = ArgValueCandidates::new(workspace_candidates))]
+        name: Option<String>,
+
+        /// Fix what was found: delete orphaned rows, drop broken documents so they are indexed
+        /// again, recompute missing document embeddings and rebuild the payload indexes
+        #[clap(long)]
+        repair: bool,
+    },
+    /// Create, list or restore point-in-time snapshots of a workspace index
+    Snapshot {
+        #[command(subcommand)]
+        command: SnapshotCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SnapshotCommands {
+    /// Snapshot the current index
+    Create { name: String },
+    /// List snapshots
+    List,
+    /// Roll the index back to a snapshot
+    Restore { name: String },
+}
+
+#[cfg(feature = "import")]
+#[derive(Subcommand, Debug)]
+enum ImportCommands {
+    /// Pages shared with the Notion integration of the config's `import.notion.api_key`
+    Notion {
+        /// Only import this page and the pages under it (an ID or page URL); can be repeated
+        #[arg(long = "page", value_name = "PAGE")]
+        pages: Vec<String>,
+    },
+    /// The pages of a Confluence space, with the config's `import.confluence` credentials
+    Confluence {
+        /// Key of the space, e.g. ENG
+        #[arg(long)]
+        space: String,
+    },
+    /// The messages of IMAP mailboxes (Gmail included), with the config's `import.mail` account
+    Mail {
+        /// Mailbox to import, as the server names it (e.g. "[Gmail]/Sent Mail"); can be repeated
+        #[arg(long = "mailbox", value_name = "MAILBOX", default_value = "INBOX")]
+        mailboxes: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ParseCommands {
+    /// Inspect or clear the parse cache ($XDG_CACHE_HOME/semtools/parse)
+    Cache {
+        /// Output results in JSON format
+        #[clap(short, long, global = true)]
+        json: bool,
+
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+    /// Inspect or cancel LlamaParse jobs left pending by an interrupted run (they are resumed
+    /// instead of uploaded again)
+    Jobs {
+        /// Output results in JSON format
+        #[clap(short, long, global = true)]
+        json: bool,
+
+        #[command(subcommand)]
+        command: JobsCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum JobsCommands {
+    /// List pending jobs and the files they parse
+    List,
+    /// Forget pending jobs, so their files are uploaded again on the next run
+    Cancel {
+        /// Files whose jobs to cancel
+        #[clap(required_unless_present = "all", conflicts_with = "all")]
+        files: Vec<String>,
+
+        /// Cancel every pending job
+        #[clap(long)]
+        all: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommands {
+    /// List cached results and the original files they came from
+    List,
+    /// Remove all cached results
+    Clear,
+    /// Print the cached output path for a file
+    Path { file: String },
+    /// Evict entries beyond the size/age limits (`max_cache_size`, `max_age_days` in the config)
+    Gc {
+        /// Path to the config file. Defaults to $XDG_CONFIG_HOME/semtools/config.json
+        #[clap(short = 'c', long)]
+        config: Option<String>,
+
+        /// Maximum total cache size in bytes (overrides config file)
+        #[clap(long)]
+        max_size: Option<u64>,
+
+        /// Remove entries not used for this many days (overrides config file)
+        #[clap(long)]
+        max_age_days: Option<u64>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ModelCommands {
+    /// Download the embedding model into the cache
+    Download,
+    /// Show which embedding model is used and whether it is available offline
+    Status,
+    /// Print the directory the embedding model is loaded from
+    Path,
+}
+
+#[derive(Subcommand, Debug)]
+enum SavedCommands {
+    /// Save a search: `saved add <name> "<query>" [files and search flags...]`
+    Add {
+        /// Short description shown by `saved list`
+        #[clap(long)]
+        description: Option<String>,
+
+        /// Name to run the search by
+        name: String,
+
+        /// Query to search for
+        query: String,
+
+        /// Files and flags passed to `search` after the query
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Run a saved search; extra files or flags are appended and override saved ones
+    Run {
+        name: String,
+
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// List saved searches, including those shared through the config file
+    List,
+    /// Remove a saved search
+    Remove { name: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Write the default config, with every section filled in, to edit in place
+    Init {
+        /// Overwrite an existing config file
+        #[clap(long)]
+        force: bool,
+    },
+    /// Print the config in effect: the file merged over the defaults (API keys masked)
+    Show,
+    /// Report unknown sections or keys and values of the wrong type
+    Validate,
+    /// Set a value by its dotted key, e.g. `config set ask.model gpt-4.1`
+    Set {
+        /// Dotted key, e.g. `ask.model` or `parse.max_age_days`
+        key: String,
+
+        /// New value, read as JSON when it parses (numbers, booleans, arrays) and as a string
+        /// otherwise
+        value: String,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     #[cfg(feature = "parse")]
     /// A CLI tool for parsing documents using various backends
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
     Parse {
-        /// Path to the config file. Defaults to ~/.semtools_config.json
+        #[command(subcommand)]
+        command: Option<ParseCommands>,
+
+        /// Path to the config file. Defaults to $XDG_CONFIG_HOME/semtools/config.json
         #[clap(short = 'c', long)]
         config: Option<String>,
 
-        /// The backend type to use for parsing. Defaults to `llama-parse`
-        #[clap(short, long, default_value = "llama-parse")]
-        backend: String,
+        /// Backend (or comma-separated fallback chain, e.g. `pdf-text,llama-parse`) to use for
+        /// parsing. Defaults to the config file's `backends`, or `llama-parse`
+        #[clap(short, long)]
+        backend: Option<String>,
+
+        /// Only parse these pages, e.g. `1-10,15` (pdf-text and llama-parse backends). Each page
+        /// of the output starts with a `<!-- page N -->` marker
+        #[clap(long)]
+        pages: Option<String>,
+
+        /// Download the images LlamaParse extracts into an assets folder next to the cached
+        /// markdown, and rewrite the markdown's image links to point at them
+        #[clap(long)]
+        save_assets: bool,
 
         /// Files to parse
         #[clap(required = true)]
@@ -46,6 +398,19 @@ enum Commands {
         /// Verbose output while parsing
         #[clap(short, long)]
         verbose: bool,
+
+        /// Print the parsed markdown instead of the cached file paths
+        #[clap(long, conflicts_with = "json")]
+        stdout: bool,
+
+        /// Header printed before each document with --stdout and multiple files
+        /// (`{file}` is replaced with the input path). Defaults to `==> {file} <==`
+        #[clap(long, requires = "stdout", allow_hyphen_values = true)]
+        separator: Option<String>,
+
+        /// Output results in JSON format
+        #[clap(short, long)]
+        json: bool,
     },
     #[cfg(feature = "search")]
     /// A CLI tool for fast semantic keyword search
@@ -57,9 +422,17 @@ enum Commands {
         #[arg(help = "Files to search, optional if using stdin")]
         files: Vec<String>,
 
-        /// How many lines before/after to return as context
-        #[arg(short = 'n', long = "n-lines", alias = "context", default_value_t = 3)]
-        n_lines: usize,
+        /// How many lines before/after to return as context, or 'auto' to grow each window while
+        /// the lines around the match stay about as close to the query, without cutting
+        /// sentences (up to 10 lines on each side)
+        #[arg(
+            short = 'n',
+            long = "n-lines",
+            alias = "context",
+            value_name = "N|auto",
+            default_value = "3"
+        )]
+        n_lines: String,
 
         /// The top-k files or texts to return (ignored if max_distance is set)
         #[arg(long, default_value_t = 3)]
@@ -73,25 +446,305 @@ enum Commands {
         #[arg(short, long, default_value_t = false)]
         ignore_case: bool,
 
+        /// Favor recently modified files: subtracts this weight times the file's normalized
+        /// mtime (0 = oldest, 1 = newest) from each match's distance when ranking
+        #[arg(long = "recency-boost", default_value_t = 0.0)]
+        recency_boost: f64,
+
+        /// Weight files matching a glob, written `glob=weight` (`docs/**=1.2`, `*.test.md=0.5`):
+        /// their matches' similarities are multiplied by the weight; can be repeated (weights of
+        /// matching globs multiply). Globs without a `/` match file names
+        #[arg(long = "boost", value_name = "GLOB=WEIGHT")]
+        boost: Vec<String>,
+
+        /// Diversify results with maximal marginal relevance (1.0 = pure relevance, 0.0 = most
+        /// diverse)
+        #[arg(long = "mmr", value_name = "LAMBDA")]
+        mmr: Option<f64>,
+
+        /// Push down lines similar to this text ("but not about X"); can be repeated
+        #[arg(long = "not", value_name = "TEXT")]
+        not: Vec<String>,
+
+        /// Weight of --not: each line's distance to the closest negative query, times this
+        /// weight, is subtracted from its score
+        #[arg(long = "not-weight", value_name = "LAMBDA", default_value_t = DEFAULT_NEGATIVE_WEIGHT)]
+        not_weight: f64,
+
+        /// Also require matches to be close to this query (AND); can be repeated. A line's
+        /// distance is its largest distance to any of the queries
+        #[arg(long = "all-of", value_name = "QUERY", conflicts_with = "any_of")]
+        all_of: Vec<String>,
+
+        /// Also accept matches close to this query instead (OR); can be repeated. A line's
+        /// distance is its smallest distance to any of the queries
+        #[arg(long = "any-of", value_name = "QUERY")]
+        any_of: Vec<String>,
+
+        /// Only print distinct matching files, ranked by their matching lines (like grep -l)
+        #[arg(short = 'l', long = "files-with-matches")]
+        files_with_matches: bool,
+
+        /// How files are scored with --files-with-matches: 'best' line or 'mean-top3' lines
+        #[arg(long = "file-score", requires = "files_with_matches")]
+        file_score: Option<String>,
+
+        /// Output format: 'default', or 'vimgrep' for one `file:line:col:text` line per match
+        /// (Vim quickfix, VS Code problem matchers)
+        #[arg(long, conflicts_with_all = ["json", "files_with_matches"])]
+        output: Option<String>,
+
+        /// Cut printed lines longer than this many characters to a snippet around the match,
+        /// with ellipses (0 prints lines whole; JSON output always has whole lines)
+        #[arg(long = "max-line-chars", value_name = "N", default_value_t = DEFAULT_MAX_LINE_CHARS)]
+        max_line_chars: usize,
+
+        /// Scores to show: 'raw' cosine distances (default), or 'normalized' to add a 0–100
+        /// relevance calibrated against random pairs of lines (stored per workspace)
+        #[arg(long, conflicts_with = "files_with_matches")]
+        score: Option<String>,
+
+        /// How inputs are split: 'lines' (default), 'jsonl'/'csv' to match whole records, or
+        /// 'ipynb' to match notebook cells (the default when every file is a notebook), reported
+        /// by record or cell index with the full record in JSON output (no workspace caching)
+        #[arg(long = "input-format", value_name = "FORMAT")]
+        input_format: Option<String>,
+
+        /// Record field to embed with --input-format (a JSON key, dotted path or CSV column);
+        /// can be repeated or comma-separated. Defaults to every field
+        #[arg(
+            long = "text-field",
+            value_name = "FIELD",
+            value_delimiter = ',',
+            requires = "input_format"
+        )]
+        text_field: Vec<String>,
+
+        /// Only search workspace documents tagged with `workspace sync --tag`, written
+        /// `tag:key=value`, or with metadata read from their frontmatter or PDF: `title:words`,
+        /// `author:name` and `source:url` (contained, ignoring case) or `created:2024-03` (date
+        /// prefix); can be repeated (documents must match every filter)
+        #[arg(long = "filter", value_name = "FILTER")]
+        filter: Vec<String>,
+
+        /// Search the files (or directories) of a git repository as they were at this revision,
+        /// e.g. a tag, branch or `HEAD~10`; their committed copies are indexed and kept, like
+        /// those of `workspace sync --git`. Defaults to the working directory's repository
+        #[arg(long, value_name = "REV")]
+        at: Option<String>,
+
+        /// Embedding model preset: 'fast', 'balanced' or 'quality' (default). In a workspace,
+        /// selects its embedding space for the preset (defaults to the workspace's own preset)
+        #[clap(long)]
+        embed_preset: Option<String>,
+
+        /// Print diagnostics to stderr: time per stage, candidates left after each stage, and
+        /// whether the workspace index or brute force was used (JSON with --json)
+        #[clap(long)]
+        explain: bool,
+
+        /// Pick the search from the query: identifiers, quoted strings and regular expressions
+        /// are matched lexically without loading the model, plain questions semantically, and
+        /// queries mixing both get both, fused into one ranking
+        #[clap(long)]
+        auto: bool,
+
+        /// Match the query against the questions indexed with `workspace kv` and print their
+        /// answers, instead of searching files
+        #[arg(
+            long,
+            conflicts_with_all = [
+                "files", "ignore_case", "files_with_matches", "input_format", "filter", "at",
+                "auto"
+            ]
+        )]
+        kv: bool,
+
         /// Output results in JSON format
         #[clap(short, long)]
         json: bool,
+    },
+    #[cfg(feature = "search")]
+    /// Measure retrieval quality: run the queries of a qrels file against files and report
+    /// recall@k, MRR and nDCG@k
+    Bench {
+        /// Tab-separated judgments, one `query<TAB>path[:line][<TAB>grade]` per line (1-based
+        /// lines; a judgment without a line accepts any hit in the file)
+        #[clap(long)]
+        qrels: String,
 
-        /// Use a specific workspace
-        #[arg(short, long, default_value = None)]
-        workspace: Option<String>,
+        /// Files to run the queries against
+        #[clap(required = true)]
+        files: Vec<String>,
+
+        /// How many lines before/after a match count as hitting a judged line
+        #[arg(short = 'n', long = "n-lines", alias = "context", default_value_t = 3)]
+        n_lines: usize,
+
+        /// Number of results per query that are scored (the k of recall@k and nDCG@k)
+        #[arg(short = 'k', long, default_value_t = 10)]
+        top_k: usize,
+
+        /// Perform case-insensitive search
+        #[arg(short, long)]
+        ignore_case: bool,
+
+        /// Favor recently modified files (see `search --recency-boost`)
+        #[arg(long = "recency-boost", default_value_t = 0.0)]
+        recency_boost: f64,
+
+        /// Diversify results with maximal marginal relevance (see `search --mmr`)
+        #[arg(long = "mmr", value_name = "LAMBDA")]
+        mmr: Option<f64>,
+
+        /// Embedding model preset: 'fast', 'balanced' or 'quality' (default). In a workspace,
+        /// selects its embedding space for the preset (defaults to the workspace's own preset)
+        #[clap(long)]
+        embed_preset: Option<String>,
+
+        /// Output results in JSON format
+        #[clap(short, long)]
+        json: bool,
+    },
+    #[cfg(feature = "search")]
+    /// Find exact and near-duplicate documents
+    Dedupe {
+        /// Files to check for duplicates
+        #[clap(required = true)]
+        files: Vec<String>,
+
+        /// Maximum document-level distance for near-duplicates (0.0+)
+        #[arg(short = 'm', long = "max-distance", default_value_t = 0.05)]
+        max_distance: f64,
+
+        /// Delete all but the first file of each exact-duplicate group
+        #[arg(long, conflicts_with = "link")]
+        delete: bool,
+
+        /// Replace all but the first file of each exact-duplicate group with a hard link
+        #[arg(long)]
+        link: bool,
+
+        /// Embedding model preset: 'fast', 'balanced' or 'quality' (default)
+        #[clap(long)]
+        embed_preset: Option<String>,
+
+        /// Output results in JSON format
+        #[clap(short, long)]
+        json: bool,
+    },
+    #[cfg(feature = "search")]
+    /// Rank documents by how similar they are as a whole to a reference document ("find
+    /// related papers"), rather than by matching lines to a query
+    Similar {
+        /// The reference document (a file, URL or archive member)
+        reference: String,
+
+        /// Documents to rank; defaults to the project's files (see `project.files` in the
+        /// config). In a workspace, their embeddings come from the index
+        corpus: Vec<String>,
+
+        /// How each document's line embeddings become one vector: 'centroid' (default, their
+        /// mean) or 'max' (the largest value of each dimension, so a topic covered in one
+        /// section of a long document counts)
+        #[arg(long, value_name = "POOLING")]
+        pooling: Option<String>,
+
+        /// The number of documents to return
+        #[arg(long, default_value_t = 10)]
+        top_k: usize,
+
+        /// Only return documents with a distance below this threshold (0.0+)
+        #[arg(short = 'm', long = "max-distance")]
+        max_distance: Option<f64>,
+
+        /// Embedding model preset: 'fast', 'balanced' or 'quality' (default). In a workspace,
+        /// selects its embedding space for the preset (defaults to the workspace's own preset)
+        #[clap(long)]
+        embed_preset: Option<String>,
+
+        /// Output results in JSON format
+        #[clap(short, long)]
+        json: bool,
+    },
+    #[cfg(feature = "ripgrep")]
+    /// Search files and directories for an exact pattern or regular expression, with
+    /// ripgrep's matcher and a parallel directory walk
+    Grep {
+        /// Regular expression to search for (a literal string with --fixed-strings)
+        pattern: String,
+
+        /// Files or directories to search (defaults to the current directory)
+        paths: Vec<String>,
+
+        /// Match the pattern as a literal string
+        #[arg(short = 'F', long)]
+        fixed_strings: bool,
+
+        /// Perform case-insensitive matching
+        #[arg(short, long)]
+        ignore_case: bool,
+
+        /// Only match whole words
+        #[arg(long)]
+        word_regexp: bool,
+
+        /// How many lines before/after each match to print as context
+        #[arg(short = 'C', long, default_value_t = 0)]
+        context: usize,
+
+        /// Also search hidden files and directories
+        #[arg(long)]
+        hidden: bool,
+
+        /// Also search files excluded by .gitignore and .ignore files
+        #[arg(long)]
+        no_ignore: bool,
+
+        /// Only print the files with at least one match (like grep -l)
+        #[arg(short = 'l', long)]
+        files_with_matches: bool,
+
+        /// Output results in JSON format
+        #[clap(short, long)]
+        json: bool,
+    },
+    #[cfg(feature = "search")]
+    /// Pick a line from stdin interactively, ranked by fuzzy and semantic match as you type
+    Pick {
+        /// Query to start with
+        #[arg(long)]
+        query: Option<String>,
+
+        /// Print the lines ranked against this query instead of opening the picker
+        #[arg(short, long, value_name = "QUERY")]
+        filter: Option<String>,
+
+        /// Weight of the fuzzy (trigram) score against the embedding distance: 0.0 ranks by
+        /// meaning only, 1.0 by spelling only
+        #[arg(long, default_value_t = DEFAULT_LEXICAL_WEIGHT)]
+        lexical_weight: f64,
+
+        /// With --filter, print only the best k lines
+        #[arg(long, requires = "filter")]
+        top_k: Option<usize>,
+
+        /// Embedding model preset: 'fast', 'balanced' or 'quality' (default)
+        #[clap(long)]
+        embed_preset: Option<String>,
     },
     #[cfg(feature = "ask")]
     /// A CLI tool for document-based question-answering
     Ask {
         /// Query to prompt the agent with
-        query: String,
+        #[arg(required_unless_present = "interactive")]
+        query: Option<String>,
 
         /// Files to search (positional arguments, optional if using stdin)
         #[arg(help = "Files to search, optional if using stdin")]
         files: Vec<String>,
 
-        /// Path to the config file. Defaults to ~/.semtools_config.json
+        /// Path to the config file. Defaults to $XDG_CONFIG_HOME/semtools/config.json
         #[clap(short = 'c', long)]
         config: Option<String>,
 
@@ -111,15 +764,155 @@ enum Commands {
         #[clap(long)]
         api_mode: Option<String>,
 
+        /// Shape the answer for piping: comma-separated 'no-citations', 'plain', 'max-chars=N'
+        #[clap(long)]
+        answer_format: Option<String>,
+
+        /// Answer in this language (e.g. 'Japanese'), whatever the language of the documents.
+        /// Citations are kept as they are and quotes are translated (also `answer_language` in
+        /// the config file)
+        #[clap(long, value_name = "LANG")]
+        answer_language: Option<String>,
+
+        /// Sampling temperature of the model, 0 to 2; 0 for the most repeatable answers (also
+        /// `temperature` in the config file)
+        #[clap(long)]
+        temperature: Option<f32>,
+
+        /// Seed the model's sampling, so repeated runs over the same files give the same answer
+        /// as far as the provider allows (chat API mode only; also `seed` in the config file)
+        #[clap(long)]
+        seed: Option<i64>,
+
+        /// Remember this question in the workspace, and let the agent look up findings from
+        /// earlier questions before searching again (requires a workspace)
+        #[clap(long)]
+        memory: bool,
+
+        /// After answering, have the model check each claim against the retrieved chunks and
+        /// revise unsupported statements. `--json` reports the revisions and dropped claims
+        #[clap(long)]
+        self_check: bool,
+
+        /// How retrieved content is checked for instructions planted to hijack the agent:
+        /// 'heuristic' (default), 'llm' (also asks the model about each tool response) or 'off'.
+        /// Retrieved content is always passed to the model as untrusted data
+        #[clap(long, value_name = "CHECK")]
+        injection_check: Option<String>,
+
+        /// File or directory the agent's tools may read besides the given files (repeatable).
+        /// Any other path the model asks for is refused
+        #[clap(long = "allow-path", value_name = "PATH")]
+        allow_paths: Vec<String>,
+
+        /// Reuse the answer from an earlier run of the same question over unchanged files with
+        /// the same model (also `cache_answers` in the config file). Not used with --memory
+        #[clap(long)]
+        cache: bool,
+
+        /// Always ask the model, even with the answer cache on; the fresh answer replaces the
+        /// cached one
+        #[clap(long)]
+        no_cache: bool,
+
+        /// Write the questions and answers to this markdown file as a shareable report, with
+        /// the agent's tool calls, the chunks they retrieved and the files consulted. With
+        /// --interactive, the whole session is written when it ends
+        #[clap(long, value_name = "PATH")]
+        export_markdown: Option<String>,
+
+        /// Write the same report as --export-markdown as a standalone HTML page
+        #[clap(long, value_name = "PATH")]
+        export_html: Option<String>,
+
+        /// Only retrieve: the agent runs its tool calls but writes no answer, and the chunks it
+        /// retrieved are printed as JSON, to audit retrieval or pass the context to another model
+        #[clap(
+            long,
+            conflicts_with_all = ["interactive", "self_check", "memory", "cache", "export_markdown", "export_html"]
+        )]
+        dry_run: bool,
+
+        /// Embedding model preset: 'fast', 'balanced' or 'quality' (default). In a workspace,
+        /// selects its embedding space for the preset (defaults to the workspace's own preset)
+        #[clap(long)]
+        embed_preset: Option<String>,
+
         /// Output results in JSON or text format
         #[clap(short, long)]
         json: bool,
 
-        /// Use a specific workspace
-        #[arg(short, long, default_value = None)]
-        workspace: Option<String>,
+        /// Chat about the files in a REPL that keeps the model loaded and the conversation as
+        /// context (type /help there for commands). Every positional argument is a file
+        #[clap(short = 'i', long, conflicts_with_all = ["json", "cache", "no_cache"])]
+        interactive: bool,
     },
-    #[cfg(feature = "workspace")]
+    #[cfg(feature = "ask")]
+    /// Extract structured fields from documents with an LLM, printing one NDJSON row per
+    /// document
+    Extract {
+        /// JSON Schema file describing the fields to extract. Every row is validated against it
+        #[clap(short, long)]
+        schema: String,
+
+        /// Files to extract from (text or parsed markdown), optional if using stdin
+        files: Vec<String>,
+
+        /// Path to the config file. Defaults to $XDG_CONFIG_HOME/semtools/config.json
+        #[clap(short = 'c', long)]
+        config: Option<String>,
+
+        /// OpenAI API key (overrides config file and env var)
+        #[clap(long)]
+        api_key: Option<String>,
+
+        /// OpenAI base URL (overrides config file)
+        #[clap(long)]
+        base_url: Option<String>,
+
+        /// Model to use for extraction (overrides config file)
+        #[clap(short, long)]
+        model: Option<String>,
+
+        /// API mode to use: 'chat' or 'responses' (overrides config file)
+        #[clap(long)]
+        api_mode: Option<String>,
+    },
+    #[cfg(feature = "ask")]
+    /// Summarize documents with an LLM, citing the sections each point comes from
+    Summarize {
+        /// Files to summarize, optional if using stdin
+        files: Vec<String>,
+
+        /// Length of the summary: 'short', 'medium' (default) or 'long'
+        #[clap(short, long)]
+        length: Option<String>,
+
+        /// Path to the config file. Defaults to $XDG_CONFIG_HOME/semtools/config.json
+        #[clap(short = 'c', long)]
+        config: Option<String>,
+
+        /// OpenAI API key (overrides config file and env var)
+        #[clap(long)]
+        api_key: Option<String>,
+
+        /// OpenAI base URL (overrides config file)
+        #[clap(long)]
+        base_url: Option<String>,
+
+        /// Model to use for summarizing (overrides config file)
+        #[clap(short, long)]
+        model: Option<String>,
+
+        /// API mode to use: 'chat' or 'responses' (overrides config file)
+        #[clap(long)]
+        api_mode: Option<String>,
+
+        /// Output results in JSON format
+        #[clap(short, long)]
+        json: bool,
+    },
+    #[cfg(feature = "workspace-lite")]
     /// Manage semtools workspaces
     Workspace {
         /// Output results in JSON format
@@ -129,11 +922,271 @@ enum Commands {
         #[command(subcommand)]
         command: WorkspaceCommands,
     },
+    #[cfg(feature = "import")]
+    /// Import Notion or Confluence pages as markdown files, or mailboxes as .eml files
+    /// ($XDG_DATA_HOME/semtools/imports), and index them into the workspace. Importing again
+    /// only fetches the pages and messages that changed
+    Import {
+        /// Path to the config file. Defaults to $XDG_CONFIG_HOME/semtools/config.json
+        #[clap(short = 'c', long, global = true)]
+        config: Option<String>,
+
+        /// Index into the workspace's embedding space for this preset: 'fast', 'balanced' or
+        /// 'quality' (defaults to the workspace's own preset)
+        #[clap(long, global = true)]
+        embed_preset: Option<String>,
+
+        /// Output results in JSON format
+        #[clap(short, long, global = true)]
+        json: bool,
+
+        #[command(subcommand)]
+        command: ImportCommands,
+    },
+    #[cfg(feature = "search")]
+    /// Manage the embedding model used by search, ask and workspaces
+    Model {
+        /// Path to the config file. Defaults to $XDG_CONFIG_HOME/semtools/config.json
+        #[clap(short = 'c', long, global = true)]
+        config: Option<String>,
+
+        /// Embedding model preset: 'fast', 'balanced' or 'quality' (default)
+        #[clap(long, global = true)]
+        embed_preset: Option<String>,
+
+        /// Output results in JSON format
+        #[clap(short, long, global = true)]
+        json: bool,
+
+        #[command(subcommand)]
+        command: ModelCommands,
+    },
+    #[cfg(feature = "grpc")]
+    /// Serve search, parse, ask and workspace operations over gRPC
+    Grpc {
+        /// Address to listen on
+        #[arg(long, default_value = semtools::grpc::DEFAULT_GRPC_ADDR)]
+        addr: std::net::SocketAddr,
+
+        /// Embedding model preset to serve: 'fast', 'balanced' or 'quality' (default). Only
+        /// workspaces created with this preset can be searched
+        #[clap(long)]
+        embed_preset: Option<String>,
+
+        /// Path to the config file. Defaults to $XDG_CONFIG_HOME/semtools/config.json
+        #[clap(short = 'c', long)]
+        config: Option<String>,
+    },
+    #[cfg(feature = "search")]
+    /// Save searches under a name and run them again ($XDG_DATA_HOME/semtools/saved_searches.json)
+    Saved {
+        /// Output results in JSON format
+        #[clap(short, long, global = true)]
+        json: bool,
+
+        #[command(subcommand)]
+        command: SavedCommands,
+    },
+    /// Create, inspect, check or edit the config file
+    Config {
+        /// Path to the config file. Defaults to $XDG_CONFIG_HOME/semtools/config.json
+        #[clap(short = 'c', long, global = true)]
+        config: Option<String>,
+
+        /// Output results in JSON format
+        #[clap(short, long, global = true)]
+        json: bool,
+
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Print a shell completion script, e.g. `source <(semtools completions bash)`. Workspace
+    /// names are completed from the existing workspaces
+    Completions {
+        /// Shell to complete in
+        #[arg(value_parser = ["bash", "zsh", "fish", "powershell", "elvish"])]
+        shell: String,
+    },
+}
+
+/// Print the script that registers `semtools` completions with `shell`. The script calls back
+/// into this binary with `COMPLETE=<shell>` set, so completions (including workspace names)
+/// are always current.
+fn print_completions(shell: &str) -> anyhow::Result<()> {
+    let shells = Shells::builtins();
+    let completer = shells
+        .completer(shell)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported shell: {shell}"))?;
+    let exe = std::env::current_exe()?;
+    let mut out = std::io::stdout().lock();
+    match completer.write_registration(
+        COMPLETE_VAR,
+        "semtools",
+        "semtools",
+        &exe.to_string_lossy(),
+        &mut out,
+    ) {
+        // A closed pipe (e.g. `| head`) is not an error
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+        other => Ok(other?),
+    }
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let args = SemtoolsArgs::parse();
+async fn main() -> ExitCode {
+    // Answers completion requests from the shell scripts printed by `completions`
+    CompleteEnv::with_factory(SemtoolsArgs::command)
+        .var(COMPLETE_VAR)
+        .complete();
+
+    let matches = SemtoolsArgs::command().get_matches();
+    let args = SemtoolsArgs::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let command = matches.subcommand_name().unwrap_or_default().to_string();
+    let json = json_requested(&matches);
+    match run(args, &command).await {
+        Ok(outcome) => outcome.into(),
+        Err(e) => {
+            let (code, error_type) = classify(&e);
+            let error_output = ErrorOutput {
+                error: format!("{e:#}"),
+                error_type: error_type.to_string(),
+            };
+            match serde_json::to_string_pretty(&error_output) {
+                Ok(json_output) if json => eprintln!("{json_output}"),
+                _ => eprintln!("Error: {e:?}"),
+            }
+            ExitCode::from(code)
+        }
+    }
+}
+
+/// Whether the (innermost) subcommand run was given `--json`, so errors are reported as JSON
+fn json_requested(matches: &clap::ArgMatches) -> bool {
+    let mut matches = matches;
+    while let Some((_, sub)) = matches.subcommand() {
+        matches = sub;
+    }
+    matches
+        .try_get_one::<bool>("json")
+        .ok()
+        .flatten()
+        .copied()
+        .unwrap_or(false)
+}
+
+/// Print the run's usage with `--show-usage`, and add it to the active workspace's history
+fn report_usage(
+    command: &str,
+    show_usage: bool,
+    workspace_name: Option<&str>,
+) -> anyhow::Result<()> {
+    let usage = semtools::usage::current();
+    if !show_usage && usage.is_empty() {
+        return Ok(());
+    }
+
+    let pricing = SemtoolsConfig::load_default()?.pricing.unwrap_or_default();
+    if show_usage {
+        eprintln!("\n{}", usage.summary(&pricing));
+    }
+
+    if !usage.is_empty()
+        && let Ok(name) = Workspace::active(workspace_name)
+        && Workspace::exists(&name)?
+    {
+        let record = UsageRecord {
+            recorded_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            command: command.to_string(),
+            cost: usage.cost(&pricing),
+            usage,
+        };
+        Workspace::open(Some(&name))?.record_usage(&record)?;
+    }
+    Ok(())
+}
+
+/// Parse `search` arguments (after `semtools`), letting a repeated option override the
+/// earlier value instead of failing
+fn parse_search_args(argv: &[String]) -> anyhow::Result<SemtoolsArgs> {
+    let matches = SemtoolsArgs::command()
+        .args_override_self(true)
+        .try_get_matches_from(
+            std::iter::once("semtools".to_string()).chain(argv.iter().cloned()),
+        )?;
+    Ok(SemtoolsArgs::from_arg_matches(&matches)?)
+}
+
+/// The global flags of `args`, as command-line arguments
+fn global_flags(args: &SemtoolsArgs) -> Vec<String> {
+    let mut argv = Vec::new();
+    if args.offline {
+        argv.push("--offline".to_string());
+    }
+    if let Some(workspace) = &args.workspace {
+        argv.extend(["--workspace".to_string(), workspace.clone()]);
+    }
+    if args.quiet {
+        argv.push("--quiet".to_string());
+    }
+    if args.show_usage {
+        argv.push("--show-usage".to_string());
+    }
+    if let Some(device) = &args.device {
+        argv.extend(["--device".to_string(), device.clone()]);
+    }
+    if args.read_only {
+        argv.push("--read-only".to_string());
+    }
+    #[cfg(feature = "ask")]
+    if args.redact {
+        argv.push("--redact".to_string());
+    }
+    argv
+}
+
+/// The arguments of a `search` that runs saved search `name`, keeping the global flags
+fn saved_search_args(
+    args: &SemtoolsArgs,
+    name: &str,
+    extra: &[String],
+) -> anyhow::Result<SemtoolsArgs> {
+    let mut argv = global_flags(args);
+    argv.extend(saved_search_argv(name, extra)?);
+    parse_search_args(&argv)
+}
+
+async fn run(mut args: SemtoolsArgs, command: &str) -> anyhow::Result<Outcome> {
+    // Flushes spans and metrics when run returns
+    let _telemetry = semtools::telemetry::init()?;
+    // A saved search runs as the `search` it stands for
+    if let Commands::Saved {
+        command: SavedCommands::Run { name, args: extra },
+        ..
+    } = &args.cmd
+    {
+        args = saved_search_args(&args, name, extra)?;
+    }
+    // A project's `.semtools.json` can name the workspace to use by default. A config that
+    // cannot be read is an error rather than a default quietly not applied; `config` commands
+    // are left to report it themselves.
+    if args.workspace.is_none()
+        && std::env::var_os("SEMTOOLS_WORKSPACE").is_none()
+        && !matches!(args.cmd, Commands::Config { .. })
+    {
+        args.workspace =
+            SemtoolsConfig::project_settings(&SemtoolsConfig::default_config_path())?.workspace;
+    }
+    if let Some(device) = &args.device {
+        set_device_override(device.parse()?);
+    }
+    if args.read_only {
+        set_read_only_override();
+    }
+    #[cfg(feature = "ask")]
+    if args.redact {
+        semtools::ask::redaction::set_redact_override();
+    }
+    let mut outcome = Outcome::Success;
     match args.cmd {
         Commands::Ask {
             query,
@@ -143,29 +1196,147 @@ async fn main() -> anyhow::Result<()> {
             base_url,
             model,
             api_mode,
+            answer_format,
+            answer_language,
+            temperature,
+            seed,
+            memory,
+            self_check,
+            injection_check,
+            allow_paths,
+            cache,
+            no_cache,
+            export_markdown,
+            export_html,
+            dry_run,
+            embed_preset,
             json,
-            workspace,
+            interactive,
         } => {
-            ask_cmd(
-                query,
-                files,
-                config,
-                api_key,
-                base_url,
-                model,
-                api_mode,
-                json,
-                workspace.as_deref(),
+            let exports = Exports {
+                markdown: export_markdown,
+                html: export_html,
+            };
+            if interactive {
+                ask_interactive_cmd(
+                    query.into_iter().chain(files).collect(),
+                    config,
+                    api_key,
+                    base_url,
+                    model,
+                    api_mode,
+                    answer_format,
+                    answer_language,
+                    Sampling { temperature, seed },
+                    memory,
+                    self_check,
+                    injection_check,
+                    allow_paths,
+                    exports,
+                    embed_preset,
+                    args.offline,
+                    args.workspace.as_deref(),
+                )
+                .await?;
+            } else {
+                ask_cmd(
+                    query.unwrap_or_default(),
+                    files,
+                    config,
+                    api_key,
+                    base_url,
+                    model,
+                    api_mode,
+                    answer_format,
+                    answer_language,
+                    Sampling { temperature, seed },
+                    memory,
+                    self_check,
+                    injection_check,
+                    allow_paths,
+                    cache,
+                    no_cache,
+                    exports,
+                    dry_run,
+                    embed_preset,
+                    args.offline,
+                    json,
+                    args.workspace.as_deref(),
+                )
+                .await?;
+            }
+        }
+        Commands::Extract {
+            schema,
+            files,
+            config,
+            api_key,
+            base_url,
+            model,
+            api_mode,
+        } => {
+            extract_cmd(schema, files, config, api_key, base_url, model, api_mode).await?;
+        }
+        Commands::Summarize {
+            files,
+            length,
+            config,
+            api_key,
+            base_url,
+            model,
+            api_mode,
+            json,
+        } => {
+            summarize_cmd(
+                files, length, config, api_key, base_url, model, api_mode, json,
             )
             .await?;
         }
         Commands::Parse {
+            command: Some(ParseCommands::Cache { json, command }),
+            ..
+        } => match command {
+            CacheCommands::List => parse_cache_list_cmd(json).await?,
+            CacheCommands::Clear => parse_cache_clear_cmd(json).await?,
+            CacheCommands::Path { file } => parse_cache_path_cmd(file, json).await?,
+            CacheCommands::Gc {
+                config,
+                max_size,
+                max_age_days,
+            } => parse_cache_gc_cmd(config, max_size, max_age_days, json).await?,
+        },
+        Commands::Parse {
+            command: Some(ParseCommands::Jobs { json, command }),
+            ..
+        } => match command {
+            JobsCommands::List => parse_jobs_list_cmd(json).await?,
+            JobsCommands::Cancel { files, all } => parse_jobs_cancel_cmd(files, all, json).await?,
+        },
+        Commands::Parse {
+            command: None,
             config,
             backend,
+            pages,
+            save_assets,
             files,
             verbose,
+            stdout,
+            separator,
+            json,
         } => {
-            parse_cmd(config, backend, files, verbose).await?;
+            outcome = parse_cmd(
+                config,
+                backend,
+                pages,
+                save_assets,
+                files,
+                verbose,
+                stdout,
+                separator,
+                json,
+                args.quiet,
+            )
+            .await?;
         }
         Commands::Search {
             query,
@@ -174,33 +1345,382 @@ async fn main() -> anyhow::Result<()> {
             top_k,
             max_distance,
             ignore_case,
+            recency_boost,
+            boost,
+            mmr,
+            not,
+            not_weight,
+            all_of,
+            any_of,
+            files_with_matches,
+            file_score,
+            output,
+            max_line_chars,
+            score,
+            input_format,
+            text_field,
+            filter,
+            at,
+            embed_preset,
+            explain,
+            auto,
+            kv,
             json,
-            workspace,
         } => {
-            search_cmd(
-                query,
+            outcome = if kv {
+                search_kv_cmd(
+                    query,
+                    top_k,
+                    max_distance,
+                    embed_preset,
+                    args.offline,
+                    json,
+                    args.quiet,
+                    args.workspace.as_deref(),
+                )
+                .await?
+            } else {
+                search_cmd(
+                    query,
+                    files,
+                    n_lines,
+                    top_k,
+                    max_distance,
+                    ignore_case,
+                    recency_boost,
+                    boost,
+                    mmr,
+                    not,
+                    not_weight,
+                    all_of,
+                    any_of,
+                    files_with_matches,
+                    file_score,
+                    output,
+                    max_line_chars,
+                    score,
+                    input_format,
+                    text_field,
+                    filter,
+                    at,
+                    embed_preset,
+                    explain,
+                    auto,
+                    args.offline,
+                    json,
+                    args.quiet,
+                    args.workspace.as_deref(),
+                )
+                .await?
+            };
+        }
+        Commands::Bench {
+            qrels,
+            files,
+            n_lines,
+            top_k,
+            ignore_case,
+            recency_boost,
+            mmr,
+            embed_preset,
+            json,
+        } => {
+            bench_cmd(
+                qrels,
                 files,
                 n_lines,
                 top_k,
-                max_distance,
                 ignore_case,
+                recency_boost,
+                mmr,
+                embed_preset,
+                args.offline,
+                json,
+                args.workspace.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Dedupe {
+            files,
+            max_distance,
+            delete,
+            link,
+            embed_preset,
+            json,
+        } => {
+            let action = if delete {
+                DedupeAction::Delete
+            } else if link {
+                DedupeAction::Link
+            } else {
+                DedupeAction::Report
+            };
+            outcome = dedupe_cmd(
+                files,
+                max_distance,
+                action,
+                embed_preset,
+                args.offline,
                 json,
-                workspace.as_deref(),
+                args.quiet,
             )
             .await?;
         }
+        Commands::Similar {
+            reference,
+            corpus,
+            pooling,
+            top_k,
+            max_distance,
+            embed_preset,
+            json,
+        } => {
+            similar_cmd(
+                reference,
+                corpus,
+                pooling,
+                top_k,
+                max_distance,
+                embed_preset,
+                args.offline,
+                json,
+                args.workspace.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Grep {
+            pattern,
+            paths,
+            fixed_strings,
+            ignore_case,
+            word_regexp,
+            context,
+            hidden,
+            no_ignore,
+            files_with_matches,
+            json,
+        } => {
+            let options = GrepOptions {
+                fixed_strings,
+                case_sensitive: !ignore_case,
+                word: word_regexp,
+                context_lines: context,
+                hidden,
+                no_ignore,
+            };
+            outcome = grep_cmd(
+                pattern,
+                paths,
+                options,
+                files_with_matches,
+                json,
+                args.quiet,
+            )?;
+        }
+        Commands::Pick {
+            query,
+            filter,
+            lexical_weight,
+            top_k,
+            embed_preset,
+        } => {
+            outcome = pick_cmd(
+                query,
+                filter,
+                lexical_weight,
+                top_k,
+                embed_preset,
+                args.offline,
+            )?;
+        }
         Commands::Workspace { json, command } => match command {
-            WorkspaceCommands::Use { name } => {
-                workspace_use_cmd(name, json).await?;
+            WorkspaceCommands::Use {
+                name,
+                encrypted,
+                store_text,
+                embed_preset,
+            } => {
+                workspace_use_cmd(name, encrypted, store_text, embed_preset, json).await?;
             }
+            WorkspaceCommands::List => {
+                workspace_list_cmd(json, args.workspace.as_deref()).await?;
+            }
+            WorkspaceCommands::Delete { name, force } => {
+                workspace_delete_cmd(name, force, json, args.workspace.as_deref()).await?;
+            }
+            // A name given to the subcommand itself wins over --workspace
             WorkspaceCommands::Prune { name } => {
-                workspace_prune_cmd(json, name.as_deref()).await?;
+                workspace_prune_cmd(json, name.as_deref().or(args.workspace.as_deref())).await?;
             }
             WorkspaceCommands::Status { name } => {
-                workspace_status_cmd(json, name.as_deref()).await?;
+                workspace_status_cmd(json, name.as_deref().or(args.workspace.as_deref())).await?;
+            }
+            WorkspaceCommands::Sync {
+                files,
+                tag,
+                git,
+                embed_preset,
+            } => {
+                workspace_sync_cmd(
+                    files,
+                    tag,
+                    git,
+                    embed_preset,
+                    args.offline,
+                    json,
+                    args.workspace.as_deref(),
+                )
+                .await?;
+            }
+            WorkspaceCommands::Kv {
+                files,
+                key,
+                value,
+                input_format,
+                embed_preset,
+            } => {
+                workspace_kv_cmd(
+                    files,
+                    key,
+                    value,
+                    input_format,
+                    embed_preset,
+                    args.offline,
+                    json,
+                    args.workspace.as_deref(),
+                )
+                .await?;
+            }
+            WorkspaceCommands::Optimize { name } => {
+                workspace_optimize_cmd(json, name.as_deref().or(args.workspace.as_deref())).await?;
+            }
+            WorkspaceCommands::Fsck { name, repair } => {
+                workspace_fsck_cmd(repair, json, name.as_deref().or(args.workspace.as_deref()))
+                    .await?;
+            }
+            WorkspaceCommands::Snapshot { command } => {
+                let action = match command {
+                    SnapshotCommands::Create { name } => SnapshotAction::Create(name),
+                    SnapshotCommands::List => SnapshotAction::List,
+                    SnapshotCommands::Restore { name } => SnapshotAction::Restore(name),
+                };
+                workspace_snapshot_cmd(action, json, args.workspace.as_deref()).await?;
+            }
+        },
+        #[cfg(feature = "import")]
+        Commands::Import {
+            config,
+            embed_preset,
+            json,
+            command,
+        } => {
+            let source = match command {
+                ImportCommands::Notion { pages } => ImportSource::Notion { pages },
+                ImportCommands::Confluence { space } => ImportSource::Confluence { space },
+                ImportCommands::Mail { mailboxes } => ImportSource::Mail { mailboxes },
+            };
+            import_cmd(
+                source,
+                config,
+                embed_preset,
+                args.offline,
+                json,
+                args.workspace.as_deref(),
+            )
+            .await?;
+        }
+        Commands::Model {
+            config,
+            embed_preset,
+            json,
+            command,
+        } => match command {
+            ModelCommands::Download => {
+                model_download_cmd(config, embed_preset, args.offline, json).await?
             }
+            ModelCommands::Status => model_status_cmd(config, embed_preset, json).await?,
+            ModelCommands::Path => model_path_cmd(config, embed_preset, json).await?,
+        },
+        #[cfg(feature = "grpc")]
+        Commands::Grpc {
+            addr,
+            embed_preset,
+            config,
+        } => {
+            let embed_preset = embed_preset.as_deref().map(str::parse).transpose()?;
+            eprintln!("Serving semtools gRPC on {addr}");
+            semtools::grpc::serve(addr, config, embed_preset, args.offline).await?;
+        }
+        Commands::Saved { json, command } => match command {
+            SavedCommands::Add {
+                description,
+                name,
+                query,
+                args: search_args,
+            } => {
+                // Catch typos in the flags now rather than when the search is run
+                let search = SavedSearch {
+                    query: query.clone(),
+                    args: search_args.clone(),
+                    description: None,
+                };
+                parse_search_args(&search_argv(&search, &[]))?;
+                saved_add_cmd(&name, query, search_args, description, json)?
+            }
+            SavedCommands::List => saved_list_cmd(json)?,
+            SavedCommands::Remove { name } => saved_remove_cmd(&name, json)?,
+            SavedCommands::Run { .. } => unreachable!("saved searches run as `search`"),
+        },
+        Commands::Config {
+            config,
+            json,
+            command,
+        } => match command {
+            ConfigCommands::Init { force } => config_init_cmd(config, force, json)?,
+            ConfigCommands::Show => config_show_cmd(config, json)?,
+            ConfigCommands::Validate => config_validate_cmd(config, json)?,
+            ConfigCommands::Set { key, value } => config_set_cmd(config, &key, &value, json)?,
         },
+        Commands::Completions { shell } => print_completions(&shell)?,
     }
 
-    Ok(())
+    // Usage accounting never fails the command it accounts for
+    if let Err(e) = report_usage(command, args.show_usage, args.workspace.as_deref()) {
+        eprintln!("Warning: failed to record usage: {e}");
+    }
+    // Workspaces kept in an object store get what the command wrote
+    semtools::workspace::remote::push_replicas().await?;
+
+    Ok(outcome)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_global_flags_reach_saved_search() {
+        let args =
+            parse_search_args(&argv(&["--read-only", "--quiet", "saved", "run", "todos"])).unwrap();
+
+        let mut search = global_flags(&args);
+        search.extend(argv(&["search", "TODO"]));
+        let search = parse_search_args(&search).unwrap();
+
+        assert!(search.read_only);
+        assert!(search.quiet);
+        assert!(matches!(search.cmd, Commands::Search { .. }));
+    }
+
+    #[cfg(feature = "ask")]
+    #[test]
+    fn test_redact_reaches_saved_search() {
+        let args = parse_search_args(&argv(&["--redact", "saved", "run", "todos"])).unwrap();
+
+        assert!(global_flags(&args).contains(&"--redact".to_string()));
+    }
 }
@@ -2,14 +2,38 @@ use anyhow::Result;
 use async_openai::Client;
 use async_openai::config::OpenAIConfig;
 use model2vec_rs::model::StaticModel;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
 use std::io::{self, BufRead, IsTerminal};
+use std::path::Path;
+use tokio::sync::mpsc;
 
 use crate::SemtoolsConfig;
+use crate::ask::answer_cache::{AnswerCache, answer_key, corpus_hash};
+use crate::ask::answer_format::AnswerFormat;
 use crate::ask::chat_agent::{ask_agent, ask_agent_with_stdin};
+use crate::ask::dry_run::dry_run_output;
+use crate::ask::error::AskError;
+use crate::ask::events::{AgentEvent, AgentEventSender};
+use crate::ask::export::Exports;
+use crate::ask::injection::InjectionCheck;
+use crate::ask::memory::AgentMemory;
+use crate::ask::piped_results::parse_search_output;
+use crate::ask::redaction;
+use crate::ask::repl::{Conversation, HELP, ReplInput};
 use crate::ask::responses_agent::{ask_agent_responses, ask_agent_responses_with_stdin};
-use crate::config::ApiMode;
-use crate::json_mode::ErrorOutput;
-use crate::search::MODEL_NAME;
+use crate::ask::sampling::{Sampling, set_sampling};
+use crate::ask::self_check::self_check;
+use crate::ask::vision;
+use crate::config::{ApiMode, files_or_project_files};
+use crate::fetch::resolve_inputs;
+use crate::json_mode::AskOutput;
+use crate::search::model::{EmbedPreset, ModelSource, resolve_embed_preset};
+
+#[cfg(feature = "workspace-lite")]
+use crate::ask::memory::MemoryEntry;
+#[cfg(feature = "workspace-lite")]
+use crate::workspace::{Workspace, set_space_override, store::MEMORY_FILE};
 
 fn read_from_stdin() -> Result<Vec<String>> {
     let stdin = io::stdin();
@@ -17,32 +41,113 @@ fn read_from_stdin() -> Result<Vec<String>> {
     Ok(lines?)
 }
 
-#[allow(clippy::too_many_arguments)]
-pub async fn ask_cmd(
-    query: String,
-    files: Vec<String>,
+/// Load the memory of the active workspace. Memory is stored in the workspace, so `--memory`
+/// needs one.
+#[cfg(feature = "workspace-lite")]
+fn load_memory(workspace_name: Option<&str>) -> Result<AgentMemory> {
+    if Workspace::active(workspace_name).is_err() {
+        return Err(AskError::MemoryNeedsWorkspace.into());
+    }
+    // An encrypted workspace's data directory only exists while the store is open
+    let store = Workspace::open(workspace_name)?.open_store()?;
+    AgentMemory::load(&store.data_dir().join(MEMORY_FILE))
+}
+
+#[cfg(feature = "workspace-lite")]
+fn record_memory(workspace_name: Option<&str>, output: &AskOutput) -> Result<()> {
+    let store = Workspace::open(workspace_name)?.open_store()?;
+    // Writes to an encrypted workspace are only sealed back when made under the lock
+    let _lock = store.write_lock()?;
+    let mut memory = AgentMemory::load(&store.data_dir().join(MEMORY_FILE))?;
+    memory.record(MemoryEntry::from_output(output)?)
+}
+
+/// The workspace memory offered to the agent with `--memory`
+fn agent_memory(memory: bool, workspace_name: Option<&str>) -> Result<Option<AgentMemory>> {
+    if !memory {
+        return Ok(None);
+    }
+    #[cfg(feature = "workspace-lite")]
+    return load_memory(workspace_name).map(Some);
+    #[cfg(not(feature = "workspace-lite"))]
+    {
+        let _ = workspace_name;
+        anyhow::bail!("--memory requires semtools to be built with the workspace feature");
+    }
+}
+
+/// Record an answer in the workspace memory. Questions answered without looking at any file
+/// have nothing worth remembering.
+#[cfg(feature = "workspace-lite")]
+fn remember(workspace_name: Option<&str>, output: &AskOutput) -> Result<()> {
+    if output.files_searched.is_empty() {
+        return Ok(());
+    }
+    if Workspace::open(workspace_name)?.is_read_only() {
+        eprintln!("Warning: the workspace is read-only, so this question is not remembered");
+        return Ok(());
+    }
+    record_memory(workspace_name, output)
+}
+
+/// Run the `--self-check` verification pass and replace the answer with its revision
+async fn verify_answer(
+    output: &mut AskOutput,
+    client: &Client<OpenAIConfig>,
+    model_name: &str,
+    api_mode: ApiMode,
+    answer_format: &AnswerFormat,
+) -> Result<()> {
+    let (response, report) = self_check(
+        client,
+        model_name,
+        api_mode,
+        &output.query,
+        &output.response,
+        &output.evidence,
+        answer_format,
+    )
+    .await?;
+    output.response = response;
+    output.self_check = Some(report);
+    Ok(())
+}
+
+/// LLM settings for an ask run, resolved from CLI args, the config file, and the environment
+pub(crate) struct AskSettings {
+    pub client: Client<OpenAIConfig>,
+    pub model_name: String,
+    pub api_mode: ApiMode,
+    pub max_iterations: Option<usize>,
+    /// `cache_answers` from the config file
+    pub cache_answers: bool,
+    /// `answer_language` from the config file
+    pub answer_language: Option<String>,
+    /// Temperature and seed of every request, from CLI args or the config file
+    pub sampling: Sampling,
+}
+
+pub(crate) fn resolve_ask_settings(
     config: Option<String>,
     api_key: Option<String>,
     base_url: Option<String>,
     model: Option<String>,
     api_mode: Option<String>,
-    json: bool,
-    workspace_name: Option<&str>,
-) -> Result<()> {
+    sampling: Sampling,
+) -> Result<AskSettings> {
     // Load configuration
     let config_path = config.unwrap_or_else(SemtoolsConfig::default_config_path);
     let semtools_config = SemtoolsConfig::from_config_file(&config_path)?;
     let ask_config = semtools_config.ask.unwrap_or_default();
 
+    // Everything sent to the API from here on is redacted, if configured or `--redact` is given
+    redaction::init(&semtools_config.redaction.unwrap_or_default())?;
+
     // Resolve API key with priority: CLI arg > config file > env var > error
     let api_key = api_key
         .or(ask_config.api_key)
         .or_else(|| std::env::var("OPENAI_API_KEY").ok())
-        .ok_or_else(|| {
-            anyhow::anyhow!(
-                "OpenAI API key not found. Set via --api-key, config file, or OPENAI_API_KEY env var"
-            )
-        })?;
+        .ok_or(AskError::MissingApiKey)?;
 
     // Resolve base URL with priority: CLI arg > config file > default
     let base_url = base_url.or(ask_config.base_url);
@@ -54,6 +159,19 @@ pub async fn ask_cmd(
 
     // Resolve max iterations from config
     let max_iterations = ask_config.max_iterations;
+    let cache_answers = ask_config.cache_answers;
+    let answer_language = ask_config.answer_language;
+
+    // Resolve sampling per setting with priority: CLI arg > config file > provider default
+    let sampling = sampling.or(Sampling {
+        temperature: ask_config.temperature,
+        seed: ask_config.seed,
+    });
+    sampling.validate()?;
+    set_sampling(sampling);
+    if let Some(vision_model) = ask_config.vision_model {
+        vision::set_vision_model(vision_model);
+    }
 
     // Resolve API mode with priority: CLI arg > config file > default
     let api_mode = if let Some(mode_str) = api_mode {
@@ -61,10 +179,10 @@ pub async fn ask_cmd(
             "chat" => ApiMode::Chat,
             "responses" => ApiMode::Responses,
             _ => {
-                return Err(anyhow::anyhow!(
-                    "Invalid API mode: '{}'. Must be 'chat' or 'responses'",
-                    mode_str
-                ));
+                return Err(AskError::InvalidArgument(format!(
+                    "Invalid API mode: '{mode_str}'. Must be 'chat' or 'responses'"
+                ))
+                .into());
             }
         }
     } else {
@@ -78,94 +196,582 @@ pub async fn ask_cmd(
     }
     let client = Client::with_config(openai_config);
 
-    // Check if we have stdin input (no files and stdin is not a terminal)
-    if files.is_empty() && !io::stdin().is_terminal() {
-        let stdin_lines = read_from_stdin()?;
-        if !stdin_lines.is_empty() {
-            let stdin_content = stdin_lines.join("\n");
-
-            // Run the appropriate agent with stdin content (no tools)
-            let output = match api_mode {
-                ApiMode::Chat => {
-                    ask_agent_with_stdin(&stdin_content, &query, &client, &model_name).await?
-                }
-                ApiMode::Responses => {
-                    ask_agent_responses_with_stdin(&stdin_content, &query, &client, &model_name)
-                        .await?
-                }
-            };
-
-            if json {
-                let json_output = serde_json::to_string_pretty(&output)?;
-                println!("\n{}", json_output);
-            } else {
-                println!("\n{}", output.response);
-            }
+    Ok(AskSettings {
+        client,
+        model_name,
+        api_mode,
+        max_iterations,
+        cache_answers,
+        answer_language,
+        sampling,
+    })
+}
 
-            return Ok(());
+/// Print an answer. One from the answer cache is marked `(cached)` on stderr, or with
+/// `"cached": true` in JSON.
+fn print_output(output: &AskOutput, json: bool) -> Result<()> {
+    if json {
+        let json_output = serde_json::to_string_pretty(output)?;
+        println!("\n{}", json_output);
+    } else {
+        if output.cached {
+            eprintln!("(cached)");
         }
+        println!("\n{}", output.response);
     }
+    Ok(())
+}
 
-    // If no stdin, we need files to search through
-    if files.is_empty() {
-        let error_msg =
-            "No input provided. Either specify files as arguments or pipe input to stdin.";
-        if json {
-            let error_output = ErrorOutput {
-                error: error_msg.to_string(),
-                error_type: "NoInput".to_string(),
-            };
-            let json_output = serde_json::to_string_pretty(&error_output)?;
-            eprintln!("{}", json_output);
-            std::process::exit(1);
-        } else {
-            eprintln!("{}", error_msg);
-        }
-        std::process::exit(1);
-    }
+/// Print an answer and write the reports of it asked for
+fn finish(output: &AskOutput, json: bool, model_name: &str, exports: &Exports) -> Result<()> {
+    print_output(output, json)?;
+    exports.write(model_name, std::slice::from_ref(output))
+}
 
-    // Load embedding model (only needed for file-based search)
-    let model = StaticModel::from_pretrained(
-        MODEL_NAME, // "minishlab/potion-multilingual-128M",
-        None,       // Optional: Hugging Face API token for private models
-        None, // Optional: bool to override model's default normalization. `None` uses model's config.
-        None, // Optional: subfolder if model files are not at the root of the repo/path
-    )?;
-
-    // Run the appropriate agent based on API mode
-    let output = match api_mode {
+/// Run the tool-using agent of the configured API mode
+#[allow(clippy::too_many_arguments)]
+async fn run_agent(
+    files: Vec<String>,
+    query: &str,
+    stdin_content: Option<&str>,
+    model: &StaticModel,
+    settings: &AskSettings,
+    workspace_name: Option<&str>,
+    answer_format: &AnswerFormat,
+    memory: Option<&AgentMemory>,
+    allow_paths: &[String],
+    injection_check: InjectionCheck,
+    events: Option<&AgentEventSender>,
+) -> Result<AskOutput> {
+    match settings.api_mode {
         ApiMode::Chat => {
             ask_agent(
                 files,
-                &query,
-                &model,
-                &client,
-                &model_name,
-                max_iterations,
+                query,
+                stdin_content,
+                model,
+                &settings.client,
+                &settings.model_name,
+                settings.max_iterations,
                 workspace_name,
+                answer_format,
+                memory,
+                allow_paths,
+                injection_check,
+                events,
             )
-            .await?
+            .await
         }
         ApiMode::Responses => {
             ask_agent_responses(
                 files,
-                &query,
-                &model,
-                &client,
-                &model_name,
-                max_iterations,
+                query,
+                stdin_content,
+                model,
+                &settings.client,
+                &settings.model_name,
+                settings.max_iterations,
                 workspace_name,
+                answer_format,
+                memory,
+                allow_paths,
+                injection_check,
+                events,
             )
-            .await?
+            .await
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn ask_cmd(
+    query: String,
+    files: Vec<String>,
+    config: Option<String>,
+    api_key: Option<String>,
+    base_url: Option<String>,
+    model: Option<String>,
+    api_mode: Option<String>,
+    answer_format: Option<String>,
+    answer_language: Option<String>,
+    sampling: Sampling,
+    memory: bool,
+    self_check: bool,
+    injection_check: Option<String>,
+    allow_paths: Vec<String>,
+    cache: bool,
+    no_cache: bool,
+    exports: Exports,
+    dry_run: bool,
+    embed_preset: Option<String>,
+    offline: bool,
+    json: bool,
+    workspace_name: Option<&str>,
+) -> Result<()> {
+    let mut answer_format = answer_format
+        .as_deref()
+        .map(str::parse::<AnswerFormat>)
+        .transpose()?
+        .unwrap_or_default();
+    let injection_check = injection_check
+        .as_deref()
+        .map(str::parse::<InjectionCheck>)
+        .transpose()?
+        .unwrap_or_default();
+    let files = files_or_project_files(files, config.as_deref())?;
+    let embed_preset = resolve_embed_preset(embed_preset.as_deref(), workspace_name)?;
+    let model_source = ModelSource::from_config_file(config.as_deref(), embed_preset)?;
+
+    let settings = resolve_ask_settings(config, api_key, base_url, model, api_mode, sampling)?;
+    answer_format.language = answer_language.or(settings.answer_language.clone());
+    answer_format.retrieval_only = dry_run;
+    let (client, model_name, api_mode) = (
+        &settings.client,
+        settings.model_name.as_str(),
+        settings.api_mode,
+    );
+
+    // Answers drawing on `--memory` depend on earlier questions, not just the corpus, so they
+    // are never cached. `--no-cache` still refreshes the stored answer.
+    let cache_answers = (cache || settings.cache_answers) && !memory && !dry_run;
+    let cache_settings = format!(
+        "{answer_format:?} {api_mode:?} self_check={self_check} injection_check={injection_check:?} \
+         allow_paths={allow_paths:?} {:?}",
+        settings.sampling
+    );
+    let cache_key = |files: &[String], stdin_content: Option<&str>| -> Result<Option<String>> {
+        if !cache_answers {
+            return Ok(None);
+        }
+        let corpus = corpus_hash(files, stdin_content)?;
+        Ok(Some(answer_key(
+            &query,
+            &corpus,
+            model_name,
+            &cache_settings,
+        )))
+    };
+    let cached_answer = |key: &Option<String>| -> Result<Option<AskOutput>> {
+        match key {
+            Some(key) if !no_cache => Ok(AnswerCache::open()?.get(key)),
+            _ => Ok(None),
         }
     };
 
-    if json {
-        let json_output = serde_json::to_string_pretty(&output)?;
-        println!("\n{}", json_output);
+    // Piped input is answered about directly, or alongside the tools when files are given too
+    let stdin_content = if io::stdin().is_terminal() {
+        None
     } else {
-        println!("\n{}", output.response);
+        Some(read_from_stdin()?.join("\n")).filter(|content| !content.is_empty())
+    };
+    // Piped `search --json` results become the lines they cite, and their files are given to
+    // the tools so the agent can read beyond the cited context
+    let (files, stdin_content) = match stdin_content.as_deref().and_then(parse_search_output) {
+        Some(piped) if files.is_empty() => (piped.files, Some(piped.context)),
+        Some(piped) => (files, Some(piped.context)),
+        None => (files, stdin_content),
+    };
+
+    if dry_run && files.is_empty() {
+        return Err(AskError::InvalidArgument(
+            "--dry-run needs files to retrieve from; piped content alone has nothing to search"
+                .to_string(),
+        )
+        .into());
+    }
+
+    if files.is_empty()
+        && let Some(stdin_content) = &stdin_content
+    {
+        let key = cache_key(&[], Some(stdin_content))?;
+        if let Some(output) = cached_answer(&key)? {
+            return finish(&output, json, model_name, &exports);
+        }
+
+        // Run the appropriate agent with stdin content (no tools)
+        let mut output = match api_mode {
+            ApiMode::Chat => {
+                ask_agent_with_stdin(stdin_content, &query, client, model_name, &answer_format)
+                    .await?
+            }
+            ApiMode::Responses => {
+                ask_agent_responses_with_stdin(
+                    stdin_content,
+                    &query,
+                    client,
+                    model_name,
+                    &answer_format,
+                )
+                .await?
+            }
+        };
+
+        if self_check {
+            verify_answer(&mut output, client, model_name, api_mode, &answer_format).await?;
+        }
+        if let Some(key) = &key {
+            AnswerCache::open()?.put(key, &output)?;
+        }
+
+        return finish(&output, json, model_name, &exports);
+    }
+
+    // If no stdin, we need files to search through
+    if files.is_empty() {
+        return Err(AskError::NoInput.into());
+    }
+
+    // Web pages are downloaded and stripped to text, so the tools can search and read them.
+    // Files inside archives keep their `archive!member` names.
+    let files = resolve_inputs(files, true).await?.member_paths();
+    if files.is_empty() {
+        anyhow::bail!("None of the given files could be read");
+    }
+
+    let key = cache_key(&files, stdin_content.as_deref())?;
+    if let Some(output) = cached_answer(&key)? {
+        return finish(&output, json, model_name, &exports);
+    }
+
+    // Load embedding model (only needed for file-based search)
+    let model = model_source.load(offline)?;
+
+    let agent_memory = agent_memory(memory, workspace_name)?;
+
+    let mut output = run_agent(
+        files,
+        &query,
+        stdin_content.as_deref(),
+        &model,
+        &settings,
+        workspace_name,
+        &answer_format,
+        agent_memory.as_ref(),
+        &allow_paths,
+        injection_check,
+        None,
+    )
+    .await?;
+
+    if dry_run {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&dry_run_output(&output))?
+        );
+        return Ok(());
+    }
+
+    if self_check {
+        verify_answer(&mut output, client, model_name, api_mode, &answer_format).await?;
+    }
+    if let Some(key) = &key {
+        AnswerCache::open()?.put(key, &output)?;
+    }
+
+    finish(&output, json, model_name, &exports)?;
+
+    #[cfg(feature = "workspace-lite")]
+    if agent_memory.is_some() {
+        remember(workspace_name, &output)?;
     }
 
+    // Index maintenance runs only after the answer has been printed
+    #[cfg(feature = "workspace-lite")]
+    if Workspace::active(workspace_name).is_ok() {
+        Workspace::run_scheduled_maintenance(workspace_name)?;
+    }
+
+    Ok(())
+}
+
+/// Lines typed at the `ask --interactive` prompt, kept across sessions in the data directory
+const HISTORY_FILE: &str = "ask_history";
+
+/// Where `/save` writes the conversation unless given a path
+const DEFAULT_TRANSCRIPT: &str = "semtools-conversation.md";
+
+/// Show a tool call of the agent as it happens
+fn print_event(event: &AgentEvent) {
+    match event {
+        AgentEvent::ToolCall { name, arguments } => eprintln!("  -> {name} {arguments}"),
+        AgentEvent::ToolResult {
+            name,
+            chunks,
+            lines,
+        } => eprintln!("  <- {name}: {chunks} chunk(s), {lines} line(s)"),
+        AgentEvent::InjectionDetected { name, reason, file } => eprintln!(
+            "  !! {name} returned suspected instructions{}: {reason}",
+            file.as_deref()
+                .map(|file| format!(" in {file}"))
+                .unwrap_or_default()
+        ),
+    }
+}
+
+/// State of an `ask --interactive` session
+struct ReplSession {
+    files: Vec<String>,
+    workspace_name: Option<String>,
+    model: StaticModel,
+    /// Embedding model `model` was loaded from
+    #[cfg_attr(not(feature = "workspace-lite"), allow(dead_code))]
+    model_source: ModelSource,
+    /// `--embed-preset`, for checking the workspaces switched to
+    #[cfg_attr(not(feature = "workspace-lite"), allow(dead_code))]
+    requested_preset: Option<EmbedPreset>,
+    #[cfg_attr(not(feature = "workspace-lite"), allow(dead_code))]
+    config: Option<String>,
+    settings: AskSettings,
+    answer_format: AnswerFormat,
+    injection_check: InjectionCheck,
+    allow_paths: Vec<String>,
+    self_check: bool,
+    #[cfg_attr(not(feature = "workspace-lite"), allow(dead_code))]
+    memory: bool,
+    agent_memory: Option<AgentMemory>,
+    conversation: Conversation,
+}
+
+impl ReplSession {
+    async fn handle(&mut self, input: ReplInput) -> Result<()> {
+        match input {
+            ReplInput::Question(question) => self.answer(question).await?,
+            ReplInput::Files(paths) if paths.is_empty() => {
+                for file in &self.files {
+                    println!("{file}");
+                }
+                println!("({} file(s))", self.files.len());
+            }
+            ReplInput::Files(paths) => {
+                self.files = resolve_inputs(paths, true).await?.member_paths();
+                println!("Searching {} file(s)", self.files.len());
+            }
+            ReplInput::Workspace(name) => self.workspace(name)?,
+            ReplInput::Clear => {
+                self.conversation.clear();
+                println!("Conversation cleared");
+            }
+            ReplInput::Save(path) => {
+                if self.conversation.is_empty() {
+                    anyhow::bail!("Nothing to save yet");
+                }
+                let path = path.unwrap_or_else(|| DEFAULT_TRANSCRIPT.to_string());
+                self.conversation.save(Path::new(&path))?;
+                println!("Saved {} answer(s) to {path}", self.conversation.len());
+            }
+            ReplInput::Help => println!("{HELP}"),
+            ReplInput::Exit | ReplInput::Empty => {}
+        }
+        Ok(())
+    }
+
+    /// Answer a question with the conversation so far as context. Tool calls are printed as
+    /// the agent makes them.
+    async fn answer(&mut self, question: String) -> Result<()> {
+        if self.files.is_empty() {
+            anyhow::bail!("No files to search. Add some with /files PATH...");
+        }
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let display = tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                print_event(&event);
+            }
+        });
+        let output = run_agent(
+            self.files.clone(),
+            &self.conversation.message(&question),
+            None,
+            &self.model,
+            &self.settings,
+            self.workspace_name.as_deref(),
+            &self.answer_format,
+            self.agent_memory.as_ref(),
+            &self.allow_paths,
+            self.injection_check,
+            Some(&sender),
+        )
+        .await;
+        drop(sender);
+        display.await?;
+        let mut output = output?;
+        output.query = question;
+
+        if self.self_check {
+            verify_answer(
+                &mut output,
+                &self.settings.client,
+                &self.settings.model_name,
+                self.settings.api_mode,
+                &self.answer_format,
+            )
+            .await?;
+        }
+        print_output(&output, false)?;
+        println!();
+
+        #[cfg(feature = "workspace-lite")]
+        if self.agent_memory.is_some() {
+            remember(self.workspace_name.as_deref(), &output)?;
+            self.agent_memory = agent_memory(true, self.workspace_name.as_deref())?;
+        }
+        self.conversation.push(output);
+        Ok(())
+    }
+
+    /// Show the active workspace, or search through `name` from the next question on. The new
+    /// workspace must use the embedding model this session loaded.
+    #[cfg(feature = "workspace-lite")]
+    fn workspace(&mut self, name: Option<String>) -> Result<()> {
+        let Some(name) = name else {
+            match Workspace::active(self.workspace_name.as_deref()) {
+                Ok(name) => println!("Active workspace: {name}"),
+                Err(_) => println!("No active workspace"),
+            }
+            return Ok(());
+        };
+        if !Workspace::exists(&name)? {
+            anyhow::bail!("Workspace '{name}' does not exist");
+        }
+        let preset = Workspace::open(Some(&name))?.embed_preset(self.requested_preset);
+        let source = ModelSource::from_config_file(self.config.as_deref(), Some(preset))?;
+        if source.name != self.model_source.name {
+            anyhow::bail!(
+                "Workspace '{name}' is searched with {}, but this session loaded {}. Restart \
+                 with --workspace {name} to use it.",
+                source.name,
+                self.model_source.name
+            );
+        }
+        set_space_override(preset);
+        self.agent_memory = agent_memory(self.memory, Some(&name))?;
+        self.workspace_name = Some(name);
+        println!(
+            "Active workspace: {}",
+            Workspace::active(self.workspace_name.as_deref())?
+        );
+        Ok(())
+    }
+
+    #[cfg(not(feature = "workspace-lite"))]
+    fn workspace(&mut self, _name: Option<String>) -> Result<()> {
+        anyhow::bail!("/workspace requires semtools to be built with the workspace feature")
+    }
+}
+
+/// `ask --interactive`: answer questions about `files` in a loop, with the embedding model
+/// loaded once and the earlier questions and answers as context
+#[allow(clippy::too_many_arguments)]
+pub async fn ask_interactive_cmd(
+    files: Vec<String>,
+    config: Option<String>,
+    api_key: Option<String>,
+    base_url: Option<String>,
+    model: Option<String>,
+    api_mode: Option<String>,
+    answer_format: Option<String>,
+    answer_language: Option<String>,
+    sampling: Sampling,
+    memory: bool,
+    self_check: bool,
+    injection_check: Option<String>,
+    allow_paths: Vec<String>,
+    exports: Exports,
+    embed_preset: Option<String>,
+    offline: bool,
+    workspace_name: Option<&str>,
+) -> Result<()> {
+    let mut answer_format = answer_format
+        .as_deref()
+        .map(str::parse::<AnswerFormat>)
+        .transpose()?
+        .unwrap_or_default();
+    let injection_check = injection_check
+        .as_deref()
+        .map(str::parse::<InjectionCheck>)
+        .transpose()?
+        .unwrap_or_default();
+    let requested_preset = embed_preset
+        .as_deref()
+        .map(str::parse::<EmbedPreset>)
+        .transpose()?;
+    let embed_preset = resolve_embed_preset(embed_preset.as_deref(), workspace_name)?;
+    let model_source = ModelSource::from_config_file(config.as_deref(), embed_preset)?;
+    let settings =
+        resolve_ask_settings(config.clone(), api_key, base_url, model, api_mode, sampling)?;
+    answer_format.language = answer_language.or(settings.answer_language.clone());
+
+    let files = files_or_project_files(files, config.as_deref())?;
+    let files = resolve_inputs(files, true).await?.member_paths();
+    let model = model_source.load(offline)?;
+    let agent_memory = agent_memory(memory, workspace_name)?;
+
+    let mut editor = DefaultEditor::new()?;
+    let history_path = crate::paths::data_path(HISTORY_FILE)?;
+    // There is no history before the first session
+    let _ = editor.load_history(&history_path);
+
+    eprintln!(
+        "Asking {} about {} file(s). Type /help for commands, /exit or Ctrl-D to quit.",
+        settings.model_name,
+        files.len()
+    );
+    let mut session = ReplSession {
+        files,
+        workspace_name: workspace_name.map(str::to_string),
+        model,
+        model_source,
+        requested_preset,
+        config,
+        settings,
+        answer_format,
+        injection_check,
+        allow_paths,
+        self_check,
+        memory,
+        agent_memory,
+        conversation: Conversation::default(),
+    };
+
+    loop {
+        let line = match editor.readline("ask> ") {
+            Ok(line) => line,
+            // Ctrl-C drops the line being typed, as in a shell
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if !line.trim().is_empty() {
+            let _ = editor.add_history_entry(line.trim());
+        }
+        let input = match ReplInput::parse(&line) {
+            Ok(ReplInput::Exit) => break,
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("{e}");
+                continue;
+            }
+        };
+        // A failed question or command is reported without ending the session
+        if let Err(e) = session.handle(input).await {
+            eprintln!("Error: {e:#}");
+        }
+    }
+
+    if let Some(parent) = history_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    if let Err(e) = editor.save_history(&history_path) {
+        eprintln!("Warning: could not save the prompt history: {e}");
+    }
+    // The whole session goes into the reports, once it is over
+    if !session.conversation.is_empty() {
+        exports.write(&session.settings.model_name, session.conversation.turns())?;
+    }
+
+    // Index maintenance runs once the session is over
+    #[cfg(feature = "workspace-lite")]
+    if Workspace::active(session.workspace_name.as_deref()).is_ok() {
+        Workspace::run_scheduled_maintenance(session.workspace_name.as_deref())?;
+    }
     Ok(())
 }
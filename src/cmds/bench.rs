@@ -0,0 +1,180 @@
+use anyhow::Result;
+
+#[cfg(feature = "workspace-lite")]
+use crate::search::search_with_workspace;
+#[cfg(feature = "workspace-lite")]
+use crate::workspace::Workspace;
+
+use crate::json_mode::{BenchOutput, BenchQueryJSON};
+use crate::search::bench::{Hit, QueryMetrics, evaluate, parse_qrels};
+use crate::search::model::{ModelSource, resolve_embed_preset};
+use crate::search::{
+    DEFAULT_NEGATIVE_WEIGHT, Document, QueryCombination, SearchConfig, embed_query, load_documents,
+    search_documents,
+};
+
+/// Where the queries are run: the active workspace's index, or documents embedded in memory
+enum Corpus {
+    #[cfg(feature = "workspace-lite")]
+    Workspace,
+    Documents(Vec<Document>),
+}
+
+fn mean(values: impl Iterator<Item = f64>) -> f64 {
+    let (sum, count) = values.fold((0.0, 0), |(sum, count), v| (sum + v, count + 1));
+    if count == 0 { 0.0 } else { sum / count as f64 }
+}
+
+fn print_bench_results(output: &BenchOutput) {
+    let k = output.k;
+    println!(
+        "{:<48} {:>7} {:>7} {:>8}",
+        "Query",
+        format!("R@{k}"),
+        "RR",
+        format!("nDCG@{k}")
+    );
+    for query in &output.queries {
+        let mut label: String = query.query.chars().take(46).collect();
+        if label.len() < query.query.len() {
+            label.push('…');
+        }
+        println!(
+            "{label:<48} {:>7.3} {:>7.3} {:>8.3}",
+            query.recall, query.reciprocal_rank, query.ndcg
+        );
+    }
+    println!();
+    println!(
+        "Mean over {} queries: recall@{k} {:.3}, MRR {:.3}, nDCG@{k} {:.3}",
+        output.queries.len(),
+        output.recall,
+        output.mrr,
+        output.ndcg
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn bench_cmd(
+    qrels: String,
+    files: Vec<String>,
+    n_lines: usize,
+    top_k: usize,
+    ignore_case: bool,
+    recency_boost: f64,
+    mmr_lambda: Option<f64>,
+    embed_preset: Option<String>,
+    offline: bool,
+    json: bool,
+    workspace_name: Option<&str>,
+) -> Result<()> {
+    if let Some(lambda) = mmr_lambda
+        && !(0.0..=1.0).contains(&lambda)
+    {
+        anyhow::bail!("--mmr must be between 0.0 and 1.0, got {lambda}");
+    }
+    let qrels = parse_qrels(&std::fs::read_to_string(&qrels)?)?;
+    if qrels.is_empty() {
+        anyhow::bail!("The qrels file has no judgments");
+    }
+    if files.is_empty() {
+        anyhow::bail!("No corpus provided. Specify the files to run the queries against.");
+    }
+
+    let embed_preset = resolve_embed_preset(embed_preset.as_deref(), workspace_name)?;
+    let model = ModelSource::from_config_file(None, embed_preset)?.load(offline)?;
+
+    let config = SearchConfig {
+        n_lines,
+        top_k,
+        max_distance: None,
+        ignore_case,
+        recency_boost,
+        mmr_lambda,
+        negative_embeddings: Vec::new(),
+        negative_weight: DEFAULT_NEGATIVE_WEIGHT,
+        extra_query_embeddings: Vec::new(),
+        query_combination: QueryCombination::All,
+        tag_filters: Vec::new(),
+        metadata_filters: Vec::new(),
+        boosts: Vec::new(),
+    };
+
+    // The same retrieval `search` would use, so results reflect the current configuration
+    #[cfg(feature = "workspace-lite")]
+    let corpus = if Workspace::active(workspace_name).is_ok() {
+        Corpus::Workspace
+    } else {
+        Corpus::Documents(load_documents(&files, &model, ignore_case)?)
+    };
+    #[cfg(not(feature = "workspace-lite"))]
+    let corpus = Corpus::Documents(load_documents(&files, &model, ignore_case)?);
+
+    let mut queries = Vec::with_capacity(qrels.len());
+    for qrel in &qrels {
+        let query = if ignore_case {
+            qrel.query.to_lowercase()
+        } else {
+            qrel.query.clone()
+        };
+
+        let hits: Vec<Hit> = match &corpus {
+            #[cfg(feature = "workspace-lite")]
+            Corpus::Workspace => {
+                search_with_workspace(&files, &query, &model, &config, workspace_name)
+                    .await?
+                    .into_iter()
+                    .map(|ranked_line| {
+                        let line = ranked_line.line_number as usize;
+                        Hit {
+                            path: ranked_line.path,
+                            start: line.saturating_sub(n_lines),
+                            end: line + n_lines + 1,
+                        }
+                    })
+                    .collect()
+            }
+            Corpus::Documents(documents) => {
+                search_documents(documents, &embed_query(&model, &query), &config)
+                    .into_iter()
+                    .map(|result| Hit {
+                        path: result.filename,
+                        start: result.start,
+                        end: result.end,
+                    })
+                    .collect()
+            }
+        };
+
+        let QueryMetrics {
+            recall,
+            reciprocal_rank,
+            ndcg,
+            found,
+        } = evaluate(&qrel.judgments, &hits, top_k);
+        queries.push(BenchQueryJSON {
+            query: qrel.query.clone(),
+            relevant: qrel.judgments.len(),
+            found,
+            recall,
+            reciprocal_rank,
+            ndcg,
+        });
+    }
+
+    let output = BenchOutput {
+        k: top_k,
+        recall: mean(queries.iter().map(|q| q.recall)),
+        mrr: mean(queries.iter().map(|q| q.reciprocal_rank)),
+        ndcg: mean(queries.iter().map(|q| q.ndcg)),
+        queries,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        print_bench_results(&output);
+    }
+
+    Ok(())
+}
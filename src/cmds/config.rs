@@ -0,0 +1,152 @@
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+use crate::SemtoolsConfig;
+use crate::config::{
+    effective_config, load_project_config, merge_config, set_config_value, validate_config,
+};
+use crate::json_mode::{ConfigFileOutput, ConfigSetOutput, ConfigShowOutput};
+
+fn config_path(config: Option<String>) -> String {
+    config.unwrap_or_else(SemtoolsConfig::default_config_path)
+}
+
+/// The config file as JSON, or an empty object if it does not exist yet
+fn read_config(path: &str) -> Result<Value> {
+    if !Path::new(path).exists() {
+        return Ok(Value::Object(Default::default()));
+    }
+    let contents = fs::read_to_string(path)?;
+    serde_json::from_str(&contents).with_context(|| format!("{path} is not valid JSON"))
+}
+
+fn write_config(path: &str, config: &Value) -> Result<()> {
+    if let Some(parent) = Path::new(path).parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(config)? + "\n")?;
+    Ok(())
+}
+
+fn remove_api_keys(value: &mut Value) {
+    if let Value::Object(map) = value {
+        map.remove("api_key");
+        map.values_mut().for_each(remove_api_keys);
+    }
+}
+
+/// Write the default config, every section filled in, so its keys can be edited in place.
+/// API keys are left out: they are better kept in the environment.
+pub fn config_init_cmd(config: Option<String>, force: bool, json: bool) -> Result<()> {
+    let path = config_path(config);
+    if Path::new(&path).exists() && !force {
+        bail!("{path} already exists. Pass --force to overwrite it with the defaults");
+    }
+    let mut defaults = serde_json::to_value(SemtoolsConfig::defaults())?;
+    remove_api_keys(&mut defaults);
+    write_config(&path, &defaults)?;
+
+    if json {
+        let output = ConfigFileOutput {
+            path,
+            problems: Vec::new(),
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        println!("Wrote the default config to {path}");
+        println!("Every key is optional: remove the ones you do not need to change.");
+        println!(
+            "API keys are read from OPENAI_API_KEY and LLAMA_CLOUD_API_KEY, or `api_key` \
+             (which may be \"${{VAR}}\") or `api_key_cmd`."
+        );
+        println!("See the Configuration section of the README for what each key does.");
+    }
+    Ok(())
+}
+
+/// Print the config in effect: the file merged over the defaults, API keys masked
+pub fn config_show_cmd(config: Option<String>, json: bool) -> Result<()> {
+    let path = config_path(config);
+    let exists = Path::new(&path).exists();
+    let mut contents = read_config(&path)?;
+    let project = load_project_config()?;
+    if let Some((_, project_config)) = &project {
+        merge_config(&mut contents, project_config);
+    }
+    let effective = effective_config(&contents)?;
+    let project = project.map(|(project_path, _)| project_path.to_string_lossy().to_string());
+
+    if json {
+        let output = ConfigShowOutput {
+            path,
+            exists,
+            project,
+            config: effective,
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        if !exists {
+            eprintln!("{path} does not exist; showing the defaults");
+        }
+        if let Some(project) = &project {
+            eprintln!("With the project config {project} merged over it");
+        }
+        println!("{}", serde_json::to_string_pretty(&effective)?);
+    }
+    Ok(())
+}
+
+/// Report unknown sections and keys, and values of the wrong type
+pub fn config_validate_cmd(config: Option<String>, json: bool) -> Result<()> {
+    let path = config_path(config);
+    let problems = validate_config(&read_config(&path)?);
+
+    if json {
+        let output = ConfigFileOutput {
+            path: path.clone(),
+            problems: problems.clone(),
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else if problems.is_empty() {
+        println!("{path} is valid");
+    } else {
+        println!("{path} has {} problem(s):", problems.len());
+        for problem in &problems {
+            println!("  - {problem}");
+        }
+    }
+    if !problems.is_empty() {
+        bail!("Invalid config file {path}");
+    }
+    Ok(())
+}
+
+/// Set a dotted key such as `ask.model`, refusing values that would make the config invalid
+pub fn config_set_cmd(config: Option<String>, key: &str, value: &str, json: bool) -> Result<()> {
+    let path = config_path(config);
+    let mut contents = read_config(&path)?;
+    let value = set_config_value(&mut contents, key, value)?;
+
+    let problems = validate_config(&contents);
+    if !problems.is_empty() {
+        bail!(
+            "Not setting {key}: the config would be invalid:\n  - {}",
+            problems.join("\n  - ")
+        );
+    }
+    write_config(&path, &contents)?;
+
+    if json {
+        let output = ConfigSetOutput {
+            path,
+            key: key.to_string(),
+            value,
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        println!("Set {key} = {value} in {path}");
+    }
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use anyhow::Result;
+use std::fs;
+use std::path::Path;
+
+use crate::cmds::exit::Outcome;
+use crate::json_mode::{DedupeOutput, DuplicateGroupJSON};
+use crate::search::dedupe::{DuplicateGroup, DuplicateKind, find_duplicates};
+use crate::search::error::SearchError;
+use crate::search::model::{EmbedPreset, ModelSource};
+
+/// What to do with redundant copies in exact-duplicate groups
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DedupeAction {
+    Report,
+    Delete,
+    Link,
+}
+
+fn print_duplicate_groups(groups: &[DuplicateGroup]) {
+    if groups.is_empty() {
+        println!("No duplicate documents found.");
+        return;
+    }
+
+    for group in groups {
+        match group.kind {
+            DuplicateKind::Exact => println!("Exact duplicates ({} files):", group.files.len()),
+            DuplicateKind::Near => println!(
+                "Near duplicates ({} files, max distance {:.4}):",
+                group.files.len(),
+                group.max_distance
+            ),
+        }
+        for file in &group.files {
+            println!("  {file}");
+        }
+        println!();
+    }
+}
+
+/// Replace `duplicate` with a hard link to `keep`. The link is made under a temporary name
+/// next to `duplicate` and renamed over it, so `duplicate` is never missing, and is left as it
+/// was when linking fails.
+fn replace_with_link(keep: &str, duplicate: &str) -> Result<()> {
+    let duplicate = Path::new(duplicate);
+    let name = duplicate.file_name().unwrap_or_default().to_string_lossy();
+    let temp = duplicate.with_file_name(format!(".{name}.semtools-link"));
+    let _ = fs::remove_file(&temp);
+    fs::hard_link(keep, &temp)?;
+    if let Err(e) = fs::rename(&temp, duplicate) {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+/// Delete or hard-link every file but the first in each exact-duplicate group.
+/// Near-duplicate groups are never modified since their contents differ.
+fn apply_action(groups: &[DuplicateGroup], action: DedupeAction) -> Result<Vec<String>> {
+    let mut changed = Vec::new();
+    if action == DedupeAction::Report {
+        return Ok(changed);
+    }
+
+    for group in groups.iter().filter(|g| g.kind == DuplicateKind::Exact) {
+        let keep = &group.files[0];
+        for duplicate in &group.files[1..] {
+            match action {
+                DedupeAction::Delete => fs::remove_file(duplicate)?,
+                DedupeAction::Link => replace_with_link(keep, duplicate)?,
+                DedupeAction::Report => {}
+            }
+            changed.push(duplicate.clone());
+        }
+    }
+
+    Ok(changed)
+}
+
+pub async fn dedupe_cmd(
+    files: Vec<String>,
+    max_distance: f64,
+    action: DedupeAction,
+    embed_preset: Option<String>,
+    offline: bool,
+    json: bool,
+    quiet: bool,
+) -> Result<Outcome> {
+    if files.is_empty() {
+        return Err(SearchError::NoInput(
+            "No input provided. Specify files to check for duplicates.".to_string(),
+        )
+        .into());
+    }
+
+    let embed_preset = embed_preset
+        .as_deref()
+        .map(str::parse::<EmbedPreset>)
+        .transpose()?;
+    let model = ModelSource::from_config_file(None, embed_preset)?.load(offline)?;
+
+    let groups = find_duplicates(&files, &model, max_distance)?;
+    let changed = apply_action(&groups, action)?;
+
+    if quiet {
+        // Only the exit status is wanted
+    } else if json {
+        let (files_removed, files_linked) = match action {
+            DedupeAction::Delete => (changed, Vec::new()),
+            DedupeAction::Link => (Vec::new(), changed),
+            DedupeAction::Report => (Vec::new(), Vec::new()),
+        };
+        let output = DedupeOutput {
+            groups: groups
+                .iter()
+                .map(|g| DuplicateGroupJSON {
+                    kind: g.kind.as_str().to_string(),
+                    files: g.files.clone(),
+                    max_distance: g.max_distance,
+                })
+                .collect(),
+            files_removed,
+            files_linked,
+        };
+        let json_output = serde_json::to_string_pretty(&output)?;
+        println!("{}", json_output);
+    } else {
+        print_duplicate_groups(&groups);
+        match action {
+            DedupeAction::Delete => println!("Removed {} duplicate files.", changed.len()),
+            DedupeAction::Link => println!(
+                "Replaced {} duplicate files with hard links.",
+                changed.len()
+            ),
+            DedupeAction::Report => {}
+        }
+    }
+
+    Ok(Outcome::of_search(!groups.is_empty(), false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact_group(files: &[&std::path::PathBuf]) -> DuplicateGroup {
+        DuplicateGroup {
+            kind: DuplicateKind::Exact,
+            files: files
+                .iter()
+                .map(|f| f.to_string_lossy().to_string())
+                .collect(),
+            max_distance: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_link_replaces_duplicate_in_place() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let keep = dir.path().join("a.md");
+        let duplicate = dir.path().join("b.md");
+        fs::write(&keep, "same").unwrap();
+        fs::write(&duplicate, "same").unwrap();
+
+        let changed =
+            apply_action(&[exact_group(&[&keep, &duplicate])], DedupeAction::Link).unwrap();
+
+        assert_eq!(changed, vec![duplicate.to_string_lossy().to_string()]);
+        assert_eq!(fs::read_to_string(&duplicate).unwrap(), "same");
+        // Writing through one name shows through the other once they are linked
+        fs::write(&keep, "changed").unwrap();
+        assert_eq!(fs::read_to_string(&duplicate).unwrap(), "changed");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_failed_link_leaves_duplicate() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let keep = dir.path().join("missing.md");
+        let duplicate = dir.path().join("b.md");
+        fs::write(&duplicate, "same").unwrap();
+
+        assert!(apply_action(&[exact_group(&[&keep, &duplicate])], DedupeAction::Link).is_err());
+
+        assert_eq!(fs::read_to_string(&duplicate).unwrap(), "same");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
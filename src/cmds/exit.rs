@@ -0,0 +1,144 @@
+use std::process::ExitCode;
+
+/// Success. For `search`, at least one match was found
+pub const EXIT_SUCCESS: u8 = 0;
+/// `search` found no matches (like grep), or a lookup such as `parse cache path` came up empty
+pub const EXIT_NO_MATCHES: u8 = 1;
+/// Invalid arguments or input, or the command failed outright
+pub const EXIT_USAGE_ERROR: u8 = 2;
+/// Some inputs were processed, but others could not be read or parsed
+pub const EXIT_PARTIAL_FAILURE: u8 = 3;
+/// Something needed first is missing: an embedding model in offline mode, an API key, an
+/// active workspace or a workspace's passphrase
+pub const EXIT_NOT_SET_UP: u8 = 4;
+/// A remote service (the LLM API or LlamaParse) failed
+pub const EXIT_SERVICE_ERROR: u8 = 5;
+/// `pick` was left without choosing a line (as fzf does on Esc or Ctrl-C)
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+/// How a subcommand that completed went, mapped to the process exit code
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Outcome {
+    #[default]
+    Success,
+    NoMatches,
+    PartialFailure,
+    Interrupted,
+}
+
+impl Outcome {
+    /// Outcome of a search: failed inputs take precedence over whether anything matched
+    pub fn of_search(found: bool, partial_failure: bool) -> Self {
+        if partial_failure {
+            Outcome::PartialFailure
+        } else if found {
+            Outcome::Success
+        } else {
+            Outcome::NoMatches
+        }
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            Outcome::Success => EXIT_SUCCESS,
+            Outcome::NoMatches => EXIT_NO_MATCHES,
+            Outcome::PartialFailure => EXIT_PARTIAL_FAILURE,
+            Outcome::Interrupted => EXIT_INTERRUPTED,
+        }
+    }
+}
+
+impl From<Outcome> for ExitCode {
+    fn from(outcome: Outcome) -> Self {
+        ExitCode::from(outcome.code())
+    }
+}
+
+/// Print `message` and exit with [`EXIT_USAGE_ERROR`]
+pub fn usage_error(message: impl std::fmt::Display) -> ! {
+    eprintln!("Error: {message}");
+    std::process::exit(EXIT_USAGE_ERROR.into())
+}
+
+/// Exit code and `error_type` of a failed command, from the first typed error in its chain.
+/// Errors with no type of their own are usage errors, reported as `Error`.
+pub fn classify(error: &anyhow::Error) -> (u8, &'static str) {
+    for cause in error.chain() {
+        #[cfg(feature = "search")]
+        if let Some(e) = cause.downcast_ref::<crate::search::error::SearchError>() {
+            use crate::search::error::SearchError;
+            let code = match e {
+                SearchError::ModelNotFound { .. } | SearchError::ModelNotCached { .. } => {
+                    EXIT_NOT_SET_UP
+                }
+                _ => EXIT_USAGE_ERROR,
+            };
+            return (code, e.error_type());
+        }
+        #[cfg(feature = "workspace-lite")]
+        if let Some(e) = cause.downcast_ref::<crate::workspace::error::WorkspaceError>() {
+            use crate::workspace::error::WorkspaceError;
+            let code = match e {
+                WorkspaceError::NoActiveWorkspace
+                | WorkspaceError::PassphraseRequired
+                | WorkspaceError::WrongPassphrase => EXIT_NOT_SET_UP,
+                _ => EXIT_USAGE_ERROR,
+            };
+            return (code, e.error_type());
+        }
+        #[cfg(feature = "ask")]
+        if let Some(e) = cause.downcast_ref::<crate::ask::error::AskError>() {
+            use crate::ask::error::AskError;
+            let code = match e {
+                AskError::MissingApiKey | AskError::MemoryNeedsWorkspace => EXIT_NOT_SET_UP,
+                AskError::Api(_) | AskError::EmptyResponse => EXIT_SERVICE_ERROR,
+                _ => EXIT_USAGE_ERROR,
+            };
+            return (code, e.error_type());
+        }
+        #[cfg(feature = "parse")]
+        if cause.is::<crate::parse::error::JobError>() {
+            return (EXIT_SERVICE_ERROR, "ParseError");
+        }
+    }
+    (EXIT_USAGE_ERROR, "Error")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_outcome() {
+        assert_eq!(Outcome::of_search(true, false).code(), EXIT_SUCCESS);
+        assert_eq!(Outcome::of_search(false, false).code(), EXIT_NO_MATCHES);
+        assert_eq!(Outcome::of_search(true, true).code(), EXIT_PARTIAL_FAILURE);
+        assert_eq!(Outcome::of_search(false, true).code(), EXIT_PARTIAL_FAILURE);
+    }
+
+    #[test]
+    fn test_classify_errors() {
+        let untyped = anyhow::anyhow!("something broke");
+        assert_eq!(classify(&untyped), (EXIT_USAGE_ERROR, "Error"));
+
+        #[cfg(feature = "search")]
+        {
+            use crate::search::error::SearchError;
+            let not_cached = anyhow::Error::from(SearchError::ModelNotCached {
+                name: "m".to_string(),
+                cache_dir: "/tmp".into(),
+            });
+            assert_eq!(classify(&not_cached), (EXIT_NOT_SET_UP, "NotCached"));
+            // Context added on the way up does not hide the typed error
+            let no_input = anyhow::Error::from(SearchError::NoInput("none".to_string()))
+                .context("search failed");
+            assert_eq!(classify(&no_input), (EXIT_USAGE_ERROR, "NoInput"));
+        }
+
+        #[cfg(feature = "ask")]
+        {
+            let missing_key = anyhow::Error::from(crate::ask::error::AskError::MissingApiKey);
+            assert_eq!(classify(&missing_key), (EXIT_NOT_SET_UP, "MissingApiKey"));
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::io::{self, IsTerminal, Read};
+
+use crate::ask::extract::{Extractor, MAX_DOCUMENT_CHARS};
+use crate::ask::sampling::Sampling;
+use crate::cmds::ask::{AskSettings, resolve_ask_settings};
+use crate::json_mode::ExtractRow;
+
+/// Cut very long documents so a single file cannot exceed the model's context
+fn limit_document(file: &str, content: String) -> String {
+    if content.chars().count() <= MAX_DOCUMENT_CHARS {
+        return content;
+    }
+    eprintln!(
+        "Warning: {file} is longer than {MAX_DOCUMENT_CHARS} characters; only the start is used"
+    );
+    content.chars().take(MAX_DOCUMENT_CHARS).collect()
+}
+
+fn print_row(row: &ExtractRow) -> Result<()> {
+    println!("{}", serde_json::to_string(row)?);
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn extract_cmd(
+    schema: String,
+    files: Vec<String>,
+    config: Option<String>,
+    api_key: Option<String>,
+    base_url: Option<String>,
+    model: Option<String>,
+    api_mode: Option<String>,
+) -> Result<()> {
+    let schema_content = std::fs::read_to_string(&schema)
+        .map_err(|e| anyhow::anyhow!("Failed to read schema file '{schema}': {e}"))?;
+    let schema_json: Value = serde_json::from_str(&schema_content)
+        .map_err(|e| anyhow::anyhow!("Schema file '{schema}' is not valid JSON: {e}"))?;
+
+    let AskSettings {
+        client,
+        model_name,
+        api_mode,
+        ..
+    } = resolve_ask_settings(
+        config,
+        api_key,
+        base_url,
+        model,
+        api_mode,
+        Sampling::default(),
+    )?;
+    let extractor = Extractor::new(&client, &model_name, api_mode, &schema_json)?;
+
+    // Read a single document from stdin when no files are given
+    let documents: Vec<(String, Result<String>)> = if files.is_empty() {
+        if io::stdin().is_terminal() {
+            anyhow::bail!(
+                "No input provided. Either specify files as arguments or pipe input to stdin."
+            );
+        }
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)?;
+        vec![("<stdin>".to_string(), Ok(content))]
+    } else {
+        files
+            .into_iter()
+            .map(|file| {
+                let content = std::fs::read_to_string(&file).map_err(anyhow::Error::from);
+                (file, content)
+            })
+            .collect()
+    };
+
+    let total = documents.len();
+    let mut failed = 0;
+    for (file, content) in documents {
+        let result = match content {
+            Ok(content) => extractor.extract(&limit_document(&file, content)).await,
+            Err(e) => Err(e),
+        };
+        let row = match result {
+            Ok(data) => ExtractRow {
+                file,
+                data: Some(data),
+                error: None,
+            },
+            Err(e) => {
+                failed += 1;
+                ExtractRow {
+                    file,
+                    data: None,
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+        print_row(&row)?;
+    }
+
+    if failed > 0 {
+        anyhow::bail!("Extraction failed for {failed} of {total} document(s)");
+    }
+
+    Ok(())
+}
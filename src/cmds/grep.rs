@@ -0,0 +1,86 @@
+use anyhow::Result;
+use std::io::{self, IsTerminal};
+
+use crate::cmds::exit::Outcome;
+use crate::grep::{GrepBlock, GrepOptions, grep_paths};
+use crate::json_mode::{GrepFilesOutput, GrepOutput, GrepResultJSON};
+
+fn print_blocks(blocks: &[GrepBlock]) {
+    let is_tty = io::stdout().is_terminal();
+    for block in blocks {
+        println!("{}:{}::{}", block.file, block.start, block.end());
+        for (i, line) in block.lines.iter().enumerate() {
+            let line_number = block.start + i;
+            if is_tty && block.matches.contains(&line_number) {
+                // Highlight matching lines like search does
+                println!("\x1b[43m\x1b[30m{:4}: {}\x1b[0m", line_number + 1, line);
+            } else {
+                println!("{:4}: {}", line_number + 1, line);
+            }
+        }
+        println!();
+    }
+}
+
+/// Files with at least one match, in order
+fn matching_files(blocks: &[GrepBlock]) -> Vec<&str> {
+    let mut files: Vec<&str> = blocks.iter().map(|block| block.file.as_str()).collect();
+    files.dedup();
+    files
+}
+
+/// `semtools grep`: exact-pattern search with ripgrep's matcher, printed like `search` results
+pub fn grep_cmd(
+    pattern: String,
+    paths: Vec<String>,
+    options: GrepOptions,
+    files_with_matches: bool,
+    json: bool,
+    quiet: bool,
+) -> Result<Outcome> {
+    // Like ripgrep, search the working directory when no paths are given
+    let paths = if paths.is_empty() {
+        vec![".".to_string()]
+    } else {
+        paths
+    };
+    let results = grep_paths(&paths, &pattern, &options)?;
+    for error in &results.errors {
+        eprintln!("Warning: Cannot search {error}");
+    }
+    let outcome = Outcome::of_search(!results.blocks.is_empty(), !results.errors.is_empty());
+
+    if quiet {
+        // Only the exit status is wanted
+    } else if files_with_matches {
+        let files = matching_files(&results.blocks);
+        if json {
+            let output = GrepFilesOutput {
+                files: files.into_iter().map(str::to_string).collect(),
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        } else {
+            for file in files {
+                println!("{file}");
+            }
+        }
+    } else if json {
+        let output = GrepOutput {
+            results: results
+                .blocks
+                .into_iter()
+                .map(|block| GrepResultJSON {
+                    start_line_number: block.start,
+                    end_line_number: block.end(),
+                    match_line_numbers: block.matches,
+                    content: block.lines.join("\n"),
+                    filename: block.file,
+                })
+                .collect(),
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        print_blocks(&results.blocks);
+    }
+    Ok(outcome)
+}
@@ -0,0 +1,176 @@
+use anyhow::{Context, Result};
+use std::collections::BTreeMap;
+
+use crate::SemtoolsConfig;
+use crate::cmds::exit::usage_error;
+use crate::import::confluence::ConfluenceClient;
+use crate::import::mail::MailAccount;
+use crate::import::notion::{NotionClient, page_id};
+use crate::import::{Connector, ImportSummary, import_pages};
+use crate::json_mode::ImportOutput;
+use crate::parse::LlamaParseBackend;
+use crate::parse::cache::CacheManager;
+use crate::parse::local::{ParseBackendKind, parse_backend_chain};
+use crate::search::explain::Explain;
+use crate::search::model::{ModelSource, resolve_embed_preset};
+use crate::search::{check_embedding_dimensions, update_workspace_index};
+use crate::workspace::Workspace;
+
+/// Where `import` reads pages from
+#[derive(Debug, Clone)]
+pub enum ImportSource {
+    /// Pages shared with the Notion integration, or only those under `pages` (IDs or URLs)
+    Notion { pages: Vec<String> },
+    /// The pages of a Confluence space
+    Confluence { space: String },
+    /// The messages of IMAP mailboxes
+    Mail { mailboxes: Vec<String> },
+}
+
+/// What an import fetches from
+enum Importer {
+    Pages(Connector, Vec<String>),
+    Mail(MailAccount, Vec<String>),
+}
+
+/// Import pages (or messages) into their managed directory and index the files into the
+/// workspace, tagged `import=<source>` (and `space=<key>` for Confluence). Pages that were
+/// moved or deleted at the source leave the workspace with their files. Messages are indexed
+/// as parsed by the email backend, with their attachments.
+pub async fn import_cmd(
+    source: ImportSource,
+    config: Option<String>,
+    embed_preset: Option<String>,
+    offline: bool,
+    json: bool,
+    workspace_name: Option<&str>,
+) -> Result<()> {
+    // The workspace is checked before anything is downloaded
+    let _name = Workspace::active(workspace_name).context("No active workspace")?;
+    let ws = Workspace::open(workspace_name)?;
+    ws.ensure_writable("import pages")?;
+
+    let config_path = config.unwrap_or_else(SemtoolsConfig::default_config_path);
+    let import_config = SemtoolsConfig::from_config_file(&config_path)?
+        .import
+        .unwrap_or_default();
+
+    let mut tags = BTreeMap::new();
+    let importer = match source {
+        ImportSource::Notion { pages } => {
+            let roots = pages
+                .iter()
+                .map(|page| {
+                    page_id(page).unwrap_or_else(|| {
+                        usage_error(format!("'{page}' is not a Notion page ID or URL"))
+                    })
+                })
+                .collect();
+            let client = NotionClient::new(&import_config.notion.unwrap_or_default())?;
+            Importer::Pages(Connector::Notion(client), roots)
+        }
+        ImportSource::Confluence { space } => {
+            let client =
+                ConfluenceClient::new(&import_config.confluence.unwrap_or_default(), &space)?;
+            tags.insert("space".to_string(), space);
+            Importer::Pages(Connector::Confluence(client), Vec::new())
+        }
+        ImportSource::Mail { mailboxes } => {
+            let account = MailAccount::new(&import_config.mail.unwrap_or_default())?;
+            Importer::Mail(account, mailboxes)
+        }
+    };
+
+    let embed_preset = resolve_embed_preset(embed_preset.as_deref(), workspace_name)?;
+    let model = ModelSource::from_config_file(None, embed_preset)?.load(offline)?;
+    check_embedding_dimensions(&ws, model.encode_single("").len())?;
+
+    // Documents are the imported files, or for mail their parse results
+    let (name, noun, dir, summary, files, removed) = match importer {
+        Importer::Pages(connector, roots) => {
+            let dir = connector.dir()?;
+            let summary = import_pages(&connector, &dir, &roots).await?;
+            let (files, removed) = (summary.files.clone(), summary.removed.clone());
+            (connector.name(), "pages", dir, summary, files, removed)
+        }
+        Importer::Mail(account, mailboxes) => {
+            let dir = account.dir()?;
+            let summary = account.import(&dir, &mailboxes).await?;
+            let (files, removed) = parse_messages(&config_path, &summary).await?;
+            ("mail", "messages", dir, summary, files, removed)
+        }
+    };
+    tags.insert("import".to_string(), name.to_string());
+
+    // Every embedding space indexes its own copy of the documents
+    if !removed.is_empty() {
+        for preset in ws.spaces() {
+            ws.open_space(preset)?.delete_documents(&removed)?;
+        }
+    }
+    let store = ws.open_store()?;
+    let documents_updated = update_workspace_index(
+        &store,
+        &files,
+        &model,
+        false,
+        ws.config.store_text,
+        &mut Explain::default(),
+    )?;
+    store.tag_documents(&files, &tags)?;
+    drop(store);
+
+    if json {
+        let output = ImportOutput {
+            workspace: ws.config.name.clone(),
+            source: name.to_string(),
+            directory: dir.to_string_lossy().to_string(),
+            pages: summary.files.len(),
+            pages_updated: summary.pages_updated,
+            files_removed: summary.removed.len(),
+            documents_updated,
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        println!(
+            "Imported {} {noun} from {name} into {} ({} new or changed, {} removed).",
+            summary.files.len(),
+            dir.display(),
+            summary.pages_updated,
+            summary.removed.len()
+        );
+        println!(
+            "Synced them into workspace '{}' ({} new or changed).",
+            ws.config.name, documents_updated
+        );
+    }
+
+    Workspace::run_scheduled_maintenance(workspace_name)?;
+    Ok(())
+}
+
+/// Parse the imported messages with the email backend (their attachments through the
+/// configured backends). Returns the parsed files, and the parse results of the deleted
+/// messages.
+async fn parse_messages(
+    config_path: &str,
+    summary: &ImportSummary,
+) -> Result<(Vec<String>, Vec<String>)> {
+    let cache = CacheManager::new(CacheManager::default_cache_dir()?);
+    let removed = cache.parsed_paths_of(&summary.removed)?;
+
+    let parse_config = SemtoolsConfig::from_config_file(config_path)?
+        .parse
+        .unwrap_or_default();
+    let mut chain = parse_backend_chain(&parse_config.backends)?;
+    if !chain.contains(&ParseBackendKind::Email) {
+        chain.insert(0, ParseBackendKind::Email);
+    }
+    let backend = LlamaParseBackend::new(parse_config, false)?.with_chain(chain);
+    let parsed = backend.parse_files(summary.files.clone()).await?;
+    let files = parsed
+        .into_iter()
+        .map(|result| result.output_path)
+        .collect();
+    Ok((files, removed))
+}
@@ -1,11 +1,45 @@
 #[cfg(feature = "ask")]
 pub mod ask;
 
+#[cfg(feature = "search")]
+pub mod bench;
+
+pub mod config;
+
+#[cfg(feature = "search")]
+pub mod dedupe;
+
+pub mod exit;
+
+#[cfg(feature = "ask")]
+pub mod extract;
+
+#[cfg(feature = "ripgrep")]
+pub mod grep;
+
+#[cfg(feature = "import")]
+pub mod import;
+
+#[cfg(feature = "search")]
+pub mod model;
+
 #[cfg(feature = "parse")]
 pub mod parse;
 
+#[cfg(feature = "search")]
+pub mod pick;
+
+#[cfg(feature = "search")]
+pub mod saved;
+
 #[cfg(feature = "search")]
 pub mod search;
 
-#[cfg(feature = "workspace")]
+#[cfg(feature = "search")]
+pub mod similar;
+
+#[cfg(feature = "ask")]
+pub mod summarize;
+
+#[cfg(feature = "workspace-lite")]
 pub mod workspace;
@@ -0,0 +1,128 @@
+use anyhow::Result;
+
+use crate::json_mode::{ErrorOutput, ModelPathOutput, ModelStatusOutput};
+use crate::search::model::{EmbedPreset, ModelSource, model_size};
+
+fn model_source(config: Option<String>, embed_preset: Option<String>) -> Result<ModelSource> {
+    let embed_preset = embed_preset
+        .as_deref()
+        .map(str::parse::<EmbedPreset>)
+        .transpose()?;
+    ModelSource::from_config_file(config.as_deref(), embed_preset)
+}
+
+fn status_output(source: &ModelSource) -> ModelStatusOutput {
+    let path = source.local_dir();
+    ModelStatusOutput {
+        name: source.name.clone(),
+        source: if source.is_local() {
+            "local".to_string()
+        } else {
+            "huggingface".to_string()
+        },
+        cache_dir: source.cache_dir.to_string_lossy().to_string(),
+        available: path.is_some(),
+        size_bytes: path.as_deref().map(model_size).unwrap_or(0),
+        path: path.map(|p| p.to_string_lossy().to_string()),
+    }
+}
+
+fn print_status(output: &ModelStatusOutput) {
+    println!("Model: {}", output.name);
+    if output.source == "local" {
+        println!("Source: local directory");
+    } else {
+        println!("Source: Hugging Face");
+        println!("Cache dir: {}", output.cache_dir);
+    }
+    match &output.path {
+        Some(path) => {
+            println!("Available: Yes");
+            println!("Path: {path}");
+            println!(
+                "Size: {:.1} MB",
+                output.size_bytes as f64 / (1024.0 * 1024.0)
+            );
+        }
+        None => println!("Available: No (run `semtools model download`)"),
+    }
+}
+
+pub async fn model_download_cmd(
+    config: Option<String>,
+    embed_preset: Option<String>,
+    offline: bool,
+    json: bool,
+) -> Result<()> {
+    let source = model_source(config, embed_preset)?;
+    if offline && source.local_dir().is_none() {
+        anyhow::bail!(
+            "Cannot download embedding model '{}' with --offline",
+            source.name
+        );
+    }
+
+    source.download(!json)?;
+    let output = status_output(&source);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        print_status(&output);
+    }
+    Ok(())
+}
+
+pub async fn model_status_cmd(
+    config: Option<String>,
+    embed_preset: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let source = model_source(config, embed_preset)?;
+    let output = status_output(&source);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        print_status(&output);
+    }
+    Ok(())
+}
+
+pub async fn model_path_cmd(
+    config: Option<String>,
+    embed_preset: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let source = model_source(config, embed_preset)?;
+
+    match source.local_dir() {
+        Some(path) => {
+            let path = path.to_string_lossy().to_string();
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&ModelPathOutput { path })?
+                );
+            } else {
+                println!("{path}");
+            }
+        }
+        None => {
+            let message = format!(
+                "Embedding model '{}' is not available locally. Run `semtools model download`.",
+                source.name
+            );
+            if json {
+                let error_output = ErrorOutput {
+                    error: message,
+                    error_type: "NotCached".to_string(),
+                };
+                eprintln!("{}", serde_json::to_string_pretty(&error_output)?);
+            } else {
+                eprintln!("{message}");
+            }
+            std::process::exit(1);
+        }
+    }
+
+    Ok(())
+}
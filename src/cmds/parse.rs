@@ -1,14 +1,72 @@
 use anyhow::Result;
+use std::io::{self, Write};
 use std::path::Path;
 
+use crate::cmds::exit::{EXIT_NO_MATCHES, Outcome, usage_error};
+use crate::fetch::resolve_inputs;
+use crate::json_mode::{
+    ErrorOutput, ParseCacheClearOutput, ParseCacheEntryJSON, ParseCacheGcOutput,
+    ParseCacheListOutput, ParseJobJSON, ParseJobsCancelOutput, ParseJobsListOutput, ParseOutput,
+    ParseResultJSON,
+};
+use crate::parse::backend::ParsedFile;
+use crate::parse::cache::CacheManager;
+use crate::parse::gdrive::download_drive_inputs;
+use crate::parse::jobs::JobLedger;
+use crate::parse::local::parse_backend_chain;
+use crate::parse::pages::PageSelection;
 use crate::{LlamaParseBackend, SemtoolsConfig};
 
+/// Header printed before each document by `--stdout` when parsing multiple files
+const DEFAULT_STDOUT_SEPARATOR: &str = "==> {file} <==";
+
+/// Write the parsed content of each file to `out`, in the order the files were given.
+/// With more than one file, each document is preceded by `separator` (`{file}` is replaced
+/// with the input path).
+fn write_parsed_documents<W: Write>(
+    out: &mut W,
+    files: &[String],
+    results: &[ParsedFile],
+    separator: &str,
+) -> Result<()> {
+    let mut ordered: Vec<&ParsedFile> = results.iter().collect();
+    ordered.sort_by_key(|r| {
+        files
+            .iter()
+            .position(|f| f == &r.input_path)
+            .unwrap_or(usize::MAX)
+    });
+
+    let with_headers = ordered.len() > 1;
+    for (i, result) in ordered.iter().enumerate() {
+        let content = std::fs::read_to_string(&result.output_path)?;
+        if with_headers {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            writeln!(out, "{}", separator.replace("{file}", &result.input_path))?;
+        }
+        out.write_all(content.as_bytes())?;
+        if !content.ends_with('\n') {
+            writeln!(out)?;
+        }
+    }
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
 pub async fn parse_cmd(
     config: Option<String>,
-    backend: String,
+    backend: Option<String>,
+    pages: Option<String>,
+    save_assets: bool,
     files: Vec<String>,
     verbose: bool,
-) -> Result<()> {
+    stdout: bool,
+    separator: Option<String>,
+    json: bool,
+    quiet: bool,
+) -> Result<Outcome> {
     // Get config file path
     let config_path = config.unwrap_or_else(SemtoolsConfig::default_config_path);
 
@@ -16,31 +74,281 @@ pub async fn parse_cmd(
     let semtools_config = SemtoolsConfig::from_config_file(&config_path)?;
     let parse_config = semtools_config.parse.unwrap_or_default();
 
+    // Download Google Drive files and other URL inputs and expand archives; the parsed results
+    // are reported under the URL or the `archive!member` path
+    let inputs = files;
+    let (local_inputs, drive_sources, drive_failed) =
+        download_drive_inputs(inputs.clone(), parse_config.gdrive.as_ref()).await?;
+    let mut resolved = resolve_inputs(local_inputs, false).await?;
+    resolved.sources.extend(drive_sources);
+    let files = resolved.files;
+
     // Validate that files exist
+    let mut missing = resolved.failed + drive_failed > 0;
     for file in &files {
         if !Path::new(file).exists() {
             eprintln!("Warning: File does not exist: {file}");
+            missing = true;
         }
     }
 
+    // Resolve backend chain with priority: CLI arg > config file > llama-parse
+    let chain = match backend {
+        Some(b) => parse_backend_chain(&[b]),
+        None => parse_backend_chain(&parse_config.backends),
+    };
+    let chain = chain.unwrap_or_else(|e| usage_error(e));
+
+    let pages = pages
+        .as_deref()
+        .map(str::parse::<PageSelection>)
+        .transpose()
+        .unwrap_or_else(|e| usage_error(e));
+
     // Create backend and process files
-    match backend.as_str() {
-        "llama-parse" => {
-            let backend = LlamaParseBackend::new(parse_config, verbose)?;
-            let results = backend.parse(files).await?;
-
-            // Output the paths to parsed files, one per line
-            for result_path in results {
-                println!("{result_path}");
+    let backend = LlamaParseBackend::new(parse_config, verbose)?
+        .with_chain(chain)
+        .with_pages(pages)
+        .with_save_assets(save_assets);
+    let mut results = backend.parse_files(files.clone()).await?;
+    for result in &mut results {
+        if let Some(url) = resolved.sources.get(&result.input_path) {
+            result.input_path = url.clone();
+        }
+    }
+    // Files that failed to parse are reported as they happen and left out of the results
+    let outcome = if missing || results.len() < files.len() {
+        Outcome::PartialFailure
+    } else {
+        Outcome::Success
+    };
+
+    if quiet {
+        // Only the exit status is wanted
+    } else if stdout {
+        // Print the parsed content itself; the cache is still populated by parse_files
+        let separator = separator.as_deref().unwrap_or(DEFAULT_STDOUT_SEPARATOR);
+        let mut out = io::stdout().lock();
+        match write_parsed_documents(&mut out, &inputs, &results, separator) {
+            // A closed pipe (e.g. `| head`) is not an error
+            Err(e)
+                if e.downcast_ref::<io::Error>()
+                    .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe) => {}
+            other => other?,
+        }
+    } else if json {
+        let output = ParseOutput {
+            results: results
+                .into_iter()
+                .map(|r| ParseResultJSON {
+                    input_path: r.input_path,
+                    output_path: r.output_path,
+                    was_cached: r.was_cached,
+                    backend: r.backend,
+                    assets: r.assets,
+                })
+                .collect(),
+        };
+        let json_output = serde_json::to_string_pretty(&output)?;
+        println!("{}", json_output);
+    } else {
+        // Output the paths to parsed files, one per line
+        for result in results {
+            println!("{}", result.output_path);
+        }
+    }
+
+    Ok(outcome)
+}
+
+/// List every cached parse result with the original file it came from
+pub async fn parse_cache_list_cmd(json: bool) -> Result<()> {
+    let cache_manager = CacheManager::new(CacheManager::default_cache_dir()?);
+    let index = cache_manager.load_index()?;
+
+    if json {
+        let output = ParseCacheListOutput {
+            entries: index
+                .entries
+                .into_values()
+                .map(|e| ParseCacheEntryJSON {
+                    source_path: e.source_path,
+                    parsed_path: e.parsed_path,
+                    backend: e.backend,
+                    pages: e.pages,
+                })
+                .collect(),
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else if index.entries.is_empty() {
+        println!("Parse cache is empty");
+    } else {
+        for entry in index.entries.values() {
+            match &entry.pages {
+                Some(pages) => println!(
+                    "{} (pages {pages}) -> {}",
+                    entry.source_path, entry.parsed_path
+                ),
+                None => println!("{} -> {}", entry.source_path, entry.parsed_path),
             }
         }
-        _ => {
-            eprintln!(
-                "Error: Unknown backend '{}'. Supported backends: llama-parse",
-                backend
+    }
+
+    Ok(())
+}
+
+/// Remove all cached parse results
+pub async fn parse_cache_clear_cmd(json: bool) -> Result<()> {
+    let cache_manager = CacheManager::new(CacheManager::default_cache_dir()?);
+    let files_removed = cache_manager.clear()?;
+
+    if json {
+        let output = ParseCacheClearOutput { files_removed };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        println!("Removed {files_removed} cached files");
+    }
+
+    Ok(())
+}
+
+/// Evict cached results beyond the configured (or given) size and age limits
+pub async fn parse_cache_gc_cmd(
+    config: Option<String>,
+    max_size: Option<u64>,
+    max_age_days: Option<u64>,
+    json: bool,
+) -> Result<()> {
+    let config_path = config.unwrap_or_else(SemtoolsConfig::default_config_path);
+    let semtools_config = SemtoolsConfig::from_config_file(&config_path)?;
+    let parse_config = semtools_config.parse.unwrap_or_default();
+
+    // Resolve limits with priority: CLI arg > config file
+    let mut limits = parse_config.cache_limits();
+    if max_size.is_some() {
+        limits.max_cache_size = max_size;
+    }
+    if max_age_days.is_some() {
+        limits.max_age_days = max_age_days;
+    }
+
+    let cache_manager = CacheManager::new(CacheManager::default_cache_dir()?);
+    let report = cache_manager.gc(limits)?;
+
+    if json {
+        let output = ParseCacheGcOutput {
+            entries_removed: report.entries_removed,
+            bytes_freed: report.bytes_freed,
+            entries_remaining: report.entries_remaining,
+            bytes_remaining: report.bytes_remaining,
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        if limits.is_unbounded() {
+            println!(
+                "No cache limits configured. Set parse.max_cache_size/max_age_days or pass --max-size/--max-age-days."
             );
-            std::process::exit(1);
         }
+        println!(
+            "Removed {} entries ({} bytes). {} entries ({} bytes) remaining.",
+            report.entries_removed,
+            report.bytes_freed,
+            report.entries_remaining,
+            report.bytes_remaining
+        );
+    }
+
+    Ok(())
+}
+
+/// Print the cached parse output for a file, if a valid one exists
+pub async fn parse_cache_path_cmd(file: String, json: bool) -> Result<()> {
+    let cache_manager = CacheManager::new(CacheManager::default_cache_dir()?);
+
+    match cache_manager.get_cached_entry(&file).await {
+        Ok(entry) => {
+            if json {
+                let output = ParseCacheEntryJSON {
+                    source_path: entry.source_path,
+                    parsed_path: entry.parsed_path,
+                    backend: entry.backend,
+                    pages: None,
+                };
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            } else {
+                println!("{}", entry.parsed_path);
+            }
+        }
+        Err(_) => {
+            let message = format!("No cached parse result for: {file}");
+            if json {
+                let error_output = ErrorOutput {
+                    error: message,
+                    error_type: "NotCached".to_string(),
+                };
+                eprintln!("{}", serde_json::to_string_pretty(&error_output)?);
+            } else {
+                eprintln!("{message}");
+            }
+            std::process::exit(EXIT_NO_MATCHES.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// List LlamaParse jobs that were submitted by an interrupted run and will be resumed
+pub async fn parse_jobs_list_cmd(json: bool) -> Result<()> {
+    let ledger = JobLedger::new(&CacheManager::default_cache_dir()?);
+    let pending = ledger.load()?;
+
+    if json {
+        let output = ParseJobsListOutput {
+            jobs: pending
+                .jobs
+                .into_iter()
+                .map(|(source_path, job)| ParseJobJSON {
+                    source_path,
+                    job_id: job.job_id,
+                    submitted_at: job.submitted_at,
+                })
+                .collect(),
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else if pending.jobs.is_empty() {
+        println!("No pending parse jobs");
+    } else {
+        for (source_path, job) in &pending.jobs {
+            println!("{source_path} -> {}", job.job_id);
+        }
+    }
+
+    Ok(())
+}
+
+/// Forget pending jobs, so their files are uploaded again on the next run
+pub async fn parse_jobs_cancel_cmd(files: Vec<String>, all: bool, json: bool) -> Result<()> {
+    let ledger = JobLedger::new(&CacheManager::default_cache_dir()?);
+
+    let jobs_cancelled = if all {
+        ledger.clear()?
+    } else {
+        let mut cancelled = 0;
+        for file in &files {
+            if ledger.remove(file)? {
+                cancelled += 1;
+            } else {
+                eprintln!("No pending parse job for: {file}");
+            }
+        }
+        cancelled
+    };
+
+    if json {
+        let output = ParseJobsCancelOutput { jobs_cancelled };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        println!("Cancelled {jobs_cancelled} pending jobs");
     }
 
     Ok(())
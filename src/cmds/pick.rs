@@ -0,0 +1,241 @@
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{
+    self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode,
+    enable_raw_mode,
+};
+use crossterm::{cursor, execute, queue};
+use model2vec_rs::model::StaticModel;
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use crate::cmds::exit::{Outcome, usage_error};
+use crate::search::embed_query;
+use crate::search::model::{EmbedPreset, ModelSource};
+use crate::search::pick::{PickIndex, PickMatch};
+
+/// Lines of the screen above the matches: the prompt and the match count
+const HEADER_ROWS: u16 = 2;
+
+fn read_candidates() -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            lines.push(line);
+        }
+    }
+    Ok(lines)
+}
+
+/// Restores the terminal when the picker exits, including on errors
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<Self> {
+        enable_raw_mode()?;
+        execute!(io::stderr(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), cursor::Show, LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
+
+/// What a key press does to the picker
+enum Action {
+    Edit,
+    Move(isize),
+    Accept,
+    Abort,
+    None,
+}
+
+fn handle_key(key: KeyEvent, query: &mut String) -> Action {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    match key.code {
+        KeyCode::Enter => Action::Accept,
+        KeyCode::Esc => Action::Abort,
+        KeyCode::Char('c' | 'g') if ctrl => Action::Abort,
+        KeyCode::Up => Action::Move(-1),
+        KeyCode::Char('p' | 'k') if ctrl => Action::Move(-1),
+        KeyCode::Down => Action::Move(1),
+        KeyCode::Char('n' | 'j') if ctrl => Action::Move(1),
+        KeyCode::PageUp => Action::Move(-10),
+        KeyCode::PageDown => Action::Move(10),
+        KeyCode::Char('u') if ctrl => {
+            query.clear();
+            Action::Edit
+        }
+        KeyCode::Char('w') if ctrl => {
+            let kept = query.trim_end().rfind(' ').map_or(0, |i| i + 1);
+            query.truncate(kept);
+            Action::Edit
+        }
+        KeyCode::Backspace => {
+            query.pop();
+            Action::Edit
+        }
+        KeyCode::Char(c) if !ctrl => {
+            query.push(c);
+            Action::Edit
+        }
+        _ => Action::None,
+    }
+}
+
+/// The live picker: rerank on every key typed, and return the line chosen with Enter
+fn run_picker(
+    index: &PickIndex,
+    model: &StaticModel,
+    mut query: String,
+    lexical_weight: f64,
+) -> Result<Option<String>> {
+    let _guard = TerminalGuard::enter()?;
+    let mut stderr = io::stderr();
+    let rank = |query: &str| {
+        // An empty query keeps the input order and needs no embedding
+        let embedding = if query.trim().is_empty() {
+            Vec::new()
+        } else {
+            embed_query(model, query)
+        };
+        index.rank(query, &embedding, lexical_weight)
+    };
+    let mut matches = rank(&query);
+    let mut selected = 0_usize;
+    let mut offset = 0_usize;
+
+    loop {
+        let (width, height) = terminal::size()?;
+        let visible = height.saturating_sub(HEADER_ROWS).max(1) as usize;
+        if selected < offset {
+            offset = selected;
+        } else if selected >= offset + visible {
+            offset = selected + 1 - visible;
+        }
+        draw(
+            &mut stderr,
+            index,
+            &query,
+            &matches[offset..matches.len().min(offset + visible)],
+            selected - offset,
+            width as usize,
+        )?;
+
+        let Event::Key(key) = event::read()? else {
+            // Resizes are handled by redrawing
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match handle_key(key, &mut query) {
+            Action::Edit => {
+                matches = rank(&query);
+                selected = 0;
+                offset = 0;
+            }
+            Action::Move(step) => {
+                let last = matches.len().saturating_sub(1);
+                selected = selected.saturating_add_signed(step).min(last);
+            }
+            Action::Accept => {
+                return Ok(matches
+                    .get(selected)
+                    .map(|m| index.lines()[m.index].clone()));
+            }
+            Action::Abort => return Ok(None),
+            Action::None => {}
+        }
+    }
+}
+
+fn draw(
+    out: &mut impl Write,
+    index: &PickIndex,
+    query: &str,
+    matches: &[PickMatch],
+    selected: usize,
+    width: usize,
+) -> Result<()> {
+    queue!(
+        out,
+        cursor::MoveTo(0, 0),
+        Clear(ClearType::All),
+        Print(format!("> {query}")),
+        cursor::MoveTo(0, 1),
+        SetAttribute(Attribute::Dim),
+        Print(format!("  {} lines", index.len())),
+        SetAttribute(Attribute::Reset),
+    )?;
+    for (row, m) in matches.iter().enumerate() {
+        let line: String = index.lines()[m.index]
+            .chars()
+            .take(width.saturating_sub(2))
+            .collect();
+        queue!(out, cursor::MoveTo(0, HEADER_ROWS + row as u16))?;
+        if row == selected {
+            queue!(
+                out,
+                SetAttribute(Attribute::Bold),
+                Print(format!("> {line}")),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(format!("  {line}")))?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// `semtools pick`: rank lines read from stdin against a query, live in the terminal or once
+/// with `--filter`
+pub fn pick_cmd(
+    query: Option<String>,
+    filter: Option<String>,
+    lexical_weight: f64,
+    top_k: Option<usize>,
+    embed_preset: Option<String>,
+    offline: bool,
+) -> Result<Outcome> {
+    if !(0.0..=1.0).contains(&lexical_weight) {
+        usage_error("--lexical-weight must be between 0.0 and 1.0");
+    }
+    if io::stdin().is_terminal() {
+        usage_error("pick reads the lines to choose from on stdin, e.g. `ls | semtools pick`");
+    }
+    let lines = read_candidates()?;
+    if lines.is_empty() {
+        return Ok(Outcome::NoMatches);
+    }
+
+    let embed_preset = embed_preset
+        .as_deref()
+        .map(str::parse::<EmbedPreset>)
+        .transpose()?;
+    let model = ModelSource::from_config_file(None, embed_preset)?.load(offline)?;
+    let index = PickIndex::new(lines, &model);
+
+    if let Some(query) = filter {
+        let matches = index.rank(&query, &embed_query(&model, &query), lexical_weight);
+        let mut stdout = io::stdout().lock();
+        for m in matches.iter().take(top_k.unwrap_or(usize::MAX)) {
+            writeln!(stdout, "{}", index.lines()[m.index])?;
+        }
+        return Ok(Outcome::Success);
+    }
+
+    match run_picker(&index, &model, query.unwrap_or_default(), lexical_weight)? {
+        Some(line) => {
+            println!("{line}");
+            Ok(Outcome::Success)
+        }
+        None => Ok(Outcome::Interrupted),
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+
+use crate::SemtoolsConfig;
+use crate::config::SavedSearch;
+use crate::json_mode::{SavedSearchJSON, SavedSearchesOutput};
+use crate::search::saved::{SavedSearches, SavedSource, search_argv};
+
+/// Save a search as `name`. `args` are the files and flags that follow the query on the
+/// `search` command line, and should already have been checked to parse.
+pub fn saved_add_cmd(
+    name: &str,
+    query: String,
+    args: Vec<String>,
+    description: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let mut store = SavedSearches::load()?;
+    let replaced = store.searches.contains_key(name);
+    let search = SavedSearch {
+        query,
+        args,
+        description,
+    };
+    store.add(name, search.clone())?;
+    store.save()?;
+
+    if json {
+        let output = saved_search_json(name, &search, SavedSource::Local);
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else if replaced {
+        println!("Updated saved search '{name}'");
+    } else {
+        println!("Saved search '{name}'. Run it with `semtools saved run {name}`");
+    }
+    Ok(())
+}
+
+pub fn saved_list_cmd(json: bool) -> Result<()> {
+    let store = SavedSearches::load()?;
+    let config = SemtoolsConfig::load_default()?;
+    let searches: Vec<SavedSearchJSON> = store
+        .merged(&config)
+        .iter()
+        .map(|(name, (search, source))| saved_search_json(name, search, *source))
+        .collect();
+
+    if json {
+        let output = SavedSearchesOutput { searches };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+    if searches.is_empty() {
+        println!(
+            "No saved searches. Add one with `semtools saved add <name> \"<query>\" [files and flags...]`"
+        );
+        return Ok(());
+    }
+    for search in &searches {
+        let source = if search.source == SavedSource::Config.as_str() {
+            " (from config)"
+        } else {
+            ""
+        };
+        println!(
+            "{}{source}: search {:?} {}",
+            search.name,
+            search.query,
+            search.args.join(" ")
+        );
+        if let Some(description) = &search.description {
+            println!("  {description}");
+        }
+    }
+    Ok(())
+}
+
+pub fn saved_remove_cmd(name: &str, json: bool) -> Result<()> {
+    let mut store = SavedSearches::load()?;
+    if !store.remove(name) {
+        let config = SemtoolsConfig::load_default()?;
+        if config.saved_searches.contains_key(name) {
+            anyhow::bail!(
+                "Saved search '{name}' comes from the config file; remove it from `saved_searches` there"
+            );
+        }
+        anyhow::bail!("No saved search named '{name}'");
+    }
+    store.save()?;
+
+    if json {
+        println!("{}", serde_json::json!({ "removed": name }));
+    } else {
+        println!("Removed saved search '{name}'");
+    }
+    Ok(())
+}
+
+/// The `search` command line of saved search `name`, with `extra` files or flags appended
+pub fn saved_search_argv(name: &str, extra: &[String]) -> Result<Vec<String>> {
+    let store = SavedSearches::load()?;
+    let config = SemtoolsConfig::load_default()?;
+    Ok(search_argv(&store.find(name, &config)?, extra))
+}
+
+fn saved_search_json(name: &str, search: &SavedSearch, source: SavedSource) -> SavedSearchJSON {
+    SavedSearchJSON {
+        name: name.to_string(),
+        query: search.query.clone(),
+        args: search.args.clone(),
+        description: search.description.clone(),
+        source: source.as_str().to_string(),
+    }
+}
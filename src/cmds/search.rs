@@ -1,49 +1,517 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use model2vec_rs::model::StaticModel;
+use std::collections::HashMap;
 use std::io::{self, BufRead, IsTerminal};
+use std::time::Instant;
 
-#[cfg(feature = "workspace")]
-use crate::workspace::{Workspace, store::RankedLine};
+#[cfg(feature = "workspace-lite")]
+use crate::workspace::{Workspace, context::ResultContext, store::RankedLine};
 
-#[cfg(feature = "workspace")]
-use crate::search::search_with_workspace;
+#[cfg(feature = "workspace-lite")]
+use crate::json_mode::{KvResultJSON, KvSearchOutput};
+#[cfg(feature = "workspace-lite")]
+use crate::search::kv::rank_entries;
+#[cfg(feature = "workspace-lite")]
+use crate::search::{check_embedding_dimensions, search_with_workspace_explained};
 
-use crate::json_mode::{ErrorOutput, SearchOutput, SearchResultJSON};
+use crate::archive;
+use crate::cmds::exit::Outcome;
+use crate::config::files_or_project_files;
+use crate::fetch::resolve_inputs;
+use crate::git::{Revision, revisions_at, short_commit};
+use crate::grep::{GrepOptions, build_matcher, grep_paths};
+use crate::json_mode::{
+    FileMatchJSON, FilesOutput, RecordResultJSON, RecordsOutput, SearchOutput, SearchResultJSON,
+};
+use crate::metadata::{DocumentMetadata, MetadataFilter};
+use crate::ranking::cosine_distance;
+use crate::search::calibration::{Calibration, ScoreScale};
+use crate::search::context_window::{ContextLines, fit_context};
+use crate::search::error::SearchError;
+use crate::search::explain::{Explain, Retrieval, Stage};
+use crate::search::model::{ModelSource, resolve_embed_preset};
+use crate::search::records::{InputFormat, parse_records};
+use crate::search::router::{Route, RouteDecision, block_results, fuse, lines_block, route};
+use crate::search::stream::{search_files_streaming, should_stream};
 use crate::search::{
-    Document, MODEL_NAME, SearchConfig, SearchResult, search_documents, search_files,
+    Document, FILE_CANDIDATE_FACTOR, FileScoreMode, PathBoost, QueryCombination, SearchConfig,
+    SearchResult, TagFilter, embed_lines, embed_queries, embed_query, embed_table_rows,
+    load_documents_explained, locate_long_match_spans, locate_match_spans, rank_files,
+    search_documents_explained,
 };
 
+/// How search results are printed when not in JSON mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Matches with their surrounding context lines
+    #[default]
+    Default,
+    /// One `file:line:col:text` line per match, for editor quickfix lists
+    Vimgrep,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "default" => Ok(OutputFormat::Default),
+            "vimgrep" => Ok(OutputFormat::Vimgrep),
+            _ => Err(SearchError::InvalidArgument(format!(
+                "Invalid output format: '{s}'. Must be 'default' or 'vimgrep'"
+            ))
+            .into()),
+        }
+    }
+}
+
+fn no_input() -> anyhow::Error {
+    SearchError::NoInput(
+        "No input provided. Either specify files as arguments or pipe input to stdin.".to_string(),
+    )
+    .into()
+}
+
 fn read_from_stdin() -> Result<Vec<String>> {
     let stdin = io::stdin();
     let lines: Result<Vec<String>, _> = stdin.lock().lines().collect();
     Ok(lines?)
 }
 
+/// Split off the files that cannot be read, warning about each, so one bad path does not fail
+/// the whole search
+fn readable_files(files: Vec<String>) -> (Vec<String>, usize) {
+    let mut unreadable = 0;
+    let files = files
+        .into_iter()
+        .filter(|f| match std::fs::File::open(archive::local_path(f)) {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("Warning: Cannot read {f}: {e}");
+                unreadable += 1;
+                false
+            }
+        })
+        .collect();
+    (files, unreadable)
+}
+
 // Convert SearchResult to SearchResultJSON
-fn search_result_to_json(result: &SearchResult) -> SearchResultJSON {
+pub(crate) fn search_result_to_json(result: &SearchResult) -> SearchResultJSON {
     SearchResultJSON {
         filename: result.filename.clone(),
         start_line_number: result.start,
         end_line_number: result.end,
         match_line_number: result.match_line,
         distance: result.distance,
+        score: result.score,
+        normalized_score: None,
+        // Spans are only located for some outputs; an empty one was never located
+        match_start_col: (result.match_end_col > 0).then_some(result.match_start_col),
+        match_end_col: (result.match_end_col > 0).then_some(result.match_end_col),
         content: result.lines.join("\n"),
+        metadata: None,
+        commit: None,
     }
 }
 
-// Extracted function to format and print results
-fn print_search_results(results: &[SearchResult]) {
+// Convert a workspace RankedLine to SearchResultJSON, reading its context from disk (or the index).
+// The library API and the gRPC server keep matches whose context cannot be read this way.
+#[cfg(any(feature = "grpc", feature = "node", feature = "ffi"))]
+pub(crate) fn ranked_line_to_json(
+    ranked_line: &RankedLine,
+    n_lines: usize,
+    context: &ResultContext,
+) -> SearchResultJSON {
+    let match_line_number = ranked_line.line_number as usize;
+    let start = match_line_number.saturating_sub(n_lines);
+    let end = match_line_number + n_lines + 1;
+
+    let content = match context.lines(&ranked_line.path, match_line_number, n_lines) {
+        Some((_, lines)) => lines.join("\n"),
+        None => "[Error: Could not read file content]".to_string(),
+    };
+
+    SearchResultJSON {
+        filename: ranked_line.path.clone(),
+        start_line_number: start,
+        end_line_number: end,
+        match_line_number,
+        distance: ranked_line.distance as f64,
+        score: ranked_line.score as f64,
+        normalized_score: None,
+        match_start_col: None,
+        match_end_col: None,
+        content,
+        metadata: None,
+        commit: None,
+    }
+}
+
+/// Add the calibrated relevance next to each result's raw distance
+fn normalize_scores(results: &mut [SearchResultJSON], calibration: Option<&Calibration>) {
+    if let Some(calibration) = calibration {
+        for result in results {
+            result.normalized_score = Some(calibration.normalize(result.distance));
+        }
+    }
+}
+
+/// Score printed after a match's location: the raw distance, led by the calibrated relevance
+/// with `--score normalized`
+fn format_score<D: Into<f64> + std::fmt::Display + Copy>(
+    distance: D,
+    calibration: Option<&Calibration>,
+) -> String {
+    match calibration {
+        Some(calibration) => format!(
+            "relevance {}/100, distance {distance}",
+            calibration.normalize(distance.into())
+        ),
+        None => distance.to_string(),
+    }
+}
+
+/// Calibration for `--score normalized` searches outside a workspace, from random pairs of the
+/// searched lines
+fn calibrate_documents(documents: &[Document], scale: ScoreScale) -> Option<Calibration> {
+    if scale != ScoreScale::Normalized {
+        return None;
+    }
+    let calibration = Calibration::from_documents(documents);
+    if calibration.is_none() {
+        eprintln!("Warning: Too few lines to calibrate scores, showing raw distances only");
+    }
+    calibration
+}
+
+/// Search files held in memory, or streamed a chunk at a time when they are too large for that
+fn search_without_workspace(
+    files: &[String],
+    model: &StaticModel,
+    query_embedding: &[f32],
+    config: &SearchConfig,
+    score_scale: ScoreScale,
+    diagnostics: &mut Explain,
+) -> Result<(Vec<SearchResult>, Option<Calibration>)> {
+    if should_stream(files) {
+        let normalized = score_scale == ScoreScale::Normalized;
+        let (results, calibration) = search_files_streaming(
+            files,
+            model,
+            query_embedding,
+            config,
+            normalized,
+            diagnostics,
+        )?;
+        if normalized && calibration.is_none() {
+            eprintln!("Warning: Too few lines to calibrate scores, showing raw distances only");
+        }
+        return Ok((results, calibration));
+    }
+
+    let documents = load_documents_explained(files, model, config.ignore_case, diagnostics)?;
+    let results = search_documents_explained(&documents, query_embedding, config, diagnostics);
+    Ok((results, calibrate_documents(&documents, score_scale)))
+}
+
+/// Search JSONL or CSV inputs record by record (`--input-format`), from the files or stdin.
+/// Workspaces index lines, so records are always embedded in memory.
+#[allow(clippy::too_many_arguments)]
+async fn search_records(
+    files: Vec<String>,
+    format: InputFormat,
+    text_fields: &[String],
+    model: &StaticModel,
+    query_embedding: &[f32],
+    config: &SearchConfig,
+    score_scale: ScoreScale,
+    output: &OutputOptions<'_>,
+    diagnostics: &mut Explain,
+) -> Result<Outcome> {
+    let mut inputs = Vec::new();
+    let mut unreadable = 0;
+    if files.is_empty() {
+        if io::stdin().is_terminal() {
+            return Err(no_input());
+        }
+        let content = diagnostics.time(Stage::FileIo, || io::read_to_string(io::stdin()))?;
+        inputs.push(("<stdin>".to_string(), content));
+    } else {
+        let started = Instant::now();
+        let resolved = resolve_inputs(files, false).await?;
+        let (files, failed) = readable_files(resolved.member_paths());
+        unreadable += failed + resolved.failed;
+        for file in files {
+            let content = std::fs::read_to_string(archive::local_path(&file))?;
+            inputs.push((file, content));
+        }
+        diagnostics.add_time(Stage::FileIo, started.elapsed());
+    }
+
+    let mut documents = Vec::new();
+    let mut records_by_file = HashMap::new();
+    for (filename, content) in inputs {
+        let records = parse_records(&content, format, text_fields)
+            .with_context(|| format!("Failed to read records from {filename}"))?;
+        if records.is_empty() {
+            continue;
+        }
+        let texts: Vec<String> = records
+            .iter()
+            .map(|r| {
+                if config.ignore_case {
+                    r.text.to_lowercase()
+                } else {
+                    r.text.clone()
+                }
+            })
+            .collect();
+        let embeddings = diagnostics.time(Stage::Embedding, || embed_lines(model, &texts));
+        documents.push(Document {
+            filename: filename.clone(),
+            lines: records.iter().map(|r| r.text.clone()).collect(),
+            embeddings,
+            table_rows: Vec::new(),
+        });
+        records_by_file.insert(filename, records);
+    }
+    diagnostics.candidates("records", documents.iter().map(|d| d.lines.len()).sum());
+
+    let results = search_documents_explained(&documents, query_embedding, config, diagnostics);
+    let outcome = Outcome::of_search(!results.is_empty(), unreadable > 0);
+    let calibration = calibrate_documents(&documents, score_scale);
+    // With `--at`, records of committed copies are shown under the files they are copies of
+    let revision = |filename: &str| output.revisions.and_then(|r| r.get(filename));
+    let shown_name = |filename: &str| {
+        revision(filename).map_or_else(|| filename.to_string(), |r| r.path.clone())
+    };
+
+    if output.quiet {
+        // Only the exit status is wanted
+    } else if let Some(mode) = output.files_with_matches {
+        print_file_matches(
+            results.iter().map(|r| (shown_name(&r.filename), r.score)),
+            mode,
+            output.top_k,
+            output.max_distance,
+            output.json,
+        )?;
+    } else if output.json {
+        let results: Vec<RecordResultJSON> = results
+            .iter()
+            .map(|r| {
+                let record = &records_by_file[&r.filename][r.match_line];
+                RecordResultJSON {
+                    filename: shown_name(&r.filename),
+                    record_index: record.index,
+                    distance: r.distance,
+                    score: r.score,
+                    normalized_score: calibration.as_ref().map(|c| c.normalize(r.distance)),
+                    content: record.text.clone(),
+                    record: record.fields.clone(),
+                    commit: revision(&r.filename).map(|r| r.commit.clone()),
+                }
+            })
+            .collect();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&RecordsOutput { results })?
+        );
+    } else {
+        for r in &results {
+            let record = &records_by_file[&r.filename][r.match_line];
+            let score = format_score(r.distance, calibration.as_ref());
+            let at = revision(&r.filename)
+                .map(|r| format!(" at {}", short_commit(&r.commit)))
+                .unwrap_or_default();
+            println!(
+                "{}:{} {} ({score}){at}",
+                shown_name(&r.filename),
+                format.record_name(),
+                record.index
+            );
+            println!("{}", record.text);
+            println!();
+        }
+    }
+    Ok(outcome)
+}
+
+/// How `search` prints its results
+#[derive(Clone, Copy)]
+struct OutputOptions<'a> {
+    quiet: bool,
+    json: bool,
+    /// `--files-with-matches`, with how its files are ranked
+    files_with_matches: Option<FileScoreMode>,
+    format: OutputFormat,
+    top_k: usize,
+    max_distance: Option<f64>,
+    max_line_chars: usize,
+    /// Calibration of `--score normalized`
+    calibration: Option<&'a Calibration>,
+    /// Metadata of the documents of workspace results, shown with them
+    metadata: Option<&'a HashMap<String, DocumentMetadata>>,
+    /// With `--at`, the files searched in their committed copies, by copy
+    revisions: Option<&'a HashMap<String, Revision>>,
+}
+
+/// The query that the match spans of semantic results are located with
+struct SpanQuery<'a> {
+    query: &'a str,
+    embedding: &'a [f32],
+    model: &'a StaticModel,
+    ignore_case: bool,
+}
+
+/// Print line results as `output` asks: nothing with `--quiet`, their files with
+/// `--files-with-matches`, or the lines as JSON, vimgrep or the default listing. With `spans`,
+/// match spans are located for the outputs that show them; lexical matches come with theirs.
+fn print_results(
+    results: &mut [SearchResult],
+    spans: Option<&SpanQuery<'_>>,
+    output: &OutputOptions<'_>,
+    partial_failure: bool,
+) -> Result<Outcome> {
+    let outcome = Outcome::of_search(!results.is_empty(), partial_failure);
+    let (commits, revision_metadata) = match output.revisions {
+        Some(revisions) => attribute_revisions(results, revisions, output.metadata),
+        None => Default::default(),
+    };
+    let metadata = match output.revisions {
+        Some(_) => Some(&revision_metadata),
+        None => output.metadata,
+    };
+    if output.quiet {
+        // Only the exit status is wanted
+    } else if let Some(mode) = output.files_with_matches {
+        print_file_matches(
+            results.iter().map(|r| (r.filename.clone(), r.score)),
+            mode,
+            output.top_k,
+            output.max_distance,
+            output.json,
+        )?;
+    } else if output.json || output.format == OutputFormat::Vimgrep {
+        if let Some(spans) = spans {
+            locate_match_spans(
+                results,
+                spans.query,
+                spans.embedding,
+                spans.model,
+                spans.ignore_case,
+            );
+        }
+        if output.json {
+            let mut results: Vec<SearchResultJSON> =
+                results.iter().map(search_result_to_json).collect();
+            for result in results.iter_mut() {
+                result.metadata = metadata.and_then(|m| m.get(&result.filename).cloned());
+                result.commit = commits.get(&result.filename).cloned();
+            }
+            normalize_scores(&mut results, output.calibration);
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&SearchOutput { results })?
+            );
+        } else {
+            print_vimgrep_results(results);
+        }
+    } else {
+        if let Some(spans) = spans {
+            locate_long_match_spans(
+                results,
+                output.max_line_chars,
+                spans.query,
+                spans.embedding,
+                spans.model,
+                spans.ignore_case,
+            );
+        }
+        print_search_results(
+            results,
+            output.calibration,
+            output.max_line_chars,
+            metadata.unwrap_or(&HashMap::new()),
+            &commits,
+        );
+    }
+    Ok(outcome)
+}
+
+/// Print one `file:line:col:text` line per result, with 1-based line and byte column like
+/// `rg --vimgrep`. The column points at the span found by `locate_match_spans`.
+fn print_vimgrep_results(results: &[SearchResult]) {
+    for result in results {
+        let line = &result.lines[result.match_line - result.start];
+        println!(
+            "{}:{}:{}:{}",
+            result.filename,
+            result.match_line + 1,
+            result.match_start_col + 1,
+            line
+        );
+    }
+}
+
+/// Show results of committed copies (`search --at`) under the files they are copies of.
+/// Returns the commit of each file, and the metadata of the copies in `metadata` by file.
+fn attribute_revisions(
+    results: &mut [SearchResult],
+    revisions: &HashMap<String, Revision>,
+    metadata: Option<&HashMap<String, DocumentMetadata>>,
+) -> (HashMap<String, String>, HashMap<String, DocumentMetadata>) {
+    let mut commits = HashMap::new();
+    let mut by_file = HashMap::new();
+    for result in results {
+        let Some(revision) = revisions.get(&result.filename) else {
+            continue;
+        };
+        if let Some(meta) = metadata.and_then(|m| m.get(&result.filename)) {
+            by_file.insert(revision.path.clone(), meta.clone());
+        }
+        commits.insert(revision.path.clone(), revision.commit.clone());
+        result.filename = revision.path.clone();
+    }
+    (commits, by_file)
+}
+
+/// Print results with their context, the matched line highlighted on a terminal, each under
+/// the metadata of its document when `metadata` has some and with the commit it was searched
+/// at from `commits`. Lines longer than `max_line_chars` are cut to snippets, the matched line
+/// around its span.
+fn print_search_results(
+    results: &[SearchResult],
+    calibration: Option<&Calibration>,
+    max_line_chars: usize,
+    metadata: &HashMap<String, DocumentMetadata>,
+    commits: &HashMap<String, String>,
+) {
     let is_tty = io::stdout().is_terminal();
     for search_result in results {
         let filename = search_result.filename.to_string();
-        let distance = search_result.distance;
+        let score = format_score(search_result.distance, calibration);
         let start = search_result.start;
         let end = search_result.end;
 
-        println!("{filename}:{start}::{end} ({distance})");
+        match commits.get(&filename) {
+            Some(commit) => println!(
+                "{filename}:{start}::{end} ({score}) at {}",
+                short_commit(commit)
+            ),
+            None => println!("{filename}:{start}::{end} ({score})"),
+        }
+        if let Some(summary) = metadata.get(&filename).and_then(DocumentMetadata::summary) {
+            if is_tty {
+                println!("\x1b[2m      {summary}\x1b[0m");
+            } else {
+                println!("      {summary}");
+            }
+        }
 
         // Print each line, highlighting the actual match
-        for (i, line) in search_result.lines.iter().enumerate() {
+        for (i, line) in search_result.snippet_lines(max_line_chars).enumerate() {
             let line_number = start + i;
 
             if line_number == search_result.match_line {
@@ -62,70 +530,377 @@ fn print_search_results(results: &[SearchResult]) {
     }
 }
 
-#[cfg(feature = "workspace")]
-fn print_workspace_search_results(ranked_lines: &[RankedLine], n_lines: usize) {
-    let is_tty = io::stdout().is_terminal();
+/// Read the context of a workspace match from disk, or the index. Returns `None` if neither
+/// has the matched line.
+#[cfg(feature = "workspace-lite")]
+pub(crate) fn ranked_line_to_search_result(
+    ranked_line: &RankedLine,
+    n_lines: usize,
+    context: &ResultContext,
+) -> Option<SearchResult> {
+    let match_line = ranked_line.line_number as usize;
+    let (start, lines) = context.lines(&ranked_line.path, match_line, n_lines)?;
 
-    for ranked_line in ranked_lines {
-        let filename = &ranked_line.path;
-        let distance = ranked_line.distance;
-        // ranked_line.line_number is 0-based from database
-        let match_line_number = ranked_line.line_number as usize;
-
-        // Calculate context range (working with 0-based indices)
-        let start = match_line_number.saturating_sub(n_lines);
-        let end = match_line_number + n_lines + 1;
-
-        println!("{filename}:{start}::{end} ({distance})");
-
-        // For workspace results, we need to read the file to get context lines
-        // This is acceptable since we're only doing this for the final results
-        if let Ok(content) = std::fs::read_to_string(filename) {
-            let lines: Vec<&str> = content.lines().collect();
-            let actual_start = start;
-            let actual_end = end.min(lines.len());
-
-            for (i, line) in lines[actual_start..actual_end].iter().enumerate() {
-                let line_number = actual_start + i;
-
-                if line_number == match_line_number {
-                    if is_tty {
-                        // Highlight the matching line with yellow background and black text
-                        println!("\x1b[43m\x1b[30m{:4}: {}\x1b[0m", line_number + 1, line);
-                    } else {
-                        println!("{:4}: {}", line_number + 1, line);
-                    }
-                } else {
-                    // Regular context line
-                    println!("{:4}: {}", line_number + 1, line);
-                }
-            }
+    Some(SearchResult {
+        filename: ranked_line.path.clone(),
+        end: start + lines.len(),
+        lines,
+        start,
+        match_line,
+        distance: ranked_line.distance as f64,
+        score: ranked_line.score as f64,
+        match_start_col: 0,
+        match_end_col: 0,
+    })
+}
+
+/// Options of the lexical scan of `search --auto`, which shows as much context as a semantic
+/// search
+fn lexical_options(decision: &RouteDecision, ignore_case: bool, n_lines: usize) -> GrepOptions {
+    GrepOptions {
+        fixed_strings: decision.fixed_strings,
+        case_sensitive: !ignore_case,
+        context_lines: n_lines,
+        ..Default::default()
+    }
+}
+
+/// Lines of `files` matching the pattern of `decision`, in file and line order, and the number
+/// of files that could not be searched
+fn lexical_file_results(
+    files: &[String],
+    decision: &RouteDecision,
+    ignore_case: bool,
+    n_lines: usize,
+) -> Result<(Vec<SearchResult>, usize)> {
+    let options = lexical_options(decision, ignore_case, n_lines);
+    let matcher = build_matcher(&decision.pattern, &options)?;
+    let grep = grep_paths(files, &decision.pattern, &options)?;
+    for error in &grep.errors {
+        eprintln!("Warning: Cannot search {error}");
+    }
+    let results = grep
+        .blocks
+        .iter()
+        .flat_map(|block| block_results(block, &matcher, n_lines))
+        .collect();
+    Ok((results, grep.errors.len()))
+}
+
+/// Lines piped in on stdin matching the pattern of `decision`, in line order
+fn lexical_line_results(
+    lines: &[String],
+    decision: &RouteDecision,
+    ignore_case: bool,
+    n_lines: usize,
+) -> Result<Vec<SearchResult>> {
+    let matcher = build_matcher(
+        &decision.pattern,
+        &lexical_options(decision, ignore_case, n_lines),
+    )?;
+    let block = lines_block("<stdin>", lines, &matcher);
+    Ok(block_results(&block, &matcher, n_lines))
+}
+
+/// Rank the lexical matches of a hybrid search by how close each matching line is to the
+/// query, so they carry distances comparable with the semantic results
+fn rank_lexical(
+    results: &mut [SearchResult],
+    model: &StaticModel,
+    query_embedding: &[f32],
+    ignore_case: bool,
+) {
+    for result in results.iter_mut() {
+        let line = &result.lines[result.match_line - result.start];
+        let line = if ignore_case {
+            line.to_lowercase()
         } else {
-            // Fallback: indicate that the file couldn't be read
-            println!("    [Error: Could not read file content]");
+            line.clone()
+        };
+        result.distance =
+            cosine_distance(&embed_query(model, &line), query_embedding).unwrap_or(2.0);
+        result.score = result.distance;
+    }
+    results.sort_by(|a, b| {
+        a.distance
+            .total_cmp(&b.distance)
+            .then_with(|| a.filename.cmp(&b.filename))
+            .then_with(|| a.match_line.cmp(&b.match_line))
+    });
+}
+
+/// `search --auto` of a query routed to a lexical scan only: the model is never loaded
+#[allow(clippy::too_many_arguments)]
+async fn search_lexical(
+    decision: &RouteDecision,
+    files: Vec<String>,
+    n_lines: usize,
+    line_top_k: usize,
+    ignore_case: bool,
+    output: &OutputOptions<'_>,
+    explain: bool,
+    mut diagnostics: Explain,
+) -> Result<Outcome> {
+    diagnostics.retrieval = Retrieval::Lexical;
+    let started = Instant::now();
+    let (mut results, unreadable) = if files.is_empty() {
+        if io::stdin().is_terminal() {
+            return Err(no_input());
         }
+        let lines = read_from_stdin()?;
+        (
+            lexical_line_results(&lines, decision, ignore_case, n_lines)?,
+            0,
+        )
+    } else {
+        let resolved = resolve_inputs(files, true).await?;
+        let (results, unreadable) =
+            lexical_file_results(&resolved.member_paths(), decision, ignore_case, n_lines)?;
+        (results, unreadable + resolved.failed)
+    };
+    diagnostics.add_time(Stage::FileIo, started.elapsed());
+    diagnostics.candidates("lines matched", results.len());
+    results.truncate(line_top_k);
 
-        println!(); // Empty line between results
+    // Lexical matches have no distance to cut files off at
+    let output = OutputOptions {
+        max_distance: None,
+        ..*output
+    };
+    let outcome = print_results(&mut results, None, &output, unreadable > 0)?;
+    if explain {
+        print_explain(&diagnostics, output.json)?;
     }
+    Ok(outcome)
+}
+
+/// Print `--explain` diagnostics to stderr, so stdout keeps only the results
+fn print_explain(explain: &Explain, json: bool) -> Result<()> {
+    if json {
+        eprintln!("{}", serde_json::to_string_pretty(explain)?);
+    } else {
+        eprintln!("{}", explain.report());
+    }
+    Ok(())
+}
+
+/// Print distinct files ranked by their matching lines (`--files-with-matches`)
+fn print_file_matches<I>(
+    line_matches: I,
+    mode: FileScoreMode,
+    top_k: usize,
+    max_distance: Option<f64>,
+    json: bool,
+) -> Result<()>
+where
+    I: IntoIterator<Item = (String, f64)>,
+{
+    let mut files = rank_files(line_matches, mode);
+    if max_distance.is_none() {
+        files.truncate(top_k);
+    }
+
+    if json {
+        let output = FilesOutput {
+            files: files
+                .into_iter()
+                .map(|f| FileMatchJSON {
+                    filename: f.filename,
+                    score: f.score,
+                    matching_lines: f.matching_lines,
+                })
+                .collect(),
+        };
+        let json_output = serde_json::to_string_pretty(&output)?;
+        println!("{}", json_output);
+    } else if io::stdout().is_terminal() {
+        for f in files {
+            println!(
+                "{} ({} matching lines, score {:.4})",
+                f.filename, f.matching_lines, f.score
+            );
+        }
+    } else {
+        // Plain filenames so the output can be piped into xargs
+        for f in files {
+            println!("{}", f.filename);
+        }
+    }
+
+    Ok(())
 }
 
 #[allow(clippy::too_many_arguments)]
 pub async fn search_cmd(
     query: String,
     files: Vec<String>,
-    n_lines: usize,
+    context_lines: String,
     top_k: usize,
     max_distance: Option<f64>,
     ignore_case: bool,
+    recency_boost: f64,
+    boosts: Vec<String>,
+    mmr_lambda: Option<f64>,
+    negative_queries: Vec<String>,
+    negative_weight: f64,
+    all_of: Vec<String>,
+    any_of: Vec<String>,
+    files_with_matches: bool,
+    file_score: Option<String>,
+    output: Option<String>,
+    max_line_chars: usize,
+    score: Option<String>,
+    input_format: Option<String>,
+    text_fields: Vec<String>,
+    filters: Vec<String>,
+    at: Option<String>,
+    embed_preset: Option<String>,
+    explain: bool,
+    auto: bool,
+    offline: bool,
     json: bool,
+    quiet: bool,
     workspace_name: Option<&str>,
-) -> Result<()> {
-    let model = StaticModel::from_pretrained(
-        MODEL_NAME, // "minishlab/potion-multilingual-128M",
-        None,       // Optional: Hugging Face API token for private models
-        None, // Optional: bool to override model's default normalization. `None` uses model's config.
-        None, // Optional: subfolder if model files are not at the root of the repo/path
-    )?;
+) -> Result<Outcome> {
+    if let Some(lambda) = mmr_lambda
+        && !(0.0..=1.0).contains(&lambda)
+    {
+        return Err(SearchError::InvalidArgument(format!(
+            "--mmr must be between 0.0 and 1.0, got {lambda}"
+        ))
+        .into());
+    }
+    let files = files_or_project_files(files, None)?;
+    // Files as of a revision are searched in their committed copies (and the index holds the
+    // embeddings of those copies); results are shown under the files they are copies of
+    let revisions: Option<HashMap<String, Revision>> = match &at {
+        Some(rev) => {
+            let paths = if files.is_empty() {
+                vec![".".to_string()]
+            } else {
+                files.clone()
+            };
+            Some(
+                revisions_at(&paths, rev)?
+                    .into_iter()
+                    .map(|revision| (revision.copy.clone(), revision))
+                    .collect(),
+            )
+        }
+        None => None,
+    };
+    let files = match &revisions {
+        Some(revisions) => revisions.keys().cloned().collect(),
+        None => files,
+    };
+    let context_lines = context_lines.parse::<ContextLines>()?;
+    // Auto windows are fitted within the lines read around each match
+    let n_lines = context_lines.max_lines();
+    let file_score_mode = file_score
+        .as_deref()
+        .map(str::parse::<FileScoreMode>)
+        .transpose()?
+        .unwrap_or_default();
+    let output_format = output
+        .as_deref()
+        .map(str::parse::<OutputFormat>)
+        .transpose()?
+        .unwrap_or_default();
+    // Notebooks are JSON, whose lines mean little, so they are searched cell by cell
+    let input_format = input_format
+        .as_deref()
+        .map(str::parse::<InputFormat>)
+        .transpose()?
+        .unwrap_or_else(|| InputFormat::detect(&files));
+    let (tag_filters, metadata_filters) = filters
+        .iter()
+        .map(String::as_str)
+        .partition::<Vec<_>, _>(|f| f.starts_with("tag:"));
+    let tag_filters = tag_filters
+        .into_iter()
+        .map(str::parse::<TagFilter>)
+        .collect::<Result<Vec<_>>>()?;
+    let metadata_filters = metadata_filters
+        .into_iter()
+        .map(str::parse::<MetadataFilter>)
+        .collect::<Result<Vec<_>>>()?;
+    let boosts = boosts
+        .iter()
+        .map(|b| b.parse::<PathBoost>())
+        .collect::<Result<Vec<_>>>()?;
+    let mut score_scale = score
+        .as_deref()
+        .map(str::parse::<ScoreScale>)
+        .transpose()?
+        .unwrap_or_default();
+    if quiet {
+        // Nothing is printed, so there is nothing to calibrate
+        score_scale = ScoreScale::Raw;
+    }
+    let output_options = OutputOptions {
+        quiet,
+        json,
+        files_with_matches: files_with_matches.then_some(file_score_mode),
+        format: output_format,
+        top_k,
+        max_distance,
+        max_line_chars,
+        calibration: None,
+        metadata: None,
+        revisions: revisions.as_ref(),
+    };
+
+    // Tags and metadata live in the workspace index, so records, stdin and plain files have none
+    #[cfg(feature = "workspace-lite")]
+    let in_workspace = Workspace::active(workspace_name).is_ok();
+    #[cfg(not(feature = "workspace-lite"))]
+    let in_workspace = false;
+    if !(tag_filters.is_empty() && metadata_filters.is_empty())
+        && (!in_workspace || files.is_empty() || input_format != InputFormat::Lines)
+    {
+        anyhow::bail!(
+            "--filter only applies to line searches of files in a workspace (tags are set with \
+             `workspace sync --tag`, metadata is read when files are indexed)"
+        );
+    }
+
+    // In files-with-matches mode, retrieve extra lines so files can be ranked and counted
+    let line_top_k = if files_with_matches {
+        top_k.saturating_mul(FILE_CANDIDATE_FACTOR)
+    } else {
+        top_k
+    };
+
+    let mut diagnostics = Explain::default();
+    // Records are always searched by meaning
+    let routing = (auto && input_format == InputFormat::Lines).then(|| route(&query));
+    if let Some(decision) = &routing {
+        diagnostics.route = Some(decision.describe());
+        if decision.route == Route::Lexical {
+            // Without the model, auto windows cannot be sized; the default window is used
+            let n_lines = match context_lines {
+                ContextLines::Auto => ContextLines::default().max_lines(),
+                ContextLines::Fixed(n) => n,
+            };
+            return search_lexical(
+                decision,
+                files,
+                n_lines,
+                line_top_k,
+                ignore_case,
+                &output_options,
+                explain,
+                diagnostics,
+            )
+            .await;
+        }
+    }
+    let hybrid = routing.filter(|decision| decision.route == Route::Hybrid);
+
+    let embed_preset = resolve_embed_preset(embed_preset.as_deref(), workspace_name)?;
+    let model = diagnostics.time(Stage::ModelLoad, || {
+        ModelSource::from_config_file(None, embed_preset)?.load(offline)
+    })?;
 
     let query = if ignore_case {
         query.to_lowercase()
@@ -133,17 +908,66 @@ pub async fn search_cmd(
         query.clone()
     };
 
-    let query_embedding = model.encode_single(&query);
+    let started = Instant::now();
+    let query_embedding = embed_query(&model, &query);
+    let spans = SpanQuery {
+        query: &query,
+        embedding: &query_embedding,
+        model: &model,
+        ignore_case,
+    };
+    let (extra_queries, query_combination) = if any_of.is_empty() {
+        (all_of, QueryCombination::All)
+    } else {
+        (any_of, QueryCombination::Any)
+    };
     let config = SearchConfig {
-        n_lines,
-        top_k,
+        // A record is its own result; neighbouring records are not context
+        n_lines: if input_format == InputFormat::Lines {
+            n_lines
+        } else {
+            0
+        },
+        top_k: line_top_k,
         max_distance,
         ignore_case,
+        recency_boost,
+        mmr_lambda,
+        negative_embeddings: embed_queries(&model, &negative_queries, ignore_case),
+        negative_weight,
+        extra_query_embeddings: embed_queries(&model, &extra_queries, ignore_case),
+        query_combination,
+        tag_filters,
+        metadata_filters,
+        boosts,
     };
+    diagnostics.add_time(Stage::Embedding, started.elapsed());
+
+    if input_format != InputFormat::Lines {
+        if output_format == OutputFormat::Vimgrep {
+            anyhow::bail!("--output vimgrep needs line-based input, not --input-format");
+        }
+        let outcome = search_records(
+            files,
+            input_format,
+            &text_fields,
+            &model,
+            &query_embedding,
+            &config,
+            score_scale,
+            &output_options,
+            &mut diagnostics,
+        )
+        .await?;
+        if explain {
+            print_explain(&diagnostics, json)?;
+        }
+        return Ok(outcome);
+    }
 
     // Handle stdin input (non-workspace mode)
     if files.is_empty() && !io::stdin().is_terminal() {
-        let stdin_lines = read_from_stdin()?;
+        let stdin_lines = diagnostics.time(Stage::FileIo, read_from_stdin)?;
         if !stdin_lines.is_empty() {
             let lines_for_embedding = if ignore_case {
                 stdin_lines.iter().map(|s| s.to_lowercase()).collect()
@@ -151,126 +975,300 @@ pub async fn search_cmd(
                 stdin_lines.clone()
             };
 
-            let embeddings = model.encode_with_args(&lines_for_embedding, Some(2048), 16384);
+            let (embeddings, table_rows) = diagnostics.time(Stage::Embedding, || {
+                (
+                    embed_lines(&model, &lines_for_embedding),
+                    embed_table_rows(&model, &stdin_lines, ignore_case),
+                )
+            });
 
             let documents = vec![Document {
                 filename: "<stdin>".to_string(),
                 lines: stdin_lines,
                 embeddings,
+                table_rows,
             }];
 
-            let search_results = search_documents(&documents, &query_embedding, &config);
-
-            if json {
-                let output = SearchOutput {
-                    results: search_results.iter().map(search_result_to_json).collect(),
-                };
-                let json_output = serde_json::to_string_pretty(&output)?;
-                println!("{}", json_output);
-            } else {
-                print_search_results(&search_results);
+            let mut search_results =
+                search_documents_explained(&documents, &query_embedding, &config, &mut diagnostics);
+            if let Some(decision) = &hybrid {
+                let mut lexical =
+                    lexical_line_results(&documents[0].lines, decision, ignore_case, n_lines)?;
+                rank_lexical(&mut lexical, &model, &query_embedding, ignore_case);
+                search_results = fuse(search_results, lexical, line_top_k);
             }
+            fit_context(
+                &mut search_results,
+                context_lines,
+                &model,
+                &query_embedding,
+                ignore_case,
+            );
+            let calibration = calibrate_documents(&documents, score_scale);
+            let output = OutputOptions {
+                calibration: calibration.as_ref(),
+                ..output_options
+            };
+            let outcome = print_results(&mut search_results, Some(&spans), &output, false)?;
 
-            return Ok(());
+            if explain {
+                print_explain(&diagnostics, json)?;
+            }
+            return Ok(outcome);
         }
     }
 
     if files.is_empty() {
-        let error_msg =
-            "No input provided. Either specify files as arguments or pipe input to stdin.";
-        if json {
-            let error_output = ErrorOutput {
-                error: error_msg.to_string(),
-                error_type: "NoInput".to_string(),
-            };
-            let json_output = serde_json::to_string_pretty(&error_output)?;
-            eprintln!("{}", json_output);
-        } else {
-            eprintln!("Error: {}", error_msg);
+        return Err(no_input());
+    }
+
+    // Web pages are downloaded and stripped to text; URLs that cannot be fetched count as
+    // unreadable files. Files inside archives are searched under their `archive!member` names.
+    let started = Instant::now();
+    let resolved = resolve_inputs(files, true).await?;
+    let resolved_files = resolved.member_paths();
+    // Indexed files that are gone are still searched when the workspace keeps their text
+    #[cfg(feature = "workspace-lite")]
+    let from_index = if Workspace::active(workspace_name).is_ok() {
+        Workspace::open(workspace_name)?.files_served_from_index(&resolved_files)?
+    } else {
+        Vec::new()
+    };
+    #[cfg(not(feature = "workspace-lite"))]
+    let from_index: Vec<String> = Vec::new();
+    let (mut files, unreadable) = readable_files(
+        resolved_files
+            .into_iter()
+            .filter(|f| !from_index.contains(f))
+            .collect(),
+    );
+    // Only files on disk can be scanned for a hybrid search's pattern
+    let lexical = match &hybrid {
+        Some(decision) if !files.is_empty() => {
+            let (mut lexical, _) = lexical_file_results(&files, decision, ignore_case, n_lines)?;
+            rank_lexical(&mut lexical, &model, &query_embedding, ignore_case);
+            Some(lexical)
         }
-        std::process::exit(1);
+        _ => None,
+    };
+    files.extend(from_index);
+    diagnostics.add_time(Stage::FileIo, started.elapsed());
+    let unreadable = unreadable + resolved.failed;
+    if files.is_empty() {
+        anyhow::bail!("None of the given files could be read");
     }
 
+    let outcome;
+
     // Handle file input with optional workspace integration
-    #[cfg(feature = "workspace")]
+    #[cfg(feature = "workspace-lite")]
     {
         if Workspace::active(workspace_name).is_ok() {
             // Workspace mode: use persisted line embeddings for speed
-            let config = SearchConfig {
-                n_lines,
-                top_k,
-                max_distance,
-                ignore_case,
-            };
-            let ranked_lines =
-                search_with_workspace(&files, &query, &model, &config, workspace_name).await?;
-
-            if json {
-                // Convert workspace results to SearchResultJSON
-                let results: Vec<SearchResultJSON> = ranked_lines
+            let ranked_lines = search_with_workspace_explained(
+                &files,
+                &query,
+                &model,
+                &config,
+                workspace_name,
+                &mut diagnostics,
+            )
+            .await?;
+            let context = ResultContext::new(workspace_name);
+            context.prefetch(ranked_lines.iter().map(|line| line.path.as_str()));
+            if let Some(lexical) = lexical {
+                let semantic = ranked_lines
                     .iter()
-                    .map(|ranked_line| {
-                        let match_line_number = ranked_line.line_number as usize;
-                        let start = match_line_number.saturating_sub(n_lines);
-                        let end = match_line_number + n_lines + 1;
-
-                        // Read file content for the result
-                        let content =
-                            if let Ok(file_content) = std::fs::read_to_string(&ranked_line.path) {
-                                let lines: Vec<&str> = file_content.lines().collect();
-                                let actual_start = start;
-                                let actual_end = end.min(lines.len());
-                                lines[actual_start..actual_end].join("\n")
-                            } else {
-                                "[Error: Could not read file content]".to_string()
-                            };
-
-                        SearchResultJSON {
-                            filename: ranked_line.path.clone(),
-                            start_line_number: start,
-                            end_line_number: end,
-                            match_line_number,
-                            distance: ranked_line.distance as f64,
-                            content,
-                        }
+                    .filter_map(|ranked_line| {
+                        ranked_line_to_search_result(ranked_line, n_lines, &context)
                     })
                     .collect();
-
-                let output = SearchOutput { results };
-                let json_output = serde_json::to_string_pretty(&output)?;
-                println!("{}", json_output);
-            } else {
-                print_workspace_search_results(&ranked_lines, n_lines);
+                let mut results = fuse(semantic, lexical, line_top_k);
+                fit_context(
+                    &mut results,
+                    context_lines,
+                    &model,
+                    &query_embedding,
+                    ignore_case,
+                );
+                outcome =
+                    print_results(&mut results, Some(&spans), &output_options, unreadable > 0)?;
+                Workspace::run_scheduled_maintenance(workspace_name)?;
+                if explain {
+                    print_explain(&diagnostics, json)?;
+                }
+                return Ok(outcome);
             }
-        } else {
-            let search_results = search_files(&files, &query, &model, &config)?;
-
-            if json {
-                let output = SearchOutput {
-                    results: search_results.iter().map(search_result_to_json).collect(),
-                };
-                let json_output = serde_json::to_string_pretty(&output)?;
-                println!("{}", json_output);
+            let mut result_paths: Vec<String> =
+                ranked_lines.iter().map(|r| r.path.clone()).collect();
+            result_paths.sort();
+            result_paths.dedup();
+            let metadata = Workspace::open(workspace_name)?.document_metadata(&result_paths)?;
+            let calibration = if score_scale == ScoreScale::Normalized {
+                let calibration = Workspace::open(workspace_name)?.calibration()?;
+                if calibration.is_none() {
+                    eprintln!(
+                        "Warning: Too few lines to calibrate scores, showing raw distances only"
+                    );
+                }
+                calibration
             } else {
-                print_search_results(&search_results);
+                None
+            };
+
+            let mut search_results: Vec<SearchResult> = ranked_lines
+                .iter()
+                .filter_map(|ranked_line| {
+                    ranked_line_to_search_result(ranked_line, n_lines, &context)
+                })
+                .collect();
+            fit_context(
+                &mut search_results,
+                context_lines,
+                &model,
+                &query_embedding,
+                ignore_case,
+            );
+            let output = OutputOptions {
+                calibration: calibration.as_ref(),
+                metadata: Some(&metadata),
+                ..output_options
+            };
+            outcome = print_results(&mut search_results, Some(&spans), &output, unreadable > 0)?;
+
+            // Index maintenance runs only after results have been printed
+            Workspace::run_scheduled_maintenance(workspace_name)?;
+        } else {
+            let (mut search_results, calibration) = search_without_workspace(
+                &files,
+                &model,
+                &query_embedding,
+                &config,
+                score_scale,
+                &mut diagnostics,
+            )?;
+            if let Some(lexical) = lexical {
+                search_results = fuse(search_results, lexical, line_top_k);
             }
+            fit_context(
+                &mut search_results,
+                context_lines,
+                &model,
+                &query_embedding,
+                ignore_case,
+            );
+            let output = OutputOptions {
+                calibration: calibration.as_ref(),
+                ..output_options
+            };
+            outcome = print_results(&mut search_results, Some(&spans), &output, unreadable > 0)?;
         }
     }
 
-    #[cfg(not(feature = "workspace"))]
+    #[cfg(not(feature = "workspace-lite"))]
     {
-        let search_results = search_files(&files, &query, &model, &config)?;
+        let (mut search_results, calibration) = search_without_workspace(
+            &files,
+            &model,
+            &query_embedding,
+            &config,
+            score_scale,
+            &mut diagnostics,
+        )?;
+        if let Some(lexical) = lexical {
+            search_results = fuse(search_results, lexical, line_top_k);
+        }
+        fit_context(
+            &mut search_results,
+            context_lines,
+            &model,
+            &query_embedding,
+            ignore_case,
+        );
+        let output = OutputOptions {
+            calibration: calibration.as_ref(),
+            ..output_options
+        };
+        outcome = print_results(&mut search_results, Some(&spans), &output, unreadable > 0)?;
+    }
 
-        if json {
-            let output = SearchOutput {
-                results: search_results.iter().map(search_result_to_json).collect(),
-            };
-            let json_output = serde_json::to_string_pretty(&output)?;
-            println!("{}", json_output);
+    if explain {
+        print_explain(&diagnostics, json)?;
+    }
+    Ok(outcome)
+}
+
+/// `search --kv`: match `query` against the keys (questions) of the workspace's key-value
+/// table, printing the values (answers) of the closest pairs
+#[allow(clippy::too_many_arguments)]
+pub async fn search_kv_cmd(
+    query: String,
+    top_k: usize,
+    max_distance: Option<f64>,
+    embed_preset: Option<String>,
+    offline: bool,
+    json: bool,
+    quiet: bool,
+    workspace_name: Option<&str>,
+) -> Result<Outcome> {
+    #[cfg(feature = "workspace-lite")]
+    {
+        let ws = Workspace::open(workspace_name)
+            .context("search --kv needs an active workspace with pairs from `workspace kv`")?;
+        let embed_preset = resolve_embed_preset(embed_preset.as_deref(), workspace_name)?;
+        let model = ModelSource::from_config_file(None, embed_preset)?.load(offline)?;
+        let query_embedding = embed_query(&model, &query);
+        check_embedding_dimensions(&ws, query_embedding.len())?;
+        let entries = ws.open_store()?.kv_entries()?;
+        ws.mark_used();
+        if entries.is_empty() {
+            anyhow::bail!(
+                "Workspace '{}' has no key-value pairs. Add them with `semtools workspace kv`",
+                ws.config.name
+            );
+        }
+
+        let results = rank_entries(&entries, &query_embedding, top_k, max_distance);
+        let outcome = Outcome::of_search(!results.is_empty(), false);
+        if quiet {
+            // Only the exit status is wanted
+        } else if json {
+            let results = results
+                .into_iter()
+                .map(|(entry, distance)| KvResultJSON {
+                    source: entry.source.clone(),
+                    record_index: entry.index,
+                    distance,
+                    key: entry.key.clone(),
+                    value: entry.value.clone(),
+                })
+                .collect();
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&KvSearchOutput { results })?
+            );
         } else {
-            print_search_results(&search_results);
+            for (entry, distance) in &results {
+                println!("{}:record {} ({distance})", entry.source, entry.index);
+                println!("Q: {}", entry.key);
+                println!("A: {}", entry.value);
+                println!();
+            }
         }
+        Ok(outcome)
+    }
+    #[cfg(not(feature = "workspace-lite"))]
+    {
+        let _ = (
+            query,
+            top_k,
+            max_distance,
+            embed_preset,
+            offline,
+            json,
+            quiet,
+            workspace_name,
+        );
+        anyhow::bail!("search --kv needs the workspace feature")
     }
-
-    Ok(())
 }
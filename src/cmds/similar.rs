@@ -0,0 +1,125 @@
+use anyhow::Result;
+use std::path::Path;
+
+#[cfg(feature = "workspace-lite")]
+use crate::search::similar::workspace_document_vectors;
+#[cfg(feature = "workspace-lite")]
+use crate::workspace::Workspace;
+
+use crate::config::files_or_project_files;
+use crate::fetch::resolve_inputs;
+use crate::json_mode::{SimilarDocumentJSON, SimilarOutput};
+use crate::search::error::SearchError;
+use crate::search::model::{ModelSource, resolve_embed_preset};
+use crate::search::similar::{
+    Pooling, SimilarDocument, document_vectors, file_vector, rank_similar,
+};
+
+/// Whether `a` and `b` name the same file
+fn same_file(a: &str, b: &str) -> bool {
+    a == b
+        || matches!(
+            (Path::new(a).canonicalize(), Path::new(b).canonicalize()),
+            (Ok(a), Ok(b)) if a == b
+        )
+}
+
+fn print_similar(reference: &str, results: &[SimilarDocument]) {
+    if results.is_empty() {
+        println!("No documents similar to {reference} found.");
+        return;
+    }
+    for result in results {
+        println!("{:.4}  {}", result.distance, result.path);
+    }
+}
+
+/// `similar`: rank the documents of `corpus` by document-level distance to `reference`
+#[allow(clippy::too_many_arguments)]
+pub async fn similar_cmd(
+    reference: String,
+    corpus: Vec<String>,
+    pooling: Option<String>,
+    top_k: usize,
+    max_distance: Option<f64>,
+    embed_preset: Option<String>,
+    offline: bool,
+    json: bool,
+    workspace_name: Option<&str>,
+) -> Result<()> {
+    let pooling = pooling
+        .as_deref()
+        .map(str::parse::<Pooling>)
+        .transpose()?
+        .unwrap_or_default();
+    let corpus = files_or_project_files(corpus, None)?;
+    if corpus.is_empty() {
+        return Err(SearchError::NoInput(
+            "No corpus provided. Specify the documents to compare the reference with.".to_string(),
+        )
+        .into());
+    }
+
+    // A URL or archive member works as the reference too
+    let Some(reference_path) = resolve_inputs(vec![reference.clone()], true)
+        .await?
+        .member_paths()
+        .into_iter()
+        .next()
+    else {
+        anyhow::bail!("Could not read the reference document {reference}");
+    };
+    let corpus: Vec<String> = resolve_inputs(corpus, true)
+        .await?
+        .member_paths()
+        .into_iter()
+        .filter(|path| !same_file(path, &reference_path))
+        .collect();
+
+    let embed_preset = resolve_embed_preset(embed_preset.as_deref(), workspace_name)?;
+    let model = ModelSource::from_config_file(None, embed_preset)?.load(offline)?;
+
+    let Some(reference_vector) = file_vector(&reference_path, &model, pooling)? else {
+        anyhow::bail!("The reference document {reference} has no text to compare");
+    };
+
+    #[cfg(feature = "workspace-lite")]
+    let documents = if Workspace::active(workspace_name).is_ok() {
+        workspace_document_vectors(
+            &corpus,
+            &model,
+            pooling,
+            reference_vector.len(),
+            workspace_name,
+        )?
+    } else {
+        document_vectors(&corpus, &model, pooling)?
+    };
+    #[cfg(not(feature = "workspace-lite"))]
+    let documents = document_vectors(&corpus, &model, pooling)?;
+
+    let mut results = rank_similar(&reference_vector, documents);
+    if let Some(max_distance) = max_distance {
+        results.retain(|result| result.distance <= max_distance);
+    }
+    results.truncate(top_k);
+
+    if json {
+        let output = SimilarOutput {
+            reference,
+            pooling: pooling.as_str().to_string(),
+            results: results
+                .into_iter()
+                .map(|result| SimilarDocumentJSON {
+                    path: result.path,
+                    distance: result.distance,
+                })
+                .collect(),
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        print_similar(&reference, &results);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use std::io::{self, IsTerminal, Read};
+
+use crate::ask::sampling::Sampling;
+use crate::ask::summarize::{Summarizer, SummaryLength, chunk_document};
+use crate::cmds::ask::{AskSettings, resolve_ask_settings};
+use crate::json_mode::SummarizeOutput;
+
+#[allow(clippy::too_many_arguments)]
+pub async fn summarize_cmd(
+    files: Vec<String>,
+    length: Option<String>,
+    config: Option<String>,
+    api_key: Option<String>,
+    base_url: Option<String>,
+    model: Option<String>,
+    api_mode: Option<String>,
+    json: bool,
+) -> Result<()> {
+    let length = length
+        .as_deref()
+        .map(str::parse::<SummaryLength>)
+        .transpose()?
+        .unwrap_or_default();
+
+    let AskSettings {
+        client,
+        model_name,
+        api_mode,
+        ..
+    } = resolve_ask_settings(
+        config,
+        api_key,
+        base_url,
+        model,
+        api_mode,
+        Sampling::default(),
+    )?;
+
+    // Summarize stdin when no files are given
+    let documents: Vec<(String, String)> = if files.is_empty() {
+        if io::stdin().is_terminal() {
+            anyhow::bail!(
+                "No input provided. Either specify files as arguments or pipe input to stdin."
+            );
+        }
+        let mut content = String::new();
+        io::stdin().read_to_string(&mut content)?;
+        vec![("<stdin>".to_string(), content)]
+    } else {
+        files
+            .into_iter()
+            .map(|file| {
+                let content = std::fs::read_to_string(&file)
+                    .map_err(|e| anyhow::anyhow!("Failed to read '{file}': {e}"))?;
+                Ok((file, content))
+            })
+            .collect::<Result<_>>()?
+    };
+
+    let chunks: Vec<_> = documents
+        .iter()
+        .flat_map(|(file, content)| chunk_document(file, content))
+        .collect();
+
+    let summarizer = Summarizer::new(&client, &model_name, api_mode, length);
+    let summary = summarizer.summarize(&chunks).await?;
+
+    if json {
+        let output = SummarizeOutput {
+            files: documents.into_iter().map(|(file, _)| file).collect(),
+            length: length.to_string(),
+            chunks: chunks.len(),
+            summary,
+        };
+        println!("{}", serde_json::to_string_pretty(&output)?);
+    } else {
+        println!("{summary}");
+    }
+
+    Ok(())
+}
@@ -1,21 +1,103 @@
 use anyhow::{Context, Result};
 
-#[cfg(feature = "workspace")]
-use crate::workspace::{Workspace, WorkspaceConfig, store::Store};
+#[cfg(feature = "workspace-lite")]
+use crate::fetch::resolve_inputs;
+#[cfg(feature = "workspace-lite")]
+use crate::git::{COMMIT_TAG, head_revisions};
+#[cfg(feature = "workspace-lite")]
+use crate::s3::{is_s3_url, mirror_prefix};
+#[cfg(feature = "workspace-lite")]
+use crate::search::explain::Explain;
+#[cfg(feature = "workspace-lite")]
+use crate::search::kv::{embed_pairs, kv_format, merge_entries, parse_pairs};
+#[cfg(feature = "workspace-lite")]
+use crate::search::model::{EmbedPreset, ModelSource, resolve_embed_preset};
+#[cfg(feature = "workspace-lite")]
+use crate::search::records::InputFormat;
+#[cfg(feature = "workspace-lite")]
+use crate::search::{check_embedding_dimensions, parse_tag, update_workspace_index};
+#[cfg(feature = "workspace-lite")]
+use crate::workspace::{
+    Workspace, WorkspaceConfig, encryption, fsck, snapshot, store::STORE_ITEMS,
+};
 
-use crate::json_mode::{PruneOutput, WorkspaceOutput};
+use crate::json_mode::{
+    FsckOutput, KvSyncOutput, OptimizeOutput, PruneOutput, SnapshotJSON, SnapshotListOutput,
+    SyncOutput, WorkspaceDeleteOutput, WorkspaceListEntryJSON, WorkspaceListOutput,
+    WorkspaceOutput,
+};
 
-#[cfg(not(feature = "workspace"))]
+#[cfg(not(feature = "workspace-lite"))]
 use crate::json_mode::ErrorOutput;
 
-pub async fn workspace_use_cmd(name: String, json: bool) -> Result<()> {
-    #[cfg(feature = "workspace")]
+pub async fn workspace_use_cmd(
+    name: String,
+    encrypted: bool,
+    store_text: bool,
+    embed_preset: Option<String>,
+    json: bool,
+) -> Result<()> {
+    #[cfg(feature = "workspace-lite")]
     {
+        let existing = if Workspace::exists(&name)? {
+            Some(Workspace::open(Some(&name))?)
+        } else {
+            None
+        };
+        // An existing workspace keeps its root, which may be elsewhere or in an object store
+        let (root_dir, remote_root) = match &existing {
+            Some(existing) => (
+                existing.config.root_dir.clone(),
+                existing.config.remote_root.clone(),
+            ),
+            None => (Workspace::root_path(&name)?, None),
+        };
+
+        // An existing workspace stays bound to the preset it was created with
+        let requested = embed_preset
+            .as_deref()
+            .map(str::parse::<EmbedPreset>)
+            .transpose()?;
+        let embed_preset = match &existing {
+            Some(existing) => existing.config.embed_preset,
+            None => requested.unwrap_or_default(),
+        };
+        if let Some(requested) = requested
+            && requested != embed_preset
+        {
+            eprintln!(
+                "Workspace '{name}' was created with the '{embed_preset}' preset. '{requested}' \
+                 embeddings are kept in a space of their own: pass --embed-preset {requested} to \
+                 search or sync them."
+            );
+        }
+        // Like encryption, keeping line text is never turned off by re-running `use`
+        let store_text = store_text || existing.as_ref().is_some_and(|ws| ws.config.store_text);
+
+        // Seal any existing index before the config says the workspace is encrypted.
+        // Encryption is never turned off implicitly by re-running `use` without the flag.
+        if encrypted {
+            encryption::enable_encryption(&root_dir, &STORE_ITEMS)?;
+            if let Some(existing) = &existing {
+                for preset in existing.spaces() {
+                    if preset != embed_preset {
+                        encryption::enable_encryption(&existing.space_dir(preset), &STORE_ITEMS)?;
+                    }
+                }
+            }
+        }
+        let encrypted = encryption::is_encrypted(std::path::Path::new(&root_dir));
+
         // Initialize new workspace configuration
         let ws = Workspace {
             config: WorkspaceConfig {
                 name: name.clone(),
-                root_dir: Workspace::root_path(&name)?,
+                root_dir,
+                encrypted,
+                embed_preset,
+                embedding_dimensions: embed_preset.dimensions(),
+                store_text,
+                remote_root,
                 ..Default::default()
             },
         };
@@ -23,7 +105,7 @@ pub async fn workspace_use_cmd(name: String, json: bool) -> Result<()> {
 
         if json {
             // Try to get document count from store, or use 0 for new workspace
-            let total_documents = if let Ok(store) = Store::open(&ws.config.root_dir) {
+            let total_documents = if let Ok(store) = ws.open_store() {
                 if let Ok(stats) = store.get_stats() {
                     stats.total_documents
                 } else {
@@ -35,22 +117,39 @@ pub async fn workspace_use_cmd(name: String, json: bool) -> Result<()> {
 
             let output = WorkspaceOutput {
                 name: ws.config.name.clone(),
-                root_dir: ws.config.root_dir.clone(),
+                root_dir: ws.root_uri().to_string(),
                 total_documents,
+                embed_preset: ws.config.embed_preset.to_string(),
             };
             let json_output = serde_json::to_string_pretty(&output)?;
             println!("{}", json_output);
         } else {
             println!("Workspace '{name}' configured.");
+            println!(
+                "Embedding preset: {embed_preset} ({}, {} dimensions)",
+                embed_preset.model_name(),
+                ws.config.embedding_dimensions
+            );
+            if encrypted {
+                println!(
+                    "The index is encrypted at rest. Keep {} set to its passphrase.",
+                    encryption::KEY_ENV_VAR
+                );
+            }
+            if store_text {
+                println!(
+                    "Line text is kept in the index, so results survive moved or deleted files."
+                );
+            }
             println!("To activate it, run:");
             println!("  export SEMTOOLS_WORKSPACE={name}");
             println!();
             println!("Or add this to your shell profile (.bashrc, .zshrc, etc.)");
             println!();
-            println!("Or use the `--workspace` option on the commands that support it");
+            println!("Or pass `--workspace {name}` to semtools, which takes precedence");
         }
     }
-    #[cfg(not(feature = "workspace"))]
+    #[cfg(not(feature = "workspace-lite"))]
     {
         if json {
             let error_output = ErrorOutput {
@@ -67,27 +166,42 @@ pub async fn workspace_use_cmd(name: String, json: bool) -> Result<()> {
 }
 
 pub async fn workspace_status_cmd(json: bool, workspace_name: Option<&str>) -> Result<()> {
-    #[cfg(feature = "workspace")]
+    #[cfg(feature = "workspace-lite")]
     {
         let _name = Workspace::active(workspace_name).context("No active workspace")?;
         let ws = Workspace::open(workspace_name)?;
 
         // Open store and get stats
-        let store = Store::open(&ws.config.root_dir)?;
+        let store = ws.open_store()?;
         let stats = store.get_stats()?;
 
         if json {
             let output = WorkspaceOutput {
                 name: ws.config.name.clone(),
-                root_dir: ws.config.root_dir.clone(),
+                root_dir: ws.root_uri().to_string(),
                 total_documents: stats.total_documents,
+                embed_preset: ws.config.embed_preset.to_string(),
             };
             let json_output = serde_json::to_string_pretty(&output)?;
             println!("{}", json_output);
         } else {
             println!("Active workspace: {}", ws.config.name);
-            println!("Root: {}", ws.config.root_dir);
+            println!("Root: {}", ws.root_uri());
             println!("Documents: {}", stats.total_documents);
+            println!(
+                "Embedding preset: {} ({}, {} dimensions)",
+                ws.config.embed_preset,
+                ws.config.embed_preset.model_name(),
+                ws.config.embedding_dimensions
+            );
+            let spaces = ws.spaces();
+            if spaces.len() > 1 {
+                let names: Vec<String> = spaces.iter().map(|p| p.to_string()).collect();
+                println!("Embedding spaces: {}", names.join(", "));
+            }
+            if encryption::is_encrypted(std::path::Path::new(&ws.config.root_dir)) {
+                println!("Encrypted: Yes");
+            }
             if stats.has_index {
                 let index_info = stats.index_type.unwrap_or_else(|| "Unknown".to_string());
                 println!("Index: Yes ({index_info})");
@@ -96,8 +210,152 @@ pub async fn workspace_status_cmd(json: bool, workspace_name: Option<&str>) -> R
             }
         }
     }
-    #[cfg(not(feature = "workspace"))]
+    #[cfg(not(feature = "workspace-lite"))]
+    {
+        if json {
+            let error_output = ErrorOutput {
+                error: "workspace feature not enabled".to_string(),
+                error_type: "FeatureNotEnabled".to_string(),
+            };
+            let json_output = serde_json::to_string_pretty(&error_output)?;
+            eprintln!("{}", json_output);
+        } else {
+            println!("workspace feature not enabled");
+        }
+    }
+    Ok(())
+}
+
+/// List every workspace with its size, document count and last use, marking the active one
+pub async fn workspace_list_cmd(json: bool, active: Option<&str>) -> Result<()> {
+    #[cfg(feature = "workspace-lite")]
+    {
+        let active = Workspace::active(active).ok();
+        let workspaces = Workspace::list();
+        if json {
+            let output = WorkspaceListOutput {
+                workspaces: workspaces
+                    .into_iter()
+                    .map(|ws| WorkspaceListEntryJSON {
+                        active: active.as_deref() == Some(ws.name.as_str()),
+                        name: ws.name,
+                        root_dir: ws.root_dir,
+                        documents: ws.documents,
+                        size_bytes: ws.size_bytes,
+                        last_used: ws.last_used,
+                    })
+                    .collect(),
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        } else if workspaces.is_empty() {
+            println!("No workspaces. Create one with: workspace use <name>");
+        } else {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            for ws in workspaces {
+                let marker = if active.as_deref() == Some(ws.name.as_str()) {
+                    "*"
+                } else {
+                    " "
+                };
+                let documents = ws
+                    .documents
+                    .map_or("? documents".to_string(), |n| format!("{n} documents"));
+                let last_used = ws.last_used.map_or("never used".to_string(), |t| {
+                    format!("used {}", format_age(now.saturating_sub(t)))
+                });
+                println!(
+                    "{marker} {}  ({documents}, {:.1} MB, {last_used})",
+                    ws.name,
+                    ws.size_bytes as f64 / (1024.0 * 1024.0)
+                );
+            }
+        }
+    }
+    #[cfg(not(feature = "workspace-lite"))]
+    {
+        let _ = active;
+        if json {
+            let error_output = ErrorOutput {
+                error: "workspace feature not enabled".to_string(),
+                error_type: "FeatureNotEnabled".to_string(),
+            };
+            let json_output = serde_json::to_string_pretty(&error_output)?;
+            eprintln!("{}", json_output);
+        } else {
+            println!("workspace feature not enabled");
+        }
+    }
+    Ok(())
+}
+
+/// How long ago `secs` seconds is, in the largest whole unit
+#[cfg(feature = "workspace-lite")]
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3_600 => format!("{}m ago", secs / 60),
+        3_600..86_400 => format!("{}h ago", secs / 3_600),
+        _ => format!("{}d ago", secs / 86_400),
+    }
+}
+
+/// Delete a workspace and its index. The active workspace is only deleted with `force`, or
+/// once confirmed at the terminal.
+pub async fn workspace_delete_cmd(
+    name: String,
+    force: bool,
+    json: bool,
+    active: Option<&str>,
+) -> Result<()> {
+    #[cfg(feature = "workspace-lite")]
+    {
+        use std::io::{BufRead, IsTerminal, Write};
+
+        let active = Workspace::active(active).ok();
+        if !Workspace::list_names().contains(&name) {
+            anyhow::bail!("Workspace '{name}' does not exist");
+        }
+        if active.as_deref() == Some(name.as_str()) && !force {
+            let confirmed = std::io::stdin().is_terminal() && {
+                eprint!("Workspace '{name}' is the active workspace. Delete it? [y/N] ");
+                std::io::stderr().flush()?;
+                let mut answer = String::new();
+                std::io::stdin().lock().read_line(&mut answer)?;
+                matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+            };
+            if !confirmed {
+                anyhow::bail!(
+                    "Workspace '{name}' is the active workspace; not deleted. Pass --force to \
+                     delete it anyway"
+                );
+            }
+        }
+
+        let ws = Workspace::open(Some(&name))?;
+        let index_left_at = ws.delete()?;
+        if json {
+            let output = WorkspaceDeleteOutput {
+                name,
+                index_left_at,
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        } else {
+            println!("Deleted workspace '{name}'.");
+            if let Some(root_dir) = index_left_at {
+                println!(
+                    "Its index at {root_dir} is outside the workspace directory and was kept."
+                );
+            }
+            if active.as_deref() == Some(name.as_str()) {
+                println!("It was the active workspace; run: unset SEMTOOLS_WORKSPACE");
+            }
+        }
+    }
+    #[cfg(not(feature = "workspace-lite"))]
     {
+        let _ = (name, force, active);
         if json {
             let error_output = ErrorOutput {
                 error: "workspace feature not enabled".to_string(),
@@ -113,31 +371,36 @@ pub async fn workspace_status_cmd(json: bool, workspace_name: Option<&str>) -> R
 }
 
 pub async fn workspace_prune_cmd(json: bool, workspace_name: Option<&str>) -> Result<()> {
-    #[cfg(feature = "workspace")]
+    #[cfg(feature = "workspace-lite")]
     {
         let _name = Workspace::active(workspace_name).context("No active workspace")?;
         let ws = Workspace::open(workspace_name)?;
-        let store = Store::open(&ws.config.root_dir)?;
+        ws.ensure_writable("prune stale documents")?;
 
-        // Get all document paths from the workspace
-        let all_paths = store.get_all_document_paths()?;
-        let total_before = all_paths.len();
+        // Every embedding space indexes its own copy of the documents
+        let mut missing_paths = std::collections::BTreeSet::new();
+        let mut files_remaining = 0;
+        for preset in ws.spaces() {
+            let store = ws.open_space(preset)?;
+            let all_paths = store.get_all_document_paths()?;
 
-        // Check which files no longer exist
-        let mut missing_paths = Vec::new();
-        for path in &all_paths {
-            if !std::path::Path::new(path).exists() {
-                missing_paths.push(path.clone());
+            // Check which files no longer exist
+            let missing: Vec<String> = all_paths
+                .iter()
+                .filter(|path| !crate::archive::local_path(path).exists())
+                .cloned()
+                .collect();
+            if preset == ws.config.embed_preset {
+                files_remaining = all_paths.len() - missing.len();
             }
-        }
-
-        let files_removed = missing_paths.len();
-        let files_remaining = total_before - files_removed;
 
-        if !missing_paths.is_empty() {
-            // Remove stale documents
-            store.delete_documents(&missing_paths)?;
+            if !missing.is_empty() {
+                // Remove stale documents
+                store.delete_documents(&missing)?;
+            }
+            missing_paths.extend(missing);
         }
+        let files_removed = missing_paths.len();
 
         if json {
             let output = PruneOutput {
@@ -159,8 +422,533 @@ pub async fn workspace_prune_cmd(json: bool, workspace_name: Option<&str>) -> Re
             );
         }
     }
-    #[cfg(not(feature = "workspace"))]
+    #[cfg(not(feature = "workspace-lite"))]
+    {
+        if json {
+            let error_output = ErrorOutput {
+                error: "workspace feature not enabled".to_string(),
+                error_type: "FeatureNotEnabled".to_string(),
+            };
+            let json_output = serde_json::to_string_pretty(&error_output)?;
+            eprintln!("{}", json_output);
+        } else {
+            println!("workspace feature not enabled");
+        }
+    }
+    Ok(())
+}
+
+pub async fn workspace_optimize_cmd(json: bool, workspace_name: Option<&str>) -> Result<()> {
+    #[cfg(feature = "workspace-lite")]
+    {
+        let _name = Workspace::active(workspace_name).context("No active workspace")?;
+        let ws = Workspace::open(workspace_name)?;
+        ws.ensure_writable("be optimized")?;
+
+        let mut rows_optimized = 0;
+        for preset in ws.spaces() {
+            rows_optimized += ws.open_space(preset)?.optimize()?;
+        }
+
+        if json {
+            let output = OptimizeOutput { rows_optimized };
+            let json_output = serde_json::to_string_pretty(&output)?;
+            println!("{}", json_output);
+        } else {
+            println!(
+                "Optimized workspace '{}' ({} rows written since last optimization).",
+                ws.config.name, rows_optimized
+            );
+        }
+    }
+    #[cfg(not(feature = "workspace-lite"))]
+    {
+        if json {
+            let error_output = ErrorOutput {
+                error: "workspace feature not enabled".to_string(),
+                error_type: "FeatureNotEnabled".to_string(),
+            };
+            let json_output = serde_json::to_string_pretty(&error_output)?;
+            eprintln!("{}", json_output);
+        } else {
+            println!("workspace feature not enabled");
+        }
+    }
+    Ok(())
+}
+
+/// Check the index for inconsistencies between its shards, and fix them with `repair`
+pub async fn workspace_fsck_cmd(
+    repair: bool,
+    json: bool,
+    workspace_name: Option<&str>,
+) -> Result<()> {
+    #[cfg(feature = "workspace-lite")]
+    {
+        let _name = Workspace::active(workspace_name).context("No active workspace")?;
+        let ws = Workspace::open(workspace_name)?;
+        if repair {
+            ws.ensure_writable("be repaired")?;
+        }
+        let store = ws.open_store()?;
+
+        // Checked under the write lock, so a write in progress is not mistaken for damage
+        let write_lock = if ws.is_read_only() {
+            None
+        } else {
+            Some(store.write_lock()?)
+        };
+        let report = fsck::check(&store, ws.space_dimensions(ws.space()))?;
+        if repair {
+            fsck::repair(&store, &report)?;
+        }
+        drop(write_lock);
+
+        if json {
+            let output = FsckOutput {
+                workspace: ws.config.name.clone(),
+                documents: report.documents,
+                line_embeddings: report.line_embeddings,
+                clean: report.is_clean(),
+                repaired: repair,
+                orphaned_lines: report.orphaned_lines,
+                orphaned_doc_embeddings: report.orphaned_doc_embeddings,
+                documents_without_lines: report.documents_without_lines,
+                documents_without_doc_embedding: report.documents_without_doc_embedding,
+                wrong_dimensions: report.wrong_dimensions,
+                unindexed_rows: report.unindexed_rows,
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        } else {
+            println!(
+                "Checked workspace '{}': {} documents, {} line embeddings.",
+                ws.config.name, report.documents, report.line_embeddings
+            );
+            let wrong_dimensions = format!(
+                "documents with embeddings that are not {}-dimensional",
+                ws.space_dimensions(ws.space())
+            );
+            let sections = [
+                (
+                    "paths with line embeddings but no document",
+                    &report.orphaned_lines,
+                ),
+                (
+                    "paths with a document embedding but no document",
+                    &report.orphaned_doc_embeddings,
+                ),
+                (
+                    "documents without line embeddings",
+                    &report.documents_without_lines,
+                ),
+                (
+                    "documents without a document embedding",
+                    &report.documents_without_doc_embedding,
+                ),
+                (wrong_dimensions.as_str(), &report.wrong_dimensions),
+            ];
+            for (label, paths) in sections {
+                if !paths.is_empty() {
+                    println!("Found {} {label}:", paths.len());
+                    for path in paths {
+                        println!("  - {path}");
+                    }
+                }
+            }
+            if report.unindexed_rows > 0 && !repair {
+                println!(
+                    "{} rows were written since the payload indexes were last built (`workspace \
+                     optimize` builds them).",
+                    report.unindexed_rows
+                );
+            }
+
+            if report.is_clean() {
+                println!("No problems found. Workspace is consistent.");
+            } else if repair {
+                println!(
+                    "Repaired {} paths. Dropped documents are indexed again by the next sync or \
+                     search.",
+                    report.problems()
+                );
+            } else {
+                println!("Run `semtools workspace fsck --repair` to fix them.");
+            }
+            if repair && (!report.is_clean() || report.unindexed_rows > 0) {
+                println!("Rebuilt the payload indexes.");
+            }
+        }
+    }
+    #[cfg(not(feature = "workspace-lite"))]
+    {
+        let _ = (repair, workspace_name);
+        if json {
+            let error_output = ErrorOutput {
+                error: "workspace feature not enabled".to_string(),
+                error_type: "FeatureNotEnabled".to_string(),
+            };
+            let json_output = serde_json::to_string_pretty(&error_output)?;
+            eprintln!("{}", json_output);
+        } else {
+            println!("workspace feature not enabled");
+        }
+    }
+    Ok(())
+}
+
+/// Index `files` into the workspace without searching, attaching `tags` (`key=value`) to them.
+/// With `git`, the committed copy of each file is indexed too and kept for `search --at`, and
+/// documents are tagged with the commit they hold.
+#[allow(clippy::too_many_arguments)]
+pub async fn workspace_sync_cmd(
+    files: Vec<String>,
+    tags: Vec<String>,
+    git: bool,
+    embed_preset: Option<String>,
+    offline: bool,
+    json: bool,
+    workspace_name: Option<&str>,
+) -> Result<()> {
+    #[cfg(feature = "workspace-lite")]
+    {
+        let _name = Workspace::active(workspace_name).context("No active workspace")?;
+        let tags = tags
+            .iter()
+            .map(|tag| parse_tag(tag))
+            .collect::<Result<std::collections::BTreeMap<_, _>>>()?;
+        let ws = Workspace::open(workspace_name)?;
+        ws.ensure_writable("sync files")?;
+
+        let embed_preset = resolve_embed_preset(embed_preset.as_deref(), workspace_name)?;
+        let model = ModelSource::from_config_file(None, embed_preset)?.load(offline)?;
+        check_embedding_dimensions(&ws, model.encode_single("").len())?;
+
+        // Objects under `s3://` prefixes are mirrored and parsed, and files inside archives
+        // are indexed under their `archive!member` pseudo-paths
+        let (files, stale) = resolve_s3_inputs(files).await?;
+        if !stale.is_empty() {
+            // Every embedding space indexes its own copy of the documents
+            for preset in ws.spaces() {
+                ws.open_space(preset)?.delete_documents(&stale)?;
+            }
+        }
+        let mut files: Vec<String> = resolve_inputs(files, true)
+            .await?
+            .member_paths()
+            .into_iter()
+            .filter(|f| {
+                let exists = crate::archive::local_path(f).is_file();
+                if !exists {
+                    eprintln!("Warning: Cannot read {f}, skipping");
+                }
+                exists
+            })
+            .collect();
+        let revisions = if git {
+            head_revisions(&files)?
+        } else {
+            Vec::new()
+        };
+        files.extend(revisions.iter().map(|revision| revision.copy.clone()));
+
+        let store = ws.open_store()?;
+        let documents_updated = update_workspace_index(
+            &store,
+            &files,
+            &model,
+            false,
+            ws.config.store_text,
+            &mut Explain::default(),
+        )?;
+        let documents_tagged = store.tag_documents(&files, &tags)?;
+        let mut by_commit = std::collections::BTreeMap::<&str, Vec<String>>::new();
+        for revision in &revisions {
+            let documents = by_commit.entry(&revision.commit).or_default();
+            documents.push(revision.copy.clone());
+            // A working copy with uncommitted changes does not hold the commit
+            if std::fs::read(&revision.path).ok() == std::fs::read(&revision.copy).ok() {
+                documents.push(revision.path.clone());
+            }
+        }
+        for (commit, documents) in by_commit {
+            let tag =
+                std::collections::BTreeMap::from([(COMMIT_TAG.to_string(), commit.to_string())]);
+            store.tag_documents(&documents, &tag)?;
+        }
+        drop(store);
+
+        if json {
+            let output = SyncOutput {
+                workspace: ws.config.name.clone(),
+                documents: files.len(),
+                documents_updated,
+                documents_tagged,
+                tags,
+                revisions: git.then_some(revisions.len()),
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        } else {
+            println!(
+                "Synced {} documents into workspace '{}' ({} new or changed).",
+                files.len(),
+                ws.config.name,
+                documents_updated
+            );
+            if git {
+                println!(
+                    "Indexed the committed copies of {} files for `search --at`.",
+                    revisions.len()
+                );
+            }
+            if !tags.is_empty() {
+                let tags: Vec<String> = tags.iter().map(|(k, v)| format!("{k}={v}")).collect();
+                println!(
+                    "Tagged {documents_tagged} documents with {}.",
+                    tags.join(", ")
+                );
+            }
+        }
+
+        Workspace::run_scheduled_maintenance(workspace_name)?;
+    }
+    #[cfg(not(feature = "workspace-lite"))]
+    {
+        let _ = (files, tags, git, embed_preset, offline, workspace_name);
+        if json {
+            let error_output = ErrorOutput {
+                error: "workspace feature not enabled".to_string(),
+                error_type: "FeatureNotEnabled".to_string(),
+            };
+            let json_output = serde_json::to_string_pretty(&error_output)?;
+            eprintln!("{}", json_output);
+        } else {
+            println!("workspace feature not enabled");
+        }
+    }
+    Ok(())
+}
+
+/// `inputs` with each `s3://bucket/prefix` replaced by the objects under it, mirrored into the
+/// cache (only new or changed objects are downloaded) and parsed to markdown unless they are
+/// already text. Also returns the documents of objects deleted from the bucket since the last
+/// sync, which leave the workspace.
+#[cfg(feature = "workspace-lite")]
+async fn resolve_s3_inputs(inputs: Vec<String>) -> Result<(Vec<String>, Vec<String>)> {
+    let mut files = Vec::new();
+    let mut mirrored = Vec::new();
+    let mut stale = Vec::new();
+    for input in inputs {
+        if !is_s3_url(&input) {
+            files.push(input);
+            continue;
+        }
+        let prefix = mirror_prefix(&input).await?;
+        eprintln!(
+            "Mirrored {} objects of {input} ({} downloaded, {} failed)",
+            prefix.files.len(),
+            prefix.downloaded,
+            prefix.failed
+        );
+        mirrored.extend(prefix.files.into_iter().map(|(path, _)| path));
+        stale.extend(prefix.deleted);
+    }
+    if mirrored.is_empty() && stale.is_empty() {
+        return Ok((files, stale));
+    }
+
+    #[cfg(feature = "parse")]
+    {
+        use crate::parse::LlamaParseBackend;
+        use crate::parse::cache::CacheManager;
+        use crate::parse::local::parse_backend_chain;
+
+        // The parsed copies of deleted objects were indexed in their place
+        let cache = CacheManager::new(CacheManager::default_cache_dir()?);
+        stale.extend(cache.parsed_paths_of(&stale)?);
+        if mirrored.is_empty() {
+            return Ok((files, stale));
+        }
+
+        // Text objects are passed through; parsed ones are cited by their s3:// URL
+        let parse_config = crate::SemtoolsConfig::load_default()?
+            .parse
+            .unwrap_or_default();
+        let chain = parse_backend_chain(&parse_config.backends)?;
+        let backend = LlamaParseBackend::new(parse_config, false)?.with_chain(chain);
+        let parsed = backend.parse_files(mirrored).await?;
+        files.extend(parsed.into_iter().map(|result| result.output_path));
+    }
+    #[cfg(not(feature = "parse"))]
+    files.extend(mirrored);
+    Ok((files, stale))
+}
+
+/// `workspace kv`: index the `key_field`→`value_field` pairs of CSV or JSONL files into the
+/// workspace's key-value table, replacing the pairs of earlier syncs of the same files
+#[allow(clippy::too_many_arguments)]
+pub async fn workspace_kv_cmd(
+    files: Vec<String>,
+    key_field: String,
+    value_field: String,
+    input_format: Option<String>,
+    embed_preset: Option<String>,
+    offline: bool,
+    json: bool,
+    workspace_name: Option<&str>,
+) -> Result<()> {
+    #[cfg(feature = "workspace-lite")]
+    {
+        let _name = Workspace::active(workspace_name).context("No active workspace")?;
+        let input_format = input_format
+            .as_deref()
+            .map(str::parse::<InputFormat>)
+            .transpose()?;
+        let ws = Workspace::open(workspace_name)?;
+        ws.ensure_writable("sync key-value pairs")?;
+
+        let embed_preset = resolve_embed_preset(embed_preset.as_deref(), workspace_name)?;
+        let model = ModelSource::from_config_file(None, embed_preset)?.load(offline)?;
+        check_embedding_dimensions(&ws, model.encode_single("").len())?;
+
+        let files = resolve_inputs(files, false).await?.member_paths();
+        let mut added = Vec::new();
+        for file in &files {
+            let format = kv_format(file, input_format)?;
+            let content = std::fs::read_to_string(crate::archive::local_path(file))
+                .with_context(|| format!("Reading {file}"))?;
+            let pairs = parse_pairs(&content, format, &key_field, &value_field)
+                .with_context(|| format!("Failed to read pairs from {file}"))?;
+            if pairs.is_empty() {
+                eprintln!(
+                    "Warning: No records of {file} have both '{key_field}' and '{value_field}'"
+                );
+            }
+            added.extend(embed_pairs(file, pairs, &model));
+        }
+
+        let store = ws.open_store()?;
+        let _lock = store.write_lock()?;
+        let mut entries = store.kv_entries()?;
+        let pairs = added.len();
+        let pairs_replaced = merge_entries(&mut entries, added);
+        store.save_kv_entries(&entries)?;
+
+        if json {
+            let output = KvSyncOutput {
+                workspace: ws.config.name.clone(),
+                files: files.len(),
+                pairs,
+                pairs_replaced,
+                pairs_total: entries.len(),
+            };
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        } else {
+            println!(
+                "Synced {pairs} key-value pairs from {} files into workspace '{}' ({} in total).",
+                files.len(),
+                ws.config.name,
+                entries.len()
+            );
+        }
+    }
+    #[cfg(not(feature = "workspace-lite"))]
+    {
+        let _ = (
+            files,
+            key_field,
+            value_field,
+            input_format,
+            embed_preset,
+            offline,
+            workspace_name,
+        );
+        if json {
+            let error_output = ErrorOutput {
+                error: "workspace feature not enabled".to_string(),
+                error_type: "FeatureNotEnabled".to_string(),
+            };
+            let json_output = serde_json::to_string_pretty(&error_output)?;
+            eprintln!("{}", json_output);
+        } else {
+            println!("workspace feature not enabled");
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub enum SnapshotAction {
+    Create(String),
+    List,
+    Restore(String),
+}
+
+pub async fn workspace_snapshot_cmd(
+    action: SnapshotAction,
+    json: bool,
+    workspace_name: Option<&str>,
+) -> Result<()> {
+    #[cfg(feature = "workspace-lite")]
+    {
+        let _name = Workspace::active(workspace_name).context("No active workspace")?;
+        let ws = Workspace::open(workspace_name)?;
+        let root_dir = &ws.config.root_dir;
+
+        let to_json = |info: snapshot::SnapshotInfo| SnapshotJSON {
+            name: info.name,
+            created_at: info.created_at,
+            total_documents: info.total_documents,
+        };
+
+        match action {
+            SnapshotAction::Create(name) => {
+                let info = snapshot::create_snapshot(root_dir, &name)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&to_json(info))?);
+                } else {
+                    println!(
+                        "Created snapshot '{}' of workspace '{}' ({} documents).",
+                        info.name, ws.config.name, info.total_documents
+                    );
+                }
+            }
+            SnapshotAction::List => {
+                let snapshots = snapshot::list_snapshots(root_dir)?;
+                if json {
+                    let output = SnapshotListOutput {
+                        workspace: ws.config.name.clone(),
+                        snapshots: snapshots.into_iter().map(to_json).collect(),
+                    };
+                    println!("{}", serde_json::to_string_pretty(&output)?);
+                } else if snapshots.is_empty() {
+                    println!("No snapshots for workspace '{}'.", ws.config.name);
+                } else {
+                    println!("Snapshots for workspace '{}':", ws.config.name);
+                    for info in snapshots {
+                        println!(
+                            "  - {} (created_at: {}, documents: {})",
+                            info.name, info.created_at, info.total_documents
+                        );
+                    }
+                }
+            }
+            SnapshotAction::Restore(name) => {
+                ws.ensure_writable("restore a snapshot")?;
+                let info = snapshot::restore_snapshot(root_dir, &name)?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&to_json(info))?);
+                } else {
+                    println!(
+                        "Restored workspace '{}' to snapshot '{}' ({} documents).",
+                        ws.config.name, info.name, info.total_documents
+                    );
+                }
+            }
+        }
+    }
+    #[cfg(not(feature = "workspace-lite"))]
     {
+        let _ = (action, workspace_name);
         if json {
             let error_output = ErrorOutput {
                 error: "workspace feature not enabled".to_string(),
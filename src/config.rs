@@ -1,7 +1,13 @@
+use anyhow::{Context, bail};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
+#[cfg(feature = "import")]
+use crate::import::ImportConfig;
 #[cfg(feature = "parse")]
 use crate::parse::LlamaParseConfig;
 
@@ -17,10 +23,214 @@ pub struct SemtoolsConfig {
     #[cfg(feature = "ask")]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ask: Option<AskConfig>,
+
+    /// Configuration for the embedding model used by search, ask and workspaces
+    #[cfg(feature = "search")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<ModelConfig>,
+
+    /// Saved searches shared through the config file (`semtools saved`); searches saved
+    /// locally with `saved add` take precedence over ones of the same name here
+    #[cfg(feature = "search")]
+    #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+    pub saved_searches: std::collections::BTreeMap<String, SavedSearch>,
+
+    /// Masking of sensitive values in text sent to LLM APIs (`--redact`)
+    #[cfg(feature = "ask")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redaction: Option<RedactionConfig>,
+
+    /// Credentials of the Notion, Confluence and mail importers (`semtools import`)
+    #[cfg(feature = "import")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub import: Option<ImportConfig>,
+
+    /// Prices used to turn token and page counts into costs (`--show-usage`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<PricingConfig>,
+
+    /// Where OpenTelemetry traces and metrics are exported
+    #[cfg(feature = "observability")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observability: Option<ObservabilityConfig>,
+
+    /// Defaults for working in a project, usually set in its `.semtools.json`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<ProjectConfig>,
+}
+
+/// Defaults for working in a project, so a team can commit them to its repository
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ProjectConfig {
+    /// Workspace used when neither --workspace nor SEMTOOLS_WORKSPACE names one
+    pub workspace: Option<String>,
+
+    /// Globs of the files `search` and `ask` read when given no files and no stdin, relative
+    /// to the directory of the `.semtools.json` setting them (e.g. `["docs/**/*.md"]`). A
+    /// leading `!` excludes files, and files ignored by git are skipped.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+
+    /// Directory `files` is relative to: the project's root, or the working directory when
+    /// they come from the user's config file
+    #[serde(skip)]
+    pub root: Option<PathBuf>,
+}
+
+#[cfg(any(feature = "search", feature = "ask"))]
+impl ProjectConfig {
+    /// The files matching `files`, sorted. Paths under the working directory are relative to
+    /// it.
+    pub fn matching_files(&self) -> anyhow::Result<Vec<String>> {
+        if self.files.is_empty() {
+            return Ok(Vec::new());
+        }
+        let cwd = std::env::current_dir()?;
+        let root = self.root.clone().unwrap_or_else(|| cwd.clone());
+        let mut overrides = ignore::overrides::OverrideBuilder::new(&root);
+        for glob in &self.files {
+            overrides
+                .add(glob)
+                .with_context(|| format!("Invalid glob '{glob}' in `project.files`"))?;
+        }
+        let walker = ignore::WalkBuilder::new(&root)
+            .overrides(overrides.build()?)
+            .build();
+
+        let mut files = Vec::new();
+        for entry in walker {
+            let entry = entry?;
+            if entry.file_type().is_some_and(|t| t.is_file()) {
+                let path = entry.path();
+                let path = path.strip_prefix(&cwd).unwrap_or(path);
+                files.push(path.to_string_lossy().to_string());
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+}
+
+/// Configuration for OTLP export (requires the `observability` feature)
+#[cfg(feature = "observability")]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ObservabilityConfig {
+    /// Base URL of the OTLP/HTTP collector, e.g. "http://localhost:4318" (defaults to the
+    /// OTEL_EXPORTER_OTLP_ENDPOINT env var; export is off when neither is set)
+    pub endpoint: Option<String>,
+
+    /// Service name attached to all telemetry (defaults to "semtools")
+    pub service_name: Option<String>,
+}
+
+/// Redaction of text before it is sent to LLM APIs (see `ask::redaction`)
+#[cfg(feature = "ask")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RedactionConfig {
+    /// Redact every request, without passing `--redact`
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Built-in detectors to apply: "email", "ssn" and "api_key" (all by default)
+    #[serde(default = "default_redaction_detectors")]
+    pub detectors: Vec<String>,
+
+    /// Extra patterns to redact, keyed by the name used in their placeholders: with
+    /// `{"employee_id": "\\bE\\d{6}\\b"}`, ids become `[EMPLOYEE_ID_1]`, `[EMPLOYEE_ID_2]`...
+    #[serde(default)]
+    pub patterns: std::collections::BTreeMap<String, String>,
+}
+
+#[cfg(feature = "ask")]
+fn default_redaction_detectors() -> Vec<String> {
+    crate::ask::redaction::BUILTIN_DETECTORS
+        .iter()
+        .map(|d| d.to_string())
+        .collect()
+}
+
+#[cfg(feature = "ask")]
+impl Default for RedactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            detectors: default_redaction_detectors(),
+            patterns: Default::default(),
+        }
+    }
+}
+
+/// Prices for usage accounting. Usage without a configured price is counted but not costed.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct PricingConfig {
+    /// Token prices per LLM model name, e.g. "gpt-4o-mini"
+    #[serde(default)]
+    pub models: HashMap<String, ModelPricing>,
+
+    /// Price of one page parsed by LlamaParse
+    pub parse_per_page: Option<f64>,
+}
+
+/// Token prices of one LLM model, in dollars per million tokens
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelPricing {
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+}
+
+/// Configuration for the static embedding model
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ModelConfig {
+    /// Hugging Face repo id or local model directory (defaults to
+    /// "minishlab/potion-multilingual-128M")
+    pub name: Option<String>,
+
+    /// Named model to use when `name` is not set: "fast", "balanced" or "quality" (the default)
+    pub preset: Option<String>,
+
+    /// Where downloaded models are cached (defaults to the Hugging Face cache,
+    /// $HF_HOME/hub or ~/.cache/huggingface/hub)
+    pub cache_dir: Option<String>,
+
+    /// Where to embed documents: "auto" (the default), "cpu", "metal" or "cuda". GPUs need a
+    /// build with the `gpu` feature; embedding falls back to the CPU when none is usable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device: Option<String>,
+
+    /// Normalization of text before it is embedded, for queries and documents alike (all off by
+    /// default). Workspace documents are embedded again when it changes.
+    #[cfg(feature = "search")]
+    #[serde(
+        default,
+        skip_serializing_if = "crate::search::normalize::Normalization::is_off"
+    )]
+    pub normalize: crate::search::normalize::Normalization,
+}
+
+/// A named search: the query plus the files and flags to run it with
+#[cfg(feature = "search")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SavedSearch {
+    pub query: String,
+
+    /// Files and `search` flags, as they would follow the query on the command line (e.g.
+    /// `["docs/", "--max-distance", "0.3"]`)
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
 }
 
 /// API mode for the ask CLI tool
-#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum ApiMode {
     /// Use the Chat Completions API (legacy)
@@ -32,6 +242,7 @@ pub enum ApiMode {
 
 /// Configuration for the ask CLI tool
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct AskConfig {
     /// OpenAI API key (can also be set via OPENAI_API_KEY env var)
     pub api_key: Option<String>,
@@ -48,6 +259,26 @@ pub struct AskConfig {
     /// API mode to use (chat or responses). Defaults to responses.
     #[serde(default)]
     pub api_mode: ApiMode,
+
+    /// Reuse answers to questions already asked over an unchanged corpus (see `ask --cache`)
+    #[serde(default)]
+    pub cache_answers: bool,
+
+    /// Vision-capable model the `view_image` tool sends images to (defaults to `model`)
+    #[serde(default)]
+    pub vision_model: Option<String>,
+
+    /// Language to answer in, e.g. "Japanese" (defaults to the language of the question)
+    #[serde(default)]
+    pub answer_language: Option<String>,
+
+    /// Sampling temperature of LLM requests, 0 to 2 (defaults to the provider's)
+    #[serde(default)]
+    pub temperature: Option<f32>,
+
+    /// Seed of LLM requests, for repeatable answers (chat API mode only)
+    #[serde(default)]
+    pub seed: Option<i64>,
 }
 
 impl Default for AskConfig {
@@ -58,28 +289,72 @@ impl Default for AskConfig {
             model: Some("gpt-4o-mini".to_string()),
             max_iterations: Some(20),
             api_mode: ApiMode::default(),
+            cache_answers: false,
+            vision_model: None,
+            answer_language: None,
+            temperature: None,
+            seed: None,
         }
     }
 }
 
 impl SemtoolsConfig {
-    /// Load configuration from a file path
-    /// If the file doesn't exist, returns default configuration
+    /// Load configuration from a file path, with the project config file of the working
+    /// directory (see [`crate::paths::project_config_file`]) merged over it. Missing files
+    /// leave the defaults in place.
     pub fn from_config_file(path: &str) -> anyhow::Result<Self> {
-        if !Path::new(path).exists() {
-            return Ok(Self::default());
+        let invalid =
+            || format!("Invalid config file {path} (check it with `semtools config validate`)");
+        let mut value =
+            read_config_value(Path::new(path))?.unwrap_or_else(|| Value::Object(Map::new()));
+        resolve_secrets(&mut value, "").with_context(|| format!("In config file {path}"))?;
+        let project = load_project_config()?;
+        if let Some((_, project_value)) = &project {
+            merge_config(&mut value, project_value);
         }
 
-        let contents = fs::read_to_string(path)?;
-        let config: SemtoolsConfig = serde_json::from_str(&contents)?;
+        let mut config: SemtoolsConfig = serde_json::from_value(value).with_context(invalid)?;
+        if let (Some(settings), Some((project_path, project_value))) =
+            (&mut config.project, project)
+            && project_value["project"].get("files").is_some()
+        {
+            settings.root = project_path.parent().map(Path::to_path_buf);
+        }
         Ok(config)
     }
 
-    /// Get the default config file path (~/.semtools_config.json)
+    /// Only the `project` section of the config in effect, loaded without running the
+    /// config's secret commands
+    pub fn project_settings(path: &str) -> anyhow::Result<ProjectConfig> {
+        let mut settings = read_config_value(Path::new(path))?
+            .and_then(|mut value| value.get_mut("project").map(Value::take))
+            .map(serde_json::from_value::<Option<ProjectConfig>>)
+            .transpose()
+            .with_context(|| format!("Invalid `project` section in {path}"))?
+            .flatten()
+            .unwrap_or_default();
+        if let Some((project_path, project_value)) = load_project_config()?
+            && let Some(section) = project_value.get("project")
+        {
+            let project: ProjectConfig =
+                serde_json::from_value(section.clone()).with_context(|| {
+                    format!("Invalid `project` section in {}", project_path.display())
+                })?;
+            if project.workspace.is_some() {
+                settings.workspace = project.workspace;
+            }
+            if !project.files.is_empty() {
+                settings.files = project.files;
+                settings.root = project_path.parent().map(Path::to_path_buf);
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Get the default config file path ($XDG_CONFIG_HOME/semtools/config.json)
     pub fn default_config_path() -> String {
-        dirs::home_dir()
+        crate::paths::config_file()
             .unwrap()
-            .join(".semtools_config.json")
             .to_string_lossy()
             .to_string()
     }
@@ -88,4 +363,601 @@ impl SemtoolsConfig {
     pub fn load_default() -> anyhow::Result<Self> {
         Self::from_config_file(&Self::default_config_path())
     }
+
+    /// Every section with its default values, as used when the config file leaves it out.
+    /// API keys come from the environment, like the defaults they stand for.
+    pub fn defaults() -> Self {
+        Self {
+            #[cfg(feature = "parse")]
+            parse: Some(LlamaParseConfig::default()),
+            #[cfg(feature = "ask")]
+            ask: Some(AskConfig::default()),
+            #[cfg(feature = "search")]
+            model: Some(ModelConfig {
+                preset: Some(crate::search::model::EmbedPreset::default().to_string()),
+                ..Default::default()
+            }),
+            #[cfg(feature = "search")]
+            saved_searches: Default::default(),
+            #[cfg(feature = "ask")]
+            redaction: Some(RedactionConfig::default()),
+            #[cfg(feature = "import")]
+            import: Some(ImportConfig {
+                notion: Some(Default::default()),
+                confluence: Some(Default::default()),
+                mail: Some(Default::default()),
+            }),
+            pricing: Some(PricingConfig::default()),
+            #[cfg(feature = "observability")]
+            observability: Some(ObservabilityConfig {
+                endpoint: std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok(),
+                service_name: Some("semtools".to_string()),
+            }),
+            project: None,
+        }
+    }
+}
+
+/// `files`, or the files `project.files` of the config at `config_path` names when none are
+/// given and nothing is piped in
+#[cfg(any(feature = "search", feature = "ask"))]
+pub fn files_or_project_files(
+    files: Vec<String>,
+    config_path: Option<&str>,
+) -> anyhow::Result<Vec<String>> {
+    use std::io::IsTerminal;
+    if !files.is_empty() || !std::io::stdin().is_terminal() {
+        return Ok(files);
+    }
+    let config_path = config_path
+        .map(str::to_string)
+        .unwrap_or_else(SemtoolsConfig::default_config_path);
+    SemtoolsConfig::project_settings(&config_path)?.matching_files()
+}
+
+/// A config file's JSON, or `None` if it does not exist
+fn read_config_value(path: &Path) -> anyhow::Result<Option<Value>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let contents = fs::read_to_string(path)?;
+    let value = serde_json::from_str(&contents).with_context(|| {
+        format!(
+            "Invalid config file {} (check it with `semtools config validate`)",
+            path.display()
+        )
+    })?;
+    Ok(Some(value))
+}
+
+/// Keys a project config file may not set, at any depth: a repository could otherwise send
+/// the user's API keys to a server of its choosing, run commands, or point semtools at files
+/// outside the project
+const PROJECT_FORBIDDEN_KEYS: [&str; 8] = [
+    "api_key",
+    "password",
+    "access_token",
+    "base_url",
+    "endpoint",
+    "host",
+    "cache_dir",
+    "service_account_key",
+];
+
+/// Keys whose values are secrets, masked when the config is shown
+const SECRET_KEYS: [&str; 5] = [
+    "api_key",
+    "password",
+    "access_token",
+    "client_secret",
+    "refresh_token",
+];
+
+/// The project config file of the working directory and its JSON, refusing keys that only
+/// the user's own config file may set
+pub fn load_project_config() -> anyhow::Result<Option<(PathBuf, Value)>> {
+    let Some(path) = crate::paths::project_config_file() else {
+        return Ok(None);
+    };
+    let Some(value) = read_config_value(&path)? else {
+        return Ok(None);
+    };
+    if let Some(key) = forbidden_project_key(&value, "") {
+        bail!(
+            "`{key}` cannot be set in the project config {}: API keys, commands and the \
+             servers requests go to are only read from your own config file",
+            path.display()
+        );
+    }
+    Ok(Some((path, value)))
+}
+
+/// The dotted path of the first key in `value` that a project config may not set
+fn forbidden_project_key(value: &Value, path: &str) -> Option<String> {
+    let Value::Object(map) = value else {
+        return None;
+    };
+    map.iter().find_map(|(key, value)| {
+        let key_path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{path}.{key}")
+        };
+        if PROJECT_FORBIDDEN_KEYS.contains(&key.as_str()) || key.ends_with(COMMAND_SUFFIX) {
+            Some(key_path)
+        } else {
+            forbidden_project_key(value, &key_path)
+        }
+    })
+}
+
+/// Merge `over` into `config`: the keys of a section override the section's keys one by one,
+/// other values replace what is there, and null values are skipped
+pub fn merge_config(config: &mut Value, over: &Value) {
+    let (Some(config), Some(over)) = (config.as_object_mut(), over.as_object()) else {
+        return;
+    };
+    for (key, section) in over {
+        match (config.get_mut(key), section) {
+            (Some(Value::Object(base)), Value::Object(section)) => {
+                for (field, value) in section {
+                    if !value.is_null() {
+                        base.insert(field.clone(), value.clone());
+                    }
+                }
+            }
+            (_, Value::Null) => {}
+            _ => {
+                config.insert(key.clone(), section.clone());
+            }
+        }
+    }
+}
+
+/// Suffix of keys holding a shell command that prints the value of the key without it, e.g.
+/// `"api_key_cmd": "op read op://vault/openai"` for `api_key`
+const COMMAND_SUFFIX: &str = "_cmd";
+
+/// Resolve the secrets of a config file in place, so they need not be stored in it: `${VAR}`
+/// in a string is replaced by the environment variable `VAR` (`$${` is a literal `${`), and
+/// `<key>_cmd` is replaced by `<key>` set to the command's output. `path` is the dotted key
+/// of `value`, used in errors.
+pub fn resolve_secrets(value: &mut Value, path: &str) -> anyhow::Result<()> {
+    let key_path = |key: &str| {
+        if path.is_empty() {
+            key.to_string()
+        } else {
+            format!("{path}.{key}")
+        }
+    };
+    match value {
+        Value::String(text) => {
+            *text = interpolate_env(text).with_context(|| format!("`{path}`"))?
+        }
+        Value::Array(values) => {
+            for (i, value) in values.iter_mut().enumerate() {
+                resolve_secrets(value, &key_path(&i.to_string()))?;
+            }
+        }
+        Value::Object(map) => {
+            let commands: Vec<String> = map
+                .keys()
+                .filter(|key| key.ends_with(COMMAND_SUFFIX))
+                .cloned()
+                .collect();
+            let mut outputs = Vec::new();
+            for command_key in commands {
+                let key = command_key
+                    .strip_suffix(COMMAND_SUFFIX)
+                    .unwrap()
+                    .to_string();
+                let Some(Value::String(command)) = map.remove(&command_key) else {
+                    bail!("`{}` must be a string", key_path(&command_key));
+                };
+                if map.get(&key).is_some_and(|value| !value.is_null()) {
+                    bail!(
+                        "Set either `{}` or `{}`, not both",
+                        key_path(&key),
+                        key_path(&command_key)
+                    );
+                }
+                let output = run_secret_command(&command)
+                    .with_context(|| format!("`{}`", key_path(&command_key)))?;
+                outputs.push((key, output));
+            }
+            for (key, value) in map.iter_mut() {
+                resolve_secrets(value, &key_path(key))?;
+            }
+            // Inserted last, so that what the commands print is taken as it is
+            for (key, output) in outputs {
+                map.insert(key, Value::String(output));
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Replace each `${VAR}` in `text` with the environment variable, failing if it is not set
+fn interpolate_env(text: &str) -> anyhow::Result<String> {
+    let mut resolved = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("${") {
+        if rest[..start].ends_with('$') {
+            // `$${` is an escaped, literal `${`
+            resolved.push_str(&rest[..start - 1]);
+            resolved.push_str("${");
+            rest = &rest[start + 2..];
+            continue;
+        }
+        resolved.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            bail!("unclosed `${{` in '{text}'");
+        };
+        let name = &rest[start + 2..start + end];
+        if name.is_empty() {
+            bail!("empty variable name `${{}}`");
+        }
+        match std::env::var(name) {
+            Ok(value) => resolved.push_str(&value),
+            Err(std::env::VarError::NotPresent) => bail!("environment variable {name} is not set"),
+            Err(e) => bail!("environment variable {name}: {e}"),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    resolved.push_str(rest);
+    Ok(resolved)
+}
+
+/// Run `command` in the shell and return what it printed, without the trailing newline
+fn run_secret_command(command: &str) -> anyhow::Result<String> {
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = std::process::Command::new("cmd");
+        shell.args(["/C", command]);
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = std::process::Command::new("sh");
+        shell.args(["-c", command]);
+        shell
+    };
+    let output = shell
+        .stdin(std::process::Stdio::null())
+        .output()
+        .with_context(|| format!("could not run `{command}`"))?;
+    if !output.status.success() {
+        bail!(
+            "`{command}` failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let stdout = String::from_utf8(output.stdout)
+        .with_context(|| format!("`{command}` printed invalid UTF-8"))?;
+    let secret = stdout.trim_end_matches(['\n', '\r']);
+    if secret.is_empty() {
+        bail!("`{command}` printed nothing");
+    }
+    Ok(secret.to_string())
+}
+
+/// `config` with each `<key>_cmd` standing in for its `<key>`, for checking the types of keys
+/// without running the commands
+fn with_commands_as_keys(config: &Value) -> Value {
+    match config {
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(
+                    |(key, value)| match (key.strip_suffix(COMMAND_SUFFIX), value) {
+                        (Some(key), Value::String(_)) => (key.to_string(), value.clone()),
+                        _ => (key.clone(), with_commands_as_keys(value)),
+                    },
+                )
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.iter().map(with_commands_as_keys).collect()),
+        _ => config.clone(),
+    }
+}
+
+/// Sections known to this build, with the error deserializing `section` as its type.
+/// `None` for keys that are not a section.
+fn check_section(key: &str, section: &Value) -> Option<Result<(), serde_json::Error>> {
+    fn check<T: DeserializeOwned>(section: &Value) -> Result<(), serde_json::Error> {
+        T::deserialize(section).map(|_| ())
+    }
+    match key {
+        #[cfg(feature = "parse")]
+        "parse" => Some(check::<Option<LlamaParseConfig>>(section)),
+        #[cfg(feature = "ask")]
+        "ask" => Some(check::<Option<AskConfig>>(section)),
+        #[cfg(feature = "search")]
+        "model" => Some(check::<Option<ModelConfig>>(section)),
+        #[cfg(feature = "search")]
+        "saved_searches" => Some(check::<std::collections::BTreeMap<String, SavedSearch>>(
+            section,
+        )),
+        #[cfg(feature = "ask")]
+        "redaction" => Some(check::<Option<RedactionConfig>>(section)),
+        #[cfg(feature = "import")]
+        "import" => Some(check::<Option<ImportConfig>>(section)),
+        "pricing" => Some(check::<Option<PricingConfig>>(section)),
+        #[cfg(feature = "observability")]
+        "observability" => Some(check::<Option<ObservabilityConfig>>(section)),
+        "project" => Some(check::<Option<ProjectConfig>>(section)),
+        _ => None,
+    }
+}
+
+/// Problems with a config file's contents: unknown sections or keys and values of the wrong
+/// type. Empty when the config is valid.
+pub fn validate_config(config: &Value) -> Vec<String> {
+    let config = with_commands_as_keys(config);
+    let Some(sections) = config.as_object() else {
+        return vec!["the config must be a JSON object".to_string()];
+    };
+    let mut problems = Vec::new();
+    for (key, section) in sections {
+        match check_section(key, section) {
+            Some(Ok(())) => {}
+            Some(Err(e)) => problems.push(format!("{key}: {e}")),
+            None => problems.push(format!(
+                "unknown section `{key}` (or its feature is not enabled in this build)"
+            )),
+        }
+    }
+    problems
+}
+
+/// The config in effect: `config` with every key it leaves out (or sets to null) taken from
+/// the defaults. API keys, passwords, tokens and client secrets are masked.
+pub fn effective_config(config: &Value) -> anyhow::Result<Value> {
+    let mut effective = serde_json::to_value(SemtoolsConfig::defaults())?;
+    merge_config(&mut effective, config);
+    mask_api_keys(&mut effective);
+    Ok(effective)
+}
+
+fn mask_api_keys(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    // References to secrets kept elsewhere are shown as they are
+                    Value::String(secret)
+                        if SECRET_KEYS.contains(&key.as_str()) && !secret.contains("${") =>
+                    {
+                        *secret = mask(secret)
+                    }
+                    _ => mask_api_keys(value),
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(mask_api_keys),
+        _ => {}
+    }
+}
+
+/// Keep only the last four characters of a secret, enough to tell keys apart
+fn mask(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    let visible: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+    if chars.len() <= 8 {
+        "****".to_string()
+    } else {
+        format!("****{visible}")
+    }
+}
+
+/// Set the dotted `key` (e.g. `ask.model`) of `config` to `raw`, read as JSON when it parses
+/// (numbers, booleans, arrays, objects) and as a string otherwise. Objects along the way are
+/// created as needed.
+pub fn set_config_value(config: &mut Value, key: &str, raw: &str) -> anyhow::Result<Value> {
+    let path: Vec<&str> = key.split('.').collect();
+    if path.iter().any(|part| part.is_empty()) {
+        bail!("Invalid key '{key}': expected a dotted path such as `ask.model`");
+    }
+    let value = serde_json::from_str(raw).unwrap_or_else(|_| Value::String(raw.to_string()));
+
+    if config.is_null() {
+        *config = Value::Object(Map::new());
+    }
+    let mut current = config;
+    for (i, part) in path.iter().enumerate() {
+        let Value::Object(map) = current else {
+            bail!("'{}' is not an object", path[..i].join("."));
+        };
+        if i == path.len() - 1 {
+            map.insert(part.to_string(), value.clone());
+            break;
+        }
+        let next = map
+            .entry(part.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+        if next.is_null() {
+            *next = Value::Object(Map::new());
+        }
+        current = next;
+    }
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_validate_config() {
+        assert!(validate_config(&json!({"pricing": {"parse_per_page": 0.003}})).is_empty());
+
+        let problems = validate_config(&json!({
+            "pricing": {"parse_per_page": "cheap"},
+            "pricng": {},
+        }));
+        assert_eq!(problems.len(), 2);
+        assert!(
+            problems
+                .iter()
+                .any(|p| p.starts_with("pricing: invalid type"))
+        );
+        assert!(
+            problems
+                .iter()
+                .any(|p| p.contains("unknown section `pricng`"))
+        );
+        assert!(!validate_config(&json!([])).is_empty());
+    }
+
+    #[cfg(feature = "ask")]
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let problems = validate_config(&json!({"ask": {"modle": "gpt-4.1"}}));
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].contains("unknown field `modle`"),
+            "{problems:?}"
+        );
+    }
+
+    #[cfg(feature = "ask")]
+    #[test]
+    fn test_effective_config() {
+        let effective = effective_config(&json!({
+            "ask": {"model": "gpt-4.1", "api_key": "sk-abcdefgh1234", "max_iterations": null}
+        }))
+        .unwrap();
+        assert_eq!(effective["ask"]["model"], "gpt-4.1");
+        assert_eq!(effective["ask"]["api_key"], "****1234");
+        // Left out or null keys keep their defaults
+        assert_eq!(effective["ask"]["max_iterations"], 20);
+        assert_eq!(effective["ask"]["api_mode"], "responses");
+    }
+
+    #[cfg(feature = "parse")]
+    #[test]
+    fn test_effective_config_masks_gdrive_secrets() {
+        let effective = effective_config(&json!({
+            "parse": {"gdrive": {
+                "client_id": "1234.apps.googleusercontent.com",
+                "client_secret": "GOCSPX-abcdefgh5678",
+                "refresh_token": "1//0gabcdefghijkl9012",
+                "access_token": "ya29.abcdefghijkl3456"
+            }}
+        }))
+        .unwrap();
+        let gdrive = &effective["parse"]["gdrive"];
+        assert_eq!(gdrive["client_id"], "1234.apps.googleusercontent.com");
+        assert_eq!(gdrive["client_secret"], "****5678");
+        assert_eq!(gdrive["refresh_token"], "****9012");
+        assert_eq!(gdrive["access_token"], "****3456");
+    }
+
+    #[test]
+    fn test_resolve_secrets() {
+        // SAFETY: only this test reads or writes this variable
+        unsafe { std::env::set_var("SEMTOOLS_TEST_SECRET", "s3cret") };
+        let mut config = json!({
+            "ask": {"api_key": "${SEMTOOLS_TEST_SECRET}", "base_url": "http://$${host}"},
+            "parse": {"api_key_cmd": "echo from-command"},
+            "saved_searches": {"a": {"args": ["pre-${SEMTOOLS_TEST_SECRET}-post"]}},
+        });
+        resolve_secrets(&mut config, "").unwrap();
+        assert_eq!(config["ask"]["api_key"], "s3cret");
+        assert_eq!(config["ask"]["base_url"], "http://${host}");
+        assert_eq!(config["saved_searches"]["a"]["args"][0], "pre-s3cret-post");
+        #[cfg(not(windows))]
+        assert_eq!(config["parse"], json!({"api_key": "from-command"}));
+
+        let err = resolve_secrets(
+            &mut json!({"ask": {"api_key": "${SEMTOOLS_UNSET_VAR}"}}),
+            "",
+        )
+        .unwrap_err();
+        assert_eq!(
+            format!("{err:#}"),
+            "`ask.api_key`: environment variable SEMTOOLS_UNSET_VAR is not set"
+        );
+        let both = json!({"ask": {"api_key": "x", "api_key_cmd": "echo y"}});
+        assert!(resolve_secrets(&mut both.clone(), "").is_err());
+        let failing = json!({"ask": {"api_key_cmd": "exit 3"}});
+        assert!(resolve_secrets(&mut failing.clone(), "").is_err());
+    }
+
+    #[cfg(feature = "ask")]
+    #[test]
+    fn test_validate_config_accepts_commands() {
+        let config = json!({"ask": {"api_key_cmd": "op read op://vault/openai"}});
+        assert!(validate_config(&config).is_empty());
+    }
+
+    #[test]
+    fn test_forbidden_project_key() {
+        let allowed = json!({"project": {"workspace": "docs"}, "ask": {"model": "gpt-4.1"}});
+        assert_eq!(forbidden_project_key(&allowed, ""), None);
+        let key = json!({"ask": {"model": "gpt-4.1", "base_url": "http://example.com"}});
+        assert_eq!(
+            forbidden_project_key(&key, ""),
+            Some("ask.base_url".to_string())
+        );
+        let command = json!({"parse": {"api_key_cmd": "cat key"}});
+        assert_eq!(
+            forbidden_project_key(&command, ""),
+            Some("parse.api_key_cmd".to_string())
+        );
+        let server = json!({"import": {"mail": {"host": "imap.example.com"}}});
+        assert_eq!(
+            forbidden_project_key(&server, ""),
+            Some("import.mail.host".to_string())
+        );
+        let cache = json!({"model": {"preset": "fast", "cache_dir": "/tmp/models"}});
+        assert_eq!(
+            forbidden_project_key(&cache, ""),
+            Some("model.cache_dir".to_string())
+        );
+        let key_file = json!({"parse": {"gdrive": {"service_account_key": "/etc/key.json"}}});
+        assert_eq!(
+            forbidden_project_key(&key_file, ""),
+            Some("parse.gdrive.service_account_key".to_string())
+        );
+    }
+
+    #[cfg(feature = "search")]
+    #[test]
+    fn test_project_matching_files() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("docs/guide")).unwrap();
+        fs::write(dir.path().join("docs/guide/a.md"), "a").unwrap();
+        fs::write(dir.path().join("docs/draft.md"), "b").unwrap();
+        fs::write(dir.path().join("docs/notes.txt"), "c").unwrap();
+        fs::write(dir.path().join("README.md"), "d").unwrap();
+
+        let project = ProjectConfig {
+            files: vec!["docs/**/*.md".to_string(), "!docs/draft.md".to_string()],
+            root: Some(dir.path().to_path_buf()),
+            ..Default::default()
+        };
+        let files = project.matching_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with("docs/guide/a.md"));
+    }
+
+    #[test]
+    fn test_set_config_value() {
+        let mut config = json!({"ask": {"model": "gpt-4o-mini"}});
+        set_config_value(&mut config, "ask.model", "gpt-4.1").unwrap();
+        set_config_value(&mut config, "ask.max_iterations", "5").unwrap();
+        set_config_value(&mut config, "pricing.parse_per_page", "0.003").unwrap();
+        assert_eq!(
+            config,
+            json!({
+                "ask": {"model": "gpt-4.1", "max_iterations": 5},
+                "pricing": {"parse_per_page": 0.003},
+            })
+        );
+        assert!(set_config_value(&mut config, "ask.model.name", "x").is_err());
+        assert!(set_config_value(&mut config, "ask..model", "x").is_err());
+    }
 }
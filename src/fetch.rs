@@ -0,0 +1,613 @@
+use anyhow::{Context, Result};
+use reqwest::header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::archive;
+
+/// Largest document downloaded for a URL input
+pub const MAX_DOWNLOAD_BYTES: u64 = 100 * 1024 * 1024;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Whether an input names a web document rather than a local file
+pub fn is_url(input: &str) -> bool {
+    input.starts_with("https://") || input.starts_with("http://")
+}
+
+/// What is remembered about a download, so the next fetch can ask the server whether it changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct DownloadMetadata {
+    pub(crate) url: String,
+    pub(crate) file_name: String,
+    pub(crate) content_type: Option<String>,
+    pub(crate) etag: Option<String>,
+    pub(crate) last_modified: Option<String>,
+}
+
+/// A downloaded document
+struct Download {
+    path: PathBuf,
+    content_type: Option<String>,
+    /// False when the server confirmed the cached copy is current
+    updated: bool,
+}
+
+/// Downloads URL inputs into `~/.cache/semtools/downloads`, reusing the cached copy when the server
+/// answers a conditional request (ETag / Last-Modified) with 304 Not Modified
+pub struct Fetcher {
+    client: reqwest::Client,
+    dir: PathBuf,
+}
+
+impl Fetcher {
+    pub fn new() -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("semtools/", env!("CARGO_PKG_VERSION")))
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            client,
+            dir: Self::default_dir()?,
+        })
+    }
+
+    pub fn default_dir() -> Result<PathBuf> {
+        crate::paths::cache_path("downloads")
+    }
+
+    /// Download `url` as-is, named with an extension matching its content type so parse
+    /// backends recognize it
+    pub async fn fetch(&self, url: &str) -> Result<PathBuf> {
+        Ok(self.download(url).await?.path)
+    }
+
+    /// Download `url` as plain text for search: HTML is stripped to its text, and documents
+    /// that are not text (e.g. PDFs) are rejected
+    pub async fn fetch_text(&self, url: &str) -> Result<PathBuf> {
+        let download = self.download(url).await?;
+        let content_type = download.content_type.as_deref().unwrap_or_default();
+        let extension = download
+            .path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+
+        if is_html(content_type) || (content_type.is_empty() && extension == "html") {
+            let text_path = download.path.with_extension("txt");
+            if download.updated || !text_path.exists() {
+                let html = String::from_utf8_lossy(&fs::read(&download.path)?).to_string();
+                fs::write(&text_path, html_to_text(&html))?;
+            }
+            return Ok(text_path);
+        }
+        if is_text(content_type) || (content_type.is_empty() && extension == "txt") {
+            return Ok(download.path);
+        }
+        anyhow::bail!(
+            "{url} is not a text document ({content_type}); parse it first with `semtools parse`"
+        )
+    }
+
+    async fn download(&self, url: &str) -> Result<Download> {
+        let parsed_url = Url::parse(url).with_context(|| format!("Invalid URL: {url}"))?;
+        let stem = cache_stem(url);
+        let metadata_path = self.dir.join(format!("{stem}.json"));
+        let cached = read_metadata(&metadata_path)
+            .filter(|m| m.url == url && self.dir.join(&m.file_name).exists());
+
+        let mut request = self.client.get(parsed_url.clone());
+        if let Some(cached) = &cached {
+            if let Some(etag) = &cached.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &cached.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let mut response = request
+            .send()
+            .await
+            .with_context(|| format!("Could not fetch {url}"))?;
+        if response.status() == StatusCode::NOT_MODIFIED
+            && let Some(cached) = cached
+        {
+            return Ok(Download {
+                path: self.dir.join(&cached.file_name),
+                content_type: cached.content_type,
+                updated: false,
+            });
+        }
+        if !response.status().is_success() {
+            anyhow::bail!("Could not fetch {url}: HTTP {}", response.status());
+        }
+        if let Some(length) = response.content_length()
+            && length > MAX_DOWNLOAD_BYTES
+        {
+            anyhow::bail!(
+                "{url} is {length} bytes, more than the {MAX_DOWNLOAD_BYTES} byte download limit"
+            );
+        }
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let content_type = header(CONTENT_TYPE).map(|ct| media_type(&ct));
+        let etag = header(ETAG);
+        let last_modified = header(LAST_MODIFIED);
+
+        let extension = extension_for(content_type.as_deref(), &parsed_url);
+        let file_name = format!("{stem}.{extension}");
+        let path = self.dir.join(&file_name);
+
+        // Stream to a temporary file so an oversized or interrupted download never replaces
+        // the cached copy
+        fs::create_dir_all(&self.dir)?;
+        let partial_path = self.dir.join(format!("{file_name}.part"));
+        let mut file = fs::File::create(&partial_path)?;
+        let mut written = 0u64;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .with_context(|| format!("Could not fetch {url}"))?
+        {
+            written += chunk.len() as u64;
+            if written > MAX_DOWNLOAD_BYTES {
+                drop(file);
+                let _ = fs::remove_file(&partial_path);
+                anyhow::bail!("{url} is larger than the {MAX_DOWNLOAD_BYTES} byte download limit");
+            }
+            file.write_all(&chunk)?;
+        }
+        drop(file);
+        fs::rename(&partial_path, &path)?;
+
+        // The content type (and with it the extension) may have changed since the last download
+        if let Some(cached) = &cached
+            && cached.file_name != file_name
+        {
+            let old_path = self.dir.join(&cached.file_name);
+            let _ = fs::remove_file(old_path.with_extension("txt"));
+            let _ = fs::remove_file(old_path);
+        }
+
+        let metadata = DownloadMetadata {
+            url: url.to_string(),
+            file_name,
+            content_type: content_type.clone(),
+            etag,
+            last_modified,
+        };
+        fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?)?;
+
+        Ok(Download {
+            path,
+            content_type,
+            updated: true,
+        })
+    }
+}
+
+/// Inputs with every URL replaced by its downloaded copy, and every archive by the files
+/// extracted from it
+#[derive(Debug, Default)]
+pub struct ResolvedInputs {
+    pub files: Vec<String>,
+    /// The URL each downloaded file came from, or the `archive!member` pseudo-path of each
+    /// extracted file
+    pub sources: HashMap<String, String>,
+    /// URLs that could not be fetched and archives that could not be expanded (a warning is
+    /// printed for each)
+    pub failed: usize,
+}
+
+impl ResolvedInputs {
+    /// The files, with those extracted from archives named by their pseudo-paths, for
+    /// commands that cite and store them under that name (read them with
+    /// `archive::local_path`)
+    pub fn member_paths(&self) -> Vec<String> {
+        self.files
+            .iter()
+            .map(|file| match self.sources.get(file) {
+                Some(source) if archive::split_member(source).is_some() => source.clone(),
+                _ => file.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Download the URLs among `inputs`, as plain text with `as_text`, and expand the archives
+/// among them, keeping other local paths as they are. URLs that cannot be fetched and archives
+/// that cannot be expanded are left out with a warning.
+pub async fn resolve_inputs(inputs: Vec<String>, as_text: bool) -> Result<ResolvedInputs> {
+    let mut resolved = ResolvedInputs::default();
+    if !inputs.iter().any(|i| is_url(i)) {
+        for input in inputs {
+            add_local(input, &mut resolved);
+        }
+        return Ok(resolved);
+    }
+
+    let fetcher = Fetcher::new()?;
+    let downloads = futures::future::join_all(inputs.iter().filter(|i| is_url(i)).map(|url| {
+        let fetcher = &fetcher;
+        async move {
+            if as_text {
+                fetcher.fetch_text(url).await
+            } else {
+                fetcher.fetch(url).await
+            }
+        }
+    }))
+    .await;
+    let mut downloads = downloads.into_iter();
+
+    for input in inputs {
+        if !is_url(&input) {
+            add_local(input, &mut resolved);
+            continue;
+        }
+        match downloads.next() {
+            Some(Ok(path)) => {
+                let path = path.to_string_lossy().to_string();
+                resolved.sources.insert(path.clone(), input);
+                resolved.files.push(path);
+            }
+            Some(Err(e)) => {
+                eprintln!("Warning: {e:#}");
+                resolved.failed += 1;
+            }
+            None => {}
+        }
+    }
+    Ok(resolved)
+}
+
+/// Add a local input to `resolved`, an archive as the files extracted from it
+fn add_local(input: String, resolved: &mut ResolvedInputs) {
+    if !archive::is_archive(&input) || !Path::new(&input).is_file() {
+        resolved.files.push(input);
+        return;
+    }
+    match archive::expand(&input) {
+        Ok(members) => {
+            for (path, member) in members {
+                resolved.sources.insert(path.clone(), member);
+                resolved.files.push(path);
+            }
+        }
+        Err(e) => {
+            eprintln!("Warning: Cannot expand archive {input}: {e:#}");
+            resolved.failed += 1;
+        }
+    }
+}
+
+pub(crate) fn read_metadata(path: &Path) -> Option<DownloadMetadata> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// The URL a downloaded file, or the text extracted from it, was fetched from, so results and
+/// citations can name the original document. `None` for any other file.
+pub fn source_url(path: &str) -> Option<String> {
+    if let Some(url) = crate::s3::source_url(path) {
+        return Some(url);
+    }
+    let path = Path::new(path);
+    if path.parent()? != Fetcher::default_dir().ok()? {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    let metadata = read_metadata(&path.with_file_name(format!("{stem}.json")))?;
+    (Path::new(&metadata.file_name).file_stem()? == stem).then_some(metadata.url)
+}
+
+/// A file name stem that is readable in search results and unique per URL, e.g.
+/// `example.com_docs_intro-3f2a9c1b04de`
+fn cache_stem(url: &str) -> String {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let mut slug = String::new();
+    for c in without_scheme.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+            slug.push(c);
+        } else if !slug.ends_with('_') {
+            slug.push('_');
+        }
+        if slug.len() >= 60 {
+            break;
+        }
+    }
+    let slug = slug.trim_matches(|c| c == '_' || c == '.');
+    let hash = hex::encode(Sha256::digest(url.as_bytes()));
+    format!("{slug}-{}", &hash[..12])
+}
+
+/// The media type of a Content-Type header, without parameters such as the charset
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+/// File extension for a download: from the content type where it is unambiguous, otherwise
+/// from the URL's path
+fn extension_for(content_type: Option<&str>, url: &Url) -> String {
+    let known = match content_type {
+        Some("text/html") | Some("application/xhtml+xml") => Some("html"),
+        Some("text/plain") => Some("txt"),
+        Some("text/markdown") => Some("md"),
+        Some("application/pdf") => Some("pdf"),
+        _ => None,
+    };
+    if let Some(extension) = known {
+        return extension.to_string();
+    }
+
+    let url_extension = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .and_then(|name| Path::new(name).extension())
+        .and_then(|e| e.to_str())
+        .filter(|e| !e.is_empty() && e.len() <= 5 && e.chars().all(|c| c.is_ascii_alphanumeric()))
+        .map(|e| e.to_ascii_lowercase());
+
+    match content_type.filter(|ct| *ct != "application/octet-stream") {
+        None => url_extension.unwrap_or_else(|| "bin".to_string()),
+        Some(ct) => {
+            // Trust the URL when it agrees with the content type (e.g. `.jpg` for image/jpeg)
+            if let Some(extension) = url_extension.filter(|e| {
+                mime_guess::from_ext(e)
+                    .iter()
+                    .any(|m| m.essence_str() == ct)
+            }) {
+                return extension;
+            }
+            mime_guess::get_mime_extensions_str(ct)
+                .and_then(|extensions| extensions.first())
+                .map(|e| e.to_string())
+                .unwrap_or_else(|| "bin".to_string())
+        }
+    }
+}
+
+fn is_html(content_type: &str) -> bool {
+    matches!(content_type, "text/html" | "application/xhtml+xml")
+}
+
+fn is_text(content_type: &str) -> bool {
+    content_type.starts_with("text/")
+        || matches!(content_type, "application/json" | "application/xml")
+        || content_type.ends_with("+json")
+        || content_type.ends_with("+xml")
+}
+
+/// Tags whose content is never shown
+const HIDDEN_TAGS: &[&str] = &["script", "style", "noscript", "template", "svg", "head"];
+
+/// Tags that start a new line of text
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "tr",
+    "ul",
+];
+
+/// Reduce an HTML page to its visible text, one block (paragraph, heading, list item, table
+/// row...) per line
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+
+    while let Some(open) = rest.find('<') {
+        push_text(&mut text, &rest[..open]);
+        let tag_start = &rest[open..];
+
+        if let Some(comment) = tag_start.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(close) = tag_start.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &tag_start[1..close];
+        rest = &tag_start[close + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if !closing && HIDDEN_TAGS.contains(&name.as_str()) {
+            // Skip everything up to the matching closing tag
+            let end_tag = format!("</{name}");
+            rest = match rest.to_ascii_lowercase().find(&end_tag) {
+                Some(end) => rest[end..].find('>').map_or("", |i| &rest[end + i + 1..]),
+                None => "",
+            };
+            continue;
+        }
+        if BLOCK_TAGS.contains(&name.as_str()) {
+            text.push('\n');
+        } else if matches!(name.as_str(), "td" | "th") && !text.ends_with(char::is_whitespace) {
+            text.push(' ');
+        }
+    }
+    push_text(&mut text, rest);
+
+    let mut out = String::new();
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+/// Append a run of HTML text, collapsing whitespace like a browser and decoding entities
+fn push_text(text: &mut String, html: &str) {
+    let decoded = decode_entities(html);
+    for (i, word) in decoded.split_whitespace().enumerate() {
+        if (i > 0 || decoded.starts_with(char::is_whitespace))
+            && !text.ends_with(char::is_whitespace)
+        {
+            text.push(' ');
+        }
+        text.push_str(word);
+    }
+    if decoded.ends_with(char::is_whitespace) && !text.ends_with(char::is_whitespace) {
+        text.push(' ');
+    }
+}
+
+/// Decode the HTML character references in `s`, leaving unknown ones as they are
+pub(crate) fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let entity = &rest[amp..];
+        let decoded = entity[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| decode_entity(&entity[1..end + 1]).map(|c| (c, end + 2)));
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &entity[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &entity[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(number) = name.strip_prefix('#') {
+        let code = match number.strip_prefix(['x', 'X']) {
+            Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+            None => number.parse().ok()?,
+        };
+        return char::from_u32(code);
+    }
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text() {
+        let html = r#"<!DOCTYPE html>
+<html><head><title>Ignored</title><style>p { color: red; }</style></head>
+<body>
+  <h1>Quarterly   report</h1>
+  <!-- draft -->
+  <p>Revenue grew <b>12%</b>
+     year over year.</p>
+  <script>var x = "<p>not text</p>";</script>
+  <ul><li>Tom &amp; Jerry</li><li>5 &lt; 6&#33;</li></ul>
+  <table><tr><td>Q1</td><td>10</td></tr></table>
+</body></html>"#;
+
+        assert_eq!(
+            html_to_text(html),
+            "Quarterly report\nRevenue grew 12% year over year.\nTom & Jerry\n5 < 6!\nQ1 10\n"
+        );
+    }
+
+    #[test]
+    fn test_decode_entities() {
+        assert_eq!(
+            decode_entities("a &amp; b &#x41; &unknown; &"),
+            "a & b A &unknown; &"
+        );
+    }
+
+    #[test]
+    fn test_cache_stem() {
+        let stem = cache_stem("https://example.com/docs/intro?lang=en");
+        assert!(stem.starts_with("example.com_docs_intro_lang_en-"));
+        assert_ne!(stem, cache_stem("https://example.com/docs/intro?lang=fr"));
+    }
+
+    #[test]
+    fn test_extension_for() {
+        let url = Url::parse("https://example.com/download?id=3").unwrap();
+        assert_eq!(extension_for(Some("application/pdf"), &url), "pdf");
+        assert_eq!(extension_for(Some("text/html"), &url), "html");
+        assert_eq!(extension_for(None, &url), "bin");
+
+        let url = Url::parse("https://example.com/files/Report.DOCX").unwrap();
+        assert_eq!(
+            extension_for(Some("application/octet-stream"), &url),
+            "docx"
+        );
+        assert_eq!(
+            extension_for(
+                Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document"),
+                &url
+            ),
+            "docx"
+        );
+    }
+}
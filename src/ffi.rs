@@ -0,0 +1,342 @@
+//! C API over search and parse (`ffi` feature), for native applications that link semtools
+//! instead of spawning it. The header, `include/semtools.h`, is generated by cbindgen when the
+//! library is built with the feature.
+//!
+//! Every call returns a `SemtoolsResults` that the caller owns and releases with
+//! `semtools_free_results`, whether it holds results or an error. Calls block until done and
+//! may be made from several threads at once.
+use anyhow::{Context, Result, bail};
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::ptr;
+use std::sync::LazyLock;
+
+use crate::api::{self, SearchOptions};
+use crate::json_mode::{ParseResultJSON, SearchResultJSON};
+
+/// Runtime the blocking C calls run the async search and parse on
+static RUNTIME: LazyLock<tokio::runtime::Runtime> =
+    LazyLock::new(|| tokio::runtime::Runtime::new().expect("Failed to start the tokio runtime"));
+
+/// Search options; start from `semtools_search_options_default`
+#[repr(C)]
+pub struct SemtoolsSearchOptions {
+    /// Lines of context before and after each match
+    pub n_lines: usize,
+    /// Number of results
+    pub top_k: usize,
+    /// Return every match under this distance instead of the top `top_k` (NaN for none)
+    pub max_distance: f64,
+    pub ignore_case: bool,
+    /// Workspace to search through, or NULL
+    pub workspace: *const c_char,
+}
+
+#[repr(C)]
+pub struct SemtoolsSearchResult {
+    pub filename: *mut c_char,
+    pub start_line_number: usize,
+    pub end_line_number: usize,
+    pub match_line_number: usize,
+    pub distance: f64,
+    pub score: f64,
+    /// The matching line with its context
+    pub content: *mut c_char,
+}
+
+#[repr(C)]
+pub struct SemtoolsParseResult {
+    pub input_path: *mut c_char,
+    /// Markdown output of the parse
+    pub output_path: *mut c_char,
+    pub was_cached: bool,
+    /// Backend that produced the output, or NULL for readable files passed through as-is
+    pub backend: *mut c_char,
+}
+
+/// The outcome of a call: an array of results, or an error
+#[repr(C)]
+pub struct SemtoolsResults {
+    /// Search results, best first (NULL for parse calls and errors)
+    pub search_results: *mut SemtoolsSearchResult,
+    /// Parse results, in input order (NULL for search calls and errors)
+    pub parse_results: *mut SemtoolsParseResult,
+    /// Number of entries in the array that is set
+    pub len: usize,
+    /// Error message, or NULL on success
+    pub error: *mut c_char,
+}
+
+/// An owned C string; interior NUL bytes, which C cannot represent, are dropped
+fn to_c(s: String) -> *mut c_char {
+    let s = CString::new(s).unwrap_or_else(|e| {
+        let mut bytes = e.into_vec();
+        bytes.retain(|&b| b != 0);
+        CString::new(bytes).unwrap()
+    });
+    s.into_raw()
+}
+
+fn free_c(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: every non-null string handed out was created by `to_c`
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+/// `items` as an owned array, released with `free_array`
+fn into_array<T>(items: Vec<T>) -> (*mut T, usize) {
+    let len = items.len();
+    (Box::into_raw(items.into_boxed_slice()) as *mut T, len)
+}
+
+/// # Safety
+///
+/// `ptr` and `len` must come from `into_array`
+unsafe fn free_array<T>(ptr: *mut T, len: usize) -> Vec<T> {
+    if ptr.is_null() {
+        return Vec::new();
+    }
+    unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)) }.into_vec()
+}
+
+impl SemtoolsResults {
+    fn search(results: Vec<SearchResultJSON>) -> Self {
+        let results = results
+            .into_iter()
+            .map(|r| SemtoolsSearchResult {
+                filename: to_c(r.filename),
+                start_line_number: r.start_line_number,
+                end_line_number: r.end_line_number,
+                match_line_number: r.match_line_number,
+                distance: r.distance,
+                score: r.score,
+                content: to_c(r.content),
+            })
+            .collect();
+        let (search_results, len) = into_array(results);
+        Self {
+            search_results,
+            parse_results: ptr::null_mut(),
+            len,
+            error: ptr::null_mut(),
+        }
+    }
+
+    fn parse(results: Vec<ParseResultJSON>) -> Self {
+        let results = results
+            .into_iter()
+            .map(|r| SemtoolsParseResult {
+                input_path: to_c(r.input_path),
+                output_path: to_c(r.output_path),
+                was_cached: r.was_cached,
+                backend: r.backend.map_or(ptr::null_mut(), to_c),
+            })
+            .collect();
+        let (parse_results, len) = into_array(results);
+        Self {
+            search_results: ptr::null_mut(),
+            parse_results,
+            len,
+            error: ptr::null_mut(),
+        }
+    }
+
+    fn error(message: String) -> Self {
+        Self {
+            search_results: ptr::null_mut(),
+            parse_results: ptr::null_mut(),
+            len: 0,
+            error: to_c(message),
+        }
+    }
+}
+
+/// Run `call`, turning its error or panic into an error result: unwinding into C is undefined
+fn run(call: impl FnOnce() -> Result<SemtoolsResults>) -> *mut SemtoolsResults {
+    let results = match catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(results)) => results,
+        Ok(Err(e)) => SemtoolsResults::error(format!("{e:#}")),
+        Err(_) => SemtoolsResults::error("semtools panicked".to_string()),
+    };
+    Box::into_raw(Box::new(results))
+}
+
+/// # Safety
+///
+/// `ptr` must be NULL or a valid NUL-terminated string
+unsafe fn read_str(ptr: *const c_char, what: &str) -> Result<Option<String>> {
+    if ptr.is_null() {
+        return Ok(None);
+    }
+    let s = unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .with_context(|| format!("{what} is not valid UTF-8"))?;
+    Ok(Some(s.to_string()))
+}
+
+/// # Safety
+///
+/// `files` must point to `n_files` valid NUL-terminated strings
+unsafe fn read_files(files: *const *const c_char, n_files: usize) -> Result<Vec<String>> {
+    if files.is_null() || n_files == 0 {
+        bail!("No files provided");
+    }
+    unsafe { std::slice::from_raw_parts(files, n_files) }
+        .iter()
+        .map(|&file| unsafe { read_str(file, "a file path") }?.context("A file path is NULL"))
+        .collect()
+}
+
+/// The options `search` uses by default
+#[unsafe(no_mangle)]
+pub extern "C" fn semtools_search_options_default() -> SemtoolsSearchOptions {
+    let defaults = SearchOptions::default();
+    SemtoolsSearchOptions {
+        n_lines: defaults.n_lines,
+        top_k: defaults.top_k,
+        max_distance: f64::NAN,
+        ignore_case: defaults.ignore_case,
+        workspace: ptr::null(),
+    }
+}
+
+/// Search `files` for lines semantically similar to `query`. `options` may be NULL for the
+/// defaults. The embedding model is loaded on the first call and kept for later ones.
+///
+/// # Safety
+///
+/// `query` must be a NUL-terminated string, `files` must point to `n_files` of them, and
+/// `options`, if not NULL, must point to valid options.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn semtools_search(
+    query: *const c_char,
+    files: *const *const c_char,
+    n_files: usize,
+    options: *const SemtoolsSearchOptions,
+) -> *mut SemtoolsResults {
+    run(|| {
+        let query = unsafe { read_str(query, "The query") }?.context("The query is NULL")?;
+        let files = unsafe { read_files(files, n_files) }?;
+        let mut search_options = SearchOptions::default();
+        if let Some(options) = unsafe { options.as_ref() } {
+            search_options.n_lines = options.n_lines;
+            search_options.top_k = options.top_k;
+            search_options.max_distance =
+                Some(options.max_distance).filter(|distance| !distance.is_nan());
+            search_options.ignore_case = options.ignore_case;
+            search_options.workspace = unsafe { read_str(options.workspace, "The workspace") }?;
+        }
+        let results = RUNTIME.block_on(api::search(query, files, search_options))?;
+        Ok(SemtoolsResults::search(results))
+    })
+}
+
+/// Parse `files` to markdown with `backend` (a `--backend` chain such as "pdf-text,llama-parse"),
+/// or the config file's chain when it is NULL.
+///
+/// # Safety
+///
+/// `files` must point to `n_files` NUL-terminated strings, and `backend` must be NULL or one.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn semtools_parse(
+    files: *const *const c_char,
+    n_files: usize,
+    backend: *const c_char,
+) -> *mut SemtoolsResults {
+    run(|| {
+        let files = unsafe { read_files(files, n_files) }?;
+        let backend = unsafe { read_str(backend, "The backend") }?;
+        let results = RUNTIME.block_on(api::parse(files, backend, None))?;
+        Ok(SemtoolsResults::parse(results))
+    })
+}
+
+/// Release what `semtools_search` or `semtools_parse` returned, including every string in it
+///
+/// # Safety
+///
+/// `results` must be NULL or a pointer returned by this library that was not freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn semtools_free_results(results: *mut SemtoolsResults) {
+    if results.is_null() {
+        return;
+    }
+    let results = unsafe { Box::from_raw(results) };
+    for result in unsafe { free_array(results.search_results, results.len) } {
+        free_c(result.filename);
+        free_c(result.content);
+    }
+    for result in unsafe { free_array(results.parse_results, results.len) } {
+        free_c(result.input_path);
+        free_c(result.output_path);
+        free_c(result.backend);
+    }
+    free_c(results.error);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(ptr: *const c_char) -> &'static str {
+        unsafe { CStr::from_ptr(ptr) }.to_str().unwrap()
+    }
+
+    #[test]
+    fn test_header_is_up_to_date() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/semtools.h"));
+        let committed = include_str!("../include/semtools.h");
+        assert!(
+            generated == committed,
+            "include/semtools.h is out of date: copy {} over it",
+            concat!(env!("OUT_DIR"), "/semtools.h")
+        );
+    }
+
+    #[test]
+    fn test_search_results_round_trip() {
+        let results = Box::into_raw(Box::new(SemtoolsResults::search(vec![SearchResultJSON {
+            filename: "notes.md".to_string(),
+            start_line_number: 0,
+            end_line_number: 4,
+            match_line_number: 2,
+            distance: 0.2,
+            score: 0.2,
+            normalized_score: None,
+            match_start_col: None,
+            match_end_col: None,
+            content: "a\0b\nc".to_string(),
+            metadata: None,
+            commit: None,
+        }])));
+        let view = unsafe { &*results };
+        assert!(view.error.is_null() && view.parse_results.is_null());
+        assert_eq!(view.len, 1);
+        let first = unsafe { &*view.search_results };
+        assert_eq!(read(first.filename), "notes.md");
+        assert_eq!(read(first.content), "ab\nc");
+        assert_eq!(first.match_line_number, 2);
+        unsafe { semtools_free_results(results) };
+    }
+
+    #[test]
+    fn test_invalid_arguments_are_errors() {
+        let options = semtools_search_options_default();
+        assert!(options.max_distance.is_nan());
+
+        let query = CString::new("query").unwrap();
+        let results = unsafe { semtools_search(query.as_ptr(), ptr::null(), 0, &options) };
+        let view = unsafe { &*results };
+        assert_eq!(read(view.error), "No files provided");
+        assert!(view.search_results.is_null());
+        unsafe { semtools_free_results(results) };
+
+        let file = CString::new("notes.md").unwrap();
+        let files = [file.as_ptr()];
+        let results = unsafe { semtools_search(ptr::null(), files.as_ptr(), 1, ptr::null()) };
+        assert_eq!(read(unsafe { &*results }.error), "The query is NULL");
+        unsafe { semtools_free_results(results) };
+        unsafe { semtools_free_results(ptr::null_mut()) };
+    }
+}
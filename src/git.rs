@@ -0,0 +1,314 @@
+//! Files of git repositories as of a revision, for `workspace sync --git` and `search --at`.
+//!
+//! A file as of a revision is written to
+//! `$XDG_CACHE_HOME/semtools/git/<repo>/<commit>/<path>`, where `<commit>` is the last commit
+//! that changed it. That copy is searched and indexed like any other file, so a workspace keeps
+//! the embeddings of every revision it has seen, and a file that did not change between two
+//! revisions is the same copy (embedded once). Indexed copies carry the commit in their
+//! `git_commit` tag.
+use anyhow::{Context, Result, bail};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Tag holding the commit a document was indexed from
+pub const COMMIT_TAG: &str = "git_commit";
+
+/// Hex digits of the commit in the directory of a revision, and where it is shown
+const COMMIT_DIR_CHARS: usize = 12;
+
+/// A git repository, run with the `git` command
+pub struct Repo {
+    root: PathBuf,
+}
+
+/// A file of a repository as of a commit
+#[derive(Debug, Clone, PartialEq)]
+pub struct Revision {
+    /// The file in the working tree
+    pub path: String,
+    /// The copy of the file as of `commit`
+    pub copy: String,
+    /// The last commit that changed the file
+    pub commit: String,
+}
+
+impl Repo {
+    /// The repository `path` is in, if any
+    pub fn of(path: &Path) -> Option<Repo> {
+        let dir = if path.is_dir() { path } else { path.parent()? };
+        let dir = if dir.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            dir
+        };
+        let root = git(dir, &["rev-parse", "--show-toplevel"]).ok()?;
+        Some(Repo {
+            root: PathBuf::from(root.trim_end_matches('\n')),
+        })
+    }
+
+    fn git(&self, args: &[&str]) -> Result<String> {
+        git(&self.root, args)
+    }
+
+    /// The commit `rev` names (a hash, branch, tag or expression such as `HEAD~3`)
+    pub fn resolve(&self, rev: &str) -> Result<String> {
+        let commit = self
+            .git(&[
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("{rev}^{{commit}}"),
+            ])
+            .with_context(|| format!("'{rev}' is not a revision of {}", self.root.display()))?;
+        Ok(commit.trim().to_string())
+    }
+
+    /// `path` relative to the root of the repository, with `/` separators
+    fn relative(&self, path: &Path) -> Option<String> {
+        let root = fs::canonicalize(&self.root).ok()?;
+        let path = fs::canonicalize(path)
+            .or_else(|_| std::path::absolute(path))
+            .ok()?;
+        let relative = path.strip_prefix(root).ok()?;
+        Some(
+            relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+        )
+    }
+
+    /// The files under `paths` (relative to the root; all of them when empty) at `commit`,
+    /// each with the last commit that changed it by then
+    pub fn files_at(&self, commit: &str, paths: &[String]) -> Result<Vec<(String, String)>> {
+        let mut args = vec!["-c", "core.quotePath=false", "ls-tree", "-r", "--name-only"];
+        args.push(commit);
+        args.push("--");
+        args.extend(paths.iter().map(String::as_str));
+        let listing = self.git(&args)?;
+        let files: Vec<&str> = listing.lines().filter(|line| !line.is_empty()).collect();
+        if files.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut args = vec![
+            "-c",
+            "core.quotePath=false",
+            "log",
+            "--format=%x00%H",
+            "--name-only",
+        ];
+        args.push(commit);
+        args.push("--");
+        args.extend(paths.iter().map(String::as_str));
+        let changes = last_changes(&self.git(&args)?);
+        Ok(files
+            .into_iter()
+            .map(|file| {
+                // Files only a merge brought in have no change of their own
+                let changed = changes.get(file).map_or(commit, String::as_str);
+                (file.to_string(), changed.to_string())
+            })
+            .collect())
+    }
+
+    /// Write `file` as of `commit` to the cache, unless it already is, and return the copy
+    pub fn checkout(&self, commit: &str, file: &str) -> Result<PathBuf> {
+        let copy = self.revisions_dir()?.join(short_commit(commit)).join(file);
+        if copy.is_file() {
+            return Ok(copy);
+        }
+        let output = Command::new("git")
+            .current_dir(&self.root)
+            .args(["cat-file", "blob", &format!("{commit}:{file}")])
+            .output()
+            .context("Cannot run git")?;
+        if !output.status.success() {
+            bail!(
+                "git cannot read {file} at {commit}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        if let Some(parent) = copy.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Written aside and renamed, so an interrupted write never passes for the copy
+        let mut partial = copy.clone().into_os_string();
+        partial.push(".partial");
+        fs::write(&partial, output.stdout)?;
+        fs::rename(&partial, &copy)?;
+        Ok(copy)
+    }
+
+    /// The revisions of this repository: `<name>-<hash of its root>`, so that repositories
+    /// with the same name get their own
+    fn revisions_dir(&self) -> Result<PathBuf> {
+        let name = self
+            .root
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "repo".to_string());
+        let hash = self
+            .root
+            .to_string_lossy()
+            .bytes()
+            .fold(0xcbf29ce484222325_u64, |hash, byte| {
+                (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+            });
+        Ok(crate::paths::cache_path("git")?.join(format!("{name}-{:08x}", hash as u32)))
+    }
+}
+
+/// The abbreviated hash that names the directory of a commit's copies, and is shown with
+/// results of `search --at`
+pub fn short_commit(commit: &str) -> &str {
+    commit.get(..COMMIT_DIR_CHARS).unwrap_or(commit)
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .context("Cannot run git; is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.iter()
+                .find(|arg| !arg.starts_with('-') && !arg.contains('='))
+                .unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The last commit that changed each file, from `git log --format=%x00%H --name-only` (newest
+/// commits first)
+fn last_changes(log: &str) -> HashMap<String, String> {
+    let mut changes = HashMap::new();
+    let mut commit = "";
+    for line in log.lines() {
+        if let Some(hash) = line.strip_prefix('\0') {
+            commit = hash;
+        } else if !line.is_empty() {
+            changes
+                .entry(line.to_string())
+                .or_insert_with(|| commit.to_string());
+        }
+    }
+    changes
+}
+
+/// A repository with paths given in it, each with its path relative to the root
+type RepoPaths = (Repo, Vec<(String, String)>);
+
+/// The repositories of `paths`, with the paths of each relative to its root. Paths outside a
+/// repository are returned apart.
+fn by_repo(paths: &[String]) -> (Vec<RepoPaths>, Vec<String>) {
+    let mut repos: Vec<RepoPaths> = Vec::new();
+    let mut roots: BTreeMap<PathBuf, usize> = BTreeMap::new();
+    let mut outside = Vec::new();
+    // Files of the same directory are in the same repository
+    let mut by_dir: HashMap<PathBuf, Option<PathBuf>> = HashMap::new();
+    for path in paths {
+        let path_ref = Path::new(path);
+        let dir = if path_ref.is_dir() {
+            path_ref
+        } else {
+            path_ref.parent().unwrap_or(Path::new(""))
+        };
+        let dir = dir.to_path_buf();
+        let root = by_dir
+            .entry(dir)
+            .or_insert_with(|| Repo::of(path_ref).map(|repo| repo.root))
+            .clone();
+        let Some(repo) = root.map(|root| Repo { root }) else {
+            outside.push(path.clone());
+            continue;
+        };
+        let Some(relative) = repo.relative(path_ref) else {
+            outside.push(path.clone());
+            continue;
+        };
+        let i = *roots.entry(repo.root.clone()).or_insert_with(|| {
+            repos.push((repo, Vec::new()));
+            repos.len() - 1
+        });
+        repos[i].1.push((path.clone(), relative));
+    }
+    (repos, outside)
+}
+
+/// The revisions of `files` at the HEAD of their repositories. Files outside a repository or
+/// not committed have none.
+pub fn head_revisions(files: &[String]) -> Result<Vec<Revision>> {
+    let (repos, _) = by_repo(files);
+    let mut revisions = Vec::new();
+    for (repo, files) in repos {
+        let head = repo.resolve("HEAD")?;
+        let relative: Vec<String> = files.iter().map(|(_, file)| file.clone()).collect();
+        let commits: HashMap<String, String> =
+            repo.files_at(&head, &relative)?.into_iter().collect();
+        for (path, file) in files {
+            if let Some(commit) = commits.get(&file) {
+                let copy = repo.checkout(commit, &file)?;
+                revisions.push(Revision {
+                    path,
+                    copy: copy.to_string_lossy().to_string(),
+                    commit: commit.clone(),
+                });
+            }
+        }
+    }
+    Ok(revisions)
+}
+
+/// `paths` (files or directories of git repositories) as of `rev`: the copies of the files
+/// under them at that revision, including files deleted since
+pub fn revisions_at(paths: &[String], rev: &str) -> Result<Vec<Revision>> {
+    let (repos, outside) = by_repo(paths);
+    if let Some(path) = outside.first() {
+        bail!("--at needs files of a git repository, and {path} is not in one");
+    }
+    let mut revisions = Vec::new();
+    for (repo, paths) in repos {
+        let commit = repo.resolve(rev)?;
+        // The root of the repository stands for all of its files
+        let relative: Vec<String> = if paths.iter().any(|(_, file)| file.is_empty()) {
+            Vec::new()
+        } else {
+            paths.iter().map(|(_, file)| file.clone()).collect()
+        };
+        for (file, changed) in repo.files_at(&commit, &relative)? {
+            let copy = repo.checkout(&changed, &file)?;
+            revisions.push(Revision {
+                path: repo.root.join(&file).to_string_lossy().to_string(),
+                copy: copy.to_string_lossy().to_string(),
+                commit: changed,
+            });
+        }
+    }
+    if revisions.is_empty() {
+        bail!("None of the files existed at {rev}");
+    }
+    Ok(revisions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_last_changes() {
+        let log = "\0bbb\n\ndocs/a.md\n\0aaa\n\ndocs/a.md\ndocs/b.md\n";
+        let changes = last_changes(log);
+        assert_eq!(changes["docs/a.md"], "bbb");
+        assert_eq!(changes["docs/b.md"], "aaa");
+        assert_eq!(changes.len(), 2);
+    }
+}
@@ -0,0 +1,320 @@
+//! Exact-pattern search on ripgrep's libraries, shared by `semtools grep` and the agent's
+//! `grep` tool.
+//!
+//! Directories are walked in parallel, skipping hidden files and whatever `.gitignore` and
+//! `.ignore` files exclude, unless asked not to. Paths given explicitly are always searched.
+//! Files containing NUL bytes are skipped as binary.
+use ::grep::regex::{RegexMatcher, RegexMatcherBuilder};
+use ::grep::searcher::{BinaryDetection, Searcher, SearcherBuilder, Sink, SinkContext, SinkMatch};
+use anyhow::{Result, anyhow, bail};
+use ignore::{WalkBuilder, WalkState};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc;
+
+use crate::archive;
+
+#[derive(Debug, Clone)]
+pub struct GrepOptions {
+    /// Match the pattern literally instead of as a regular expression
+    pub fixed_strings: bool,
+    pub case_sensitive: bool,
+    /// Only match whole words
+    pub word: bool,
+    /// Lines of context before and after each match
+    pub context_lines: usize,
+    /// Also search hidden files and directories
+    pub hidden: bool,
+    /// Also search files excluded by `.gitignore` and `.ignore` files
+    pub no_ignore: bool,
+}
+
+impl Default for GrepOptions {
+    fn default() -> Self {
+        Self {
+            fixed_strings: false,
+            case_sensitive: true,
+            word: false,
+            context_lines: 0,
+            hidden: false,
+            no_ignore: false,
+        }
+    }
+}
+
+/// Consecutive lines of a file: one or more matches with their context
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrepBlock {
+    pub file: String,
+    /// 0-based line number of the first line
+    pub start: usize,
+    pub lines: Vec<String>,
+    /// 0-based line numbers of the matching lines
+    pub matches: Vec<usize>,
+}
+
+impl GrepBlock {
+    /// 0-based line number after the last line
+    pub fn end(&self) -> usize {
+        self.start + self.lines.len()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GrepResults {
+    /// Matches, ordered by file and line
+    pub blocks: Vec<GrepBlock>,
+    /// Paths that could not be searched, with the reason
+    pub errors: Vec<String>,
+}
+
+pub fn build_matcher(pattern: &str, options: &GrepOptions) -> Result<RegexMatcher> {
+    RegexMatcherBuilder::new()
+        .fixed_strings(options.fixed_strings)
+        .case_insensitive(!options.case_sensitive)
+        .word(options.word)
+        // ^ and $ match at line boundaries, and no match spans lines
+        .multi_line(true)
+        .line_terminator(Some(b'\n'))
+        .build(pattern)
+        .map_err(|e| anyhow!("Invalid regex pattern: {e}"))
+}
+
+/// Collects the matches and context lines of one file into blocks
+struct BlockSink<'a> {
+    file: &'a str,
+    blocks: Vec<GrepBlock>,
+    binary: bool,
+}
+
+impl BlockSink<'_> {
+    fn push(&mut self, first_line_number: Option<u64>, bytes: &[u8], is_match: bool) {
+        // Line numbers are 1-based and always on, see `searcher`
+        let first = first_line_number.unwrap_or(1) as usize - 1;
+        for (offset, line) in bytes.split_inclusive(|&b| b == b'\n').enumerate() {
+            let line_number = first + offset;
+            let text = String::from_utf8_lossy(line)
+                .trim_end_matches(['\n', '\r'])
+                .to_string();
+            let block = match self.blocks.last_mut() {
+                Some(block) if block.end() == line_number => block,
+                _ => {
+                    self.blocks.push(GrepBlock {
+                        file: self.file.to_string(),
+                        start: line_number,
+                        lines: Vec::new(),
+                        matches: Vec::new(),
+                    });
+                    self.blocks.last_mut().unwrap()
+                }
+            };
+            block.lines.push(text);
+            if is_match {
+                block.matches.push(line_number);
+            }
+        }
+    }
+}
+
+impl Sink for BlockSink<'_> {
+    type Error = std::io::Error;
+
+    fn matched(&mut self, _: &Searcher, mat: &SinkMatch<'_>) -> Result<bool, Self::Error> {
+        self.push(mat.line_number(), mat.bytes(), true);
+        Ok(true)
+    }
+
+    fn context(&mut self, _: &Searcher, context: &SinkContext<'_>) -> Result<bool, Self::Error> {
+        self.push(context.line_number(), context.bytes(), false);
+        Ok(true)
+    }
+
+    fn binary_data(&mut self, _: &Searcher, _: u64) -> Result<bool, Self::Error> {
+        self.binary = true;
+        Ok(false)
+    }
+}
+
+fn searcher(options: &GrepOptions) -> Searcher {
+    SearcherBuilder::new()
+        .binary_detection(BinaryDetection::quit(b'\x00'))
+        .line_number(true)
+        .before_context(options.context_lines)
+        .after_context(options.context_lines)
+        .build()
+}
+
+fn search_file(
+    searcher: &mut Searcher,
+    matcher: &RegexMatcher,
+    path: &Path,
+) -> Result<Vec<GrepBlock>> {
+    let file = path.to_string_lossy();
+    let mut sink = BlockSink {
+        file: &file,
+        blocks: Vec::new(),
+        binary: false,
+    };
+    searcher
+        .search_path(matcher, path, &mut sink)
+        .map_err(|e| anyhow!("{file}: {e}"))?;
+    if sink.binary {
+        return Ok(Vec::new());
+    }
+    Ok(sink.blocks)
+}
+
+/// Search `paths` (files or directories) for `pattern`. Files inside archives, given by their
+/// `archive!member` pseudo-paths, are searched where they were extracted and cited as given.
+pub fn grep_paths(paths: &[String], pattern: &str, options: &GrepOptions) -> Result<GrepResults> {
+    let local_paths: Vec<String> = paths
+        .iter()
+        .map(|path| archive::local_path(path).to_string_lossy().to_string())
+        .collect();
+    let cited: HashMap<&String, &String> = local_paths
+        .iter()
+        .zip(paths)
+        .filter(|(local, path)| local != path)
+        .collect();
+    let Some((first, rest)) = local_paths.split_first() else {
+        bail!("No files to search");
+    };
+    let matcher = build_matcher(pattern, options)?;
+
+    let mut walker = WalkBuilder::new(first);
+    for path in rest {
+        walker.add(path);
+    }
+    walker
+        .standard_filters(!options.no_ignore)
+        .hidden(!options.hidden);
+
+    let (sender, receiver) = mpsc::channel();
+    walker.build_parallel().run(|| {
+        let matcher = matcher.clone();
+        let sender = sender.clone();
+        let mut searcher = searcher(options);
+        Box::new(move |entry| {
+            let result = match entry {
+                Ok(entry) => {
+                    let is_file = entry.file_type().is_some_and(|t| t.is_file())
+                        // A symlink given as a path is searched like the file it points to
+                        || (entry.depth() == 0 && entry.path().is_file());
+                    if !is_file {
+                        return WalkState::Continue;
+                    }
+                    search_file(&mut searcher, &matcher, entry.path())
+                }
+                Err(e) => Err(e.into()),
+            };
+            let _ = sender.send(result);
+            WalkState::Continue
+        })
+    });
+    drop(sender);
+
+    let mut results = GrepResults::default();
+    for result in receiver {
+        match result {
+            Ok(mut blocks) => {
+                for block in &mut blocks {
+                    if let Some(path) = cited.get(&block.file) {
+                        block.file = path.to_string();
+                    }
+                }
+                results.blocks.extend(blocks);
+            }
+            Err(e) => results.errors.push(format!("{e:#}")),
+        }
+    }
+    results
+        .blocks
+        .sort_by(|a, b| (a.file.as_str(), a.start).cmp(&(b.file.as_str(), b.start)));
+    results.errors.sort();
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn search(dir: &Path, pattern: &str, options: &GrepOptions) -> GrepResults {
+        grep_paths(&[dir.to_string_lossy().to_string()], pattern, options).unwrap()
+    }
+
+    #[test]
+    fn test_grep_blocks_and_context() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let lines: Vec<String> = (0..20).map(|i| format!("line {i}")).collect();
+        fs::write(dir.path().join("a.txt"), lines.join("\n") + "\n").unwrap();
+
+        let options = GrepOptions {
+            context_lines: 1,
+            ..Default::default()
+        };
+        let results = search(dir.path(), r"line (3|5|15)$", &options);
+        assert!(results.errors.is_empty());
+        // Lines 3 and 5 share their context, line 15 stands alone
+        assert_eq!(results.blocks.len(), 2);
+        assert_eq!(results.blocks[0].start, 2);
+        assert_eq!(results.blocks[0].end(), 7);
+        assert_eq!(results.blocks[0].matches, vec![3, 5]);
+        assert_eq!(
+            results.blocks[1].lines,
+            vec!["line 14", "line 15", "line 16"]
+        );
+    }
+
+    #[test]
+    fn test_grep_options() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::write(dir.path().join("a.txt"), "Price: $5 (USD)\nprices\n").unwrap();
+
+        let fixed = GrepOptions {
+            fixed_strings: true,
+            ..Default::default()
+        };
+        assert_eq!(search(dir.path(), "$5 (USD)", &fixed).blocks.len(), 1);
+        assert!(build_matcher("(unclosed", &GrepOptions::default()).is_err());
+
+        let insensitive_word = GrepOptions {
+            case_sensitive: false,
+            word: true,
+            ..Default::default()
+        };
+        let results = search(dir.path(), "PRICE", &insensitive_word);
+        assert_eq!(results.blocks[0].matches, vec![0]);
+    }
+
+    #[test]
+    fn test_grep_walk_skips_ignored_hidden_and_binary() {
+        let dir = tempfile::TempDir::new().unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/found.txt"), "needle\n").unwrap();
+        fs::write(dir.path().join(".hidden.txt"), "needle\n").unwrap();
+        fs::write(dir.path().join(".ignore"), "skipped.txt\n").unwrap();
+        fs::write(dir.path().join("skipped.txt"), "needle\n").unwrap();
+        fs::write(dir.path().join("data.bin"), b"needle\n\x00\x01").unwrap();
+
+        let results = search(dir.path(), "needle", &GrepOptions::default());
+        let files: Vec<&str> = results.blocks.iter().map(|b| b.file.as_str()).collect();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with("found.txt"));
+
+        let everything = GrepOptions {
+            hidden: true,
+            no_ignore: true,
+            ..Default::default()
+        };
+        assert_eq!(search(dir.path(), "needle", &everything).blocks.len(), 3);
+
+        // A path given explicitly is searched even when ignored
+        let skipped = dir.path().join("skipped.txt").to_string_lossy().to_string();
+        let results = grep_paths(&[skipped], "needle", &GrepOptions::default()).unwrap();
+        assert_eq!(results.blocks.len(), 1);
+
+        let missing = grep_paths(&["/no/such/file".to_string()], "x", &GrepOptions::default());
+        assert_eq!(missing.unwrap().errors.len(), 1);
+    }
+}
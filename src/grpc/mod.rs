@@ -0,0 +1,432 @@
+//! gRPC API over the search, parse, ask and workspace commands, generated from
+//! `proto/semtools.proto`
+use anyhow::Result;
+use model2vec_rs::model::StaticModel;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tonic::{Request, Response, Status, transport::Server};
+
+use crate::ask::answer_format::AnswerFormat;
+use crate::ask::chat_agent::ask_agent;
+use crate::ask::events::AgentEvent;
+use crate::ask::injection::InjectionCheck;
+use crate::ask::responses_agent::ask_agent_responses;
+use crate::ask::sampling::Sampling;
+use crate::cmds::ask::{AskSettings, resolve_ask_settings};
+use crate::cmds::search::{ranked_line_to_json, search_result_to_json};
+use crate::config::ApiMode;
+use crate::json_mode::SearchResultJSON;
+use crate::parse::local::parse_backend_chain;
+use crate::search::model::{EmbedPreset, ModelSource};
+use crate::search::{
+    DEFAULT_NEGATIVE_WEIGHT, QueryCombination, SearchConfig, search_files, search_with_workspace,
+};
+use crate::workspace::context::ResultContext;
+use crate::workspace::{Workspace, set_space_override};
+use crate::{LlamaParseBackend, SemtoolsConfig};
+
+pub mod proto {
+    tonic::include_proto!("semtools.v1");
+}
+
+use proto::semtools_server::{Semtools, SemtoolsServer};
+use proto::{
+    AskAnswer, AskEvent, AskRequest, InjectionDetected, ParseRequest, ParseResponse, ParseResult,
+    SearchRequest, SearchResponse, SearchResult, ToolCall, ToolResult, WorkspaceOp,
+    WorkspaceRequest, WorkspaceResponse, ask_event,
+};
+
+/// Address the gRPC server listens on unless `--addr` is given
+pub const DEFAULT_GRPC_ADDR: &str = "127.0.0.1:50051";
+
+fn internal(e: impl std::fmt::Display) -> Status {
+    Status::internal(e.to_string())
+}
+
+impl From<SearchResultJSON> for SearchResult {
+    fn from(result: SearchResultJSON) -> Self {
+        SearchResult {
+            filename: result.filename,
+            start_line_number: result.start_line_number as u64,
+            end_line_number: result.end_line_number as u64,
+            match_line_number: result.match_line_number as u64,
+            distance: result.distance,
+            score: result.score,
+            content: result.content,
+        }
+    }
+}
+
+impl From<AgentEvent> for AskEvent {
+    fn from(event: AgentEvent) -> Self {
+        let event = match event {
+            AgentEvent::ToolCall { name, arguments } => {
+                ask_event::Event::ToolCall(ToolCall { name, arguments })
+            }
+            AgentEvent::ToolResult {
+                name,
+                chunks,
+                lines,
+            } => ask_event::Event::ToolResult(ToolResult {
+                name,
+                chunks: chunks as u64,
+                lines: lines as u64,
+            }),
+            AgentEvent::InjectionDetected { name, reason, file } => {
+                ask_event::Event::InjectionDetected(InjectionDetected { name, reason, file })
+            }
+        };
+        AskEvent { event: Some(event) }
+    }
+}
+
+/// Shared state for all RPCs. The embedding model is loaded once at startup.
+pub struct SemtoolsService {
+    model: Arc<StaticModel>,
+    /// Hugging Face repo id or directory the model was loaded from
+    model_name: String,
+    config: Option<String>,
+}
+
+impl SemtoolsService {
+    pub fn new(
+        config: Option<String>,
+        embed_preset: Option<EmbedPreset>,
+        offline: bool,
+    ) -> Result<Self> {
+        let source = ModelSource::from_config_file(config.as_deref(), embed_preset)?;
+        let model = source.load(offline)?;
+        if let Some(preset) = embed_preset {
+            set_space_override(preset);
+        }
+        Ok(Self {
+            model: Arc::new(model),
+            model_name: source.name,
+            config,
+        })
+    }
+
+    /// Workspaces are searched in the embedding space of the server's preset (their own preset
+    /// unless one was given), which must be the model this server loaded
+    fn check_workspace_model(&self, workspace_name: Option<&str>) -> Result<()> {
+        if Workspace::active(workspace_name).is_err() {
+            return Ok(());
+        }
+        let ws = Workspace::open(workspace_name)?;
+        let preset = ws.space();
+        if preset.model_name() != self.model_name {
+            anyhow::bail!(
+                "Workspace '{}' uses the '{preset}' embedding preset ({}), but this server loaded \
+                 '{}'. Start the server with --embed-preset {preset}.",
+                ws.config.name,
+                preset.model_name(),
+                self.model_name
+            );
+        }
+        Ok(())
+    }
+}
+
+#[tonic::async_trait]
+impl Semtools for SemtoolsService {
+    async fn search(
+        &self,
+        request: Request<SearchRequest>,
+    ) -> Result<Response<SearchResponse>, Status> {
+        let request = request.into_inner();
+        if request.files.is_empty() {
+            return Err(Status::invalid_argument("No files provided"));
+        }
+
+        let n_lines = request.n_lines.unwrap_or(3) as usize;
+        let config = SearchConfig {
+            n_lines,
+            top_k: request.top_k.unwrap_or(3) as usize,
+            max_distance: request.max_distance,
+            ignore_case: request.ignore_case,
+            recency_boost: 0.0,
+            mmr_lambda: None,
+            negative_embeddings: Vec::new(),
+            negative_weight: DEFAULT_NEGATIVE_WEIGHT,
+            extra_query_embeddings: Vec::new(),
+            query_combination: QueryCombination::All,
+            tag_filters: Vec::new(),
+            metadata_filters: Vec::new(),
+            boosts: Vec::new(),
+        };
+        let query = if request.ignore_case {
+            request.query.to_lowercase()
+        } else {
+            request.query
+        };
+        let workspace_name = request.workspace.as_deref();
+        self.check_workspace_model(workspace_name)
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+
+        let results: Vec<SearchResultJSON> = if Workspace::active(workspace_name).is_ok() {
+            let ranked_lines =
+                search_with_workspace(&request.files, &query, &self.model, &config, workspace_name)
+                    .await
+                    .map_err(internal)?;
+            let context = ResultContext::new(workspace_name);
+            context.prefetch(ranked_lines.iter().map(|line| line.path.as_str()));
+            ranked_lines
+                .iter()
+                .map(|ranked_line| ranked_line_to_json(ranked_line, n_lines, &context))
+                .collect()
+        } else {
+            search_files(&request.files, &query, &self.model, &config)
+                .map_err(internal)?
+                .iter()
+                .map(search_result_to_json)
+                .collect()
+        };
+
+        Ok(Response::new(SearchResponse {
+            results: results.into_iter().map(SearchResult::from).collect(),
+        }))
+    }
+
+    async fn parse(
+        &self,
+        request: Request<ParseRequest>,
+    ) -> Result<Response<ParseResponse>, Status> {
+        let request = request.into_inner();
+
+        let config_path = self
+            .config
+            .clone()
+            .unwrap_or_else(SemtoolsConfig::default_config_path);
+        let semtools_config = SemtoolsConfig::from_config_file(&config_path).map_err(internal)?;
+        let parse_config = semtools_config.parse.unwrap_or_default();
+
+        // Resolve backend chain with priority: request > config file > llama-parse
+        let chain = match request.backend {
+            Some(b) => parse_backend_chain(&[b]),
+            None => parse_backend_chain(&parse_config.backends),
+        }
+        .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let backend = LlamaParseBackend::new(parse_config, false)
+            .map_err(internal)?
+            .with_chain(chain);
+        let results = backend.parse_files(request.files).await.map_err(internal)?;
+
+        Ok(Response::new(ParseResponse {
+            results: results
+                .into_iter()
+                .map(|r| ParseResult {
+                    input_path: r.input_path,
+                    output_path: r.output_path,
+                    was_cached: r.was_cached,
+                    backend: r.backend,
+                })
+                .collect(),
+        }))
+    }
+
+    type AskStream = UnboundedReceiverStream<Result<AskEvent, Status>>;
+
+    async fn ask(&self, request: Request<AskRequest>) -> Result<Response<Self::AskStream>, Status> {
+        let request = request.into_inner();
+        if request.files.is_empty() {
+            return Err(Status::invalid_argument("No files provided"));
+        }
+
+        self.check_workspace_model(request.workspace.as_deref())
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+
+        let mut answer_format = request
+            .answer_format
+            .as_deref()
+            .map(str::parse::<AnswerFormat>)
+            .transpose()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?
+            .unwrap_or_default();
+        let injection_check = request
+            .injection_check
+            .as_deref()
+            .map(str::parse::<InjectionCheck>)
+            .transpose()
+            .map_err(|e| Status::invalid_argument(e.to_string()))?
+            .unwrap_or_default();
+
+        let AskSettings {
+            client,
+            model_name,
+            api_mode,
+            max_iterations,
+            answer_language,
+            ..
+        } = resolve_ask_settings(
+            self.config.clone(),
+            None,
+            None,
+            request.model,
+            request.api_mode,
+            Sampling::default(),
+        )
+        .map_err(|e| Status::failed_precondition(e.to_string()))?;
+        answer_format.language = request.answer_language.or(answer_language);
+
+        let (stream_tx, stream_rx) = mpsc::unbounded_channel();
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel::<AgentEvent>();
+
+        // Forward tool events to the client as they happen
+        let forward_tx = stream_tx.clone();
+        let forwarder = tokio::spawn(async move {
+            while let Some(event) = events_rx.recv().await {
+                if forward_tx.send(Ok(AskEvent::from(event))).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let model = Arc::clone(&self.model);
+        tokio::spawn(async move {
+            let workspace_name = request.workspace.as_deref();
+            let output = match api_mode {
+                ApiMode::Chat => {
+                    ask_agent(
+                        request.files,
+                        &request.query,
+                        None,
+                        &model,
+                        &client,
+                        &model_name,
+                        max_iterations,
+                        workspace_name,
+                        &answer_format,
+                        None,
+                        &[],
+                        injection_check,
+                        Some(&events_tx),
+                    )
+                    .await
+                }
+                ApiMode::Responses => {
+                    ask_agent_responses(
+                        request.files,
+                        &request.query,
+                        None,
+                        &model,
+                        &client,
+                        &model_name,
+                        max_iterations,
+                        workspace_name,
+                        &answer_format,
+                        None,
+                        &[],
+                        injection_check,
+                        Some(&events_tx),
+                    )
+                    .await
+                }
+            };
+
+            // Make sure every tool event is delivered before the answer
+            drop(events_tx);
+            let _ = forwarder.await;
+
+            let message = output.map_err(internal).map(|output| AskEvent {
+                event: Some(ask_event::Event::Answer(AskAnswer {
+                    response: output.response,
+                    files_searched: output.files_searched,
+                })),
+            });
+            let _ = stream_tx.send(message);
+        });
+
+        Ok(Response::new(UnboundedReceiverStream::new(stream_rx)))
+    }
+
+    async fn workspace_ops(
+        &self,
+        request: Request<WorkspaceRequest>,
+    ) -> Result<Response<WorkspaceResponse>, Status> {
+        let request = request.into_inner();
+        let op = request.op();
+        let workspace_name = request.workspace.as_deref();
+
+        let ws = Workspace::open(workspace_name)
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+        let store = ws.open_store().map_err(internal)?;
+
+        let mut response = WorkspaceResponse {
+            name: ws.config.name.clone(),
+            root_dir: ws.root_uri().to_string(),
+            ..Default::default()
+        };
+
+        match op {
+            WorkspaceOp::Status => {}
+            WorkspaceOp::Prune => {
+                let missing_paths: Vec<String> = store
+                    .get_all_document_paths()
+                    .map_err(internal)?
+                    .into_iter()
+                    .filter(|path| !crate::archive::local_path(path).exists())
+                    .collect();
+                if !missing_paths.is_empty() {
+                    store.delete_documents(&missing_paths).map_err(internal)?;
+                }
+                response.files_removed = missing_paths;
+            }
+            WorkspaceOp::Optimize => {
+                response.rows_optimized = store.optimize().map_err(internal)? as u64;
+            }
+        }
+
+        response.total_documents = store.get_stats().map_err(internal)?.total_documents as u64;
+        // Upload the changes of a workspace kept in an object store
+        drop(store);
+        crate::workspace::remote::push_replicas()
+            .await
+            .map_err(internal)?;
+        Ok(Response::new(response))
+    }
+}
+
+/// Serve the gRPC API until the process is stopped
+pub async fn serve(
+    addr: SocketAddr,
+    config: Option<String>,
+    embed_preset: Option<EmbedPreset>,
+    offline: bool,
+) -> Result<()> {
+    let service = SemtoolsService::new(config, embed_preset, offline)?;
+    Server::builder()
+        .add_service(SemtoolsServer::new(service))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_event_to_ask_event() {
+        let event = AskEvent::from(AgentEvent::ToolResult {
+            name: "search".to_string(),
+            chunks: 2,
+            lines: 14,
+        });
+        assert_eq!(
+            event.event,
+            Some(ask_event::Event::ToolResult(ToolResult {
+                name: "search".to_string(),
+                chunks: 2,
+                lines: 14,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_default_workspace_op_is_status() {
+        assert_eq!(WorkspaceRequest::default().op(), WorkspaceOp::Status);
+    }
+}
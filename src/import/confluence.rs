@@ -0,0 +1,194 @@
+//! Confluence spaces through the REST API of Confluence Cloud, Server and Data Center: the
+//! current pages of a space are listed with their ancestors, and each page's storage format
+//! (XHTML) is converted to markdown.
+use anyhow::{Result, bail};
+use serde_json::Value;
+
+use super::{ConfluenceConfig, RemotePage, send_json};
+use crate::parse::html::document_to_markdown;
+
+/// Largest page of results requested
+const PAGE_SIZE: usize = 100;
+
+pub struct ConfluenceClient {
+    client: reqwest::Client,
+    base_url: String,
+    email: Option<String>,
+    api_key: String,
+    space: String,
+}
+
+impl ConfluenceClient {
+    pub fn new(config: &ConfluenceConfig, space: &str) -> Result<Self> {
+        let defaults = ConfluenceConfig::default();
+        let base_url = config.base_url.clone().or(defaults.base_url);
+        let api_key = config.api_key.clone().or(defaults.api_key);
+        let (Some(base_url), Some(api_key)) = (base_url, api_key) else {
+            bail!(
+                "Confluence needs `import.confluence.base_url` and `import.confluence.api_key` \
+                 in the config file (or CONFLUENCE_BASE_URL and CONFLUENCE_API_TOKEN)"
+            );
+        };
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("semtools/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            email: config.email.clone().or(defaults.email),
+            api_key,
+            space: space.to_string(),
+        })
+    }
+
+    /// Key of the space being imported
+    pub fn space(&self) -> &str {
+        &self.space
+    }
+
+    /// A request authorized with the API token (Cloud) or personal access token
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        let request = self.client.get(format!("{}/rest/api{path}", self.base_url));
+        match &self.email {
+            Some(email) => request.basic_auth(email, Some(&self.api_key)),
+            None => request.bearer_auth(&self.api_key),
+        }
+    }
+
+    /// Every current page of the space
+    pub async fn list_pages(&self) -> Result<Vec<RemotePage>> {
+        let mut pages = Vec::new();
+        let mut start = 0;
+        loop {
+            let request = self.get("/content").query(&[
+                ("spaceKey", self.space.clone()),
+                ("type", "page".to_string()),
+                ("status", "current".to_string()),
+                ("expand", "ancestors,version".to_string()),
+                ("limit", PAGE_SIZE.to_string()),
+                ("start", start.to_string()),
+            ]);
+            let response = send_json(request).await?;
+            let results = response["results"].as_array().cloned().unwrap_or_default();
+            start += results.len();
+            pages.extend(
+                results
+                    .iter()
+                    .filter_map(|page| remote_page(page, &self.base_url)),
+            );
+            if results.is_empty() || response["_links"]["next"].is_null() {
+                if pages.is_empty() {
+                    bail!("No pages found in Confluence space '{}'", self.space);
+                }
+                return Ok(pages);
+            }
+        }
+    }
+
+    /// The content of page `id` as markdown
+    pub async fn page_markdown(&self, id: &str) -> Result<String> {
+        let request = self
+            .get(&format!("/content/{id}"))
+            .query(&[("expand", "body.storage")]);
+        let response = send_json(request).await?;
+        let storage = response["body"]["storage"]["value"]
+            .as_str()
+            .unwrap_or_default();
+        Ok(document_to_markdown(&prepare_storage(storage)))
+    }
+}
+
+/// A page of the content listing. Its parent is the last of its ancestors.
+fn remote_page(page: &Value, base_url: &str) -> Option<RemotePage> {
+    let id = page["id"].as_str()?.to_string();
+    let parent = page["ancestors"]
+        .as_array()
+        .and_then(|ancestors| ancestors.last())
+        .and_then(|ancestor| ancestor["id"].as_str())
+        .map(str::to_string);
+    let url = match page["_links"]["webui"].as_str() {
+        Some(webui) => format!("{base_url}{webui}"),
+        None => format!("{base_url}/pages/viewpage.action?pageId={id}"),
+    };
+    Some(RemotePage {
+        id,
+        parent,
+        title: page["title"].as_str().unwrap_or_default().to_string(),
+        url,
+        version: page["version"]["number"].to_string(),
+    })
+}
+
+/// Storage format made readable by the HTML converter: the parameters of macros (such as a
+/// code block's language) are dropped, and the plain-text bodies of code macros, which are
+/// CDATA sections, become `<pre>` blocks
+fn prepare_storage(storage: &str) -> String {
+    let mut html = String::with_capacity(storage.len());
+    let mut rest = storage;
+    while let Some(start) = rest.find("<![CDATA[") {
+        html.push_str(&rest[..start]);
+        let data = &rest[start + "<![CDATA[".len()..];
+        let end = data.find("]]>").unwrap_or(data.len());
+        html.push_str(&escape(&data[..end]));
+        rest = data.get(end + "]]>".len()..).unwrap_or_default();
+    }
+    html.push_str(rest);
+
+    let mut prepared = String::with_capacity(html.len());
+    let mut rest = html.as_str();
+    while let Some(start) = rest.find("<ac:parameter") {
+        prepared.push_str(&rest[..start]);
+        rest = match rest[start..].find("</ac:parameter>") {
+            Some(end) => &rest[start + end + "</ac:parameter>".len()..],
+            None => "",
+        };
+    }
+    prepared.push_str(rest);
+    prepared
+        .replace("<ac:plain-text-body>", "<pre>")
+        .replace("</ac:plain-text-body>", "</pre>")
+}
+
+/// Text escaped for HTML, so it reads back as the same text
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_remote_page_and_storage() {
+        let page = json!({
+            "id": "42",
+            "title": "Deploys",
+            "ancestors": [{"id": "1"}, {"id": "7"}],
+            "version": {"number": 12},
+            "_links": {"webui": "/spaces/ENG/pages/42/Deploys"},
+        });
+        assert_eq!(
+            remote_page(&page, "https://acme.atlassian.net/wiki"),
+            Some(RemotePage {
+                id: "42".to_string(),
+                parent: Some("7".to_string()),
+                title: "Deploys".to_string(),
+                url: "https://acme.atlassian.net/wiki/spaces/ENG/pages/42/Deploys".to_string(),
+                version: "12".to_string(),
+            })
+        );
+
+        let storage = "<p>Run:</p><ac:structured-macro ac:name=\"code\">\
+                       <ac:parameter ac:name=\"language\">bash</ac:parameter>\
+                       <ac:plain-text-body><![CDATA[make deploy && echo <done>]]></ac:plain-text-body>\
+                       </ac:structured-macro>";
+        assert_eq!(
+            prepare_storage(storage),
+            "<p>Run:</p><ac:structured-macro ac:name=\"code\">\
+             <pre>make deploy &amp;&amp; echo &lt;done&gt;</pre></ac:structured-macro>"
+        );
+    }
+}
@@ -0,0 +1,414 @@
+//! Mailboxes over IMAP (Gmail included, at imap.gmail.com) saved as `.eml` files: each message
+//! is `<mailbox>/<uid>.eml` in the account's managed directory. The manifest records each
+//! mailbox's UIDVALIDITY and the highest UID fetched, so importing again only fetches the
+//! messages that arrived since; messages deleted from the mailbox are deleted from the
+//! directory, and a new UIDVALIDITY (the server renumbered the mailbox) starts it over.
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, BufReader};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+use super::{ImportSummary, MANIFEST_FILE, file_name};
+
+const GMAIL_HOST: &str = "imap.gmail.com";
+
+/// IMAP over TLS
+const IMAPS_PORT: u16 = 993;
+
+/// Messages fetched per command
+const FETCH_BATCH: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MailConfig {
+    /// IMAP server (defaults to IMAP_HOST, or imap.gmail.com for Gmail addresses)
+    pub host: Option<String>,
+    /// Port of IMAP over TLS (993 by default)
+    pub port: Option<u16>,
+    /// Account to log in as (defaults to IMAP_USERNAME)
+    pub username: Option<String>,
+    /// Password of the account, or an app password for Gmail (defaults to IMAP_PASSWORD)
+    pub password: Option<String>,
+    /// OAuth access token sent with XOAUTH2 instead of a password, e.g. one with the
+    /// `https://mail.google.com/` scope for Gmail (defaults to IMAP_ACCESS_TOKEN)
+    pub access_token: Option<String>,
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            host: std::env::var("IMAP_HOST").ok(),
+            port: None,
+            username: std::env::var("IMAP_USERNAME").ok(),
+            password: std::env::var("IMAP_PASSWORD").ok(),
+            access_token: std::env::var("IMAP_ACCESS_TOKEN").ok(),
+        }
+    }
+}
+
+enum Credentials {
+    Password(String),
+    AccessToken(String),
+}
+
+/// An IMAP account to import mailboxes from
+pub struct MailAccount {
+    host: String,
+    port: u16,
+    username: String,
+    credentials: Credentials,
+}
+
+/// What is remembered about an imported mailbox
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct MailboxState {
+    uid_validity: u32,
+    /// Messages up to this UID were fetched
+    last_uid: u32,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct MailManifest {
+    mailboxes: BTreeMap<String, MailboxState>,
+}
+
+impl MailAccount {
+    pub fn new(config: &MailConfig) -> Result<Self> {
+        let defaults = MailConfig::default();
+        let Some(username) = config.username.clone().or(defaults.username) else {
+            bail!(
+                "No IMAP account: set `import.mail.username` in the config file or IMAP_USERNAME"
+            );
+        };
+        let host = config.host.clone().or(defaults.host).or_else(|| {
+            let domain = username.rsplit_once('@')?.1.to_lowercase();
+            matches!(domain.as_str(), "gmail.com" | "googlemail.com").then(|| GMAIL_HOST.into())
+        });
+        let Some(host) = host else {
+            bail!("No IMAP server: set `import.mail.host` in the config file or IMAP_HOST");
+        };
+        let access_token = config.access_token.clone().or(defaults.access_token);
+        let credentials = match (config.password.clone().or(defaults.password), access_token) {
+            (_, Some(token)) => Credentials::AccessToken(token),
+            (Some(password), None) => Credentials::Password(password),
+            (None, None) => bail!(
+                "No IMAP credentials: set `import.mail.password` or `import.mail.access_token` \
+                 in the config file (or IMAP_PASSWORD or IMAP_ACCESS_TOKEN)"
+            ),
+        };
+        Ok(Self {
+            host,
+            port: config.port.unwrap_or(IMAPS_PORT),
+            username,
+            credentials,
+        })
+    }
+
+    /// The managed directory of this account: `imports/mail-<username>`
+    pub fn dir(&self) -> Result<PathBuf> {
+        Ok(crate::paths::data_path("imports")?.join(format!("mail-{}", file_name(&self.username))))
+    }
+
+    /// Fetch the new messages of `mailboxes` into `dir`, and delete the files of messages that
+    /// left them
+    pub async fn import(&self, dir: &Path, mailboxes: &[String]) -> Result<ImportSummary> {
+        fs::create_dir_all(dir)?;
+        let manifest_path = dir.join(MANIFEST_FILE);
+        let mut manifest: MailManifest = match fs::read_to_string(&manifest_path) {
+            Ok(json) => serde_json::from_str(&json)
+                .with_context(|| format!("Invalid import manifest {}", manifest_path.display()))?,
+            Err(_) => MailManifest::default(),
+        };
+
+        let mut session = Session::connect(&self.host, self.port).await?;
+        session.login(&self.username, &self.credentials).await?;
+
+        let mut summary = ImportSummary::default();
+        for mailbox in mailboxes {
+            let mailbox_dir = dir.join(file_name(mailbox));
+            fs::create_dir_all(&mailbox_dir)?;
+            let uid_validity = session.examine(mailbox).await?;
+            let mut state = manifest.mailboxes.remove(mailbox).unwrap_or_default();
+            if state.uid_validity != uid_validity {
+                // UIDs of an earlier UIDVALIDITY name other messages now
+                state = MailboxState {
+                    uid_validity,
+                    last_uid: 0,
+                };
+            }
+
+            let uids = session.search_all().await?;
+            let current: HashSet<u32> = uids.iter().copied().collect();
+            for (uid, path) in saved_messages(&mailbox_dir)? {
+                if !current.contains(&uid) || uid > state.last_uid {
+                    fs::remove_file(&path)?;
+                    summary.removed.push(path.to_string_lossy().to_string());
+                }
+            }
+
+            let new: Vec<u32> = uids
+                .into_iter()
+                .filter(|uid| *uid > state.last_uid)
+                .collect();
+            let mut fetched = 0;
+            for batch in new.chunks(FETCH_BATCH) {
+                for (uid, message) in session.fetch(batch).await? {
+                    fs::write(mailbox_dir.join(format!("{uid}.eml")), message)?;
+                    fetched += 1;
+                }
+                state.last_uid = batch.iter().copied().max().unwrap_or(state.last_uid);
+                eprintln!(
+                    "Fetched {fetched} of {} new messages in {mailbox}",
+                    new.len()
+                );
+                // Saved after every batch, so an interrupted import resumes where it stopped
+                manifest.mailboxes.insert(mailbox.clone(), state.clone());
+                fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+            }
+            summary.pages_updated += fetched;
+            manifest.mailboxes.insert(mailbox.clone(), state);
+            summary.files.extend(
+                saved_messages(&mailbox_dir)?
+                    .into_iter()
+                    .map(|(_, path)| path.to_string_lossy().to_string()),
+            );
+        }
+        session.logout().await;
+        fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+        Ok(summary)
+    }
+}
+
+/// The `<uid>.eml` files of a mailbox's directory
+fn saved_messages(dir: &Path) -> Result<Vec<(u32, PathBuf)>> {
+    let mut messages = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "eml")
+            && let Some(uid) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str()?.parse().ok())
+        {
+            messages.push((uid, path));
+        }
+    }
+    messages.sort();
+    Ok(messages)
+}
+
+/// A line of a server response, with the literals (`{size}` then that many bytes) it carries
+#[derive(Debug, Default)]
+struct Line {
+    text: String,
+    literals: Vec<Vec<u8>>,
+}
+
+/// Read a response line and the literals it carries
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Line> {
+    let mut line = Line::default();
+    loop {
+        let mut raw = Vec::new();
+        if reader.read_until(b'\n', &mut raw).await? == 0 {
+            bail!("The IMAP server closed the connection");
+        }
+        let text = String::from_utf8_lossy(&raw);
+        let text = text.trim_end_matches(['\r', '\n']);
+        line.text.push_str(text);
+        let size = text
+            .strip_suffix('}')
+            .and_then(|text| text.rsplit_once('{'))
+            .and_then(|(_, size)| size.parse::<usize>().ok());
+        let Some(size) = size else {
+            return Ok(line);
+        };
+        let mut literal = vec![0; size];
+        reader.read_exact(&mut literal).await?;
+        line.literals.push(literal);
+    }
+}
+
+/// `text` as an IMAP quoted string
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// The UID of a `FETCH` response line
+fn fetched_uid(line: &Line) -> Option<u32> {
+    let (_, rest) = line.text.split_once("UID ")?;
+    rest.split(|c: char| !c.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// The numbers of a `SEARCH` response line
+fn search_results(line: &Line) -> Option<Vec<u32>> {
+    let rest = line.text.strip_prefix("* SEARCH")?;
+    Some(
+        rest.split_whitespace()
+            .filter_map(|uid| uid.parse().ok())
+            .collect(),
+    )
+}
+
+/// A connection to an IMAP server, sending one command at a time
+struct Session<S> {
+    stream: BufReader<S>,
+    tag: usize,
+}
+
+impl Session<tokio_rustls::client::TlsStream<TcpStream>> {
+    async fn connect(host: &str, port: u16) -> Result<Self> {
+        let mut roots = RootCertStore::empty();
+        roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+        let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+        let config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let tcp = TcpStream::connect((host, port))
+            .await
+            .with_context(|| format!("Cannot connect to {host}:{port}"))?;
+        let server_name = ServerName::try_from(host.to_string())?;
+        let tls = TlsConnector::from(Arc::new(config))
+            .connect(server_name, tcp)
+            .await
+            .with_context(|| format!("TLS handshake with {host} failed"))?;
+
+        let mut session = Self {
+            stream: BufReader::new(tls),
+            tag: 0,
+        };
+        let greeting = read_line(&mut session.stream).await?;
+        if !greeting.text.starts_with("* OK") {
+            bail!("Unexpected IMAP greeting from {host}: {}", greeting.text);
+        }
+        Ok(session)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Session<S> {
+    /// Send `command` and return the untagged lines of its response once it completes
+    async fn command(&mut self, command: &str) -> Result<Vec<Line>> {
+        self.tag += 1;
+        let tag = format!("a{}", self.tag);
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(format!("{tag} {command}\r\n").as_bytes())
+            .await?;
+        stream.flush().await?;
+
+        let mut lines = Vec::new();
+        loop {
+            let line = read_line(&mut self.stream).await?;
+            if line.text.starts_with('+') {
+                // A failed AUTHENTICATE asks for a reply before it reports the error
+                let stream = self.stream.get_mut();
+                stream.write_all(b"\r\n").await?;
+                stream.flush().await?;
+                continue;
+            }
+            let Some(status) = line.text.strip_prefix(&tag).map(str::trim_start) else {
+                lines.push(line);
+                continue;
+            };
+            if status.starts_with("OK") {
+                return Ok(lines);
+            }
+            // Only the command's name: its arguments may hold credentials
+            let name = command.split(' ').next().unwrap_or_default();
+            bail!("IMAP {name} failed: {status}");
+        }
+    }
+
+    async fn login(&mut self, username: &str, credentials: &Credentials) -> Result<()> {
+        let command = match credentials {
+            Credentials::Password(password) => {
+                format!("LOGIN {} {}", quote(username), quote(password))
+            }
+            Credentials::AccessToken(token) => {
+                let response = format!("user={username}\x01auth=Bearer {token}\x01\x01");
+                let response = base64::engine::general_purpose::STANDARD.encode(response);
+                format!("AUTHENTICATE XOAUTH2 {response}")
+            }
+        };
+        self.command(&command).await?;
+        Ok(())
+    }
+
+    /// Open `mailbox` read-only and return its UIDVALIDITY
+    async fn examine(&mut self, mailbox: &str) -> Result<u32> {
+        let lines = self
+            .command(&format!("EXAMINE {}", quote(mailbox)))
+            .await
+            .with_context(|| format!("Cannot open mailbox '{mailbox}'"))?;
+        lines
+            .iter()
+            .find_map(|line| {
+                let (_, rest) = line.text.split_once("[UIDVALIDITY ")?;
+                rest.split(']').next()?.trim().parse().ok()
+            })
+            .with_context(|| format!("No UIDVALIDITY for mailbox '{mailbox}'"))
+    }
+
+    /// The UIDs of every message of the open mailbox
+    async fn search_all(&mut self) -> Result<Vec<u32>> {
+        let lines = self.command("UID SEARCH ALL").await?;
+        let mut uids: Vec<u32> = lines.iter().filter_map(search_results).flatten().collect();
+        uids.sort_unstable();
+        Ok(uids)
+    }
+
+    /// The raw messages of `uids`, without marking them as read
+    async fn fetch(&mut self, uids: &[u32]) -> Result<Vec<(u32, Vec<u8>)>> {
+        let set: Vec<String> = uids.iter().map(u32::to_string).collect();
+        let lines = self
+            .command(&format!("UID FETCH {} (UID BODY.PEEK[])", set.join(",")))
+            .await?;
+        Ok(lines
+            .into_iter()
+            .filter_map(|mut line| {
+                let uid = fetched_uid(&line)?;
+                Some((uid, line.literals.pop()?))
+            })
+            .collect())
+    }
+
+    async fn logout(&mut self) {
+        let _ = self.command("LOGOUT").await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_read_responses() {
+        let response: &[u8] = b"* 1 FETCH (UID 42 BODY[] {19}\r\nSubject: Hi\r\n\r\nYo\r\n)\r\n\
+                                * SEARCH 7 42 108\r\n\
+                                a3 OK done\r\n";
+        let mut reader = response;
+        let fetch = read_line(&mut reader).await.unwrap();
+        assert_eq!(fetched_uid(&fetch), Some(42));
+        assert_eq!(fetch.literals, [b"Subject: Hi\r\n\r\nYo\r\n".to_vec()]);
+        assert_eq!(fetch.text, "* 1 FETCH (UID 42 BODY[] {19})");
+
+        let search = read_line(&mut reader).await.unwrap();
+        assert_eq!(search_results(&search), Some(vec![7, 42, 108]));
+        assert_eq!(search_results(&fetch), None);
+        assert_eq!(read_line(&mut reader).await.unwrap().text, "a3 OK done");
+        assert!(read_line(&mut reader).await.is_err());
+
+        assert_eq!(quote(r#"pa"ss\word"#), r#""pa\"ss\\word""#);
+    }
+}
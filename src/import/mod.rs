@@ -0,0 +1,450 @@
+//! Importers that copy pages from Notion and Confluence into markdown files, and messages from
+//! IMAP mailboxes into `.eml` files, so they can be indexed into a workspace like any other
+//! document.
+//!
+//! Each source gets its own managed directory under `$XDG_DATA_HOME/semtools/imports`, where
+//! pages are laid out like their hierarchy (`Handbook/Onboarding/First week.md`). A manifest in
+//! that directory records the remote ID, path and version of every page, so importing again
+//! only fetches the pages that changed, moves the ones that were renamed or moved, and deletes
+//! the ones that are gone.
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub mod confluence;
+pub mod mail;
+pub mod notion;
+
+use confluence::ConfluenceClient;
+use mail::MailConfig;
+use notion::NotionClient;
+
+/// The manifest of a managed directory
+const MANIFEST_FILE: &str = ".import.json";
+
+/// Longest file name given to a page, in characters (before the `.md`)
+const MAX_NAME_CHARS: usize = 80;
+
+/// Attempts at a request the API answers with 429 (rate limited)
+const MAX_ATTEMPTS: usize = 5;
+
+/// Credentials of the importers, in the config file's `import` section
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ImportConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notion: Option<NotionConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confluence: Option<ConfluenceConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mail: Option<MailConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NotionConfig {
+    /// Secret of a Notion integration (defaults to NOTION_API_KEY). Only the pages shared with
+    /// the integration are imported.
+    pub api_key: Option<String>,
+}
+
+impl Default for NotionConfig {
+    fn default() -> Self {
+        Self {
+            api_key: std::env::var("NOTION_API_KEY").ok(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfluenceConfig {
+    /// The wiki's address, e.g. "https://example.atlassian.net/wiki" for Confluence Cloud
+    /// (defaults to CONFLUENCE_BASE_URL)
+    pub base_url: Option<String>,
+    /// Account of the API token on Confluence Cloud; leave it out to send `api_key` as a
+    /// personal access token (Server and Data Center)
+    pub email: Option<String>,
+    /// API token or personal access token (defaults to CONFLUENCE_API_TOKEN)
+    pub api_key: Option<String>,
+}
+
+impl Default for ConfluenceConfig {
+    fn default() -> Self {
+        Self {
+            base_url: std::env::var("CONFLUENCE_BASE_URL").ok(),
+            email: std::env::var("CONFLUENCE_EMAIL").ok(),
+            api_key: std::env::var("CONFLUENCE_API_TOKEN").ok(),
+        }
+    }
+}
+
+/// A page as listed by its source, before its content is fetched
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemotePage {
+    pub id: String,
+    /// The page this one is nested under, if it is a page that was listed too
+    pub parent: Option<String>,
+    pub title: String,
+    /// Where the page is read in a browser
+    pub url: String,
+    /// Changes whenever the page is edited (a version number or edit time)
+    pub version: String,
+}
+
+/// What is remembered about an imported page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct ManifestEntry {
+    /// Path of the markdown file, relative to the managed directory
+    path: PathBuf,
+    title: String,
+    version: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    pages: BTreeMap<String, ManifestEntry>,
+}
+
+/// A source of pages
+pub enum Connector {
+    Notion(NotionClient),
+    Confluence(ConfluenceClient),
+}
+
+impl Connector {
+    /// Name of the source, used in tags and messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            Connector::Notion(_) => "notion",
+            Connector::Confluence(_) => "confluence",
+        }
+    }
+
+    /// The managed directory of this source: `imports/notion`, or `imports/confluence-<space>`
+    pub fn dir(&self) -> Result<PathBuf> {
+        let name = match self {
+            Connector::Notion(_) => "notion".to_string(),
+            Connector::Confluence(client) => format!("confluence-{}", file_name(client.space())),
+        };
+        Ok(crate::paths::data_path("imports")?.join(name))
+    }
+
+    async fn list_pages(&self) -> Result<Vec<RemotePage>> {
+        match self {
+            Connector::Notion(client) => client.list_pages().await,
+            Connector::Confluence(client) => client.list_pages().await,
+        }
+    }
+
+    async fn page_markdown(&self, page: &RemotePage) -> Result<String> {
+        match self {
+            Connector::Notion(client) => client.page_markdown(&page.id).await,
+            Connector::Confluence(client) => client.page_markdown(&page.id).await,
+        }
+    }
+}
+
+/// The outcome of an import
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    /// Every imported page's file
+    pub files: Vec<String>,
+    /// Pages (or messages) fetched because they are new or were edited
+    pub pages_updated: usize,
+    /// Files of pages (or messages) that were moved, renamed or deleted at the source, whose
+    /// documents have to leave the workspace
+    pub removed: Vec<String>,
+}
+
+/// Import the pages of `connector` into `dir`: all of them, or only the pages in the subtrees
+/// of `roots` (page IDs) when there are any
+pub async fn import_pages(
+    connector: &Connector,
+    dir: &Path,
+    roots: &[String],
+) -> Result<ImportSummary> {
+    let mut pages = connector.list_pages().await?;
+    // Pages left out by `roots` are kept; only those the source no longer lists are deleted
+    let listed: HashSet<String> = pages.iter().map(|page| page.id.clone()).collect();
+    // Paths follow the whole hierarchy, so a subtree lands where a full import puts it
+    let paths = page_paths(&pages);
+    if !roots.is_empty() {
+        pages = within_roots(pages, roots);
+        if pages.is_empty() {
+            bail!(
+                "None of the pages {} were found in {}",
+                roots.join(", "),
+                connector.name()
+            );
+        }
+    }
+
+    fs::create_dir_all(dir)?;
+    let manifest_path = dir.join(MANIFEST_FILE);
+    let mut manifest: Manifest = match fs::read_to_string(&manifest_path) {
+        Ok(json) => serde_json::from_str(&json)
+            .with_context(|| format!("Invalid import manifest {}", manifest_path.display()))?,
+        Err(_) => Manifest::default(),
+    };
+
+    let mut summary = ImportSummary::default();
+    let gone: Vec<String> = manifest
+        .pages
+        .keys()
+        .filter(|id| !listed.contains(*id))
+        .cloned()
+        .collect();
+    for id in gone {
+        if let Some(entry) = manifest.pages.remove(&id) {
+            remove_page_file(dir, &entry.path, &mut summary.removed);
+        }
+    }
+
+    // Moved pages leave their old files first, which another page may now be written to
+    for page in &pages {
+        if let Some(previous) = manifest.pages.get(&page.id)
+            && previous.path != paths[&page.id]
+        {
+            remove_page_file(dir, &previous.path, &mut summary.removed);
+        }
+    }
+
+    for page in &pages {
+        let path = &paths[&page.id];
+        let file = dir.join(path);
+        let unchanged = manifest
+            .pages
+            .get(&page.id)
+            .is_some_and(|previous| previous.version == page.version && &previous.path == path)
+            && file.is_file();
+
+        if !unchanged {
+            let markdown = match connector.page_markdown(page).await {
+                Ok(markdown) => markdown,
+                Err(e) => {
+                    eprintln!("Warning: Cannot import '{}': {e:#}", page.title);
+                    continue;
+                }
+            };
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&file, page_document(page, &markdown))?;
+            summary.pages_updated += 1;
+            manifest.pages.insert(
+                page.id.clone(),
+                ManifestEntry {
+                    path: path.clone(),
+                    title: page.title.clone(),
+                    version: page.version.clone(),
+                },
+            );
+            // Saved after every page, so an interrupted import resumes where it stopped
+            fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+        }
+        summary.files.push(file.to_string_lossy().to_string());
+    }
+    fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    Ok(summary)
+}
+
+/// The markdown file of a page: frontmatter naming its title and URL (the `title:` and
+/// `source:` search filters), then the page under its title
+fn page_document(page: &RemotePage, markdown: &str) -> String {
+    let title = page.title.replace('"', "'");
+    format!(
+        "---\ntitle: \"{title}\"\nsource: {}\n---\n\n# {}\n\n{}\n",
+        page.url,
+        page.title,
+        markdown.trim()
+    )
+}
+
+/// Delete an imported file, and the directories it leaves empty
+fn remove_page_file(dir: &Path, path: &Path, removed: &mut Vec<String>) {
+    let file = dir.join(path);
+    if fs::remove_file(&file).is_ok() {
+        removed.push(file.to_string_lossy().to_string());
+    }
+    for parent in file.ancestors().skip(1) {
+        if parent == dir || fs::remove_dir(parent).is_err() {
+            break;
+        }
+    }
+}
+
+/// The pages of the subtrees rooted at `roots`
+fn within_roots(pages: Vec<RemotePage>, roots: &[String]) -> Vec<RemotePage> {
+    let parents: HashMap<&str, &str> = pages
+        .iter()
+        .filter_map(|page| Some((page.id.as_str(), page.parent.as_deref()?)))
+        .collect();
+    let in_subtree = |id: &str| {
+        let mut current = Some(id);
+        let mut seen = HashSet::new();
+        while let Some(id) = current {
+            if roots.iter().any(|root| root == id) {
+                return true;
+            }
+            if !seen.insert(id) {
+                return false;
+            }
+            current = parents.get(id).copied();
+        }
+        false
+    };
+    let keep: HashSet<String> = pages
+        .iter()
+        .filter(|page| in_subtree(&page.id))
+        .map(|page| page.id.clone())
+        .collect();
+    pages
+        .into_iter()
+        .filter(|page| keep.contains(&page.id))
+        .collect()
+}
+
+/// The file of each page, relative to the managed directory: its title under the directory of
+/// its parent page, e.g. `Handbook/Onboarding.md` for a page "Onboarding" in "Handbook".
+/// Pages whose parent was not listed are at the top. Siblings with the same title are told
+/// apart by their IDs.
+fn page_paths(pages: &[RemotePage]) -> HashMap<String, PathBuf> {
+    let by_id: HashMap<&str, &RemotePage> =
+        pages.iter().map(|page| (page.id.as_str(), page)).collect();
+
+    // The name of each page within its parent's directory
+    let mut names: HashMap<&str, String> = HashMap::new();
+    let mut siblings: HashMap<(Option<&str>, String), Vec<&str>> = HashMap::new();
+    for page in pages {
+        let parent = page
+            .parent
+            .as_deref()
+            .filter(|parent| by_id.contains_key(parent));
+        let name = file_name(&page.title);
+        siblings
+            .entry((parent, name.to_lowercase()))
+            .or_default()
+            .push(&page.id);
+        names.insert(&page.id, name);
+    }
+    for ids in siblings.values().filter(|ids| ids.len() > 1) {
+        for id in ids {
+            let short_id: String = id.chars().filter(|c| *c != '-').take(8).collect();
+            if let Some(name) = names.get_mut(id) {
+                *name = format!("{name} ({short_id})");
+            }
+        }
+    }
+
+    pages
+        .iter()
+        .map(|page| {
+            let mut components = vec![names[page.id.as_str()].clone()];
+            let mut seen = HashSet::from([page.id.as_str()]);
+            let mut parent = page.parent.as_deref();
+            while let Some(id) = parent.filter(|id| by_id.contains_key(id) && seen.insert(id)) {
+                components.push(names[id].clone());
+                parent = by_id[id].parent.as_deref();
+            }
+            components.reverse();
+            let mut path: PathBuf = components.iter().collect();
+            path.set_extension("md");
+            (page.id.clone(), path)
+        })
+        .collect()
+}
+
+/// A title made safe to use as a file name on any platform
+fn file_name(title: &str) -> String {
+    let name: String = title
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|') {
+                '-'
+            } else {
+                c
+            }
+        })
+        .take(MAX_NAME_CHARS)
+        .collect();
+    let name = name.trim().trim_matches('.').trim();
+    if name.is_empty() {
+        "Untitled".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Send `request` and read its JSON response, waiting out rate limits (429) as the API's
+/// `Retry-After` header asks
+async fn send_json(request: reqwest::RequestBuilder) -> Result<Value> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let response = request
+            .try_clone()
+            .context("Cannot retry a streaming request")?
+            .send()
+            .await?;
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS && attempt < MAX_ATTEMPTS {
+            let wait = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+                .unwrap_or(attempt as u64);
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+            continue;
+        }
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            bail!("{status}: {}", body.trim());
+        }
+        return Ok(response.json().await?);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(id: &str, parent: Option<&str>, title: &str) -> RemotePage {
+        RemotePage {
+            id: id.to_string(),
+            parent: parent.map(str::to_string),
+            title: title.to_string(),
+            url: format!("https://wiki.example.com/{id}"),
+            version: "1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_page_paths() {
+        let pages = vec![
+            page("a1", None, "Handbook"),
+            page("b2", Some("a1"), "Onboarding: first week"),
+            page("c3", Some("b2"), "Laptop"),
+            page("d4", Some("a1"), "FAQ"),
+            page("e5", Some("a1"), "faq"),
+            page("f6", Some("unlisted"), ""),
+        ];
+        let paths = page_paths(&pages);
+        assert_eq!(paths["a1"], PathBuf::from("Handbook.md"));
+        assert_eq!(
+            paths["c3"],
+            PathBuf::from("Handbook/Onboarding- first week/Laptop.md")
+        );
+        assert_eq!(paths["d4"], PathBuf::from("Handbook/FAQ (d4).md"));
+        assert_eq!(paths["e5"], PathBuf::from("Handbook/faq (e5).md"));
+        assert_eq!(paths["f6"], PathBuf::from("Untitled.md"));
+
+        let subtree = within_roots(pages, &["b2".to_string()]);
+        let ids: Vec<&str> = subtree.iter().map(|page| page.id.as_str()).collect();
+        assert_eq!(ids, ["b2", "c3"]);
+    }
+}
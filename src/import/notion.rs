@@ -0,0 +1,384 @@
+//! Notion pages through the public API: the pages shared with an integration are listed with
+//! `search`, and each page's blocks are rendered as markdown. Pages nested in a page keep that
+//! parent; pages of databases or the workspace root are at the top.
+use anyhow::{Result, bail};
+use serde_json::{Value, json};
+
+use super::{NotionConfig, RemotePage, send_json};
+
+const API_URL: &str = "https://api.notion.com/v1";
+const NOTION_VERSION: &str = "2022-06-28";
+
+/// Largest page of results the API returns
+const PAGE_SIZE: usize = 100;
+
+pub struct NotionClient {
+    client: reqwest::Client,
+    api_key: String,
+}
+
+/// A block with its children, which the API returns separately
+#[derive(Debug)]
+pub struct Block {
+    pub data: Value,
+    pub children: Vec<Block>,
+}
+
+/// The ID of a page given as an ID (with or without dashes) or a page URL such as
+/// `https://www.notion.so/acme/Onboarding-0123456789abcdef0123456789abcdef`, without dashes
+pub fn page_id(input: &str) -> Option<String> {
+    let path = input.split(['?', '#']).next()?;
+    let last = path.trim_end_matches('/').rsplit('/').next()?;
+    let hex: String = last.chars().filter(|c| *c != '-').collect();
+    let id = hex.get(hex.len().checked_sub(32)?..)?;
+    id.chars()
+        .all(|c| c.is_ascii_hexdigit())
+        .then(|| id.to_lowercase())
+}
+
+impl NotionClient {
+    pub fn new(config: &NotionConfig) -> Result<Self> {
+        let api_key = config
+            .api_key
+            .clone()
+            .or_else(|| std::env::var("NOTION_API_KEY").ok());
+        let Some(api_key) = api_key else {
+            bail!(
+                "No Notion API key: set `import.notion.api_key` in the config file or \
+                 NOTION_API_KEY, and share the pages to import with the integration"
+            );
+        };
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("semtools/", env!("CARGO_PKG_VERSION")))
+            .build()?;
+        Ok(Self { client, api_key })
+    }
+
+    fn get(&self, path: &str) -> reqwest::RequestBuilder {
+        self.client
+            .get(format!("{API_URL}{path}"))
+            .bearer_auth(&self.api_key)
+            .header("Notion-Version", NOTION_VERSION)
+    }
+
+    /// Every page shared with the integration
+    pub async fn list_pages(&self) -> Result<Vec<RemotePage>> {
+        let mut pages = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut body = json!({
+                "filter": {"property": "object", "value": "page"},
+                "page_size": PAGE_SIZE,
+            });
+            if let Some(cursor) = &cursor {
+                body["start_cursor"] = json!(cursor);
+            }
+            let request = self
+                .client
+                .post(format!("{API_URL}/search"))
+                .bearer_auth(&self.api_key)
+                .header("Notion-Version", NOTION_VERSION)
+                .json(&body);
+            let response = send_json(request).await?;
+            if let Some(results) = response["results"].as_array() {
+                pages.extend(results.iter().filter_map(remote_page));
+            }
+            cursor = response["next_cursor"].as_str().map(str::to_string);
+            if response["has_more"] != json!(true) || cursor.is_none() {
+                return Ok(pages);
+            }
+        }
+    }
+
+    /// The content of page `id` as markdown
+    pub async fn page_markdown(&self, id: &str) -> Result<String> {
+        let blocks = self.children(id).await?;
+        Ok(render_blocks(&blocks))
+    }
+
+    /// The blocks under `id`, each with its own children. Pages and databases nested in the
+    /// page are left out: they are imported on their own.
+    async fn children(&self, id: &str) -> Result<Vec<Block>> {
+        let mut blocks = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut request = self
+                .get(&format!("/blocks/{id}/children"))
+                .query(&[("page_size", PAGE_SIZE.to_string())]);
+            if let Some(cursor) = &cursor {
+                request = request.query(&[("start_cursor", cursor)]);
+            }
+            let response = send_json(request).await?;
+            for data in response["results"].as_array().into_iter().flatten() {
+                let kind = data["type"].as_str().unwrap_or_default();
+                if matches!(kind, "child_page" | "child_database") {
+                    continue;
+                }
+                let children = match (data["has_children"].as_bool(), data["id"].as_str()) {
+                    (Some(true), Some(child)) => Box::pin(self.children(child)).await?,
+                    _ => Vec::new(),
+                };
+                blocks.push(Block {
+                    data: data.clone(),
+                    children,
+                });
+            }
+            cursor = response["next_cursor"].as_str().map(str::to_string);
+            if response["has_more"] != json!(true) || cursor.is_none() {
+                return Ok(blocks);
+            }
+        }
+    }
+}
+
+/// A page of the `search` results; archived pages are skipped
+fn remote_page(page: &Value) -> Option<RemotePage> {
+    if page["archived"] == json!(true) || page["in_trash"] == json!(true) {
+        return None;
+    }
+    let id = page_id(page["id"].as_str()?)?;
+    let title = page["properties"]
+        .as_object()
+        .into_iter()
+        .flat_map(|properties| properties.values())
+        .find(|property| property["type"] == "title")
+        .map(|property| plain_text(&property["title"]))
+        .unwrap_or_default();
+    let parent = match page["parent"]["type"].as_str() {
+        Some("page_id") => page["parent"]["page_id"].as_str().and_then(page_id),
+        _ => None,
+    };
+    Some(RemotePage {
+        id,
+        parent,
+        title,
+        url: page["url"].as_str().unwrap_or_default().to_string(),
+        version: page["last_edited_time"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+fn plain_text(rich_text: &Value) -> String {
+    rich_text
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|text| text["plain_text"].as_str())
+        .collect()
+}
+
+/// Rich text as markdown: bold, italic, strikethrough, inline code and links
+fn rich_text(rich_text: &Value) -> String {
+    let mut markdown = String::new();
+    for text in rich_text.as_array().into_iter().flatten() {
+        let Some(plain) = text["plain_text"].as_str() else {
+            continue;
+        };
+        let annotations = &text["annotations"];
+        let mut span = plain.to_string();
+        if !span.trim().is_empty() {
+            if annotations["code"] == json!(true) {
+                span = format!("`{span}`");
+            }
+            for (annotation, marker) in [("bold", "**"), ("italic", "*"), ("strikethrough", "~~")] {
+                if annotations[annotation] == json!(true) {
+                    span = format!("{marker}{span}{marker}");
+                }
+            }
+            if let Some(href) = text["href"].as_str() {
+                span = format!("[{span}]({href})");
+            }
+        }
+        markdown.push_str(&span);
+    }
+    markdown
+}
+
+/// Blocks as markdown: list items follow one another, other blocks are separated by a blank
+/// line, and the children of a block are indented under it
+pub fn render_blocks(blocks: &[Block]) -> String {
+    let mut markdown = String::new();
+    let mut previous_item = false;
+    let mut number = 0;
+    for block in blocks {
+        let kind = block.data["type"].as_str().unwrap_or_default();
+        number = if kind == "numbered_list_item" {
+            number + 1
+        } else {
+            0
+        };
+        let Some(rendered) = render_block(block, kind, number) else {
+            continue;
+        };
+        let item = matches!(
+            kind,
+            "bulleted_list_item" | "numbered_list_item" | "to_do" | "toggle"
+        );
+        if !markdown.is_empty() {
+            markdown.push_str(if item && previous_item { "\n" } else { "\n\n" });
+        }
+        markdown.push_str(&rendered);
+        previous_item = item;
+    }
+    markdown
+}
+
+fn render_block(block: &Block, kind: &str, number: usize) -> Option<String> {
+    let data = &block.data[kind];
+    let text = rich_text(&data["rich_text"]);
+    let children = render_blocks(&block.children);
+    let indented = || {
+        children
+            .lines()
+            .map(|line| {
+                if line.is_empty() {
+                    String::new()
+                } else {
+                    format!("    {line}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let with_children = |head: String| {
+        if children.is_empty() {
+            head
+        } else {
+            format!("{head}\n{}", indented())
+        }
+    };
+    let quoted = |text: String| {
+        text.lines()
+            .map(|line| format!("> {line}").trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let rendered = match kind {
+        "paragraph" => with_children(text),
+        "heading_1" => format!("## {text}"),
+        "heading_2" => format!("### {text}"),
+        "heading_3" => format!("#### {text}"),
+        "bulleted_list_item" | "toggle" => with_children(format!("- {text}")),
+        "numbered_list_item" => with_children(format!("{number}. {text}")),
+        "to_do" => {
+            let checked = if data["checked"] == json!(true) {
+                "x"
+            } else {
+                " "
+            };
+            with_children(format!("- [{checked}] {text}"))
+        }
+        "quote" => quoted([text, children].join("\n").trim().to_string()),
+        "callout" => {
+            let icon = data["icon"]["emoji"].as_str().unwrap_or_default();
+            quoted(
+                [format!("{icon} {text}").trim().to_string(), children]
+                    .join("\n")
+                    .trim()
+                    .to_string(),
+            )
+        }
+        "code" => {
+            let language = data["language"].as_str().unwrap_or_default();
+            let language = if language == "plain text" {
+                ""
+            } else {
+                language
+            };
+            format!("```{language}\n{}\n```", plain_text(&data["rich_text"]))
+        }
+        "equation" => format!("$$\n{}\n$$", data["expression"].as_str()?),
+        "divider" => "---".to_string(),
+        "image" => {
+            let url = data[data["type"].as_str()?]["url"].as_str()?;
+            format!("![{}]({url})", plain_text(&data["caption"]))
+        }
+        "bookmark" | "embed" | "link_preview" => {
+            let url = data["url"].as_str()?;
+            format!("[{url}]({url})")
+        }
+        "table" => render_table(block),
+        "column_list" | "column" | "synced_block" => children,
+        _ => return None,
+    };
+    (!rendered.trim().is_empty()).then_some(rendered)
+}
+
+/// A table block as a markdown table, its first row as the header
+fn render_table(table: &Block) -> String {
+    let rows: Vec<Vec<String>> = table
+        .children
+        .iter()
+        .map(|row| {
+            row.data["table_row"]["cells"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|cell| rich_text(cell).replace('|', "\\|").replace('\n', " "))
+                .collect()
+        })
+        .collect();
+    let mut lines = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        lines.push(format!("| {} |", row.join(" | ")));
+        if i == 0 {
+            lines.push(format!("|{}", " --- |".repeat(row.len())));
+        }
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(kind: &str, text: &str, children: Vec<Block>) -> Block {
+        let mut data = json!({"type": kind});
+        data[kind] = json!({
+            "rich_text": [{"plain_text": text, "annotations": {}, "href": null}],
+        });
+        Block { data, children }
+    }
+
+    #[test]
+    fn test_render_blocks() {
+        let mut bold = block("paragraph", "", vec![]);
+        bold.data["paragraph"]["rich_text"] = json!([
+            {"plain_text": "Read the ", "annotations": {}},
+            {"plain_text": "handbook", "annotations": {"bold": true}, "href": "https://x.io/h"},
+        ]);
+        let blocks = vec![
+            block("heading_1", "Onboarding", vec![]),
+            bold,
+            block("numbered_list_item", "Laptop", vec![]),
+            block(
+                "numbered_list_item",
+                "Accounts",
+                vec![block("bulleted_list_item", "Email", vec![])],
+            ),
+            block("quote", "Welcome!", vec![]),
+            block("child_page", "Team", vec![]),
+        ];
+        assert_eq!(
+            render_blocks(&blocks),
+            "## Onboarding\n\n\
+             Read the [**handbook**](https://x.io/h)\n\n\
+             1. Laptop\n\
+             2. Accounts\n    - Email\n\n\
+             > Welcome!"
+        );
+
+        assert_eq!(
+            page_id("https://www.notion.so/acme/Onboarding-0123456789ABCDEF0123456789abcdef?pvs=4")
+                .as_deref(),
+            Some("0123456789abcdef0123456789abcdef")
+        );
+        assert_eq!(
+            page_id("01234567-89ab-cdef-0123-456789abcdef").as_deref(),
+            Some("0123456789abcdef0123456789abcdef")
+        );
+        assert_eq!(page_id("Onboarding"), None);
+    }
+}
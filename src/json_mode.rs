@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 // Parse
 #[derive(Debug, Serialize)]
@@ -6,6 +6,10 @@ pub struct ParseResultJSON {
     pub input_path: String,
     pub output_path: String,
     pub was_cached: bool,
+    pub backend: Option<String>,
+    /// Images saved next to the output with `--save-assets`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub assets: Vec<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -13,28 +17,352 @@ pub struct ParseOutput {
     pub results: Vec<ParseResultJSON>,
 }
 
-// Search
 #[derive(Debug, Serialize)]
+pub struct ParseCacheEntryJSON {
+    pub source_path: String,
+    pub parsed_path: String,
+    pub backend: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pages: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParseCacheListOutput {
+    pub entries: Vec<ParseCacheEntryJSON>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParseCacheClearOutput {
+    pub files_removed: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParseCacheGcOutput {
+    pub entries_removed: usize,
+    pub bytes_freed: u64,
+    pub entries_remaining: usize,
+    pub bytes_remaining: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParseJobJSON {
+    pub source_path: String,
+    pub job_id: String,
+    pub submitted_at: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParseJobsListOutput {
+    pub jobs: Vec<ParseJobJSON>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ParseJobsCancelOutput {
+    pub jobs_cancelled: usize,
+}
+
+// Search
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SearchResultJSON {
     pub filename: String,
     pub start_line_number: usize,
     pub end_line_number: usize,
     pub match_line_number: usize,
     pub distance: f64,
+    /// Ranking score (equal to `distance` unless a recency boost is applied)
+    pub score: f64,
+    /// Calibrated 0–100 relevance (with `--score normalized`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalized_score: Option<f64>,
+    /// Byte offset in the matched line where the span closest to the query starts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_start_col: Option<usize>,
+    /// Byte offset in the matched line just past that span (exclusive)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub match_end_col: Option<usize>,
     pub content: String,
+    /// Title, author and creation date of the document, for workspace results indexed with any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<crate::metadata::DocumentMetadata>,
+    /// Last commit that changed the file as of the revision of `search --at`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+/// Also read back by `ask`, when search results are piped into it
+#[derive(Debug, Serialize, Deserialize)]
 pub struct SearchOutput {
     pub results: Vec<SearchResultJSON>,
 }
 
-// Ask
+/// A matching record of a JSONL or CSV input (`--input-format`)
+#[derive(Debug, Serialize)]
+pub struct RecordResultJSON {
+    pub filename: String,
+    /// 0-based index of the record in its file (of the cell, for notebooks)
+    pub record_index: usize,
+    pub distance: f64,
+    pub score: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalized_score: Option<f64>,
+    /// The embedded text of the record
+    pub content: String,
+    /// The full record; CSV rows are objects keyed by column
+    pub record: serde_json::Value,
+    /// Last commit that changed the file as of the revision of `search --at`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecordsOutput {
+    pub results: Vec<RecordResultJSON>,
+}
+
+/// A pair of the key-value table matching a `search --kv` query
+#[derive(Debug, Serialize)]
+pub struct KvResultJSON {
+    /// File the pair was read from
+    pub source: String,
+    /// 0-based index of the record in its file
+    pub record_index: usize,
+    pub distance: f64,
+    /// The embedded key (the question)
+    pub key: String,
+    /// The value returned for it (the answer)
+    pub value: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KvSearchOutput {
+    pub results: Vec<KvResultJSON>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileMatchJSON {
+    pub filename: String,
+    pub score: f64,
+    pub matching_lines: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FilesOutput {
+    pub files: Vec<FileMatchJSON>,
+}
+
+// Grep
+/// Consecutive lines of a file holding one or more matches of `semtools grep`
+#[derive(Debug, Serialize)]
+pub struct GrepResultJSON {
+    pub filename: String,
+    pub start_line_number: usize,
+    pub end_line_number: usize,
+    pub match_line_numbers: Vec<usize>,
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GrepOutput {
+    pub results: Vec<GrepResultJSON>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GrepFilesOutput {
+    pub files: Vec<String>,
+}
+
+// Bench
+#[derive(Debug, Serialize)]
+pub struct BenchQueryJSON {
+    pub query: String,
+    /// Number of judgments for the query
+    pub relevant: usize,
+    /// Judgments found in the top k
+    pub found: usize,
+    pub recall: f64,
+    pub reciprocal_rank: f64,
+    pub ndcg: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BenchOutput {
+    pub k: usize,
+    /// Mean recall@k over the queries
+    pub recall: f64,
+    /// Mean reciprocal rank
+    pub mrr: f64,
+    /// Mean nDCG@k
+    pub ndcg: f64,
+    pub queries: Vec<BenchQueryJSON>,
+}
+
+// Dedupe
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroupJSON {
+    pub kind: String,
+    pub files: Vec<String>,
+    pub max_distance: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DedupeOutput {
+    pub groups: Vec<DuplicateGroupJSON>,
+    pub files_removed: Vec<String>,
+    pub files_linked: Vec<String>,
+}
+
+// Similar
+#[derive(Debug, Serialize)]
+pub struct SimilarDocumentJSON {
+    pub path: String,
+    pub distance: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SimilarOutput {
+    pub reference: String,
+    /// "centroid" or "max"
+    pub pooling: String,
+    pub results: Vec<SimilarDocumentJSON>,
+}
+
+// Model
+#[derive(Debug, Serialize)]
+pub struct ModelStatusOutput {
+    pub name: String,
+    /// "local" for a model directory, "huggingface" for a repo id
+    pub source: String,
+    pub cache_dir: String,
+    pub available: bool,
+    pub path: Option<String>,
+    pub size_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelPathOutput {
+    pub path: String,
+}
+
+// Saved searches
+#[derive(Debug, Serialize)]
+pub struct SavedSearchJSON {
+    pub name: String,
+    pub query: String,
+    pub args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// "local" (saved with `saved add`) or "config" (the config file)
+    pub source: String,
+}
+
 #[derive(Debug, Serialize)]
+pub struct SavedSearchesOutput {
+    pub searches: Vec<SavedSearchJSON>,
+}
+
+// Ask
+#[derive(Debug, Serialize, Deserialize)]
 pub struct AskOutput {
     pub query: String,
     pub response: String,
     pub files_searched: Vec<String>,
+    /// What the verification pass changed (with `--self-check`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub self_check: Option<SelfCheckOutput>,
+    /// The answer came from the answer cache (`ask --cache`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    /// Tool responses the answer was written from, checked by `--self-check`
+    #[serde(skip)]
+    pub evidence: Vec<String>,
+    /// Retrieved content that looked like instructions to the agent (`--injection-check`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub injections: Vec<InjectionJSON>,
+    /// The agent's tool calls in order, for `--export-markdown` and `--export-html`
+    #[serde(skip)]
+    pub tool_calls: Vec<ToolCallJSON>,
+}
+
+/// A tool call of the agent and its response, as the model saw them (redacted)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolCallJSON {
+    pub tool: String,
+    /// The raw JSON arguments from the model
+    pub arguments: String,
+    pub response: String,
+}
+
+/// `ask --dry-run`: what the agent retrieved for the question, without an answer
+#[derive(Debug, Serialize)]
+pub struct AskDryRunOutput {
+    pub query: String,
+    pub files_searched: Vec<String>,
+    pub tool_calls: Vec<RetrievalCallJSON>,
+    /// Every distinct chunk the tool calls returned, in the order they were first retrieved
+    pub chunks: Vec<RetrievedChunkJSON>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetrievalCallJSON {
+    pub tool: String,
+    /// The arguments from the model (a string when they are not valid JSON)
+    pub arguments: serde_json::Value,
+    /// Chunks in its response
+    pub chunks: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RetrievedChunkJSON {
+    pub file: String,
+    /// 0-based line range, end exclusive, as the tools report it
+    pub start: usize,
+    pub end: usize,
+    /// Distance of a semantic search match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f32>,
+    /// Tool that first retrieved the chunk
+    pub tool: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InjectionJSON {
+    /// Tool whose response contained it
+    pub tool: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub reason: String,
+    /// The suspicious line (heuristic findings only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub excerpt: Option<String>,
+    /// "heuristic" or "llm"
+    pub detector: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelfCheckOutput {
+    /// Verification turns that changed the answer
+    pub revisions: usize,
+    /// Claims removed or corrected because the retrieved chunks did not support them
+    pub dropped_claims: Vec<String>,
+}
+
+// Extract
+/// One NDJSON row of `semtools extract`: `data` on success, `error` otherwise
+#[derive(Debug, Serialize)]
+pub struct ExtractRow {
+    pub file: String,
+    pub data: Option<serde_json::Value>,
+    pub error: Option<String>,
+}
+
+// Summarize
+#[derive(Debug, Serialize)]
+pub struct SummarizeOutput {
+    pub files: Vec<String>,
+    pub length: String,
+    pub chunks: usize,
+    pub summary: String,
 }
 
 // Workspace
@@ -43,6 +371,73 @@ pub struct WorkspaceOutput {
     pub name: String,
     pub root_dir: String,
     pub total_documents: usize,
+    pub embed_preset: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceListEntryJSON {
+    pub name: String,
+    pub root_dir: String,
+    /// `null` when the index cannot be opened (e.g. encrypted and no passphrase given)
+    pub documents: Option<usize>,
+    pub size_bytes: u64,
+    /// Unix timestamp (seconds) of the last search or write
+    pub last_used: Option<u64>,
+    pub active: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceListOutput {
+    pub workspaces: Vec<WorkspaceListEntryJSON>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WorkspaceDeleteOutput {
+    pub name: String,
+    /// Index kept outside the workspace directory, which was left in place
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index_left_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SyncOutput {
+    pub workspace: String,
+    pub documents: usize,
+    pub documents_updated: usize,
+    pub documents_tagged: usize,
+    pub tags: std::collections::BTreeMap<String, String>,
+    /// Committed copies of the files indexed with `--git`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revisions: Option<usize>,
+}
+
+/// `workspace kv`: question→answer pairs indexed into the key-value table
+#[derive(Debug, Serialize)]
+pub struct KvSyncOutput {
+    pub workspace: String,
+    pub files: usize,
+    /// Pairs read from the files
+    pub pairs: usize,
+    /// Pairs of earlier syncs of the same files that were replaced
+    pub pairs_replaced: usize,
+    /// Pairs in the table afterwards
+    pub pairs_total: usize,
+}
+
+/// `import notion|confluence|mail`: pages (or messages) written to the managed directory and
+/// indexed
+#[derive(Debug, Serialize)]
+pub struct ImportOutput {
+    pub workspace: String,
+    /// "notion", "confluence" or "mail"
+    pub source: String,
+    pub directory: String,
+    pub pages: usize,
+    /// Pages fetched because they are new or were edited
+    pub pages_updated: usize,
+    /// Files of pages that were moved or deleted at the source, removed from the workspace
+    pub files_removed: usize,
+    pub documents_updated: usize,
 }
 
 #[derive(Debug, Serialize)]
@@ -51,6 +446,65 @@ pub struct PruneOutput {
     pub files_remaining: usize,
 }
 
+#[derive(Debug, Serialize)]
+pub struct OptimizeOutput {
+    pub rows_optimized: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FsckOutput {
+    pub workspace: String,
+    pub documents: usize,
+    pub line_embeddings: usize,
+    pub orphaned_lines: Vec<String>,
+    pub orphaned_doc_embeddings: Vec<String>,
+    pub documents_without_lines: Vec<String>,
+    pub documents_without_doc_embedding: Vec<String>,
+    pub wrong_dimensions: Vec<String>,
+    pub unindexed_rows: usize,
+    pub clean: bool,
+    pub repaired: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotJSON {
+    pub name: String,
+    pub created_at: u64,
+    pub total_documents: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SnapshotListOutput {
+    pub workspace: String,
+    pub snapshots: Vec<SnapshotJSON>,
+}
+
+// Config
+/// `config init` and `config validate`: the file, and its problems (empty when valid)
+#[derive(Debug, Serialize)]
+pub struct ConfigFileOutput {
+    pub path: String,
+    pub problems: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfigShowOutput {
+    pub path: String,
+    /// Whether the file exists; the defaults are shown when it does not
+    pub exists: bool,
+    /// The project config file merged over it, if one applies in the working directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub project: Option<String>,
+    pub config: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConfigSetOutput {
+    pub path: String,
+    pub key: String,
+    pub value: serde_json::Value,
+}
+
 // Error output
 #[derive(Debug, Serialize)]
 pub struct ErrorOutput {
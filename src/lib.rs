@@ -3,8 +3,26 @@
 pub mod config;
 pub use config::{AskConfig, SemtoolsConfig};
 
+pub mod paths;
+
+pub mod archive;
+
+pub mod git;
+
+pub mod metadata;
+
+pub mod notebook;
+
 pub mod cmds;
 pub mod json_mode;
+pub mod telemetry;
+pub mod usage;
+
+#[cfg(feature = "fetch")]
+pub mod fetch;
+
+#[cfg(feature = "fetch")]
+pub mod s3;
 
 #[cfg(feature = "parse")]
 pub mod parse;
@@ -12,11 +30,34 @@ pub mod parse;
 #[cfg(feature = "parse")]
 pub use parse::{JobError, LlamaParseBackend, LlamaParseConfig};
 
-#[cfg(feature = "workspace")]
+#[cfg(feature = "workspace-lite")]
 pub mod workspace;
 
+pub mod ranking;
+
+#[cfg(feature = "ripgrep")]
+pub mod grep;
+
 #[cfg(feature = "search")]
 pub mod search;
 
 #[cfg(feature = "ask")]
 pub mod ask;
+
+#[cfg(feature = "import")]
+pub mod import;
+
+#[cfg(feature = "grpc")]
+pub mod grpc;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(any(feature = "node", feature = "ffi"))]
+pub mod api;
+
+#[cfg(feature = "node")]
+pub mod node;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
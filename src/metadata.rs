@@ -0,0 +1,298 @@
+//! Document metadata: the title, author and creation date of a document, and the URL it was
+//! fetched from. Markdown files carry it in YAML frontmatter; for a PDF or a downloaded
+//! document, `parse` reads it from the PDF or the download and keeps it in the
+//! `.metadata.json` file next to the parsed markdown. Workspaces store it with each document,
+//! where `search --filter author:Smith` can select documents by it.
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    /// Creation date, ISO 8601 when the source gives one (`2024-03-05` or `2024-03-05T10:22:01Z`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created: Option<String>,
+    /// URL of the original document, for documents downloaded from the web or Google Drive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// A field of `DocumentMetadata`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataField {
+    Title,
+    Author,
+    Created,
+    Source,
+}
+
+impl MetadataField {
+    /// The field a frontmatter key holds, if any
+    fn of_key(key: &str) -> Option<Self> {
+        match key.to_lowercase().as_str() {
+            "title" => Some(MetadataField::Title),
+            "author" | "authors" | "creator" => Some(MetadataField::Author),
+            "created" | "created_at" | "creation_date" | "date" => Some(MetadataField::Created),
+            "source" | "url" => Some(MetadataField::Source),
+            _ => None,
+        }
+    }
+}
+
+impl DocumentMetadata {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.author.is_none()
+            && self.created.is_none()
+            && self.source.is_none()
+    }
+
+    pub fn get(&self, field: MetadataField) -> Option<&str> {
+        match field {
+            MetadataField::Title => self.title.as_deref(),
+            MetadataField::Author => self.author.as_deref(),
+            MetadataField::Created => self.created.as_deref(),
+            MetadataField::Source => self.source.as_deref(),
+        }
+    }
+
+    /// Set `field` to `value`, unless it is already set or `value` is blank
+    pub fn set(&mut self, field: MetadataField, value: &str) {
+        let value = value.trim();
+        let slot = match field {
+            MetadataField::Title => &mut self.title,
+            MetadataField::Author => &mut self.author,
+            MetadataField::Created => &mut self.created,
+            MetadataField::Source => &mut self.source,
+        };
+        if slot.is_none() && !value.is_empty() {
+            *slot = Some(value.to_string());
+        }
+    }
+
+    /// One line for terminal output, such as `Quarterly report · J. Smith · 2024-03-05`
+    pub fn summary(&self) -> Option<String> {
+        let parts: Vec<&str> = [&self.title, &self.author, &self.created, &self.source]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        (!parts.is_empty()).then(|| parts.join(" · "))
+    }
+}
+
+/// The metadata of the file at `path` with `content`: its frontmatter, completed from the
+/// metadata `parse` kept for the original document when `path` is a parsed result
+pub fn read(path: &str, content: &str) -> DocumentMetadata {
+    let mut metadata = frontmatter(content);
+    if let Some(parsed) = parsed_metadata(path) {
+        for field in [
+            MetadataField::Title,
+            MetadataField::Author,
+            MetadataField::Created,
+            MetadataField::Source,
+        ] {
+            if let Some(value) = parsed.get(field) {
+                metadata.set(field, value);
+            }
+        }
+    }
+    metadata
+}
+
+/// The URL the original document of a parsed result was downloaded from, if any
+pub fn source_of(path: &str) -> Option<String> {
+    parsed_metadata(path)?.source
+}
+
+/// The `.metadata.json` file `parse` writes next to a parsed `.md` result
+fn parsed_metadata_path(path: &str) -> Option<PathBuf> {
+    let prefix = path.strip_suffix(".md")?;
+    Some(PathBuf::from(format!("{prefix}.metadata.json")))
+}
+
+/// Metadata of the original document of a parsed result, if `parse` recorded any
+fn parsed_metadata(path: &str) -> Option<DocumentMetadata> {
+    #[derive(Deserialize)]
+    struct ParsedFile {
+        #[serde(default)]
+        document: DocumentMetadata,
+    }
+
+    let sidecar = parsed_metadata_path(path)?;
+    let local = crate::archive::local_path(&sidecar.to_string_lossy());
+    let json = std::fs::read_to_string(local).ok()?;
+    let parsed: ParsedFile = serde_json::from_str(&json).ok()?;
+    (!parsed.document.is_empty()).then_some(parsed.document)
+}
+
+/// The title, author and date of a YAML frontmatter block: a `---` line at the very start of
+/// the document and the `---` (or `...`) line closing it. Only plain `key: value` entries and
+/// lists of authors are read; nothing is taken from a block that is never closed.
+pub fn frontmatter(content: &str) -> DocumentMetadata {
+    let mut lines = content.lines();
+    if lines.next().map(|line| line.trim_end()) != Some("---") {
+        return DocumentMetadata::default();
+    }
+
+    let mut metadata = DocumentMetadata::default();
+    // A key whose value is the list on the following lines, and the items read so far
+    let mut list: Option<(MetadataField, Vec<String>)> = None;
+    for line in lines {
+        let line = line.trim_end();
+        if line == "---" || line == "..." {
+            if let Some((field, items)) = list.take() {
+                metadata.set(field, &items.join(", "));
+            }
+            return metadata;
+        }
+        if let Some((_, items)) = &mut list
+            && let Some(item) = line.trim_start().strip_prefix("- ")
+        {
+            items.push(unquote(item).to_string());
+            continue;
+        }
+        if let Some((field, items)) = list.take() {
+            metadata.set(field, &items.join(", "));
+        }
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(field) = MetadataField::of_key(key.trim()) else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            list = Some((field, Vec::new()));
+        } else if let Some(inline) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+            let items: Vec<&str> = inline.split(',').map(|item| unquote(item.trim())).collect();
+            metadata.set(field, &items.join(", "));
+        } else {
+            metadata.set(field, unquote(value));
+        }
+    }
+    DocumentMetadata::default()
+}
+
+fn unquote(value: &str) -> &str {
+    ['"', '\'']
+        .iter()
+        .find_map(|&quote| value.strip_prefix(quote)?.strip_suffix(quote))
+        .unwrap_or(value)
+}
+
+/// A `--filter` on the metadata of workspace documents, written `title:words`, `author:name`,
+/// `created:date` or `source:url`. Titles, authors and sources match when they contain the
+/// value, ignoring case; creation dates when they start with it, so `created:2024-03` matches
+/// any day of March 2024.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataFilter {
+    pub field: MetadataField,
+    pub value: String,
+}
+
+impl std::str::FromStr for MetadataFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let field = match s.split_once(':') {
+            Some(("title", _)) => MetadataField::Title,
+            Some(("author", _)) => MetadataField::Author,
+            Some(("created", _)) => MetadataField::Created,
+            Some(("source", _)) => MetadataField::Source,
+            _ => anyhow::bail!(
+                "Invalid filter: '{s}'. Filters have the form 'tag:key=value', 'title:words', \
+                 'author:name', 'created:date' or 'source:url'"
+            ),
+        };
+        let value = s.split_once(':').map_or("", |(_, value)| value.trim());
+        if value.is_empty() {
+            anyhow::bail!("Invalid filter: '{s}'. The value is empty");
+        }
+        Ok(MetadataFilter {
+            field,
+            value: value.to_string(),
+        })
+    }
+}
+
+impl std::fmt::Display for MetadataFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let field = match self.field {
+            MetadataField::Title => "title",
+            MetadataField::Author => "author",
+            MetadataField::Created => "created",
+            MetadataField::Source => "source",
+        };
+        write!(f, "{field}:{}", self.value)
+    }
+}
+
+impl MetadataFilter {
+    pub fn matches(&self, metadata: &DocumentMetadata) -> bool {
+        let Some(value) = metadata.get(self.field) else {
+            return false;
+        };
+        match self.field {
+            MetadataField::Created => value.starts_with(&self.value),
+            _ => value.to_lowercase().contains(&self.value.to_lowercase()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frontmatter() {
+        let metadata = frontmatter(
+            "---\ntitle: \"Q3 Results: EMEA\"\nauthors:\n  - Jane Smith\n  - 'Li Wei'\n\
+             tags: [finance]\ndate: 2024-03-05\n---\n# Q3\n",
+        );
+        assert_eq!(
+            metadata,
+            DocumentMetadata {
+                title: Some("Q3 Results: EMEA".to_string()),
+                author: Some("Jane Smith, Li Wei".to_string()),
+                created: Some("2024-03-05".to_string()),
+                source: None,
+            }
+        );
+        assert_eq!(
+            frontmatter("---\nauthor: [A, \"B\"]\n...\n")
+                .author
+                .as_deref(),
+            Some("A, B")
+        );
+        // Not at the start, or never closed
+        assert!(frontmatter("# Notes\n---\ntitle: x\n---\n").is_empty());
+        assert!(frontmatter("---\ntitle: x\n\nSome text").is_empty());
+    }
+
+    #[test]
+    fn test_metadata_filter() {
+        let metadata = DocumentMetadata {
+            title: Some("Annual Report".to_string()),
+            author: Some("Jane Smith".to_string()),
+            created: Some("2024-03-05T10:22:01Z".to_string()),
+            source: Some("https://docs.google.com/document/d/abc123/edit".to_string()),
+        };
+        let matches = |filter: &str| filter.parse::<MetadataFilter>().unwrap().matches(&metadata);
+        assert!(matches("author:smith"));
+        assert!(matches("title:annual report"));
+        assert!(matches("created:2024-03"));
+        assert!(!matches("created:2023"));
+        assert!(!matches("author:Jones"));
+        assert!(matches("source:docs.google.com"));
+        assert!("publisher:x".parse::<MetadataFilter>().is_err());
+        assert!("author: ".parse::<MetadataFilter>().is_err());
+    }
+}
@@ -0,0 +1,127 @@
+//! Node.js bindings to search and parse (`node` feature), loaded by the npm package's
+//! `index.js`. JavaScript agents call these in-process and get structured results, instead of
+//! spawning a `semtools` process per query.
+use napi_derive::napi;
+
+use crate::api;
+use crate::json_mode::{ParseResultJSON, SearchResultJSON};
+
+fn to_napi(e: anyhow::Error) -> napi::Error {
+    napi::Error::from_reason(format!("{e:#}"))
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct SearchOptions {
+    /// Lines of context before and after each match (default 3)
+    pub n_lines: Option<u32>,
+    /// Number of results (default 3)
+    pub top_k: Option<u32>,
+    /// Return every match under this distance instead of the top `topK`
+    pub max_distance: Option<f64>,
+    pub ignore_case: Option<bool>,
+    /// Workspace to search through (defaults to `SEMTOOLS_WORKSPACE`, if set)
+    pub workspace: Option<String>,
+    /// Embedding preset, as with `--embed-preset`
+    pub embed_preset: Option<String>,
+    /// Config file (defaults to `~/.semtools_config.json`)
+    pub config: Option<String>,
+}
+
+#[napi(object)]
+pub struct SearchResult {
+    pub filename: String,
+    pub start_line_number: u32,
+    pub end_line_number: u32,
+    pub match_line_number: u32,
+    pub distance: f64,
+    pub score: f64,
+    pub content: String,
+}
+
+impl From<SearchResultJSON> for SearchResult {
+    fn from(result: SearchResultJSON) -> Self {
+        SearchResult {
+            filename: result.filename,
+            start_line_number: result.start_line_number as u32,
+            end_line_number: result.end_line_number as u32,
+            match_line_number: result.match_line_number as u32,
+            distance: result.distance,
+            score: result.score,
+            content: result.content,
+        }
+    }
+}
+
+impl From<SearchOptions> for api::SearchOptions {
+    fn from(options: SearchOptions) -> Self {
+        let defaults = api::SearchOptions::default();
+        api::SearchOptions {
+            n_lines: options.n_lines.map_or(defaults.n_lines, |n| n as usize),
+            top_k: options.top_k.map_or(defaults.top_k, |k| k as usize),
+            max_distance: options.max_distance,
+            ignore_case: options.ignore_case.unwrap_or(defaults.ignore_case),
+            workspace: options.workspace,
+            embed_preset: options.embed_preset,
+            config: options.config,
+        }
+    }
+}
+
+/// Search `files` for lines semantically similar to `query`, best first
+#[napi]
+pub async fn search(
+    query: String,
+    files: Vec<String>,
+    options: Option<SearchOptions>,
+) -> napi::Result<Vec<SearchResult>> {
+    let options = options.unwrap_or_default().into();
+    let results = api::search(query, files, options).await.map_err(to_napi)?;
+    Ok(results.into_iter().map(SearchResult::from).collect())
+}
+
+#[napi(object)]
+#[derive(Default)]
+pub struct ParseOptions {
+    /// Parse backend, as with `--backend` (defaults to the config file's chain)
+    pub backend: Option<String>,
+    /// Config file (defaults to `~/.semtools_config.json`)
+    pub config: Option<String>,
+}
+
+#[napi(object)]
+pub struct ParseResult {
+    pub input_path: String,
+    /// Markdown output of the parse
+    pub output_path: String,
+    pub was_cached: bool,
+    /// Backend that produced the output (unset for readable files passed through as-is)
+    pub backend: Option<String>,
+    /// Images saved next to the output
+    pub assets: Vec<String>,
+}
+
+impl From<ParseResultJSON> for ParseResult {
+    fn from(result: ParseResultJSON) -> Self {
+        ParseResult {
+            input_path: result.input_path,
+            output_path: result.output_path,
+            was_cached: result.was_cached,
+            backend: result.backend,
+            assets: result.assets,
+        }
+    }
+}
+
+/// Parse documents to markdown, returning where each one's output was written
+#[napi]
+pub async fn parse(
+    files: Vec<String>,
+    options: Option<ParseOptions>,
+) -> napi::Result<Vec<ParseResult>> {
+    let options = options.unwrap_or_default();
+    let results = api::parse(files, options.backend, options.config)
+        .await
+        .map_err(to_napi)?;
+    Ok(results.into_iter().map(ParseResult::from).collect())
+}
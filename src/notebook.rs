@@ -0,0 +1,130 @@
+//! Jupyter notebooks (`.ipynb`). Read as text, a notebook is JSON whose lines say little: the
+//! cells are split out instead, so `parse` renders them as markdown and `search` matches them
+//! one cell at a time. Cells are cited by their 0-based index in the notebook, as `nbformat`
+//! numbers them. Outputs are left out.
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+/// A markdown, code or raw cell of a notebook
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    /// 0-based position among the notebook's cells
+    pub index: usize,
+    /// `markdown`, `code` or `raw`
+    pub cell_type: String,
+    pub source: String,
+}
+
+/// Whether `path` names a notebook
+pub fn is_notebook(path: &str) -> bool {
+    path.to_lowercase().ends_with(".ipynb")
+}
+
+/// Marker placed before each cell of a parsed notebook, so citations can point at cells
+pub fn cell_marker(index: usize) -> String {
+    format!("<!-- cell {index} -->")
+}
+
+/// The cells of a notebook, including empty ones so indices match the notebook's
+pub fn cells(content: &str) -> Result<Vec<Cell>> {
+    cells_of(&serde_json::from_str(content).context("Not a valid notebook")?)
+}
+
+fn cells_of(notebook: &Value) -> Result<Vec<Cell>> {
+    let cells = notebook
+        .get("cells")
+        .and_then(Value::as_array)
+        .context("Not a valid notebook: it has no cells")?;
+    Ok(cells
+        .iter()
+        .enumerate()
+        .map(|(index, cell)| Cell {
+            index,
+            cell_type: cell
+                .get("cell_type")
+                .and_then(Value::as_str)
+                .unwrap_or("raw")
+                .to_string(),
+            source: cell.get("source").map(source_text).unwrap_or_default(),
+        })
+        .collect())
+}
+
+/// A cell's source, which nbformat stores as one string or as a list of lines
+fn source_text(source: &Value) -> String {
+    match source {
+        Value::String(text) => text.clone(),
+        Value::Array(lines) => lines.iter().filter_map(Value::as_str).collect(),
+        _ => String::new(),
+    }
+}
+
+/// The language of the notebook's code cells, for fenced code blocks
+fn language(notebook: &Value) -> &str {
+    let metadata = &notebook["metadata"];
+    metadata["language_info"]["name"]
+        .as_str()
+        .or_else(|| metadata["kernelspec"]["language"].as_str())
+        .unwrap_or_default()
+}
+
+/// Render a notebook as markdown: every cell with text after a `<!-- cell N -->` marker,
+/// markdown cells as they are and code cells fenced in the notebook's language
+pub fn to_markdown(content: &str) -> Result<String> {
+    let notebook: Value = serde_json::from_str(content).context("Not a valid notebook")?;
+    let language = language(&notebook);
+    let mut markdown = String::new();
+    for cell in cells_of(&notebook)? {
+        let source = cell.source.trim_end();
+        if source.trim().is_empty() {
+            continue;
+        }
+        markdown += &cell_marker(cell.index);
+        markdown += "\n\n";
+        match cell.cell_type.as_str() {
+            "markdown" => markdown += source,
+            "code" => markdown += &format!("```{language}\n{source}\n```"),
+            _ => markdown += &format!("```\n{source}\n```"),
+        }
+        markdown += "\n\n";
+    }
+    Ok(markdown)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK: &str = r##"{
+ "cells": [
+  {"cell_type": "markdown", "metadata": {}, "source": ["# Churn\n", "Monthly churn by plan."]},
+  {"cell_type": "code", "execution_count": 1, "metadata": {}, "outputs": [
+    {"output_type": "stream", "name": "stdout", "text": ["42\n"]}
+  ], "source": "df = load()\ndf.churn.mean()"},
+  {"cell_type": "code", "execution_count": null, "metadata": {}, "outputs": [], "source": []},
+  {"cell_type": "raw", "metadata": {}, "source": "notes"}
+ ],
+ "metadata": {"kernelspec": {"language": "python", "name": "python3"}},
+ "nbformat": 4,
+ "nbformat_minor": 5
+}"##;
+
+    #[test]
+    fn test_cells() {
+        let cells = cells(NOTEBOOK).unwrap();
+        assert_eq!(cells.len(), 4);
+        assert_eq!(cells[0].source, "# Churn\nMonthly churn by plan.");
+        assert_eq!(cells[1].cell_type, "code");
+        assert_eq!(cells[2].source, "");
+        assert!(super::cells("[1, 2]").is_err());
+    }
+
+    #[test]
+    fn test_to_markdown() {
+        assert_eq!(
+            to_markdown(NOTEBOOK).unwrap(),
+            "<!-- cell 0 -->\n\n# Churn\nMonthly churn by plan.\n\n<!-- cell 1 -->\n\n\
+             ```python\ndf = load()\ndf.churn.mean()\n```\n\n<!-- cell 3 -->\n\n```\nnotes\n```\n\n"
+        );
+    }
+}
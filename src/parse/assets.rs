@@ -0,0 +1,138 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::parse::client::{ImageAsset, ParseClient};
+use crate::parse::error::JobError;
+
+/// Suffix of the directory next to a cached result that holds its images
+pub const ASSETS_DIR_SUFFIX: &str = ".assets";
+
+/// Images saved to an assets directory
+#[derive(Debug, Default)]
+pub struct SavedAssets {
+    dir_name: String,
+    names: HashSet<String>,
+}
+
+impl SavedAssets {
+    /// Point the markdown's links to saved images at the local copies
+    pub fn rewrite_links(&self, markdown: &str) -> String {
+        rewrite_image_links(markdown, &self.dir_name, &self.names)
+    }
+}
+
+/// Download `images` into `dir`. With `replace`, images from an earlier parse are removed
+/// first; otherwise the new ones are added next to them (e.g. when retrying failed pages).
+pub async fn save_assets(
+    client: &ParseClient,
+    dir: &Path,
+    images: &[ImageAsset],
+    replace: bool,
+) -> Result<SavedAssets, JobError> {
+    if replace && dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    let mut saved = SavedAssets {
+        dir_name: dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+        names: HashSet::new(),
+    };
+    if images.is_empty() {
+        return Ok(saved);
+    }
+    fs::create_dir_all(dir)?;
+
+    for image in images {
+        let Some(name) = asset_file_name(&image.filename) else {
+            continue;
+        };
+        match client.download_asset(&image.url, &dir.join(name)).await {
+            Ok(()) => {
+                saved.names.insert(name.to_string());
+            }
+            Err(e) => eprintln!("Warning: could not download image {name}: {e}"),
+        }
+    }
+    Ok(saved)
+}
+
+/// Files in an assets directory, sorted (empty if there is none)
+pub fn list_assets(dir: &Path) -> Vec<String> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut assets: Vec<String> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .map(|p| p.to_string_lossy().to_string())
+        .collect();
+    assets.sort();
+    assets
+}
+
+/// The bare file name of an asset, so a name from the API can never escape the assets directory
+fn asset_file_name(filename: &str) -> Option<&str> {
+    Path::new(filename).file_name().and_then(|n| n.to_str())
+}
+
+/// Rewrite `![alt](target)` links whose target names a saved asset to `dir_name/<file>`
+fn rewrite_image_links(markdown: &str, dir_name: &str, saved: &HashSet<String>) -> String {
+    let mut out = String::with_capacity(markdown.len());
+    let mut rest = markdown;
+
+    while let Some(start) = rest.find("![") {
+        let Some(target_start) = rest[start..].find("](").map(|i| start + i + 2) else {
+            break;
+        };
+        let Some(target_end) = rest[target_start..].find(')').map(|i| target_start + i) else {
+            break;
+        };
+
+        out.push_str(&rest[..target_start]);
+        let target = &rest[target_start..target_end];
+        match asset_file_name(target).filter(|name| saved.contains(*name)) {
+            Some(name) => out.push_str(&format!("{dir_name}/{name}")),
+            None => out.push_str(target),
+        }
+        rest = &rest[target_end..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_image_links() {
+        let saved = HashSet::from(["page_1_image_1.jpg".to_string()]);
+        let markdown = "# Results\n\n![Figure 1](page_1_image_1.jpg)\n\n![logo](https://example.com/logo.png) and [a link](page_1_image_1.jpg)";
+
+        assert_eq!(
+            rewrite_image_links(markdown, "report.pdf.3f2a.assets", &saved),
+            "# Results\n\n![Figure 1](report.pdf.3f2a.assets/page_1_image_1.jpg)\n\n![logo](https://example.com/logo.png) and [a link](page_1_image_1.jpg)"
+        );
+
+        // Unterminated links are left alone
+        assert_eq!(
+            rewrite_image_links("![broken](page_1", "assets", &saved),
+            "![broken](page_1"
+        );
+    }
+
+    #[test]
+    fn test_asset_file_name() {
+        assert_eq!(
+            asset_file_name("page_2_chart.png"),
+            Some("page_2_chart.png")
+        );
+        assert_eq!(asset_file_name("../../etc/passwd"), Some("passwd"));
+        assert_eq!(asset_file_name(".."), None);
+    }
+}
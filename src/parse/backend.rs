@@ -1,35 +1,181 @@
+use serde_json::Value;
 use std::fs;
+use std::path::Path;
 use std::sync::Arc;
-use tokio::sync::Semaphore;
 
+use crate::parse::assets::{list_assets, save_assets};
 use crate::parse::cache::CacheManager;
-use crate::parse::client::ParseClient;
+use crate::parse::client::{ParseClient, UploadProgress};
 use crate::parse::config::LlamaParseConfig;
+use crate::parse::email::{self, AttachmentSection};
 use crate::parse::error::JobError;
+use crate::parse::jobs::{JobLedger, PendingJob};
+use crate::parse::local::{ParseBackendKind, check_output_quality, run_local_backend};
+use crate::parse::pages::PageSelection;
+use crate::parse::throttle::Throttle;
+use crate::telemetry::{self, Operation};
+
+/// Outcome of parsing a single input file
+#[derive(Debug, Clone)]
+pub struct ParsedFile {
+    pub input_path: String,
+    pub output_path: String,
+    pub was_cached: bool,
+    /// Backend that produced the output (`None` for readable files passed through as-is)
+    pub backend: Option<String>,
+    /// Images saved next to the output with `--save-assets`
+    pub assets: Vec<String>,
+}
 
 pub struct LlamaParseBackend {
     config: LlamaParseConfig,
     cache_manager: CacheManager,
+    chain: Vec<ParseBackendKind>,
     verbose: bool,
 }
 
 impl LlamaParseBackend {
     pub fn new(config: LlamaParseConfig, verbose: bool) -> anyhow::Result<Self> {
-        let cache_dir = dirs::home_dir()
-            .ok_or_else(|| anyhow::Error::msg("Could not find home directory"))?
-            .join(".parse");
+        let cache_dir = CacheManager::default_cache_dir()?;
 
         fs::create_dir_all(&cache_dir)?;
 
+        let cache_manager = CacheManager::new(cache_dir).with_limits(config.cache_limits());
+
         Ok(Self {
             config,
-            cache_manager: CacheManager::new(cache_dir),
+            cache_manager,
+            chain: vec![ParseBackendKind::LlamaParse],
             verbose,
         })
     }
 
+    /// Use a fallback chain of backends. Local backends are tried first, in order, and a file
+    /// only escalates to the next backend when the output fails the quality heuristics.
+    pub fn with_chain(mut self, chain: Vec<ParseBackendKind>) -> Self {
+        if !chain.is_empty() {
+            self.chain = chain;
+        }
+        self
+    }
+
+    /// Parse only the selected pages. Local backends that cannot select pages are skipped, and
+    /// the results are cached apart from whole documents.
+    pub fn with_pages(mut self, pages: Option<PageSelection>) -> Self {
+        if let Some(pages) = &pages {
+            set_target_pages(&mut self.config, pages);
+        }
+        self.cache_manager = self.cache_manager.with_pages(pages);
+        self
+    }
+
+    /// Download the images LlamaParse extracts into an assets folder next to each cached
+    /// result, and point the markdown's image links at them
+    pub fn with_save_assets(mut self, save_assets: bool) -> Self {
+        self.cache_manager = self.cache_manager.with_save_assets(save_assets);
+        self
+    }
+
     pub async fn parse(&self, files: Vec<String>) -> Result<Vec<String>, JobError> {
-        let semaphore = Arc::new(Semaphore::new(self.config.num_ongoing_requests));
+        let parsed = self.parse_files(files).await?;
+        Ok(parsed.into_iter().map(|p| p.output_path).collect())
+    }
+
+    /// Try each local backend in the chain, returning the first acceptable result
+    async fn try_local_backends(&self, file_path: &str) -> Option<ParsedFile> {
+        let pages = self.cache_manager.pages.as_ref();
+        // Output density is measured against the whole file, which says little about a few pages
+        let input_bytes = match pages {
+            Some(_) => 0,
+            None => fs::metadata(file_path).map(|m| m.len()).unwrap_or(0),
+        };
+
+        for kind in self.chain.iter().filter(|k| k.is_local()) {
+            if !kind.supports(file_path) || (pages.is_some() && !kind.supports_pages()) {
+                continue;
+            }
+
+            // Attachments make up most of an email, so density against its size means little
+            let (result, input_bytes) = if *kind == ParseBackendKind::Email {
+                (self.parse_email(file_path).await, 0)
+            } else {
+                let _timed = telemetry::timed(Operation::ParseJob, &[("backend", kind.as_str())]);
+                (run_local_backend(*kind, file_path, pages), input_bytes)
+            };
+
+            let content = match result {
+                Ok(content) => content,
+                Err(e) => {
+                    if self.verbose {
+                        eprintln!("Backend {kind} failed for {file_path}: {e}");
+                    }
+                    continue;
+                }
+            };
+
+            if let Err(issue) = check_output_quality(&content, input_bytes) {
+                if self.verbose {
+                    eprintln!("Backend {kind} rejected for {file_path}: {issue}");
+                }
+                continue;
+            }
+
+            match self
+                .cache_manager
+                .write_results_to_disk(file_path, &content, kind.as_str())
+                .await
+            {
+                Ok(output_path) => {
+                    if self.verbose {
+                        eprintln!("Parsed {file_path} with backend {kind}");
+                    }
+                    return Some(ParsedFile {
+                        input_path: file_path.to_string(),
+                        output_path,
+                        was_cached: false,
+                        backend: Some(kind.as_str().to_string()),
+                        assets: Vec::new(),
+                    });
+                }
+                Err(e) => eprintln!("Error writing parse result for {file_path}: {e}"),
+            }
+        }
+
+        None
+    }
+
+    /// Render an email, parsing each of its attachments through the whole backend chain
+    async fn parse_email(&self, file_path: &str) -> Result<String, JobError> {
+        let message = email::read_email(file_path)?;
+        let dir = self.cache_manager.attachments_dir(file_path);
+        let paths: Vec<String> = email::save_attachments(&message, &dir)?
+            .iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+
+        // Boxed, as attachments can be emails themselves
+        let parsed = Box::pin(self.parse_files(paths.clone())).await?;
+        let sections: Vec<AttachmentSection> = paths
+            .iter()
+            .map(|path| AttachmentSection {
+                name: Path::new(path)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default(),
+                markdown: parsed
+                    .iter()
+                    .find(|p| p.input_path == *path)
+                    .and_then(|p| fs::read_to_string(&p.output_path).ok()),
+            })
+            .collect();
+        Ok(message.to_markdown(&sections))
+    }
+
+    pub async fn parse_files(&self, files: Vec<String>) -> Result<Vec<ParsedFile>, JobError> {
+        let throttle = Arc::new(Throttle::new(
+            self.config.num_ongoing_requests,
+            self.config.max_bytes_in_flight(),
+        ));
 
         let base_url = self
             .config
@@ -42,6 +188,13 @@ impl LlamaParseBackend {
             .clone()
             .unwrap_or_else(|| std::env::var("LLAMA_CLOUD_API_KEY").unwrap_or_default());
 
+        let progress: UploadProgress = Arc::new(|file_path: &str, sent: u64, total: u64| {
+            eprintln!(
+                "Uploading {file_path}: {}% ({sent}/{total} bytes)",
+                sent * 100 / total
+            );
+        });
+
         let mut handles = Vec::new();
         let mut results = Vec::new();
 
@@ -51,29 +204,79 @@ impl LlamaParseBackend {
                 if self.verbose {
                     eprintln!("Skipping readable file: {file_path}");
                 }
-                results.push(file_path);
+                results.push(ParsedFile {
+                    input_path: file_path.clone(),
+                    output_path: file_path,
+                    was_cached: false,
+                    backend: None,
+                    assets: Vec::new(),
+                });
                 continue;
             }
 
-            // Check cache first
-            if let Ok(cached_path) = self.cache_manager.get_cached_result(&file_path).await {
-                if self.verbose {
-                    eprintln!("Using cached result for: {file_path}");
+            // Check cache first. A result with failed pages only needs those pages parsed again.
+            let mut retry_pages = None;
+            if let Ok(cached) = self.cache_manager.get_cached_entry(&file_path).await {
+                match PageSelection::from_pages(cached.failed_pages.iter().copied()) {
+                    Some(failed) if self.chain.contains(&ParseBackendKind::LlamaParse) => {
+                        if self.verbose {
+                            eprintln!("Retrying failed pages {failed} of {file_path}");
+                        }
+                        retry_pages = Some(failed);
+                    }
+                    _ => {
+                        if self.verbose {
+                            eprintln!("Using cached result for: {file_path}");
+                        }
+                        // An older result parsed without --save-assets may sit next to stale images
+                        let assets = if cached.assets_saved {
+                            list_assets(&self.cache_manager.assets_dir(&file_path))
+                        } else {
+                            Vec::new()
+                        };
+                        results.push(ParsedFile {
+                            assets,
+                            input_path: file_path,
+                            output_path: cached.parsed_path,
+                            was_cached: true,
+                            backend: cached.backend,
+                        });
+                        continue;
+                    }
                 }
-                results.push(cached_path);
+            }
+
+            // Cheap local backends next
+            if retry_pages.is_none()
+                && let Some(parsed) = self.try_local_backends(&file_path).await
+            {
+                results.push(parsed);
+                continue;
+            }
+
+            if !self.chain.contains(&ParseBackendKind::LlamaParse) {
+                eprintln!(
+                    "Error processing file: no backend produced acceptable output for {file_path}"
+                );
                 continue;
             }
 
-            let semaphore = Arc::clone(&semaphore);
+            let throttle = Arc::clone(&throttle);
             let base_url = base_url.clone();
             let api_key = api_key.clone();
             let config = self.config.clone();
-            let cache_manager = CacheManager::new(self.cache_manager.cache_dir.clone());
-            let client = ParseClient::new();
+            let cache_manager = self.cache_manager.clone();
+            let mut client = ParseClient::new()
+                .with_throttle(Arc::clone(&throttle))
+                .with_assets(self.cache_manager.save_assets);
+            if self.verbose {
+                client = client.with_progress(Arc::clone(&progress));
+            }
             let verbose = self.verbose;
 
+            let input_path = file_path.clone();
             let handle = tokio::spawn(async move {
-                let _permit = semaphore.acquire_owned().await.unwrap();
+                let _slot = throttle.acquire_slot().await;
 
                 Self::process_single_document(
                     client,
@@ -82,19 +285,26 @@ impl LlamaParseBackend {
                     api_key,
                     config,
                     cache_manager,
+                    retry_pages,
                     verbose,
                 )
                 .await
             });
 
-            handles.push(handle);
+            handles.push((input_path, handle));
         }
 
         // Wait for all tasks to complete
-        for handle in handles {
+        for (input_path, handle) in handles {
             let result = handle.await?;
             match result {
-                Ok(path) => results.push(path),
+                Ok((output_path, assets)) => results.push(ParsedFile {
+                    input_path,
+                    output_path,
+                    was_cached: false,
+                    backend: Some(ParseBackendKind::LlamaParse.as_str().to_string()),
+                    assets,
+                }),
                 Err(e) => eprintln!("Error processing file: {e:?}"),
             }
         }
@@ -102,38 +312,125 @@ impl LlamaParseBackend {
         Ok(results)
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn process_single_document(
         client: ParseClient,
         file_path: String,
         base_url: String,
         api_key: String,
-        config: LlamaParseConfig,
+        mut config: LlamaParseConfig,
         cache_manager: CacheManager,
+        retry_pages: Option<PageSelection>,
         verbose: bool,
-    ) -> Result<String, JobError> {
+    ) -> Result<(String, Vec<String>), JobError> {
         if verbose {
             eprintln!("Processing file: {file_path}");
         }
 
-        // Create job with retry
-        let retval = client
-            .create_parse_job_with_retry(&file_path, &base_url, &api_key, &config)
-            .await?;
+        let _timed = telemetry::timed(
+            Operation::ParseJob,
+            &[("backend", ParseBackendKind::LlamaParse.as_str())],
+        );
+
+        let ledger = JobLedger::new(&cache_manager.cache_dir);
+        if let Some(retry_pages) = &retry_pages {
+            set_target_pages(&mut config, retry_pages);
+        }
+        let pages = retry_pages.as_ref().or(cache_manager.pages.as_ref());
+        let mut output = None;
+
+        // Pick up a job submitted by an earlier run that was interrupted
+        if let Some(job) = ledger.resumable(&file_path, &base_url, pages)? {
+            if verbose {
+                eprintln!("Resuming parse job {} for {file_path}", job.job_id);
+            }
+            match client
+                .poll_for_result_with_retry(
+                    &job.job_id,
+                    &job.expand_key,
+                    &base_url,
+                    &api_key,
+                    &config,
+                )
+                .await
+            {
+                Ok(result) => output = Some(result),
+                Err(JobError::TimeoutError) => return Err(JobError::TimeoutError),
+                Err(e) => eprintln!(
+                    "Could not resume parse job {} for {file_path} ({e}), uploading it again",
+                    job.job_id
+                ),
+            }
+        }
+
+        let output = match output {
+            Some(output) => output,
+            None => {
+                // Create job with retry
+                let retval = client
+                    .create_parse_job_with_retry(&file_path, &base_url, &api_key, &config)
+                    .await?;
+                ledger.record(
+                    &file_path,
+                    PendingJob::new(&file_path, &base_url, pages, &retval)?,
+                )?;
+
+                // Poll for result with retry
+                client
+                    .poll_for_result_with_retry(
+                        &retval.job_id,
+                        &retval.expand_key,
+                        &base_url,
+                        &api_key,
+                        &config,
+                    )
+                    .await?
+            }
+        };
+
+        if !output.failed_pages.is_empty() {
+            eprintln!(
+                "{} page(s) of {file_path} failed to parse and will be retried on the next run",
+                output.failed_pages.len()
+            );
+        }
+
+        // Retried pages are merged into the cached ones; a new parse replaces them
+        let merge = retry_pages.is_some();
+        let mut pages = output.pages;
+        let mut assets = Vec::new();
+        if cache_manager.save_assets {
+            let assets_dir = cache_manager.assets_dir(&file_path);
+            let saved = save_assets(&client, &assets_dir, &output.images, !merge).await?;
+            for (_, content) in &mut pages {
+                *content = saved.rewrite_links(content);
+            }
+            assets = list_assets(&assets_dir);
+        }
 
-        // Poll for result with retry
-        let markdown_content = client
-            .poll_for_result_with_retry(
-                &retval.job_id,
-                &retval.expand_key,
-                &base_url,
-                &api_key,
-                &config,
+        // Write results to disk
+        let output_path = cache_manager
+            .write_page_results(
+                &file_path,
+                &pages,
+                &output.failed_pages,
+                merge,
+                ParseBackendKind::LlamaParse.as_str(),
             )
             .await?;
+        ledger.remove(&file_path)?;
+        Ok((output_path, assets))
+    }
+}
 
-        // Write results to disk
-        cache_manager
-            .write_results_to_disk(&file_path, &markdown_content)
-            .await
+/// Have LlamaParse parse only `pages`, which it takes 1-based like `1-10,15` in
+/// `page_ranges.target_pages`
+fn set_target_pages(config: &mut LlamaParseConfig, pages: &PageSelection) {
+    let page_ranges = config
+        .parse_kwargs
+        .entry("page_ranges".to_string())
+        .or_insert_with(|| Value::Object(Default::default()));
+    if let Some(page_ranges) = page_ranges.as_object_mut() {
+        page_ranges.insert("target_pages".to_string(), Value::String(pages.to_string()));
     }
 }
@@ -1,24 +1,224 @@
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::UNIX_EPOCH;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::metadata::DocumentMetadata;
+use crate::parse::assets::ASSETS_DIR_SUFFIX;
+use crate::parse::email::ATTACHMENTS_DIR_SUFFIX;
 use crate::parse::error::JobError;
+use crate::parse::jobs::PENDING_JOBS_FILE;
+use crate::parse::local::{ParseBackendKind, pdf_metadata};
+use crate::parse::pages::{PageSelection, join_pages};
+
+/// Maps cache keys back to the original file paths
+const INDEX_FILE: &str = "index.json";
+
+/// Suffix of the directory next to a cached result that holds each of its pages
+const PAGES_DIR_SUFFIX: &str = ".md.pages";
+
+/// Lock file next to the index, held while the index is read, changed and written back
+const INDEX_LOCK_FILE: &str = "index.lock";
+
+/// Exclusive lock on the cache's `index.lock`, serializing index updates from concurrent parse
+/// tasks and processes. It is an OS advisory lock, released when dropped or when the process
+/// exits.
+struct IndexLock {
+    file: fs::File,
+}
+
+impl IndexLock {
+    fn acquire(cache_dir: &Path) -> Result<Self, JobError> {
+        fs::create_dir_all(cache_dir)?;
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(cache_dir.join(INDEX_LOCK_FILE))?;
+        file.lock()?;
+        Ok(Self { file })
+    }
+}
+
+impl Drop for IndexLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileMetadata {
     pub modified_time: u64,
     pub size: u64,
     pub parsed_path: String,
+    /// Backend that produced the cached result (missing for caches written before fallback chains)
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Absolute path of the original file
+    #[serde(default)]
+    pub source_path: String,
+    /// SHA-256 of the original file, used to keep the cache valid when only the mtime changed
+    #[serde(default)]
+    pub content_hash: String,
+    /// Whether the document's images were downloaded next to the result
+    #[serde(default)]
+    pub assets_saved: bool,
+    /// Pages that failed to parse and are retried on the next run
+    #[serde(default)]
+    pub failed_pages: Vec<u32>,
+    /// Title, author and creation date of the original document (PDFs only), which workspaces
+    /// store with the parsed markdown
+    #[serde(default, skip_serializing_if = "DocumentMetadata::is_empty")]
+    pub document: DocumentMetadata,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheIndexEntry {
+    pub source_path: String,
+    pub parsed_path: String,
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Bytes on disk for the parsed output and its metadata (0 for entries written before
+    /// cache accounting; measured from disk when needed)
+    #[serde(default)]
+    pub size_bytes: u64,
+    /// Unix timestamp (seconds) of the last write or cache hit
+    #[serde(default)]
+    pub last_accessed: u64,
+    /// Pages the result was parsed from, when not the whole document
+    #[serde(default)]
+    pub pages: Option<String>,
+}
+
+impl CacheIndexEntry {
+    fn prefix(&self) -> &str {
+        self.parsed_path
+            .strip_suffix(".md")
+            .unwrap_or(&self.parsed_path)
+    }
+
+    fn metadata_path(&self) -> PathBuf {
+        PathBuf::from(format!("{}.metadata.json", self.prefix()))
+    }
+
+    /// Directories kept next to the markdown: extracted images, per-page results and email
+    /// attachments
+    fn entry_dirs(&self) -> [PathBuf; 3] {
+        [
+            PathBuf::from(format!("{}{ASSETS_DIR_SUFFIX}", self.prefix())),
+            PathBuf::from(format!("{}{PAGES_DIR_SUFFIX}", self.prefix())),
+            PathBuf::from(format!("{}{ATTACHMENTS_DIR_SUFFIX}", self.prefix())),
+        ]
+    }
+
+    /// Recorded size, or the size on disk for entries without one
+    fn disk_size(&self) -> u64 {
+        if self.size_bytes > 0 {
+            return self.size_bytes;
+        }
+        [PathBuf::from(&self.parsed_path), self.metadata_path()]
+            .iter()
+            .filter_map(|p| fs::metadata(p).ok())
+            .map(|m| m.len())
+            .sum::<u64>()
+            + self.entry_dirs().iter().map(|d| dir_size(d)).sum::<u64>()
+    }
+}
+
+/// Bounds on the parse cache, enforced on every write and by `parse cache gc`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CacheLimits {
+    /// Maximum total size of cached results, in bytes
+    pub max_cache_size: Option<u64>,
+    /// Entries not used for this many days are removed
+    pub max_age_days: Option<u64>,
+}
+
+impl CacheLimits {
+    pub fn is_unbounded(&self) -> bool {
+        self.max_cache_size.is_none() && self.max_age_days.is_none()
+    }
+}
+
+/// Outcome of a cache eviction pass
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct GcReport {
+    pub entries_removed: usize,
+    pub bytes_freed: u64,
+    pub entries_remaining: usize,
+    pub bytes_remaining: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CacheIndex {
+    /// Cache key -> entry
+    pub entries: BTreeMap<String, CacheIndexEntry>,
+}
+
+#[derive(Clone)]
 pub struct CacheManager {
     pub cache_dir: PathBuf,
+    pub limits: CacheLimits,
+    /// Page selection the cached results are for (`None` for whole documents)
+    pub pages: Option<PageSelection>,
+    /// Whether results need the document's images saved next to them
+    pub save_assets: bool,
 }
 
 impl CacheManager {
     pub fn new(cache_dir: PathBuf) -> Self {
-        Self { cache_dir }
+        Self {
+            cache_dir,
+            limits: CacheLimits::default(),
+            pages: None,
+            save_assets: false,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: CacheLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Read and write results for a selection of pages, cached apart from whole documents
+    pub fn with_pages(mut self, pages: Option<PageSelection>) -> Self {
+        self.pages = pages;
+        self
+    }
+
+    /// Only count results as cached if their images were saved too
+    pub fn with_save_assets(mut self, save_assets: bool) -> Self {
+        self.save_assets = save_assets;
+        self
+    }
+
+    /// The default cache directory, `$XDG_CACHE_HOME/semtools/parse`
+    pub fn default_cache_dir() -> anyhow::Result<PathBuf> {
+        crate::paths::parse_cache_dir()
+    }
+
+    /// Cache key for a file: a hash of its absolute path (and page selection), so files that
+    /// share a name in different directories never collide
+    pub fn cache_key(&self, file_path: &str) -> String {
+        let absolute = absolute_path(file_path);
+        let mut hasher = Sha256::new();
+        hasher.update(absolute.to_string_lossy().as_bytes());
+        if let Some(pages) = &self.pages {
+            hasher.update(format!("#pages={pages}").as_bytes());
+        }
+        hex::encode(hasher.finalize())[..16].to_string()
+    }
+
+    /// File name prefix for a cache entry, e.g. `report.pdf.3f2a9c0d1e4b5a67`
+    fn entry_prefix(&self, file_path: &str) -> String {
+        let filename = Path::new(file_path)
+            .file_name()
+            .map(|f| f.to_string_lossy().to_string())
+            .unwrap_or_default();
+        format!("{filename}.{}", self.cache_key(file_path))
     }
 
     pub fn should_skip_file(&self, file_path: &str) -> bool {
@@ -53,6 +253,12 @@ impl CacheManager {
     }
 
     pub async fn get_cached_result(&self, file_path: &str) -> Result<String, JobError> {
+        let cached_metadata = self.get_cached_entry(file_path).await?;
+        Ok(cached_metadata.parsed_path)
+    }
+
+    /// Get the full cache metadata for a file, if a valid cached result exists
+    pub async fn get_cached_entry(&self, file_path: &str) -> Result<FileMetadata, JobError> {
         let metadata = self.get_file_metadata(file_path)?;
         let metadata_path = self.get_metadata_path(file_path);
 
@@ -63,12 +269,29 @@ impl CacheManager {
         let cached_metadata: FileMetadata =
             serde_json::from_str(&fs::read_to_string(metadata_path)?)?;
 
-        // Check if file has changed
+        if !Path::new(&cached_metadata.parsed_path).exists()
+            || cached_metadata.size != metadata.size
+            || (self.save_assets && !cached_metadata.assets_saved)
+        {
+            return Err(JobError::InvalidResponse("Cache invalid".to_string()));
+        }
+
+        // Unchanged mtime is the cheap check; fall back to the content hash when only the
+        // mtime moved (e.g. after a copy or checkout)
         if cached_metadata.modified_time == metadata.modified_time
-            && cached_metadata.size == metadata.size
-            && Path::new(&cached_metadata.parsed_path).exists()
+            || (!cached_metadata.content_hash.is_empty()
+                && cached_metadata.content_hash == file_content_hash(file_path)?)
         {
-            Ok(cached_metadata.parsed_path)
+            // Record the hit for LRU eviction; only needed when the cache is bounded
+            if !self.limits.is_unbounded() {
+                let key = self.cache_key(file_path);
+                self.update_index(|index| {
+                    if let Some(entry) = index.entries.get_mut(&key) {
+                        entry.last_accessed = now_secs();
+                    }
+                })?;
+            }
+            Ok(cached_metadata)
         } else {
             Err(JobError::InvalidResponse("Cache invalid".to_string()))
         }
@@ -88,30 +311,121 @@ impl CacheManager {
             modified_time,
             size: metadata.len(),
             parsed_path: String::new(), // Will be set later
+            backend: None,
+            source_path: absolute_path(file_path).to_string_lossy().to_string(),
+            content_hash: String::new(),
+            assets_saved: false,
+            failed_pages: Vec::new(),
+            document: DocumentMetadata::default(),
         })
     }
 
     pub fn get_metadata_path(&self, file_path: &str) -> PathBuf {
-        let path = Path::new(file_path);
-        let filename = path.file_name().unwrap().to_str().unwrap();
-        self.cache_dir.join(format!("{filename}.metadata.json"))
+        self.cache_dir
+            .join(format!("{}.metadata.json", self.entry_prefix(file_path)))
+    }
+
+    /// Directory the images of a parsed file are saved to, next to its markdown
+    pub fn assets_dir(&self, file_path: &str) -> PathBuf {
+        self.cache_dir.join(format!(
+            "{}{ASSETS_DIR_SUFFIX}",
+            self.entry_prefix(file_path)
+        ))
+    }
+
+    /// Directory the attachments of a parsed email are extracted to, so they can be parsed too
+    pub fn attachments_dir(&self, file_path: &str) -> PathBuf {
+        self.cache_dir.join(format!(
+            "{}{ATTACHMENTS_DIR_SUFFIX}",
+            self.entry_prefix(file_path)
+        ))
+    }
+
+    /// Directory each page of a parsed file is cached in, e.g. `report.pdf.<key>.md.pages/0003.md`
+    pub fn pages_dir(&self, file_path: &str) -> PathBuf {
+        self.cache_dir.join(format!(
+            "{}{PAGES_DIR_SUFFIX}",
+            self.entry_prefix(file_path)
+        ))
+    }
+
+    /// Cache a result page by page, then stitch the markdown from every cached page. With
+    /// `merge`, the pages are added to those of the previous result (when retrying its failed
+    /// pages); otherwise they replace it.
+    pub async fn write_page_results(
+        &self,
+        file_path: &str,
+        pages: &[(u32, String)],
+        failed_pages: &[u32],
+        merge: bool,
+        backend: &str,
+    ) -> Result<String, JobError> {
+        let pages_dir = self.pages_dir(file_path);
+        if !merge && pages_dir.exists() {
+            fs::remove_dir_all(&pages_dir)?;
+        }
+        fs::create_dir_all(&pages_dir)?;
+        for (page, content) in pages {
+            fs::write(pages_dir.join(format!("{page:04}.md")), content)?;
+        }
+
+        let mut cached_pages = Vec::new();
+        for entry in fs::read_dir(&pages_dir)? {
+            let path = entry?.path();
+            let page = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u32>().ok());
+            if let Some(page) = page {
+                cached_pages.push((page, fs::read_to_string(&path)?));
+            }
+        }
+        cached_pages.sort_by_key(|(page, _)| *page);
+
+        let content = join_pages(
+            cached_pages
+                .iter()
+                .map(|(page, content)| (*page, content.as_str())),
+        );
+        self.write_entry(file_path, &content, backend, failed_pages)
+            .await
     }
 
     pub async fn write_results_to_disk(
         &self,
         file_path: &str,
         markdown_content: &str,
+        backend: &str,
+    ) -> Result<String, JobError> {
+        // Pages of an earlier result must not be stitched into a later one
+        let pages_dir = self.pages_dir(file_path);
+        if pages_dir.exists() {
+            fs::remove_dir_all(pages_dir)?;
+        }
+        self.write_entry(file_path, markdown_content, backend, &[])
+            .await
+    }
+
+    async fn write_entry(
+        &self,
+        file_path: &str,
+        markdown_content: &str,
+        backend: &str,
+        failed_pages: &[u32],
     ) -> Result<String, JobError> {
         let path = Path::new(file_path);
-        let filename = path.file_name().unwrap().to_str().unwrap();
+        let key = self.cache_key(file_path);
 
         // Write the markdown content
-        let parsed_path = self.cache_dir.join(format!("{filename}.md"));
+        let parsed_path = self
+            .cache_dir
+            .join(format!("{}.md", self.entry_prefix(file_path)));
         fs::write(&parsed_path, markdown_content)?;
 
         // Write metadata
-        let metadata_path = self.cache_dir.join(format!("{filename}.metadata.json"));
+        let metadata_path = self.get_metadata_path(file_path);
         let file_metadata = fs::metadata(path)?;
+        let source_path = absolute_path(file_path).to_string_lossy().to_string();
 
         let modified_time = file_metadata
             .modified()?
@@ -119,14 +433,517 @@ impl CacheManager {
             .unwrap()
             .as_secs();
 
+        let mut document = if ParseBackendKind::PdfText.supports(file_path) {
+            pdf_metadata(file_path)
+        } else {
+            DocumentMetadata::default()
+        };
+        // Downloaded documents are cited by the URL they came from
+        document.source = crate::fetch::source_url(file_path);
+
         let metadata = FileMetadata {
             modified_time,
             size: file_metadata.len(),
             parsed_path: parsed_path.to_string_lossy().to_string(),
+            backend: Some(backend.to_string()),
+            source_path: source_path.clone(),
+            content_hash: file_content_hash(file_path)?,
+            assets_saved: self.save_assets,
+            failed_pages: failed_pages.to_vec(),
+            document,
         };
 
-        fs::write(metadata_path, serde_json::to_string_pretty(&metadata)?)?;
+        let metadata_json = serde_json::to_string_pretty(&metadata)?;
+        fs::write(metadata_path, &metadata_json)?;
+
+        self.update_index(|index| {
+            index.entries.insert(
+                key.clone(),
+                CacheIndexEntry {
+                    source_path,
+                    parsed_path: metadata.parsed_path.clone(),
+                    backend: metadata.backend.clone(),
+                    size_bytes: (markdown_content.len() + metadata_json.len()) as u64
+                        + dir_size(&self.assets_dir(file_path))
+                        + dir_size(&self.pages_dir(file_path)),
+                    last_accessed: now_secs(),
+                    pages: self.pages.as_ref().map(PageSelection::to_string),
+                },
+            );
+        })?;
+
+        // Evict on write, but never the entry that was just written
+        if !self.limits.is_unbounded() {
+            self.evict(self.limits, Some(&key))?;
+        }
+
+        Ok(metadata.parsed_path)
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.cache_dir.join(INDEX_FILE)
+    }
+
+    /// Load the cache index (empty if the cache has never been written)
+    pub fn load_index(&self) -> Result<CacheIndex, JobError> {
+        let index_path = self.index_path();
+        if !index_path.exists() {
+            return Ok(CacheIndex::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(index_path)?)?)
+    }
+
+    /// The cached parse results of `sources` (input paths, as given to the parser)
+    pub fn parsed_paths_of(&self, sources: &[String]) -> Result<Vec<String>, JobError> {
+        Ok(self
+            .load_index()?
+            .entries
+            .into_values()
+            .filter(|entry| sources.contains(&entry.source_path))
+            .map(|entry| entry.parsed_path)
+            .collect())
+    }
+
+    fn update_index(&self, update: impl FnOnce(&mut CacheIndex)) -> Result<(), JobError> {
+        let _lock = IndexLock::acquire(&self.cache_dir)?;
+        let mut index = self.load_index()?;
+        update(&mut index);
+        self.write_index(&index)
+    }
+
+    /// Replace the index through a rename, so a reader never sees it half-written
+    fn write_index(&self, index: &CacheIndex) -> Result<(), JobError> {
+        let temp_path = self.cache_dir.join(format!("{INDEX_FILE}.tmp"));
+        fs::write(&temp_path, serde_json::to_string_pretty(index)?)?;
+        fs::rename(temp_path, self.index_path())?;
+        Ok(())
+    }
+
+    /// Total size in bytes of all indexed cache entries
+    pub fn total_size(&self) -> Result<u64, JobError> {
+        Ok(self
+            .load_index()?
+            .entries
+            .values()
+            .map(CacheIndexEntry::disk_size)
+            .sum())
+    }
+
+    /// Remove entries older than `limits.max_age_days`, then the least recently used entries
+    /// until the cache fits in `limits.max_cache_size`
+    pub fn gc(&self, limits: CacheLimits) -> Result<GcReport, JobError> {
+        self.evict(limits, None)
+    }
+
+    fn evict(&self, limits: CacheLimits, keep: Option<&str>) -> Result<GcReport, JobError> {
+        let _lock = IndexLock::acquire(&self.cache_dir)?;
+        let mut index = self.load_index()?;
+        let now = now_secs();
+
+        let mut entries: Vec<(String, CacheIndexEntry, u64)> = std::mem::take(&mut index.entries)
+            .into_iter()
+            .map(|(key, entry)| {
+                let size = entry.disk_size();
+                (key, entry, size)
+            })
+            .collect();
+        // Least recently used first
+        entries.sort_by_key(|(_, entry, _)| entry.last_accessed);
+
+        let mut total: u64 = entries.iter().map(|(_, _, size)| size).sum();
+        let mut report = GcReport::default();
+
+        for (key, entry, size) in entries {
+            let protected = keep == Some(key.as_str());
+            let expired = limits
+                .max_age_days
+                .is_some_and(|days| now.saturating_sub(entry.last_accessed) > days * 86_400);
+            let over_size = limits.max_cache_size.is_some_and(|max| total > max);
+
+            if !protected && (expired || over_size) {
+                for path in [PathBuf::from(&entry.parsed_path), entry.metadata_path()] {
+                    if path.exists() {
+                        fs::remove_file(path)?;
+                    }
+                }
+                for dir in entry.entry_dirs() {
+                    if dir.exists() {
+                        fs::remove_dir_all(dir)?;
+                    }
+                }
+                total -= size;
+                report.entries_removed += 1;
+                report.bytes_freed += size;
+            } else {
+                index.entries.insert(key, entry);
+            }
+        }
+
+        report.entries_remaining = index.entries.len();
+        report.bytes_remaining = total;
+        self.write_index(&index)?;
+        Ok(report)
+    }
+
+    /// Remove every cached result (including entries from older cache layouts), returning
+    /// the number of files deleted. Pending jobs are kept so they can still be resumed.
+    pub fn clear(&self) -> Result<usize, JobError> {
+        if !self.cache_dir.exists() {
+            return Ok(0);
+        }
+        let _lock = IndexLock::acquire(&self.cache_dir)?;
+
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.cache_dir)? {
+            let path = entry?.path();
+            // The lock file stays, or another process could lock a new one while this one
+            // still holds it
+            if path.is_file()
+                && !path.ends_with(PENDING_JOBS_FILE)
+                && !path.ends_with(INDEX_LOCK_FILE)
+            {
+                fs::remove_file(&path)?;
+                removed += 1;
+            } else if path.is_dir()
+                && [ASSETS_DIR_SUFFIX, PAGES_DIR_SUFFIX, ATTACHMENTS_DIR_SUFFIX]
+                    .iter()
+                    .any(|suffix| path.to_string_lossy().ends_with(suffix))
+            {
+                removed += fs::read_dir(&path)?.count();
+                fs::remove_dir_all(&path)?;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+pub(crate) fn absolute_path(file_path: &str) -> PathBuf {
+    fs::canonicalize(file_path)
+        .or_else(|_| std::path::absolute(file_path))
+        .unwrap_or_else(|_| PathBuf::from(file_path))
+}
+
+pub(crate) fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Total size of the files directly in `dir` (0 if it does not exist)
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok()?.metadata().ok())
+                .map(|m| m.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+fn file_content_hash(file_path: &str) -> Result<String, JobError> {
+    let mut hasher = Sha256::new();
+    hasher.update(fs::read(file_path)?);
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_same_filename_in_different_dirs_does_not_collide() {
+        let cache_dir = TempDir::new().unwrap();
+        let source_dir = TempDir::new().unwrap();
+        let cache = CacheManager::new(cache_dir.path().to_path_buf());
+
+        let first = source_dir.path().join("a").join("report.pdf");
+        let second = source_dir.path().join("b").join("report.pdf");
+        for (path, content) in [(&first, "first"), (&second, "second")] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let first = first.to_str().unwrap();
+        let second = second.to_str().unwrap();
+
+        cache
+            .write_results_to_disk(first, "first parsed", "llama-parse")
+            .await
+            .unwrap();
+        cache
+            .write_results_to_disk(second, "second parsed", "llama-parse")
+            .await
+            .unwrap();
+
+        let first_cached = cache.get_cached_result(first).await.unwrap();
+        let second_cached = cache.get_cached_result(second).await.unwrap();
+        assert_ne!(first_cached, second_cached);
+        assert_eq!(fs::read_to_string(first_cached).unwrap(), "first parsed");
+        assert_eq!(fs::read_to_string(second_cached).unwrap(), "second parsed");
+
+        let index = cache.load_index().unwrap();
+        assert_eq!(index.entries.len(), 2);
+        assert!(
+            index
+                .entries
+                .values()
+                .any(|e| e.source_path.ends_with("report.pdf"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_page_selections_are_cached_apart() {
+        let cache_dir = TempDir::new().unwrap();
+        let source_dir = TempDir::new().unwrap();
+        let file = source_dir.path().join("report.pdf");
+        fs::write(&file, "pdf").unwrap();
+        let file = file.to_str().unwrap();
+
+        let whole = CacheManager::new(cache_dir.path().to_path_buf());
+        let pages = whole.clone().with_pages(Some("1-2".parse().unwrap()));
+        whole
+            .write_results_to_disk(file, "all pages", "pdf-text")
+            .await
+            .unwrap();
+
+        assert!(pages.get_cached_result(file).await.is_err());
+        pages
+            .write_results_to_disk(file, "pages 1-2", "pdf-text")
+            .await
+            .unwrap();
+
+        let cached = pages.get_cached_result(file).await.unwrap();
+        assert_eq!(fs::read_to_string(cached).unwrap(), "pages 1-2");
+        let cached = whole.get_cached_result(file).await.unwrap();
+        assert_eq!(fs::read_to_string(cached).unwrap(), "all pages");
+
+        let index = whole.load_index().unwrap();
+        assert_eq!(index.entries.len(), 2);
+        assert!(
+            index
+                .entries
+                .values()
+                .any(|e| e.pages.as_deref() == Some("1-2"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_results_without_assets_are_refetched_for_assets() {
+        let cache_dir = TempDir::new().unwrap();
+        let source_dir = TempDir::new().unwrap();
+        let file = source_dir.path().join("slides.pdf");
+        fs::write(&file, "pdf").unwrap();
+        let file = file.to_str().unwrap();
+
+        let plain = CacheManager::new(cache_dir.path().to_path_buf());
+        let with_assets = plain.clone().with_save_assets(true);
+        plain
+            .write_results_to_disk(file, "parsed", "llama-parse")
+            .await
+            .unwrap();
+        assert!(with_assets.get_cached_result(file).await.is_err());
+
+        let assets_dir = with_assets.assets_dir(file);
+        fs::create_dir_all(&assets_dir).unwrap();
+        fs::write(assets_dir.join("page_1_image_1.png"), [0u8; 100]).unwrap();
+        with_assets
+            .write_results_to_disk(file, "parsed", "llama-parse")
+            .await
+            .unwrap();
+        assert!(with_assets.get_cached_result(file).await.is_ok());
+        assert!(plain.get_cached_result(file).await.is_ok());
+        assert!(with_assets.total_size().unwrap() > 100);
+
+        // Markdown, metadata, index and the image
+        assert_eq!(with_assets.clear().unwrap(), 4);
+        assert!(!assets_dir.exists());
+    }
+
+    #[tokio::test]
+    async fn test_failed_pages_are_merged_on_retry() {
+        let cache_dir = TempDir::new().unwrap();
+        let source_dir = TempDir::new().unwrap();
+        let file = source_dir.path().join("book.pdf");
+        fs::write(&file, "pdf").unwrap();
+        let file = file.to_str().unwrap();
+        let cache = CacheManager::new(cache_dir.path().to_path_buf());
+
+        let pages = vec![(1, "one".to_string()), (3, "three".to_string())];
+        cache
+            .write_page_results(file, &pages, &[2], false, "llama-parse")
+            .await
+            .unwrap();
+        let cached = cache.get_cached_entry(file).await.unwrap();
+        assert_eq!(cached.failed_pages, vec![2]);
+        assert_eq!(
+            fs::read_to_string(&cached.parsed_path).unwrap(),
+            "<!-- page 1 -->\n\none\n\n<!-- page 3 -->\n\nthree\n\n"
+        );
+
+        // Retrying only the failed page stitches it between the cached ones
+        cache
+            .write_page_results(file, &[(2, "two".to_string())], &[], true, "llama-parse")
+            .await
+            .unwrap();
+        let cached = cache.get_cached_entry(file).await.unwrap();
+        assert!(cached.failed_pages.is_empty());
+        let content = fs::read_to_string(&cached.parsed_path).unwrap();
+        assert!(content.contains("one\n\n<!-- page 2 -->\n\ntwo\n\n<!-- page 3 -->"));
+
+        // A fresh parse replaces the cached pages
+        cache
+            .write_page_results(file, &[(1, "new".to_string())], &[], false, "llama-parse")
+            .await
+            .unwrap();
+        let cached = cache.get_cached_result(file).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(cached).unwrap(),
+            "<!-- page 1 -->\n\nnew\n\n"
+        );
+
+        // Markdown, metadata, index and one page
+        assert_eq!(cache.clear().unwrap(), 4);
+        assert!(!cache.pages_dir(file).exists());
+    }
+
+    #[test]
+    fn test_concurrent_index_updates_are_all_kept() {
+        let cache_dir = TempDir::new().unwrap();
+
+        // Each thread has its own manager, as separate processes would
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let cache = CacheManager::new(cache_dir.path().to_path_buf());
+                scope.spawn(move || {
+                    cache
+                        .update_index(|index| {
+                            index.entries.insert(
+                                format!("key{i}"),
+                                CacheIndexEntry {
+                                    source_path: format!("doc{i}.pdf"),
+                                    parsed_path: format!("doc{i}.md"),
+                                    backend: None,
+                                    size_bytes: 0,
+                                    last_accessed: 0,
+                                    pages: None,
+                                },
+                            );
+                        })
+                        .unwrap();
+                });
+            }
+        });
+
+        let cache = CacheManager::new(cache_dir.path().to_path_buf());
+        assert_eq!(cache.load_index().unwrap().entries.len(), 8);
+        assert!(!cache_dir.path().join(format!("{INDEX_FILE}.tmp")).exists());
+    }
+
+    #[tokio::test]
+    async fn test_clear_removes_entries() {
+        let cache_dir = TempDir::new().unwrap();
+        let source_dir = TempDir::new().unwrap();
+        let cache = CacheManager::new(cache_dir.path().to_path_buf());
+
+        let file = source_dir.path().join("doc.pdf");
+        fs::write(&file, "content").unwrap();
+        let file = file.to_str().unwrap();
+
+        cache
+            .write_results_to_disk(file, "parsed", "llama-parse")
+            .await
+            .unwrap();
+
+        // Markdown, metadata and index
+        assert_eq!(cache.clear().unwrap(), 3);
+        assert!(cache.get_cached_result(file).await.is_err());
+        assert!(cache.load_index().unwrap().entries.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_size_limit_evicts_least_recently_used() {
+        let cache_dir = TempDir::new().unwrap();
+        let source_dir = TempDir::new().unwrap();
+        let cache = CacheManager::new(cache_dir.path().to_path_buf());
+
+        let mut files = Vec::new();
+        for name in ["old.pdf", "used.pdf", "new.pdf"] {
+            let path = source_dir.path().join(name);
+            fs::write(&path, name).unwrap();
+            files.push(path.to_str().unwrap().to_string());
+        }
+
+        cache
+            .write_results_to_disk(&files[0], &"a".repeat(1000), "llama-parse")
+            .await
+            .unwrap();
+        cache
+            .write_results_to_disk(&files[1], &"b".repeat(1000), "llama-parse")
+            .await
+            .unwrap();
+
+        // Make "old" the least recently used entry
+        let old_key = cache.cache_key(&files[0]);
+        cache
+            .update_index(|index| index.entries.get_mut(&old_key).unwrap().last_accessed = 1)
+            .unwrap();
+
+        // Room for two entries: writing a third evicts the least recently used one
+        let entry_size = cache.total_size().unwrap() / 2;
+        let cache = cache.with_limits(CacheLimits {
+            max_cache_size: Some(entry_size * 2 + entry_size / 2),
+            max_age_days: None,
+        });
+        cache
+            .write_results_to_disk(&files[2], &"c".repeat(1000), "llama-parse")
+            .await
+            .unwrap();
+
+        assert!(cache.get_cached_result(&files[0]).await.is_err());
+        assert!(cache.get_cached_result(&files[1]).await.is_ok());
+        assert!(cache.get_cached_result(&files[2]).await.is_ok());
+        assert_eq!(cache.load_index().unwrap().entries.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_gc_removes_expired_entries() {
+        let cache_dir = TempDir::new().unwrap();
+        let source_dir = TempDir::new().unwrap();
+        let cache = CacheManager::new(cache_dir.path().to_path_buf());
+
+        let stale = source_dir.path().join("stale.pdf");
+        let fresh = source_dir.path().join("fresh.pdf");
+        fs::write(&stale, "stale").unwrap();
+        fs::write(&fresh, "fresh").unwrap();
+        let (stale, fresh) = (stale.to_str().unwrap(), fresh.to_str().unwrap());
+
+        for file in [stale, fresh] {
+            cache
+                .write_results_to_disk(file, "parsed", "llama-parse")
+                .await
+                .unwrap();
+        }
+        let stale_key = cache.cache_key(stale);
+        cache
+            .update_index(|index| {
+                index.entries.get_mut(&stale_key).unwrap().last_accessed = now_secs() - 31 * 86_400
+            })
+            .unwrap();
+
+        let report = cache
+            .gc(CacheLimits {
+                max_cache_size: None,
+                max_age_days: Some(30),
+            })
+            .unwrap();
 
-        Ok(parsed_path.to_string_lossy().to_string())
+        assert_eq!(report.entries_removed, 1);
+        assert_eq!(report.entries_remaining, 1);
+        assert!(cache.get_cached_result(stale).await.is_err());
+        assert!(cache.get_cached_result(fresh).await.is_ok());
+        // Markdown and metadata of the evicted entry are gone; the index and its lock remain
+        assert_eq!(fs::read_dir(cache_dir.path()).unwrap().count(), 4);
     }
 }
@@ -1,18 +1,29 @@
-use reqwest::{Client, multipart};
+use futures::TryStreamExt;
+use reqwest::{Body, Client, multipart};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 use tokio::time::sleep;
+use tokio_util::io::ReaderStream;
 
 use crate::parse::config::LlamaParseConfig;
 use crate::parse::error::JobError;
+use crate::parse::throttle::{Throttle, is_overload_status};
+use crate::usage::record_pages;
 
 const DEFAULT_PARSE_TIER: &str = "cost_effective";
 const DEFAULT_PARSE_VERSION: &str = "latest";
 
+/// Upload progress is reported each time another this many percent of a file was sent
+const PROGRESS_STEP_PERCENT: u64 = 10;
+
+/// Called with `(file_path, bytes_sent, total_bytes)` as an upload progresses
+pub type UploadProgress = Arc<dyn Fn(&str, u64, u64) + Send + Sync>;
+
 #[derive(Debug)]
 pub struct CreateParseJobRetVal {
     pub job_id: String,
@@ -38,6 +49,38 @@ struct JobStatus {
 struct JobResult {
     markdown: Option<Markdown>,
     text: Option<Text>,
+    #[serde(default)]
+    images_content_metadata: Option<ImagesContentMetadata>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ImagesContentMetadata {
+    #[serde(default)]
+    images: Vec<ImageMetadata>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ImageMetadata {
+    filename: String,
+    presigned_url: Option<String>,
+}
+
+/// An image extracted from a parsed document, downloadable from `url`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageAsset {
+    pub filename: String,
+    pub url: String,
+}
+
+/// Parsed content of a completed job
+#[derive(Debug)]
+pub struct JobOutput {
+    /// `(page number, content)` of each page that parsed
+    pub pages: Vec<(u32, String)>,
+    /// Pages LlamaParse could not parse
+    pub failed_pages: Vec<u32>,
+    /// Images the markdown links to, when the client was asked for assets
+    pub images: Vec<ImageAsset>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -77,61 +120,123 @@ enum MarkdownPage {
     Failure(MarkdownPageFailure),
 }
 
+/// `(page number, content)` of the pages that parsed, and the numbers of those that failed
+type ParsedPages = (Vec<(u32, String)>, Vec<u32>);
+
 impl Markdown {
-    fn get_content(&self) -> String {
-        let mut content = String::new();
+    fn get_pages(&self) -> ParsedPages {
+        let mut pages = Vec::new();
+        let mut failed_pages = Vec::new();
         for page in &self.pages {
             match page {
-                MarkdownPage::Success(p) => {
-                    content += &p.markdown;
-                    content += "\n\n";
+                MarkdownPage::Success(p) => pages.push((p.page_number, p.markdown.clone())),
+                MarkdownPage::Failure(p) => {
+                    eprintln!(
+                        "An error occurred while parsing page {:?}: {}",
+                        p.page_number, p.error
+                    );
+                    failed_pages.push(p.page_number);
                 }
-                MarkdownPage::Failure(p) => eprintln!(
-                    "An error occurred while parsing page {:?}: {}",
-                    p.page_number, p.error
-                ),
             }
         }
-        content
+        (pages, failed_pages)
     }
 }
 
 impl Text {
-    fn get_content(&self) -> String {
-        let mut content = String::new();
-        for page in &self.pages {
-            content += &page.text;
-            content += "\n\n";
-        }
-        content
+    fn get_pages(&self) -> Vec<(u32, String)> {
+        self.pages
+            .iter()
+            .map(|page| (page.page_number, page.text.clone()))
+            .collect()
     }
 }
 
 impl JobResult {
-    fn get_markdown(&self) -> anyhow::Result<String> {
+    fn get_pages(&self) -> anyhow::Result<ParsedPages> {
         match &self.markdown {
-            Some(m) => Ok(m.get_content()),
+            Some(m) => Ok(m.get_pages()),
             None => match &self.text {
-                Some(t) => Ok(t.get_content()),
+                Some(t) => Ok((t.get_pages(), Vec::new())),
                 None => Err(anyhow::anyhow!(
                     "Could not produce a parsing result for the current document"
                 )),
             },
         }
     }
+
+    fn images(&self) -> Vec<ImageAsset> {
+        self.images_content_metadata
+            .iter()
+            .flat_map(|metadata| &metadata.images)
+            .filter_map(|image| {
+                Some(ImageAsset {
+                    filename: image.filename.clone(),
+                    url: image.presigned_url.clone()?,
+                })
+            })
+            .collect()
+    }
 }
 
 pub struct ParseClient {
     client: Client,
+    throttle: Option<Arc<Throttle>>,
+    progress: Option<UploadProgress>,
+    assets: bool,
 }
 
 impl ParseClient {
     pub fn new() -> Self {
         Self {
             client: Client::new(),
+            throttle: None,
+            progress: None,
+            assets: false,
         }
     }
 
+    /// Also fetch the images extracted from each document along with its content
+    pub fn with_assets(mut self, assets: bool) -> Self {
+        self.assets = assets;
+        self
+    }
+
+    /// Report upload progress, every `PROGRESS_STEP_PERCENT` percent of each file
+    pub fn with_progress(mut self, progress: UploadProgress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Share a throttle with other clients: upload sizes count against its byte budget, and
+    /// response statuses adjust its concurrency limit
+    pub fn with_throttle(mut self, throttle: Arc<Throttle>) -> Self {
+        self.throttle = Some(throttle);
+        self
+    }
+
+    fn record_status(&self, status: reqwest::StatusCode) {
+        if let Some(throttle) = &self.throttle {
+            throttle.record_status(status);
+        }
+    }
+
+    async fn create_parse_job_throttled(
+        &self,
+        file_path: &str,
+        base_url: &str,
+        api_key: &str,
+        parse_kwargs: &HashMap<String, Value>,
+    ) -> Result<CreateParseJobRetVal, JobError> {
+        // Uploads stream from disk, but the budget still bounds how much is sent at once
+        let _bytes = match &self.throttle {
+            Some(throttle) => Some(throttle.reserve_bytes(fs::metadata(file_path)?.len()).await),
+            None => None,
+        };
+        self.create_parse_job(file_path, base_url, api_key, parse_kwargs)
+            .await
+    }
+
     pub async fn create_parse_job_with_retry(
         &self,
         file_path: &str,
@@ -148,10 +253,29 @@ impl ParseClient {
 
         for attempt in 0..=config.max_retries {
             match self
-                .create_parse_job(&file_path, &base_url, &api_key, &parse_kwargs)
+                .create_parse_job_throttled(&file_path, &base_url, &api_key, &parse_kwargs)
                 .await
             {
                 Ok(retval) => return Ok(retval),
+                Err(JobError::HttpStatus(status, detail)) if is_overload_status(status) => {
+                    last_error = Some(format!("{status}: {detail}"));
+
+                    if attempt == config.max_retries {
+                        return Err(JobError::RetryExhausted(format!(
+                            "Job creation failed after {} attempts. Last error: {status}: {detail}",
+                            config.max_retries + 1
+                        )));
+                    }
+
+                    let delay_ms = config.retry_delay_ms(attempt);
+                    eprintln!(
+                        "Job creation was throttled (attempt {}/{}): {status}. Retrying in {delay_ms}ms...",
+                        attempt + 1,
+                        config.max_retries + 1,
+                    );
+
+                    sleep(Duration::from_millis(delay_ms)).await;
+                }
                 Err(JobError::HttpError(err)) => {
                     last_error = Some(err.to_string());
 
@@ -178,10 +302,7 @@ impl ParseClient {
                         return Err(JobError::HttpError(err));
                     }
 
-                    // Calculate backoff delay
-                    let delay = config.retry_delay_ms as f64
-                        * config.backoff_multiplier.powi(attempt as i32);
-                    let delay_ms = delay as u64;
+                    let delay_ms = config.retry_delay_ms(attempt);
 
                     eprintln!(
                         "Job creation failed (attempt {}/{}): {}. Retrying in {}ms...",
@@ -211,7 +332,7 @@ impl ParseClient {
         base_url: &str,
         api_key: &str,
         config: &LlamaParseConfig,
-    ) -> Result<String, JobError> {
+    ) -> Result<JobOutput, JobError> {
         let job_id = job_id.to_string();
         let base_url = base_url.to_string();
         let api_key = api_key.to_string();
@@ -257,10 +378,7 @@ impl ParseClient {
                         return Err(JobError::HttpError(err));
                     }
 
-                    // Calculate backoff delay
-                    let delay = config.retry_delay_ms as f64
-                        * config.backoff_multiplier.powi(attempt as i32);
-                    let delay_ms = delay as u64;
+                    let delay_ms = config.retry_delay_ms(attempt);
 
                     eprintln!(
                         "Polling failed (attempt {}/{}): {}. Retrying in {}ms...",
@@ -294,14 +412,29 @@ impl ParseClient {
         api_key: &str,
         parse_kwargs: &HashMap<String, Value>,
     ) -> Result<CreateParseJobRetVal, JobError> {
-        let file_content = fs::read(file_path)?;
         let filename = Path::new(file_path).file_name().unwrap().to_str().unwrap();
 
         let mime_type = mime_guess::from_path(file_path)
             .first_or_octet_stream()
             .to_string();
 
-        let file_part = multipart::Part::bytes(file_content)
+        // Stream the file from disk instead of reading it into memory
+        let file = tokio::fs::File::open(file_path).await?;
+        let total = file.metadata().await?.len();
+        let progress = self.progress.clone();
+        let path = file_path.to_string();
+        let mut sent = 0;
+        let stream = ReaderStream::new(file).inspect_ok(move |chunk| {
+            let before = sent;
+            sent += chunk.len() as u64;
+            if let Some(progress) = &progress
+                && crosses_progress_step(before, sent, total)
+            {
+                progress(&path, sent, total);
+            }
+        });
+
+        let file_part = multipart::Part::stream_with_length(Body::wrap_stream(stream), total)
             .file_name(filename.to_string())
             .mime_str(&mime_type)
             .map_err(|e| JobError::InvalidResponse(e.to_string()))?;
@@ -321,14 +454,8 @@ impl ParseClient {
             );
         }
         let expand_key = match parse_kwargs.get("tier") {
-            Some(v) => {
-                if v.as_str() == Some("fast") {
-                    "text"
-                } else {
-                    "markdown"
-                }
-            }
-            None => "markdown",
+            Some(v) if v.as_str() == Some("fast") => "text",
+            _ => "markdown",
         };
         let config_text = serde_json::to_string(&configuration)?;
         form = form.text("configuration", config_text);
@@ -341,11 +468,14 @@ impl ParseClient {
             .send()
             .await?;
 
-        if !response.status().is_success() {
+        let status = response.status();
+        self.record_status(status);
+        if !status.is_success() {
             let error_text = response.text().await.unwrap_or_default();
-            return Err(JobError::InvalidResponse(format!(
-                "Upload failed: {error_text}"
-            )));
+            return Err(JobError::HttpStatus(
+                status,
+                format!("Upload failed: {error_text}"),
+            ));
         }
 
         let job_response: JobResponse = response.json().await?;
@@ -363,7 +493,7 @@ impl ParseClient {
         api_key: &str,
         max_timeout: u64,
         check_interval: u64,
-    ) -> Result<String, JobError> {
+    ) -> Result<JobOutput, JobError> {
         let start = SystemTime::now();
         let timeout_duration = Duration::from_secs(max_timeout);
 
@@ -383,7 +513,14 @@ impl ParseClient {
                 .send()
                 .await?;
 
-            if !status_response.status().is_success() {
+            let status = status_response.status();
+            self.record_status(status);
+            // The job is unknown or not ours (e.g. expired); polling again will not help
+            if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                let detail = status_response.text().await.unwrap_or_default();
+                return Err(JobError::HttpStatus(status, detail));
+            }
+            if !status.is_success() {
                 let detail = status_response.text().await?;
                 eprintln!("An error occurred: {}\nRetrying...", detail);
                 continue; // Retry on error
@@ -394,11 +531,16 @@ impl ParseClient {
             match job_status.job.status.as_str() {
                 "COMPLETED" => {
                     // Get the result
+                    let expand = if self.assets {
+                        format!("{expand_key},images_content_metadata")
+                    } else {
+                        expand_key.to_string()
+                    };
                     let result_response = self
                         .client
                         .get(format!("{base_url}/api/v2/parse/{job_id}"))
                         .header("Authorization", format!("Bearer {api_key}"))
-                        .query(&[("expand", expand_key)])
+                        .query(&[("expand", expand)])
                         .send()
                         .await?;
 
@@ -409,8 +551,13 @@ impl ParseClient {
                     }
 
                     let job_result: JobResult = result_response.json().await?;
-                    let content = job_result.get_markdown()?;
-                    return Ok(content);
+                    let (pages, failed_pages) = job_result.get_pages()?;
+                    record_pages(pages.len());
+                    return Ok(JobOutput {
+                        pages,
+                        failed_pages,
+                        images: job_result.images(),
+                    });
                 }
                 "PENDING" | "RUNNING" => {
                     // Continue polling
@@ -431,6 +578,18 @@ impl ParseClient {
             }
         }
     }
+
+    /// Download an extracted image to `dest`. Image URLs are presigned, so no API key is sent.
+    pub async fn download_asset(&self, url: &str, dest: &Path) -> Result<(), JobError> {
+        let response = self.client.get(url).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let detail = response.text().await.unwrap_or_default();
+            return Err(JobError::HttpStatus(status, detail));
+        }
+        tokio::fs::write(dest, response.bytes().await?).await?;
+        Ok(())
+    }
 }
 
 impl Default for ParseClient {
@@ -438,3 +597,60 @@ impl Default for ParseClient {
         Self::new()
     }
 }
+
+/// Whether sending `before..after` of `total` bytes completes another `PROGRESS_STEP_PERCENT`
+fn crosses_progress_step(before: u64, after: u64, total: u64) -> bool {
+    if total == 0 {
+        return false;
+    }
+    let step = |sent: u64| sent * 100 / total / PROGRESS_STEP_PERCENT;
+    step(after) > step(before)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crosses_progress_step() {
+        assert!(!crosses_progress_step(0, 50, 1000));
+        assert!(crosses_progress_step(50, 100, 1000));
+        assert!(!crosses_progress_step(100, 150, 1000));
+        // A single chunk can cover several steps, but is reported once
+        assert!(crosses_progress_step(0, 1000, 1000));
+        assert!(!crosses_progress_step(0, 0, 0));
+    }
+
+    #[test]
+    fn test_job_result_pages_and_images() {
+        let result: JobResult = serde_json::from_str(
+            r#"{
+                "markdown": {"pages": [
+                    {"markdown": "![chart](page_1_chart.png)", "page_number": 1},
+                    {"error": "timed out", "page_number": 2, "success": false}
+                ]},
+                "images_content_metadata": {"images": [
+                    {"filename": "page_1_chart.png", "presigned_url": "https://files.example.com/chart"},
+                    {"filename": "page_2_pending.png", "presigned_url": null}
+                ]}
+            }"#,
+        )
+        .unwrap();
+        let (pages, failed_pages) = result.get_pages().unwrap();
+        assert_eq!(pages, vec![(1, "![chart](page_1_chart.png)".to_string())]);
+        assert_eq!(failed_pages, vec![2]);
+        assert_eq!(
+            result.images(),
+            vec![ImageAsset {
+                filename: "page_1_chart.png".to_string(),
+                url: "https://files.example.com/chart".to_string(),
+            }]
+        );
+
+        // Results fetched without images have none
+        let result: JobResult =
+            serde_json::from_str(r#"{"text": {"pages": [{"text": "hi", "page_number": 1}]}}"#)
+                .unwrap();
+        assert!(result.images().is_empty());
+    }
+}
@@ -4,9 +4,16 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
+use crate::parse::cache::CacheLimits;
+use crate::parse::gdrive::GdriveConfig;
+use crate::parse::throttle::DEFAULT_MAX_BYTES_IN_FLIGHT;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct LlamaParseConfig {
     pub api_key: Option<String>,
+    /// Maximum number of concurrent parse jobs. Fewer run while the API answers with 429 or
+    /// 5xx, recovering gradually once it is healthy again.
     pub num_ongoing_requests: usize,
     pub base_url: Option<String>,
     pub parse_kwargs: HashMap<String, Value>,
@@ -15,6 +22,23 @@ pub struct LlamaParseConfig {
     pub max_retries: usize,
     pub retry_delay_ms: u64,
     pub backoff_multiplier: f64,
+    /// Fallback chain of backends to try in order, e.g. `["pdf-text", "llama-parse"]`.
+    /// Empty means LlamaParse only.
+    #[serde(default)]
+    pub backends: Vec<String>,
+    /// Maximum total size of the parse cache (`~/.cache/semtools/parse`) in bytes. Least
+    /// recently used entries are evicted when a new result is written.
+    #[serde(default)]
+    pub max_cache_size: Option<u64>,
+    /// Cached results not used for this many days are evicted
+    #[serde(default)]
+    pub max_age_days: Option<u64>,
+    /// Maximum total size in bytes of the files being uploaded at once (default: 512 MiB)
+    #[serde(default)]
+    pub max_bytes_in_flight: Option<u64>,
+    /// Credentials and export format for Google Drive and Docs URLs
+    #[serde(default)]
+    pub gdrive: Option<GdriveConfig>,
 }
 
 impl Default for LlamaParseConfig {
@@ -36,11 +60,33 @@ impl Default for LlamaParseConfig {
             max_retries: 10,
             retry_delay_ms: 1000,
             backoff_multiplier: 2.0,
+            backends: Vec::new(),
+            max_cache_size: None,
+            max_age_days: None,
+            max_bytes_in_flight: None,
+            gdrive: None,
         }
     }
 }
 
 impl LlamaParseConfig {
+    pub fn cache_limits(&self) -> CacheLimits {
+        CacheLimits {
+            max_cache_size: self.max_cache_size,
+            max_age_days: self.max_age_days,
+        }
+    }
+
+    /// Backoff before retry number `attempt + 1`, in milliseconds
+    pub fn retry_delay_ms(&self, attempt: usize) -> u64 {
+        (self.retry_delay_ms as f64 * self.backoff_multiplier.powi(attempt as i32)) as u64
+    }
+
+    pub fn max_bytes_in_flight(&self) -> u64 {
+        self.max_bytes_in_flight
+            .unwrap_or(DEFAULT_MAX_BYTES_IN_FLIGHT)
+    }
+
     pub fn from_config_file(path: &str) -> anyhow::Result<Self> {
         if !Path::new(path).exists() {
             return Ok(Self::default());
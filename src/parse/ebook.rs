@@ -0,0 +1,308 @@
+//! Ebooks converted to markdown locally. EPUB archives are unpacked in-process and their
+//! chapters rendered in reading order, each after a `<!-- chapter N -->` marker; Kindle books
+//! (`.mobi`, `.azw`, `.azw3`) are first converted to EPUB with calibre's `ebook-convert`.
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::Path;
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use zip::ZipArchive;
+
+use crate::fetch::decode_entities;
+use crate::parse::error::JobError;
+use crate::parse::html::document_to_markdown;
+
+/// Where every EPUB names its package file
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+
+/// Marker placed before each chapter of a parsed ebook, so citations can point at chapters
+pub fn chapter_marker(chapter: usize) -> String {
+    format!("<!-- chapter {chapter} -->")
+}
+
+/// Convert an `.epub`, `.mobi`, `.azw` or `.azw3` file to markdown
+pub fn file_to_markdown(file_path: &str) -> Result<String, JobError> {
+    let is_epub = Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("epub"));
+    if is_epub {
+        epub_to_markdown(fs::File::open(file_path)?)
+    } else {
+        kindle_to_markdown(file_path)
+    }
+}
+
+/// Convert a Kindle book to EPUB in a scratch directory, then parse the EPUB
+fn kindle_to_markdown(file_path: &str) -> Result<String, JobError> {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos());
+    let work_dir =
+        std::env::temp_dir().join(format!("semtools-ebook-{}-{nanos}", std::process::id()));
+    fs::create_dir_all(&work_dir)?;
+    let epub = work_dir.join("book.epub");
+
+    let result = Command::new("ebook-convert")
+        .arg(file_path)
+        .arg(&epub)
+        .output()
+        .map_err(JobError::from)
+        .and_then(|output| {
+            if !output.status.success() {
+                return Err(JobError::InvalidResponse(format!(
+                    "ebook-convert failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )));
+            }
+            epub_to_markdown(fs::File::open(&epub)?)
+        });
+    let _ = fs::remove_dir_all(&work_dir);
+    result
+}
+
+fn invalid_epub(detail: impl Display) -> JobError {
+    JobError::InvalidResponse(format!("Not a valid EPUB: {detail}"))
+}
+
+/// Convert an EPUB archive to markdown: its title and author, then every chapter of the spine
+/// with text, numbered from 1. Chapters marked `linear="no"` (notes, covers) are skipped.
+pub fn epub_to_markdown<R: Read + Seek>(reader: R) -> Result<String, JobError> {
+    let mut archive = ZipArchive::new(reader).map_err(invalid_epub)?;
+    let container = read_entry(&mut archive, CONTAINER_PATH)?;
+    let package_path = start_tags(&container)
+        .find(|(name, ..)| *name == "rootfile")
+        .and_then(|(_, attrs, _)| attributes(attrs).remove("full-path"))
+        .ok_or_else(|| invalid_epub(format!("no package file named in {CONTAINER_PATH}")))?;
+    let package = read_entry(&mut archive, &package_path)?;
+
+    // Manifest items by id, as (href, media type), and the spine's ids in reading order
+    let mut manifest: HashMap<String, (String, String)> = HashMap::new();
+    let mut spine = Vec::new();
+    let mut title = None;
+    let mut author = None;
+    for (name, attrs, text) in start_tags(&package) {
+        match name {
+            "item" => {
+                let mut attrs = attributes(attrs);
+                if let (Some(id), Some(href)) = (attrs.remove("id"), attrs.remove("href")) {
+                    let media_type = attrs.remove("media-type").unwrap_or_default();
+                    manifest.insert(id, (href, media_type));
+                }
+            }
+            "itemref" => {
+                let attrs = attributes(attrs);
+                if attrs.get("linear").map(String::as_str) != Some("no")
+                    && let Some(idref) = attrs.get("idref")
+                {
+                    spine.push(idref.clone());
+                }
+            }
+            "title" if title.is_none() => title = Some(decode_entities(text.trim())),
+            "creator" if author.is_none() => author = Some(decode_entities(text.trim())),
+            _ => {}
+        }
+    }
+
+    let mut markdown = String::new();
+    if let Some(title) = title.filter(|t| !t.is_empty()) {
+        markdown += &format!("# {title}\n\n");
+    }
+    if let Some(author) = author.filter(|a| !a.is_empty()) {
+        markdown += &format!("Author: {author}\n\n");
+    }
+
+    let mut chapters = 0;
+    for idref in &spine {
+        let Some((href, media_type)) = manifest.get(idref) else {
+            continue;
+        };
+        if !media_type.contains("html") {
+            continue;
+        }
+        let chapter = document_to_markdown(&read_entry(
+            &mut archive,
+            &resolve_href(&package_path, href),
+        )?);
+        if chapter.trim().is_empty() {
+            continue;
+        }
+        chapters += 1;
+        markdown += &chapter_marker(chapters);
+        markdown += "\n\n";
+        markdown += chapter.trim_end();
+        markdown += "\n\n";
+    }
+    if chapters == 0 {
+        return Err(invalid_epub("no chapter has any text"));
+    }
+    Ok(markdown)
+}
+
+fn read_entry<R: Read + Seek>(archive: &mut ZipArchive<R>, name: &str) -> Result<String, JobError> {
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|e| invalid_epub(format!("{name}: {e}")))?;
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes)?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Start tags of an XML document as (local name, attribute text, text up to the next tag).
+/// Namespace prefixes are dropped, so `<dc:title>` is `title`.
+fn start_tags(xml: &str) -> impl Iterator<Item = (&str, &str, &str)> {
+    xml.split('<').skip(1).filter_map(|chunk| {
+        let (tag, text) = chunk.split_once('>')?;
+        if tag.starts_with(['/', '?', '!']) {
+            return None;
+        }
+        let name_end = tag
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let name = &tag[..name_end];
+        Some((
+            name.rsplit(':').next().unwrap_or(name),
+            &tag[name_end..],
+            text,
+        ))
+    })
+}
+
+/// The `key="value"` attributes of a start tag, with character references decoded
+fn attributes(attrs: &str) -> HashMap<String, String> {
+    let mut parsed = HashMap::new();
+    let mut rest = attrs;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].split_whitespace().last().unwrap_or_default();
+        let after = rest[eq + 1..].trim_start();
+        let (value, remainder) = match after.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let quoted = &after[1..];
+                let end = quoted.find(quote).unwrap_or(quoted.len());
+                (&quoted[..end], quoted.get(end + 1..).unwrap_or_default())
+            }
+            _ => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        parsed.insert(key.to_string(), decode_entities(value));
+        rest = remainder;
+    }
+    parsed
+}
+
+/// Path inside the archive of `href`, which is relative to the package file at `base`
+fn resolve_href(base: &str, href: &str) -> String {
+    let href = percent_decode(href.split('#').next().unwrap_or_default());
+    let mut parts: Vec<&str> = base.split('/').collect();
+    parts.pop();
+    for part in href.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+    parts.join("/")
+}
+
+/// Decode `%XX` escapes, as used in hrefs for spaces and non-ASCII file names
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| s.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    fn epub(entries: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        let mut cursor = writer.finish().unwrap();
+        cursor.set_position(0);
+        cursor
+    }
+
+    #[test]
+    fn test_epub_to_markdown() {
+        let container = r#"<?xml version="1.0"?>
+<container><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#;
+        let package = r#"<?xml version="1.0"?>
+<package><metadata><dc:title>Tides &amp; Currents</dc:title><dc:creator>A. Sailor</dc:creator></metadata>
+<manifest>
+  <item id="cover" href="cover.xhtml" media-type="application/xhtml+xml"/>
+  <item id="c1" href="text/chapter%201.xhtml" media-type="application/xhtml+xml"/>
+  <item id="c2" href="text/c2.xhtml" media-type="application/xhtml+xml"/>
+  <item id="css" href="style.css" media-type="text/css"/>
+</manifest>
+<spine><itemref idref="cover" linear="no"/><itemref idref="c1"/><itemref idref="css"/><itemref idref="c2"/></spine>
+</package>"#;
+        let book = epub(&[
+            (CONTAINER_PATH, container),
+            ("OEBPS/content.opf", package),
+            (
+                "OEBPS/cover.xhtml",
+                "<html><body><p>Cover</p></body></html>",
+            ),
+            (
+                "OEBPS/text/chapter 1.xhtml",
+                "<html><head><title>x</title></head><body><h1>Low Tide</h1><p>The sea withdraws.</p></body></html>",
+            ),
+            (
+                "OEBPS/text/c2.xhtml",
+                "<html><body><h1>High Tide</h1><p>It returns.</p></body></html>",
+            ),
+        ]);
+
+        let markdown = epub_to_markdown(book).unwrap();
+        assert_eq!(
+            markdown,
+            "# Tides & Currents\n\nAuthor: A. Sailor\n\n<!-- chapter 1 -->\n\n# Low Tide\n\nThe \
+             sea withdraws.\n\n<!-- chapter 2 -->\n\n# High Tide\n\nIt returns.\n\n"
+        );
+
+        assert!(epub_to_markdown(Cursor::new(b"not a zip".to_vec())).is_err());
+    }
+
+    #[test]
+    fn test_resolve_href() {
+        assert_eq!(
+            resolve_href("OEBPS/content.opf", "text/ch%C3%A9.xhtml#s1"),
+            "OEBPS/text/ché.xhtml"
+        );
+        assert_eq!(resolve_href("OEBPS/content.opf", "../a.xhtml"), "a.xhtml");
+        assert_eq!(resolve_href("content.opf", "./a.xhtml"), "a.xhtml");
+    }
+}
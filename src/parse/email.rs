@@ -0,0 +1,358 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::parse::error::JobError;
+use crate::parse::html::html_to_markdown;
+use crate::parse::mime::Part;
+use crate::parse::msg;
+
+/// Suffix of the directory next to a cached result that holds an email's attachments
+pub const ATTACHMENTS_DIR_SUFFIX: &str = ".attachments";
+
+/// The parts of an email that end up in its markdown
+#[derive(Debug, Default)]
+pub struct Email {
+    pub subject: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub cc: Option<String>,
+    pub date: Option<String>,
+    /// The plaintext body, or the HTML body converted to markdown when there is no plaintext
+    pub body: String,
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Debug)]
+pub struct Attachment {
+    pub name: String,
+    pub content_type: String,
+    pub data: Vec<u8>,
+}
+
+/// A parsed attachment, rendered as a section after the body. `markdown` is `None` when no
+/// backend could parse it.
+#[derive(Debug)]
+pub struct AttachmentSection {
+    pub name: String,
+    pub markdown: Option<String>,
+}
+
+impl Email {
+    /// Render a metadata block, the body, then one section per parsed attachment
+    pub fn to_markdown(&self, sections: &[AttachmentSection]) -> String {
+        let mut out = format!(
+            "# {}\n\n",
+            self.subject.as_deref().unwrap_or("(no subject)")
+        );
+
+        let names: Vec<&str> = self.attachments.iter().map(|a| a.name.as_str()).collect();
+        let names = names.join(", ");
+        let metadata = [
+            ("From", self.from.as_deref()),
+            ("To", self.to.as_deref()),
+            ("Cc", self.cc.as_deref()),
+            ("Date", self.date.as_deref()),
+            (
+                "Attachments",
+                Some(names.as_str()).filter(|n| !n.is_empty()),
+            ),
+        ];
+        for (label, value) in metadata {
+            if let Some(value) = value {
+                out.push_str(&format!("- **{label}:** {value}\n"));
+            }
+        }
+
+        let body = self.body.trim();
+        if !body.is_empty() {
+            out.push('\n');
+            out.push_str(body);
+            out.push('\n');
+        }
+
+        for section in sections {
+            out.push_str(&format!("\n## Attachment: {}\n\n", section.name));
+            match section.markdown.as_deref().map(str::trim) {
+                Some(markdown) if !markdown.is_empty() => out.push_str(markdown),
+                Some(_) => out.push_str("_The attachment is empty._"),
+                None => out.push_str("_The attachment could not be parsed._"),
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Read an `.eml` (MIME) or Outlook `.msg` file
+pub fn read_email(file_path: &str) -> Result<Email, JobError> {
+    let data = fs::read(file_path)?;
+    let is_msg = Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("msg"));
+
+    if is_msg {
+        msg::read_msg(&data)
+    } else {
+        Ok(read_eml(&data))
+    }
+}
+
+/// Convert an email to markdown without parsing its attachments, which are only listed
+pub fn file_to_markdown(file_path: &str) -> Result<String, JobError> {
+    Ok(read_email(file_path)?.to_markdown(&[]))
+}
+
+pub fn read_eml(raw: &[u8]) -> Email {
+    let message = Part::parse(raw);
+    let mut email = Email {
+        subject: message.decoded_header("subject"),
+        from: message.decoded_header("from"),
+        to: message.decoded_header("to"),
+        cc: message.decoded_header("cc"),
+        date: message.decoded_header("date"),
+        ..Default::default()
+    };
+
+    let mut plain = None;
+    let mut html = None;
+    collect_parts(&message, &mut plain, &mut html, &mut email.attachments);
+    email.body = match (plain, html) {
+        (Some(plain), _) => plain.replace("\r\n", "\n"),
+        (None, Some(html)) => html_to_markdown(&html),
+        (None, None) => String::new(),
+    };
+    email
+}
+
+/// Walk the MIME tree, keeping the first plaintext and HTML bodies and every attachment
+fn collect_parts(
+    part: &Part,
+    plain: &mut Option<String>,
+    html: &mut Option<String>,
+    attachments: &mut Vec<Attachment>,
+) {
+    let content_type = part.content_type();
+    if content_type.starts_with("multipart/") {
+        for subpart in part.subparts() {
+            collect_parts(&subpart, plain, html, attachments);
+        }
+        return;
+    }
+
+    // Forwarded messages are attachments in their own right, parsed as emails again
+    if content_type == "message/rfc822" {
+        let data = part.decoded_body();
+        let name = part.filename().unwrap_or_else(|| {
+            let subject = Part::parse(&data).decoded_header("subject");
+            format!("{}.eml", subject.as_deref().unwrap_or("message"))
+        });
+        attachments.push(Attachment {
+            name,
+            content_type,
+            data,
+        });
+        return;
+    }
+
+    // Inline images are mostly logos and signatures referenced from the HTML body
+    let inline_image = content_type.starts_with("image/") && !part.is_attachment();
+    match part.filename() {
+        Some(_) if inline_image => {}
+        Some(name) => attachments.push(Attachment {
+            name,
+            content_type,
+            data: part.decoded_body(),
+        }),
+        None if part.is_attachment() => attachments.push(Attachment {
+            name: String::new(),
+            content_type,
+            data: part.decoded_body(),
+        }),
+        None if content_type == "text/plain" && plain.is_none() => *plain = Some(part.text()),
+        None if content_type == "text/html" && html.is_none() => *html = Some(part.text()),
+        None => {}
+    }
+}
+
+/// Write the attachments into `dir` (replacing what an earlier parse left there) under safe,
+/// unique file names, returning each path in the order of `email.attachments`
+pub fn save_attachments(email: &Email, dir: &Path) -> Result<Vec<PathBuf>, JobError> {
+    if dir.exists() {
+        fs::remove_dir_all(dir)?;
+    }
+    if email.attachments.is_empty() {
+        return Ok(Vec::new());
+    }
+    fs::create_dir_all(dir)?;
+
+    let mut taken = HashSet::new();
+    let mut paths = Vec::new();
+    for (index, attachment) in email.attachments.iter().enumerate() {
+        let name = attachment_file_name(attachment, index, &mut taken);
+        let path = dir.join(name);
+        fs::write(&path, &attachment.data)?;
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// The attachment's own name without any directories, with an extension so backends can pick
+/// it up, and made unique among `taken`
+fn attachment_file_name(
+    attachment: &Attachment,
+    index: usize,
+    taken: &mut HashSet<String>,
+) -> String {
+    let name = attachment.name.replace('\\', "/");
+    let mut name = Path::new(&name)
+        .file_name()
+        .map(|n| {
+            n.to_string_lossy()
+                .replace(|c: char| c.is_control() || r#":*?"<>|"#.contains(c), "_")
+                .trim()
+                .to_string()
+        })
+        .filter(|n| !n.is_empty() && !n.starts_with('.'))
+        .unwrap_or_else(|| format!("attachment-{}", index + 1));
+
+    if Path::new(&name).extension().is_none() {
+        let extension = match attachment.content_type.as_str() {
+            "text/plain" => Some("txt"),
+            "message/rfc822" => Some("eml"),
+            content_type => mime_guess::get_mime_extensions_str(content_type)
+                .and_then(|extensions| extensions.first().copied()),
+        };
+        if let Some(extension) = extension {
+            name = format!("{name}.{extension}");
+        }
+    }
+
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) => (stem.to_string(), format!(".{extension}")),
+        None => (name.clone(), String::new()),
+    };
+    let mut candidate = name;
+    let mut n = 2;
+    while !taken.insert(candidate.to_lowercase()) {
+        candidate = format!("{stem} ({n}){extension}");
+        n += 1;
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &str = "From: =?UTF-8?Q?Ren=C3=A9e?= <renee@example.com>\r\n\
+To: legal@example.com\r\n\
+Subject: Q3 contract\r\n\treview\r\n\
+Date: Tue, 1 Oct 2024 09:30:00 +0000\r\n\
+MIME-Version: 1.0\r\n\
+Content-Type: multipart/mixed; boundary=\"outer\"\r\n\
+\r\n\
+--outer\r\n\
+Content-Type: multipart/alternative; boundary=\"inner\"\r\n\
+\r\n\
+--inner\r\n\
+Content-Type: text/plain; charset=utf-8\r\n\
+Content-Transfer-Encoding: quoted-printable\r\n\
+\r\n\
+Please review the attached draft before Friday=2E\r\n\
+--inner\r\n\
+Content-Type: text/html\r\n\
+\r\n\
+<p>Please review the <b>attached</b> draft.</p>\r\n\
+--inner--\r\n\
+--outer\r\n\
+Content-Type: image/png; name=\"logo.png\"\r\n\
+Content-Disposition: inline\r\n\
+\r\n\
+PNG\r\n\
+--outer\r\n\
+Content-Type: text/csv; name=\"terms.csv\"\r\n\
+Content-Disposition: attachment; filename=\"../terms.csv\"\r\n\
+Content-Transfer-Encoding: base64\r\n\
+\r\n\
+dGVybSx2YWx1ZQpkdXJhdGlvbiwxMiBtb250aHMK\r\n\
+--outer--\r\n";
+
+    #[test]
+    fn test_read_eml() {
+        let email = read_eml(MESSAGE.as_bytes());
+        assert_eq!(email.subject.as_deref(), Some("Q3 contract review"));
+        assert_eq!(email.from.as_deref(), Some("Renée <renee@example.com>"));
+        assert_eq!(
+            email.body,
+            "Please review the attached draft before Friday."
+        );
+        assert_eq!(email.attachments.len(), 1);
+        assert_eq!(email.attachments[0].name, "../terms.csv");
+        assert_eq!(
+            email.attachments[0].data,
+            b"term,value\nduration,12 months\n"
+        );
+
+        let sections = [AttachmentSection {
+            name: "terms.csv".to_string(),
+            markdown: Some("term,value\nduration,12 months\n".to_string()),
+        }];
+        assert_eq!(
+            email.to_markdown(&sections),
+            "# Q3 contract review\n\n\
+             - **From:** Renée <renee@example.com>\n\
+             - **To:** legal@example.com\n\
+             - **Date:** Tue, 1 Oct 2024 09:30:00 +0000\n\
+             - **Attachments:** ../terms.csv\n\n\
+             Please review the attached draft before Friday.\n\n\
+             ## Attachment: terms.csv\n\n\
+             term,value\nduration,12 months\n"
+        );
+    }
+
+    #[test]
+    fn test_html_body_used_without_plaintext() {
+        let email = read_eml(
+            b"Subject: Hi\nContent-Type: text/html\n\n<html><body><p>Hello <i>there</i></p></body></html>\n",
+        );
+        assert_eq!(email.body, "Hello *there*\n");
+        assert!(email.to.is_none());
+    }
+
+    #[test]
+    fn test_save_attachments_uses_safe_unique_names() {
+        let attachment = |name: &str, content_type: &str| Attachment {
+            name: name.to_string(),
+            content_type: content_type.to_string(),
+            data: b"x".to_vec(),
+        };
+        let email = Email {
+            attachments: vec![
+                attachment("../../etc/notes.txt", "text/plain"),
+                attachment("Notes.txt", "text/plain"),
+                attachment("", "text/plain"),
+                attachment("Fwd: budget", "message/rfc822"),
+            ],
+            ..Default::default()
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        let paths = save_attachments(&email, &dir.path().join("mail.attachments")).unwrap();
+        let names: Vec<String> = paths
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "notes.txt",
+                "Notes (2).txt",
+                "attachment-3.txt",
+                "Fwd_ budget.eml"
+            ]
+        );
+        assert!(paths.iter().all(|p| p.exists()));
+    }
+}
@@ -1,6 +1,7 @@
 #[derive(Debug)]
 pub enum JobError {
     HttpError(reqwest::Error),
+    HttpStatus(reqwest::StatusCode, String),
     IoError(std::io::Error),
     TimeoutError,
     InvalidResponse(String),
@@ -44,6 +45,7 @@ impl std::fmt::Display for JobError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             JobError::HttpError(err) => write!(f, "HTTP error: {err}"),
+            JobError::HttpStatus(status, detail) => write!(f, "HTTP {status}: {detail}"),
             JobError::IoError(err) => write!(f, "IO error: {err}"),
             JobError::TimeoutError => write!(f, "Operation timed out"),
             JobError::InvalidResponse(msg) => write!(f, "Invalid response: {msg}"),
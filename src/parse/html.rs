@@ -0,0 +1,823 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::fetch::decode_entities;
+use crate::parse::error::JobError;
+use crate::parse::mime::Part;
+
+/// Elements that never have children
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Elements dropped with their content while parsing (the content of scripts and styles is not
+/// markup, so it cannot be parsed into the tree)
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "script", "style", "noscript", "template", "svg", "math", "iframe", "object", "canvas",
+    "textarea",
+];
+
+/// Page furniture that is never part of the content
+const BOILERPLATE_ELEMENTS: &[&str] = &[
+    "head", "nav", "aside", "footer", "form", "button", "select", "input", "dialog",
+];
+
+/// `class`/`id` fragments of navigation, ads and other boilerplate...
+const UNLIKELY_NAMES: &[&str] = &[
+    "advert",
+    "banner",
+    "breadcrumb",
+    "comment",
+    "cookie",
+    "footer",
+    "menu",
+    "modal",
+    "nav",
+    "newsletter",
+    "popup",
+    "promo",
+    "related",
+    "share",
+    "sidebar",
+    "social",
+    "subscribe",
+];
+
+/// ...unless the name also suggests content
+const LIKELY_NAMES: &[&str] = &[
+    "article", "body", "content", "entry", "main", "post", "story",
+];
+
+/// Elements rendered as blocks of their own rather than inline
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "caption",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+
+/// Minimum text length of the `<article>`/`<main>` element before it is trusted as the content
+const MIN_CONTENT_CHARS: usize = 200;
+
+#[derive(Debug)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    attrs: Vec<(String, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn child_elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|c| match c {
+            Node::Element(el) => Some(el),
+            Node::Text(_) => None,
+        })
+    }
+
+    /// All text below this element, as written
+    fn text(&self) -> String {
+        let mut text = String::new();
+        self.collect_text(&mut text);
+        text
+    }
+
+    fn collect_text(&self, text: &mut String) {
+        for child in &self.children {
+            match child {
+                Node::Text(t) => text.push_str(t),
+                Node::Element(el) => el.collect_text(text),
+            }
+        }
+    }
+
+    fn text_len(&self) -> usize {
+        self.text().split_whitespace().map(str::len).sum()
+    }
+
+    fn find_all<'a>(&'a self, predicate: &dyn Fn(&Element) -> bool, out: &mut Vec<&'a Element>) {
+        for el in self.child_elements() {
+            if predicate(el) {
+                out.push(el);
+            }
+            el.find_all(predicate, out);
+        }
+    }
+
+    /// Whether this element is navigation, ads or similar rather than content
+    fn is_boilerplate(&self) -> bool {
+        if BOILERPLATE_ELEMENTS.contains(&self.name.as_str()) {
+            return true;
+        }
+        if matches!(self.name.as_str(), "html" | "body" | "article" | "main") {
+            return false;
+        }
+        let names = format!(
+            "{} {}",
+            self.attr("class").unwrap_or_default(),
+            self.attr("id").unwrap_or_default()
+        )
+        .to_lowercase();
+        UNLIKELY_NAMES.iter().any(|n| names.contains(n))
+            && !LIKELY_NAMES.iter().any(|n| names.contains(n))
+    }
+}
+
+/// Convert an `.html`/`.htm` file, or the HTML part of an `.mht`/`.mhtml` archive, to markdown
+pub fn file_to_markdown(file_path: &str) -> Result<String, JobError> {
+    let content = fs::read(file_path)?;
+    let extension = Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+        .unwrap_or_default();
+
+    let html = if matches!(extension.as_str(), "mht" | "mhtml") {
+        mhtml_html_part(&content).ok_or_else(|| {
+            JobError::InvalidResponse(format!("No HTML part found in {file_path}"))
+        })?
+    } else {
+        String::from_utf8_lossy(&content).to_string()
+    };
+    Ok(html_to_markdown(&html))
+}
+
+/// Convert a web page to markdown, keeping only its main content
+pub fn html_to_markdown(html: &str) -> String {
+    let root = parse_html(html);
+    let content = main_content(&root);
+    let mut markdown = render_blocks(&content.children);
+
+    // Exported pages often carry their title only in <title>
+    if !markdown.lines().any(|l| l.starts_with("# ")) {
+        let mut titles = Vec::new();
+        root.find_all(&|el| el.name == "title", &mut titles);
+        if let Some(title) = titles.first().map(|t| collapse_whitespace(&t.text()))
+            && !title.is_empty()
+        {
+            markdown = format!("# {title}\n\n{markdown}");
+        }
+    }
+
+    if !markdown.is_empty() {
+        markdown.push('\n');
+    }
+    markdown
+}
+
+fn parse_html(html: &str) -> Element {
+    let mut stack = vec![Element {
+        name: "#root".to_string(),
+        ..Default::default()
+    }];
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        let Some(open) = rest.find('<') else {
+            push_text(&mut stack, rest);
+            break;
+        };
+        push_text(&mut stack, &rest[..open]);
+        rest = &rest[open..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+        } else if rest.starts_with("<!") || rest.starts_with("<?") {
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+        } else if let Some(end_tag) = rest.strip_prefix("</") {
+            let name_len = end_tag
+                .find(|c: char| c.is_whitespace() || c == '>')
+                .unwrap_or(end_tag.len());
+            close_element(&mut stack, &end_tag[..name_len].to_ascii_lowercase());
+            rest = end_tag.find('>').map_or("", |end| &end_tag[end + 1..]);
+        } else if rest[1..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let (element, self_closing, after) = parse_start_tag(rest);
+            rest = after;
+
+            if SKIPPED_ELEMENTS.contains(&element.name.as_str()) {
+                if !self_closing {
+                    let end_tag = format!("</{}", element.name);
+                    rest = match rest.to_ascii_lowercase().find(&end_tag) {
+                        Some(end) => rest[end..].find('>').map_or("", |i| &rest[end + i + 1..]),
+                        None => "",
+                    };
+                }
+                continue;
+            }
+
+            close_implicitly(&mut stack, &element.name);
+            if self_closing || VOID_ELEMENTS.contains(&element.name.as_str()) {
+                if let Some(parent) = stack.last_mut() {
+                    parent.children.push(Node::Element(element));
+                }
+            } else {
+                stack.push(element);
+            }
+        } else {
+            push_text(&mut stack, "<");
+            rest = &rest[1..];
+        }
+    }
+
+    while stack.len() > 1 {
+        pop_element(&mut stack);
+    }
+    stack.pop().unwrap_or_default()
+}
+
+fn push_text(stack: &mut [Element], text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    if let Some(parent) = stack.last_mut() {
+        parent.children.push(Node::Text(decode_entities(text)));
+    }
+}
+
+fn pop_element(stack: &mut Vec<Element>) {
+    if let Some(element) = stack.pop()
+        && let Some(parent) = stack.last_mut()
+    {
+        parent.children.push(Node::Element(element));
+    }
+}
+
+/// Close the innermost open `name` element, and anything left open inside it. Stray end tags
+/// are ignored.
+fn close_element(stack: &mut Vec<Element>, name: &str) {
+    if let Some(pos) = stack.iter().skip(1).rposition(|el| el.name == name) {
+        while stack.len() > pos + 1 {
+            pop_element(stack);
+        }
+    }
+}
+
+/// Close elements whose end tag HTML lets authors omit, e.g. `<li>` before the next `<li>`
+fn close_implicitly(stack: &mut Vec<Element>, opening: &str) {
+    let top = |stack: &Vec<Element>| stack.last().map(|el| el.name.clone()).unwrap_or_default();
+    match opening {
+        "li" if top(stack) == "li" => pop_element(stack),
+        "dt" | "dd" if matches!(top(stack).as_str(), "dt" | "dd") => pop_element(stack),
+        "td" | "th" if matches!(top(stack).as_str(), "td" | "th") => pop_element(stack),
+        "tr" => {
+            if matches!(top(stack).as_str(), "td" | "th") {
+                pop_element(stack);
+            }
+            if top(stack) == "tr" {
+                pop_element(stack);
+            }
+        }
+        name if BLOCK_ELEMENTS.contains(&name) && top(stack) == "p" => pop_element(stack),
+        _ => {}
+    }
+}
+
+/// Parse a start tag at the beginning of `s`, returning the element, whether it was written as
+/// self-closing (`<br/>`), and the input after the tag
+fn parse_start_tag(s: &str) -> (Element, bool, &str) {
+    let bytes = s.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && !matches!(bytes[i], b'>' | b'/') {
+        i += 1;
+    }
+    let mut element = Element {
+        name: s[1..i].to_ascii_lowercase(),
+        ..Default::default()
+    };
+    let mut self_closing = false;
+
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= bytes.len() {
+            return (element, self_closing, "");
+        }
+        match bytes[i] {
+            b'>' => return (element, self_closing, &s[i + 1..]),
+            b'/' => {
+                self_closing = true;
+                i += 1;
+                continue;
+            }
+            _ => self_closing = false,
+        }
+
+        let name_start = i;
+        while i < bytes.len()
+            && !bytes[i].is_ascii_whitespace()
+            && !matches!(bytes[i], b'=' | b'>' | b'/')
+        {
+            i += 1;
+        }
+        let name = s[name_start..i].to_ascii_lowercase();
+
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let mut value = "";
+        if i < bytes.len() && bytes[i] == b'=' {
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            if i < bytes.len() && matches!(bytes[i], b'"' | b'\'') {
+                let quote = bytes[i];
+                let value_start = i + 1;
+                i = value_start;
+                while i < bytes.len() && bytes[i] != quote {
+                    i += 1;
+                }
+                value = &s[value_start..i];
+                i = (i + 1).min(bytes.len());
+            } else {
+                let value_start = i;
+                while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                    i += 1;
+                }
+                value = &s[value_start..i];
+            }
+        }
+        if !name.is_empty() {
+            element.attrs.push((name, decode_entities(value)));
+        }
+    }
+}
+
+/// The element holding the page's main content: its `<article>`/`<main>` when it has one,
+/// otherwise the container with the most paragraph text (scored like Readability), otherwise
+/// the whole page
+/// Convert a document that is all content, such as an ebook chapter, to markdown. Unlike
+/// `html_to_markdown`, the whole body is kept rather than the block that looks most like an
+/// article.
+pub fn document_to_markdown(html: &str) -> String {
+    let root = parse_html(html);
+    let mut bodies = Vec::new();
+    root.find_all(&|el| el.name == "body", &mut bodies);
+    let body = bodies.first().copied().unwrap_or(&root);
+    let mut markdown = render_blocks(&body.children);
+    if !markdown.is_empty() {
+        markdown.push('\n');
+    }
+    markdown
+}
+
+fn main_content(root: &Element) -> &Element {
+    let mut marked = Vec::new();
+    root.find_all(
+        &|el| matches!(el.name.as_str(), "article" | "main") || el.attr("role") == Some("main"),
+        &mut marked,
+    );
+    if let Some(best) = marked.into_iter().max_by_key(|el| el.text_len())
+        && best.text_len() >= MIN_CONTENT_CHARS
+    {
+        return best;
+    }
+
+    let mut scores: HashMap<*const Element, (f64, &Element)> = HashMap::new();
+    score_paragraphs(root, &mut Vec::new(), &mut scores);
+    let best = scores
+        .into_values()
+        .map(|(score, el)| (score * (1.0 - link_density(el)), el))
+        .max_by(|a, b| a.0.total_cmp(&b.0));
+    if let Some((_, best)) = best {
+        return best;
+    }
+
+    let mut bodies = Vec::new();
+    root.find_all(&|el| el.name == "body", &mut bodies);
+    bodies.first().copied().unwrap_or(root)
+}
+
+/// Credit each paragraph's parent (and, half as much, its grandparent) with the paragraph's
+/// length and number of commas
+fn score_paragraphs<'a>(
+    element: &'a Element,
+    ancestors: &mut Vec<&'a Element>,
+    scores: &mut HashMap<*const Element, (f64, &'a Element)>,
+) {
+    if element.is_boilerplate() {
+        return;
+    }
+
+    if matches!(element.name.as_str(), "p" | "pre" | "blockquote") {
+        let text = element.text();
+        let len = text.trim().len();
+        if len >= 25 {
+            let score = 1.0 + text.matches(',').count() as f64 + (len as f64 / 100.0).min(3.0);
+            for (ancestor, weight) in ancestors.iter().rev().zip([1.0, 0.5]) {
+                scores
+                    .entry(*ancestor as *const Element)
+                    .or_insert((0.0, ancestor))
+                    .0 += score * weight;
+            }
+        }
+        return;
+    }
+
+    ancestors.push(element);
+    for child in element.child_elements() {
+        score_paragraphs(child, ancestors, scores);
+    }
+    ancestors.pop();
+}
+
+/// Share of an element's text that is link text
+fn link_density(element: &Element) -> f64 {
+    let total = element.text_len();
+    if total == 0 {
+        return 0.0;
+    }
+    let mut links = Vec::new();
+    element.find_all(&|el| el.name == "a", &mut links);
+    let link_len: usize = links.iter().map(|a| a.text_len()).sum();
+    (link_len as f64 / total as f64).min(1.0)
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Render a sequence of nodes as markdown blocks separated by blank lines
+fn render_blocks(children: &[Node]) -> String {
+    let mut blocks = Vec::new();
+    let mut inline = String::new();
+
+    for child in children {
+        match child {
+            Node::Element(el) if el.is_boilerplate() => {}
+            Node::Element(el) if BLOCK_ELEMENTS.contains(&el.name.as_str()) => {
+                flush_inline(&mut inline, &mut blocks);
+                let block = render_block(el);
+                if !block.trim().is_empty() {
+                    blocks.push(block);
+                }
+            }
+            _ => render_inline(child, &mut inline),
+        }
+    }
+    flush_inline(&mut inline, &mut blocks);
+
+    blocks.join("\n\n")
+}
+
+fn flush_inline(inline: &mut String, blocks: &mut Vec<String>) {
+    let lines: Vec<String> = inline
+        .split('\n')
+        .map(collapse_whitespace)
+        .filter(|l| !l.is_empty())
+        .collect();
+    if !lines.is_empty() {
+        blocks.push(lines.join("\n"));
+    }
+    inline.clear();
+}
+
+fn render_block(el: &Element) -> String {
+    match el.name.as_str() {
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = el.name[1..].parse::<usize>().unwrap_or(1);
+            let mut text = String::new();
+            for child in &el.children {
+                render_inline(child, &mut text);
+            }
+            let text = collapse_whitespace(&text);
+            if text.is_empty() {
+                String::new()
+            } else {
+                format!("{} {text}", "#".repeat(level))
+            }
+        }
+        "pre" => {
+            let language = el
+                .child_elements()
+                .find(|c| c.name == "code")
+                .and_then(|code| code.attr("class"))
+                .and_then(|class| {
+                    class
+                        .split_whitespace()
+                        .find_map(|c| c.strip_prefix("language-"))
+                })
+                .unwrap_or_default();
+            let text = el.text();
+            let text = text.trim_start_matches('\n').trim_end();
+            format!("```{language}\n{text}\n```")
+        }
+        "blockquote" => render_blocks(&el.children)
+            .lines()
+            .map(|l| {
+                if l.is_empty() {
+                    ">".to_string()
+                } else {
+                    format!("> {l}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        "ul" | "ol" => render_list(el),
+        "li" => indent_item("- ", &render_blocks(&el.children)),
+        "hr" => "---".to_string(),
+        "table" => render_table(el),
+        "dt" => {
+            let text = render_blocks(&el.children);
+            if text.is_empty() {
+                text
+            } else {
+                format!("**{text}**")
+            }
+        }
+        _ => render_blocks(&el.children),
+    }
+}
+
+fn render_list(list: &Element) -> String {
+    let ordered = list.name == "ol";
+    list.child_elements()
+        .filter(|el| el.name == "li")
+        .enumerate()
+        .map(|(i, item)| {
+            let marker = if ordered {
+                format!("{}. ", i + 1)
+            } else {
+                "- ".to_string()
+            };
+            // Keep list items tight
+            indent_item(
+                &marker,
+                &render_blocks(&item.children).replace("\n\n", "\n"),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Prefix the first line of a list item with its marker and indent the rest to match
+fn indent_item(marker: &str, content: &str) -> String {
+    let indent = " ".repeat(marker.len());
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, line)| match i {
+            0 => format!("{marker}{line}"),
+            _ if line.is_empty() => String::new(),
+            _ => format!("{indent}{line}"),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn render_table(table: &Element) -> String {
+    let mut rows = Vec::new();
+    collect_rows(table, &mut rows);
+    let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+
+    // Tables used for layout have a single column; their cells are just blocks
+    if width <= 1 {
+        return rows
+            .into_iter()
+            .flatten()
+            .filter(|cell| !cell.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+    }
+
+    let mut lines = Vec::new();
+    for (i, mut row) in rows.into_iter().enumerate() {
+        row.resize(width, String::new());
+        lines.push(format!("| {} |", row.join(" | ")));
+        if i == 0 {
+            lines.push(format!("|{}", " --- |".repeat(width)));
+        }
+    }
+    lines.join("\n")
+}
+
+fn collect_rows(element: &Element, rows: &mut Vec<Vec<String>>) {
+    for child in element.child_elements() {
+        match child.name.as_str() {
+            "tr" => rows.push(
+                child
+                    .child_elements()
+                    .filter(|cell| matches!(cell.name.as_str(), "td" | "th"))
+                    .map(|cell| {
+                        collapse_whitespace(&render_blocks(&cell.children)).replace('|', "\\|")
+                    })
+                    .collect(),
+            ),
+            "thead" | "tbody" | "tfoot" => collect_rows(child, rows),
+            _ => {}
+        }
+    }
+}
+
+fn render_inline(node: &Node, out: &mut String) {
+    let el = match node {
+        Node::Text(text) => {
+            for (i, word) in text.split_whitespace().enumerate() {
+                if (i > 0 || text.starts_with(char::is_whitespace))
+                    && !out.ends_with([' ', '\n'])
+                    && !out.is_empty()
+                {
+                    out.push(' ');
+                }
+                out.push_str(word);
+            }
+            if text.ends_with(char::is_whitespace) && !out.ends_with([' ', '\n']) {
+                out.push(' ');
+            }
+            return;
+        }
+        Node::Element(el) => el,
+    };
+    if el.is_boilerplate() {
+        return;
+    }
+
+    let inner = || {
+        let mut inner = String::new();
+        for child in &el.children {
+            render_inline(child, &mut inner);
+        }
+        inner
+    };
+    let wrap = |out: &mut String, marker: &str, text: String| {
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            out.push_str(&text);
+            return;
+        }
+        if text.starts_with(char::is_whitespace) && !out.ends_with([' ', '\n']) {
+            out.push(' ');
+        }
+        out.push_str(&format!("{marker}{trimmed}{marker}"));
+        if text.ends_with(char::is_whitespace) {
+            out.push(' ');
+        }
+    };
+
+    match el.name.as_str() {
+        "br" => out.push('\n'),
+        "img" => {
+            let src = el.attr("src").unwrap_or_default();
+            if !src.is_empty() && !src.starts_with("data:") {
+                let alt = collapse_whitespace(el.attr("alt").unwrap_or_default());
+                out.push_str(&format!("![{alt}]({src})"));
+            }
+        }
+        "a" => {
+            let text = inner();
+            match el.attr("href") {
+                Some(href)
+                    if !href.is_empty()
+                        && !href.starts_with('#')
+                        && !href.starts_with("javascript:")
+                        && !text.trim().is_empty() =>
+                {
+                    wrap(out, "", format!("[{}]({href})", text.trim()));
+                }
+                _ => out.push_str(&text),
+            }
+        }
+        "strong" | "b" => wrap(out, "**", inner()),
+        "em" | "i" => wrap(out, "*", inner()),
+        "s" | "del" | "strike" => wrap(out, "~~", inner()),
+        "code" | "kbd" | "samp" => wrap(out, "`", collapse_whitespace(&el.text())),
+        name => {
+            let text = inner();
+            // A block inside inline content still needs to be set apart from its neighbours
+            if BLOCK_ELEMENTS.contains(&name) && !out.ends_with([' ', '\n']) && !out.is_empty() {
+                out.push(' ');
+            }
+            out.push_str(&text);
+        }
+    }
+}
+
+/// The HTML document inside an MHTML (`.mht`) web archive
+fn mhtml_html_part(archive: &[u8]) -> Option<String> {
+    Part::parse(archive)
+        .subparts()
+        .into_iter()
+        .find(|part| part.content_type() == "text/html")
+        .map(|part| part.text())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_main_content_drops_boilerplate() {
+        let html = r#"<html><head><title>Release notes</title></head><body>
+<nav><a href="/">Home</a> | <a href="/docs">Docs</a></nav>
+<div class="sidebar"><p>Subscribe to our newsletter for weekly updates, news and offers.</p></div>
+<div id="content">
+  <h1>Version 2.0</h1>
+  <p>This release rewrites the storage engine, which makes writes <b>twice as fast</b>.</p>
+  <p>See the <a href="https://example.com/guide">upgrade guide</a> before upgrading, since the format changed.</p>
+</div>
+<footer><p>Copyright 2024, Example Inc. All rights reserved.</p></footer>
+</body></html>"#;
+
+        assert_eq!(
+            html_to_markdown(html),
+            "# Version 2.0\n\n\
+             This release rewrites the storage engine, which makes writes **twice as fast**.\n\n\
+             See the [upgrade guide](https://example.com/guide) before upgrading, since the format changed.\n"
+        );
+    }
+
+    #[test]
+    fn test_lists_tables_and_code() {
+        let html = r#"<body><h2>Setup</h2>
+<ol><li>Install<li>Run <code>semtools  parse</code></ol>
+<ul><li>One<ul><li>Nested</li></ul></li></ul>
+<table><tr><th>Name<th>Size</tr><tr><td>a|b</td><td>1</td></tr></table>
+<pre><code class="language-bash">echo hi
+echo bye</code></pre>
+<p>Line one<br>line two &amp; more</p></body>"#;
+
+        assert_eq!(
+            html_to_markdown(html),
+            "## Setup\n\n\
+             1. Install\n\
+             2. Run `semtools parse`\n\n\
+             - One\n  - Nested\n\n\
+             | Name | Size |\n| --- | --- |\n| a\\|b | 1 |\n\n\
+             ```bash\necho hi\necho bye\n```\n\n\
+             Line one\nline two & more\n"
+        );
+    }
+
+    #[test]
+    fn test_title_used_when_page_has_no_heading() {
+        let html = "<html><head><title>Meeting notes</title><style>p{}</style></head>\
+                    <body><p>Budget approved.</p><script>alert('<p>x</p>')</script></body></html>";
+        assert_eq!(
+            html_to_markdown(html),
+            "# Meeting notes\n\nBudget approved.\n"
+        );
+    }
+
+    #[test]
+    fn test_mhtml_html_part() {
+        let archive = "From: <Saved by Blink>\r\n\
+                       MIME-Version: 1.0\r\n\
+                       Content-Type: multipart/related;\r\n\ttype=\"text/html\";\r\n\tboundary=\"----MultipartBoundary--abc\"\r\n\
+                       \r\n\
+                       ------MultipartBoundary--abc\r\n\
+                       Content-Type: text/html\r\n\
+                       Content-Transfer-Encoding: quoted-printable\r\n\
+                       \r\n\
+                       <html><body><p class=3D\"x\">Caf=C3=A9 menu, long line=\r\n continued</p></body></html>\r\n\
+                       ------MultipartBoundary--abc--\r\n";
+
+        let html = mhtml_html_part(archive.as_bytes()).unwrap();
+        assert!(html.contains("<p class=\"x\">Café menu, long line continued</p>"));
+    }
+}
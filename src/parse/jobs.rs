@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use crate::parse::cache::{absolute_path, now_secs};
+use crate::parse::client::CreateParseJobRetVal;
+use crate::parse::error::JobError;
+use crate::parse::pages::PageSelection;
+
+/// LlamaParse jobs that were submitted but whose results were not written to the cache yet
+pub(crate) const PENDING_JOBS_FILE: &str = "pending_jobs.json";
+
+/// Serializes ledger updates from concurrent parse tasks
+static LEDGER_LOCK: Mutex<()> = Mutex::new(());
+
+/// A submitted LlamaParse job, recorded so an interrupted run can resume polling it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingJob {
+    pub job_id: String,
+    pub expand_key: String,
+    /// API the job was submitted to
+    pub base_url: String,
+    /// Size and modification time of the file when it was uploaded
+    pub size: u64,
+    pub modified_time: u64,
+    /// Unix timestamp (seconds) of the upload
+    pub submitted_at: u64,
+    /// Pages the job parses, when not the whole document
+    #[serde(default)]
+    pub pages: Option<String>,
+}
+
+impl PendingJob {
+    pub fn new(
+        file_path: &str,
+        base_url: &str,
+        pages: Option<&PageSelection>,
+        retval: &CreateParseJobRetVal,
+    ) -> Result<Self, JobError> {
+        let (size, modified_time) = file_stamp(file_path)?;
+        Ok(Self {
+            job_id: retval.job_id.clone(),
+            expand_key: retval.expand_key.clone(),
+            base_url: base_url.to_string(),
+            size,
+            modified_time,
+            submitted_at: now_secs(),
+            pages: pages.map(PageSelection::to_string),
+        })
+    }
+}
+
+/// Pending jobs, keyed by the absolute path of the file they parse
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PendingJobs {
+    pub jobs: BTreeMap<String, PendingJob>,
+}
+
+/// The pending jobs file in the parse cache directory
+#[derive(Debug, Clone)]
+pub struct JobLedger {
+    path: PathBuf,
+}
+
+impl JobLedger {
+    pub fn new(cache_dir: &Path) -> Self {
+        Self {
+            path: cache_dir.join(PENDING_JOBS_FILE),
+        }
+    }
+
+    pub fn load(&self) -> Result<PendingJobs, JobError> {
+        if !self.path.exists() {
+            return Ok(PendingJobs::default());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(&self.path)?)?)
+    }
+
+    fn update<T>(&self, update: impl FnOnce(&mut PendingJobs) -> T) -> Result<T, JobError> {
+        let _guard = LEDGER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut pending = self.load()?;
+        let result = update(&mut pending);
+        if pending.jobs.is_empty() {
+            if self.path.exists() {
+                fs::remove_file(&self.path)?;
+            }
+        } else {
+            fs::write(&self.path, serde_json::to_string_pretty(&pending)?)?;
+        }
+        Ok(result)
+    }
+
+    /// Remember the job parsing `file_path`, replacing any earlier one
+    pub fn record(&self, file_path: &str, job: PendingJob) -> Result<(), JobError> {
+        let key = ledger_key(file_path);
+        self.update(|pending| {
+            pending.jobs.insert(key, job);
+        })
+    }
+
+    /// Forget the job for `file_path`, returning whether there was one
+    pub fn remove(&self, file_path: &str) -> Result<bool, JobError> {
+        let key = ledger_key(file_path);
+        self.update(|pending| pending.jobs.remove(&key).is_some())
+    }
+
+    /// Forget every pending job, returning how many there were
+    pub fn clear(&self) -> Result<usize, JobError> {
+        self.update(|pending| std::mem::take(&mut pending.jobs).len())
+    }
+
+    /// The job for `file_path`, if it was submitted to `base_url` for the same pages and the
+    /// file has not changed since
+    pub fn resumable(
+        &self,
+        file_path: &str,
+        base_url: &str,
+        pages: Option<&PageSelection>,
+    ) -> Result<Option<PendingJob>, JobError> {
+        let Some(job) = self.load()?.jobs.remove(&ledger_key(file_path)) else {
+            return Ok(None);
+        };
+        let unchanged =
+            file_stamp(file_path).is_ok_and(|stamp| stamp == (job.size, job.modified_time));
+        let same_pages = job.pages == pages.map(PageSelection::to_string);
+        Ok((unchanged && same_pages && job.base_url == base_url).then_some(job))
+    }
+}
+
+fn ledger_key(file_path: &str) -> String {
+    absolute_path(file_path).to_string_lossy().to_string()
+}
+
+/// Size and modification time (Unix seconds) of a file
+fn file_stamp(file_path: &str) -> Result<(u64, u64), JobError> {
+    let metadata = fs::metadata(file_path)?;
+    let modified_time = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Ok((metadata.len(), modified_time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn submitted(job_id: &str) -> CreateParseJobRetVal {
+        CreateParseJobRetVal {
+            job_id: job_id.to_string(),
+            expand_key: "markdown".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_record_resume_and_remove() {
+        let temp_dir = TempDir::new().unwrap();
+        let ledger = JobLedger::new(temp_dir.path());
+        let file = temp_dir.path().join("report.pdf");
+        fs::write(&file, b"%PDF-1.7").unwrap();
+        let file = file.to_string_lossy().to_string();
+        let base_url = "https://api.cloud.llamaindex.ai";
+
+        assert!(ledger.resumable(&file, base_url, None).unwrap().is_none());
+
+        let job = PendingJob::new(&file, base_url, None, &submitted("job-1")).unwrap();
+        ledger.record(&file, job.clone()).unwrap();
+        assert_eq!(ledger.resumable(&file, base_url, None).unwrap(), Some(job));
+
+        // Jobs from another API or for other pages are not resumed
+        let pages: PageSelection = "1-3".parse().unwrap();
+        assert!(
+            ledger
+                .resumable(&file, base_url, Some(&pages))
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            ledger
+                .resumable(&file, "https://api.cloud.eu.llamaindex.ai", None)
+                .unwrap()
+                .is_none()
+        );
+
+        assert!(ledger.remove(&file).unwrap());
+        assert!(!ledger.remove(&file).unwrap());
+        // The ledger file goes away with its last job
+        assert!(!temp_dir.path().join(PENDING_JOBS_FILE).exists());
+    }
+
+    #[test]
+    fn test_changed_file_is_not_resumed() {
+        let temp_dir = TempDir::new().unwrap();
+        let ledger = JobLedger::new(temp_dir.path());
+        let file = temp_dir.path().join("report.pdf");
+        fs::write(&file, b"%PDF-1.7").unwrap();
+        let file = file.to_string_lossy().to_string();
+        let base_url = "https://api.cloud.llamaindex.ai";
+
+        let job = PendingJob::new(&file, base_url, None, &submitted("job-1")).unwrap();
+        ledger.record(&file, job).unwrap();
+
+        fs::write(&file, b"%PDF-1.7 with more pages").unwrap();
+        assert!(ledger.resumable(&file, base_url, None).unwrap().is_none());
+
+        assert_eq!(ledger.clear().unwrap(), 1);
+        assert!(ledger.load().unwrap().jobs.is_empty());
+    }
+}
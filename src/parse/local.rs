@@ -0,0 +1,390 @@
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+use std::str::FromStr;
+
+use crate::metadata::{DocumentMetadata, MetadataField};
+use crate::notebook;
+use crate::parse::ebook;
+use crate::parse::email;
+use crate::parse::error::JobError;
+use crate::parse::html;
+use crate::parse::pages::{PageSelection, select_pdftotext_pages};
+
+/// Parse backends that can be combined into a fallback chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseBackendKind {
+    /// Web pages (and MHTML archives) converted to markdown in-process, keeping only the main
+    /// content
+    Html,
+    /// Emails (`.eml`, Outlook `.msg`) rendered with a metadata block, with each attachment
+    /// parsed through the rest of the chain
+    Email,
+    /// Ebooks: EPUB unpacked in-process, one marked section per chapter; Kindle formats are
+    /// converted to EPUB with calibre's `ebook-convert` first
+    Ebook,
+    /// Jupyter notebooks rendered in-process, one marked section per cell
+    Notebook,
+    /// Office documents converted locally with `pandoc`
+    Office,
+    /// PDF text layer extracted locally with `pdftotext`
+    PdfText,
+    /// LlamaParse cloud API
+    LlamaParse,
+}
+
+impl ParseBackendKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ParseBackendKind::Html => "html",
+            ParseBackendKind::Email => "email",
+            ParseBackendKind::Ebook => "ebook",
+            ParseBackendKind::Notebook => "notebook",
+            ParseBackendKind::Office => "office",
+            ParseBackendKind::PdfText => "pdf-text",
+            ParseBackendKind::LlamaParse => "llama-parse",
+        }
+    }
+
+    /// Whether this backend runs locally (and synchronously) instead of calling an API
+    pub fn is_local(&self) -> bool {
+        !matches!(self, ParseBackendKind::LlamaParse)
+    }
+
+    /// Whether this backend can parse a selection of pages
+    pub fn supports_pages(&self) -> bool {
+        !matches!(
+            self,
+            ParseBackendKind::Office
+                | ParseBackendKind::Html
+                | ParseBackendKind::Email
+                | ParseBackendKind::Ebook
+                | ParseBackendKind::Notebook
+        )
+    }
+
+    /// Whether this backend can handle the given file at all
+    pub fn supports(&self, file_path: &str) -> bool {
+        let extension = Path::new(file_path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase())
+            .unwrap_or_default();
+
+        match self {
+            ParseBackendKind::Html => {
+                matches!(
+                    extension.as_str(),
+                    "html" | "htm" | "xhtml" | "mht" | "mhtml"
+                )
+            }
+            ParseBackendKind::Email => matches!(extension.as_str(), "eml" | "msg"),
+            ParseBackendKind::Ebook => {
+                matches!(extension.as_str(), "epub" | "mobi" | "azw" | "azw3")
+            }
+            ParseBackendKind::Notebook => extension == "ipynb",
+            ParseBackendKind::Office => {
+                matches!(
+                    extension.as_str(),
+                    "docx" | "odt" | "rtf" | "epub" | "html" | "htm"
+                )
+            }
+            ParseBackendKind::PdfText => extension == "pdf",
+            ParseBackendKind::LlamaParse => true,
+        }
+    }
+}
+
+impl fmt::Display for ParseBackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ParseBackendKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "html" => Ok(ParseBackendKind::Html),
+            "email" => Ok(ParseBackendKind::Email),
+            "ebook" => Ok(ParseBackendKind::Ebook),
+            "notebook" => Ok(ParseBackendKind::Notebook),
+            "office" => Ok(ParseBackendKind::Office),
+            "pdf-text" => Ok(ParseBackendKind::PdfText),
+            "llama-parse" => Ok(ParseBackendKind::LlamaParse),
+            other => Err(anyhow::anyhow!(
+                "Unknown backend '{other}'. Supported backends: html, email, ebook, notebook, office, pdf-text, llama-parse"
+            )),
+        }
+    }
+}
+
+/// Parse a backend chain such as `pdf-text,llama-parse`
+pub fn parse_backend_chain<S: AsRef<str>>(names: &[S]) -> anyhow::Result<Vec<ParseBackendKind>> {
+    let mut chain = Vec::new();
+    for name in names {
+        for part in name.as_ref().split(',').filter(|p| !p.trim().is_empty()) {
+            let kind = part.parse::<ParseBackendKind>()?;
+            if !chain.contains(&kind) {
+                chain.push(kind);
+            }
+        }
+    }
+    if chain.is_empty() {
+        chain.push(ParseBackendKind::LlamaParse);
+    }
+    Ok(chain)
+}
+
+/// Run a local backend and return its markdown/text output. `pdf-text` output starts each page
+/// with a page marker, and only has the selected `pages`.
+pub fn run_local_backend(
+    kind: ParseBackendKind,
+    file_path: &str,
+    pages: Option<&PageSelection>,
+) -> Result<String, JobError> {
+    let first_page = pages.map_or(1, PageSelection::first);
+    let mut command = match kind {
+        ParseBackendKind::Html => return html::file_to_markdown(file_path),
+        ParseBackendKind::Email => return email::file_to_markdown(file_path),
+        ParseBackendKind::Ebook => return ebook::file_to_markdown(file_path),
+        ParseBackendKind::Notebook => {
+            return notebook::to_markdown(&std::fs::read_to_string(file_path)?)
+                .map_err(|e| JobError::InvalidResponse(format!("{e:#}")));
+        }
+        ParseBackendKind::Office => {
+            let mut c = Command::new("pandoc");
+            c.arg("--to").arg("gfm").arg(file_path);
+            c
+        }
+        ParseBackendKind::PdfText => {
+            let mut c = Command::new("pdftotext");
+            c.arg("-layout");
+            if let Some(pages) = pages {
+                c.arg("-f")
+                    .arg(pages.first().to_string())
+                    .arg("-l")
+                    .arg(pages.last().to_string());
+            }
+            c.arg(file_path).arg("-");
+            c
+        }
+        ParseBackendKind::LlamaParse => {
+            return Err(JobError::InvalidResponse(
+                "llama-parse is not a local backend".to_string(),
+            ));
+        }
+    };
+
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(JobError::InvalidResponse(format!(
+            "{kind} backend failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let content = String::from_utf8_lossy(&output.stdout);
+    Ok(match kind {
+        ParseBackendKind::PdfText => select_pdftotext_pages(&content, first_page, pages),
+        _ => content.to_string(),
+    })
+}
+
+/// The title, author and creation date of a PDF, read with poppler's `pdfinfo` (installed
+/// with `pdftotext`). Empty when `pdfinfo` is missing or cannot read the file.
+pub fn pdf_metadata(file_path: &str) -> DocumentMetadata {
+    Command::new("pdfinfo")
+        .arg("-isodates")
+        .arg(file_path)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| parse_pdfinfo(&String::from_utf8_lossy(&output.stdout)))
+        .unwrap_or_default()
+}
+
+/// The metadata in `pdfinfo` output, which has one `Key:   value` line per entry
+fn parse_pdfinfo(output: &str) -> DocumentMetadata {
+    let mut metadata = DocumentMetadata::default();
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let field = match key {
+            "Title" => MetadataField::Title,
+            "Author" => MetadataField::Author,
+            "CreationDate" => MetadataField::Created,
+            _ => continue,
+        };
+        metadata.set(field, value);
+    }
+    metadata
+}
+
+/// Reasons a parse result is considered too poor to keep
+#[derive(Debug, Clone, PartialEq)]
+pub enum QualityIssue {
+    Empty,
+    LowDensity { chars: usize, input_bytes: u64 },
+    Mojibake { ratio: f64 },
+}
+
+impl fmt::Display for QualityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QualityIssue::Empty => write!(f, "empty output"),
+            QualityIssue::LowDensity { chars, input_bytes } => write!(
+                f,
+                "low character density ({chars} chars from {input_bytes} input bytes)"
+            ),
+            QualityIssue::Mojibake { ratio } => {
+                write!(f, "likely mojibake ({:.1}% garbled chars)", ratio * 100.0)
+            }
+        }
+    }
+}
+
+/// Minimum non-whitespace characters per KiB of input before output is considered too sparse
+const MIN_CHARS_PER_KIB: f64 = 1.0;
+
+/// Maximum fraction of garbled characters before output is considered mojibake
+const MAX_MOJIBAKE_RATIO: f64 = 0.01;
+
+/// Check whether a local backend's output is good enough, or should escalate to the next backend
+pub fn check_output_quality(content: &str, input_bytes: u64) -> Result<(), QualityIssue> {
+    let chars = content.chars().filter(|c| !c.is_whitespace()).count();
+    if chars == 0 {
+        return Err(QualityIssue::Empty);
+    }
+
+    let min_chars = (input_bytes as f64 / 1024.0) * MIN_CHARS_PER_KIB;
+    if (chars as f64) < min_chars {
+        return Err(QualityIssue::LowDensity { chars, input_bytes });
+    }
+
+    // Replacement characters, stray control characters, and UTF-8 read as Latin-1 or
+    // Windows-1252 ("Ã©", "â€œ") are the usual signs of a broken text layer
+    let mut garbled = 0;
+    let mut prev = None;
+    for c in content.chars() {
+        if c == '\u{FFFD}' || (c.is_control() && !c.is_whitespace()) {
+            garbled += 1;
+        } else if prev.is_some_and(|lead| is_misdecoded_pair(lead, c)) {
+            garbled += 2;
+        }
+        prev = Some(c);
+    }
+    let ratio = garbled as f64 / chars as f64;
+    if ratio > MAX_MOJIBAKE_RATIO {
+        return Err(QualityIssue::Mojibake { ratio });
+    }
+
+    Ok(())
+}
+
+/// Whether `lead` followed by `next` is a two-byte UTF-8 sequence decoded one byte at a time:
+/// "Ã"/"Â" with a continuation byte, or the "â€" that starts curly quotes and dashes
+fn is_misdecoded_pair(lead: char, next: char) -> bool {
+    // Continuation bytes 0x80-0x9F as Windows-1252 shows them
+    const CP1252_CONTINUATIONS: &str = "€‚ƒ„…†‡ˆ‰Š‹ŒŽ‘’“”•–—˜™š›œžŸ";
+    let continuation = ('\u{80}'..='\u{BF}').contains(&next) || CP1252_CONTINUATIONS.contains(next);
+    match lead {
+        'Ã' | 'Â' => continuation,
+        'â' => next == '€',
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_backend_chain() {
+        let chain = parse_backend_chain(&["office,pdf-text", "llama-parse"]).unwrap();
+        assert_eq!(
+            chain,
+            vec![
+                ParseBackendKind::Office,
+                ParseBackendKind::PdfText,
+                ParseBackendKind::LlamaParse
+            ]
+        );
+
+        let empty: [&str; 0] = [];
+        assert_eq!(
+            parse_backend_chain(&empty).unwrap(),
+            vec![ParseBackendKind::LlamaParse]
+        );
+        assert!(parse_backend_chain(&["unknown"]).is_err());
+    }
+
+    #[test]
+    fn test_backend_supports() {
+        assert!(ParseBackendKind::PdfText.supports("report.PDF"));
+        assert!(!ParseBackendKind::PdfText.supports("report.docx"));
+        assert!(ParseBackendKind::Office.supports("report.docx"));
+        assert!(ParseBackendKind::Html.supports("saved_page.MHTML"));
+        assert!(!ParseBackendKind::Html.supports("report.docx"));
+        assert!(ParseBackendKind::Email.supports("export/0001.msg"));
+        assert!(!ParseBackendKind::Email.supports_pages());
+        assert!(ParseBackendKind::Ebook.supports("library/novel.mobi"));
+        assert!(!ParseBackendKind::Ebook.supports("report.pdf"));
+        assert!(ParseBackendKind::Notebook.supports("analysis/Churn.IPYNB"));
+        assert!(ParseBackendKind::LlamaParse.supports("anything.xyz"));
+        assert!(ParseBackendKind::PdfText.supports_pages());
+        assert!(!ParseBackendKind::Office.supports_pages());
+    }
+
+    #[test]
+    fn test_parse_pdfinfo() {
+        let metadata = parse_pdfinfo(
+            "Title:           Annual Report: 2024\nSubject:         \nAuthor:          Jane Smith\n\
+             Creator:         Writer\nCreationDate:    2024-03-05T10:22:01+01\nPages:           12\n",
+        );
+        assert_eq!(metadata.title.as_deref(), Some("Annual Report: 2024"));
+        assert_eq!(metadata.author.as_deref(), Some("Jane Smith"));
+        assert_eq!(metadata.created.as_deref(), Some("2024-03-05T10:22:01+01"));
+        assert!(parse_pdfinfo("Title:  \nPages: 1\n").is_empty());
+    }
+
+    #[test]
+    fn test_quality_empty() {
+        assert_eq!(
+            check_output_quality("  \n\n ", 100),
+            Err(QualityIssue::Empty)
+        );
+    }
+
+    #[test]
+    fn test_quality_low_density() {
+        // 5 chars from a 100 KiB file
+        assert!(matches!(
+            check_output_quality("hello", 100 * 1024),
+            Err(QualityIssue::LowDensity { .. })
+        ));
+    }
+
+    #[test]
+    fn test_quality_mojibake() {
+        let garbled = "Ã©tÃ© â€œquotedâ€\u{FFFD}\u{FFFD} text";
+        assert!(matches!(
+            check_output_quality(garbled, 10),
+            Err(QualityIssue::Mojibake { .. })
+        ));
+    }
+
+    #[test]
+    fn test_quality_accented_text_is_not_mojibake() {
+        let text = "NÃO À PÂTE: l'âge de la pâte, Ãgua e Âmbar, â la carte. ".repeat(20);
+        assert!(check_output_quality(&text, 1024).is_ok());
+    }
+
+    #[test]
+    fn test_quality_ok() {
+        let text = "A perfectly normal paragraph of extracted text.\n".repeat(50);
+        assert!(check_output_quality(&text, 2048).is_ok());
+    }
+}
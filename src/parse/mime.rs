@@ -0,0 +1,352 @@
+/// One part of a MIME message (or the whole message): unfolded headers and the raw body,
+/// still in its transfer encoding
+#[derive(Debug, Clone, Default)]
+pub struct Part {
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Part {
+    pub fn parse(raw: &[u8]) -> Self {
+        let (head, body) = match find(raw, b"\r\n\r\n") {
+            Some(end) => (&raw[..end], &raw[end + 4..]),
+            None => match find(raw, b"\n\n") {
+                Some(end) => (&raw[..end], &raw[end + 2..]),
+                None => (raw, &[][..]),
+            },
+        };
+
+        let mut headers: Vec<(String, String)> = Vec::new();
+        for line in String::from_utf8_lossy(head).lines() {
+            if line.starts_with([' ', '\t']) {
+                // Folded continuation of the previous header
+                if let Some((_, value)) = headers.last_mut() {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+            } else if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+            }
+        }
+
+        Part {
+            headers,
+            body: body.to_vec(),
+        }
+    }
+
+    /// The first header called `name` (case-insensitive), as written
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// The first header called `name` with encoded words decoded
+    pub fn decoded_header(&self, name: &str) -> Option<String> {
+        self.header(name)
+            .map(decode_header_value)
+            .filter(|v| !v.is_empty())
+    }
+
+    /// Media type such as `text/html`, defaulting to `text/plain` like MIME does
+    pub fn content_type(&self) -> String {
+        self.header("content-type")
+            .and_then(|ct| ct.split(';').next())
+            .map(|ct| ct.trim().to_ascii_lowercase())
+            .filter(|ct| !ct.is_empty())
+            .unwrap_or_else(|| "text/plain".to_string())
+    }
+
+    /// A parameter of a structured header, e.g. the `boundary` of `Content-Type`
+    pub fn param(&self, header: &str, name: &str) -> Option<String> {
+        let params = header_params(self.header(header)?);
+        if let Some((_, value)) = params.iter().find(|(n, _)| *n == format!("{name}*")) {
+            return Some(decode_extended_param(value));
+        }
+        params
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| decode_header_value(&v))
+    }
+
+    /// File name from `Content-Disposition`, or the older `name` parameter of `Content-Type`
+    pub fn filename(&self) -> Option<String> {
+        self.param("content-disposition", "filename")
+            .or_else(|| self.param("content-type", "name"))
+            .filter(|name| !name.trim().is_empty())
+    }
+
+    pub fn is_attachment(&self) -> bool {
+        self.header("content-disposition")
+            .is_some_and(|d| d.trim().to_ascii_lowercase().starts_with("attachment"))
+    }
+
+    /// The body with its `Content-Transfer-Encoding` undone
+    pub fn decoded_body(&self) -> Vec<u8> {
+        match self
+            .header("content-transfer-encoding")
+            .map(|e| e.trim().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("base64") => decode_base64(&self.body),
+            Some("quoted-printable") => decode_quoted_printable(&self.body),
+            _ => self.body.clone(),
+        }
+    }
+
+    /// The decoded body as text in its declared charset
+    pub fn text(&self) -> String {
+        decode_text(
+            &self.decoded_body(),
+            self.param("content-type", "charset").as_deref(),
+        )
+    }
+
+    /// The parts of a `multipart/*` body
+    pub fn subparts(&self) -> Vec<Part> {
+        let Some(boundary) = self.param("content-type", "boundary") else {
+            return Vec::new();
+        };
+        let delimiter = format!("--{boundary}");
+        let delimiter = delimiter.as_bytes();
+
+        // Delimiters only count at the start of a line
+        let mut starts = Vec::new();
+        let mut offset = 0;
+        while let Some(pos) = find(&self.body[offset..], delimiter) {
+            let at = offset + pos;
+            if at == 0 || self.body[at - 1] == b'\n' {
+                starts.push(at);
+            }
+            offset = at + delimiter.len();
+        }
+
+        let mut parts = Vec::new();
+        for window in starts.windows(2) {
+            let segment = &self.body[window[0] + delimiter.len()..window[1]];
+            if segment.starts_with(b"--") {
+                break;
+            }
+            // Skip the rest of the delimiter line, and the line break that belongs to the next one
+            let content = match find(segment, b"\n") {
+                Some(eol) => &segment[eol + 1..],
+                None => &[][..],
+            };
+            let content = content
+                .strip_suffix(b"\r\n")
+                .or_else(|| content.strip_suffix(b"\n"))
+                .unwrap_or(content);
+            parts.push(Part::parse(content));
+        }
+        parts
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() || haystack.len() < needle.len() {
+        return None;
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Split `value; a=1; b="x; y"` into its lowercased parameter names and unquoted values
+fn header_params(value: &str) -> Vec<(String, String)> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            _ if escaped => {
+                current.push(c);
+                escaped = false;
+            }
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted => segments.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    segments.push(current);
+
+    segments
+        .into_iter()
+        .skip(1)
+        .filter_map(|segment| {
+            let (name, value) = segment.split_once('=')?;
+            Some((name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Decode an RFC 2231 value such as `UTF-8''na%C3%AFve.pdf`
+fn decode_extended_param(value: &str) -> String {
+    let mut fields = value.splitn(3, '\'');
+    let (charset, encoded) = match (fields.next(), fields.next(), fields.next()) {
+        (Some(charset), Some(_language), Some(encoded)) => (Some(charset), encoded),
+        _ => (None, value),
+    };
+
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(byte) = encoded
+                .get(i + 1..i + 3)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    decode_text(&decoded, charset)
+}
+
+/// Decode RFC 2047 encoded words (`=?UTF-8?B?...?=`) in a header value
+pub fn decode_header_value(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+
+    while let Some(start) = rest.find("=?") {
+        let Some((word, len)) = decode_encoded_word(&rest[start..]) else {
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            after_word = false;
+            continue;
+        };
+        // Whitespace between adjacent encoded words is not part of the text
+        let between = &rest[..start];
+        if !(after_word && between.trim().is_empty()) {
+            out.push_str(between);
+        }
+        out.push_str(&word);
+        rest = &rest[start + len..];
+        after_word = true;
+    }
+
+    out.push_str(rest);
+    out.trim().to_string()
+}
+
+/// Decode the encoded word at the start of `s`, returning it and its length
+fn decode_encoded_word(s: &str) -> Option<(String, usize)> {
+    let inner = s.strip_prefix("=?")?;
+    let (charset, inner) = inner.split_once('?')?;
+    let (encoding, inner) = inner.split_once('?')?;
+    let end = inner.find("?=")?;
+    let text = &inner[..end];
+    let len = 2 + charset.len() + 1 + encoding.len() + 1 + end + 2;
+
+    let bytes = match encoding.to_ascii_uppercase().as_str() {
+        "B" => decode_base64(text.as_bytes()),
+        "Q" => decode_quoted_printable(text.replace('_', " ").as_bytes()),
+        _ => return None,
+    };
+    // Drop an RFC 2231 language suffix such as `UTF-8*en`
+    let charset = charset.split('*').next().unwrap_or(charset);
+    Some((decode_text(&bytes, Some(charset)), len))
+}
+
+/// Decode text in the given charset, falling back to UTF-8 for unknown labels
+pub fn decode_text(bytes: &[u8], charset: Option<&str>) -> String {
+    let encoding = charset
+        .and_then(|c| encoding_rs::Encoding::for_label(c.trim().as_bytes()))
+        .unwrap_or(encoding_rs::UTF_8);
+    encoding.decode(bytes).0.into_owned()
+}
+
+pub fn decode_quoted_printable(body: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(body.len());
+    let mut i = 0;
+    while i < body.len() {
+        if body[i] != b'=' {
+            out.push(body[i]);
+            i += 1;
+            continue;
+        }
+        let hex = body
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        // Soft line break
+        if body[i + 1..].starts_with(b"\r\n") {
+            i += 3;
+        } else if body[i + 1..].starts_with(b"\n") {
+            i += 2;
+        } else if let Some(byte) = hex {
+            out.push(byte);
+            i += 3;
+        } else {
+            out.push(b'=');
+            i += 1;
+        }
+    }
+    out
+}
+
+pub fn decode_base64(body: &[u8]) -> Vec<u8> {
+    let value = |c: u8| match c {
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'a'..=b'z' => Some(c - b'a' + 26),
+        b'0'..=b'9' => Some(c - b'0' + 52),
+        b'+' | b'-' => Some(62),
+        b'/' | b'_' => Some(63),
+        _ => None,
+    };
+
+    let mut out = Vec::with_capacity(body.len() * 3 / 4);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for sextet in body.iter().copied().filter_map(value) {
+        buffer = (buffer << 6) | sextet as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_header_value() {
+        assert_eq!(
+            decode_header_value("=?UTF-8?B?UXVhcnRlcmx5?= =?UTF-8?Q?_r=C3=A9sum=C3=A9?="),
+            "Quarterly résumé"
+        );
+        assert_eq!(
+            decode_header_value("Re: =?iso-8859-1?q?caf=E9?= plans"),
+            "Re: café plans"
+        );
+        assert_eq!(decode_header_value("plain =? text"), "plain =? text");
+    }
+
+    #[test]
+    fn test_params_and_filename() {
+        let part = Part::parse(
+            b"Content-Type: application/pdf; name=\"old.pdf\"\r\n\
+              Content-Disposition: attachment;\r\n\tfilename*=UTF-8''na%C3%AFve%20plan.pdf\r\n\r\nbody",
+        );
+        assert!(part.is_attachment());
+        assert_eq!(part.content_type(), "application/pdf");
+        assert_eq!(part.filename().as_deref(), Some("naïve plan.pdf"));
+        assert_eq!(part.body, b"body");
+    }
+
+    #[test]
+    fn test_decode_base64() {
+        assert_eq!(decode_base64(b"PGgxPkhp\r\nPC9oMT4="), b"<h1>Hi</h1>");
+    }
+}
@@ -1,9 +1,22 @@
+pub mod assets;
 pub mod backend;
 pub mod cache;
 pub mod client;
 pub mod config;
+pub mod ebook;
+pub mod email;
 pub mod error;
+pub mod gdrive;
+pub mod html;
+pub mod jobs;
+pub mod local;
+pub mod mime;
+pub mod msg;
+pub mod pages;
+pub mod throttle;
 
-pub use backend::LlamaParseBackend;
+pub use backend::{LlamaParseBackend, ParsedFile};
 pub use config::LlamaParseConfig;
 pub use error::JobError;
+pub use local::ParseBackendKind;
+pub use pages::PageSelection;
@@ -0,0 +1,531 @@
+use crate::parse::email::{Attachment, Email};
+use crate::parse::error::JobError;
+use crate::parse::html::html_to_markdown;
+use crate::parse::mime::{Part, decode_text};
+
+const SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+/// Sector ids from here up mark free sectors, chain ends and FAT/DIFAT sectors
+const MAX_REGULAR_SECTOR: u32 = 0xFFFF_FFFA;
+const NO_STREAM: u32 = 0xFFFF_FFFF;
+const DIR_ENTRY_SIZE: usize = 128;
+const MINI_SECTOR_SIZE: usize = 64;
+
+const STORAGE: u8 = 1;
+const STREAM: u8 = 2;
+
+// MAPI property ids
+const PR_TRANSPORT_MESSAGE_HEADERS: u16 = 0x007D;
+const PR_SUBJECT: u16 = 0x0037;
+const PR_CLIENT_SUBMIT_TIME: u16 = 0x0039;
+const PR_SENDER_NAME: u16 = 0x0C1A;
+const PR_SENDER_EMAIL_ADDRESS: u16 = 0x0C1F;
+const PR_SENDER_SMTP_ADDRESS: u16 = 0x5D01;
+const PR_DISPLAY_CC: u16 = 0x0E03;
+const PR_DISPLAY_TO: u16 = 0x0E04;
+const PR_MESSAGE_DELIVERY_TIME: u16 = 0x0E06;
+const PR_BODY: u16 = 0x1000;
+const PR_HTML: u16 = 0x1013;
+const PR_DISPLAY_NAME: u16 = 0x3001;
+const PR_ATTACH_DATA: u16 = 0x3701;
+const PR_ATTACH_FILENAME: u16 = 0x3704;
+const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+const PR_ATTACH_MIME_TAG: u16 = 0x370E;
+
+const PT_SYSTIME: u16 = 0x0040;
+
+#[derive(Debug)]
+struct DirEntry {
+    name: String,
+    kind: u8,
+    left: u32,
+    right: u32,
+    child: u32,
+    start: u32,
+    size: u64,
+}
+
+/// Read-only view of an OLE compound file, the container format of Outlook `.msg` files
+struct CompoundFile<'a> {
+    data: &'a [u8],
+    sector_size: usize,
+    mini_cutoff: u64,
+    fat: Vec<u32>,
+    mini_fat: Vec<u32>,
+    entries: Vec<DirEntry>,
+    mini_stream: Vec<u8>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        data.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+impl<'a> CompoundFile<'a> {
+    fn open(data: &'a [u8]) -> Option<Self> {
+        if !data.starts_with(&SIGNATURE) {
+            return None;
+        }
+        let sector_size = match u16_at(data, 0x1E)? {
+            9 => 512,
+            12 => 4096,
+            _ => return None,
+        };
+
+        let mut file = CompoundFile {
+            data,
+            sector_size,
+            mini_cutoff: u32_at(data, 0x38)? as u64,
+            fat: Vec::new(),
+            mini_fat: Vec::new(),
+            entries: Vec::new(),
+            mini_stream: Vec::new(),
+        };
+
+        // The FAT sectors are listed in the header, then in a chain of DIFAT sectors
+        let fat_count = u32_at(data, 0x2C)? as usize;
+        let mut fat_sectors: Vec<u32> = (0..109)
+            .filter_map(|i| u32_at(data, 0x4C + i * 4))
+            .filter(|&id| id < MAX_REGULAR_SECTOR)
+            .collect();
+        let ids_per_sector = sector_size / 4;
+        let mut difat = u32_at(data, 0x44)?;
+        let mut difat_count = u32_at(data, 0x48)?;
+        while difat < MAX_REGULAR_SECTOR && difat_count > 0 {
+            let sector = file.sector(difat)?;
+            fat_sectors.extend(
+                (0..ids_per_sector - 1)
+                    .filter_map(|i| u32_at(sector, i * 4))
+                    .filter(|&id| id < MAX_REGULAR_SECTOR),
+            );
+            difat = u32_at(sector, (ids_per_sector - 1) * 4)?;
+            difat_count -= 1;
+        }
+        fat_sectors.truncate(fat_count);
+        for id in fat_sectors {
+            let sector = file.sector(id)?;
+            file.fat
+                .extend((0..ids_per_sector).filter_map(|i| u32_at(sector, i * 4)));
+        }
+
+        let directory = file.read_chain(u32_at(data, 0x30)?, None);
+        file.entries = directory
+            .chunks_exact(DIR_ENTRY_SIZE)
+            .map(parse_dir_entry)
+            .collect();
+
+        let mini_fat = file.read_chain(u32_at(data, 0x3C)?, None);
+        file.mini_fat = mini_fat
+            .chunks_exact(4)
+            .map(|id| u32::from_le_bytes([id[0], id[1], id[2], id[3]]))
+            .collect();
+
+        let root = file.entries.first()?;
+        file.mini_stream = file.read_chain(root.start, Some(root.size));
+        Some(file)
+    }
+
+    fn sector(&self, id: u32) -> Option<&'a [u8]> {
+        let start = (id as usize + 1) * self.sector_size;
+        let end = (start + self.sector_size).min(self.data.len());
+        self.data.get(start..end)
+    }
+
+    /// Follow a FAT chain, stopping at its end or when it loops
+    fn read_chain(&self, start: u32, size: Option<u64>) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut id = start;
+        let mut steps = 0;
+        while id < MAX_REGULAR_SECTOR && steps <= self.fat.len() {
+            let Some(sector) = self.sector(id) else { break };
+            out.extend_from_slice(sector);
+            id = self.fat.get(id as usize).copied().unwrap_or(NO_STREAM);
+            steps += 1;
+        }
+        if let Some(size) = size {
+            out.truncate(size as usize);
+        }
+        out
+    }
+
+    /// Streams under the cutoff size live in the mini stream, in 64-byte sectors
+    fn read_mini_chain(&self, start: u32, size: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut id = start;
+        let mut steps = 0;
+        while id < MAX_REGULAR_SECTOR && steps <= self.mini_fat.len() {
+            let offset = id as usize * MINI_SECTOR_SIZE;
+            let Some(sector) = self
+                .mini_stream
+                .get(offset..(offset + MINI_SECTOR_SIZE).min(self.mini_stream.len()))
+            else {
+                break;
+            };
+            out.extend_from_slice(sector);
+            id = self.mini_fat.get(id as usize).copied().unwrap_or(NO_STREAM);
+            steps += 1;
+        }
+        out.truncate(size as usize);
+        out
+    }
+
+    /// Entries directly inside a storage, which are kept as a binary tree of siblings
+    fn children(&self, storage: usize) -> Vec<usize> {
+        let mut children = Vec::new();
+        let mut pending = match self.entries.get(storage) {
+            Some(entry) => vec![entry.child],
+            None => return children,
+        };
+        while let Some(id) = pending.pop() {
+            let Some(entry) = self.entries.get(id as usize) else {
+                continue;
+            };
+            if children.contains(&(id as usize)) || children.len() >= self.entries.len() {
+                continue;
+            }
+            children.push(id as usize);
+            pending.push(entry.left);
+            pending.push(entry.right);
+        }
+        children
+    }
+
+    fn child(&self, storage: usize, name: &str) -> Option<usize> {
+        self.children(storage)
+            .into_iter()
+            .find(|&id| self.entries[id].name.eq_ignore_ascii_case(name))
+    }
+
+    fn stream(&self, storage: usize, name: &str) -> Option<Vec<u8>> {
+        let entry = &self.entries[self.child(storage, name)?];
+        if entry.kind != STREAM {
+            return None;
+        }
+        Some(if entry.size < self.mini_cutoff {
+            self.read_mini_chain(entry.start, entry.size)
+        } else {
+            self.read_chain(entry.start, Some(entry.size))
+        })
+    }
+
+    /// A string property, stored as UTF-16 or (in older files) as 8-bit text
+    fn string(&self, storage: usize, id: u16) -> Option<String> {
+        let text = if let Some(bytes) = self.stream(storage, &format!("__substg1.0_{id:04X}001F")) {
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                .collect();
+            String::from_utf16_lossy(&units)
+        } else {
+            let bytes = self.stream(storage, &format!("__substg1.0_{id:04X}001E"))?;
+            decode_text(&bytes, Some("windows-1252"))
+        };
+        let text = text.trim_end_matches('\0').trim().to_string();
+        (!text.is_empty()).then_some(text)
+    }
+
+    fn binary(&self, storage: usize, id: u16) -> Option<Vec<u8>> {
+        self.stream(storage, &format!("__substg1.0_{id:04X}0102"))
+    }
+
+    /// A timestamp from the fixed-size property stream, which starts with a header of
+    /// `header_size` bytes followed by 16-byte entries
+    fn time(&self, storage: usize, id: u16, header_size: usize) -> Option<String> {
+        let properties = self.stream(storage, "__properties_version1.0")?;
+        properties
+            .get(header_size..)?
+            .chunks_exact(16)
+            .find(|entry| u16_at(entry, 0) == Some(PT_SYSTIME) && u16_at(entry, 2) == Some(id))
+            .and_then(|entry| u64_at(entry, 8))
+            .and_then(format_filetime)
+    }
+}
+
+fn parse_dir_entry(raw: &[u8]) -> DirEntry {
+    let name_len = (u16_at(raw, 64).unwrap_or(0) as usize).min(64);
+    let units: Vec<u16> = raw[..name_len]
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+    DirEntry {
+        name: String::from_utf16_lossy(&units),
+        kind: raw[66],
+        left: u32_at(raw, 68).unwrap_or(NO_STREAM),
+        right: u32_at(raw, 72).unwrap_or(NO_STREAM),
+        child: u32_at(raw, 76).unwrap_or(NO_STREAM),
+        start: u32_at(raw, 116).unwrap_or(NO_STREAM),
+        // Version 3 files may leave junk in the upper half
+        size: u32_at(raw, 120).unwrap_or(0) as u64,
+    }
+}
+
+/// Format a Windows FILETIME (100ns ticks since 1601) as a UTC date and time
+fn format_filetime(filetime: u64) -> Option<String> {
+    let secs = (filetime / 10_000_000).checked_sub(11_644_473_600)?;
+    let (days, secs_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    Some(format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        secs_of_day / 3_600,
+        secs_of_day % 3_600 / 60,
+        secs_of_day % 60
+    ))
+}
+
+/// Read an Outlook `.msg` file
+pub fn read_msg(data: &[u8]) -> Result<Email, JobError> {
+    let file = CompoundFile::open(data)
+        .ok_or_else(|| JobError::InvalidResponse("Not a valid Outlook .msg file".to_string()))?;
+    // The property stream header is 32 bytes for a message file, 24 for an embedded message
+    Ok(read_message(&file, 0, 32))
+}
+
+fn read_message(file: &CompoundFile, storage: usize, header_size: usize) -> Email {
+    let string = |id| file.string(storage, id);
+
+    // Messages that went through a mail server keep their original headers
+    let headers = string(PR_TRANSPORT_MESSAGE_HEADERS)
+        .map(|headers| Part::parse(format!("{headers}\r\n\r\n").as_bytes()));
+    let date = headers
+        .as_ref()
+        .and_then(|headers| headers.decoded_header("date"))
+        .or_else(|| file.time(storage, PR_CLIENT_SUBMIT_TIME, header_size))
+        .or_else(|| file.time(storage, PR_MESSAGE_DELIVERY_TIME, header_size));
+
+    let address = string(PR_SENDER_SMTP_ADDRESS)
+        .or_else(|| string(PR_SENDER_EMAIL_ADDRESS))
+        .filter(|address| address.contains('@'));
+    let from = match (string(PR_SENDER_NAME), address) {
+        (Some(name), Some(address)) if name != address => Some(format!("{name} <{address}>")),
+        (name, address) => address.or(name),
+    };
+
+    let body = match string(PR_BODY) {
+        Some(body) => body.replace("\r\n", "\n"),
+        None => file
+            .binary(storage, PR_HTML)
+            .map(|html| html_to_markdown(&String::from_utf8_lossy(&html)))
+            .unwrap_or_default(),
+    };
+
+    let mut attachments = Vec::new();
+    for id in file.children(storage) {
+        let entry = &file.entries[id];
+        if entry.kind != STORAGE || !entry.name.starts_with("__attach_version1.0_") {
+            continue;
+        }
+        if let Some(attachment) = read_attachment(file, id) {
+            attachments.push(attachment);
+        }
+    }
+
+    Email {
+        subject: string(PR_SUBJECT),
+        from,
+        to: string(PR_DISPLAY_TO),
+        cc: string(PR_DISPLAY_CC),
+        date,
+        body,
+        attachments,
+    }
+}
+
+fn read_attachment(file: &CompoundFile, storage: usize) -> Option<Attachment> {
+    let name = file
+        .string(storage, PR_ATTACH_LONG_FILENAME)
+        .or_else(|| file.string(storage, PR_ATTACH_FILENAME))
+        .or_else(|| file.string(storage, PR_DISPLAY_NAME))
+        .unwrap_or_default();
+
+    if let Some(data) = file.binary(storage, PR_ATTACH_DATA) {
+        return Some(Attachment {
+            name,
+            content_type: file
+                .string(storage, PR_ATTACH_MIME_TAG)
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+            data,
+        });
+    }
+
+    // An attached Outlook item is stored as a nested message, rendered here on its own
+    let embedded = file.child(storage, &format!("__substg1.0_{PR_ATTACH_DATA:04X}000D"))?;
+    let message = read_message(file, embedded, 24);
+    let name = message.subject.clone().unwrap_or(name);
+    Some(Attachment {
+        name: format!("{name}.md"),
+        content_type: "text/markdown".to_string(),
+        data: message.to_markdown(&[]).into_bytes(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a compound file with one storage level below the root. Every stream goes in
+    /// regular sectors (the mini stream cutoff is zero), and siblings are chained to the right.
+    fn compound_file(streams: &[(&str, Vec<u8>)], attachment: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let utf16 = |s: &str| -> Vec<u8> { s.encode_utf16().flat_map(u16::to_le_bytes).collect() };
+
+        // (name, kind, child, right, data)
+        let mut entries: Vec<(String, u8, u32, u32, Vec<u8>)> = vec![(
+            "Root Entry".to_string(),
+            5,
+            NO_STREAM,
+            NO_STREAM,
+            Vec::new(),
+        )];
+        let add_siblings = |entries: &mut Vec<(String, u8, u32, u32, Vec<u8>)>,
+                            items: &[(&str, Vec<u8>)]|
+         -> u32 {
+            let first = entries.len() as u32;
+            for (i, (name, data)) in items.iter().enumerate() {
+                let right = if i + 1 < items.len() {
+                    first + i as u32 + 1
+                } else {
+                    NO_STREAM
+                };
+                entries.push((name.to_string(), STREAM, NO_STREAM, right, data.clone()));
+            }
+            first
+        };
+        let mut root_children = streams.to_vec();
+        root_children.push(("__attach_version1.0_#00000000", Vec::new()));
+        entries[0].2 = add_siblings(&mut entries, &root_children);
+        let storage = entries.len() - 1;
+        entries[storage].1 = STORAGE;
+        entries[storage].2 = add_siblings(&mut entries, attachment);
+
+        // Sector 0 holds the FAT, then the directory, then one chain per stream
+        let sector = 512;
+        let dir_sectors = entries.len().div_ceil(4);
+        let mut fat = vec![0xFFFF_FFFDu32];
+        let mut body = vec![0u8; dir_sectors * sector];
+        for i in 0..dir_sectors {
+            fat.push(if i + 1 < dir_sectors {
+                i as u32 + 2
+            } else {
+                0xFFFF_FFFE
+            });
+        }
+        let mut starts = Vec::new();
+        for (_, _, _, _, data) in &entries {
+            if data.is_empty() {
+                starts.push(0xFFFF_FFFE);
+                continue;
+            }
+            let first = fat.len() as u32;
+            let count = data.len().div_ceil(sector);
+            for i in 0..count {
+                fat.push(if i + 1 < count {
+                    first + i as u32 + 1
+                } else {
+                    0xFFFF_FFFE
+                });
+            }
+            let mut padded = data.clone();
+            padded.resize(count * sector, 0);
+            body.extend(padded);
+            starts.push(first);
+        }
+        fat.resize(sector / 4, NO_STREAM);
+
+        for (i, (name, kind, child, right, data)) in entries.iter().enumerate() {
+            let raw = &mut body[i * DIR_ENTRY_SIZE..(i + 1) * DIR_ENTRY_SIZE];
+            let name = utf16(name);
+            raw[..name.len()].copy_from_slice(&name);
+            raw[64..66].copy_from_slice(&(name.len() as u16 + 2).to_le_bytes());
+            raw[66] = *kind;
+            raw[68..72].copy_from_slice(&NO_STREAM.to_le_bytes());
+            raw[72..76].copy_from_slice(&right.to_le_bytes());
+            raw[76..80].copy_from_slice(&child.to_le_bytes());
+            raw[116..120].copy_from_slice(&starts[i].to_le_bytes());
+            raw[120..124].copy_from_slice(&(data.len() as u32).to_le_bytes());
+        }
+
+        let mut header = vec![0u8; sector];
+        header[..8].copy_from_slice(&SIGNATURE);
+        header[0x1E..0x20].copy_from_slice(&9u16.to_le_bytes());
+        header[0x2C..0x30].copy_from_slice(&1u32.to_le_bytes());
+        header[0x30..0x34].copy_from_slice(&1u32.to_le_bytes());
+        header[0x3C..0x40].copy_from_slice(&0xFFFF_FFFEu32.to_le_bytes());
+        header[0x44..0x48].copy_from_slice(&0xFFFF_FFFEu32.to_le_bytes());
+        header[0x4C..0x50].copy_from_slice(&0u32.to_le_bytes());
+        for i in 1..109 {
+            header[0x4C + i * 4..0x50 + i * 4].copy_from_slice(&NO_STREAM.to_le_bytes());
+        }
+
+        let mut file = header;
+        file.extend(fat.iter().flat_map(|id| id.to_le_bytes()));
+        file.extend(body);
+        file
+    }
+
+    #[test]
+    fn test_read_msg() {
+        let utf16 = |s: &str| -> Vec<u8> { s.encode_utf16().flat_map(u16::to_le_bytes).collect() };
+        // 2024-10-01 09:30:00 UTC
+        let mut properties = vec![0u8; 32];
+        properties.extend(PT_SYSTIME.to_le_bytes());
+        properties.extend(PR_CLIENT_SUBMIT_TIME.to_le_bytes());
+        properties.extend([0u8; 4]);
+        properties.extend(133_722_486_000_000_000u64.to_le_bytes());
+
+        let data = compound_file(
+            &[
+                ("__substg1.0_0037001F", utf16("Board minutes")),
+                ("__substg1.0_0C1A001F", utf16("Dana Ortiz")),
+                ("__substg1.0_5D01001F", utf16("dana@example.com")),
+                ("__substg1.0_0E04001F", utf16("Board")),
+                (
+                    "__substg1.0_1000001E",
+                    b"Minutes attached.\r\nThanks\0".to_vec(),
+                ),
+                ("__properties_version1.0", properties),
+            ],
+            &[
+                ("__substg1.0_3707001F", utf16("minutes.txt")),
+                ("__substg1.0_37010102", b"Motion carried.".to_vec()),
+            ],
+        );
+
+        let email = read_msg(&data).unwrap();
+        assert_eq!(email.subject.as_deref(), Some("Board minutes"));
+        assert_eq!(email.from.as_deref(), Some("Dana Ortiz <dana@example.com>"));
+        assert_eq!(email.to.as_deref(), Some("Board"));
+        assert_eq!(email.date.as_deref(), Some("2024-10-01 09:30:00 UTC"));
+        assert_eq!(email.body, "Minutes attached.\nThanks");
+        assert_eq!(email.attachments.len(), 1);
+        assert_eq!(email.attachments[0].name, "minutes.txt");
+        assert_eq!(email.attachments[0].data, b"Motion carried.");
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        assert!(read_msg(b"From: someone\r\n\r\nhello").is_err());
+    }
+}
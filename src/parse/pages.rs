@@ -0,0 +1,162 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Form feed, which `pdftotext` writes after every page
+const PAGE_BREAK: char = '\u{c}';
+
+/// A set of 1-based pages such as `1-10,15`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageSelection {
+    /// Inclusive ranges, sorted and non-overlapping
+    ranges: Vec<(u32, u32)>,
+}
+
+impl PageSelection {
+    /// Selection of exactly `pages` (`None` if there are none)
+    pub fn from_pages(pages: impl IntoIterator<Item = u32>) -> Option<Self> {
+        let ranges: Vec<(u32, u32)> = pages.into_iter().map(|p| (p, p)).collect();
+        (!ranges.is_empty()).then(|| Self::from_ranges(ranges))
+    }
+
+    /// Merge overlapping and adjacent ranges, so equal selections compare (and cache) equal
+    fn from_ranges(mut ranges: Vec<(u32, u32)>) -> Self {
+        ranges.sort_unstable();
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(ranges.len());
+        for (start, end) in ranges {
+            match merged.last_mut() {
+                Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        Self { ranges: merged }
+    }
+
+    pub fn contains(&self, page: u32) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&page))
+    }
+
+    pub fn first(&self) -> u32 {
+        self.ranges[0].0
+    }
+
+    pub fn last(&self) -> u32 {
+        self.ranges[self.ranges.len() - 1].1
+    }
+}
+
+impl FromStr for PageSelection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            anyhow::anyhow!(
+                "Invalid page selection '{s}'. Use page numbers and ranges starting at 1, e.g. 1-10,15"
+            )
+        };
+        let page = |p: &str| p.trim().parse::<u32>().ok().filter(|&p| p > 0);
+
+        let mut ranges = Vec::new();
+        for part in s.split(',').filter(|p| !p.trim().is_empty()) {
+            let range = match part.split_once('-') {
+                Some((start, end)) => (page(start), page(end)),
+                None => (page(part), page(part)),
+            };
+            match range {
+                (Some(start), Some(end)) if start <= end => ranges.push((start, end)),
+                _ => return Err(invalid()),
+            }
+        }
+        if ranges.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self::from_ranges(ranges))
+    }
+}
+
+impl fmt::Display for PageSelection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = self
+            .ranges
+            .iter()
+            .map(|&(start, end)| {
+                if start == end {
+                    start.to_string()
+                } else {
+                    format!("{start}-{end}")
+                }
+            })
+            .collect();
+        f.write_str(&parts.join(","))
+    }
+}
+
+/// Marker placed before each page of parsed output, so citations can point at pages
+pub fn page_marker(page: u32) -> String {
+    format!("<!-- page {page} -->")
+}
+
+/// Join pages of parsed content, each preceded by its page marker
+pub fn join_pages<'a>(pages: impl IntoIterator<Item = (u32, &'a str)>) -> String {
+    let mut content = String::new();
+    for (page, text) in pages {
+        content += &page_marker(page);
+        content += "\n\n";
+        content += text.trim_end();
+        content += "\n\n";
+    }
+    content
+}
+
+/// Split `pdftotext` output into pages, numbered from `first_page`, keeping the selected ones
+pub fn select_pdftotext_pages(
+    output: &str,
+    first_page: u32,
+    pages: Option<&PageSelection>,
+) -> String {
+    let text = output.strip_suffix(PAGE_BREAK).unwrap_or(output);
+    join_pages(
+        (first_page..)
+            .zip(text.split(PAGE_BREAK))
+            .filter(|(page, _)| pages.is_none_or(|p| p.contains(*page))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_page_selection() {
+        let pages: PageSelection = "15, 1-10,3-4,11".parse().unwrap();
+        assert_eq!(pages.to_string(), "1-11,15");
+        assert!(pages.contains(1) && pages.contains(11) && pages.contains(15));
+        assert!(!pages.contains(12));
+        assert_eq!((pages.first(), pages.last()), (1, 15));
+
+        for invalid in ["", "0", "5-2", "a-b", "1-", "3,x"] {
+            assert!(invalid.parse::<PageSelection>().is_err(), "{invalid}");
+        }
+
+        let failed = PageSelection::from_pages([9, 3, 4, 5]).unwrap();
+        assert_eq!(failed.to_string(), "3-5,9");
+        assert!(PageSelection::from_pages([]).is_none());
+    }
+
+    #[test]
+    fn test_select_pdftotext_pages() {
+        let output = "first\u{c}second\u{c}third\u{c}";
+        assert_eq!(
+            select_pdftotext_pages(output, 1, None),
+            "<!-- page 1 -->\n\nfirst\n\n<!-- page 2 -->\n\nsecond\n\n<!-- page 3 -->\n\nthird\n\n"
+        );
+
+        // pdftotext was asked for pages 2-3 only
+        let pages: PageSelection = "3".parse().unwrap();
+        assert_eq!(
+            select_pdftotext_pages("second\u{c}third\u{c}", 2, Some(&pages)),
+            "<!-- page 3 -->\n\nthird\n\n"
+        );
+    }
+}
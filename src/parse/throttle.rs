@@ -0,0 +1,251 @@
+//! Backpressure for LlamaParse uploads: an AIMD concurrency limit that backs off when the API
+//! is overloaded, and a budget on the bytes of files being uploaded at once
+use reqwest::StatusCode;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{Notify, OwnedSemaphorePermit, Semaphore};
+
+/// Default for `max_bytes_in_flight`
+pub const DEFAULT_MAX_BYTES_IN_FLIGHT: u64 = 512 * 1024 * 1024;
+
+/// Overload responses within this long of a decrease are treated as part of the same burst
+const DECREASE_COOLDOWN: Duration = Duration::from_secs(2);
+
+/// Byte budget granularity. Semaphore permits are counted in these units.
+const BYTES_PER_PERMIT: u64 = 1024;
+
+/// Additive-increase/multiplicative-decrease concurrency limit
+#[derive(Debug, Clone)]
+struct Aimd {
+    limit: f64,
+    max: usize,
+    last_decrease: Option<Instant>,
+}
+
+impl Aimd {
+    fn new(max: usize) -> Self {
+        let max = max.max(1);
+        Self {
+            limit: max as f64,
+            max,
+            last_decrease: None,
+        }
+    }
+
+    fn limit(&self) -> usize {
+        self.limit as usize
+    }
+
+    /// Grow by about one slot per `limit` successful responses
+    fn increase(&mut self) {
+        self.limit = (self.limit + 1.0 / self.limit).min(self.max as f64);
+    }
+
+    /// Halve the limit, once per burst of overload responses. Returns whether it changed.
+    fn decrease(&mut self, now: Instant) -> bool {
+        if let Some(last) = self.last_decrease
+            && now.duration_since(last) < DECREASE_COOLDOWN
+        {
+            return false;
+        }
+        self.last_decrease = Some(now);
+        let halved = (self.limit / 2.0).max(1.0);
+        let changed = halved.floor() < self.limit.floor();
+        self.limit = halved;
+        changed
+    }
+}
+
+struct SlotState {
+    aimd: Aimd,
+    in_flight: usize,
+}
+
+/// Shared by all parse jobs of one run
+pub struct Throttle {
+    slots: Mutex<SlotState>,
+    slot_released: Notify,
+    bytes: Arc<Semaphore>,
+    byte_permits: u32,
+}
+
+impl Throttle {
+    /// `max_concurrency` is the ceiling the limit starts at and recovers to
+    pub fn new(max_concurrency: usize, max_bytes_in_flight: u64) -> Self {
+        let byte_permits = max_bytes_in_flight
+            .div_ceil(BYTES_PER_PERMIT)
+            .clamp(1, u32::MAX as u64) as u32;
+        Self {
+            slots: Mutex::new(SlotState {
+                aimd: Aimd::new(max_concurrency),
+                in_flight: 0,
+            }),
+            slot_released: Notify::new(),
+            bytes: Arc::new(Semaphore::new(byte_permits as usize)),
+            byte_permits,
+        }
+    }
+
+    /// Current concurrency limit
+    pub fn limit(&self) -> usize {
+        self.slots.lock().unwrap().aimd.limit()
+    }
+
+    /// Wait for a job slot under the current concurrency limit
+    pub async fn acquire_slot(self: &Arc<Self>) -> SlotPermit {
+        loop {
+            // Registered before checking, so a release in between is not missed
+            let released = self.slot_released.notified();
+            {
+                let mut state = self.slots.lock().unwrap();
+                if state.in_flight < state.aimd.limit() {
+                    state.in_flight += 1;
+                    return SlotPermit {
+                        throttle: Arc::clone(self),
+                    };
+                }
+            }
+            released.await;
+        }
+    }
+
+    /// Wait until `bytes` more can be held in memory. A file larger than the whole budget
+    /// waits for all other uploads to finish and then goes alone.
+    pub async fn reserve_bytes(&self, bytes: u64) -> OwnedSemaphorePermit {
+        let permits = bytes
+            .div_ceil(BYTES_PER_PERMIT)
+            .min(self.byte_permits as u64) as u32;
+        Arc::clone(&self.bytes)
+            .acquire_many_owned(permits)
+            .await
+            .expect("byte budget semaphore is never closed")
+    }
+
+    /// Adjust the concurrency limit from the status of an API response
+    pub fn record_status(&self, status: StatusCode) {
+        let mut state = self.slots.lock().unwrap();
+        if is_overload_status(status) {
+            if state.aimd.decrease(Instant::now()) {
+                eprintln!(
+                    "LlamaParse is overloaded ({status}), reducing concurrency to {}",
+                    state.aimd.limit()
+                );
+            }
+        } else if status.is_success() {
+            let before = state.aimd.limit();
+            state.aimd.increase();
+            if state.aimd.limit() > before {
+                self.slot_released.notify_waiters();
+            }
+        }
+    }
+}
+
+/// A job slot, released on drop
+pub struct SlotPermit {
+    throttle: Arc<Throttle>,
+}
+
+impl Drop for SlotPermit {
+    fn drop(&mut self) {
+        self.throttle.slots.lock().unwrap().in_flight -= 1;
+        self.throttle.slot_released.notify_waiters();
+    }
+}
+
+/// Rate limiting and server errors, which call for fewer concurrent requests
+pub fn is_overload_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aimd() {
+        let start = Instant::now();
+        let mut aimd = Aimd::new(8);
+        assert_eq!(aimd.limit(), 8);
+
+        assert!(aimd.decrease(start));
+        assert_eq!(aimd.limit(), 4);
+
+        // The rest of the same burst is ignored
+        assert!(!aimd.decrease(start + Duration::from_millis(500)));
+        assert_eq!(aimd.limit(), 4);
+
+        for i in 1..3 {
+            assert!(aimd.decrease(start + DECREASE_COOLDOWN * i));
+        }
+        assert_eq!(aimd.limit(), 1);
+        assert!(!aimd.decrease(start + DECREASE_COOLDOWN * 3));
+        assert_eq!(aimd.limit(), 1);
+
+        // Recovery takes about `limit` successes per extra slot, and stops at the ceiling
+        aimd.increase();
+        assert_eq!(aimd.limit(), 2);
+        aimd.increase();
+        assert_eq!(aimd.limit(), 2);
+        for _ in 0..100 {
+            aimd.increase();
+        }
+        assert_eq!(aimd.limit(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_slots_follow_limit() {
+        let throttle = Arc::new(Throttle::new(2, DEFAULT_MAX_BYTES_IN_FLIGHT));
+        let first = throttle.acquire_slot().await;
+        let _second = throttle.acquire_slot().await;
+
+        throttle.record_status(StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(throttle.limit(), 1);
+
+        // Two jobs are still running, so a third has to wait even after one finishes
+        let waiting = tokio::spawn({
+            let throttle = Arc::clone(&throttle);
+            async move { throttle.acquire_slot().await }
+        });
+        drop(first);
+        tokio::task::yield_now().await;
+        assert!(!waiting.is_finished());
+
+        throttle.record_status(StatusCode::OK);
+        let _third = tokio::time::timeout(Duration::from_secs(5), waiting)
+            .await
+            .expect("slot freed up once the limit recovered")
+            .unwrap();
+        assert_eq!(throttle.limit(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_byte_budget() {
+        let throttle = Throttle::new(4, 10 * BYTES_PER_PERMIT);
+        let first = throttle.reserve_bytes(6 * BYTES_PER_PERMIT).await;
+        assert_eq!(throttle.bytes.available_permits(), 4);
+
+        // Does not fit next to the first upload
+        let second = throttle.reserve_bytes(6 * BYTES_PER_PERMIT);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), second)
+                .await
+                .is_err()
+        );
+        drop(first);
+
+        // Larger than the whole budget: goes alone
+        let huge = throttle.reserve_bytes(100 * BYTES_PER_PERMIT).await;
+        assert_eq!(throttle.bytes.available_permits(), 0);
+        drop(huge);
+        assert_eq!(throttle.bytes.available_permits(), 10);
+    }
+
+    #[test]
+    fn test_is_overload_status() {
+        assert!(is_overload_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_overload_status(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(!is_overload_status(StatusCode::BAD_REQUEST));
+        assert!(!is_overload_status(StatusCode::OK));
+    }
+}
@@ -0,0 +1,185 @@
+//! Where semtools keeps its state, following the XDG base directory spec:
+//!
+//! - the config file in `$XDG_CONFIG_HOME/semtools/config.json` (`~/.config` by default)
+//! - the parse cache, downloads and cached answers in `$XDG_CACHE_HOME/semtools` (`~/.cache`)
+//! - workspaces, saved searches and the `ask --interactive` prompt history in
+//!   `$XDG_DATA_HOME/semtools` (`~/.local/share`)
+//!
+//! A project can add a `.semtools.json` of its own, found in the working directory or one of
+//! its parents and merged over the config file.
+//!
+//! Older versions used `~/.semtools_config.json`, `~/.parse` and `~/.semtools`. Each of them
+//! is moved to its new location the first time it is looked up.
+use anyhow::Result;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const APP_DIR: &str = "semtools";
+
+fn home() -> Result<PathBuf> {
+    dirs::home_dir().ok_or_else(|| anyhow::anyhow!("No home dir found?"))
+}
+
+/// `$var/semtools`, or `~/<default>/semtools` when the variable is unset or not an absolute
+/// path (which the spec says to ignore)
+fn xdg_dir(value: Option<OsString>, home: &Path, default: &str) -> PathBuf {
+    let base = value
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| home.join(default));
+    base.join(APP_DIR)
+}
+
+fn config_dir() -> Result<PathBuf> {
+    Ok(xdg_dir(
+        std::env::var_os("XDG_CONFIG_HOME"),
+        &home()?,
+        ".config",
+    ))
+}
+
+/// Caches that can be deleted at any time
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(xdg_dir(
+        std::env::var_os("XDG_CACHE_HOME"),
+        &home()?,
+        ".cache",
+    ))
+}
+
+/// State that cannot be recreated, such as workspaces
+pub fn data_dir() -> Result<PathBuf> {
+    Ok(xdg_dir(
+        std::env::var_os("XDG_DATA_HOME"),
+        &home()?,
+        ".local/share",
+    ))
+}
+
+/// `~/.semtools`, where workspaces, downloads and saved searches used to live
+pub fn legacy_dir() -> Result<PathBuf> {
+    Ok(home()?.join(".semtools"))
+}
+
+/// The config file, moved from `~/.semtools_config.json` if needed
+pub fn config_file() -> Result<PathBuf> {
+    Ok(migrate(
+        &home()?.join(".semtools_config.json"),
+        config_dir()?.join("config.json"),
+    ))
+}
+
+/// Name of the project config file, merged over the config file
+pub const PROJECT_CONFIG_FILE: &str = ".semtools.json";
+
+/// The nearest project config file in `dir` or one of its parents, like `.editorconfig`
+pub fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// The project config file that applies in the working directory, if any
+pub fn project_config_file() -> Option<PathBuf> {
+    find_project_config(&std::env::current_dir().ok()?)
+}
+
+/// The parse cache, moved from `~/.parse` if needed
+pub fn parse_cache_dir() -> Result<PathBuf> {
+    Ok(migrate(&home()?.join(".parse"), cache_dir()?.join("parse")))
+}
+
+/// `name` under the cache directory, moved from `~/.semtools/<name>` if needed
+pub fn cache_path(name: &str) -> Result<PathBuf> {
+    Ok(migrate(&legacy_dir()?.join(name), cache_dir()?.join(name)))
+}
+
+/// `name` under the data directory, moved from `~/.semtools/<name>` if needed
+pub fn data_path(name: &str) -> Result<PathBuf> {
+    Ok(migrate(&legacy_dir()?.join(name), data_dir()?.join(name)))
+}
+
+/// Move `legacy` to `new` unless `new` already exists, and return the path to use. If the
+/// move fails (e.g. across filesystems) the legacy path keeps being used, so nothing is lost.
+pub fn migrate(legacy: &Path, new: PathBuf) -> PathBuf {
+    if new.exists() || !legacy.exists() {
+        return new;
+    }
+    let moved = new
+        .parent()
+        .is_none_or(|parent| fs::create_dir_all(parent).is_ok())
+        && fs::rename(legacy, &new).is_ok();
+    if moved {
+        eprintln!("Moved {} to {}", legacy.display(), new.display());
+        new
+    } else {
+        legacy.to_path_buf()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_xdg_dir() {
+        let home = Path::new("/home/me");
+        assert_eq!(
+            xdg_dir(None, home, ".config"),
+            PathBuf::from("/home/me/.config/semtools")
+        );
+        assert_eq!(
+            xdg_dir(Some("/xdg/config".into()), home, ".config"),
+            PathBuf::from("/xdg/config/semtools")
+        );
+        // Relative values are invalid and ignored
+        assert_eq!(
+            xdg_dir(Some("config".into()), home, ".config"),
+            PathBuf::from("/home/me/.config/semtools")
+        );
+    }
+
+    #[test]
+    fn test_find_project_config() {
+        let temp_dir = TempDir::new().unwrap();
+        let nested = temp_dir.path().join("src").join("deep");
+        fs::create_dir_all(&nested).unwrap();
+        assert_eq!(find_project_config(&nested), None);
+
+        let project = temp_dir.path().join(PROJECT_CONFIG_FILE);
+        fs::write(&project, "{}").unwrap();
+        assert_eq!(find_project_config(&nested), Some(project.clone()));
+        assert_eq!(find_project_config(temp_dir.path()), Some(project));
+    }
+
+    #[test]
+    fn test_migrate() {
+        let temp_dir = TempDir::new().unwrap();
+        let legacy = temp_dir.path().join(".parse");
+        let new = temp_dir
+            .path()
+            .join(".cache")
+            .join("semtools")
+            .join("parse");
+
+        // Nothing to move
+        assert_eq!(migrate(&legacy, new.clone()), new);
+        assert!(!new.exists());
+
+        fs::create_dir_all(&legacy).unwrap();
+        fs::write(legacy.join("doc.pdf.md"), "parsed").unwrap();
+        assert_eq!(migrate(&legacy, new.clone()), new);
+        assert!(!legacy.exists());
+        assert_eq!(
+            fs::read_to_string(new.join("doc.pdf.md")).unwrap(),
+            "parsed"
+        );
+
+        // An existing new location is never overwritten
+        fs::create_dir_all(&legacy).unwrap();
+        assert_eq!(migrate(&legacy, new.clone()), new);
+        assert!(legacy.exists());
+    }
+}
@@ -0,0 +1,1093 @@
+//! The search ranking logic, free of the embedding model, the filesystem and native code.
+//!
+//! Documents come with their line embeddings already computed, so the same ranking runs in
+//! the CLI and, built for `wasm32-unknown-unknown` with the `wasm` feature, in a browser
+//! against embeddings shipped from a server (see `crate::wasm`). Cosine distances use simsimd
+//! when the `simsimd` dependency is built, and portable Rust otherwise.
+use anyhow::Result;
+use std::borrow::Cow;
+use std::cmp::{max, min};
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::metadata::MetadataFilter;
+
+#[cfg(feature = "simsimd")]
+use simsimd::SpatialSimilarity;
+
+/// Cosine distance between two embeddings (0 is identical), or `None` if their lengths differ
+#[cfg(feature = "simsimd")]
+pub fn cosine_distance(a: &[f32], b: &[f32]) -> Option<f64> {
+    f32::cosine(a, b)
+}
+
+/// Cosine distance between two embeddings (0 is identical), or `None` if their lengths differ
+#[cfg(not(feature = "simsimd"))]
+pub fn cosine_distance(a: &[f32], b: &[f32]) -> Option<f64> {
+    if a.len() != b.len() {
+        return None;
+    }
+    let (mut ab, mut a2, mut b2) = (0.0_f64, 0.0_f64, 0.0_f64);
+    for (&x, &y) in a.iter().zip(b) {
+        let (x, y) = (x as f64, y as f64);
+        ab += x * y;
+        a2 += x * x;
+        b2 += y * y;
+    }
+    // Same conventions as simsimd for zero vectors
+    if a2 == 0.0 && b2 == 0.0 {
+        return Some(0.0);
+    }
+    if ab == 0.0 {
+        return Some(1.0);
+    }
+    Some((1.0 - ab / (a2 * b2).sqrt()).max(0.0))
+}
+
+/// Split a document into the lines that are embedded and searched
+pub fn split_lines(content: &str) -> Vec<String> {
+    content.lines().map(str::to_string).collect()
+}
+
+/// A document split into lines, with one embedding per line
+pub struct Document {
+    pub filename: String,
+    pub lines: Vec<String>,
+    pub embeddings: Vec<Vec<f32>>,
+    /// Embedded rows of the document's tables, each matching on behalf of its line
+    pub table_rows: Vec<RowEmbedding>,
+}
+
+/// The embedding of a table row, searched as a pseudo-line of `header: cell` pairs (see
+/// `crate::search::tables`), and the 0-based line holding the row
+#[derive(Debug, Clone)]
+pub struct RowEmbedding {
+    pub line: usize,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Default)]
+pub struct SearchConfig {
+    pub n_lines: usize,
+    pub top_k: usize,
+    pub max_distance: Option<f64>,
+    pub ignore_case: bool,
+    /// Weight of file recency in the ranking score (0.0 disables)
+    pub recency_boost: f64,
+    /// Re-select results with maximal marginal relevance; 1.0 is pure relevance, 0.0 pure diversity
+    pub mmr_lambda: Option<f64>,
+    /// Embedded negative queries ("but not about X"); see `negative_penalty`
+    pub negative_embeddings: Vec<Vec<f32>>,
+    /// Weight of the distance to the closest negative query in the ranking score
+    pub negative_weight: f64,
+    /// Embedded queries combined with the main query (`--all-of`/`--any-of`)
+    pub extra_query_embeddings: Vec<Vec<f32>>,
+    pub query_combination: QueryCombination,
+    /// Workspace documents must carry all of these tags (`--filter tag:key=value`)
+    pub tag_filters: Vec<TagFilter>,
+    /// Workspace documents must also match these filters on their metadata
+    /// (`--filter author:name`)
+    pub metadata_filters: Vec<MetadataFilter>,
+    /// Weights of the files matching a glob (`--boost 'docs/**=1.2'`); see `path_weight`
+    pub boosts: Vec<PathBoost>,
+}
+
+/// How the main query and the extra queries combine into a line's distance
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QueryCombination {
+    /// The line must be close to every query: the largest per-query distance
+    #[default]
+    All,
+    /// The line may be close to any query: the smallest per-query distance
+    Any,
+}
+
+/// Default weight of negative queries in the ranking score
+pub const DEFAULT_NEGATIVE_WEIGHT: f64 = 0.5;
+
+/// Candidates considered per requested result when re-ranking in-memory results with MMR
+pub(crate) const MMR_OVERSAMPLE_FACTOR: usize = 3;
+
+/// How a file's score is derived from its matching lines in files-with-matches mode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FileScoreMode {
+    /// Score of the best matching line
+    #[default]
+    Best,
+    /// Mean score of the (up to) three best matching lines
+    MeanTop3,
+}
+
+impl std::str::FromStr for FileScoreMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "best" => Ok(FileScoreMode::Best),
+            "mean-top3" => Ok(FileScoreMode::MeanTop3),
+            _ => Err(anyhow::anyhow!(
+                "Invalid file score mode: '{s}'. Must be 'best' or 'mean-top3'"
+            )),
+        }
+    }
+}
+
+/// A `--filter` on the tags stored with workspace documents, written `tag:key=value`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagFilter {
+    pub key: String,
+    pub value: String,
+}
+
+impl std::str::FromStr for TagFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(tag) = s.strip_prefix("tag:") else {
+            anyhow::bail!("Invalid filter: '{s}'. Filters have the form 'tag:key=value'");
+        };
+        let (key, value) = parse_tag(tag)?;
+        Ok(TagFilter { key, value })
+    }
+}
+
+/// A `--boost` of the files matching a glob, written `glob=weight`. Similarities of their
+/// lines are multiplied by the weight, so weights above 1 promote the files and below 1 demote
+/// them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathBoost {
+    pub glob: String,
+    pub weight: f64,
+}
+
+impl std::str::FromStr for PathBoost {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((glob, weight)) = s.rsplit_once('=') else {
+            anyhow::bail!("Invalid boost: '{s}'. Boosts have the form 'glob=weight'");
+        };
+        let weight: f64 = weight
+            .trim()
+            .parse()
+            .ok()
+            .filter(|w: &f64| w.is_finite() && *w >= 0.0)
+            .ok_or_else(|| {
+                anyhow::anyhow!("Invalid boost weight '{weight}': use a number of 0 or more")
+            })?;
+        let glob = glob.trim().trim_start_matches("./");
+        if glob.is_empty() {
+            anyhow::bail!("Invalid boost: '{s}'. The glob is empty");
+        }
+        Ok(PathBoost {
+            glob: glob.to_string(),
+            weight,
+        })
+    }
+}
+
+impl PathBoost {
+    /// Whether the boost applies to `path`. A glob with a `/` is matched against the whole
+    /// path, as given on the command line or stored in the workspace; one without is matched
+    /// against the file name. A trailing `/` matches everything under a directory.
+    pub fn matches(&self, path: &str) -> bool {
+        let path = path.trim_start_matches("./");
+        if let Some(dir) = self.glob.strip_suffix('/') {
+            return glob_match(&format!("{dir}/**"), path);
+        }
+        if self.glob.contains('/') {
+            glob_match(&self.glob, path)
+        } else {
+            glob_match(&self.glob, path.rsplit('/').next().unwrap_or(path))
+        }
+    }
+}
+
+/// Product of the weights of the boosts matching `path` (1.0 when none does)
+pub fn path_weight(path: &str, boosts: &[PathBoost]) -> f64 {
+    boosts
+        .iter()
+        .filter(|boost| boost.matches(path))
+        .map(|boost| boost.weight)
+        .product()
+}
+
+/// Apply a path weight to a ranking score (lower is better) by scaling the similarity it
+/// stands for, `1 - score`
+pub fn apply_path_weight(score: f64, weight: f64) -> f64 {
+    if weight == 1.0 {
+        return score;
+    }
+    1.0 - (1.0 - score) * weight
+}
+
+/// Match `text` against a glob where `*` and `?` stay within a path segment and `**` crosses
+/// segments (`docs/**` matches everything under `docs`)
+fn glob_match(glob: &str, text: &str) -> bool {
+    fn matches(glob: &[u8], text: &[u8]) -> bool {
+        match glob {
+            [] => text.is_empty(),
+            [b'*', b'*', b'/', rest @ ..] => {
+                matches(rest, text)
+                    || text
+                        .iter()
+                        .enumerate()
+                        .any(|(i, &c)| c == b'/' && matches(rest, &text[i + 1..]))
+            }
+            [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+            [b'*', rest @ ..] => {
+                let segment = text.iter().position(|&c| c == b'/').unwrap_or(text.len());
+                (0..=segment).any(|i| matches(rest, &text[i..]))
+            }
+            [b'?', rest @ ..] => {
+                text.first().is_some_and(|&c| c != b'/') && matches(rest, &text[1..])
+            }
+            [c, rest @ ..] => text.first() == Some(c) && matches(rest, &text[1..]),
+        }
+    }
+    matches(glob.as_bytes(), text.as_bytes())
+}
+
+/// Parse a `key=value` tag. Keys are limited to letters, digits, '-' and '_' so they can be
+/// used as payload paths in the index.
+pub fn parse_tag(s: &str) -> Result<(String, String)> {
+    let Some((key, value)) = s.split_once('=') else {
+        anyhow::bail!("Invalid tag: '{s}'. Tags have the form 'key=value'");
+    };
+    if key.is_empty()
+        || !key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        anyhow::bail!("Invalid tag key '{key}': use letters, digits, '-' and '_'");
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// A file ranked by its matching lines
+#[derive(Debug, Clone)]
+pub struct FileMatch {
+    pub filename: String,
+    pub score: f64,
+    pub matching_lines: usize,
+}
+
+/// Collapse line-level matches `(filename, score)` into distinct files, best first
+pub fn rank_files<I>(line_matches: I, mode: FileScoreMode) -> Vec<FileMatch>
+where
+    I: IntoIterator<Item = (String, f64)>,
+{
+    let mut scores_by_file: HashMap<String, Vec<f64>> = HashMap::new();
+    for (filename, score) in line_matches {
+        scores_by_file.entry(filename).or_default().push(score);
+    }
+
+    let mut files: Vec<FileMatch> = scores_by_file
+        .into_iter()
+        .map(|(filename, mut scores)| {
+            scores.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+            let score = match mode {
+                FileScoreMode::Best => scores[0],
+                FileScoreMode::MeanTop3 => {
+                    let top = &scores[..scores.len().min(3)];
+                    top.iter().sum::<f64>() / top.len() as f64
+                }
+            };
+            FileMatch {
+                filename,
+                score,
+                matching_lines: scores.len(),
+            }
+        })
+        .collect();
+
+    files.sort_by(|a, b| {
+        a.score
+            .partial_cmp(&b.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.filename.cmp(&b.filename))
+    });
+    files
+}
+
+pub struct SearchResult {
+    pub filename: String,
+    pub lines: Vec<String>,
+    pub start: usize,
+    pub end: usize,
+    pub match_line: usize, // The actual line number that matched
+    pub distance: f64,
+    /// Ranking score: the distance, minus the recency boost if enabled, with the path weight
+    /// applied (lower is better)
+    pub score: f64,
+    /// Byte offset in the matched line where the best-matching span starts (0 until located
+    /// with `locate_match_spans`)
+    pub match_start_col: usize,
+    /// Byte offset in the matched line just past the best-matching span (0 until located)
+    pub match_end_col: usize,
+}
+
+impl SearchResult {
+    /// The matched line
+    pub fn match_text(&self) -> Option<&str> {
+        self.lines
+            .get(self.match_line.saturating_sub(self.start))
+            .map(String::as_str)
+    }
+
+    /// The best-matching span of the matched line, if located
+    pub fn match_span(&self) -> Option<(usize, usize)> {
+        (self.match_end_col > 0).then_some((self.match_start_col, self.match_end_col))
+    }
+
+    /// Whether the matched line is longer than `max_line_chars` (0 for no limit)
+    pub fn has_long_match_line(&self, max_line_chars: usize) -> bool {
+        max_line_chars > 0
+            && self
+                .match_text()
+                .is_some_and(|line| line.chars().count() > max_line_chars)
+    }
+
+    /// The result's lines as snippets of at most `max_line_chars` characters (see `snippet`),
+    /// the matched line cut around its best-matching span
+    pub fn snippet_lines(&self, max_line_chars: usize) -> impl Iterator<Item = Cow<'_, str>> {
+        let match_idx = self.match_line.saturating_sub(self.start);
+        self.lines.iter().enumerate().map(move |(i, line)| {
+            let span = if i == match_idx {
+                self.match_span()
+            } else {
+                None
+            };
+            snippet(line, span, max_line_chars)
+        })
+    }
+}
+
+/// Longest line printed whole in search results by default; longer ones are cut to a snippet
+pub const DEFAULT_MAX_LINE_CHARS: usize = 300;
+
+/// `line` cut to at most `max_chars` characters around the byte range `span` (or from its
+/// start without one), with an ellipsis where text was left out. Lines of parsed documents
+/// (tables, pages without line breaks) can run for thousands of characters. A `max_chars` of
+/// 0 keeps every line whole.
+pub fn snippet(line: &str, span: Option<(usize, usize)>, max_chars: usize) -> Cow<'_, str> {
+    let total = line.chars().count();
+    if max_chars == 0 || total <= max_chars {
+        return Cow::Borrowed(line);
+    }
+    let (span_start, span_end) = span
+        .filter(|&(start, end)| {
+            start <= end
+                && end <= line.len()
+                && line.is_char_boundary(start)
+                && line.is_char_boundary(end)
+        })
+        .map(|(start, end)| (line[..start].chars().count(), line[..end].chars().count()))
+        .unwrap_or((0, 0));
+
+    // Center the span in the snippet, or start with it when it does not fit
+    let span_chars = span_end - span_start;
+    let first = span_start
+        .saturating_sub(max_chars.saturating_sub(span_chars) / 2)
+        .min(total - max_chars);
+    let text: String = line.chars().skip(first).take(max_chars).collect();
+
+    let mut cut = String::with_capacity(text.len() + 6);
+    if first > 0 {
+        cut.push('…');
+        cut.push_str(text.trim_start());
+    } else {
+        cut.push_str(&text);
+    }
+    if first + max_chars < total {
+        cut.truncate(cut.trim_end().len());
+        cut.push('…');
+    }
+    Cow::Owned(cut)
+}
+
+/// Normalize file modification times to `[0, 1]`, where the oldest file is 0 and the newest 1.
+/// If all files share the same mtime, every file gets 0.
+pub fn normalized_recency(mtimes: &HashMap<String, i64>) -> HashMap<String, f64> {
+    let oldest = mtimes.values().copied().min().unwrap_or(0);
+    let newest = mtimes.values().copied().max().unwrap_or(0);
+    let span = (newest - oldest) as f64;
+
+    mtimes
+        .iter()
+        .map(|(path, &mtime)| {
+            let recency = if span > 0.0 {
+                (mtime - oldest) as f64 / span
+            } else {
+                0.0
+            };
+            (path.clone(), recency)
+        })
+        .collect()
+}
+
+/// Distance of a line to the query, combined with the distance to each extra query
+pub fn combined_distance(
+    query_embedding: &[f32],
+    embedding: &[f32],
+    config: &SearchConfig,
+) -> Option<f64> {
+    let distance = cosine_distance(query_embedding, embedding)?;
+    config
+        .extra_query_embeddings
+        .iter()
+        .try_fold(distance, |combined, query| {
+            let distance = cosine_distance(query, embedding)?;
+            Some(match config.query_combination {
+                QueryCombination::All => combined.max(distance),
+                QueryCombination::Any => combined.min(distance),
+            })
+        })
+}
+
+/// Ranking score of a line with negative queries: its distance minus `negative_weight` times
+/// its distance to the closest negative query, so lines near a negative query drop down.
+/// Without negative queries the score is the distance.
+pub fn negative_penalty(distance: f64, embedding: &[f32], config: &SearchConfig) -> f64 {
+    let closest = config
+        .negative_embeddings
+        .iter()
+        .filter_map(|negative| cosine_distance(embedding, negative))
+        .fold(f64::INFINITY, f64::min);
+    if closest.is_finite() {
+        distance - config.negative_weight * closest
+    } else {
+        distance
+    }
+}
+
+/// Blend a semantic distance with a normalized recency into a ranking score (lower is better)
+pub fn blend_recency(distance: f64, recency: f64, recency_boost: f64) -> f64 {
+    distance - recency_boost * recency
+}
+
+/// Select up to `k` candidates by maximal marginal relevance, returning their indices in
+/// selection order. `relevance` is each candidate's similarity to the query (higher is better),
+/// and `lambda` trades relevance (1.0) against diversity from already selected results (0.0).
+pub fn mmr_select(embeddings: &[&[f32]], relevance: &[f64], k: usize, lambda: f64) -> Vec<usize> {
+    let mut selected: Vec<usize> = Vec::with_capacity(k.min(embeddings.len()));
+    let mut remaining: Vec<usize> = (0..embeddings.len()).collect();
+
+    while selected.len() < k && !remaining.is_empty() {
+        let mut best_pos = 0;
+        let mut best_score = f64::NEG_INFINITY;
+
+        for (pos, &candidate) in remaining.iter().enumerate() {
+            let redundancy = selected
+                .iter()
+                .filter_map(|&s| cosine_distance(embeddings[candidate], embeddings[s]))
+                .map(|distance| 1.0 - distance)
+                .fold(0.0, f64::max);
+            let score = lambda * relevance[candidate] - (1.0 - lambda) * redundancy;
+            if score > best_score {
+                best_score = score;
+                best_pos = pos;
+            }
+        }
+
+        selected.push(remaining.remove(best_pos));
+    }
+
+    selected
+}
+
+/// Lines scored per batch when searching in-memory documents
+const SCORE_BATCH_SIZE: usize = 1024;
+
+/// A scored line, kept without its context until it makes the final results
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Candidate<'a> {
+    pub(crate) doc: usize,
+    pub(crate) filename: &'a str,
+    pub(crate) line: usize,
+    pub(crate) distance: f64,
+    pub(crate) score: f64,
+}
+
+impl Candidate<'_> {
+    /// Best score first, ties in file and line order so output is stable
+    fn rank(&self, other: &Self) -> std::cmp::Ordering {
+        self.score
+            .total_cmp(&other.score)
+            .then_with(|| self.filename.cmp(other.filename))
+            .then_with(|| self.line.cmp(&other.line))
+    }
+}
+
+impl PartialEq for Candidate<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank(other).is_eq()
+    }
+}
+
+impl Eq for Candidate<'_> {}
+
+impl PartialOrd for Candidate<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate<'_> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.rank(other)
+    }
+}
+
+/// The best-ranked candidates seen so far, bounded to `limit` when set. The heap's top is the
+/// worst candidate kept, so a better one replaces it in O(log k).
+pub(crate) struct TopCandidates<'a> {
+    heap: BinaryHeap<Candidate<'a>>,
+    limit: Option<usize>,
+}
+
+impl<'a> TopCandidates<'a> {
+    pub(crate) fn new(limit: Option<usize>) -> Self {
+        Self {
+            heap: BinaryHeap::with_capacity(limit.unwrap_or(0)),
+            limit,
+        }
+    }
+
+    /// Add a candidate, returning the one that didn't make the cut: `candidate` itself, or the
+    /// worst one kept so far when `candidate` replaced it
+    pub(crate) fn push(&mut self, candidate: Candidate<'a>) -> Option<Candidate<'a>> {
+        match self.limit {
+            Some(0) => Some(candidate),
+            Some(limit) if self.heap.len() >= limit => match self.heap.peek_mut() {
+                Some(mut worst) if candidate < *worst => {
+                    Some(std::mem::replace(&mut *worst, candidate))
+                }
+                _ => Some(candidate),
+            },
+            _ => {
+                self.heap.push(candidate);
+                None
+            }
+        }
+    }
+
+    /// Candidates best first
+    pub(crate) fn into_sorted_vec(self) -> Vec<Candidate<'a>> {
+        self.heap.into_sorted_vec()
+    }
+}
+
+/// The lines of a search scored against the query, of which only the best are kept
+pub struct ScoredLines<'a> {
+    candidates: TopCandidates<'a>,
+    /// Lines compared with the query
+    pub lines_scored: usize,
+    /// Lines under `max_distance` (all of them without a threshold)
+    pub within_threshold: usize,
+}
+
+/// Score every line of `documents` against the query. `recency` holds each document's
+/// normalized recency (see `normalized_recency`), blended in with `config.recency_boost`, and
+/// each document's `--boost` weight is applied last.
+pub fn score_lines<'a>(
+    documents: &'a [Document],
+    query_embedding: &[f32],
+    config: &SearchConfig,
+    recency: &[f64],
+) -> ScoredLines<'a> {
+    // With a distance threshold every line under it is a result; otherwise only the best
+    // `top_k` (or the MMR candidate pool) are kept while scoring
+    let limit = match (config.max_distance, config.mmr_lambda) {
+        (Some(_), _) => None,
+        (None, Some(_)) => Some(config.top_k.saturating_mul(MMR_OVERSAMPLE_FACTOR)),
+        (None, None) => Some(config.top_k),
+    };
+    let mut candidates = TopCandidates::new(limit);
+    let mut within_threshold = 0;
+    let mut distances = Vec::with_capacity(SCORE_BATCH_SIZE);
+    let weights: Vec<f64> = documents
+        .iter()
+        .map(|doc| path_weight(&doc.filename, &config.boosts))
+        .collect();
+
+    for (doc_idx, doc) in documents.iter().enumerate() {
+        // A line with table rows is as close as the closest of them, or the line itself
+        let mut row_distances: HashMap<usize, f64> = HashMap::new();
+        for row in &doc.table_rows {
+            if let Some(distance) = combined_distance(query_embedding, &row.embedding, config) {
+                let best = row_distances.entry(row.line).or_insert(distance);
+                *best = best.min(distance);
+            }
+        }
+
+        for (batch_idx, batch) in doc.embeddings.chunks(SCORE_BATCH_SIZE).enumerate() {
+            distances.clear();
+            distances.extend(
+                batch
+                    .iter()
+                    .map(|embedding| combined_distance(query_embedding, embedding, config)),
+            );
+
+            for (offset, distance) in distances.iter().enumerate() {
+                let line = batch_idx * SCORE_BATCH_SIZE + offset;
+                let distance = match (*distance, row_distances.get(&line)) {
+                    (Some(distance), Some(&row)) => distance.min(row),
+                    (Some(distance), None) => distance,
+                    (None, Some(&row)) => row,
+                    (None, None) => continue,
+                };
+                if distance >= config.max_distance.unwrap_or(100.0) {
+                    continue;
+                }
+                within_threshold += 1;
+
+                let score = apply_path_weight(
+                    blend_recency(
+                        negative_penalty(distance, &batch[offset], config),
+                        recency.get(doc_idx).copied().unwrap_or(0.0),
+                        config.recency_boost,
+                    ),
+                    weights[doc_idx],
+                );
+                candidates.push(Candidate {
+                    doc: doc_idx,
+                    filename: &doc.filename,
+                    line,
+                    distance,
+                    score,
+                });
+            }
+        }
+    }
+
+    ScoredLines {
+        candidates,
+        lines_scored: documents.iter().map(|doc| doc.embeddings.len()).sum(),
+        within_threshold,
+    }
+}
+
+/// The results of a search from its scored lines, best first
+pub fn rank_lines(
+    documents: &[Document],
+    scored: ScoredLines<'_>,
+    config: &SearchConfig,
+) -> Vec<SearchResult> {
+    rank_candidates(documents, scored.candidates.into_sorted_vec(), config)
+}
+
+/// Search documents whose lines are already embedded: `score_lines` then `rank_lines`
+pub fn rank_documents(
+    documents: &[Document],
+    query_embedding: &[f32],
+    config: &SearchConfig,
+    recency: &[f64],
+) -> Vec<SearchResult> {
+    let scored = score_lines(documents, query_embedding, config, recency);
+    rank_lines(documents, scored, config)
+}
+
+/// Diversify or cut the ranked candidates to `top_k`, then materialize the context lines of
+/// the ones that remain
+fn rank_candidates(
+    documents: &[Document],
+    mut candidates: Vec<Candidate<'_>>,
+    config: &SearchConfig,
+) -> Vec<SearchResult> {
+    // Diversify with MMR over the best candidates
+    if let Some(lambda) = config.mmr_lambda {
+        let k = if config.max_distance.is_some() {
+            candidates.len()
+        } else {
+            config.top_k
+        };
+        candidates.truncate(k.saturating_mul(MMR_OVERSAMPLE_FACTOR));
+
+        let embeddings: Vec<&[f32]> = candidates
+            .iter()
+            .map(|c| documents[c.doc].embeddings[c.line].as_slice())
+            .collect();
+        let relevance: Vec<f64> = candidates.iter().map(|c| 1.0 - c.score).collect();
+
+        let order = mmr_select(&embeddings, &relevance, k, lambda);
+        return order
+            .into_iter()
+            .map(|i| materialize(documents, &candidates[i], config.n_lines))
+            .collect();
+    }
+
+    // If threshold is specified, return all results under threshold
+    // Otherwise, limit to top_k results
+    if config.max_distance.is_none() {
+        candidates.truncate(config.top_k);
+    }
+    candidates
+        .iter()
+        .map(|c| materialize(documents, c, config.n_lines))
+        .collect()
+}
+
+/// Build the result for a candidate, with `n_lines` of context around the match
+fn materialize(documents: &[Document], candidate: &Candidate<'_>, n_lines: usize) -> SearchResult {
+    let doc = &documents[candidate.doc];
+    let idx = candidate.line;
+    let bottom_range = max(0, idx.saturating_sub(n_lines));
+    let top_range = min(doc.lines.len(), idx + n_lines + 1);
+
+    SearchResult {
+        filename: doc.filename.clone(),
+        lines: doc.lines[bottom_range..top_range].to_vec(),
+        distance: candidate.distance,
+        score: candidate.score,
+        start: bottom_range,
+        end: top_range,
+        match_line: idx,
+        match_start_col: 0,
+        match_end_col: 0,
+    }
+}
+
+/// Indices of the query terms carrying most of its meaning, given each term's distance to the
+/// whole query: those no farther than the average term, in query order. Never empty unless
+/// `distances` is.
+pub fn strongest_terms(distances: &[f64]) -> Vec<usize> {
+    if distances.is_empty() {
+        return Vec::new();
+    }
+    let mean = distances.iter().sum::<f64>() / distances.len() as f64;
+    (0..distances.len())
+        .filter(|&i| distances[i] <= mean)
+        .collect()
+}
+
+/// Byte ranges of every run of `window` consecutive whitespace-separated words in `line`
+pub fn word_windows(line: &str, window: usize) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut word_start = None;
+    for (idx, c) in line.char_indices() {
+        match (c.is_whitespace(), word_start) {
+            (true, Some(start)) => {
+                words.push((start, idx));
+                word_start = None;
+            }
+            (false, None) => word_start = Some(idx),
+            _ => {}
+        }
+    }
+    if let Some(start) = word_start {
+        words.push((start, line.len()));
+    }
+
+    let window = window.clamp(1, words.len().max(1));
+    words
+        .windows(window)
+        .map(|w| (w[0].0, w[window - 1].1))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_config() -> SearchConfig {
+        SearchConfig {
+            n_lines: 3,
+            top_k: 3,
+            negative_weight: DEFAULT_NEGATIVE_WEIGHT,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cosine_distance() {
+        assert!(cosine_distance(&[1.0, 0.0], &[1.0, 0.0]).unwrap().abs() < 1e-9);
+        assert!((cosine_distance(&[1.0, 0.0], &[0.0, 2.0]).unwrap() - 1.0).abs() < 1e-9);
+        assert!((cosine_distance(&[1.0, 1.0], &[1.0, 0.0]).unwrap() - 0.2929).abs() < 1e-3);
+        assert_eq!(cosine_distance(&[1.0, 0.0], &[1.0]), None);
+    }
+
+    #[test]
+    fn test_word_windows() {
+        let line = "  let total = a + b;";
+        assert_eq!(word_windows(line, 1)[0], (2, 5));
+        assert_eq!(
+            &line[word_windows(line, 2)[1].0..word_windows(line, 2)[1].1],
+            "total ="
+        );
+        // Windows wider than the line cover the whole trimmed line
+        assert_eq!(word_windows(line, 10), vec![(2, line.len())]);
+        assert!(word_windows("   ", 2).is_empty());
+    }
+
+    #[test]
+    fn test_strongest_terms() {
+        // "the" and "of" sit far from the query they belong to
+        assert_eq!(strongest_terms(&[0.9, 0.2, 0.8, 0.3]), vec![1, 3]);
+        assert_eq!(strongest_terms(&[0.5, 0.5]), vec![0, 1]);
+        assert!(strongest_terms(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_rank_files() {
+        let line_matches = vec![
+            ("a.md".to_string(), 0.10),
+            ("a.md".to_string(), 0.50),
+            ("a.md".to_string(), 0.60),
+            ("b.md".to_string(), 0.20),
+            ("b.md".to_string(), 0.25),
+        ];
+
+        let best = rank_files(line_matches.clone(), FileScoreMode::Best);
+        assert_eq!(best[0].filename, "a.md");
+        assert_eq!(best[0].matching_lines, 3);
+        assert_eq!(best[1].filename, "b.md");
+        assert_eq!(best[1].matching_lines, 2);
+
+        let mean = rank_files(line_matches, FileScoreMode::MeanTop3);
+        assert_eq!(mean[0].filename, "b.md");
+        assert!((mean[1].score - 0.4).abs() < 1e-9);
+
+        assert!("mean-top3".parse::<FileScoreMode>().is_ok());
+        assert!("worst".parse::<FileScoreMode>().is_err());
+    }
+
+    #[test]
+    fn test_tag_filter_from_str() {
+        assert_eq!(
+            "tag:project=alpha".parse::<TagFilter>().unwrap(),
+            TagFilter {
+                key: "project".to_string(),
+                value: "alpha".to_string(),
+            }
+        );
+        // Only the first '=' separates the key
+        assert_eq!(parse_tag("q=a=b").unwrap().1, "a=b");
+        assert!("project=alpha".parse::<TagFilter>().is_err());
+        assert!("tag:project".parse::<TagFilter>().is_err());
+        assert!("tag:a.b=c".parse::<TagFilter>().is_err());
+    }
+
+    #[test]
+    fn test_mmr_select_prefers_diverse_results() {
+        let a = [1.0_f32, 0.0];
+        let a_dup = [0.99_f32, 0.01];
+        let b = [0.0_f32, 1.0];
+        let embeddings: Vec<&[f32]> = vec![&a, &a_dup, &b];
+        let relevance = vec![0.9, 0.89, 0.6];
+
+        // Pure relevance keeps the original order
+        assert_eq!(mmr_select(&embeddings, &relevance, 2, 1.0), vec![0, 1]);
+
+        // Balancing relevance and diversity skips the near-duplicate
+        assert_eq!(mmr_select(&embeddings, &relevance, 2, 0.5), vec![0, 2]);
+
+        // Asking for more than available returns everything once
+        assert_eq!(mmr_select(&embeddings, &relevance, 10, 0.5).len(), 3);
+    }
+
+    #[test]
+    fn test_normalized_recency() {
+        let mtimes = HashMap::from([
+            ("old.md".to_string(), 1000),
+            ("mid.md".to_string(), 1500),
+            ("new.md".to_string(), 2000),
+        ]);
+        let recency = normalized_recency(&mtimes);
+        assert_eq!(recency["old.md"], 0.0);
+        assert_eq!(recency["mid.md"], 0.5);
+        assert_eq!(recency["new.md"], 1.0);
+
+        let same = HashMap::from([("a.md".to_string(), 5), ("b.md".to_string(), 5)]);
+        assert!(normalized_recency(&same).values().all(|&r| r == 0.0));
+    }
+
+    #[test]
+    fn test_negative_query_reranks_results() {
+        // Both lines are about "python"; one about the language, the other about the snake
+        let documents = vec![Document {
+            filename: "notes.md".to_string(),
+            lines: vec![
+                "python snake habitat".to_string(),
+                "python language release".to_string(),
+            ],
+            embeddings: vec![vec![0.95, 0.1, 0.3], vec![0.9, 0.4, 0.1]],
+            table_rows: Vec::new(),
+        }];
+        let query = vec![1.0, 0.0, 0.0];
+
+        let mut config = create_test_config();
+        let results = rank_documents(&documents, &query, &config, &[]);
+        assert_eq!(results[0].match_line, 0);
+
+        // "but not about snakes"
+        config.negative_embeddings = vec![vec![0.0, 0.0, 1.0]];
+        let results = rank_documents(&documents, &query, &config, &[]);
+        assert_eq!(results[0].match_line, 1);
+        assert!(results[0].score < results[0].distance);
+        assert!(results[0].distance > results[1].distance);
+    }
+
+    #[test]
+    fn test_path_boosts() {
+        let boost = |s: &str| s.parse::<PathBoost>().unwrap();
+        assert_eq!(
+            boost("./docs/**=1.2"),
+            PathBoost {
+                glob: "docs/**".to_string(),
+                weight: 1.2,
+            }
+        );
+        assert!("docs/**".parse::<PathBoost>().is_err());
+        assert!("docs/**=high".parse::<PathBoost>().is_err());
+        assert!("=2".parse::<PathBoost>().is_err());
+
+        assert!(boost("docs/**=2").matches("./docs/guide/setup.md"));
+        assert!(!boost("docs/**=2").matches("src/docs.md"));
+        assert!(boost("docs/=2").matches("docs/a.md"));
+        assert!(boost("*.test.md=0.5").matches("notes/api.test.md"));
+        assert!(!boost("src/*.rs=2").matches("src/search/mod.rs"));
+        assert!(boost("src/**/*.rs=2").matches("src/search/mod.rs"));
+        assert!(boost("src/**/*.rs=2").matches("src/lib.rs"));
+        assert!(boost("v?.md=2").matches("v1.md"));
+
+        let boosts = [boost("docs/**=2"), boost("*.md=0.5"), boost("*.txt=3")];
+        assert_eq!(path_weight("docs/a.md", &boosts), 1.0);
+        assert_eq!(path_weight("docs/a.rst", &boosts), 2.0);
+        assert_eq!(path_weight("b.rs", &boosts), 1.0);
+
+        // A demoted file drops below an equally close match elsewhere
+        let document = |filename: &str| Document {
+            filename: filename.to_string(),
+            lines: vec!["refund policy".to_string()],
+            embeddings: vec![vec![1.0, 0.2]],
+            table_rows: Vec::new(),
+        };
+        let documents = vec![document("api.test.md"), document("policy.md")];
+        let mut config = create_test_config();
+        config.boosts = vec![boost("*.test.md=0.5")];
+        let results = rank_documents(&documents, &[1.0, 0.0], &config, &[]);
+        assert_eq!(results[0].filename, "policy.md");
+        assert_eq!(results[0].score, results[0].distance);
+        assert!(results[1].score > results[1].distance);
+    }
+
+    #[test]
+    fn test_snippet() {
+        assert_eq!(snippet("short line", None, 5), "short…");
+        assert_eq!(snippet("short line", None, 0), "short line");
+        assert_eq!(snippet("short line", None, 10), "short line");
+
+        let line = format!("{} refund policy {}", "a".repeat(100), "b".repeat(100));
+        let start = line.find("refund").unwrap();
+        let span = Some((start, start + "refund policy".len()));
+        let cut = snippet(&line, span, 21);
+        assert_eq!(cut, "…aaa refund policy bbb…");
+
+        // At the end of the line only the start is cut
+        let cut = snippet(&line, Some((line.len() - 3, line.len())), 10);
+        assert_eq!(cut, "…bbbbbbbbbb");
+
+        // Spans that are not on character boundaries are ignored
+        let line = "é".repeat(20);
+        assert_eq!(snippet(&line, Some((1, 3)), 4), "éééé…");
+    }
+
+    #[test]
+    fn test_table_rows_match_for_their_line() {
+        // The second line holds a whole table, which is far from the query as one line
+        let documents = vec![Document {
+            filename: "report.md".to_string(),
+            lines: vec![
+                "revenue overview".to_string(),
+                "<table>...</table>".to_string(),
+            ],
+            embeddings: vec![vec![0.8, 0.6], vec![0.0, 1.0]],
+            table_rows: vec![
+                RowEmbedding {
+                    line: 1,
+                    embedding: vec![0.3, 0.9],
+                },
+                RowEmbedding {
+                    line: 1,
+                    embedding: vec![1.0, 0.05],
+                },
+            ],
+        }];
+        let query = vec![1.0, 0.0];
+
+        let results = rank_documents(&documents, &query, &create_test_config(), &[]);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].match_line, 1);
+        assert!(results[0].distance < 0.01);
+    }
+
+    #[test]
+    fn test_query_combinations() {
+        // One line about each concept, one about both
+        let documents = vec![Document {
+            filename: "notes.md".to_string(),
+            lines: vec![
+                "kubernetes cluster upgrade".to_string(),
+                "billing for kubernetes nodes".to_string(),
+                "monthly billing report".to_string(),
+            ],
+            embeddings: vec![vec![1.0, 0.05], vec![0.7, 0.7], vec![0.05, 1.0]],
+            table_rows: Vec::new(),
+        }];
+        let kubernetes = vec![1.0, 0.0];
+        let billing = vec![0.0, 1.0];
+
+        let mut config = create_test_config();
+        config.top_k = 1;
+        config.extra_query_embeddings = vec![billing.clone()];
+        let results = rank_documents(&documents, &kubernetes, &config, &[]);
+        assert_eq!(results[0].match_line, 1);
+        // The distance is to the farther of the two queries
+        let to_billing = cosine_distance(&billing, &documents[0].embeddings[1]).unwrap();
+        assert!((results[0].distance - to_billing).abs() < 1e-9);
+
+        config.top_k = 3;
+        config.query_combination = QueryCombination::Any;
+        let results = rank_documents(&documents, &kubernetes, &config, &[]);
+        let lines: Vec<usize> = results.iter().map(|r| r.match_line).collect();
+        assert_eq!(lines, [0, 2, 1]);
+    }
+
+    #[test]
+    fn test_top_candidates_keeps_best_in_order() {
+        let scores = [0.5, 0.1, 0.9, 0.3, 0.1, 0.7];
+        let mut top = TopCandidates::new(Some(3));
+        for (line, &score) in scores.iter().enumerate() {
+            top.push(Candidate {
+                doc: 0,
+                filename: "a.txt",
+                line,
+                distance: score,
+                score,
+            });
+        }
+        let kept: Vec<(usize, f64)> = top
+            .into_sorted_vec()
+            .iter()
+            .map(|c| (c.line, c.score))
+            .collect();
+        // Ties keep line order
+        assert_eq!(kept, vec![(1, 0.1), (4, 0.1), (3, 0.3)]);
+
+        let mut unbounded = TopCandidates::new(None);
+        let mut empty = TopCandidates::new(Some(0));
+        for (line, &score) in scores.iter().enumerate() {
+            let candidate = Candidate {
+                doc: 0,
+                filename: "a.txt",
+                line,
+                distance: score,
+                score,
+            };
+            unbounded.push(candidate);
+            empty.push(candidate);
+        }
+        assert_eq!(unbounded.into_sorted_vec().len(), scores.len());
+        assert!(empty.into_sorted_vec().is_empty());
+    }
+}
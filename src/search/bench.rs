@@ -0,0 +1,252 @@
+//! Retrieval quality metrics for `semtools bench`: relevance judgments (qrels) and recall@k,
+//! reciprocal rank and nDCG@k over ranked search hits
+use anyhow::{Result, bail};
+use std::collections::BTreeMap;
+
+/// One judgment: a file, or a line of a file, that is relevant to a query
+#[derive(Debug, Clone, PartialEq)]
+pub struct Judgment {
+    pub path: String,
+    /// 0-based line, or `None` if any hit in the file counts
+    pub line: Option<usize>,
+    /// Graded relevance used by nDCG (1 unless given)
+    pub grade: f64,
+}
+
+/// A query and everything judged relevant to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Qrel {
+    pub query: String,
+    pub judgments: Vec<Judgment>,
+}
+
+/// A ranked search hit: the matched file and the lines shown for it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub path: String,
+    /// First line of the context, 0-based
+    pub start: usize,
+    /// One past the last line of the context
+    pub end: usize,
+}
+
+/// Metrics for one query
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryMetrics {
+    /// Share of the judgments found in the top k
+    pub recall: f64,
+    /// 1 / rank of the first relevant hit, or 0 if none
+    pub reciprocal_rank: f64,
+    pub ndcg: f64,
+    /// Judgments found in the top k
+    pub found: usize,
+}
+
+/// Strip a leading `./` so `docs/a.md` and `./docs/a.md` name the same file
+fn normalize_path(path: &str) -> &str {
+    let mut path = path;
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+    path
+}
+
+/// Parse a qrels file: one tab-separated `query<TAB>path[:line][<TAB>grade]` judgment per line,
+/// with 1-based line numbers. Rows for the same query are grouped in order of first
+/// appearance. Blank lines and lines starting with `#` are skipped.
+pub fn parse_qrels(content: &str) -> Result<Vec<Qrel>> {
+    let mut order: Vec<String> = Vec::new();
+    let mut by_query: BTreeMap<String, Vec<Judgment>> = BTreeMap::new();
+
+    for (idx, line) in content.lines().enumerate() {
+        let line_number = idx + 1;
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').map(str::trim).collect();
+        if fields.len() < 2 || fields[0].is_empty() || fields[1].is_empty() {
+            bail!("qrels line {line_number}: expected `query<TAB>path[:line][<TAB>grade]`");
+        }
+
+        let (path, line) = match fields[1].rsplit_once(':') {
+            Some((path, n)) if !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()) => {
+                let n: usize = n.parse()?;
+                if n == 0 {
+                    bail!("qrels line {line_number}: line numbers start at 1");
+                }
+                (path, Some(n - 1))
+            }
+            _ => (fields[1], None),
+        };
+        let grade = match fields.get(2).filter(|g| !g.is_empty()) {
+            Some(grade) => grade
+                .parse::<f64>()
+                .ok()
+                .filter(|g| *g > 0.0)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "qrels line {line_number}: grade must be a positive number, got '{grade}'"
+                    )
+                })?,
+            None => 1.0,
+        };
+
+        let query = fields[0].to_string();
+        if !by_query.contains_key(&query) {
+            order.push(query.clone());
+        }
+        by_query.entry(query).or_default().push(Judgment {
+            path: normalize_path(path).to_string(),
+            line,
+            grade,
+        });
+    }
+
+    Ok(order
+        .into_iter()
+        .map(|query| {
+            let judgments = by_query.remove(&query).unwrap_or_default();
+            Qrel { query, judgments }
+        })
+        .collect())
+}
+
+impl Judgment {
+    fn matches(&self, hit: &Hit) -> bool {
+        normalize_path(&hit.path) == self.path
+            && self
+                .line
+                .is_none_or(|line| (hit.start..hit.end).contains(&line))
+    }
+}
+
+/// Score the top `k` of `hits` against `judgments`. Each judgment counts once, for the first
+/// hit that matches it; later hits matching only judgments already found count as irrelevant.
+pub fn evaluate(judgments: &[Judgment], hits: &[Hit], k: usize) -> QueryMetrics {
+    let mut found = vec![false; judgments.len()];
+    let mut dcg = 0.0;
+    let mut reciprocal_rank = 0.0;
+
+    for (rank, hit) in hits.iter().take(k).enumerate() {
+        let mut gain = 0.0;
+        for (idx, judgment) in judgments.iter().enumerate() {
+            if !found[idx] && judgment.matches(hit) {
+                found[idx] = true;
+                gain += judgment.grade;
+            }
+        }
+        if gain > 0.0 {
+            if reciprocal_rank == 0.0 {
+                reciprocal_rank = 1.0 / (rank + 1) as f64;
+            }
+            dcg += gain / ((rank + 2) as f64).log2();
+        }
+    }
+
+    let mut grades: Vec<f64> = judgments.iter().map(|j| j.grade).collect();
+    grades.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    let idcg: f64 = grades
+        .iter()
+        .take(k)
+        .enumerate()
+        .map(|(rank, grade)| grade / ((rank + 2) as f64).log2())
+        .sum();
+
+    let found = found.iter().filter(|f| **f).count();
+    QueryMetrics {
+        recall: if judgments.is_empty() {
+            0.0
+        } else {
+            found as f64 / judgments.len() as f64
+        },
+        reciprocal_rank,
+        ndcg: if idcg > 0.0 { dcg / idcg } else { 0.0 },
+        found,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(path: &str, start: usize, end: usize) -> Hit {
+        Hit {
+            path: path.to_string(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn test_parse_qrels() {
+        let content = "# query\tpath\tgrade\nrefund policy\tdocs/billing.md:12\t2\n\nsso setup\t./docs/auth.md\nrefund policy\tdocs/faq.md\n";
+        let qrels = parse_qrels(content).unwrap();
+        assert_eq!(qrels.len(), 2);
+        assert_eq!(qrels[0].query, "refund policy");
+        assert_eq!(
+            qrels[0].judgments,
+            vec![
+                Judgment {
+                    path: "docs/billing.md".to_string(),
+                    line: Some(11),
+                    grade: 2.0,
+                },
+                Judgment {
+                    path: "docs/faq.md".to_string(),
+                    line: None,
+                    grade: 1.0,
+                },
+            ]
+        );
+        assert_eq!(qrels[1].judgments[0].path, "docs/auth.md");
+
+        assert!(parse_qrels("only a query\n").is_err());
+        assert!(parse_qrels("q\ta.md:0\n").is_err());
+        assert!(parse_qrels("q\ta.md\t-1\n").is_err());
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let judgments = vec![
+            Judgment {
+                path: "a.md".to_string(),
+                line: Some(10),
+                grade: 1.0,
+            },
+            Judgment {
+                path: "b.md".to_string(),
+                line: None,
+                grade: 1.0,
+            },
+        ];
+
+        // Relevant hits at ranks 2 and 3; the line judgment matches through the context
+        let hits = vec![hit("c.md", 0, 4), hit("a.md", 8, 15), hit("./b.md", 0, 3)];
+        let metrics = evaluate(&judgments, &hits, 3);
+        assert_eq!(metrics.found, 2);
+        assert_eq!(metrics.recall, 1.0);
+        assert_eq!(metrics.reciprocal_rank, 0.5);
+        let expected = (1.0 / 3_f64.log2() + 1.0 / 4_f64.log2()) / (1.0 + 1.0 / 3_f64.log2());
+        assert!((metrics.ndcg - expected).abs() < 1e-9);
+
+        // Cut at k = 1 nothing relevant is found
+        let metrics = evaluate(&judgments, &hits, 1);
+        assert_eq!(metrics.recall, 0.0);
+        assert_eq!(metrics.reciprocal_rank, 0.0);
+        assert_eq!(metrics.ndcg, 0.0);
+    }
+
+    #[test]
+    fn test_repeated_hits_count_once() {
+        let judgments = vec![Judgment {
+            path: "a.md".to_string(),
+            line: None,
+            grade: 1.0,
+        }];
+        let hits = vec![hit("a.md", 0, 3), hit("a.md", 10, 13)];
+        let metrics = evaluate(&judgments, &hits, 2);
+        assert_eq!(metrics.found, 1);
+        assert!((metrics.ndcg - 1.0).abs() < 1e-9);
+    }
+}
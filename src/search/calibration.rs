@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+use simsimd::SpatialSimilarity;
+
+use crate::search::Document;
+use crate::search::error::SearchError;
+
+/// Random line pairs whose distances make up a calibration
+pub const CALIBRATION_PAIRS: usize = 2_000;
+
+/// Lines sampled from a workspace index to draw the pairs from
+pub const CALIBRATION_SAMPLE_LINES: usize = 2_000;
+
+/// How match scores are reported
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScoreScale {
+    /// Raw cosine distance (lower is better)
+    #[default]
+    Raw,
+    /// 0–100 relevance, calibrated against the distances between random pairs of lines
+    Normalized,
+}
+
+impl std::str::FromStr for ScoreScale {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "raw" => Ok(ScoreScale::Raw),
+            "normalized" => Ok(ScoreScale::Normalized),
+            _ => Err(SearchError::InvalidArgument(format!(
+                "Invalid score scale: '{s}'. Must be 'raw' or 'normalized'"
+            ))
+            .into()),
+        }
+    }
+}
+
+/// Distribution of cosine distances between random pairs of lines. Distances depend on the
+/// model and the corpus, so this is the baseline that says what an unrelated line looks like.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Calibration {
+    /// Number of lines the pairs were drawn from (used to tell when to recalibrate)
+    pub lines: usize,
+    pub pairs: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub p05: f64,
+    pub p50: f64,
+    pub p95: f64,
+}
+
+impl Calibration {
+    /// Calibrate from the distances of random pairs of `embeddings`. Lines without content
+    /// (zero vectors) are left out, and `None` is returned when fewer than two remain.
+    pub fn from_embeddings<E: AsRef<[f32]>>(embeddings: &[E], lines: usize) -> Option<Self> {
+        let embeddings: Vec<&[f32]> = embeddings
+            .iter()
+            .map(|e| e.as_ref())
+            .filter(|e| e.iter().any(|x| *x != 0.0))
+            .collect();
+        if embeddings.len() < 2 {
+            return None;
+        }
+
+        // A fixed seed keeps the calibration (and so the scores) stable for the same lines
+        let mut rng = XorShift(0x9E37_79B9_7F4A_7C15);
+        let mut distances = Vec::with_capacity(CALIBRATION_PAIRS);
+        for _ in 0..CALIBRATION_PAIRS {
+            let i = rng.below(embeddings.len());
+            let j = (i + 1 + rng.below(embeddings.len() - 1)) % embeddings.len();
+            if let Some(distance) = f32::cosine(embeddings[i], embeddings[j])
+                && distance.is_finite()
+            {
+                distances.push(distance);
+            }
+        }
+        Self::from_distances(distances, lines)
+    }
+
+    /// Calibrate from the lines of in-memory documents
+    pub fn from_documents(documents: &[Document]) -> Option<Self> {
+        let embeddings: Vec<&Vec<f32>> = documents.iter().flat_map(|d| &d.embeddings).collect();
+        Self::from_embeddings(&embeddings, embeddings.len())
+    }
+
+    pub fn from_distances(mut distances: Vec<f64>, lines: usize) -> Option<Self> {
+        if distances.is_empty() {
+            return None;
+        }
+        distances.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+
+        let n = distances.len() as f64;
+        let mean = distances.iter().sum::<f64>() / n;
+        let variance = distances.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / n;
+        let percentile = |p: f64| distances[((distances.len() - 1) as f64 * p).round() as usize];
+
+        Some(Calibration {
+            lines,
+            pairs: distances.len(),
+            mean,
+            std_dev: variance.sqrt(),
+            p05: percentile(0.05),
+            p50: percentile(0.50),
+            p95: percentile(0.95),
+        })
+    }
+
+    /// Map a distance to 0–100: 0 is as far from the query as a typical pair of unrelated
+    /// lines (the median random-pair distance), 100 is an identical line
+    pub fn normalize(&self, distance: f64) -> f64 {
+        if self.p50 <= 0.0 {
+            return 0.0;
+        }
+        let score = (100.0 * (1.0 - distance / self.p50)).clamp(0.0, 100.0);
+        (score * 10.0).round() / 10.0
+    }
+
+    /// Whether the index has grown or shrunk enough since calibrating that the random pairs no
+    /// longer represent it
+    pub fn is_stale(&self, lines: usize) -> bool {
+        lines > self.lines.saturating_mul(2) || lines.saturating_mul(2) < self.lines
+    }
+}
+
+/// Small deterministic generator for picking pairs (xorshift64*)
+struct XorShift(u64);
+
+impl XorShift {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        (self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_distances_and_normalize() {
+        let distances: Vec<f64> = (0..=100).map(|i| 0.5 + i as f64 / 200.0).collect();
+        let calibration = Calibration::from_distances(distances, 500).unwrap();
+        assert_eq!(calibration.pairs, 101);
+        assert_eq!(calibration.p05, 0.525);
+        assert_eq!(calibration.p50, 0.75);
+        assert_eq!(calibration.p95, 0.975);
+        assert!((calibration.mean - 0.75).abs() < 1e-9);
+
+        assert_eq!(calibration.normalize(0.0), 100.0);
+        assert_eq!(calibration.normalize(0.3), 60.0);
+        assert_eq!(calibration.normalize(0.75), 0.0);
+        assert_eq!(calibration.normalize(1.2), 0.0);
+    }
+
+    #[test]
+    fn test_from_embeddings_skips_blank_lines() {
+        let embeddings = vec![
+            vec![1.0, 0.0],
+            vec![0.0, 0.0],
+            vec![0.0, 1.0],
+            vec![1.0, 1.0],
+        ];
+        let calibration = Calibration::from_embeddings(&embeddings, 4).unwrap();
+        assert_eq!(calibration.lines, 4);
+        assert!(calibration.pairs > 0);
+        assert!(calibration.p05 >= 0.0 && calibration.p95 <= 1.0 + 1e-6);
+        // Same lines, same calibration
+        assert_eq!(
+            Calibration::from_embeddings(&embeddings, 4),
+            Some(calibration)
+        );
+
+        assert!(Calibration::from_embeddings(&[vec![1.0, 0.0], vec![0.0, 0.0]], 2).is_none());
+    }
+
+    #[test]
+    fn test_is_stale() {
+        let calibration = Calibration::from_distances(vec![0.8], 1_000).unwrap();
+        assert!(!calibration.is_stale(1_500));
+        assert!(!calibration.is_stale(600));
+        assert!(calibration.is_stale(2_001));
+        assert!(calibration.is_stale(400));
+    }
+}
@@ -0,0 +1,163 @@
+//! Context windows of search results: a fixed number of lines around each match (`-n 3`), or
+//! sized to the match (`--context auto`), growing while the neighbouring lines stay about as
+//! close to the query as the match itself. Auto windows then take in the lines a sentence
+//! continues over, so results neither cut sentences nor bleed into unrelated sections.
+use model2vec_rs::model::StaticModel;
+
+use crate::ranking::{SearchResult, cosine_distance};
+use crate::search::embed_lines;
+
+/// Most lines an auto window grows by on each side of the match
+pub const AUTO_CONTEXT_MAX_LINES: usize = 10;
+
+/// How much farther from the query than the match a neighbouring line may be and still count
+/// as context
+const AUTO_CONTEXT_SLACK: f64 = 0.15;
+
+/// How many lines of context surround each match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContextLines {
+    Fixed(usize),
+    Auto,
+}
+
+impl Default for ContextLines {
+    fn default() -> Self {
+        ContextLines::Fixed(3)
+    }
+}
+
+impl std::str::FromStr for ContextLines {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(ContextLines::Auto);
+        }
+        s.parse().map(ContextLines::Fixed).map_err(|_| {
+            anyhow::anyhow!("Invalid context: '{s}'. Must be a number of lines or 'auto'")
+        })
+    }
+}
+
+impl ContextLines {
+    /// Lines of context to read on each side of a match; auto windows are fitted within these
+    pub fn max_lines(&self) -> usize {
+        match self {
+            ContextLines::Fixed(n) => *n,
+            ContextLines::Auto => AUTO_CONTEXT_MAX_LINES,
+        }
+    }
+}
+
+/// Fit the context of each result to its match when `context` is `Auto`, embedding the lines
+/// read around it. Fixed windows are left as they are.
+pub fn fit_context(
+    results: &mut [SearchResult],
+    context: ContextLines,
+    model: &StaticModel,
+    query_embedding: &[f32],
+    ignore_case: bool,
+) {
+    if context != ContextLines::Auto {
+        return;
+    }
+    for result in results {
+        let Some(match_idx) = result
+            .match_line
+            .checked_sub(result.start)
+            .filter(|&i| i < result.lines.len())
+        else {
+            continue;
+        };
+        let lines: Vec<String> = if ignore_case {
+            result.lines.iter().map(|l| l.to_lowercase()).collect()
+        } else {
+            result.lines.clone()
+        };
+        let distances: Vec<Option<f64>> = embed_lines(model, &lines)
+            .iter()
+            .map(|embedding| cosine_distance(query_embedding, embedding))
+            .collect();
+        let (lo, hi) = auto_window(&result.lines, &distances, match_idx);
+        result.lines = result.lines[lo..hi].to_vec();
+        result.end = result.start + hi;
+        result.start += lo;
+    }
+}
+
+/// The window `[lo, hi)` of `lines` around `match_idx`: grown while the next line out is within
+/// `AUTO_CONTEXT_SLACK` of the match's distance to the query, then over the lines that continue
+/// a sentence cut at either edge
+fn auto_window(lines: &[String], distances: &[Option<f64>], match_idx: usize) -> (usize, usize) {
+    let threshold = distances[match_idx].unwrap_or(0.0) + AUTO_CONTEXT_SLACK;
+    let similar = |i: usize| distances[i].is_some_and(|d| d <= threshold);
+
+    let mut lo = match_idx;
+    while lo > 0 && similar(lo - 1) {
+        lo -= 1;
+    }
+    let mut hi = match_idx + 1;
+    while hi < lines.len() && similar(hi) {
+        hi += 1;
+    }
+
+    while lo > 0 && continues(&lines[lo - 1], &lines[lo]) {
+        lo -= 1;
+    }
+    while hi < lines.len() && continues(&lines[hi - 1], &lines[hi]) {
+        hi += 1;
+    }
+    (lo, hi)
+}
+
+/// Whether `next` carries on the sentence of `line`: neither is blank, `line` does not end a
+/// sentence and `next` does not start a heading
+fn continues(line: &str, next: &str) -> bool {
+    let line = line.trim();
+    let next = next.trim();
+    !line.is_empty()
+        && !next.is_empty()
+        && !line.starts_with('#')
+        && !next.starts_with('#')
+        && !line.ends_with(['.', '!', '?', ':', ';', '。', '！', '？'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_window() {
+        let lines: Vec<String> = [
+            "# Billing",
+            "",
+            "Invoices are sent monthly.",
+            "Refunds are granted within 30 days of",
+            "the purchase, on request.",
+            "Refunds go to the original card.",
+            "",
+            "# Support",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let distances = [
+            Some(0.9),
+            Some(1.0),
+            Some(0.7),
+            Some(0.3),
+            Some(0.5),
+            Some(0.4),
+            Some(1.0),
+            Some(0.9),
+        ];
+        // Line 4 is too far from the query, but ends the sentence line 3 starts. From line 5
+        // the window grows back over lines 4 and 3, but not the sentence before, which is too far
+        assert_eq!(auto_window(&lines, &distances, 3), (3, 5));
+        assert_eq!(auto_window(&lines, &distances, 5), (3, 6));
+        assert_eq!("auto".parse::<ContextLines>().unwrap(), ContextLines::Auto);
+        assert_eq!("5".parse::<ContextLines>().unwrap(), ContextLines::Fixed(5));
+        assert!("wide".parse::<ContextLines>().is_err());
+    }
+}
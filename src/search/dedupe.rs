@@ -0,0 +1,339 @@
+use anyhow::Result;
+use model2vec_rs::model::StaticModel;
+use sha2::{Digest, Sha256};
+use simsimd::SpatialSimilarity;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+
+use crate::search::create_document_from_content;
+
+/// Default maximum cosine distance for two documents to count as near-duplicates
+pub const DEFAULT_NEAR_DUPLICATE_DISTANCE: f64 = 0.05;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKind {
+    /// Byte-for-byte identical content
+    Exact,
+    /// Document-level embeddings within the configured distance
+    Near,
+}
+
+impl DuplicateKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DuplicateKind::Exact => "exact",
+            DuplicateKind::Near => "near",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    /// Files in the group, sorted. The first file is the one kept by `--delete`/`--link`.
+    pub files: Vec<String>,
+    /// Largest pairwise distance between linked members (0.0 for exact groups)
+    pub max_distance: f64,
+}
+
+/// Content hash and document-level embedding for a single file
+pub struct DocumentFingerprint {
+    pub filename: String,
+    pub content_hash: String,
+    pub embedding: Option<Vec<f32>>,
+}
+
+pub fn content_hash(content: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+    hex::encode(hasher.finalize())
+}
+
+/// Average the line embeddings of a document into a single document-level embedding
+pub fn document_embedding(line_embeddings: &[Vec<f32>]) -> Option<Vec<f32>> {
+    let first = line_embeddings.first()?;
+    let mut mean = vec![0_f32; first.len()];
+    for embedding in line_embeddings {
+        for (acc, value) in mean.iter_mut().zip(embedding) {
+            *acc += value;
+        }
+    }
+    let count = line_embeddings.len() as f32;
+    for value in mean.iter_mut() {
+        *value /= count;
+    }
+    Some(mean)
+}
+
+/// The device and inode of the file `path` names, equal for every path to the same file:
+/// `a.md` and `./a.md`, hard links, and symlinks with their targets
+#[cfg(unix)]
+fn file_identity(path: &str) -> io::Result<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(path: &str) -> io::Result<std::path::PathBuf> {
+    fs::canonicalize(path)
+}
+
+/// `files` without the later paths to a file already listed, so no file is ever its own
+/// duplicate
+fn distinct_files(files: &[String]) -> Result<Vec<&String>> {
+    let mut seen = HashSet::new();
+    let mut distinct = Vec::new();
+    for f in files {
+        if seen.insert(file_identity(f)?) {
+            distinct.push(f);
+        }
+    }
+    Ok(distinct)
+}
+
+pub fn fingerprint_files(
+    files: &[String],
+    model: &StaticModel,
+) -> Result<Vec<DocumentFingerprint>> {
+    let mut fingerprints = Vec::new();
+    for f in distinct_files(files)? {
+        let bytes = fs::read(f)?;
+        let content_hash = content_hash(&bytes);
+        let content = String::from_utf8_lossy(&bytes);
+        let embedding = create_document_from_content(f.clone(), &content, model, false)
+            .and_then(|doc| document_embedding(&doc.embeddings));
+
+        fingerprints.push(DocumentFingerprint {
+            filename: f.clone(),
+            content_hash,
+            embedding,
+        });
+    }
+    Ok(fingerprints)
+}
+
+/// Group fingerprints into exact duplicates (same content hash) and near-duplicates
+/// (document embeddings within `max_distance`). Near-duplicate groups are built from one
+/// representative per distinct content, so exact copies are only ever reported once.
+pub fn find_duplicate_groups(
+    fingerprints: &[DocumentFingerprint],
+    max_distance: f64,
+) -> Vec<DuplicateGroup> {
+    let mut groups = Vec::new();
+
+    // Step 1: Exact duplicates by content hash
+    let mut by_hash: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (idx, fp) in fingerprints.iter().enumerate() {
+        by_hash
+            .entry(fp.content_hash.as_str())
+            .or_default()
+            .push(idx);
+    }
+
+    let mut representatives: Vec<usize> = Vec::new();
+    for indices in by_hash.values() {
+        let mut files: Vec<String> = indices
+            .iter()
+            .map(|&i| fingerprints[i].filename.clone())
+            .collect();
+        files.sort();
+
+        // Use the first file (by sorted name) as the representative for near-duplicate checks
+        let representative = *indices
+            .iter()
+            .min_by(|&&a, &&b| fingerprints[a].filename.cmp(&fingerprints[b].filename))
+            .unwrap();
+        representatives.push(representative);
+
+        if files.len() > 1 {
+            groups.push(DuplicateGroup {
+                kind: DuplicateKind::Exact,
+                files,
+                max_distance: 0.0,
+            });
+        }
+    }
+    representatives.sort();
+
+    // Step 2: Near duplicates among distinct documents, merged with union-find
+    let mut parent: Vec<usize> = (0..representatives.len()).collect();
+    let mut linked_distance: Vec<f64> = vec![0.0; representatives.len()];
+
+    fn find(parent: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parent[root] != root {
+            root = parent[root];
+        }
+        let mut cur = i;
+        while parent[cur] != root {
+            let next = parent[cur];
+            parent[cur] = root;
+            cur = next;
+        }
+        root
+    }
+
+    for a in 0..representatives.len() {
+        let Some(emb_a) = &fingerprints[representatives[a]].embedding else {
+            continue;
+        };
+        for b in (a + 1)..representatives.len() {
+            let Some(emb_b) = &fingerprints[representatives[b]].embedding else {
+                continue;
+            };
+            if let Some(distance) = f32::cosine(emb_a, emb_b)
+                && distance <= max_distance
+            {
+                let root_a = find(&mut parent, a);
+                let root_b = find(&mut parent, b);
+                let merged = linked_distance[root_a]
+                    .max(linked_distance[root_b])
+                    .max(distance);
+                if root_a != root_b {
+                    parent[root_b] = root_a;
+                }
+                linked_distance[root_a] = merged;
+            }
+        }
+    }
+
+    let mut near: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..representatives.len() {
+        let root = find(&mut parent, i);
+        near.entry(root).or_default().push(i);
+    }
+
+    for (root, members) in near {
+        if members.len() < 2 {
+            continue;
+        }
+        let mut files: Vec<String> = members
+            .iter()
+            .map(|&i| fingerprints[representatives[i]].filename.clone())
+            .collect();
+        files.sort();
+        groups.push(DuplicateGroup {
+            kind: DuplicateKind::Near,
+            files,
+            max_distance: linked_distance[root],
+        });
+    }
+
+    // Exact groups first, then by first filename for stable output
+    groups.sort_by(|a, b| {
+        (a.kind != DuplicateKind::Exact, &a.files[0])
+            .cmp(&(b.kind != DuplicateKind::Exact, &b.files[0]))
+    });
+
+    groups
+}
+
+pub fn find_duplicates(
+    files: &[String],
+    model: &StaticModel,
+    max_distance: f64,
+) -> Result<Vec<DuplicateGroup>> {
+    let fingerprints = fingerprint_files(files, model)?;
+    Ok(find_duplicate_groups(&fingerprints, max_distance))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fingerprint(filename: &str, content: &str, embedding: Vec<f32>) -> DocumentFingerprint {
+        DocumentFingerprint {
+            filename: filename.to_string(),
+            content_hash: content_hash(content.as_bytes()),
+            embedding: Some(embedding),
+        }
+    }
+
+    #[test]
+    fn test_content_hash_is_stable() {
+        assert_eq!(content_hash(b"hello"), content_hash(b"hello"));
+        assert_ne!(content_hash(b"hello"), content_hash(b"hello!"));
+    }
+
+    #[test]
+    fn test_document_embedding_mean() {
+        let embedding = document_embedding(&[vec![1.0, 0.0], vec![0.0, 1.0]]).unwrap();
+        assert_eq!(embedding, vec![0.5, 0.5]);
+        assert!(document_embedding(&[]).is_none());
+    }
+
+    #[test]
+    fn test_distinct_files_skips_paths_to_the_same_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let a = dir.path().join("a.md");
+        let b = dir.path().join("b.md");
+        fs::write(&a, "same").unwrap();
+        fs::write(&b, "same").unwrap();
+        let hard_link = dir.path().join("hard.md");
+        fs::hard_link(&a, &hard_link).unwrap();
+        let dotted = dir.path().join(".").join("a.md");
+
+        let mut files = vec![
+            a.to_string_lossy().to_string(),
+            dotted.to_string_lossy().to_string(),
+            hard_link.to_string_lossy().to_string(),
+            b.to_string_lossy().to_string(),
+        ];
+        #[cfg(unix)]
+        {
+            let symlink = dir.path().join("sym.md");
+            std::os::unix::fs::symlink(&a, &symlink).unwrap();
+            files.push(symlink.to_string_lossy().to_string());
+        }
+
+        let distinct = distinct_files(&files).unwrap();
+
+        assert_eq!(distinct, vec![&files[0], &files[3]]);
+    }
+
+    #[test]
+    fn test_find_exact_duplicates() {
+        let fingerprints = vec![
+            fingerprint("b.md", "same", vec![1.0, 0.0]),
+            fingerprint("a.md", "same", vec![1.0, 0.0]),
+            fingerprint("c.md", "other", vec![0.0, 1.0]),
+        ];
+
+        let groups = find_duplicate_groups(&fingerprints, DEFAULT_NEAR_DUPLICATE_DISTANCE);
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].kind, DuplicateKind::Exact);
+        assert_eq!(groups[0].files, vec!["a.md", "b.md"]);
+    }
+
+    #[test]
+    fn test_find_near_duplicates() {
+        let fingerprints = vec![
+            fingerprint("a.md", "one", vec![1.0, 0.0]),
+            fingerprint("b.md", "two", vec![0.99, 0.01]),
+            fingerprint("c.md", "three", vec![0.0, 1.0]),
+        ];
+
+        let groups = find_duplicate_groups(&fingerprints, DEFAULT_NEAR_DUPLICATE_DISTANCE);
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].kind, DuplicateKind::Near);
+        assert_eq!(groups[0].files, vec!["a.md", "b.md"]);
+        assert!(groups[0].max_distance <= DEFAULT_NEAR_DUPLICATE_DISTANCE);
+    }
+
+    #[test]
+    fn test_exact_copies_not_reported_as_near() {
+        let fingerprints = vec![
+            fingerprint("a.md", "same", vec![1.0, 0.0]),
+            fingerprint("b.md", "same", vec![1.0, 0.0]),
+        ];
+
+        let groups = find_duplicate_groups(&fingerprints, DEFAULT_NEAR_DUPLICATE_DISTANCE);
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].kind, DuplicateKind::Exact);
+    }
+}
@@ -0,0 +1,89 @@
+//! Choosing where document embeddings are computed
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use crate::search::error::SearchError;
+
+/// Device set by `--device`, taking precedence over the config file
+static DEVICE_OVERRIDE: OnceLock<EmbedDevice> = OnceLock::new();
+
+/// Where to run document embedding. Anything but `cpu` needs the `gpu` feature (plus `metal` or
+/// `cuda` for the matching backend); when the device is unavailable, embedding falls back to the
+/// CPU.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbedDevice {
+    /// The first available GPU, otherwise the CPU
+    #[default]
+    Auto,
+    Cpu,
+    Metal,
+    Cuda,
+}
+
+impl EmbedDevice {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmbedDevice::Auto => "auto",
+            EmbedDevice::Cpu => "cpu",
+            EmbedDevice::Metal => "metal",
+            EmbedDevice::Cuda => "cuda",
+        }
+    }
+
+    /// Whether this device was asked for by name, so failing to use it deserves a warning
+    pub fn is_explicit_gpu(&self) -> bool {
+        matches!(self, EmbedDevice::Metal | EmbedDevice::Cuda)
+    }
+}
+
+impl fmt::Display for EmbedDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EmbedDevice {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(EmbedDevice::Auto),
+            "cpu" => Ok(EmbedDevice::Cpu),
+            "metal" | "mps" => Ok(EmbedDevice::Metal),
+            "cuda" | "gpu" => Ok(EmbedDevice::Cuda),
+            other => Err(SearchError::InvalidArgument(format!(
+                "Unknown device '{other}'. Supported devices: auto, cpu, metal, cuda"
+            ))
+            .into()),
+        }
+    }
+}
+
+/// Use `device` for every model loaded by this process (`--device`)
+pub fn set_device_override(device: EmbedDevice) {
+    let _ = DEVICE_OVERRIDE.set(device);
+}
+
+pub(crate) fn device_override() -> Option<EmbedDevice> {
+    DEVICE_OVERRIDE.get().copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_device() {
+        assert_eq!("CPU".parse::<EmbedDevice>().unwrap(), EmbedDevice::Cpu);
+        assert_eq!("mps".parse::<EmbedDevice>().unwrap(), EmbedDevice::Metal);
+        assert_eq!("cuda".parse::<EmbedDevice>().unwrap(), EmbedDevice::Cuda);
+        assert_eq!(EmbedDevice::default(), EmbedDevice::Auto);
+        assert!("tpu".parse::<EmbedDevice>().is_err());
+        assert!(EmbedDevice::Metal.is_explicit_gpu());
+        assert!(!EmbedDevice::Auto.is_explicit_gpu());
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+use crate::search::model::EmbedPreset;
+
+/// Search failures a caller can act on. They reach callers inside `anyhow::Error`; match on
+/// them with `error.downcast_ref::<SearchError>()`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SearchError {
+    /// Nothing to search: no files were given and nothing was piped in. Holds what to do.
+    NoInput(String),
+    /// An option has a value that is not allowed
+    InvalidArgument(String),
+    /// `model.name` is a local directory that does not exist
+    ModelNotFound { name: String },
+    /// The embedding model is not downloaded, and downloads are disabled (offline mode)
+    ModelNotCached { name: String, cache_dir: PathBuf },
+    /// A workspace's embedding space stores embeddings of another size than the model makes
+    DimensionMismatch {
+        workspace: String,
+        space: EmbedPreset,
+        stored: usize,
+        model: usize,
+    },
+}
+
+impl SearchError {
+    /// Stable name of the error, reported as `error_type` by `--json`
+    pub fn error_type(&self) -> &'static str {
+        match self {
+            SearchError::NoInput(_) => "NoInput",
+            SearchError::InvalidArgument(_) => "InvalidArgument",
+            SearchError::ModelNotFound { .. } => "ModelNotFound",
+            SearchError::ModelNotCached { .. } => "NotCached",
+            SearchError::DimensionMismatch { .. } => "DimensionMismatch",
+        }
+    }
+}
+
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SearchError::NoInput(msg) | SearchError::InvalidArgument(msg) => f.write_str(msg),
+            SearchError::ModelNotFound { name } => {
+                write!(f, "Model directory '{name}' does not exist")
+            }
+            SearchError::ModelNotCached { name, cache_dir } => write!(
+                f,
+                "Embedding model '{name}' is not cached in {} and downloads are disabled \
+                 (offline mode). Run `semtools model download` with network access first, or \
+                 set `model.name` in the config file to a local model directory.",
+                cache_dir.display()
+            ),
+            SearchError::DimensionMismatch {
+                workspace,
+                space,
+                stored,
+                model,
+            } => write!(
+                f,
+                "The '{space}' embedding space of workspace '{workspace}' stores \
+                 {stored}-dimensional embeddings, but the embedding model produces {model} \
+                 dimensions. Select the space matching the model with --embed-preset."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
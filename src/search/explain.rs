@@ -0,0 +1,190 @@
+//! Diagnostics for `search --explain`: where a query spent its time, how many candidates
+//! survived each stage, and how the workspace index was queried
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+/// Stages a search spends time in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// Loading the embedding model
+    ModelLoad,
+    /// Reading input files and checking them against the workspace
+    FileIo,
+    /// Embedding the queries and the lines of new or changed documents
+    Embedding,
+    /// Writing new embeddings to the workspace index
+    IndexUpdate,
+    /// Scoring lines against the query, in the index or by brute force
+    VectorSearch,
+    /// Re-ranking, diversifying and cutting the candidates to the final results
+    Ranking,
+}
+
+impl Stage {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Stage::ModelLoad => "model load",
+            Stage::FileIo => "file io",
+            Stage::Embedding => "embedding",
+            Stage::IndexUpdate => "index update",
+            Stage::VectorSearch => "vector search",
+            Stage::Ranking => "ranking",
+        }
+    }
+}
+
+/// How the lines were scored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Retrieval {
+    /// Every line embedded in memory and compared with the query
+    #[default]
+    BruteForce,
+    /// Nearest-neighbour queries against the workspace index
+    WorkspaceIndex,
+    /// Lines matched against a pattern, without embeddings (`--auto`)
+    Lexical,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StageTiming {
+    pub stage: Stage,
+    pub millis: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CandidateCount {
+    pub stage: String,
+    pub count: usize,
+}
+
+/// Diagnostics collected while a search runs
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Explain {
+    pub retrieval: Retrieval,
+    /// Time spent per stage, in the order the stages first ran
+    pub timings: Vec<StageTiming>,
+    /// Candidates left after each step, in order
+    pub candidates: Vec<CandidateCount>,
+    /// How filters and thresholds were applied, e.g. pushed down into the index query
+    pub filters: Vec<String>,
+    /// How `--auto` chose to search the query, and why
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub route: Option<String>,
+}
+
+impl Explain {
+    /// Run `f`, adding its duration to `stage`
+    pub fn time<T>(&mut self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let value = f();
+        self.add_time(stage, started.elapsed());
+        value
+    }
+
+    /// Add `elapsed` to the time spent in `stage`
+    pub fn add_time(&mut self, stage: Stage, elapsed: Duration) {
+        let millis = elapsed.as_secs_f64() * 1000.0;
+        match self.timings.iter_mut().find(|t| t.stage == stage) {
+            Some(timing) => timing.millis += millis,
+            None => self.timings.push(StageTiming { stage, millis }),
+        }
+    }
+
+    /// Record how many candidates were left after `stage`
+    pub fn candidates(&mut self, stage: impl Into<String>, count: usize) {
+        self.candidates.push(CandidateCount {
+            stage: stage.into(),
+            count,
+        });
+    }
+
+    /// Record how a filter or threshold was applied
+    pub fn filter(&mut self, detail: impl Into<String>) {
+        self.filters.push(detail.into());
+    }
+
+    /// Human-readable report, printed to stderr
+    pub fn report(&self) -> String {
+        let mut lines = vec![format!(
+            "Retrieval: {}",
+            match self.retrieval {
+                Retrieval::BruteForce => "brute force (lines embedded and scored in memory)",
+                Retrieval::WorkspaceIndex => "workspace index",
+                Retrieval::Lexical => "lexical scan (lines matched against a pattern)",
+            }
+        )];
+        if let Some(route) = &self.route {
+            lines.push(format!("Route: {route}"));
+        }
+
+        let total: f64 = self.timings.iter().map(|t| t.millis).sum();
+        lines.push(format!("Timings (total {total:.1} ms):"));
+        for timing in &self.timings {
+            lines.push(format!(
+                "  {:<14} {:>10.1} ms",
+                timing.stage.label(),
+                timing.millis
+            ));
+        }
+
+        if !self.candidates.is_empty() {
+            lines.push("Candidates:".to_string());
+            for count in &self.candidates {
+                lines.push(format!("  {:<40} {:>8}", count.stage, count.count));
+            }
+        }
+
+        if !self.filters.is_empty() {
+            lines.push("Filters:".to_string());
+            for filter in &self.filters {
+                lines.push(format!("  {filter}"));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timings_accumulate_per_stage() {
+        let mut explain = Explain::default();
+        explain.add_time(Stage::Embedding, Duration::from_millis(5));
+        explain.add_time(Stage::VectorSearch, Duration::from_millis(2));
+        explain.add_time(Stage::Embedding, Duration::from_millis(3));
+        let value = explain.time(Stage::Ranking, || 7);
+        assert_eq!(value, 7);
+
+        let stages: Vec<Stage> = explain.timings.iter().map(|t| t.stage).collect();
+        assert_eq!(
+            stages,
+            vec![Stage::Embedding, Stage::VectorSearch, Stage::Ranking]
+        );
+        assert!((explain.timings[0].millis - 8.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut explain = Explain {
+            retrieval: Retrieval::WorkspaceIndex,
+            ..Default::default()
+        };
+        explain.add_time(Stage::ModelLoad, Duration::from_millis(120));
+        explain.candidates("lines within max distance", 42);
+        explain.filter("path filter pushed down to the line index (3 paths)");
+
+        let report = explain.report();
+        assert!(report.starts_with("Retrieval: workspace index"));
+        assert!(report.contains("model load"));
+        assert!(report.contains("lines within max distance"));
+        assert!(report.contains("path filter pushed down"));
+
+        let json = serde_json::to_value(&explain).unwrap();
+        assert_eq!(json["retrieval"], "workspace_index");
+        assert_eq!(json["timings"][0]["stage"], "model_load");
+    }
+}
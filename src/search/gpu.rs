@@ -0,0 +1,282 @@
+//! GPU embedding of document lines (requires the `gpu` feature).
+//!
+//! Mirrors Model2Vec's pooling: lines are tokenized on the CPU, then the token rows are gathered,
+//! weighted and mean-pooled on the device, so results match `StaticModel::encode_with_args`.
+use anyhow::{Context, Result, anyhow, bail};
+use candle_core::{DType, Device, Tensor};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokenizers::Tokenizer;
+
+use crate::search::device::EmbedDevice;
+
+/// Tokens kept per line, as in the CPU path
+const MAX_TOKENS: usize = 2048;
+
+/// Tokens gathered per device batch, bounding device memory to about this many embedding rows
+const MAX_BATCH_TOKENS: usize = 262_144;
+
+/// Embedder for the most recently loaded model, when a GPU is in use
+static EMBEDDER: Mutex<Option<Arc<GpuEmbedder>>> = Mutex::new(None);
+
+pub struct GpuEmbedder {
+    device: Device,
+    tokenizer: Tokenizer,
+    /// `[vocab, dim]` embedding matrix on the device
+    embeddings: Tensor,
+    /// Per-token weights, when the model has them
+    weights: Option<Vec<f32>>,
+    /// Token id -> embedding row, for vocabulary-quantized models
+    mapping: Option<Vec<u32>>,
+    unk_token_id: Option<u32>,
+    median_token_length: usize,
+    normalize: bool,
+}
+
+/// Set up GPU embedding for the model in `dir`, warning and leaving embedding on the CPU when
+/// the device can't be used
+pub fn activate(dir: &Path, device: EmbedDevice) {
+    let mut embedder = EMBEDDER.lock().unwrap();
+    *embedder = None;
+    let device = match select_device(device) {
+        Ok(Some(device)) => device,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Warning: {e:#}; embedding on the CPU");
+            return;
+        }
+    };
+    match GpuEmbedder::load(dir, device) {
+        Ok(loaded) => *embedder = Some(Arc::new(loaded)),
+        Err(e) => eprintln!(
+            "Warning: failed to load the embedding model on the GPU ({e:#}); embedding on the CPU"
+        ),
+    }
+}
+
+/// Embed `lines` on the GPU. Returns `None` when no GPU is active, or when it failed, in which
+/// case the GPU is dropped for the rest of the process and the caller embeds on the CPU.
+pub fn encode(lines: &[String]) -> Option<Vec<Vec<f32>>> {
+    let embedder = EMBEDDER.lock().unwrap().clone()?;
+    match embedder.encode(lines) {
+        Ok(embeddings) => Some(embeddings),
+        Err(e) => {
+            eprintln!("Warning: GPU embedding failed ({e:#}); falling back to the CPU");
+            *EMBEDDER.lock().unwrap() = None;
+            None
+        }
+    }
+}
+
+fn select_device(device: EmbedDevice) -> Result<Option<Device>> {
+    match device {
+        EmbedDevice::Cpu => Ok(None),
+        EmbedDevice::Cuda => Device::new_cuda(0)
+            .map(Some)
+            .context("CUDA device unavailable"),
+        EmbedDevice::Metal => Device::new_metal(0)
+            .map(Some)
+            .context("Metal device unavailable"),
+        EmbedDevice::Auto => {
+            if candle_core::utils::cuda_is_available()
+                && let Ok(device) = Device::new_cuda(0)
+            {
+                return Ok(Some(device));
+            }
+            if candle_core::utils::metal_is_available()
+                && let Ok(device) = Device::new_metal(0)
+            {
+                return Ok(Some(device));
+            }
+            Ok(None)
+        }
+    }
+}
+
+impl GpuEmbedder {
+    pub fn load(dir: &Path, device: Device) -> Result<Self> {
+        let tokenizer = Tokenizer::from_file(dir.join("tokenizer.json"))
+            .map_err(|e| anyhow!("Failed to load tokenizer: {e}"))?;
+
+        let tokenizer_json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("tokenizer.json"))?)?;
+        let unk_token_id = tokenizer_json["model"]["unk_token"]
+            .as_str()
+            .and_then(|token| tokenizer.token_to_id(token));
+
+        let config: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("config.json"))?)?;
+        let normalize = config["normalize"].as_bool().unwrap_or(true);
+
+        let mut lengths: Vec<usize> = tokenizer.get_vocab(false).keys().map(String::len).collect();
+        lengths.sort_unstable();
+        let median_token_length = lengths.get(lengths.len() / 2).copied().unwrap_or(1).max(1);
+
+        let tensors = candle_core::safetensors::load(dir.join("model.safetensors"), &Device::Cpu)
+            .context("Failed to read model.safetensors")?;
+        let embeddings = tensors
+            .get("embeddings")
+            .ok_or_else(|| anyhow!("model.safetensors has no 'embeddings' tensor"))?
+            .to_dtype(DType::F32)?
+            .to_device(&device)?;
+        if embeddings.rank() != 2 {
+            bail!(
+                "Expected a 2D embedding matrix, got {:?}",
+                embeddings.shape()
+            );
+        }
+        let weights = tensors
+            .get("weights")
+            .map(|t| t.to_dtype(DType::F32)?.to_vec1::<f32>())
+            .transpose()?;
+        let mapping = tensors
+            .get("mapping")
+            .map(|t| t.to_dtype(DType::U32)?.to_vec1::<u32>())
+            .transpose()?;
+
+        Ok(Self {
+            device,
+            tokenizer,
+            embeddings,
+            weights,
+            mapping,
+            unk_token_id,
+            median_token_length,
+            normalize,
+        })
+    }
+
+    pub fn encode(&self, lines: &[String]) -> Result<Vec<Vec<f32>>> {
+        let token_ids = self.tokenize(lines)?;
+
+        let mut embeddings = Vec::with_capacity(lines.len());
+        let mut start = 0;
+        while start < token_ids.len() {
+            // Always take at least one line, however long
+            let mut end = start + 1;
+            let mut tokens = token_ids[start].len();
+            while end < token_ids.len() && tokens + token_ids[end].len() <= MAX_BATCH_TOKENS {
+                tokens += token_ids[end].len();
+                end += 1;
+            }
+            embeddings.extend(self.pool(&token_ids[start..end])?);
+            start = end;
+        }
+        Ok(embeddings)
+    }
+
+    /// Token ids per line, truncated and with unknown tokens removed like the CPU path
+    fn tokenize(&self, lines: &[String]) -> Result<Vec<Vec<u32>>> {
+        let max_chars = MAX_TOKENS.saturating_mul(self.median_token_length);
+        let truncated: Vec<String> = lines
+            .iter()
+            .map(|line| match line.char_indices().nth(max_chars) {
+                Some((idx, _)) => line[..idx].to_string(),
+                None => line.clone(),
+            })
+            .collect();
+        let encodings = self
+            .tokenizer
+            .encode_batch_fast(truncated, false)
+            .map_err(|e| anyhow!("Tokenization failed: {e}"))?;
+
+        Ok(encodings
+            .iter()
+            .map(|encoding| {
+                let mut ids = encoding.get_ids().to_vec();
+                if let Some(unk) = self.unk_token_id {
+                    ids.retain(|&id| id != unk);
+                }
+                ids.truncate(MAX_TOKENS);
+                ids
+            })
+            .collect())
+    }
+
+    /// Weighted mean of each line's token rows, computed on the device
+    fn pool(&self, token_ids: &[Vec<u32>]) -> Result<Vec<Vec<f32>>> {
+        let total: usize = token_ids.iter().map(Vec::len).sum();
+        let dim = self.embeddings.dim(1)?;
+        if total == 0 {
+            return Ok(vec![vec![0.0; dim]; token_ids.len()]);
+        }
+
+        let mut rows = Vec::with_capacity(total);
+        let mut scales = Vec::with_capacity(total);
+        let mut segments = Vec::with_capacity(total);
+        for (line, ids) in token_ids.iter().enumerate() {
+            let denom = ids.len().max(1) as f32;
+            for &id in ids {
+                let weight = self
+                    .weights
+                    .as_ref()
+                    .and_then(|w| w.get(id as usize).copied())
+                    .unwrap_or(1.0);
+                let row = self
+                    .mapping
+                    .as_ref()
+                    .and_then(|m| m.get(id as usize).copied())
+                    .unwrap_or(id);
+                rows.push(row);
+                scales.push(weight / denom);
+                segments.push(line as u32);
+            }
+        }
+
+        let rows = Tensor::from_vec(rows, total, &self.device)?;
+        let scales = Tensor::from_vec(scales, (total, 1), &self.device)?;
+        let segments = Tensor::from_vec(segments, total, &self.device)?;
+
+        let gathered = self
+            .embeddings
+            .index_select(&rows, 0)?
+            .broadcast_mul(&scales)?;
+        let mut pooled = Tensor::zeros((token_ids.len(), dim), DType::F32, &self.device)?
+            .index_add(&segments, &gathered, 0)?;
+        if self.normalize {
+            let norms = pooled
+                .sqr()?
+                .sum_keepdim(1)?
+                .sqrt()?
+                .clamp(1e-12f32, f32::MAX)?;
+            pooled = pooled.broadcast_div(&norms)?;
+        }
+        Ok(pooled.to_vec2::<f32>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokenizers::models::wordlevel::WordLevel;
+
+    fn embedder(weights: Option<Vec<f32>>, normalize: bool) -> GpuEmbedder {
+        GpuEmbedder {
+            device: Device::Cpu,
+            tokenizer: Tokenizer::new(WordLevel::default()),
+            embeddings: Tensor::new(&[[1.0f32, 0.0], [0.0, 3.0]], &Device::Cpu).unwrap(),
+            weights,
+            mapping: None,
+            unk_token_id: None,
+            median_token_length: 1,
+            normalize,
+        }
+    }
+
+    #[test]
+    fn test_pool_matches_mean_of_rows() {
+        let pooled = embedder(None, false)
+            .pool(&[vec![0, 1], vec![1], vec![]])
+            .unwrap();
+        assert_eq!(pooled, vec![vec![0.5, 1.5], vec![0.0, 3.0], vec![0.0, 0.0]]);
+
+        let pooled = embedder(Some(vec![2.0, 1.0]), true)
+            .pool(&[vec![0, 1]])
+            .unwrap();
+        // (2 * [1, 0] + [0, 3]) / 2 = [1, 1.5], normalized
+        let norm = (1.0f32 + 2.25).sqrt();
+        assert!((pooled[0][0] - 1.0 / norm).abs() < 1e-6);
+        assert!((pooled[0][1] - 1.5 / norm).abs() < 1e-6);
+    }
+}
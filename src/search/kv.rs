@@ -0,0 +1,168 @@
+//! Key-value pairs for FAQ-style retrieval: records of a CSV or JSONL file are indexed by one
+//! field (the question), which is embedded, and return another (the answer), which is not.
+//! Pairs are stored in their own table of the workspace (see `workspace kv`) and searched with
+//! `search --kv`.
+use anyhow::{Result, bail};
+use model2vec_rs::model::StaticModel;
+use serde::{Deserialize, Serialize};
+
+use crate::search::embed_lines;
+use crate::search::records::{InputFormat, field_text, parse_records};
+
+/// A pair of the key-value table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct KvEntry {
+    /// File the pair was read from
+    pub source: String,
+    /// 0-based index of the record in its file
+    pub index: usize,
+    pub key: String,
+    pub value: String,
+    /// Embedding of the key
+    pub embedding: Vec<f32>,
+}
+
+/// The format of a key-value file: `format` if given, or else from its extension
+pub fn kv_format(path: &str, format: Option<InputFormat>) -> Result<InputFormat> {
+    if let Some(format) = format {
+        if !matches!(format, InputFormat::Jsonl | InputFormat::Csv) {
+            bail!("Key-value pairs are read from 'jsonl' or 'csv' inputs");
+        }
+        return Ok(format);
+    }
+    let extension = path.rsplit_once('.').map(|(_, ext)| ext.to_lowercase());
+    match extension.as_deref() {
+        Some("csv") => Ok(InputFormat::Csv),
+        Some("jsonl" | "ndjson") => Ok(InputFormat::Jsonl),
+        _ => bail!("Cannot tell the format of {path}; pass --input-format jsonl or csv"),
+    }
+}
+
+/// The `(index, key, value)` pairs of `content`. Records missing either field are skipped.
+pub fn parse_pairs(
+    content: &str,
+    format: InputFormat,
+    key_field: &str,
+    value_field: &str,
+) -> Result<Vec<(usize, String, String)>> {
+    Ok(parse_records(content, format, &[key_field.to_string()])?
+        .into_iter()
+        .filter_map(|record| {
+            let value = field_text(&record.fields, value_field)?;
+            Some((record.index, record.text, value))
+        })
+        .collect())
+}
+
+/// Entries for the pairs read from `source`, with their keys embedded
+pub fn embed_pairs(
+    source: &str,
+    pairs: Vec<(usize, String, String)>,
+    model: &StaticModel,
+) -> Vec<KvEntry> {
+    let keys: Vec<String> = pairs.iter().map(|(_, key, _)| key.clone()).collect();
+    pairs
+        .into_iter()
+        .zip(embed_lines(model, &keys))
+        .map(|((index, key, value), embedding)| KvEntry {
+            source: source.to_string(),
+            index,
+            key,
+            value,
+            embedding,
+        })
+        .collect()
+}
+
+/// Replace the entries of each source in `added` with its new ones, which go at the end.
+/// Returns how many entries were replaced.
+pub fn merge_entries(entries: &mut Vec<KvEntry>, added: Vec<KvEntry>) -> usize {
+    let before = entries.len();
+    entries.retain(|entry| !added.iter().any(|new| new.source == entry.source));
+    let replaced = before - entries.len();
+    entries.extend(added);
+    replaced
+}
+
+/// The `top_k` entries whose keys are closest to `query_embedding`, best first, with their
+/// cosine distance. Ties keep the table order.
+pub fn rank_entries<'a>(
+    entries: &'a [KvEntry],
+    query_embedding: &[f32],
+    top_k: usize,
+    max_distance: Option<f64>,
+) -> Vec<(&'a KvEntry, f64)> {
+    let mut ranked: Vec<(&KvEntry, f64)> = entries
+        .iter()
+        .filter_map(|entry| {
+            let distance = crate::search::cosine_distance(query_embedding, &entry.embedding)?;
+            Some((entry, distance))
+        })
+        .filter(|(_, distance)| max_distance.is_none_or(|max| *distance <= max))
+        .collect();
+    ranked.sort_by(|a, b| a.1.total_cmp(&b.1));
+    ranked.truncate(top_k);
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(source: &str, key: &str, embedding: Vec<f32>) -> KvEntry {
+        KvEntry {
+            source: source.to_string(),
+            index: 0,
+            key: key.to_string(),
+            value: format!("answer to {key}"),
+            embedding,
+        }
+    }
+
+    #[test]
+    fn test_parse_pairs() {
+        let content = "question,answer,team\n\
+                       How do I reset my password?,Use the login page,it\n\
+                       Where is the invoice?,,billing\n\
+                       ,Nobody asked,it\n";
+        let pairs = parse_pairs(content, InputFormat::Csv, "question", "answer").unwrap();
+        assert_eq!(
+            pairs,
+            vec![(
+                0,
+                "How do I reset my password?".to_string(),
+                "Use the login page".to_string()
+            )]
+        );
+
+        let content = r#"{"q": "Refunds?", "a": {"days": 30}}"#;
+        let pairs = parse_pairs(content, InputFormat::Jsonl, "q", "a.days").unwrap();
+        assert_eq!(pairs[0].2, "30");
+
+        assert_eq!(kv_format("faq.CSV", None).unwrap(), InputFormat::Csv);
+        assert!(kv_format("faq.txt", None).is_err());
+        assert!(kv_format("faq.csv", Some(InputFormat::Lines)).is_err());
+    }
+
+    #[test]
+    fn test_merge_and_rank_entries() {
+        let mut entries = vec![
+            entry("a.csv", "old", vec![1.0, 0.0]),
+            entry("b.csv", "billing", vec![0.0, 1.0]),
+        ];
+        let replaced = merge_entries(
+            &mut entries,
+            vec![
+                entry("a.csv", "password", vec![1.0, 0.1]),
+                entry("a.csv", "login", vec![1.0, 1.0]),
+            ],
+        );
+        assert_eq!(replaced, 1);
+        assert_eq!(entries.len(), 3);
+
+        let ranked = rank_entries(&entries, &[1.0, 0.0], 2, None);
+        let keys: Vec<&str> = ranked.iter().map(|(e, _)| e.key.as_str()).collect();
+        assert_eq!(keys, ["password", "login"]);
+        assert_eq!(rank_entries(&entries, &[1.0, 0.0], 10, Some(0.1)).len(), 1);
+    }
+}
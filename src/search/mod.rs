@@ -1,27 +1,60 @@
 use anyhow::Result;
 use model2vec_rs::model::StaticModel;
-use simsimd::SpatialSimilarity;
-use std::cmp::{max, min};
+use std::collections::HashMap;
 use std::fs::read_to_string;
+use std::time::{Instant, UNIX_EPOCH};
+
+use crate::archive;
+use crate::search::error::SearchError;
+use crate::search::normalize::{normalize, normalize_lines};
+use crate::telemetry::{self, Operation};
+
+// The ranking itself lives in `crate::ranking`, which does not depend on the model
+pub(crate) use crate::ranking::{Candidate, MMR_OVERSAMPLE_FACTOR, TopCandidates};
+pub use crate::ranking::{
+    DEFAULT_MAX_LINE_CHARS, DEFAULT_NEGATIVE_WEIGHT, Document, FileMatch, FileScoreMode, PathBoost,
+    QueryCombination, RowEmbedding, SearchConfig, SearchResult, TagFilter, apply_path_weight,
+    blend_recency, combined_distance, cosine_distance, mmr_select, negative_penalty,
+    normalized_recency, parse_tag, path_weight, rank_files, rank_lines, score_lines, split_lines,
+    strongest_terms, word_windows,
+};
 
-#[cfg(feature = "workspace")]
-use crate::workspace::store::{DocMeta, DocumentState, RankedLine};
-
-#[cfg(feature = "workspace")]
+pub mod bench;
+pub mod calibration;
+pub mod context_window;
+pub mod dedupe;
+pub mod device;
+pub mod error;
+pub mod explain;
+#[cfg(feature = "gpu")]
+pub mod gpu;
+pub mod kv;
+pub mod model;
+pub mod normalize;
+pub mod pick;
+pub mod records;
+pub mod router;
+pub mod saved;
+pub mod similar;
+pub mod stream;
+pub mod tables;
+
+#[cfg(feature = "workspace-lite")]
+use explain::Retrieval;
+use explain::{Explain, Stage};
+
+#[cfg(feature = "workspace-lite")]
+use crate::workspace::store::{DocMeta, DocumentState, RankedLine, Store};
+
+#[cfg(feature = "workspace-lite")]
 use crate::workspace::{
     Workspace,
-    store::{LineEmbedding, Store},
+    store::{DocEmbedding, LineEmbedding},
 };
 
 pub const MODEL_NAME: &str = "minishlab/potion-multilingual-128M";
 
-pub struct Document {
-    pub filename: String,
-    pub lines: Vec<String>,
-    pub embeddings: Vec<Vec<f32>>,
-}
-
-#[cfg(feature = "workspace")]
+#[cfg(feature = "workspace-lite")]
 #[derive(Debug)]
 pub struct DocumentInfo {
     pub filename: String,
@@ -29,21 +62,53 @@ pub struct DocumentInfo {
     pub meta: DocMeta,
 }
 
-#[derive(Default)]
-pub struct SearchConfig {
-    pub n_lines: usize,
-    pub top_k: usize,
-    pub max_distance: Option<f64>,
-    pub ignore_case: bool,
+/// Candidate lines retrieved per requested file in files-with-matches mode
+pub const FILE_CANDIDATE_FACTOR: usize = 10;
+
+/// Embed a query, normalized like the documents it is compared with (see `normalize`)
+pub fn embed_query(model: &StaticModel, query: &str) -> Vec<f32> {
+    model.encode_single(&normalize(query))
 }
 
-pub struct SearchResult {
-    pub filename: String,
-    pub lines: Vec<String>,
-    pub start: usize,
-    pub end: usize,
-    pub match_line: usize, // The actual line number that matched
-    pub distance: f64,
+/// Embed extra queries (negative or combined ones) the same way as the query itself
+pub fn embed_queries(model: &StaticModel, queries: &[String], ignore_case: bool) -> Vec<Vec<f32>> {
+    queries
+        .iter()
+        .map(|q| {
+            if ignore_case {
+                embed_query(model, &q.to_lowercase())
+            } else {
+                embed_query(model, q)
+            }
+        })
+        .collect()
+}
+
+/// Embed short texts, such as the words of a query, each normalized on its own
+fn embed_texts(model: &StaticModel, texts: &[String]) -> Vec<Vec<f32>> {
+    let texts: Vec<String> = texts.iter().map(|t| normalize(t).into_owned()).collect();
+    model.encode_with_args(&texts, Some(2048), 1024)
+}
+
+fn file_mtime(path: &str) -> Option<i64> {
+    let modified = std::fs::metadata(archive::local_path(path))
+        .ok()?
+        .modified()
+        .ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64)
+}
+
+/// Embed consecutive document lines, normalized (see `normalize`), recording embedding time and
+/// throughput
+pub(crate) fn embed_lines(model: &StaticModel, lines: &[String]) -> Vec<Vec<f32>> {
+    let _timed = telemetry::timed(Operation::Embedding, &[]);
+    telemetry::add_embedded_lines(lines.len());
+    let lines = normalize_lines(lines);
+    #[cfg(feature = "gpu")]
+    if let Some(embeddings) = gpu::encode(&lines) {
+        return embeddings;
+    }
+    model.encode_with_args(&lines, Some(2048), 16384)
 }
 
 pub(crate) fn create_document_from_content(
@@ -52,97 +117,255 @@ pub(crate) fn create_document_from_content(
     model: &StaticModel,
     ignore_case: bool,
 ) -> Option<Document> {
-    let lines: Vec<&str> = content.lines().collect();
-
-    if lines.is_empty() {
+    let owned_lines = split_lines(content);
+    if owned_lines.is_empty() {
         return None;
     }
 
-    let owned_lines: Vec<String> = lines.iter().map(|s| s.to_string()).collect();
-
     let lines_for_embedding = if ignore_case {
         owned_lines.iter().map(|s| s.to_lowercase()).collect()
     } else {
         owned_lines.clone()
     };
 
-    let embeddings = model.encode_with_args(&lines_for_embedding, Some(2048), 16384);
+    let embeddings = embed_lines(model, &lines_for_embedding);
+    let table_rows = embed_table_rows(model, &owned_lines, ignore_case);
     Some(Document {
         filename,
         lines: owned_lines,
         embeddings,
+        table_rows,
     })
 }
 
+/// Embed the rows of the tables in `lines` as pseudo-lines of their own (see `tables`)
+pub fn embed_table_rows(
+    model: &StaticModel,
+    lines: &[String],
+    ignore_case: bool,
+) -> Vec<RowEmbedding> {
+    let rows = tables::table_rows(lines);
+    if rows.is_empty() {
+        return Vec::new();
+    }
+    let texts: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            if ignore_case {
+                row.text.to_lowercase()
+            } else {
+                row.text.clone()
+            }
+        })
+        .collect();
+    embed_lines(model, &texts)
+        .into_iter()
+        .zip(rows)
+        .map(|(embedding, row)| RowEmbedding {
+            line: row.line,
+            embedding,
+        })
+        .collect()
+}
+
 pub fn search_documents(
     documents: &[Document],
     query_embedding: &[f32],
     config: &SearchConfig,
 ) -> Vec<SearchResult> {
-    let mut search_results = Vec::new();
-
-    for doc in documents {
-        for (idx, line_embedding) in doc.embeddings.iter().enumerate() {
-            let distance = f32::cosine(query_embedding, line_embedding);
-            if let Some(distance) = distance {
-                let distance_threshold = config.max_distance.unwrap_or(100.0);
-                if distance < distance_threshold {
-                    let bottom_range = max(0, idx.saturating_sub(config.n_lines));
-                    let top_range = min(doc.lines.len(), idx + config.n_lines + 1);
-
-                    search_results.push(SearchResult {
-                        filename: doc.filename.clone(),
-                        lines: doc.lines[bottom_range..top_range].to_vec(),
-                        distance,
-                        start: bottom_range,
-                        end: top_range,
-                        match_line: idx,
-                    })
-                }
-            }
-        }
+    search_documents_explained(documents, query_embedding, config, &mut Explain::default())
+}
+
+/// `search_documents`, recording scoring and ranking diagnostics in `explain`
+pub fn search_documents_explained(
+    documents: &[Document],
+    query_embedding: &[f32],
+    config: &SearchConfig,
+    explain: &mut Explain,
+) -> Vec<SearchResult> {
+    let started = Instant::now();
+    let recency = document_recency(documents, config);
+
+    let scored = score_lines(documents, query_embedding, config, &recency);
+    explain.add_time(Stage::VectorSearch, started.elapsed());
+    explain.candidates("lines scored", scored.lines_scored);
+    if let Some(max_distance) = config.max_distance {
+        explain.filter(format!(
+            "max distance {max_distance} checked against every scored line"
+        ));
+        explain.candidates("lines within max distance", scored.within_threshold);
     }
 
-    // Sort by distance (best matches first)
-    search_results.sort_by(|a, b| {
-        a.distance
-            .partial_cmp(&b.distance)
-            .unwrap_or(std::cmp::Ordering::Equal)
-    });
+    let results = explain.time(Stage::Ranking, || rank_lines(documents, scored, config));
+    explain.candidates("results", results.len());
+    results
+}
+
+/// Normalized recency of each document, or all zeros when the recency boost is off (documents
+/// without an mtime, e.g. stdin, get no boost)
+fn document_recency(documents: &[Document], config: &SearchConfig) -> Vec<f64> {
+    let filenames: Vec<&str> = documents.iter().map(|doc| doc.filename.as_str()).collect();
+    file_recency(&filenames, config)
+}
+
+/// Normalized recency of each file, or all zeros when the recency boost is off
+fn file_recency(filenames: &[&str], config: &SearchConfig) -> Vec<f64> {
+    if config.recency_boost <= 0.0 {
+        return vec![0.0; filenames.len()];
+    }
+    let mtimes: HashMap<String, i64> = filenames
+        .iter()
+        .filter_map(|name| file_mtime(name).map(|m| (name.to_string(), m)))
+        .collect();
+    let recency = normalized_recency(&mtimes);
+    filenames
+        .iter()
+        .map(|name| recency.get(*name).copied().unwrap_or(0.0))
+        .collect()
+}
+
+/// Longest span, in words, considered when locating the best match within a line
+const MAX_SPAN_WORDS: usize = 8;
 
-    // If threshold is specified, return all results under threshold
-    // Otherwise, limit to top_k results
-    if config.max_distance.is_some() {
-        search_results
+/// Find the span of each result's matching line that is closest to the query's strongest terms,
+/// sliding a window of as many words as there are strong terms over the line. Filler words
+/// such as "the" or "how" would otherwise widen the span past the words that matched.
+pub fn locate_match_spans(
+    results: &mut [SearchResult],
+    query: &str,
+    query_embedding: &[f32],
+    model: &StaticModel,
+    ignore_case: bool,
+) {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| {
+            if ignore_case {
+                term.to_lowercase()
+            } else {
+                term.to_string()
+            }
+        })
+        .collect();
+    let (window, target) = if terms.len() <= 1 {
+        (1, query_embedding.to_vec())
     } else {
-        search_results.into_iter().take(config.top_k).collect()
+        let distances: Vec<f64> = embed_texts(model, &terms)
+            .iter()
+            .map(|e| cosine_distance(query_embedding, e).unwrap_or(2.0))
+            .collect();
+        let strong: Vec<&str> = strongest_terms(&distances)
+            .into_iter()
+            .map(|i| terms[i].as_str())
+            .collect();
+        (strong.len(), embed_query(model, &strong.join(" ")))
+    };
+    let window = window.clamp(1, MAX_SPAN_WORDS);
+
+    // Spans already located, such as those of lexical matches, are kept
+    for result in results
+        .iter_mut()
+        .filter(|r| (r.match_start_col, r.match_end_col) == (0, 0))
+    {
+        let Some(line) = result
+            .lines
+            .get(result.match_line.saturating_sub(result.start))
+        else {
+            continue;
+        };
+        let spans = word_windows(line, window);
+        if spans.len() <= 1 {
+            (result.match_start_col, result.match_end_col) =
+                spans.first().copied().unwrap_or((0, 0));
+            continue;
+        }
+
+        let texts: Vec<String> = spans
+            .iter()
+            .map(|&(start, end)| {
+                let text = &line[start..end];
+                if ignore_case {
+                    text.to_lowercase()
+                } else {
+                    text.to_string()
+                }
+            })
+            .collect();
+        let embeddings = embed_texts(model, &texts);
+
+        let best = embeddings
+            .iter()
+            .enumerate()
+            .filter_map(|(i, e)| cosine_distance(&target, e).map(|d| (i, d)))
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+        if let Some((i, _)) = best {
+            (result.match_start_col, result.match_end_col) = spans[i];
+        }
     }
 }
 
-pub fn search_files(
-    files: &[String],
+/// Locate match spans for results printed as snippets, when some matched line is longer than
+/// `max_line_chars` and would be cut (see `SearchResult::snippet_lines`)
+pub fn locate_long_match_spans(
+    results: &mut [SearchResult],
+    max_line_chars: usize,
     query: &str,
+    query_embedding: &[f32],
     model: &StaticModel,
-    config: &SearchConfig,
-) -> Result<Vec<SearchResult>> {
+    ignore_case: bool,
+) {
+    if results
+        .iter()
+        .any(|r| r.has_long_match_line(max_line_chars))
+    {
+        locate_match_spans(results, query, query_embedding, model, ignore_case);
+    }
+}
+
+/// Read and embed files, skipping empty ones
+pub fn load_documents(
+    files: &[String],
+    model: &StaticModel,
+    ignore_case: bool,
+) -> Result<Vec<Document>> {
+    load_documents_explained(files, model, ignore_case, &mut Explain::default())
+}
+
+/// `load_documents`, timing file reads and embedding separately in `explain`
+pub fn load_documents_explained(
+    files: &[String],
+    model: &StaticModel,
+    ignore_case: bool,
+    explain: &mut Explain,
+) -> Result<Vec<Document>> {
     let mut documents = Vec::new();
     for f in files {
-        let content = read_to_string(f)?;
-        if let Some(doc) =
-            create_document_from_content(f.clone(), &content, model, config.ignore_case)
-        {
+        let content = explain.time(Stage::FileIo, || read_to_string(archive::local_path(f)))?;
+        if let Some(doc) = explain.time(Stage::Embedding, || {
+            create_document_from_content(f.clone(), &content, model, ignore_case)
+        }) {
             documents.push(doc);
         }
     }
+    Ok(documents)
+}
+
+pub fn search_files(
+    files: &[String],
+    query: &str,
+    model: &StaticModel,
+    config: &SearchConfig,
+) -> Result<Vec<SearchResult>> {
+    let documents = load_documents(files, model, config.ignore_case)?;
 
-    let query_embedding = model.encode_single(query);
+    let query_embedding = embed_query(model, query);
 
     let results = search_documents(&documents, &query_embedding, config);
 
     Ok(results)
 }
 
-#[cfg(feature = "workspace")]
+#[cfg(feature = "workspace-lite")]
 pub async fn search_with_workspace(
     files: &[String],
     query: &str,
@@ -150,38 +373,108 @@ pub async fn search_with_workspace(
     config: &SearchConfig,
     workspace_name: Option<&str>,
 ) -> Result<Vec<RankedLine>> {
-    let query_embedding = model.encode_single(query);
-    let ws = Workspace::open(workspace_name)?;
-    let store = Store::open(&ws.config.root_dir)?;
+    search_with_workspace_explained(
+        files,
+        query,
+        model,
+        config,
+        workspace_name,
+        &mut Explain::default(),
+    )
+    .await
+}
 
+/// Fail when the workspace's embedding space in use stores embeddings of a different size than
+/// `model_dimensions`
+#[cfg(feature = "workspace-lite")]
+pub fn check_embedding_dimensions(ws: &Workspace, model_dimensions: usize) -> Result<()> {
+    let space = ws.space();
+    let dimensions = ws.space_dimensions(space);
+    if model_dimensions != dimensions {
+        return Err(SearchError::DimensionMismatch {
+            workspace: ws.config.name.clone(),
+            space,
+            stored: dimensions,
+            model: model_dimensions,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Embed the new and changed documents among `files` into the workspace index. Returns the
+/// number of documents (re)indexed.
+#[cfg(feature = "workspace-lite")]
+pub fn update_workspace_index(
+    store: &Store,
+    files: &[String],
+    model: &StaticModel,
+    ignore_case: bool,
+    store_text: bool,
+    explain: &mut Explain,
+) -> Result<usize> {
     // Step 1: Analyze document states (changed/new/unchanged)
-    let doc_states = store.analyze_document_states(files)?;
+    let doc_states = explain.time(Stage::FileIo, || store.analyze_document_states(files))?;
+    explain.candidates("input files", files.len());
+    explain.candidates(
+        "new or changed documents",
+        doc_states
+            .iter()
+            .filter(|state| !matches!(state, DocumentState::Unchanged(_)))
+            .count(),
+    );
 
     // Step 2: Process documents that need embedding updates
     let mut line_embeddings_to_upsert = Vec::new();
+    let mut doc_embeddings_to_upsert = Vec::new();
     let mut docs_to_upsert = Vec::new();
+    // Documents (re)indexed without lines, whose former lines and embedding are removed
+    let mut emptied_docs = Vec::new();
 
     for state in &doc_states {
         match state {
             DocumentState::Changed(doc_info) | DocumentState::New(doc_info) => {
                 // Generate line-by-line embeddings and store them
-                if let Some(doc) = create_document_from_content(
-                    doc_info.filename.clone(),
-                    &doc_info.content,
-                    model,
-                    config.ignore_case,
-                ) {
+                if let Some(doc) = explain.time(Stage::Embedding, || {
+                    create_document_from_content(
+                        doc_info.filename.clone(),
+                        &doc_info.content,
+                        model,
+                        ignore_case,
+                    )
+                }) {
                     // Create LineEmbedding entries for each line
                     for (line_idx, embedding) in doc.embeddings.iter().enumerate() {
                         line_embeddings_to_upsert.push(LineEmbedding {
                             path: doc_info.filename.clone(),
                             line_number: line_idx as i32, // Store as 0-based for consistency
                             embedding: embedding.clone(),
+                            text: store_text.then(|| doc.lines[line_idx].clone()),
+                            table_line: None,
                         });
                     }
-                    // Also track document metadata for change detection
-                    docs_to_upsert.push(doc_info.meta.clone());
+                    // Table rows are stored after the lines, and cite the line holding them
+                    for (row_idx, row) in doc.table_rows.iter().enumerate() {
+                        line_embeddings_to_upsert.push(LineEmbedding {
+                            path: doc_info.filename.clone(),
+                            line_number: (doc.lines.len() + row_idx) as i32,
+                            embedding: row.embedding.clone(),
+                            text: None,
+                            table_line: Some(row.line as i32),
+                        });
+                    }
+                    // Mean of the line embeddings, for first-stage document retrieval
+                    if let Some(embedding) = dedupe::document_embedding(&doc.embeddings) {
+                        doc_embeddings_to_upsert.push(DocEmbedding {
+                            path: doc_info.filename.clone(),
+                            embedding,
+                        });
+                    }
+                } else {
+                    emptied_docs.push(doc_info.filename.clone());
                 }
+                // Also track document metadata for change detection
+                docs_to_upsert.push(doc_info.meta.clone());
             }
             DocumentState::Unchanged(_) => {
                 // Skip - already in workspace and unchanged
@@ -189,16 +482,33 @@ pub async fn search_with_workspace(
         }
     }
 
-    // Step 3: Update workspace with new/changed line embeddings
+    // Step 3: Update workspace with new/changed line embeddings, as one batch with respect
+    // to other processes writing to the workspace
+    let started = Instant::now();
+    let write_lock = if docs_to_upsert.is_empty() {
+        None
+    } else {
+        Some(store.write_lock()?)
+    };
     if !line_embeddings_to_upsert.is_empty() {
         eprintln!(
             "Updating workspace with {} lines from new/changed docs...",
             line_embeddings_to_upsert.len()
         );
-        store.upsert_line_embeddings(&line_embeddings_to_upsert)?;
+    }
+    if !docs_to_upsert.is_empty() {
+        // Every (re)indexed document is named, so the lines of those now empty are removed too
+        let paths: Vec<String> = docs_to_upsert
+            .iter()
+            .map(|meta| meta.path.clone())
+            .collect();
+        store.replace_line_embeddings(&paths, &line_embeddings_to_upsert)?;
+        store.delete_doc_embeddings(&emptied_docs)?;
+        store.upsert_doc_embeddings(&doc_embeddings_to_upsert)?;
     }
 
-    // Also update document metadata for tracking changes
+    // Also update document metadata for tracking changes. This goes last: if we are
+    // interrupted before this point the documents still look changed and are redone next run.
     if !docs_to_upsert.is_empty() {
         eprintln!(
             "Updating workspace with {} new/changed documents...",
@@ -206,12 +516,391 @@ pub async fn search_with_workspace(
         );
         store.upsert_document_metadata(&docs_to_upsert)?;
     }
+    drop(write_lock);
+    if !docs_to_upsert.is_empty() {
+        explain.add_time(Stage::IndexUpdate, started.elapsed());
+    }
 
-    // Step 4: Search line embeddings directly from the workspace
+    Ok(docs_to_upsert.len())
+}
+
+/// `search_with_workspace`, recording timings, candidate counts and how the index was
+/// queried in `explain`
+#[cfg(feature = "workspace-lite")]
+pub async fn search_with_workspace_explained(
+    files: &[String],
+    query: &str,
+    model: &StaticModel,
+    config: &SearchConfig,
+    workspace_name: Option<&str>,
+    explain: &mut Explain,
+) -> Result<Vec<RankedLine>> {
+    explain.retrieval = Retrieval::WorkspaceIndex;
+    let query_embedding = explain.time(Stage::Embedding, || embed_query(model, query));
+    let ws = Workspace::open(workspace_name)?;
+    check_embedding_dimensions(&ws, query_embedding.len())?;
+    let store = ws.open_store()?;
+    ws.mark_used();
+
+    if !ws.is_read_only() {
+        // Steps 1-3: Bring the index up to date with the input files
+        update_workspace_index(
+            &store,
+            files,
+            model,
+            config.ignore_case,
+            ws.config.store_text,
+            explain,
+        )?;
+        return search_index(&ws, &store, files, &query_embedding, config, explain);
+    }
+
+    // A read-only index is searched for the files it is up to date for, while new or changed
+    // files are embedded and searched in memory without being written
+    let (indexed, unindexed) =
+        embed_unindexed_documents(&store, files, model, config.ignore_case, explain)?;
+    // Documents outside the index carry no tags or stored metadata
+    let unindexed = if config.tag_filters.is_empty() && config.metadata_filters.is_empty() {
+        unindexed
+    } else {
+        Vec::new()
+    };
+    if !unindexed.is_empty() {
+        eprintln!(
+            "Workspace '{}' is read-only: searching {} new or changed files in memory without \
+             indexing them",
+            ws.config.name,
+            unindexed.len()
+        );
+    }
+    let mut ranked_lines = search_index(&ws, &store, &indexed, &query_embedding, config, explain)?;
+    if unindexed.is_empty() {
+        return Ok(ranked_lines);
+    }
+
+    explain.filter("read-only workspace: new or changed files searched in memory".to_string());
+    explain.candidates("files searched in memory", unindexed.len());
+    let in_memory = explain.time(Stage::VectorSearch, || {
+        search_documents(&unindexed, &query_embedding, config)
+    });
+    ranked_lines.extend(in_memory.into_iter().map(|result| RankedLine {
+        path: result.filename,
+        line_number: result.match_line as i32,
+        distance: result.distance as f32,
+        score: result.score as f32,
+        embedding: Vec::new(),
+    }));
+    ranked_lines.sort_by(|a, b| {
+        a.score
+            .partial_cmp(&b.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.path.cmp(&b.path))
+            .then_with(|| a.line_number.cmp(&b.line_number))
+    });
+    if config.max_distance.is_none() {
+        ranked_lines.truncate(config.top_k);
+    }
+    explain.candidates("results after merging", ranked_lines.len());
+    Ok(ranked_lines)
+}
+
+/// Split `files` into those the index is up to date for, and in-memory documents for the new or
+/// changed rest, without writing to the index
+#[cfg(feature = "workspace-lite")]
+fn embed_unindexed_documents(
+    store: &Store,
+    files: &[String],
+    model: &StaticModel,
+    ignore_case: bool,
+    explain: &mut Explain,
+) -> Result<(Vec<String>, Vec<Document>)> {
+    let doc_states = explain.time(Stage::FileIo, || store.analyze_document_states(files))?;
+    explain.candidates("input files", files.len());
+
+    let mut indexed = Vec::new();
+    let mut unindexed = Vec::new();
+    for state in doc_states {
+        match state {
+            DocumentState::Unchanged(path) => indexed.push(path),
+            DocumentState::Changed(doc_info) | DocumentState::New(doc_info) => {
+                if let Some(doc) = explain.time(Stage::Embedding, || {
+                    create_document_from_content(
+                        doc_info.filename,
+                        &doc_info.content,
+                        model,
+                        ignore_case,
+                    )
+                }) {
+                    unindexed.push(doc);
+                }
+            }
+        }
+    }
+    // Files gone from disk are left to the index, which may still hold them (`store_text`)
+    indexed.extend(
+        files
+            .iter()
+            .filter(|f| !archive::local_path(f).exists())
+            .cloned(),
+    );
+    Ok((indexed, unindexed))
+}
+
+/// Steps 4 and 5 of a workspace search: narrow `files` by tags and first-stage document
+/// retrieval, then search their line embeddings
+#[cfg(feature = "workspace-lite")]
+fn search_index(
+    ws: &Workspace,
+    store: &Store,
+    files: &[String],
+    query_embedding: &[f32],
+    config: &SearchConfig,
+    explain: &mut Explain,
+) -> Result<Vec<RankedLine>> {
+    // Tag and metadata filters are matched by the documents shard, so lines of other
+    // documents are never searched
+    let tagged_files;
+    let files = if config.tag_filters.is_empty() && config.metadata_filters.is_empty() {
+        files
+    } else {
+        tagged_files = explain.time(Stage::VectorSearch, || {
+            store.filter_documents(files, &config.tag_filters, &config.metadata_filters)
+        })?;
+        let filters: Vec<String> = config
+            .tag_filters
+            .iter()
+            .map(|f| format!("tag:{}={}", f.key, f.value))
+            .chain(config.metadata_filters.iter().map(|f| f.to_string()))
+            .collect();
+        explain.filter(format!(
+            "document filter {} pushed down into the document index",
+            filters.join(", ")
+        ));
+        explain.candidates("documents matching filters", tagged_files.len());
+        tagged_files.as_slice()
+    };
+
+    // Step 4: Two-stage retrieval for large inputs: keep the closest documents first, then
+    // search only their lines
+    let candidate_files;
+    let files = if ws.config.doc_top_k > 0 && files.len() > ws.config.doc_top_k {
+        candidate_files = explain.time(Stage::VectorSearch, || {
+            store.select_candidate_documents(query_embedding, files, ws.config.doc_top_k)
+        })?;
+        explain.filter(format!(
+            "first-stage document retrieval kept the {} closest of {} documents (doc_top_k)",
+            candidate_files.len(),
+            files.len()
+        ));
+        explain.candidates("documents after first stage", candidate_files.len());
+        candidate_files.as_slice()
+    } else {
+        explain.filter(if ws.config.doc_top_k == 0 {
+            "first-stage document retrieval disabled (doc_top_k = 0)".to_string()
+        } else {
+            format!(
+                "first-stage document retrieval skipped: {} documents <= doc_top_k {}",
+                files.len(),
+                ws.config.doc_top_k
+            )
+        });
+        files
+    };
+
+    // Step 5: Search line embeddings directly from the workspace
     let max_distance = config.max_distance.map(|d| d as f32);
-    let ranked_lines =
-        store.search_line_embeddings(&query_embedding, files, config.top_k, max_distance)?;
+    let negatives = !config.negative_embeddings.is_empty();
+    let combined = !config.extra_query_embeddings.is_empty();
+    explain.filter(format!(
+        "path filter on {} file(s) pushed down into the line index query",
+        files.len()
+    ));
+    explain.filter(match max_distance {
+        Some(d) => format!(
+            "max distance {d} pushed down as score threshold {}",
+            1_f32 - d
+        ),
+        None => "no distance threshold; nearest lines limited by top-k".to_string(),
+    });
+    let boosted = !config.boosts.is_empty();
+    if config.recency_boost <= 0.0
+        && config.mmr_lambda.is_none()
+        && !negatives
+        && !combined
+        && !boosted
+    {
+        // Oversampled, as a table's line and its rows can all be among the nearest
+        let candidates = config.top_k * ws.config.oversample_factor.max(1);
+        let mut ranked_lines = explain.time(Stage::VectorSearch, || {
+            store.search_line_embeddings(query_embedding, files, candidates, max_distance)
+        })?;
+        dedupe_cited_lines(&mut ranked_lines);
+        ranked_lines.truncate(config.top_k);
+        explain.candidates("results", ranked_lines.len());
+        return Ok(ranked_lines);
+    }
 
+    // When re-ranking, oversample candidates and re-rank them by the blended score
+    let candidates = config.top_k * ws.config.oversample_factor.max(1);
+    let ranked_lines = explain.time(Stage::VectorSearch, || {
+        if combined {
+            search_combined_queries(
+                store,
+                query_embedding,
+                files,
+                candidates,
+                max_distance,
+                config,
+            )
+        } else {
+            store.search_line_embeddings_with_vectors(
+                query_embedding,
+                files,
+                candidates,
+                max_distance,
+                config.mmr_lambda.is_some() || negatives,
+            )
+        }
+    })?;
+    explain.candidates(
+        format!(
+            "line candidates (oversampled x{})",
+            ws.config.oversample_factor.max(1)
+        ),
+        ranked_lines.len(),
+    );
+
+    let ranked_lines = explain.time(Stage::Ranking, || {
+        rerank_lines(store, files, ranked_lines, config)
+    })?;
+    explain.candidates("results", ranked_lines.len());
+    Ok(ranked_lines)
+}
+
+/// Re-rank oversampled workspace candidates by the blended score, then diversify or cut them
+/// to `top_k`
+#[cfg(feature = "workspace-lite")]
+fn rerank_lines(
+    store: &Store,
+    files: &[String],
+    mut ranked_lines: Vec<RankedLine>,
+    config: &SearchConfig,
+) -> Result<Vec<RankedLine>> {
+    let negatives = !config.negative_embeddings.is_empty();
+
+    if negatives {
+        for ranked_line in ranked_lines.iter_mut() {
+            ranked_line.score =
+                negative_penalty(ranked_line.distance as f64, &ranked_line.embedding, config)
+                    as f32;
+        }
+    }
+
+    if config.recency_boost > 0.0 {
+        let mtimes: HashMap<String, i64> = store
+            .get_existing_docs(files)?
+            .into_iter()
+            .map(|(path, meta)| (path, meta.mtime))
+            .collect();
+        let recency = normalized_recency(&mtimes);
+        for ranked_line in ranked_lines.iter_mut() {
+            let r = recency.get(&ranked_line.path).copied().unwrap_or(0.0);
+            ranked_line.score =
+                blend_recency(ranked_line.score as f64, r, config.recency_boost) as f32;
+        }
+    }
+
+    let boosted = !config.boosts.is_empty();
+    if boosted {
+        for ranked_line in ranked_lines.iter_mut() {
+            let weight = path_weight(&ranked_line.path, &config.boosts);
+            ranked_line.score = apply_path_weight(ranked_line.score as f64, weight) as f32;
+        }
+    }
+
+    if negatives || config.recency_boost > 0.0 || boosted {
+        ranked_lines.sort_by(|a, b| {
+            a.score
+                .partial_cmp(&b.score)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.path.cmp(&b.path))
+                .then_with(|| a.line_number.cmp(&b.line_number))
+        });
+    }
+
+    dedupe_cited_lines(&mut ranked_lines);
+
+    if let Some(lambda) = config.mmr_lambda {
+        let embeddings: Vec<&[f32]> = ranked_lines
+            .iter()
+            .map(|r| r.embedding.as_slice())
+            .collect();
+        let relevance: Vec<f64> = ranked_lines.iter().map(|r| 1.0 - r.score as f64).collect();
+        let order = mmr_select(&embeddings, &relevance, config.top_k, lambda);
+        let mut slots: Vec<Option<RankedLine>> = ranked_lines.into_iter().map(Some).collect();
+        return Ok(order.into_iter().filter_map(|i| slots[i].take()).collect());
+    }
+
+    ranked_lines.truncate(config.top_k);
+    Ok(ranked_lines)
+}
+
+/// Keep the best of the ranked lines citing the same line, as a table's rows cite the line
+/// holding the table
+#[cfg(feature = "workspace-lite")]
+fn dedupe_cited_lines(ranked_lines: &mut Vec<RankedLine>) {
+    let mut seen = std::collections::HashSet::new();
+    ranked_lines.retain(|r| seen.insert((r.path.clone(), r.line_number)));
+}
+
+/// Gather candidates for each query separately, then rank their union by the combined
+/// distance. A line within `max_distance` of every query (or of any query) is among the
+/// candidates of that query, so the threshold can be applied per query.
+#[cfg(feature = "workspace-lite")]
+fn search_combined_queries(
+    store: &Store,
+    query_embedding: &[f32],
+    files: &[String],
+    candidates: usize,
+    max_distance: Option<f32>,
+    config: &SearchConfig,
+) -> Result<Vec<RankedLine>> {
+    let mut seen = std::collections::HashSet::new();
+    let mut ranked_lines = Vec::new();
+    for query in std::iter::once(query_embedding)
+        .chain(config.extra_query_embeddings.iter().map(Vec::as_slice))
+    {
+        for mut ranked_line in store.search_line_embeddings_with_vectors(
+            query,
+            files,
+            candidates,
+            max_distance,
+            true,
+        )? {
+            if !seen.insert((ranked_line.path.clone(), ranked_line.line_number)) {
+                continue;
+            }
+            let Some(distance) = combined_distance(query_embedding, &ranked_line.embedding, config)
+            else {
+                continue;
+            };
+            if max_distance.is_some_and(|max| distance as f32 > max) {
+                continue;
+            }
+            ranked_line.distance = distance as f32;
+            ranked_line.score = distance as f32;
+            ranked_lines.push(ranked_line);
+        }
+    }
+
+    ranked_lines.sort_by(|a, b| {
+        a.distance
+            .partial_cmp(&b.distance)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.path.cmp(&b.path))
+            .then_with(|| a.line_number.cmp(&b.line_number))
+    });
+    ranked_lines.truncate(candidates);
     Ok(ranked_lines)
 }
 
@@ -245,9 +934,73 @@ mod tests {
             top_k: 3,
             max_distance: None,
             ignore_case: false,
+            recency_boost: 0.0,
+            mmr_lambda: None,
+            negative_embeddings: Vec::new(),
+            negative_weight: DEFAULT_NEGATIVE_WEIGHT,
+            extra_query_embeddings: Vec::new(),
+            query_combination: QueryCombination::All,
+            tag_filters: Vec::new(),
+            metadata_filters: Vec::new(),
+            boosts: Vec::new(),
         }
     }
 
+    #[test]
+    fn test_locate_match_spans() {
+        let model = get_model();
+        let doc = create_test_document_with_model(
+            "spans.txt",
+            vec!["The quick brown fox jumps; the weather forecast predicts heavy rain tomorrow"],
+        );
+        let query = "rain";
+        let query_embedding = model.encode_single(query);
+        let mut results = search_documents(&[doc], &query_embedding, &create_test_config());
+
+        locate_match_spans(&mut results, query, &query_embedding, model, false);
+        let (start, end) = (results[0].match_start_col, results[0].match_end_col);
+        assert_eq!(&results[0].lines[0][start..end], "rain");
+    }
+
+    #[test]
+    fn test_recency_boost_reranks_results() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let old_path = temp_dir.path().join("old.md");
+        let new_path = temp_dir.path().join("new.md");
+        for (path, age_secs) in [(&old_path, 86_400 * 30), (&new_path, 0)] {
+            let file = std::fs::File::create(path).unwrap();
+            let mtime = std::time::SystemTime::now() - std::time::Duration::from_secs(age_secs);
+            file.set_modified(mtime).unwrap();
+        }
+
+        // The old file is a slightly better semantic match
+        let documents = vec![
+            Document {
+                filename: old_path.to_string_lossy().to_string(),
+                lines: vec!["meeting notes".to_string()],
+                embeddings: vec![vec![1.0, 0.0]],
+                table_rows: Vec::new(),
+            },
+            Document {
+                filename: new_path.to_string_lossy().to_string(),
+                lines: vec!["meeting notes".to_string()],
+                embeddings: vec![vec![0.95, 0.05]],
+                table_rows: Vec::new(),
+            },
+        ];
+        let query = vec![1.0, 0.0];
+
+        let mut config = create_test_config();
+        let results = search_documents(&documents, &query, &config);
+        assert!(results[0].filename.ends_with("old.md"));
+        assert_eq!(results[0].score, results[0].distance);
+
+        config.recency_boost = 0.1;
+        let results = search_documents(&documents, &query, &config);
+        assert!(results[0].filename.ends_with("new.md"));
+        assert!(results[0].score < results[0].distance);
+    }
+
     #[test]
     fn test_search_documents_basic() {
         let model = get_model();
@@ -0,0 +1,376 @@
+//! Locating, downloading and loading the static embedding model
+use anyhow::{Context, Result, anyhow};
+use hf_hub::Cache;
+use hf_hub::api::sync::ApiBuilder;
+use model2vec_rs::model::StaticModel;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::SemtoolsConfig;
+use crate::config::ModelConfig;
+use crate::search::MODEL_NAME;
+use crate::search::device::{EmbedDevice, device_override};
+use crate::search::error::SearchError;
+use crate::search::normalize::{Normalization, set_normalization};
+
+/// Files that make up a Model2Vec model
+const MODEL_FILES: [&str; 3] = ["tokenizer.json", "model.safetensors", "config.json"];
+
+/// Standard Hugging Face variable that, like `--offline`, forbids downloads when set to `1`
+const HF_HUB_OFFLINE: &str = "HF_HUB_OFFLINE";
+
+/// Named embedding models, trading quality for size and speed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbedPreset {
+    /// Small English model, for code and English-only text
+    Fast,
+    /// Mid-sized English model
+    Balanced,
+    /// Large multilingual model
+    #[default]
+    Quality,
+}
+
+impl EmbedPreset {
+    pub fn model_name(&self) -> &'static str {
+        match self {
+            EmbedPreset::Fast => "minishlab/potion-base-4M",
+            EmbedPreset::Balanced => "minishlab/potion-base-8M",
+            EmbedPreset::Quality => MODEL_NAME,
+        }
+    }
+
+    /// Size of the embeddings the preset's model produces
+    pub fn dimensions(&self) -> usize {
+        match self {
+            EmbedPreset::Fast => 128,
+            EmbedPreset::Balanced | EmbedPreset::Quality => 256,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmbedPreset::Fast => "fast",
+            EmbedPreset::Balanced => "balanced",
+            EmbedPreset::Quality => "quality",
+        }
+    }
+}
+
+impl fmt::Display for EmbedPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EmbedPreset {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fast" => Ok(EmbedPreset::Fast),
+            "balanced" => Ok(EmbedPreset::Balanced),
+            "quality" => Ok(EmbedPreset::Quality),
+            other => Err(SearchError::InvalidArgument(format!(
+                "Unknown embedding preset '{other}'. Supported presets: fast, balanced, quality"
+            ))
+            .into()),
+        }
+    }
+}
+
+/// Where the embedding model is loaded from
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelSource {
+    /// Hugging Face repo id, or a local model directory
+    pub name: String,
+    /// Cache directory for models downloaded from Hugging Face
+    pub cache_dir: PathBuf,
+    /// Where document embeddings are computed
+    pub device: EmbedDevice,
+    /// Normalization of the text embedded with the model
+    pub normalization: Normalization,
+}
+
+impl ModelSource {
+    /// Resolve the model with priority: `preset` (from the CLI or a workspace) > config `name` >
+    /// config `preset` > the default model. The device comes from `--device`, then the config.
+    pub fn from_config(config: &ModelConfig, preset: Option<EmbedPreset>) -> Result<Self> {
+        let config_preset = config
+            .preset
+            .as_deref()
+            .map(str::parse::<EmbedPreset>)
+            .transpose()?;
+        let name = match (preset, &config.name, config_preset) {
+            (Some(preset), _, _) => preset.model_name().to_string(),
+            (None, Some(name), _) => name.clone(),
+            (None, None, preset) => preset.unwrap_or_default().model_name().to_string(),
+        };
+        let device = match device_override() {
+            Some(device) => device,
+            None => config
+                .device
+                .as_deref()
+                .map(str::parse::<EmbedDevice>)
+                .transpose()?
+                .unwrap_or_default(),
+        };
+        Ok(Self {
+            name,
+            cache_dir: config
+                .cache_dir
+                .as_ref()
+                .map(PathBuf::from)
+                .unwrap_or_else(|| Cache::from_env().path().clone()),
+            device,
+            normalization: config.normalize,
+        })
+    }
+
+    /// Read the `model` section of a config file (defaults to ~/.config/semtools/config.json)
+    pub fn from_config_file(config: Option<&str>, preset: Option<EmbedPreset>) -> Result<Self> {
+        let config_path = config
+            .map(str::to_string)
+            .unwrap_or_else(SemtoolsConfig::default_config_path);
+        let semtools_config = SemtoolsConfig::from_config_file(&config_path)?;
+        Self::from_config(&semtools_config.model.unwrap_or_default(), preset)
+    }
+
+    /// Whether `name` is a local directory rather than a Hugging Face repo id
+    pub fn is_local(&self) -> bool {
+        let path = Path::new(&self.name);
+        path.is_dir() || path.is_absolute() || self.name.starts_with('.')
+    }
+
+    /// Directory holding the model files, if they are available without downloading
+    pub fn local_dir(&self) -> Option<PathBuf> {
+        if self.is_local() {
+            let dir = PathBuf::from(&self.name);
+            return dir.is_dir().then_some(dir);
+        }
+
+        let repo = Cache::new(self.cache_dir.clone()).model(self.name.clone());
+        let mut dir = None;
+        for file in MODEL_FILES {
+            dir = repo.get(file)?.parent().map(Path::to_path_buf);
+        }
+        dir
+    }
+
+    fn not_found(&self) -> anyhow::Error {
+        SearchError::ModelNotFound {
+            name: self.name.clone(),
+        }
+        .into()
+    }
+
+    /// Download the model into the cache unless it is already there, returning its directory
+    pub fn download(&self, progress: bool) -> Result<PathBuf> {
+        if let Some(dir) = self.local_dir() {
+            return Ok(dir);
+        }
+        if self.is_local() {
+            return Err(self.not_found());
+        }
+
+        let api = ApiBuilder::from_env()
+            .with_cache_dir(self.cache_dir.clone())
+            .with_progress(progress)
+            .build()
+            .context("Failed to set up the Hugging Face client")?;
+        let repo = api.model(self.name.clone());
+
+        let mut dir = None;
+        for file in MODEL_FILES {
+            let path = repo.get(file).with_context(|| {
+                format!(
+                    "Failed to download '{file}' of embedding model '{}'",
+                    self.name
+                )
+            })?;
+            dir = path.parent().map(Path::to_path_buf);
+        }
+        dir.ok_or_else(|| anyhow!("Embedding model '{}' has no files", self.name))
+    }
+
+    /// Load the model, downloading it first unless `offline` is set (or `HF_HUB_OFFLINE=1`).
+    /// Document embedding also moves to the configured GPU when one is usable.
+    pub fn load(&self, offline: bool) -> Result<StaticModel> {
+        let dir = match self.local_dir() {
+            Some(dir) => dir,
+            None if self.is_local() => return Err(self.not_found()),
+            None if offline || offline_from_env() => {
+                return Err(SearchError::ModelNotCached {
+                    name: self.name.clone(),
+                    cache_dir: self.cache_dir.clone(),
+                }
+                .into());
+            }
+            None => {
+                eprintln!(
+                    "Downloading embedding model '{}' to {} (first run only)...",
+                    self.name,
+                    self.cache_dir.display()
+                );
+                self.download(false)?
+            }
+        };
+        let model = StaticModel::from_pretrained(&dir, None, None, None)
+            .with_context(|| format!("Failed to load embedding model from {}", dir.display()))?;
+        set_normalization(self.normalization);
+
+        #[cfg(feature = "gpu")]
+        crate::search::gpu::activate(&dir, self.device);
+        #[cfg(not(feature = "gpu"))]
+        if self.device.is_explicit_gpu() {
+            eprintln!(
+                "Warning: semtools was built without the `gpu` feature; embedding on the CPU instead of {}",
+                self.device
+            );
+        }
+        Ok(model)
+    }
+}
+
+/// Parse `--embed-preset`. When a workspace is active, it defaults to the workspace's own
+/// preset, and selects the workspace's embedding space for that preset.
+pub fn resolve_embed_preset(
+    embed_preset: Option<&str>,
+    workspace_name: Option<&str>,
+) -> Result<Option<EmbedPreset>> {
+    let requested = embed_preset.map(str::parse::<EmbedPreset>).transpose()?;
+
+    #[cfg(feature = "workspace-lite")]
+    if crate::workspace::Workspace::active(workspace_name).is_ok() {
+        let ws = crate::workspace::Workspace::open(workspace_name)?;
+        let preset = ws.embed_preset(requested);
+        crate::workspace::set_space_override(preset);
+        return Ok(Some(preset));
+    }
+    #[cfg(not(feature = "workspace-lite"))]
+    let _ = workspace_name;
+
+    Ok(requested)
+}
+
+fn offline_from_env() -> bool {
+    std::env::var(HF_HUB_OFFLINE).is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+}
+
+/// Total size in bytes of the model files in `dir`, following the cache's symlinks
+pub fn model_size(dir: &Path) -> u64 {
+    MODEL_FILES
+        .iter()
+        .filter_map(|file| fs::metadata(dir.join(file)).ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_model_files(dir: &Path) {
+        fs::create_dir_all(dir).unwrap();
+        for file in MODEL_FILES {
+            fs::write(dir.join(file), b"{}").unwrap();
+        }
+    }
+
+    #[test]
+    fn test_local_directory_model() {
+        let temp_dir = TempDir::new().unwrap();
+        let model_dir = temp_dir.path().join("potion");
+        write_model_files(&model_dir);
+
+        let source = ModelSource::from_config(
+            &ModelConfig {
+                name: Some(model_dir.to_string_lossy().to_string()),
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+        assert!(source.is_local());
+        assert_eq!(source.local_dir(), Some(model_dir.clone()));
+        assert_eq!(model_size(&model_dir), 6);
+
+        let missing = ModelSource::from_config(
+            &ModelConfig {
+                name: Some(
+                    temp_dir
+                        .path()
+                        .join("missing")
+                        .to_string_lossy()
+                        .to_string(),
+                ),
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+        assert!(missing.is_local());
+        assert!(missing.load(false).is_err());
+    }
+
+    #[test]
+    fn test_cached_hub_model() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = ModelSource::from_config(
+            &ModelConfig {
+                cache_dir: Some(temp_dir.path().to_string_lossy().to_string()),
+                ..Default::default()
+            },
+            None,
+        )
+        .unwrap();
+        assert_eq!(source.name, MODEL_NAME);
+        assert!(!source.is_local());
+        assert_eq!(source.local_dir(), None);
+
+        let err = source.load(true).err().unwrap().to_string();
+        assert!(err.contains("not cached"));
+
+        // Same layout hf-hub uses: refs/main -> snapshots/<commit>/<file>
+        let repo_dir = temp_dir
+            .path()
+            .join(format!("models--{}", MODEL_NAME.replace('/', "--")));
+        fs::create_dir_all(repo_dir.join("refs")).unwrap();
+        fs::write(repo_dir.join("refs/main"), "abc123").unwrap();
+        write_model_files(&repo_dir.join("snapshots/abc123"));
+
+        assert_eq!(source.local_dir(), Some(repo_dir.join("snapshots/abc123")));
+    }
+
+    #[test]
+    fn test_embed_preset_resolution() {
+        assert_eq!(EmbedPreset::default().model_name(), MODEL_NAME);
+        assert_eq!("Fast".parse::<EmbedPreset>().unwrap(), EmbedPreset::Fast);
+        assert!("tiny".parse::<EmbedPreset>().is_err());
+
+        let config = ModelConfig {
+            name: Some("org/custom-model".to_string()),
+            preset: Some("balanced".to_string()),
+            cache_dir: None,
+            device: None,
+            normalize: Default::default(),
+        };
+        // The config name wins over the config preset, but not over an explicit preset
+        let source = ModelSource::from_config(&config, None).unwrap();
+        assert_eq!(source.name, "org/custom-model");
+        let source = ModelSource::from_config(&config, Some(EmbedPreset::Fast)).unwrap();
+        assert_eq!(source.name, EmbedPreset::Fast.model_name());
+
+        let config = ModelConfig {
+            preset: Some("balanced".to_string()),
+            ..Default::default()
+        };
+        let source = ModelSource::from_config(&config, None).unwrap();
+        assert_eq!(source.name, EmbedPreset::Balanced.model_name());
+    }
+}
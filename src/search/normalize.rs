@@ -0,0 +1,184 @@
+//! Text normalization before embedding (`normalize` in the `model` section of the config file).
+//! OCR'd PDFs often spell words differently from how a query does: with ligatures ("ﬁnance"),
+//! typographic quotes and dashes, decomposed accents, or words hyphenated across lines. The same
+//! normalization applies to queries, to documents searched in memory and to documents indexed in
+//! a workspace, which are embedded again when it changes.
+//!
+//! Everything is off by default. Only the embedded text changes: results show lines as the files
+//! have them.
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::OnceLock;
+use unicode_normalization::UnicodeNormalization;
+
+/// Normalization of this process, set when the embedding model is loaded
+static NORMALIZATION: OnceLock<Normalization> = OnceLock::new();
+
+/// Steps applied to text before it is embedded, in the order listed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Normalization {
+    /// Compose characters to Unicode NFC, so "é" is one character even where the source wrote
+    /// "e" and a combining accent
+    #[serde(default)]
+    pub nfc: bool,
+    /// Replace ligatures ("ﬁ"), typographic quotes, dashes, ellipses and non-breaking spaces with
+    /// their plain forms
+    #[serde(default)]
+    pub punctuation: bool,
+    /// Join words hyphenated at line breaks: a line ending in "infor-" is embedded as ending in
+    /// "information" when the next line starts with "mation". Soft hyphens are dropped
+    #[serde(default)]
+    pub dehyphenate: bool,
+    /// Lowercase all text, as `--ignore-case` does for one search
+    #[serde(default)]
+    pub lowercase: bool,
+}
+
+impl Normalization {
+    pub fn is_off(&self) -> bool {
+        *self == Normalization::default()
+    }
+
+    /// `text` normalized on its own, as a query is
+    pub fn apply(&self, text: &str) -> String {
+        let mut text = if self.nfc {
+            text.nfc().collect()
+        } else {
+            text.to_string()
+        };
+        if self.punctuation {
+            text = plain_punctuation(&text);
+        }
+        if self.dehyphenate {
+            text = text.replace('\u{00AD}', "");
+        }
+        if self.lowercase {
+            text = text.to_lowercase();
+        }
+        text
+    }
+
+    /// Consecutive lines of a document normalized, completing the words hyphenated at the end
+    /// of a line from the line after it
+    pub fn apply_lines(&self, lines: &[String]) -> Vec<String> {
+        let mut normalized: Vec<String> = lines.iter().map(|line| self.apply(line)).collect();
+        if self.dehyphenate {
+            for i in 1..normalized.len() {
+                // Checked on the line as written, since lowercasing would make it always hold
+                if !starts_lowercase(&lines[i]) {
+                    continue;
+                }
+                if let Some(rest) = word_continuation(&normalized[i - 1], &normalized[i]) {
+                    let line = &mut normalized[i - 1];
+                    line.pop();
+                    line.push_str(&rest);
+                }
+            }
+        }
+        normalized
+    }
+}
+
+/// Use `normalization` for this process; the first call wins
+pub fn set_normalization(normalization: Normalization) {
+    let _ = NORMALIZATION.set(normalization);
+}
+
+pub fn normalization() -> Normalization {
+    NORMALIZATION.get().copied().unwrap_or_default()
+}
+
+/// `text` with this process's normalization
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    let normalization = normalization();
+    if normalization.is_off() {
+        Cow::Borrowed(text)
+    } else {
+        Cow::Owned(normalization.apply(text))
+    }
+}
+
+/// Consecutive lines of a document with this process's normalization
+pub fn normalize_lines(lines: &[String]) -> Cow<'_, [String]> {
+    let normalization = normalization();
+    if normalization.is_off() {
+        Cow::Borrowed(lines)
+    } else {
+        Cow::Owned(normalization.apply_lines(lines))
+    }
+}
+
+fn starts_lowercase(line: &str) -> bool {
+    line.trim_start()
+        .chars()
+        .next()
+        .is_some_and(char::is_lowercase)
+}
+
+/// The start of `next` that completes a word broken by a hyphen at the end of `line`, which
+/// ends in a letter and a hyphen
+fn word_continuation(line: &str, next: &str) -> Option<String> {
+    let mut tail = line.strip_suffix('-')?.chars().rev();
+    if !tail.next().is_some_and(char::is_alphabetic) {
+        return None;
+    }
+    let rest: String = next
+        .trim_start()
+        .chars()
+        .take_while(|c| c.is_alphabetic())
+        .collect();
+    (!rest.is_empty()).then_some(rest)
+}
+
+fn plain_punctuation(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => plain.push('\''),
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' | '«' | '»' => {
+                plain.push('"')
+            }
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => plain.push('-'),
+            '\u{00A0}' | '\u{2007}' | '\u{2009}' | '\u{200A}' | '\u{202F}' => plain.push(' '),
+            '\u{2026}' => plain.push_str("..."),
+            'ﬀ' => plain.push_str("ff"),
+            'ﬁ' => plain.push_str("fi"),
+            'ﬂ' => plain.push_str("fl"),
+            'ﬃ' => plain.push_str("ffi"),
+            'ﬄ' => plain.push_str("ffl"),
+            'ﬅ' | 'ﬆ' => plain.push_str("st"),
+            c => plain.push(c),
+        }
+    }
+    plain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize() {
+        let all = Normalization {
+            nfc: true,
+            punctuation: true,
+            dehyphenate: true,
+            lowercase: true,
+        };
+        assert_eq!(
+            all.apply("Cafe\u{0301} “ﬁnance” report—2024\u{00A0}Q3 co\u{00AD}operate"),
+            "café \"finance\" report-2024 q3 cooperate"
+        );
+        assert_eq!(Normalization::default().apply("“ﬁ”"), "“ﬁ”");
+
+        let lines: Vec<String> = ["The infor-", "mation age.", "Pre-", "War era", "x -"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(
+            all.apply_lines(&lines),
+            ["the information", "mation age.", "pre-", "war era", "x -"]
+        );
+    }
+}
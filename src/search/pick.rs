@@ -0,0 +1,182 @@
+//! Ranking for `semtools pick`: candidate lines ordered by a mix of trigram fuzzy matching and
+//! embedding distance to the query.
+//!
+//! The fuzzy score catches typos and fragments of the line ("confg mod" for
+//! `src/config/mod.rs`), the embedding distance lines that mean the same in other words. Both
+//! are scaled to [0, 1] with lower being better, so they are blended linearly.
+use model2vec_rs::model::StaticModel;
+use std::collections::HashSet;
+
+use crate::ranking::cosine_distance;
+use crate::search::embed_lines;
+
+/// Weight of the fuzzy score in the blend unless `--lexical-weight` is given
+pub const DEFAULT_LEXICAL_WEIGHT: f64 = 0.5;
+
+type Trigram = [char; 3];
+
+/// Lowercased character trigrams of each word, padded like PostgreSQL's `pg_trgm` so that
+/// short words and word starts get trigrams of their own
+fn trigrams(text: &str) -> HashSet<Trigram> {
+    let mut trigrams = HashSet::new();
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+    {
+        let chars: Vec<char> = ["  ", &word.to_lowercase(), " "].concat().chars().collect();
+        trigrams.extend(chars.windows(3).map(|w| [w[0], w[1], w[2]]));
+    }
+    trigrams
+}
+
+/// Share of the query's trigrams found in the candidate: 1.0 when every fragment typed occurs
+/// in it, however long the candidate is
+fn fuzzy_similarity(query: &HashSet<Trigram>, candidate: &HashSet<Trigram>) -> f64 {
+    if query.is_empty() {
+        return 0.0;
+    }
+    query.intersection(candidate).count() as f64 / query.len() as f64
+}
+
+/// A candidate line's place in the ranking
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickMatch {
+    /// Index of the line in the input
+    pub index: usize,
+    /// Blended score (0 is best)
+    pub score: f64,
+    /// Share of the query's trigrams found in the line
+    pub fuzzy: f64,
+    /// Cosine distance between the line and the query embeddings
+    pub distance: f64,
+}
+
+/// Candidate lines with their embeddings and trigrams, computed once for every query typed
+pub struct PickIndex {
+    lines: Vec<String>,
+    embeddings: Vec<Vec<f32>>,
+    trigrams: Vec<HashSet<Trigram>>,
+}
+
+impl PickIndex {
+    pub fn new(lines: Vec<String>, model: &StaticModel) -> Self {
+        let embeddings = embed_lines(model, &lines);
+        Self::from_embeddings(lines, embeddings)
+    }
+
+    pub fn from_embeddings(lines: Vec<String>, embeddings: Vec<Vec<f32>>) -> Self {
+        let trigrams = lines.iter().map(|line| trigrams(line)).collect();
+        Self {
+            lines,
+            embeddings,
+            trigrams,
+        }
+    }
+
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Every line, best match first. `lexical_weight` is the weight of the fuzzy score (0.0
+    /// ranks by meaning only, 1.0 by spelling only). An empty query keeps the input order.
+    pub fn rank(
+        &self,
+        query: &str,
+        query_embedding: &[f32],
+        lexical_weight: f64,
+    ) -> Vec<PickMatch> {
+        if query.trim().is_empty() {
+            return (0..self.lines.len())
+                .map(|index| PickMatch {
+                    index,
+                    score: 0.0,
+                    fuzzy: 0.0,
+                    distance: 0.0,
+                })
+                .collect();
+        }
+        let query_trigrams = trigrams(query);
+        let mut matches: Vec<PickMatch> = self
+            .embeddings
+            .iter()
+            .zip(&self.trigrams)
+            .enumerate()
+            .map(|(index, (embedding, trigrams))| {
+                let fuzzy = fuzzy_similarity(&query_trigrams, trigrams);
+                // Cosine distance is in [0, 2]; halve it to weigh it like the fuzzy score
+                let distance = cosine_distance(embedding, query_embedding).unwrap_or(2.0);
+                let score =
+                    lexical_weight * (1.0 - fuzzy) + (1.0 - lexical_weight) * distance / 2.0;
+                PickMatch {
+                    index,
+                    score,
+                    fuzzy,
+                    distance,
+                }
+            })
+            .collect();
+        // Stable, so equally good lines keep their input order
+        matches.sort_by(|a, b| a.score.total_cmp(&b.score));
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_similarity() {
+        let candidate = trigrams("src/config/mod.rs");
+        assert_eq!(fuzzy_similarity(&trigrams("config"), &candidate), 1.0);
+        assert_eq!(fuzzy_similarity(&trigrams("CONFIG mod"), &candidate), 1.0);
+        let partial = fuzzy_similarity(&trigrams("confog"), &candidate);
+        assert!(partial > 0.0 && partial < 1.0);
+        assert_eq!(fuzzy_similarity(&trigrams("xyz"), &candidate), 0.0);
+        assert_eq!(fuzzy_similarity(&trigrams(""), &candidate), 0.0);
+    }
+
+    #[test]
+    fn test_rank_blends_fuzzy_and_semantic() {
+        let index = PickIndex::from_embeddings(
+            vec![
+                "error: disk full".to_string(),
+                "warning: low space on volume".to_string(),
+                "info: started".to_string(),
+            ],
+            vec![vec![0.0, 1.0], vec![0.1, 1.0], vec![1.0, 0.0]],
+        );
+        let query_embedding = [0.1, 1.0];
+
+        let order = |weight| -> Vec<usize> {
+            index
+                .rank("disk", &query_embedding, weight)
+                .iter()
+                .map(|m| m.index)
+                .collect()
+        };
+        // Spelling alone finds the line with "disk", meaning alone the one about space
+        assert_eq!(order(1.0)[0], 0);
+        assert_eq!(order(0.0)[0], 1);
+        assert_eq!(order(0.0)[2], 2);
+
+        let first = &index.rank("disk", &query_embedding, 0.5)[0];
+        assert_eq!(first.index, 0);
+        assert_eq!(first.fuzzy, 1.0);
+
+        let unranked: Vec<usize> = index
+            .rank("  ", &query_embedding, 0.5)
+            .iter()
+            .map(|m| m.index)
+            .collect();
+        assert_eq!(unranked, vec![0, 1, 2]);
+    }
+}
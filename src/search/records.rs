@@ -0,0 +1,266 @@
+//! Structured inputs for search: JSON Lines and CSV files, where each record (not each line)
+//! is embedded and matched, and Jupyter notebooks, matched cell by cell
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value, json};
+
+use crate::notebook;
+use crate::search::error::SearchError;
+
+/// How search inputs are split into units to embed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Every line of text
+    #[default]
+    Lines,
+    /// One JSON object per line
+    Jsonl,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Jupyter notebook, one record per cell
+    Ipynb,
+}
+
+impl InputFormat {
+    /// The format to search `files` in when none is given: notebooks by cell, anything
+    /// else by line
+    pub fn detect(files: &[String]) -> Self {
+        if !files.is_empty() && files.iter().all(|f| notebook::is_notebook(f)) {
+            InputFormat::Ipynb
+        } else {
+            InputFormat::Lines
+        }
+    }
+
+    /// What a record is called in results
+    pub fn record_name(&self) -> &'static str {
+        match self {
+            InputFormat::Ipynb => "cell",
+            _ => "record",
+        }
+    }
+}
+
+impl std::str::FromStr for InputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lines" | "text" => Ok(InputFormat::Lines),
+            "jsonl" | "ndjson" => Ok(InputFormat::Jsonl),
+            "csv" => Ok(InputFormat::Csv),
+            "ipynb" | "notebook" => Ok(InputFormat::Ipynb),
+            _ => Err(SearchError::InvalidArgument(format!(
+                "Invalid input format: '{s}'. Must be 'lines', 'jsonl', 'csv' or 'ipynb'"
+            ))
+            .into()),
+        }
+    }
+}
+
+/// A record of a structured input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    /// 0-based position among the input's records (blank JSONL lines don't count)
+    pub index: usize,
+    /// Text that is embedded: the selected fields, joined by spaces
+    pub text: String,
+    /// The whole record; CSV rows become objects keyed by the header
+    pub fields: Value,
+}
+
+/// Split `content` into records, embedding the `text_fields` of each (dotted paths such as
+/// `message.body` reach into nested JSON). Without `text_fields` every column, or every
+/// top-level JSON value, is used. Records with none of the fields are skipped.
+pub fn parse_records(
+    content: &str,
+    format: InputFormat,
+    text_fields: &[String],
+) -> Result<Vec<Record>> {
+    let (rows, columns) = match format {
+        InputFormat::Lines => bail!("Plain text inputs have no records"),
+        InputFormat::Jsonl => (parse_jsonl(content)?, Vec::new()),
+        InputFormat::Csv => parse_csv(content)?,
+        InputFormat::Ipynb => (parse_notebook(content)?, vec!["source".to_string()]),
+    };
+    // CSV columns default to header order rather than the (sorted) order of the JSON object
+    let text_fields = if text_fields.is_empty() {
+        &columns
+    } else {
+        text_fields
+    };
+
+    Ok(rows
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, fields)| {
+            let text = record_text(&fields, text_fields)?;
+            Some(Record {
+                index,
+                text,
+                fields,
+            })
+        })
+        .collect())
+}
+
+fn parse_jsonl(content: &str) -> Result<Vec<Value>> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(line_number, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Invalid JSON on line {}", line_number + 1))
+        })
+        .collect()
+}
+
+/// Cells as `{"cell_type", "source"}` objects, empty ones included so indices match the
+/// notebook's
+fn parse_notebook(content: &str) -> Result<Vec<Value>> {
+    Ok(notebook::cells(content)?
+        .into_iter()
+        .map(|cell| json!({"cell_type": cell.cell_type, "source": cell.source}))
+        .collect())
+}
+
+/// Rows as JSON objects, with the column names
+fn parse_csv(content: &str) -> Result<(Vec<Value>, Vec<String>)> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let headers = reader.headers().context("Invalid CSV header")?.clone();
+
+    let rows = reader
+        .records()
+        .map(|row| {
+            let row = row.context("Invalid CSV row")?;
+            let fields: Map<String, Value> = headers
+                .iter()
+                .zip(row.iter())
+                .map(|(header, value)| (header.to_string(), Value::String(value.to_string())))
+                .collect();
+            Ok(Value::Object(fields))
+        })
+        .collect::<Result<_>>()?;
+    Ok((rows, headers.iter().map(str::to_string).collect()))
+}
+
+/// The text to embed for a record, or `None` when it has none of the fields
+fn record_text(fields: &Value, text_fields: &[String]) -> Option<String> {
+    let parts: Vec<String> = if text_fields.is_empty() {
+        fields
+            .as_object()?
+            .values()
+            .filter_map(scalar_text)
+            .collect()
+    } else {
+        text_fields
+            .iter()
+            .filter_map(|path| lookup(fields, path))
+            .filter_map(scalar_text)
+            .collect()
+    };
+    let text = parts.join(" ");
+    (!text.trim().is_empty()).then_some(text)
+}
+
+/// The text of the field at `path` of a record (a dotted path reaches into nested JSON), or
+/// `None` when it is missing or blank
+pub fn field_text(fields: &Value, path: &str) -> Option<String> {
+    lookup(fields, path)
+        .and_then(scalar_text)
+        .filter(|text| !text.trim().is_empty())
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    // An exact key wins, so keys containing dots still work
+    if let Some(field) = value.get(path) {
+        return Some(field);
+    }
+    path.split('.').try_fold(value, |value, key| value.get(key))
+}
+
+fn scalar_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Null => None,
+        // Nested values are embedded as compact JSON
+        other => Some(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_jsonl_records() {
+        let content = r#"{"id": 1, "body": "printer on fire", "meta": {"team": "it"}}
+
+{"id": 2, "title": "no body here"}
+{"id": 3, "body": "password reset", "meta": {"team": "security"}}
+"#;
+        let records = parse_records(
+            content,
+            InputFormat::Jsonl,
+            &["body".to_string(), "meta.team".to_string()],
+        )
+        .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].index, 0);
+        assert_eq!(records[0].text, "printer on fire it");
+        // The blank line is not a record; the record without the fields is skipped
+        assert_eq!(records[1].index, 2);
+        assert_eq!(records[1].fields["id"], json!(3));
+
+        let err = parse_records("{not json}", InputFormat::Jsonl, &[]).unwrap_err();
+        assert!(err.to_string().contains("line 1"));
+    }
+
+    #[test]
+    fn test_parse_csv_records() {
+        let content =
+            "id,subject,body\n1,Login,\"Can't log in,\nafter reset\"\n2,Billing,Invoice twice\n";
+        let records = parse_records(content, InputFormat::Csv, &["body".to_string()]).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].text, "Can't log in,\nafter reset");
+        assert_eq!(records[1].fields["subject"], json!("Billing"));
+
+        // Without fields, every column is embedded
+        let records = parse_records(content, InputFormat::Csv, &[]).unwrap();
+        assert_eq!(records[1].text, "2 Billing Invoice twice");
+    }
+
+    #[test]
+    fn test_input_format_from_str() {
+        assert_eq!("JSONL".parse::<InputFormat>().unwrap(), InputFormat::Jsonl);
+        assert_eq!("csv".parse::<InputFormat>().unwrap(), InputFormat::Csv);
+        assert!("xml".parse::<InputFormat>().is_err());
+    }
+
+    #[test]
+    fn test_parse_notebook_records() {
+        let notebook = r##"{"cells": [
+            {"cell_type": "markdown", "source": ["# Churn\n", "By plan"]},
+            {"cell_type": "code", "source": []},
+            {"cell_type": "code", "source": "df.churn.mean()"}
+        ]}"##;
+        let records = parse_records(notebook, InputFormat::Ipynb, &[]).unwrap();
+        // The empty cell is skipped but still counted
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].text, "# Churn\nBy plan");
+        assert_eq!(records[1].index, 2);
+        assert_eq!(records[1].fields["cell_type"], json!("code"));
+
+        let notebooks = vec!["a.ipynb".to_string(), "b.IPYNB".to_string()];
+        assert_eq!(InputFormat::detect(&notebooks), InputFormat::Ipynb);
+        assert_eq!(
+            InputFormat::detect(&["a.ipynb".to_string(), "b.md".to_string()]),
+            InputFormat::Lines
+        );
+        assert_eq!(InputFormat::detect(&[]), InputFormat::Lines);
+    }
+}
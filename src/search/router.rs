@@ -0,0 +1,369 @@
+//! Query routing for `search --auto`: queries that name something exactly (identifiers,
+//! quoted strings, regular expressions) are matched lexically like `grep`, plain questions are
+//! searched by meaning, and queries mixing both get both, fused into one ranking.
+//!
+//! The router is a handful of cheap heuristics on the query's tokens, so choosing costs
+//! nothing next to loading the model.
+use ::grep::matcher::Matcher;
+use ::grep::regex::RegexMatcher;
+use std::collections::HashMap;
+
+use crate::grep::GrepBlock;
+use crate::ranking::SearchResult;
+
+/// Constant of reciprocal rank fusion: higher values flatten the difference between ranks
+const RRF_K: f64 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    /// Embed and rank by meaning, as `search` does without `--auto`
+    Semantic,
+    /// Scan lines for the pattern only; the model is not loaded
+    Lexical,
+    /// Both, fused with reciprocal rank fusion
+    Hybrid,
+}
+
+impl Route {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Route::Semantic => "semantic",
+            Route::Lexical => "lexical",
+            Route::Hybrid => "hybrid",
+        }
+    }
+}
+
+/// How `--auto` searches a query, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteDecision {
+    pub route: Route,
+    /// Pattern matched by the lexical scan (empty for semantic searches)
+    pub pattern: String,
+    /// Whether `pattern` is a literal string rather than a regular expression
+    pub fixed_strings: bool,
+    pub reason: &'static str,
+}
+
+impl RouteDecision {
+    fn semantic(reason: &'static str) -> Self {
+        Self {
+            route: Route::Semantic,
+            pattern: String::new(),
+            fixed_strings: true,
+            reason,
+        }
+    }
+
+    /// One line for `--explain`
+    pub fn describe(&self) -> String {
+        match self.route {
+            Route::Semantic => format!("semantic ({})", self.reason),
+            route => format!("{} on `{}` ({})", route.as_str(), self.pattern, self.reason),
+        }
+    }
+}
+
+/// Characters that only occur in a query written as a regular expression
+fn looks_like_regex(query: &str) -> bool {
+    const MARKERS: [&str; 12] = [
+        ".*", ".+", "\\b", "\\d", "\\w", "\\s", "(?", "[a-", "[0-", "|", "{1", "{2",
+    ];
+    (query.starts_with('^') || query.ends_with('$') || MARKERS.iter().any(|m| query.contains(m)))
+        && RegexMatcher::new_line_matcher(query).is_ok()
+}
+
+/// Whether a token names something exactly: `camelCase`, `PascalCase`, `snake_case`,
+/// `SCREAMING_CASE`, paths like `a::b`, `a.b` or `a/b.rs`, calls like `f()`, and codes mixing
+/// letters and digits such as `E0425`
+fn is_identifier(token: &str) -> bool {
+    let chars: Vec<char> = token.chars().collect();
+    if chars.len() < 2 || !chars.iter().any(|c| c.is_alphabetic()) {
+        return false;
+    }
+    let has_inner = |c: char| {
+        chars
+            .iter()
+            .enumerate()
+            .any(|(i, &x)| x == c && i > 0 && i + 1 < chars.len())
+    };
+    let humps = chars
+        .windows(2)
+        .filter(|w| w[0].is_lowercase() && w[1].is_uppercase())
+        .count();
+    let mixes_digits = chars.iter().any(|c| c.is_ascii_digit())
+        && chars.iter().all(|c| c.is_alphanumeric())
+        && chars.iter().any(|c| c.is_uppercase());
+    // Dotted names, but not abbreviations such as "e.g"
+    let dotted = has_inner('.') && token.split('.').all(|part| part.chars().count() >= 2);
+    // Paths, but not "and/or"
+    let path = has_inner('/') && (token.contains('.') || token.matches('/').count() >= 2);
+    humps > 0
+        || has_inner('_')
+        || token.contains("::")
+        || token.contains("->")
+        || token.ends_with("()")
+        || dotted
+        || path
+        || mixes_digits
+}
+
+/// `token` without the punctuation of the sentence around it. The parentheses of a call such
+/// as `init()` are kept.
+fn trim_token(token: &str) -> &str {
+    let mut token = token
+        .trim_start_matches(['(', '[', ',', ';'])
+        .trim_end_matches(['?', '!', ',', ';', ':', '.']);
+    while token.ends_with(')') && !token.ends_with("()") {
+        token = &token[..token.len() - 1];
+    }
+    token.trim_end_matches(['?', '!', ',', ';', ':', '.', ']'])
+}
+
+/// `text` with the regex metacharacters escaped
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$#&-~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Decide how to search `query`
+pub fn route(query: &str) -> RouteDecision {
+    let query = query.trim();
+    for quote in ['"', '\'', '`'] {
+        if query.len() > 2
+            && query.starts_with(quote)
+            && query.ends_with(quote)
+            && !query[1..query.len() - 1].contains(quote)
+        {
+            return RouteDecision {
+                route: Route::Lexical,
+                pattern: query[1..query.len() - 1].to_string(),
+                fixed_strings: true,
+                reason: "the query is quoted",
+            };
+        }
+    }
+    if looks_like_regex(query) {
+        return RouteDecision {
+            route: Route::Lexical,
+            pattern: query.to_string(),
+            fixed_strings: false,
+            reason: "the query looks like a regular expression",
+        };
+    }
+
+    let tokens: Vec<&str> = query
+        .split_whitespace()
+        .map(trim_token)
+        .filter(|t| !t.is_empty())
+        .collect();
+    let identifiers: Vec<&str> = tokens
+        .iter()
+        .copied()
+        .filter(|t| is_identifier(t))
+        .collect();
+    if identifiers.is_empty() {
+        return RouteDecision::semantic("the query is plain words");
+    }
+    let (route, reason) = if identifiers.len() == tokens.len() {
+        (Route::Lexical, "every word of the query is an identifier")
+    } else {
+        (Route::Hybrid, "the query mixes words and identifiers")
+    };
+    let (pattern, fixed_strings) = match identifiers.as_slice() {
+        [identifier] => (identifier.to_string(), true),
+        _ => (
+            identifiers
+                .iter()
+                .map(|i| escape(i))
+                .collect::<Vec<_>>()
+                .join("|"),
+            false,
+        ),
+    };
+    RouteDecision {
+        route,
+        pattern,
+        fixed_strings,
+        reason,
+    }
+}
+
+/// One result per matching line of `block`, with up to `n_lines` of context on each side,
+/// and the span of the first match within the line
+pub fn block_results(
+    block: &GrepBlock,
+    matcher: &RegexMatcher,
+    n_lines: usize,
+) -> Vec<SearchResult> {
+    block
+        .matches
+        .iter()
+        .map(|&match_line| {
+            let start = match_line.saturating_sub(n_lines).max(block.start);
+            let end = (match_line + n_lines + 1).min(block.end());
+            let line = &block.lines[match_line - block.start];
+            let (match_start_col, match_end_col) = matcher
+                .find(line.as_bytes())
+                .ok()
+                .flatten()
+                .map_or((0, 0), |m| (m.start(), m.end()));
+            SearchResult {
+                filename: block.file.clone(),
+                lines: block.lines[start - block.start..end - block.start].to_vec(),
+                start,
+                end,
+                match_line,
+                distance: 0.0,
+                score: 0.0,
+                match_start_col,
+                match_end_col,
+            }
+        })
+        .collect()
+}
+
+/// The lines of an in-memory document (such as stdin) matching `matcher`, as one block
+pub fn lines_block(file: &str, lines: &[String], matcher: &RegexMatcher) -> GrepBlock {
+    let matches = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| matcher.is_match(line.as_bytes()).unwrap_or(false))
+        .map(|(i, _)| i)
+        .collect();
+    GrepBlock {
+        file: file.to_string(),
+        start: 0,
+        lines: lines.to_vec(),
+        matches,
+    }
+}
+
+/// Merge semantic and lexical results with reciprocal rank fusion, keeping the best
+/// `top_k`. A line found by both is listed once, with the semantic result's context.
+pub fn fuse(
+    semantic: Vec<SearchResult>,
+    lexical: Vec<SearchResult>,
+    top_k: usize,
+) -> Vec<SearchResult> {
+    let mut fused: Vec<(f64, SearchResult)> = Vec::new();
+    let mut positions: HashMap<(String, usize), usize> = HashMap::new();
+    for results in [semantic, lexical] {
+        for (rank, result) in results.into_iter().enumerate() {
+            let rrf = 1.0 / (RRF_K + rank as f64 + 1.0);
+            let key = (result.filename.clone(), result.match_line);
+            match positions.get(&key) {
+                Some(&i) => fused[i].0 += rrf,
+                None => {
+                    positions.insert(key, fused.len());
+                    fused.push((rrf, result));
+                }
+            }
+        }
+    }
+    // Stable, so ties keep semantic results first
+    fused.sort_by(|a, b| b.0.total_cmp(&a.0));
+    fused
+        .into_iter()
+        .take(top_k)
+        .map(|(_, result)| result)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route() {
+        let plain = route("how are refunds handled?");
+        assert_eq!(plain.route, Route::Semantic);
+
+        let identifier = route("parseConfig");
+        assert_eq!(identifier.route, Route::Lexical);
+        assert_eq!(identifier.pattern, "parseConfig");
+        assert!(identifier.fixed_strings);
+        for query in [
+            "MAX_RETRIES",
+            "std::fs::read",
+            "src/main.rs",
+            "E0425",
+            "init()",
+        ] {
+            assert_eq!(route(query).route, Route::Lexical, "{query}");
+        }
+
+        let quoted = route("\"connection reset by peer\"");
+        assert_eq!(quoted.route, Route::Lexical);
+        assert_eq!(quoted.pattern, "connection reset by peer");
+
+        let regex = route(r"timeout \d+ms");
+        assert_eq!(regex.route, Route::Lexical);
+        assert!(!regex.fixed_strings);
+
+        let mixed = route("where is load_config called?");
+        assert_eq!(mixed.route, Route::Hybrid);
+        assert_eq!(mixed.pattern, "load_config");
+        let two = route("why do parseConfig and load_config differ");
+        assert_eq!(two.pattern, "parseConfig|load_config");
+        assert!(!two.fixed_strings);
+
+        // Sentence punctuation and abbreviations are not identifiers
+        assert_eq!(route("what is e.g. a refund.").route, Route::Semantic);
+        assert_eq!(route("cash and/or card (or both)").route, Route::Semantic);
+        assert_eq!(route("(see parseConfig)").pattern, "parseConfig");
+    }
+
+    fn result(file: &str, line: usize) -> SearchResult {
+        SearchResult {
+            filename: file.to_string(),
+            lines: vec![String::new()],
+            start: line,
+            end: line + 1,
+            match_line: line,
+            distance: 0.0,
+            score: 0.0,
+            match_start_col: 0,
+            match_end_col: 0,
+        }
+    }
+
+    #[test]
+    fn test_fuse() {
+        let semantic = vec![result("a", 1), result("a", 2), result("b", 3)];
+        let lexical = vec![result("b", 3), result("c", 4)];
+        let fused = fuse(semantic, lexical, 3);
+        let keys: Vec<(&str, usize)> = fused
+            .iter()
+            .map(|r| (r.filename.as_str(), r.match_line))
+            .collect();
+        // Found by both ranks first; ties keep the semantic order
+        assert_eq!(keys, vec![("b", 3), ("a", 1), ("a", 2)]);
+    }
+
+    #[test]
+    fn test_block_results() {
+        let lines: Vec<String> = ["a", "let x = parseConfig();", "b", "c", "parseConfig"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let matcher = RegexMatcher::new_line_matcher("parseConfig").unwrap();
+        let block = lines_block("<stdin>", &lines, &matcher);
+        assert_eq!(block.matches, vec![1, 4]);
+
+        let results = block_results(&block, &matcher, 1);
+        assert_eq!(results.len(), 2);
+        assert_eq!((results[0].start, results[0].end), (0, 3));
+        assert_eq!(
+            (results[0].match_start_col, results[0].match_end_col),
+            (8, 19)
+        );
+        assert_eq!((results[1].start, results[1].end), (3, 5));
+    }
+}
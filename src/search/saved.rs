@@ -0,0 +1,187 @@
+//! Saved searches (`semtools saved`), stored in ~/.local/share/semtools/saved_searches.json
+//! and shared through the `saved_searches` section of the config file
+use anyhow::{Context, Result, bail};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config::{SavedSearch, SemtoolsConfig};
+
+const SAVED_SEARCHES_FILE: &str = "saved_searches.json";
+
+/// Where a saved search is defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SavedSource {
+    /// ~/.local/share/semtools/saved_searches.json, written by `saved add`
+    Local,
+    /// The config file's `saved_searches` section
+    Config,
+}
+
+impl SavedSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SavedSource::Local => "local",
+            SavedSource::Config => "config",
+        }
+    }
+}
+
+/// Searches saved with `saved add`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SavedSearches {
+    pub searches: BTreeMap<String, SavedSearch>,
+    path: PathBuf,
+}
+
+impl SavedSearches {
+    pub fn default_path() -> Result<PathBuf> {
+        crate::paths::data_path(SAVED_SEARCHES_FILE)
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::load_from(Self::default_path()?)
+    }
+
+    pub fn load_from(path: PathBuf) -> Result<Self> {
+        let searches = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Ok(Self { searches, path })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_string_pretty(&self.searches)?)
+            .with_context(|| format!("Failed to write {}", self.path.display()))
+    }
+
+    /// Save `search` as `name`, replacing any search of that name
+    pub fn add(&mut self, name: &str, search: SavedSearch) -> Result<()> {
+        validate_name(name)?;
+        self.searches.insert(name.to_string(), search);
+        Ok(())
+    }
+
+    /// Remove `name`, returning whether it existed
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.searches.remove(name).is_some()
+    }
+
+    /// Every saved search by name, local ones shadowing those from `config`
+    pub fn merged(&self, config: &SemtoolsConfig) -> BTreeMap<String, (SavedSearch, SavedSource)> {
+        let mut merged: BTreeMap<String, (SavedSearch, SavedSource)> = config
+            .saved_searches
+            .iter()
+            .map(|(name, search)| (name.clone(), (search.clone(), SavedSource::Config)))
+            .collect();
+        for (name, search) in &self.searches {
+            merged.insert(name.clone(), (search.clone(), SavedSource::Local));
+        }
+        merged
+    }
+
+    /// Look up `name`, locally first and then in `config`
+    pub fn find(&self, name: &str, config: &SemtoolsConfig) -> Result<SavedSearch> {
+        self.searches
+            .get(name)
+            .or_else(|| config.saved_searches.get(name))
+            .cloned()
+            .ok_or_else(|| {
+                anyhow::anyhow!("No saved search named '{name}' (see `semtools saved list`)")
+            })
+    }
+}
+
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.')
+    {
+        bail!("Invalid saved search name '{name}': use letters, digits, '-', '_' and '.'");
+    }
+    Ok(())
+}
+
+/// Command line for a saved search: `search <query> <saved args> <extra args>`. Later flags
+/// win for options that take one value, so `extra` can override saved thresholds.
+pub fn search_argv(search: &SavedSearch, extra: &[String]) -> Vec<String> {
+    let mut argv = vec!["search".to_string(), search.query.clone()];
+    argv.extend(search.args.iter().cloned());
+    argv.extend(extra.iter().cloned());
+    argv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn saved(query: &str, args: &[&str]) -> SavedSearch {
+        SavedSearch {
+            query: query.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            description: None,
+        }
+    }
+
+    #[test]
+    fn test_add_save_and_reload() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("nested").join(SAVED_SEARCHES_FILE);
+
+        let mut store = SavedSearches::load_from(path.clone()).unwrap();
+        assert!(store.searches.is_empty());
+        store
+            .add("pii", saved("PII retention", &["docs/", "-m", "0.3"]))
+            .unwrap();
+        assert!(store.add("bad name", saved("x", &[])).is_err());
+        store.save().unwrap();
+
+        let mut reloaded = SavedSearches::load_from(path).unwrap();
+        assert_eq!(reloaded, store);
+        assert!(reloaded.remove("pii"));
+        assert!(!reloaded.remove("pii"));
+    }
+
+    #[test]
+    fn test_local_searches_shadow_config() {
+        let mut config = SemtoolsConfig::default();
+        config
+            .saved_searches
+            .insert("pii".to_string(), saved("shared", &[]));
+        config
+            .saved_searches
+            .insert("secrets".to_string(), saved("api keys", &[]));
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut store = SavedSearches::load_from(temp_dir.path().join("s.json")).unwrap();
+        store.add("pii", saved("mine", &[])).unwrap();
+
+        assert_eq!(store.find("pii", &config).unwrap().query, "mine");
+        assert_eq!(store.find("secrets", &config).unwrap().query, "api keys");
+        assert!(store.find("missing", &config).is_err());
+
+        let merged = store.merged(&config);
+        assert_eq!(merged["pii"].1, SavedSource::Local);
+        assert_eq!(merged["secrets"].1, SavedSource::Config);
+    }
+
+    #[test]
+    fn test_search_argv() {
+        let argv = search_argv(
+            &saved("PII retention", &["docs/", "-m", "0.3"]),
+            &["--json".to_string()],
+        );
+        assert_eq!(
+            argv,
+            vec!["search", "PII retention", "docs/", "-m", "0.3", "--json"]
+        );
+    }
+}
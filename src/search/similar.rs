@@ -0,0 +1,197 @@
+//! Document-level similarity (`semtools similar`): a reference document is embedded as a
+//! whole and the documents of the corpus are ranked by their distance to it, for "find related
+//! papers/tickets" questions that are not really queries.
+//!
+//! A document's vector pools the embeddings of its lines: their mean (`centroid`, the vector
+//! workspaces use for first-stage retrieval), or the largest value of each dimension (`max`),
+//! which lets a topic covered in a single section of a long document count.
+use anyhow::{Context, Result};
+use model2vec_rs::model::StaticModel;
+use std::fs;
+
+use crate::archive;
+use crate::search::create_document_from_content;
+use crate::search::dedupe::document_embedding;
+
+#[cfg(feature = "workspace-lite")]
+use crate::search::explain::Explain;
+#[cfg(feature = "workspace-lite")]
+use crate::search::{
+    check_embedding_dimensions, embed_unindexed_documents, update_workspace_index,
+};
+#[cfg(feature = "workspace-lite")]
+use crate::workspace::Workspace;
+
+/// How the line embeddings of a document are pooled into one vector
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Pooling {
+    #[default]
+    Centroid,
+    Max,
+}
+
+impl std::str::FromStr for Pooling {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "centroid" | "mean" => Ok(Pooling::Centroid),
+            "max" => Ok(Pooling::Max),
+            _ => Err(anyhow::anyhow!(
+                "Invalid pooling: '{s}'. Must be 'centroid' or 'max'"
+            )),
+        }
+    }
+}
+
+impl Pooling {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Pooling::Centroid => "centroid",
+            Pooling::Max => "max",
+        }
+    }
+
+    /// One vector for a document with `line_embeddings`, or `None` for an empty document
+    pub fn pool(&self, line_embeddings: &[Vec<f32>]) -> Option<Vec<f32>> {
+        match self {
+            Pooling::Centroid => document_embedding(line_embeddings),
+            Pooling::Max => {
+                let (first, rest) = line_embeddings.split_first()?;
+                let mut max = first.clone();
+                for embedding in rest {
+                    for (acc, &value) in max.iter_mut().zip(embedding) {
+                        *acc = acc.max(value);
+                    }
+                }
+                Some(max)
+            }
+        }
+    }
+}
+
+/// A document of the corpus and its distance to the reference document
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimilarDocument {
+    pub path: String,
+    pub distance: f64,
+}
+
+/// `documents` ranked by cosine distance to `reference`, closest first and ties in path order.
+/// Documents whose vectors have another size are left out.
+pub fn rank_similar(reference: &[f32], documents: Vec<(String, Vec<f32>)>) -> Vec<SimilarDocument> {
+    let mut ranked: Vec<SimilarDocument> = documents
+        .into_iter()
+        .filter_map(|(path, vector)| {
+            let distance = crate::search::cosine_distance(reference, &vector)?;
+            Some(SimilarDocument { path, distance })
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        a.distance
+            .total_cmp(&b.distance)
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    ranked
+}
+
+/// The pooled vector of the file at `path`, embedded in memory. `None` if it has no text.
+pub fn file_vector(path: &str, model: &StaticModel, pooling: Pooling) -> Result<Option<Vec<f32>>> {
+    let bytes = fs::read(archive::local_path(path)).with_context(|| format!("Reading {path}"))?;
+    let content = String::from_utf8_lossy(&bytes);
+    Ok(
+        create_document_from_content(path.to_string(), &content, model, false)
+            .and_then(|doc| pooling.pool(&doc.embeddings)),
+    )
+}
+
+/// The pooled vectors of `files`, embedded in memory. Files without text are left out.
+pub fn document_vectors(
+    files: &[String],
+    model: &StaticModel,
+    pooling: Pooling,
+) -> Result<Vec<(String, Vec<f32>)>> {
+    let mut vectors = Vec::with_capacity(files.len());
+    for file in files {
+        if let Some(vector) = file_vector(file, model, pooling)? {
+            vectors.push((file.clone(), vector));
+        }
+    }
+    Ok(vectors)
+}
+
+/// The pooled vectors of `files` from the workspace index, which is brought up to date with
+/// them first. A read-only workspace serves the files it is up to date for, and the others are
+/// embedded in memory. Table rows stored with a document count as lines of it.
+#[cfg(feature = "workspace-lite")]
+pub fn workspace_document_vectors(
+    files: &[String],
+    model: &StaticModel,
+    pooling: Pooling,
+    dimensions: usize,
+    workspace_name: Option<&str>,
+) -> Result<Vec<(String, Vec<f32>)>> {
+    let ws = Workspace::open(workspace_name)?;
+    check_embedding_dimensions(&ws, dimensions)?;
+    let store = ws.open_store()?;
+    ws.mark_used();
+
+    let mut explain = Explain::default();
+    let (indexed, unindexed) = if ws.is_read_only() {
+        embed_unindexed_documents(&store, files, model, false, &mut explain)?
+    } else {
+        update_workspace_index(
+            &store,
+            files,
+            model,
+            false,
+            ws.config.store_text,
+            &mut explain,
+        )?;
+        (files.to_vec(), Vec::new())
+    };
+
+    let mut vectors = Vec::with_capacity(files.len());
+    for path in indexed {
+        if let Some(vector) = pooling.pool(&store.line_vectors(&path)?) {
+            vectors.push((path, vector));
+        }
+    }
+    for doc in unindexed {
+        if let Some(vector) = pooling.pool(&doc.embeddings) {
+            vectors.push((doc.filename, vector));
+        }
+    }
+    Ok(vectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pooling() {
+        let lines = vec![vec![1.0, 0.0, -1.0], vec![0.0, 2.0, -3.0]];
+        assert_eq!(Pooling::Centroid.pool(&lines), Some(vec![0.5, 1.0, -2.0]));
+        assert_eq!(Pooling::Max.pool(&lines), Some(vec![1.0, 2.0, -1.0]));
+        assert_eq!(Pooling::Max.pool(&[]), None);
+        assert_eq!("mean".parse::<Pooling>().unwrap(), Pooling::Centroid);
+        assert!("sum".parse::<Pooling>().is_err());
+    }
+
+    #[test]
+    fn test_rank_similar() {
+        let ranked = rank_similar(
+            &[1.0, 0.0],
+            vec![
+                ("b.md".to_string(), vec![0.0, 1.0]),
+                ("c.md".to_string(), vec![2.0, 0.1]),
+                ("a.md".to_string(), vec![0.0, 3.0]),
+                ("short.md".to_string(), vec![1.0]),
+            ],
+        );
+        let paths: Vec<&str> = ranked.iter().map(|doc| doc.path.as_str()).collect();
+        assert_eq!(paths, ["c.md", "a.md", "b.md"]);
+        assert!(ranked[0].distance < 0.01);
+    }
+}
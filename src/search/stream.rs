@@ -0,0 +1,291 @@
+//! Searching files too large to hold in memory. Lines are read and embedded in chunks, scored
+//! into the bounded top-k heap, and only the context of candidates still in the heap is kept.
+use anyhow::{Context, Result};
+use model2vec_rs::model::StaticModel;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::time::Instant;
+
+use crate::archive;
+use crate::search::calibration::{CALIBRATION_SAMPLE_LINES, Calibration};
+use crate::search::explain::{Explain, Stage};
+use crate::search::{
+    Candidate, MMR_OVERSAMPLE_FACTOR, SearchConfig, SearchResult, TopCandidates, apply_path_weight,
+    blend_recency, combined_distance, embed_lines, file_recency, mmr_select, negative_penalty,
+    path_weight,
+};
+
+/// Inputs at least this large in total are streamed instead of loaded whole
+pub const STREAMING_THRESHOLD_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Lines read and embedded at a time
+const STREAM_CHUNK_LINES: usize = 16_384;
+
+/// Whether `files` are large enough to stream
+pub fn should_stream(files: &[String]) -> bool {
+    let total: u64 = files
+        .iter()
+        .filter_map(|f| fs::metadata(archive::local_path(f)).ok())
+        .map(|m| m.len())
+        .sum();
+    total >= STREAMING_THRESHOLD_BYTES
+}
+
+/// Context collected for a candidate while it is in the heap
+struct MatchContext {
+    start: usize,
+    lines: Vec<String>,
+    /// Lines after the match still to be collected
+    remaining_after: usize,
+    /// The matched line's embedding, kept for MMR
+    embedding: Option<Vec<f32>>,
+}
+
+/// Every `stride`-th embedding, with the stride doubling whenever the sample gets too big, so
+/// the sample stays spread over the whole input without knowing its size up front
+struct StrideSample {
+    embeddings: Vec<Vec<f32>>,
+    stride: usize,
+    seen: usize,
+}
+
+impl StrideSample {
+    fn new() -> Self {
+        Self {
+            embeddings: Vec::new(),
+            stride: 1,
+            seen: 0,
+        }
+    }
+
+    fn offer(&mut self, embedding: &[f32]) {
+        if self.seen.is_multiple_of(self.stride) {
+            self.embeddings.push(embedding.to_vec());
+            if self.embeddings.len() >= 2 * CALIBRATION_SAMPLE_LINES {
+                let mut keep = false;
+                self.embeddings.retain(|_| {
+                    keep = !keep;
+                    keep
+                });
+                self.stride *= 2;
+            }
+        }
+        self.seen += 1;
+    }
+}
+
+/// Search `files` a chunk of lines at a time, the streaming counterpart of `load_documents` and
+/// `search_documents`. With `calibrate`, a sample of line embeddings is also calibrated for
+/// normalized scores.
+pub fn search_files_streaming(
+    files: &[String],
+    model: &StaticModel,
+    query_embedding: &[f32],
+    config: &SearchConfig,
+    calibrate: bool,
+    explain: &mut Explain,
+) -> Result<(Vec<SearchResult>, Option<Calibration>)> {
+    explain.filter(format!(
+        "inputs streamed in chunks of {STREAM_CHUNK_LINES} lines"
+    ));
+    let filenames: Vec<&str> = files.iter().map(String::as_str).collect();
+    let recency = file_recency(&filenames, config);
+    let weights: Vec<f64> = filenames
+        .iter()
+        .map(|name| path_weight(name, &config.boosts))
+        .collect();
+    let limit = match (config.max_distance, config.mmr_lambda) {
+        (Some(_), _) => None,
+        (None, Some(_)) => Some(config.top_k.saturating_mul(MMR_OVERSAMPLE_FACTOR)),
+        (None, None) => Some(config.top_k),
+    };
+
+    let mut candidates = TopCandidates::new(limit);
+    let mut contexts: HashMap<(usize, usize), MatchContext> = HashMap::new();
+    let mut sample = StrideSample::new();
+    let mut lines_scored = 0;
+    let mut within_threshold = 0;
+
+    for (doc_idx, &filename) in filenames.iter().enumerate() {
+        let file = File::open(archive::local_path(filename))
+            .with_context(|| format!("Failed to open {filename}"))?;
+        let mut reader = BufReader::new(file).lines();
+        // The last `n_lines` lines, for the context before a match
+        let mut before: VecDeque<String> = VecDeque::with_capacity(config.n_lines + 1);
+        // Candidates (by line) still collecting context after their match
+        let mut open: VecDeque<usize> = VecDeque::new();
+        let mut line_idx = 0;
+
+        loop {
+            let chunk = explain.time(Stage::FileIo, || {
+                reader
+                    .by_ref()
+                    .take(STREAM_CHUNK_LINES)
+                    .collect::<std::io::Result<Vec<String>>>()
+            })?;
+            if chunk.is_empty() {
+                break;
+            }
+
+            let embeddings = explain.time(Stage::Embedding, || {
+                if config.ignore_case {
+                    let lowered: Vec<String> = chunk.iter().map(|l| l.to_lowercase()).collect();
+                    embed_lines(model, &lowered)
+                } else {
+                    embed_lines(model, &chunk)
+                }
+            });
+
+            let started = Instant::now();
+            for (line, embedding) in chunk.into_iter().zip(embeddings) {
+                if calibrate {
+                    sample.offer(&embedding);
+                }
+                lines_scored += 1;
+
+                open.retain(
+                    |&match_line| match contexts.get_mut(&(doc_idx, match_line)) {
+                        Some(context) if context.remaining_after > 0 => {
+                            context.lines.push(line.clone());
+                            context.remaining_after -= 1;
+                            context.remaining_after > 0
+                        }
+                        _ => false,
+                    },
+                );
+
+                if let Some(distance) = combined_distance(query_embedding, &embedding, config)
+                    && distance < config.max_distance.unwrap_or(100.0)
+                {
+                    within_threshold += 1;
+                    let candidate = Candidate {
+                        doc: doc_idx,
+                        filename,
+                        line: line_idx,
+                        distance,
+                        score: apply_path_weight(
+                            blend_recency(
+                                negative_penalty(distance, &embedding, config),
+                                recency[doc_idx],
+                                config.recency_boost,
+                            ),
+                            weights[doc_idx],
+                        ),
+                    };
+                    let rejected = candidates.push(candidate);
+                    if let Some(rejected) = rejected {
+                        contexts.remove(&(rejected.doc, rejected.line));
+                    }
+                    if rejected.is_none_or(|r| (r.doc, r.line) != (doc_idx, line_idx)) {
+                        let mut lines: Vec<String> = before.iter().cloned().collect();
+                        lines.push(line.clone());
+                        contexts.insert(
+                            (doc_idx, line_idx),
+                            MatchContext {
+                                start: line_idx - before.len(),
+                                lines,
+                                remaining_after: config.n_lines,
+                                embedding: config.mmr_lambda.map(|_| embedding.clone()),
+                            },
+                        );
+                        if config.n_lines > 0 {
+                            open.push_back(line_idx);
+                        }
+                    }
+                }
+
+                before.push_back(line);
+                if before.len() > config.n_lines {
+                    before.pop_front();
+                }
+                line_idx += 1;
+            }
+            explain.add_time(Stage::VectorSearch, started.elapsed());
+        }
+    }
+    explain.candidates("lines scored", lines_scored);
+    if let Some(max_distance) = config.max_distance {
+        explain.filter(format!(
+            "max distance {max_distance} checked against every scored line"
+        ));
+        explain.candidates("lines within max distance", within_threshold);
+    }
+
+    let results = explain.time(Stage::Ranking, || {
+        let mut ranked = candidates.into_sorted_vec();
+        let order: Vec<usize> = if let Some(lambda) = config.mmr_lambda {
+            let k = if config.max_distance.is_some() {
+                ranked.len()
+            } else {
+                config.top_k
+            };
+            ranked.truncate(k.saturating_mul(MMR_OVERSAMPLE_FACTOR));
+            let embeddings: Vec<&[f32]> = ranked
+                .iter()
+                .map(|c| {
+                    contexts[&(c.doc, c.line)]
+                        .embedding
+                        .as_deref()
+                        .unwrap_or_default()
+                })
+                .collect();
+            let relevance: Vec<f64> = ranked.iter().map(|c| 1.0 - c.score).collect();
+            mmr_select(&embeddings, &relevance, k, lambda)
+        } else {
+            if config.max_distance.is_none() {
+                ranked.truncate(config.top_k);
+            }
+            (0..ranked.len()).collect()
+        };
+
+        order
+            .into_iter()
+            .filter_map(|i| {
+                let candidate = &ranked[i];
+                let context = contexts.remove(&(candidate.doc, candidate.line))?;
+                Some(SearchResult {
+                    filename: candidate.filename.to_string(),
+                    start: context.start,
+                    end: context.start + context.lines.len(),
+                    lines: context.lines,
+                    match_line: candidate.line,
+                    distance: candidate.distance,
+                    score: candidate.score,
+                    match_start_col: 0,
+                    match_end_col: 0,
+                })
+            })
+            .collect::<Vec<_>>()
+    });
+    explain.candidates("results", results.len());
+
+    let calibration = calibrate
+        .then(|| Calibration::from_embeddings(&sample.embeddings, lines_scored))
+        .flatten();
+    Ok((results, calibration))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stride_sample_stays_bounded_and_spread() {
+        let mut sample = StrideSample::new();
+        let total = 10 * CALIBRATION_SAMPLE_LINES;
+        for i in 0..total {
+            sample.offer(&[i as f32]);
+        }
+        assert!(sample.embeddings.len() < 2 * CALIBRATION_SAMPLE_LINES);
+        assert!(sample.embeddings.len() >= CALIBRATION_SAMPLE_LINES / 2);
+        // Evenly spaced from the start to near the end of the input
+        assert_eq!(sample.embeddings[0], vec![0.0]);
+        assert_eq!(
+            sample.embeddings[1][0] as usize, sample.stride,
+            "sampled every stride-th line"
+        );
+        let last = sample.embeddings.last().unwrap()[0] as usize;
+        assert!(last + sample.stride >= total);
+    }
+}
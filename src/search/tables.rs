@@ -0,0 +1,262 @@
+//! Tables in documents, searched row by row. Parsed documents often hold a whole HTML table on
+//! one line, and a markdown table row says little without its header, so a query such as
+//! "Q3 revenue EMEA" matches neither well. Each row of a table becomes a pseudo-line of
+//! `header: cell` pairs (`Region: EMEA | Quarter: Q3 | Revenue: 1.2M`), embedded on its own
+//! and matching on behalf of the line it came from: results still cite the table's line.
+//!
+//! The first row of a table is taken as its header. Streamed inputs are searched line by line
+//! only.
+use crate::fetch::decode_entities;
+
+/// A row of a table as a pseudo-line, and the line of the document holding it
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableRow {
+    /// 0-based line of the document: the line of an HTML table, or of a markdown table row
+    pub line: usize,
+    pub text: String,
+}
+
+/// The rows of every HTML and markdown table in `lines`, in document order
+pub fn table_rows(lines: &[String]) -> Vec<TableRow> {
+    let mut rows = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if is_markdown_row(&lines[i]) && lines.get(i + 1).is_some_and(|line| is_delimiter_row(line))
+        {
+            let header = markdown_cells(&lines[i]);
+            i += 2;
+            while i < lines.len() && is_markdown_row(&lines[i]) {
+                push_row(&mut rows, i, None, &header, &markdown_cells(&lines[i]));
+                i += 1;
+            }
+            continue;
+        }
+        html_rows(i, &lines[i], &mut rows);
+        i += 1;
+    }
+    rows
+}
+
+/// Add the row `cells` of a table under `header` as a pseudo-line, unless it has no text
+fn push_row(
+    rows: &mut Vec<TableRow>,
+    line: usize,
+    caption: Option<&str>,
+    header: &[String],
+    cells: &[String],
+) {
+    let pairs: Vec<String> = cells
+        .iter()
+        .enumerate()
+        .filter(|(_, cell)| !cell.is_empty())
+        .map(|(i, cell)| match header.get(i).filter(|h| !h.is_empty()) {
+            Some(label) if label != cell => format!("{label}: {cell}"),
+            _ => cell.clone(),
+        })
+        .collect();
+    if pairs.is_empty() {
+        return;
+    }
+    let mut text = pairs.join(" | ");
+    if let Some(caption) = caption.filter(|c| !c.is_empty()) {
+        text = format!("{caption} — {text}");
+    }
+    rows.push(TableRow { line, text });
+}
+
+fn is_markdown_row(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with('|') && line.len() > 1
+}
+
+/// Whether `line` is the `|---|:---:|` line separating a markdown table's header from its rows
+fn is_delimiter_row(line: &str) -> bool {
+    let cells = markdown_cells(line);
+    is_markdown_row(line)
+        && !cells.is_empty()
+        && cells.iter().all(|cell| {
+            let dashes = cell.trim_matches(':');
+            !dashes.is_empty() && dashes.chars().all(|c| c == '-')
+        })
+}
+
+/// The cells of a markdown table row, with `\|` unescaped
+fn markdown_cells(line: &str) -> Vec<String> {
+    let line = line.trim();
+    let line = line.strip_prefix('|').unwrap_or(line);
+    let line = line.strip_suffix('|').unwrap_or(line);
+    let mut cells = vec![String::new()];
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                chars.next();
+                cells.last_mut().unwrap().push('|');
+            }
+            '|' => cells.push(String::new()),
+            c => cells.last_mut().unwrap().push(c),
+        }
+    }
+    cells.iter().map(|cell| cell.trim().to_string()).collect()
+}
+
+/// Add the rows of the HTML tables on `line`
+fn html_rows(line_number: usize, line: &str, rows: &mut Vec<TableRow>) {
+    // Most lines have no table, and are not copied to be lowercased
+    if !line
+        .as_bytes()
+        .windows(6)
+        .any(|w| w.eq_ignore_ascii_case(b"<table"))
+    {
+        return;
+    }
+    let lower = line.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(start) = lower[from..].find("<table").map(|i| from + i) {
+        let end = lower[start..]
+            .find("</table>")
+            .map_or(line.len(), |i| start + i);
+        let (table, table_lower) = (&line[start..end], &lower[start..end]);
+        let caption = element_text(table, table_lower, "caption");
+
+        let mut table_rows = Vec::new();
+        let mut at = 0;
+        while let Some(row_start) = find_tag(table_lower, "tr", at) {
+            let row_end = table_lower[row_start..]
+                .find("</tr>")
+                .map_or(table.len(), |i| row_start + i);
+            table_rows.push(html_cells(
+                &table[row_start..row_end],
+                &table_lower[row_start..row_end],
+            ));
+            at = row_end;
+        }
+        if let Some((header, body)) = table_rows.split_first() {
+            for cells in body {
+                push_row(rows, line_number, caption.as_deref(), header, cells);
+            }
+        }
+        from = end;
+    }
+}
+
+/// Offset of the next `<tag>` or `<tag ...>` at or after `from`
+fn find_tag(lower: &str, tag: &str, from: usize) -> Option<usize> {
+    let open = format!("<{tag}");
+    let mut at = from;
+    while let Some(i) = lower[at..].find(&open).map(|i| at + i) {
+        let next = lower[i + open.len()..].chars().next();
+        if matches!(next, Some('>' | '/' | ' ' | '\t' | '\n')) {
+            return Some(i);
+        }
+        at = i + open.len();
+    }
+    None
+}
+
+/// The text of the `<td>` and `<th>` cells of an HTML table row
+fn html_cells(row: &str, lower: &str) -> Vec<String> {
+    let mut starts: Vec<usize> = Vec::new();
+    for tag in ["td", "th"] {
+        let mut at = 0;
+        while let Some(i) = find_tag(lower, tag, at) {
+            starts.push(i);
+            at = i + 1;
+        }
+    }
+    starts.sort_unstable();
+    starts
+        .iter()
+        .enumerate()
+        .map(|(n, &start)| {
+            let content_start = lower[start..]
+                .find('>')
+                .map_or(row.len(), |i| start + i + 1);
+            let next = starts.get(n + 1).copied().unwrap_or(row.len());
+            let content_end = ["</td>", "</th>"]
+                .iter()
+                .filter_map(|close| lower[content_start.min(next)..next].find(close))
+                .min()
+                .map_or(next, |i| content_start + i);
+            text_of(&row[content_start.min(content_end)..content_end])
+        })
+        .collect()
+}
+
+/// The text of the first `<tag>` element of an HTML fragment
+fn element_text(html: &str, lower: &str, tag: &str) -> Option<String> {
+    let start = find_tag(lower, tag, 0)?;
+    let content_start = start + lower[start..].find('>')? + 1;
+    let content_end = content_start + lower[content_start..].find(&format!("</{tag}>"))?;
+    Some(text_of(&html[content_start..content_end]))
+}
+
+/// Text of an HTML fragment: tags dropped, entities decoded and whitespace collapsed
+fn text_of(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    decode_entities(&text)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_html_table_rows() {
+        let doc = lines(
+            "# Results\n<p>Intro</p><table><caption>Revenue by region</caption>\
+             <tr><th>Region</th><th>Q3</th></tr><tr><td>EMEA</td><td><b>1.2M</b></td></tr>\
+             <tr><td>APAC &amp; Japan</td><td></td></tr></table>",
+        );
+        assert_eq!(
+            table_rows(&doc),
+            vec![
+                TableRow {
+                    line: 1,
+                    text: "Revenue by region — Region: EMEA | Q3: 1.2M".to_string(),
+                },
+                TableRow {
+                    line: 1,
+                    text: "Revenue by region — Region: APAC & Japan".to_string(),
+                },
+            ]
+        );
+        // `<track>` is not a row, and lines without tables have none
+        assert!(table_rows(&lines("<video><track src=a></video>")).is_empty());
+    }
+
+    #[test]
+    fn test_markdown_table_rows() {
+        let doc = lines(
+            "Quarterly numbers\n\n| Region | Quarter | Revenue |\n|:--|:-:|--:|\n\
+             | EMEA | Q3 | 1.2M |\n| a \\| b | | 3 |\nAfter",
+        );
+        let rows = table_rows(&doc);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].line, 4);
+        assert_eq!(rows[0].text, "Region: EMEA | Quarter: Q3 | Revenue: 1.2M");
+        assert_eq!(rows[1].text, "Region: a | b | Revenue: 3");
+
+        // Pipes without a delimiter row are not a table
+        assert!(table_rows(&lines("| just a quote |\n| more |")).is_empty());
+    }
+}
@@ -0,0 +1,282 @@
+//! OpenTelemetry traces and metrics for parse jobs, LLM requests, embedding, and workspace
+//! queries.
+//!
+//! With the `observability` feature and an OTLP endpoint configured (`observability.endpoint`
+//! in the config file, or `OTEL_EXPORTER_OTLP_ENDPOINT`), every timed operation is exported as
+//! a span plus a duration histogram sample over OTLP/HTTP. Otherwise everything here is a no-op,
+//! so call sites need no feature gates.
+use anyhow::Result;
+
+/// Kinds of operations that are timed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Parsing one file, with any backend
+    ParseJob,
+    /// One request to the LLM provider
+    LlmRequest,
+    /// Embedding a batch of lines
+    Embedding,
+    /// A vector query against the workspace store
+    StoreQuery,
+}
+
+impl Operation {
+    pub fn span_name(&self) -> &'static str {
+        match self {
+            Operation::ParseJob => "parse_job",
+            Operation::LlmRequest => "llm_request",
+            Operation::Embedding => "embedding",
+            Operation::StoreQuery => "store_query",
+        }
+    }
+
+    /// Name of the histogram recording the operation's duration, in seconds
+    pub fn metric_name(&self) -> &'static str {
+        match self {
+            Operation::ParseJob => "semtools.parse.duration",
+            Operation::LlmRequest => "semtools.llm.duration",
+            Operation::Embedding => "semtools.embedding.duration",
+            Operation::StoreQuery => "semtools.store.query.duration",
+        }
+    }
+}
+
+/// Times an operation until dropped
+#[must_use = "the operation is timed until this is dropped"]
+pub struct Timed {
+    #[cfg(feature = "observability")]
+    _inner: otel::TimedInner,
+}
+
+/// Start timing `operation`. `attributes` are attached to both the span and the metric.
+pub fn timed(operation: Operation, attributes: &[(&'static str, &str)]) -> Timed {
+    #[cfg(feature = "observability")]
+    {
+        Timed {
+            _inner: otel::TimedInner::start(operation, attributes),
+        }
+    }
+    #[cfg(not(feature = "observability"))]
+    {
+        let _ = (operation, attributes);
+        Timed {}
+    }
+}
+
+/// Time `future` as `operation` until it completes
+pub async fn measure<F: Future>(
+    operation: Operation,
+    attributes: &[(&'static str, &str)],
+    future: F,
+) -> F::Output {
+    let _timed = timed(operation, attributes);
+    future.await
+}
+
+/// Count lines embedded, for embedding throughput (`semtools.embedding.lines`)
+pub fn add_embedded_lines(lines: usize) {
+    #[cfg(feature = "observability")]
+    otel::add_embedded_lines(lines);
+    #[cfg(not(feature = "observability"))]
+    let _ = lines;
+}
+
+/// Exporters set up by `init`. Dropping this flushes pending spans and metrics.
+#[derive(Default)]
+pub struct Telemetry {
+    #[cfg(feature = "observability")]
+    providers: Option<otel::Providers>,
+}
+
+/// Set up OTLP export if the `observability` feature is on and an endpoint is configured
+pub fn init() -> Result<Telemetry> {
+    #[cfg(feature = "observability")]
+    {
+        Ok(Telemetry {
+            providers: otel::init()?,
+        })
+    }
+    #[cfg(not(feature = "observability"))]
+    {
+        Ok(Telemetry::default())
+    }
+}
+
+#[cfg(feature = "observability")]
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Some(providers) = self.providers.take() {
+            providers.shutdown();
+        }
+    }
+}
+
+#[cfg(feature = "observability")]
+mod otel {
+    use anyhow::Result;
+    use opentelemetry::global::{self, BoxedSpan};
+    use opentelemetry::metrics::{Counter, Histogram};
+    use opentelemetry::trace::{Span, Tracer};
+    use opentelemetry::{KeyValue, global::BoxedTracer};
+    use opentelemetry_otlp::{MetricExporter, SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::Resource;
+    use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use std::sync::OnceLock;
+    use std::time::Instant;
+
+    use super::Operation;
+    use crate::SemtoolsConfig;
+
+    /// Instrumentation scope of every span and metric
+    const SCOPE: &str = "semtools";
+
+    /// Standard variable the OTLP exporters read when no endpoint is configured
+    const OTLP_ENDPOINT_ENV: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+    pub struct Providers {
+        tracer: SdkTracerProvider,
+        meter: SdkMeterProvider,
+    }
+
+    impl Providers {
+        pub fn shutdown(self) {
+            if let Err(e) = self.tracer.shutdown() {
+                eprintln!("Warning: failed to export traces: {e}");
+            }
+            if let Err(e) = self.meter.shutdown() {
+                eprintln!("Warning: failed to export metrics: {e}");
+            }
+        }
+    }
+
+    pub fn init() -> Result<Option<Providers>> {
+        let config = SemtoolsConfig::from_config_file(&SemtoolsConfig::default_config_path())?
+            .observability
+            .unwrap_or_default();
+        let endpoint = config.endpoint.filter(|e| !e.is_empty());
+        if endpoint.is_none() && std::env::var(OTLP_ENDPOINT_ENV).is_err() {
+            return Ok(None);
+        }
+
+        let resource = Resource::builder()
+            .with_service_name(
+                config
+                    .service_name
+                    .unwrap_or_else(|| "semtools".to_string()),
+            )
+            .build();
+
+        // A configured endpoint is the collector's base URL, like OTEL_EXPORTER_OTLP_ENDPOINT
+        let signal_url = |path: &str| {
+            endpoint
+                .as_ref()
+                .map(|e| format!("{}/{path}", e.trim_end_matches('/')))
+        };
+
+        let mut spans = SpanExporter::builder().with_http();
+        if let Some(url) = signal_url("v1/traces") {
+            spans = spans.with_endpoint(url);
+        }
+        let tracer = SdkTracerProvider::builder()
+            .with_batch_exporter(spans.build()?)
+            .with_resource(resource.clone())
+            .build();
+
+        let mut metrics = MetricExporter::builder().with_http();
+        if let Some(url) = signal_url("v1/metrics") {
+            metrics = metrics.with_endpoint(url);
+        }
+        let meter = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(metrics.build()?).build())
+            .with_resource(resource)
+            .build();
+
+        global::set_tracer_provider(tracer.clone());
+        global::set_meter_provider(meter.clone());
+        Ok(Some(Providers { tracer, meter }))
+    }
+
+    struct Instruments {
+        tracer: BoxedTracer,
+        /// Duration histograms, in `Operation` order
+        durations: [Histogram<f64>; 4],
+        embedded_lines: Counter<u64>,
+    }
+
+    /// Instruments are created on first use, after `init` installed the providers
+    fn instruments() -> &'static Instruments {
+        static INSTRUMENTS: OnceLock<Instruments> = OnceLock::new();
+        INSTRUMENTS.get_or_init(|| {
+            let meter = global::meter(SCOPE);
+            let histogram = |operation: Operation| {
+                meter
+                    .f64_histogram(operation.metric_name())
+                    .with_unit("s")
+                    .build()
+            };
+            Instruments {
+                tracer: global::tracer(SCOPE),
+                durations: [
+                    histogram(Operation::ParseJob),
+                    histogram(Operation::LlmRequest),
+                    histogram(Operation::Embedding),
+                    histogram(Operation::StoreQuery),
+                ],
+                embedded_lines: meter
+                    .u64_counter("semtools.embedding.lines")
+                    .with_unit("{line}")
+                    .build(),
+            }
+        })
+    }
+
+    pub fn add_embedded_lines(lines: usize) {
+        instruments().embedded_lines.add(lines as u64, &[]);
+    }
+
+    pub struct TimedInner {
+        operation: Operation,
+        attributes: Vec<KeyValue>,
+        span: BoxedSpan,
+        started: Instant,
+    }
+
+    impl TimedInner {
+        pub fn start(operation: Operation, attributes: &[(&'static str, &str)]) -> Self {
+            let attributes: Vec<KeyValue> = attributes
+                .iter()
+                .map(|(key, value)| KeyValue::new(*key, value.to_string()))
+                .collect();
+            let mut span = instruments().tracer.start(operation.span_name());
+            span.set_attributes(attributes.clone());
+            Self {
+                operation,
+                attributes,
+                span,
+                started: Instant::now(),
+            }
+        }
+    }
+
+    impl Drop for TimedInner {
+        fn drop(&mut self) {
+            let histogram = &instruments().durations[self.operation as usize];
+            histogram.record(self.started.elapsed().as_secs_f64(), &self.attributes);
+            self.span.end();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_measure_without_exporter() {
+        // Without `init`, timing is a no-op that still returns the future's output
+        let value = measure(Operation::LlmRequest, &[("model", "test")], async { 42 }).await;
+        assert_eq!(value, 42);
+        add_embedded_lines(3);
+    }
+}
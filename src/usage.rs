@@ -0,0 +1,187 @@
+//! Token and page accounting for LLM requests and LlamaParse jobs.
+//!
+//! Call sites record what the providers report as they go; `semtools --show-usage` prints the
+//! totals (priced with the `pricing` section of the config file) when the command finishes, and
+//! runs inside a workspace append them to the workspace's usage history.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::config::PricingConfig;
+
+/// Tokens used with one model
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// Everything used by one run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    /// Token usage per LLM model name
+    pub models: BTreeMap<String, TokenUsage>,
+    /// Pages returned by LlamaParse jobs
+    pub pages_parsed: u64,
+}
+
+static USAGE: Mutex<Usage> = Mutex::new(Usage {
+    models: BTreeMap::new(),
+    pages_parsed: 0,
+});
+
+/// Record one LLM request and the tokens it used
+pub fn record_tokens(model: &str, prompt_tokens: u64, completion_tokens: u64) {
+    let mut usage = USAGE.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = usage.models.entry(model.to_string()).or_default();
+    entry.requests += 1;
+    entry.prompt_tokens += prompt_tokens;
+    entry.completion_tokens += completion_tokens;
+}
+
+/// Record pages returned by a parse job
+pub fn record_pages(pages: usize) {
+    let mut usage = USAGE.lock().unwrap_or_else(|e| e.into_inner());
+    usage.pages_parsed += pages as u64;
+}
+
+/// Usage recorded so far in this process
+pub fn current() -> Usage {
+    USAGE.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+impl Usage {
+    pub fn is_empty(&self) -> bool {
+        self.models.is_empty() && self.pages_parsed == 0
+    }
+
+    /// Cost of the tokens used with `model`, if it has a price
+    fn model_cost(&self, model: &str, pricing: &PricingConfig) -> Option<f64> {
+        let tokens = self.models.get(model)?;
+        let price = pricing.models.get(model)?;
+        Some(
+            (tokens.prompt_tokens as f64 * price.input_per_million
+                + tokens.completion_tokens as f64 * price.output_per_million)
+                / 1_000_000.0,
+        )
+    }
+
+    fn parse_cost(&self, pricing: &PricingConfig) -> Option<f64> {
+        if self.pages_parsed == 0 {
+            return None;
+        }
+        pricing
+            .parse_per_page
+            .map(|price| self.pages_parsed as f64 * price)
+    }
+
+    /// Total cost of what has a price. `None` when nothing used has one.
+    pub fn cost(&self, pricing: &PricingConfig) -> Option<f64> {
+        let costs: Vec<f64> = self
+            .models
+            .keys()
+            .filter_map(|model| self.model_cost(model, pricing))
+            .chain(self.parse_cost(pricing))
+            .collect();
+        (!costs.is_empty()).then(|| costs.iter().sum())
+    }
+
+    /// Human-readable summary, one line per model plus parsed pages and the total cost
+    pub fn summary(&self, pricing: &PricingConfig) -> String {
+        let mut lines = vec!["Usage:".to_string()];
+        for (model, tokens) in &self.models {
+            let mut line = format!(
+                "  {model}: {} request(s), {} prompt + {} completion tokens",
+                tokens.requests, tokens.prompt_tokens, tokens.completion_tokens
+            );
+            if let Some(cost) = self.model_cost(model, pricing) {
+                line.push_str(&format!(" (${cost:.4})"));
+            }
+            lines.push(line);
+        }
+        if self.pages_parsed > 0 {
+            let mut line = format!("  llama-parse: {} page(s)", self.pages_parsed);
+            if let Some(cost) = self.parse_cost(pricing) {
+                line.push_str(&format!(" (${cost:.4})"));
+            }
+            lines.push(line);
+        }
+        match self.cost(pricing) {
+            Some(cost) => lines.push(format!("  Total: ${cost:.4}")),
+            None if !self.is_empty() => {
+                lines.push("  Total: unknown (no pricing configured)".to_string())
+            }
+            None => lines.push("  Nothing used".to_string()),
+        }
+        lines.join("\n")
+    }
+}
+
+/// One entry of a workspace's usage history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    /// Unix timestamp (seconds) of when the run finished
+    pub recorded_at: u64,
+    /// Subcommand that was run, e.g. "ask"
+    pub command: String,
+    pub usage: Usage,
+    /// Cost at the configured prices when the run finished
+    pub cost: Option<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ModelPricing;
+    use std::collections::HashMap;
+
+    fn usage() -> Usage {
+        Usage {
+            models: BTreeMap::from([(
+                "gpt-4o-mini".to_string(),
+                TokenUsage {
+                    requests: 2,
+                    prompt_tokens: 2_000_000,
+                    completion_tokens: 500_000,
+                },
+            )]),
+            pages_parsed: 10,
+        }
+    }
+
+    #[test]
+    fn test_cost() {
+        let usage = usage();
+        assert_eq!(usage.cost(&PricingConfig::default()), None);
+
+        let pricing = PricingConfig {
+            models: HashMap::from([(
+                "gpt-4o-mini".to_string(),
+                ModelPricing {
+                    input_per_million: 0.15,
+                    output_per_million: 0.6,
+                },
+            )]),
+            parse_per_page: Some(0.003),
+        };
+        let cost = usage.cost(&pricing).unwrap();
+        assert!((cost - (0.3 + 0.3 + 0.03)).abs() < 1e-9);
+
+        let summary = usage.summary(&pricing);
+        assert!(summary.contains("gpt-4o-mini: 2 request(s)"));
+        assert!(summary.contains("llama-parse: 10 page(s) ($0.0300)"));
+        assert!(summary.contains("Total: $0.6300"));
+    }
+
+    #[test]
+    fn test_summary_without_pricing() {
+        let summary = usage().summary(&PricingConfig::default());
+        assert!(summary.contains("Total: unknown"));
+        assert!(
+            Usage::default()
+                .summary(&PricingConfig::default())
+                .contains("Nothing used")
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! JavaScript bindings to the search ranking (`wasm` feature).
+//!
+//! A server embeds documents with the semtools model and ships the lines and their embeddings
+//! to a web page, which ranks them locally for each query embedding:
+//!
+//! ```js
+//! const index = new SearchIndex(JSON.stringify([
+//!   { filename: "notes.md", lines: ["..."], embeddings: [[0.1, ...]], mtime: 1718000000 },
+//! ]));
+//! const { results } = JSON.parse(index.search(queryEmbedding, JSON.stringify({ top_k: 5 })));
+//! ```
+//!
+//! Results have the same shape as `semtools search --json`.
+use anyhow::{Result, bail};
+use serde::Deserialize;
+use std::collections::HashMap;
+use wasm_bindgen::prelude::*;
+
+use crate::json_mode::{SearchOutput, SearchResultJSON};
+use crate::ranking::{
+    DEFAULT_NEGATIVE_WEIGHT, Document, QueryCombination, SearchConfig, normalized_recency,
+    rank_documents, split_lines,
+};
+
+/// A document as shipped to the browser: its lines (or whole content) and one embedding per line
+#[derive(Debug, Deserialize)]
+struct DocumentInput {
+    filename: String,
+    #[serde(default)]
+    lines: Option<Vec<String>>,
+    #[serde(default)]
+    content: Option<String>,
+    embeddings: Vec<Vec<f32>>,
+    /// Modification time in seconds, for `recency_boost`
+    #[serde(default)]
+    mtime: Option<i64>,
+}
+
+/// Search options, named and defaulted like the `search` flags
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SearchOptions {
+    n_lines: usize,
+    top_k: usize,
+    max_distance: Option<f64>,
+    recency_boost: f64,
+    mmr_lambda: Option<f64>,
+    negative_embeddings: Vec<Vec<f32>>,
+    negative_weight: f64,
+    extra_query_embeddings: Vec<Vec<f32>>,
+    /// Combine the extra queries with "any of" rather than "all of"
+    any_of: bool,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            n_lines: 3,
+            top_k: 3,
+            max_distance: None,
+            recency_boost: 0.0,
+            mmr_lambda: None,
+            negative_embeddings: Vec::new(),
+            negative_weight: DEFAULT_NEGATIVE_WEIGHT,
+            extra_query_embeddings: Vec::new(),
+            any_of: false,
+        }
+    }
+}
+
+impl From<SearchOptions> for SearchConfig {
+    fn from(options: SearchOptions) -> Self {
+        SearchConfig {
+            n_lines: options.n_lines,
+            top_k: options.top_k,
+            max_distance: options.max_distance,
+            recency_boost: options.recency_boost,
+            mmr_lambda: options.mmr_lambda,
+            negative_embeddings: options.negative_embeddings,
+            negative_weight: options.negative_weight,
+            extra_query_embeddings: options.extra_query_embeddings,
+            query_combination: if options.any_of {
+                QueryCombination::Any
+            } else {
+                QueryCombination::All
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Documents with precomputed embeddings, parsed once and searched for each query
+#[wasm_bindgen]
+pub struct SearchIndex {
+    documents: Vec<Document>,
+    /// Normalized recency of each document, in the order of `documents`
+    recency: Vec<f64>,
+}
+
+#[wasm_bindgen]
+impl SearchIndex {
+    /// Parse a JSON array of `{filename, lines | content, embeddings, mtime?}` documents
+    #[wasm_bindgen(constructor)]
+    pub fn new(documents_json: &str) -> Result<SearchIndex, JsError> {
+        Self::from_json(documents_json).map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Rank the documents against `query_embedding`, returning `{"results": [...]}` as JSON.
+    /// `options_json` may be empty; see `SearchOptions` for the keys.
+    pub fn search(&self, query_embedding: &[f32], options_json: &str) -> Result<String, JsError> {
+        self.search_json(query_embedding, options_json)
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Number of documents in the index
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.documents.len()
+    }
+}
+
+impl SearchIndex {
+    fn from_json(documents_json: &str) -> Result<Self> {
+        let inputs: Vec<DocumentInput> = serde_json::from_str(documents_json)?;
+
+        let mtimes: HashMap<String, i64> = inputs
+            .iter()
+            .filter_map(|doc| doc.mtime.map(|mtime| (doc.filename.clone(), mtime)))
+            .collect();
+        let recency_by_file = normalized_recency(&mtimes);
+
+        let mut documents = Vec::with_capacity(inputs.len());
+        let mut recency = Vec::with_capacity(inputs.len());
+        for input in inputs {
+            let lines = match (input.lines, input.content) {
+                (Some(lines), None) => lines,
+                (None, Some(content)) => split_lines(&content),
+                _ => bail!("{}: give either `lines` or `content`", input.filename),
+            };
+            if lines.len() != input.embeddings.len() {
+                bail!(
+                    "{}: {} lines but {} embeddings",
+                    input.filename,
+                    lines.len(),
+                    input.embeddings.len()
+                );
+            }
+            recency.push(recency_by_file.get(&input.filename).copied().unwrap_or(0.0));
+            documents.push(Document {
+                filename: input.filename,
+                lines,
+                embeddings: input.embeddings,
+                table_rows: Vec::new(),
+            });
+        }
+        Ok(Self { documents, recency })
+    }
+
+    fn search_json(&self, query_embedding: &[f32], options_json: &str) -> Result<String> {
+        let options: SearchOptions = if options_json.trim().is_empty() {
+            SearchOptions::default()
+        } else {
+            serde_json::from_str(options_json)?
+        };
+        let config = SearchConfig::from(options);
+
+        let results = rank_documents(&self.documents, query_embedding, &config, &self.recency)
+            .into_iter()
+            .map(|result| SearchResultJSON {
+                filename: result.filename,
+                start_line_number: result.start,
+                end_line_number: result.end,
+                match_line_number: result.match_line,
+                distance: result.distance,
+                score: result.score,
+                normalized_score: None,
+                match_start_col: None,
+                match_end_col: None,
+                content: result.lines.join("\n"),
+                metadata: None,
+                commit: None,
+            })
+            .collect();
+        Ok(serde_json::to_string(&SearchOutput { results })?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_index() {
+        let documents = r#"[
+            {"filename": "old.md", "lines": ["kubernetes upgrade", "lunch menu"],
+             "embeddings": [[1.0, 0.0], [0.0, 1.0]], "mtime": 1000},
+            {"filename": "new.md", "content": "cluster upgrade notes",
+             "embeddings": [[0.95, 0.05]], "mtime": 2000}
+        ]"#;
+        let index = SearchIndex::from_json(documents).unwrap();
+        assert_eq!(index.length(), 2);
+
+        let search = |options: &str| -> serde_json::Value {
+            serde_json::from_str(&index.search_json(&[1.0, 0.0], options).unwrap()).unwrap()
+        };
+        let output = search("");
+        assert_eq!(output["results"][0]["filename"], "old.md");
+        assert_eq!(
+            output["results"][0]["content"],
+            "kubernetes upgrade\nlunch menu"
+        );
+        assert_eq!(output["results"].as_array().unwrap().len(), 3);
+
+        let output = search(r#"{"top_k": 1, "recency_boost": 0.1}"#);
+        assert_eq!(output["results"][0]["filename"], "new.md");
+        assert_eq!(output["results"].as_array().unwrap().len(), 1);
+
+        assert!(index.search_json(&[1.0, 0.0], r#"{"k": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_invalid_documents() {
+        let mismatched = r#"[{"filename": "a.md", "lines": ["a", "b"], "embeddings": [[1.0]]}]"#;
+        assert!(SearchIndex::from_json(mismatched).is_err());
+        let no_text = r#"[{"filename": "a.md", "embeddings": []}]"#;
+        assert!(SearchIndex::from_json(no_text).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+//! Context lines of workspace results. They are read from the original file, falling back to
+//! the line text kept in the index (`store_text`) when the file moved, was deleted or no
+//! longer has the matched line.
+//!
+//! Each file is read once, however many results it has. `prefetch` reads the files of a page of
+//! results in parallel, since over a network filesystem a read mostly waits.
+use std::cell::{OnceCell, RefCell};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+
+use crate::workspace::Workspace;
+use crate::workspace::store::Store;
+
+/// Most files `prefetch` reads at once
+const MAX_PARALLEL_READS: usize = 16;
+
+pub struct ResultContext {
+    workspace_name: Option<String>,
+    /// The index, opened on the first file that cannot serve a result, when it keeps line text
+    store: OnceCell<Option<Store>>,
+    /// Lines of the files read so far, `None` for those that could not be read
+    files: RefCell<HashMap<String, Option<Vec<String>>>>,
+}
+
+impl ResultContext {
+    /// Context for results from `workspace_name`. Without `store_text` (or when the index
+    /// cannot be opened) context only comes from the files.
+    pub fn new(workspace_name: Option<&str>) -> Self {
+        Self {
+            workspace_name: workspace_name.map(str::to_string),
+            store: OnceCell::new(),
+            files: RefCell::default(),
+        }
+    }
+
+    /// Read the files of `paths` not read yet, several at a time, so the results in them are
+    /// served without waiting on each file in turn
+    pub fn prefetch<'a>(&self, paths: impl IntoIterator<Item = &'a str>) {
+        let mut files = self.files.borrow_mut();
+        let pending: Vec<&str> = paths
+            .into_iter()
+            .filter(|path| !files.contains_key(*path))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        if pending.is_empty() {
+            return;
+        }
+        let per_thread = pending.len().div_ceil(MAX_PARALLEL_READS);
+        std::thread::scope(|scope| {
+            let readers: Vec<_> = pending
+                .chunks(per_thread)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|path| (path.to_string(), read_lines(path)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            for reader in readers {
+                // A reader that panicked leaves its files to be read on demand
+                if let Ok(read) = reader.join() {
+                    files.extend(read);
+                }
+            }
+        });
+    }
+
+    fn store(&self) -> Option<&Store> {
+        self.store
+            .get_or_init(|| {
+                Workspace::open(self.workspace_name.as_deref())
+                    .ok()
+                    .filter(|ws| ws.config.store_text)
+                    .and_then(|ws| ws.open_store().ok())
+            })
+            .as_ref()
+    }
+
+    /// Up to `n_lines` lines either side of `match_line` (0-based) in `path`, with the line
+    /// number of the first. `None` if neither the file nor the index has the matched line.
+    pub fn lines(
+        &self,
+        path: &str,
+        match_line: usize,
+        n_lines: usize,
+    ) -> Option<(usize, Vec<String>)> {
+        let start = match_line.saturating_sub(n_lines);
+        let end = match_line + n_lines + 1;
+
+        {
+            let mut files = self.files.borrow_mut();
+            let file = files
+                .entry(path.to_string())
+                .or_insert_with(|| read_lines(path));
+            if let Some(lines) = file
+                && match_line < lines.len()
+            {
+                let end = end.min(lines.len());
+                return Some((start, lines[start..end].to_vec()));
+            }
+        }
+
+        let texts = self.store()?.line_texts(path, start, end).ok()?;
+        if !texts.contains_key(&match_line) {
+            return None;
+        }
+        let first = *texts.keys().next()?;
+        Some((first, texts.into_values().collect()))
+    }
+}
+
+fn read_lines(path: &str) -> Option<Vec<String>> {
+    let content = fs::read_to_string(crate::archive::local_path(path)).ok()?;
+    Some(content.lines().map(str::to_string).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::workspace::store::{LINE_EMBEDDING_SIZE, LineEmbedding};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_context_falls_back_to_stored_text() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("notes.md");
+        std::fs::write(&path, "one\ntwo\nthree\nfour\n").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let store = Store::open(&temp_dir.path().join("index").to_string_lossy()).unwrap();
+        let lines: Vec<LineEmbedding> = ["one", "two", "three", "four"]
+            .iter()
+            .enumerate()
+            .map(|(line_number, text)| LineEmbedding {
+                path: path.clone(),
+                line_number: line_number as i32,
+                embedding: vec![0.1; LINE_EMBEDDING_SIZE],
+                text: Some(text.to_string()),
+                table_line: None,
+            })
+            .collect();
+        store.upsert_line_embeddings(&lines).unwrap();
+        let context = ResultContext {
+            workspace_name: None,
+            store: OnceCell::from(Some(store)),
+            files: RefCell::default(),
+        };
+
+        // The file wins while it has the matched line, and is read once for all its results
+        std::fs::write(&path, "ONE\nTWO\nTHREE\nFOUR\n").unwrap();
+        context.prefetch([path.as_str(), path.as_str()]);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            context.lines(&path, 1, 1),
+            Some((0, vec!["ONE".into(), "TWO".into(), "THREE".into()]))
+        );
+
+        // Once it is gone, the stored text is served
+        context.files.borrow_mut().clear();
+        assert_eq!(
+            context.lines(&path, 3, 2),
+            Some((1, vec!["two".into(), "three".into(), "four".into()]))
+        );
+        assert_eq!(context.lines(&path, 7, 1), None);
+        let files_only = ResultContext {
+            workspace_name: None,
+            store: OnceCell::from(None),
+            files: RefCell::default(),
+        };
+        assert_eq!(files_only.lines(&path, 1, 1), None);
+    }
+}
@@ -0,0 +1,634 @@
+//! At-rest encryption for workspaces.
+//!
+//! Qdrant Edge memory-maps its segment files, so they cannot be encrypted transparently.
+//! Instead, an encrypted workspace keeps only a sealed copy of its index (every file encrypted
+//! with ChaCha20-Poly1305) in `<root>/sealed`. Opening the store decrypts it into a private
+//! temporary directory, and dropping the store seals it back and removes the plaintext.
+//!
+//! Qdrant preallocates large, mostly empty files, so all-zero blocks are left out before
+//! encrypting and recreated as holes when decrypting. Files that did not change while the
+//! workspace was open keep their existing sealed copy.
+//!
+//! Each process works on its own decrypted copy, so a store that writes keeps the write lock
+//! from its first write until it has sealed its copy back. A copy that was never written to is
+//! only sealed back if no other process sealed in the meantime.
+use anyhow::{Context, Result, anyhow, bail};
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+use crate::workspace::error::WorkspaceError;
+use std::time::SystemTime;
+
+use crate::workspace::lock::WriteLock;
+
+/// Marker file (inside the workspace root) describing how the workspace is encrypted
+pub const ENCRYPTION_FILE: &str = "encryption.json";
+
+/// Directory (inside the workspace root) holding the encrypted index
+pub const SEALED_DIR: &str = "sealed";
+
+/// Environment variable holding the passphrase for encrypted workspaces
+pub const KEY_ENV_VAR: &str = "SEMTOOLS_WORKSPACE_KEY";
+
+/// Encrypted file suffix inside the sealed directory
+const SEALED_SUFFIX: &str = ".enc";
+
+/// File (inside the sealed directory) holding a random stamp that changes with every seal, so
+/// a working copy can tell whether another process sealed since it was decrypted
+const SEAL_STAMP_FILE: &str = "stamp";
+
+/// Known plaintext used to tell a wrong passphrase apart from corrupted data
+const KEY_CHECK_PLAINTEXT: &[u8] = b"semtools-workspace-key-check";
+
+const NONCE_LEN: usize = 12;
+
+/// Block size used to detect all-zero regions of a file
+const BLOCK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptionInfo {
+    version: u32,
+    cipher: String,
+    kdf: String,
+    /// Hex-encoded Argon2 salt
+    salt: String,
+    /// Hex-encoded nonce + ciphertext of `KEY_CHECK_PLAINTEXT`
+    key_check: String,
+}
+
+pub fn is_encrypted(root_dir: &Path) -> bool {
+    root_dir.join(ENCRYPTION_FILE).exists()
+}
+
+fn passphrase() -> Result<String> {
+    match std::env::var(KEY_ENV_VAR) {
+        Ok(p) if !p.is_empty() => Ok(p),
+        _ => Err(WorkspaceError::PassphraseRequired.into()),
+    }
+}
+
+fn derive_cipher(passphrase: &str, salt: &[u8]) -> Result<ChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow!("Key derivation failed: {e}"))?;
+    Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+}
+
+fn encrypt(cipher: &ChaCha20Poly1305, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| anyhow!("Encryption failed"))?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(sealed)
+}
+
+fn decrypt(cipher: &ChaCha20Poly1305, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        bail!("Encrypted data is truncated");
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    cipher
+        .decrypt(
+            Nonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad,
+            },
+        )
+        .map_err(|_| anyhow!("Decryption failed"))
+}
+
+/// Load the workspace's encryption settings and derive its cipher from the passphrase
+fn workspace_cipher(root_dir: &Path, passphrase: &str) -> Result<ChaCha20Poly1305> {
+    let info: EncryptionInfo =
+        serde_json::from_str(&fs::read_to_string(root_dir.join(ENCRYPTION_FILE))?)?;
+    if info.version != 1 {
+        bail!("Unsupported workspace encryption version {}", info.version);
+    }
+
+    let cipher = derive_cipher(passphrase, &hex::decode(&info.salt)?)?;
+    decrypt(&cipher, &hex::decode(&info.key_check)?, b"")
+        .map_err(|_| WorkspaceError::WrongPassphrase)?;
+    Ok(cipher)
+}
+
+/// Path of a file relative to `base`, with `/` separators, used as associated data so a sealed
+/// file cannot be swapped for another one
+fn relative_name(base: &Path, path: &Path) -> Result<String> {
+    Ok(path
+        .strip_prefix(base)?
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+/// Encode file contents as `<len: u64><per block: 0 | 1 + data>`, dropping all-zero blocks
+fn sparse_encode(data: &[u8]) -> Vec<u8> {
+    let mut encoded = (data.len() as u64).to_le_bytes().to_vec();
+    for block in data.chunks(BLOCK_SIZE) {
+        if block.iter().all(|&b| b == 0) {
+            encoded.push(0);
+        } else {
+            encoded.push(1);
+            encoded.extend_from_slice(block);
+        }
+    }
+    encoded
+}
+
+/// Write the output of `sparse_encode` to `dst`, leaving dropped blocks as holes
+fn sparse_decode_to(encoded: &[u8], dst: &Path) -> Result<()> {
+    let corrupted = || anyhow!("Encrypted workspace file is malformed");
+    let (len, mut rest) = encoded.split_first_chunk::<8>().ok_or_else(corrupted)?;
+    let len = u64::from_le_bytes(*len);
+
+    let mut file = fs::File::create(dst)?;
+    file.set_len(len)?;
+    let mut offset = 0u64;
+    while offset < len {
+        let block_len = (len - offset).min(BLOCK_SIZE as u64) as usize;
+        let (&flag, tail) = rest.split_first().ok_or_else(corrupted)?;
+        rest = tail;
+        if flag == 1 {
+            if rest.len() < block_len {
+                return Err(corrupted());
+            }
+            let (block, tail) = rest.split_at(block_len);
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(block)?;
+            rest = tail;
+        }
+        offset += block_len as u64;
+    }
+    Ok(())
+}
+
+/// Size and modification time of a plaintext file, used to detect unchanged files
+type FileStamp = (u64, Option<SystemTime>);
+
+fn file_stamp(path: &Path) -> Result<FileStamp> {
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.len(), metadata.modified().ok()))
+}
+
+fn read_seal_stamp(sealed_dir: &Path) -> Option<String> {
+    fs::read_to_string(sealed_dir.join(SEAL_STAMP_FILE)).ok()
+}
+
+/// Record the stamp of every file under `path`
+fn collect_stamps(base: &Path, path: &Path, stamps: &mut HashMap<String, FileStamp>) -> Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            collect_stamps(base, &entry?.path(), stamps)?;
+        }
+    } else {
+        stamps.insert(relative_name(base, path)?, file_stamp(path)?);
+    }
+    Ok(())
+}
+
+/// Encrypt every file under `src` into `dst_base`. Files whose stamp matches `unchanged` are
+/// moved over from `previous` instead of being encrypted again.
+fn seal_tree(
+    cipher: &ChaCha20Poly1305,
+    base: &Path,
+    src: &Path,
+    dst_base: &Path,
+    previous: &Path,
+    unchanged: &HashMap<String, FileStamp>,
+) -> Result<()> {
+    if src.is_dir() {
+        for entry in fs::read_dir(src)? {
+            seal_tree(cipher, base, &entry?.path(), dst_base, previous, unchanged)?;
+        }
+    } else {
+        let name = relative_name(base, src)?;
+        let sealed_name = format!("{name}{SEALED_SUFFIX}");
+        let dst = dst_base.join(&sealed_name);
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let old = previous.join(&sealed_name);
+        if unchanged.get(&name) == Some(&file_stamp(src)?) && old.exists() {
+            fs::rename(old, dst)?;
+        } else {
+            let plaintext = sparse_encode(&fs::read(src)?);
+            fs::write(dst, encrypt(cipher, &plaintext, name.as_bytes())?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Decrypt every file under `src` into `dst_base`, recording the stamp of each written file
+fn unseal_tree(
+    cipher: &ChaCha20Poly1305,
+    base: &Path,
+    src: &Path,
+    dst_base: &Path,
+    stamps: &mut HashMap<String, FileStamp>,
+) -> Result<()> {
+    if src.is_dir() {
+        for entry in fs::read_dir(src)? {
+            unseal_tree(cipher, base, &entry?.path(), dst_base, stamps)?;
+        }
+    } else {
+        let sealed_name = relative_name(base, src)?;
+        let Some(name) = sealed_name.strip_suffix(SEALED_SUFFIX) else {
+            return Ok(());
+        };
+        let dst = dst_base.join(name);
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        decrypt(cipher, &fs::read(src)?, name.as_bytes())
+            .and_then(|plaintext| sparse_decode_to(&plaintext, &dst))
+            .with_context(|| format!("Encrypted workspace file '{name}' is corrupted"))?;
+        stamps.insert(name.to_string(), file_stamp(&dst)?);
+    }
+    Ok(())
+}
+
+/// Encrypt `src_dir` (or the listed `items` inside it) into `<root>/sealed`, replacing the
+/// previous sealed copy only once the new one is complete
+fn write_sealed(
+    cipher: &ChaCha20Poly1305,
+    root_dir: &Path,
+    src_dir: &Path,
+    items: &[&str],
+    unchanged: &HashMap<String, FileStamp>,
+) -> Result<()> {
+    let sealed_dir = root_dir.join(SEALED_DIR);
+    let staging_dir = root_dir.join(format!(".{SEALED_DIR}.tmp"));
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+    fs::create_dir_all(&staging_dir)?;
+
+    for item in items {
+        let src = src_dir.join(item);
+        if src.exists() {
+            seal_tree(cipher, src_dir, &src, &staging_dir, &sealed_dir, unchanged)?;
+        }
+    }
+    fs::write(
+        staging_dir.join(SEAL_STAMP_FILE),
+        format!("{:016x}", rand::rngs::OsRng.next_u64()),
+    )?;
+
+    if sealed_dir.exists() {
+        fs::remove_dir_all(&sealed_dir)?;
+    }
+    fs::rename(&staging_dir, &sealed_dir)?;
+    Ok(())
+}
+
+/// Turn on encryption for a workspace, sealing any existing plaintext index `items` and
+/// removing them. Uses the passphrase from `SEMTOOLS_WORKSPACE_KEY`.
+pub fn enable_encryption(root_dir: &str, items: &[&str]) -> Result<()> {
+    enable_encryption_with(Path::new(root_dir), items, &passphrase()?)
+}
+
+fn enable_encryption_with(root: &Path, items: &[&str], passphrase: &str) -> Result<()> {
+    if is_encrypted(root) {
+        // Make sure the passphrase matches before reporting success
+        workspace_cipher(root, passphrase)?;
+        return Ok(());
+    }
+    fs::create_dir_all(root)?;
+
+    let mut salt = [0u8; 16];
+    rand::rngs::OsRng.fill_bytes(&mut salt);
+    let cipher = derive_cipher(passphrase, &salt)?;
+
+    write_sealed(&cipher, root, root, items, &HashMap::new())?;
+
+    let info = EncryptionInfo {
+        version: 1,
+        cipher: "chacha20poly1305".to_string(),
+        kdf: "argon2id".to_string(),
+        salt: hex::encode(salt),
+        key_check: hex::encode(encrypt(&cipher, KEY_CHECK_PLAINTEXT, b"")?),
+    };
+    fs::write(
+        root.join(ENCRYPTION_FILE),
+        serde_json::to_string_pretty(&info)?,
+    )?;
+
+    for item in items {
+        let path = root.join(item);
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else if path.exists() {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Decrypted working copy of an encrypted workspace, sealed back and deleted on drop
+pub struct UnsealedWorkspace {
+    root_dir: PathBuf,
+    work_dir: PathBuf,
+    cipher: ChaCha20Poly1305,
+    /// Stamp of every file as it was decrypted
+    stamps: HashMap<String, FileStamp>,
+    /// Seal stamp of the sealed copy that was decrypted
+    seal_stamp: Option<String>,
+    /// Write lock, held from the first write until the copy is sealed back
+    write_lock: Mutex<Option<WriteLock>>,
+}
+
+impl UnsealedWorkspace {
+    /// Decrypt the workspace using the passphrase from `SEMTOOLS_WORKSPACE_KEY`
+    pub fn open(root_dir: &Path) -> Result<Self> {
+        Self::open_with(root_dir, &passphrase()?)
+    }
+
+    fn open_with(root_dir: &Path, passphrase: &str) -> Result<Self> {
+        let cipher = workspace_cipher(root_dir, passphrase)?;
+
+        // Recover from a crash between removing the old sealed copy and renaming the new one
+        let sealed_dir = root_dir.join(SEALED_DIR);
+        let staging_dir = root_dir.join(format!(".{SEALED_DIR}.tmp"));
+        if !sealed_dir.exists() && staging_dir.exists() {
+            fs::rename(&staging_dir, &sealed_dir)?;
+        }
+
+        let work_dir = std::env::temp_dir().join(format!(
+            "semtools-{}-{:016x}",
+            std::process::id(),
+            rand::rngs::OsRng.next_u64()
+        ));
+        create_private_dir(&work_dir)?;
+
+        let mut unsealed = Self {
+            root_dir: root_dir.to_path_buf(),
+            work_dir,
+            cipher,
+            stamps: HashMap::new(),
+            seal_stamp: read_seal_stamp(&sealed_dir),
+            write_lock: Mutex::new(None),
+        };
+        if sealed_dir.exists() {
+            unseal_tree(
+                &unsealed.cipher,
+                &sealed_dir,
+                &sealed_dir,
+                &unsealed.work_dir,
+                &mut unsealed.stamps,
+            )?;
+        }
+        Ok(unsealed)
+    }
+
+    pub fn work_dir(&self) -> &Path {
+        &self.work_dir
+    }
+
+    /// Take the write lock for the rest of this copy's life, before writing to it. Fails if
+    /// another process sealed its changes since the copy was decrypted, as sealing this copy
+    /// would then discard them.
+    pub fn lock_for_write(&self) -> Result<()> {
+        let mut held = self
+            .write_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if held.is_some() {
+            return Ok(());
+        }
+        let lock = WriteLock::acquire(&self.root_dir)?;
+        if read_seal_stamp(&self.root_dir.join(SEALED_DIR)) != self.seal_stamp {
+            bail!(
+                "Encrypted workspace {} was changed by another process since it was opened. \
+                 Run the command again",
+                self.root_dir.display()
+            );
+        }
+        *held = Some(lock);
+        Ok(())
+    }
+
+    fn seal(&self) -> Result<()> {
+        let held = self
+            .write_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        let mut current = HashMap::new();
+        collect_stamps(&self.work_dir, &self.work_dir, &mut current)?;
+        if current == self.stamps {
+            return Ok(());
+        }
+        let _lock = match held {
+            Some(lock) => lock,
+            None => {
+                let lock = WriteLock::acquire(&self.root_dir)?;
+                // Nothing was written through this copy, so the changes are incidental ones
+                // from opening the index. Keep what another process sealed instead.
+                if read_seal_stamp(&self.root_dir.join(SEALED_DIR)) != self.seal_stamp {
+                    return Ok(());
+                }
+                lock
+            }
+        };
+
+        let items: Vec<String> = fs::read_dir(&self.work_dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        let items: Vec<&str> = items.iter().map(String::as_str).collect();
+        write_sealed(
+            &self.cipher,
+            &self.root_dir,
+            &self.work_dir,
+            &items,
+            &self.stamps,
+        )
+    }
+}
+
+impl Drop for UnsealedWorkspace {
+    fn drop(&mut self) {
+        if let Err(e) = self.seal() {
+            eprintln!(
+                "Error: failed to re-encrypt workspace (decrypted copy left at {}): {e}",
+                self.work_dir.display()
+            );
+            return;
+        }
+        let _ = fs::remove_dir_all(&self.work_dir);
+    }
+}
+
+#[cfg(unix)]
+fn create_private_dir(path: &Path) -> Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(path)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(path: &Path) -> Result<()> {
+    fs::create_dir_all(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_enable_encryption_seals_existing_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("documents.qdrant/segments")).unwrap();
+        fs::write(root.join("documents.qdrant/segments/data"), b"secret paths").unwrap();
+        fs::write(root.join("maintenance.json"), b"{}").unwrap();
+
+        enable_encryption_with(root, &["documents.qdrant", "maintenance.json"], "hunter2").unwrap();
+
+        assert!(is_encrypted(root));
+        assert!(!root.join("documents.qdrant").exists());
+        assert!(!root.join("maintenance.json").exists());
+        let sealed = fs::read(root.join("sealed/documents.qdrant/segments/data.enc")).unwrap();
+        assert!(!sealed.windows(6).any(|w| w == b"secret"));
+
+        let unsealed = UnsealedWorkspace::open_with(root, "hunter2").unwrap();
+        assert_eq!(
+            fs::read(unsealed.work_dir().join("documents.qdrant/segments/data")).unwrap(),
+            b"secret paths"
+        );
+    }
+
+    #[test]
+    fn test_changes_are_resealed_on_drop() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("meta.json"), b"{}").unwrap();
+        enable_encryption_with(root, &["meta.json"], "hunter2").unwrap();
+        let sealed_meta = fs::read(root.join("sealed/meta.json.enc")).unwrap();
+
+        let work_dir = {
+            let unsealed = UnsealedWorkspace::open_with(root, "hunter2").unwrap();
+            fs::write(
+                unsealed.work_dir().join("maintenance.json"),
+                b"{\"rows\":1}",
+            )
+            .unwrap();
+            unsealed.work_dir().to_path_buf()
+        };
+        // Plaintext is removed once the workspace is sealed again
+        assert!(!work_dir.exists());
+
+        // Untouched files keep their sealed copy instead of being encrypted again
+        assert_eq!(
+            fs::read(root.join("sealed/meta.json.enc")).unwrap(),
+            sealed_meta
+        );
+
+        let unsealed = UnsealedWorkspace::open_with(root, "hunter2").unwrap();
+        assert_eq!(
+            fs::read(unsealed.work_dir().join("maintenance.json")).unwrap(),
+            b"{\"rows\":1}"
+        );
+    }
+
+    #[test]
+    fn test_unchanged_copy_is_not_resealed() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("meta.json"), b"{}").unwrap();
+        enable_encryption_with(root, &["meta.json"], "hunter2").unwrap();
+        let stamp = read_seal_stamp(&root.join(SEALED_DIR));
+
+        drop(UnsealedWorkspace::open_with(root, "hunter2").unwrap());
+        assert_eq!(read_seal_stamp(&root.join(SEALED_DIR)), stamp);
+    }
+
+    #[test]
+    fn test_concurrent_seal_is_not_overwritten() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join("meta.json"), b"{}").unwrap();
+        enable_encryption_with(root, &["meta.json"], "hunter2").unwrap();
+
+        let reader = UnsealedWorkspace::open_with(root, "hunter2").unwrap();
+        let writer = UnsealedWorkspace::open_with(root, "hunter2").unwrap();
+        let late_writer = UnsealedWorkspace::open_with(root, "hunter2").unwrap();
+        writer.lock_for_write().unwrap();
+        fs::write(writer.work_dir().join("meta.json"), b"{\"rows\":1}").unwrap();
+        drop(writer);
+
+        // A copy that was only read keeps its incidental changes to itself
+        fs::write(reader.work_dir().join("meta.json"), b"{\"rows\":0}").unwrap();
+        drop(reader);
+        // and one that has yet to write refuses to, rather than discard the sealed changes
+        assert!(late_writer.lock_for_write().is_err());
+        drop(late_writer);
+
+        let unsealed = UnsealedWorkspace::open_with(root, "hunter2").unwrap();
+        assert_eq!(
+            fs::read(unsealed.work_dir().join("meta.json")).unwrap(),
+            b"{\"rows\":1}"
+        );
+    }
+
+    #[test]
+    fn test_zero_blocks_are_not_stored() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        let mut data = vec![0u8; BLOCK_SIZE * 8 + 5];
+        data[BLOCK_SIZE * 3 + 1] = 7;
+        data[BLOCK_SIZE * 8 + 4] = 9;
+        fs::write(root.join("page_0.dat"), &data).unwrap();
+
+        enable_encryption_with(root, &["page_0.dat"], "hunter2").unwrap();
+        let sealed_len = fs::metadata(root.join("sealed/page_0.dat.enc"))
+            .unwrap()
+            .len();
+        assert!(sealed_len < (BLOCK_SIZE * 2) as u64);
+
+        let unsealed = UnsealedWorkspace::open_with(root, "hunter2").unwrap();
+        assert_eq!(
+            fs::read(unsealed.work_dir().join("page_0.dat")).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn test_wrong_passphrase_is_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        enable_encryption_with(root, &[], "hunter2").unwrap();
+
+        let err = UnsealedWorkspace::open_with(root, "wrong")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("Wrong passphrase"));
+        assert!(enable_encryption_with(root, &[], "wrong").is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::Duration;
+
+use crate::search::model::EmbedPreset;
+use crate::workspace::encryption::KEY_ENV_VAR;
+
+/// Workspace failures a caller can act on. They reach callers inside `anyhow::Error`; match on
+/// them with `error.downcast_ref::<WorkspaceError>()`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum WorkspaceError {
+    /// Neither `--workspace` nor `SEMTOOLS_WORKSPACE` names a workspace
+    NoActiveWorkspace,
+    /// The workspace is read-only and `action` would modify it
+    ReadOnly { name: String, action: String },
+    /// The workspace has no index for `space`, and being read-only it cannot get one
+    SpaceUnavailable {
+        name: String,
+        space: EmbedPreset,
+        default_space: EmbedPreset,
+    },
+    /// An embedding space was built with another model than its preset now uses
+    SpaceMismatch {
+        name: String,
+        space: EmbedPreset,
+        dir: String,
+        recorded_model: String,
+        recorded_dimensions: usize,
+        model: String,
+        dimensions: usize,
+    },
+    /// `root_dir` is a URI of a scheme workspaces cannot live in
+    UnsupportedRoot {
+        name: String,
+        root_dir: String,
+        scheme: String,
+    },
+    /// Another process kept the workspace's write lock for longer than `waited`
+    Locked { holder: String, waited: Duration },
+    /// The workspace is encrypted and no passphrase was given
+    PassphraseRequired,
+    /// The passphrase given does not decrypt the workspace
+    WrongPassphrase,
+}
+
+impl WorkspaceError {
+    /// Stable name of the error, reported as `error_type` by `--json`
+    pub fn error_type(&self) -> &'static str {
+        match self {
+            WorkspaceError::NoActiveWorkspace => "NoActiveWorkspace",
+            WorkspaceError::ReadOnly { .. } => "ReadOnly",
+            WorkspaceError::SpaceUnavailable { .. } => "SpaceUnavailable",
+            WorkspaceError::SpaceMismatch { .. } => "SpaceMismatch",
+            WorkspaceError::UnsupportedRoot { .. } => "UnsupportedRoot",
+            WorkspaceError::Locked { .. } => "Locked",
+            WorkspaceError::PassphraseRequired => "PassphraseRequired",
+            WorkspaceError::WrongPassphrase => "WrongPassphrase",
+        }
+    }
+}
+
+impl std::fmt::Display for WorkspaceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorkspaceError::NoActiveWorkspace => {
+                f.write_str("No active workspace. Run: workspace use <name>")
+            }
+            WorkspaceError::ReadOnly { name, action } => write!(
+                f,
+                "Workspace '{name}' is read-only, so it cannot {action}. Drop --read-only, or set \
+                 \"read_only\": false in the workspace's config.json."
+            ),
+            WorkspaceError::SpaceUnavailable {
+                name,
+                space,
+                default_space,
+            } => write!(
+                f,
+                "Workspace '{name}' has no '{space}' embedding space, and it is read-only so \
+                 one cannot be created. Drop --embed-preset to search its '{default_space}' space."
+            ),
+            WorkspaceError::SpaceMismatch {
+                name,
+                space,
+                dir,
+                recorded_model,
+                recorded_dimensions,
+                model,
+                dimensions,
+            } => write!(
+                f,
+                "The '{space}' embedding space of workspace '{name}' was built with \
+                 {recorded_model} ({recorded_dimensions} dimensions), but '{space}' now embeds \
+                 with {model} ({dimensions} dimensions). Its distances would be meaningless; \
+                 remove {dir} to rebuild the space."
+            ),
+            WorkspaceError::UnsupportedRoot {
+                name,
+                root_dir,
+                scheme,
+            } => write!(
+                f,
+                "Workspace '{name}' has root_dir '{root_dir}', but {scheme}:// workspaces are \
+                 not supported. Use a local path, or an s3:// or gs:// URI."
+            ),
+            WorkspaceError::Locked { holder, waited } => write!(
+                f,
+                "Timed out after {}s waiting for {holder} to finish writing to the workspace",
+                waited.as_secs()
+            ),
+            WorkspaceError::PassphraseRequired => write!(
+                f,
+                "This workspace is encrypted. Set {KEY_ENV_VAR} to its passphrase."
+            ),
+            WorkspaceError::WrongPassphrase => write!(
+                f,
+                "Wrong passphrase for encrypted workspace (check {KEY_ENV_VAR})"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WorkspaceError {}
@@ -0,0 +1,194 @@
+//! Consistency checks across the workspace index (`workspace fsck`).
+//!
+//! Document metadata, line embeddings and document embeddings live in separate shards, and a
+//! write interrupted between them (or an older semtools) can leave them disagreeing about
+//! which paths are indexed. Nothing else notices, so such paths are either searched without
+//! ever being refreshed or silently missing from results.
+use anyhow::Result;
+use std::collections::HashSet;
+
+use crate::search::dedupe::document_embedding;
+use crate::workspace::store::{DocEmbedding, Store};
+
+/// Inconsistencies found in an index
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FsckReport {
+    pub documents: usize,
+    pub line_embeddings: usize,
+    /// Paths with line embeddings but no document metadata
+    pub orphaned_lines: Vec<String>,
+    /// Paths with a document embedding but no document metadata
+    pub orphaned_doc_embeddings: Vec<String>,
+    /// Documents with metadata but no line embeddings, so they are never searched
+    pub documents_without_lines: Vec<String>,
+    /// Documents without a document embedding, which first-stage retrieval cannot rank
+    pub documents_without_doc_embedding: Vec<String>,
+    /// Documents with line embeddings of a size other than the workspace's
+    pub wrong_dimensions: Vec<String>,
+    /// Rows written since the payload indexes were last built
+    pub unindexed_rows: usize,
+}
+
+impl FsckReport {
+    /// No path has a problem. Unindexed rows are normal between optimizations.
+    pub fn is_clean(&self) -> bool {
+        self.problems() == 0
+    }
+
+    /// Number of paths with a problem
+    pub fn problems(&self) -> usize {
+        self.orphaned_lines.len()
+            + self.orphaned_doc_embeddings.len()
+            + self.documents_without_lines.len()
+            + self.documents_without_doc_embedding.len()
+            + self.wrong_dimensions.len()
+    }
+}
+
+/// Check `store`, whose embeddings should all have `dimensions` values
+pub fn check(store: &Store, dimensions: usize) -> Result<FsckReport> {
+    let documents: HashSet<String> = store.get_all_document_paths()?.into_iter().collect();
+    let line_counts = store.line_embedding_counts()?;
+    let doc_embeddings = store.doc_embedding_paths()?;
+
+    let mut report = FsckReport {
+        documents: documents.len(),
+        line_embeddings: line_counts.values().sum(),
+        unindexed_rows: store.maintenance_state().rows_since_optimize,
+        ..Default::default()
+    };
+    report.orphaned_lines = sorted(line_counts.keys().filter(|path| !documents.contains(*path)));
+    report.orphaned_doc_embeddings = sorted(
+        doc_embeddings
+            .iter()
+            .filter(|path| !documents.contains(*path)),
+    );
+    report.documents_without_lines = sorted(
+        documents
+            .iter()
+            .filter(|path| !line_counts.contains_key(*path)),
+    );
+
+    for path in sorted(
+        documents
+            .iter()
+            .filter(|path| line_counts.contains_key(*path)),
+    ) {
+        let vectors = store.line_vectors(&path)?;
+        if vectors.iter().any(|v| v.len() != dimensions) {
+            report.wrong_dimensions.push(path);
+        } else if !doc_embeddings.contains(&path) {
+            report.documents_without_doc_embedding.push(path);
+        }
+    }
+    Ok(report)
+}
+
+/// Fix what `check` found: orphaned rows are deleted, documents without lines or with
+/// embeddings of the wrong size are dropped so the next sync or search indexes them afresh,
+/// missing document embeddings are recomputed from the stored lines and the payload indexes
+/// are rebuilt. Returns the number of paths repaired.
+pub fn repair(store: &Store, report: &FsckReport) -> Result<usize> {
+    let _lock = store.write_lock()?;
+
+    store.delete_line_embeddings(&report.orphaned_lines)?;
+    store.delete_doc_embeddings(&report.orphaned_doc_embeddings)?;
+    let mut dropped = report.documents_without_lines.clone();
+    dropped.extend(report.wrong_dimensions.iter().cloned());
+    store.delete_documents(&dropped)?;
+
+    let mut doc_embeddings = Vec::new();
+    for path in &report.documents_without_doc_embedding {
+        if let Some(embedding) = document_embedding(&store.line_vectors(path)?) {
+            doc_embeddings.push(DocEmbedding {
+                path: path.clone(),
+                embedding,
+            });
+        }
+    }
+    store.upsert_doc_embeddings(&doc_embeddings)?;
+
+    if report.problems() > 0 || report.unindexed_rows > 0 {
+        store.optimize()?;
+    }
+    Ok(report.problems())
+}
+
+fn sorted<'a>(paths: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut paths: Vec<String> = paths.cloned().collect();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::DocumentMetadata;
+    use crate::search::normalize::Normalization;
+    use crate::workspace::store::{DocMeta, LINE_EMBEDDING_SIZE, LineEmbedding};
+    use std::collections::BTreeMap;
+    use tempfile::TempDir;
+
+    fn meta(path: &str) -> DocMeta {
+        DocMeta {
+            path: path.to_string(),
+            size_bytes: 10,
+            mtime: 1,
+            _version: crate::workspace::store::CURRENT_EMBEDDING_VERSION,
+            tags: BTreeMap::new(),
+            metadata: DocumentMetadata::default(),
+            normalization: Normalization::default(),
+        }
+    }
+
+    fn lines(path: &str, count: i32) -> Vec<LineEmbedding> {
+        (0..count)
+            .map(|line_number| LineEmbedding {
+                path: path.to_string(),
+                line_number,
+                embedding: vec![0.1; LINE_EMBEDDING_SIZE],
+                text: None,
+                table_line: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_check_and_repair() {
+        let temp_dir = TempDir::new().unwrap();
+        let store = Store::open(temp_dir.path().to_str().unwrap()).unwrap();
+
+        // A healthy document, one whose metadata write was interrupted, one whose lines were
+        // lost, and one indexed before document embeddings existed
+        store
+            .upsert_document_metadata(&[meta("/ok.txt"), meta("/no-lines.txt"), meta("/old.txt")])
+            .unwrap();
+        let mut all_lines = lines("/ok.txt", 2);
+        all_lines.extend(lines("/orphan.txt", 3));
+        all_lines.extend(lines("/old.txt", 2));
+        store.upsert_line_embeddings(&all_lines).unwrap();
+        store
+            .upsert_doc_embeddings(&[DocEmbedding {
+                path: "/ok.txt".to_string(),
+                embedding: vec![0.1; LINE_EMBEDDING_SIZE],
+            }])
+            .unwrap();
+
+        let report = check(&store, LINE_EMBEDDING_SIZE).unwrap();
+        assert_eq!(report.orphaned_lines, vec!["/orphan.txt"]);
+        assert_eq!(report.documents_without_lines, vec!["/no-lines.txt"]);
+        assert_eq!(report.documents_without_doc_embedding, vec!["/old.txt"]);
+        assert!(report.wrong_dimensions.is_empty());
+        assert_eq!(report.problems(), 3);
+
+        // Every stored vector is the wrong size for a 128-dimensional workspace
+        let mismatched = check(&store, 128).unwrap();
+        assert_eq!(mismatched.wrong_dimensions, vec!["/ok.txt", "/old.txt"]);
+
+        assert_eq!(repair(&store, &report).unwrap(), 3);
+        let after = check(&store, LINE_EMBEDDING_SIZE).unwrap();
+        assert!(after.is_clean(), "{after:?}");
+        assert_eq!(after.documents, 2);
+        assert_eq!(after.line_embeddings, 4);
+    }
+}
@@ -0,0 +1,138 @@
+//! Advisory write lock serializing index writes from concurrent semtools processes
+use anyhow::Result;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::workspace::error::WorkspaceError;
+
+/// File (inside the workspace directory) that writers lock
+pub const LOCK_FILE: &str = "write.lock";
+
+/// How long a writer waits for another process to finish before giving up
+pub(crate) const LOCK_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub(crate) const RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Exclusive lock on a workspace's `write.lock`, held until dropped.
+///
+/// This is an OS advisory lock, so it is released when the holding process exits, even if it
+/// crashes. The holder writes its pid into the file and clears it on release: a pid still there
+/// when the lock is taken means the previous writer died while writing, which is reported as a
+/// stale lock and taken over.
+#[derive(Debug)]
+pub struct WriteLock {
+    file: File,
+}
+
+impl WriteLock {
+    pub fn acquire(root_dir: &Path) -> Result<Self> {
+        Self::acquire_with_timeout(root_dir, LOCK_TIMEOUT)
+    }
+
+    pub fn acquire_with_timeout(root_dir: &Path, timeout: Duration) -> Result<Self> {
+        fs::create_dir_all(root_dir)?;
+        let path = root_dir.join(LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let started = Instant::now();
+        let mut waiting = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => {
+                    let holder = describe_holder(read_holder(&mut file));
+                    if started.elapsed() >= timeout {
+                        return Err(WorkspaceError::Locked {
+                            holder,
+                            waited: timeout,
+                        }
+                        .into());
+                    }
+                    if !waiting {
+                        eprintln!("Waiting for {holder} to finish writing to the workspace...");
+                        waiting = true;
+                    }
+                    std::thread::sleep(RETRY_INTERVAL);
+                }
+                Err(TryLockError::Error(e)) => return Err(e.into()),
+            }
+        }
+
+        if let Some(pid) = read_holder(&mut file) {
+            eprintln!(
+                "Warning: Taking over a stale workspace lock from process {pid}, which exited \
+                 while writing. Its last changes may be incomplete."
+            );
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(std::process::id().to_string().as_bytes())?;
+        file.flush()?;
+
+        Ok(Self { file })
+    }
+}
+
+impl Drop for WriteLock {
+    fn drop(&mut self) {
+        // An empty lock file tells the next writer that this one finished cleanly
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
+
+/// Pid recorded in the lock file, if any
+fn read_holder(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+fn describe_holder(pid: Option<u32>) -> String {
+    match pid {
+        Some(pid) => format!("another semtools process (pid {pid})"),
+        None => "another semtools process".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_second_writer_waits_for_the_first() {
+        let temp_dir = TempDir::new().unwrap();
+        let lock = WriteLock::acquire(temp_dir.path()).unwrap();
+        let content = fs::read_to_string(temp_dir.path().join(LOCK_FILE)).unwrap();
+        assert_eq!(content, std::process::id().to_string());
+
+        let err = WriteLock::acquire_with_timeout(temp_dir.path(), Duration::ZERO).unwrap_err();
+        assert!(err.to_string().contains("Timed out"));
+
+        drop(lock);
+        let content = fs::read_to_string(temp_dir.path().join(LOCK_FILE)).unwrap();
+        assert!(content.is_empty());
+        WriteLock::acquire_with_timeout(temp_dir.path(), Duration::ZERO).unwrap();
+    }
+
+    #[test]
+    fn test_stale_lock_is_taken_over() {
+        let temp_dir = TempDir::new().unwrap();
+        // A writer that crashed leaves its pid behind, but no OS lock
+        fs::write(temp_dir.path().join(LOCK_FILE), "999999").unwrap();
+
+        let lock = WriteLock::acquire_with_timeout(temp_dir.path(), Duration::ZERO).unwrap();
+        let content = fs::read_to_string(temp_dir.path().join(LOCK_FILE)).unwrap();
+        assert_eq!(content, std::process::id().to_string());
+        drop(lock);
+    }
+}
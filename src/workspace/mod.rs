@@ -1,16 +1,97 @@
-use anyhow::{Result, bail};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
-
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::metadata::DocumentMetadata;
+use crate::search::calibration::{CALIBRATION_SAMPLE_LINES, Calibration};
+use crate::search::model::EmbedPreset;
+use crate::usage::UsageRecord;
+use error::WorkspaceError;
+
+pub mod context;
+pub mod encryption;
+pub mod error;
+pub mod fsck;
+pub mod lock;
+#[cfg(feature = "fetch")]
+pub mod remote;
+pub mod snapshot;
 pub mod store;
 
 pub use store::WorkspaceStats;
+use store::{EmbeddingSpace, SPACES_DIR, STORE_ITEMS, Store};
+
+/// File (inside the workspace directory) holding the usage history, one record per run. It
+/// lives outside the index, so snapshot restores do not roll it back.
+pub const USAGE_FILE: &str = "usage.jsonl";
+
+/// File (inside the workspace directory) rewritten by every search, so `workspace list` can
+/// tell when a workspace was last used even if searching it wrote nothing else
+const LAST_USED_FILE: &str = "last_used";
+
+/// Set by `--read-only`, making every workspace opened by this process read-only
+static READ_ONLY_OVERRIDE: OnceLock<bool> = OnceLock::new();
+
+/// Treat every workspace as read-only for the rest of the process (`--read-only`)
+pub fn set_read_only_override() {
+    let _ = READ_ONLY_OVERRIDE.set(true);
+}
+
+/// Set by `--embed-preset`, selecting the embedding space workspaces are searched and indexed in
+static SPACE_OVERRIDE: OnceLock<EmbedPreset> = OnceLock::new();
+
+/// Use `preset`'s embedding space of every workspace for the rest of the process
+pub fn set_space_override(preset: EmbedPreset) {
+    let _ = SPACE_OVERRIDE.set(preset);
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkspaceConfig {
     pub name: String,
-    pub root_dir: String,         // e.g., ~/.semtools/my-workspace
-    pub in_batch_size: usize,     // default 5_000
+    pub root_dir: String, // e.g., ~/.local/share/semtools/workspaces/my-workspace
+    pub in_batch_size: usize, // default 5_000
     pub oversample_factor: usize, // default 3
+    /// Documents kept by first-stage document retrieval before line search (0 disables)
+    #[serde(default = "default_doc_top_k")]
+    pub doc_top_k: usize, // default 100
+    /// Optimize the index once this many rows were written since the last optimization (0 disables)
+    #[serde(default = "default_optimize_after_rows")]
+    pub optimize_after_rows: usize, // default 100_000
+    /// Index is encrypted at rest (see `encryption`); the passphrase comes from SEMTOOLS_WORKSPACE_KEY
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Embedding preset the index was built with. A workspace stays bound to it, since
+    /// embeddings from different models cannot be compared.
+    #[serde(default)]
+    pub embed_preset: EmbedPreset,
+    /// Size of the stored embeddings (recorded from the preset at creation)
+    #[serde(default = "default_embedding_dimensions")]
+    pub embedding_dimensions: usize,
+    /// Never write to the index: searches embed new or changed files in memory instead, and
+    /// commands that modify the index fail (also `--read-only` for a single command)
+    #[serde(default)]
+    pub read_only: bool,
+    /// Keep each line's text in the index, so results are served even when the original files
+    /// moved or are gone (costs roughly the size of the indexed text)
+    #[serde(default)]
+    pub store_text: bool,
+    /// The `s3://` or `gs://` URI `root_dir` is set to, for a workspace used through a local
+    /// replica (see `remote`). While it is open, `root_dir` holds the replica's directory.
+    #[serde(skip)]
+    pub remote_root: Option<String>,
+}
+
+fn default_embedding_dimensions() -> usize {
+    EmbedPreset::default().dimensions()
+}
+
+fn default_optimize_after_rows() -> usize {
+    100_000
+}
+
+fn default_doc_top_k() -> usize {
+    100
 }
 
 impl Default for WorkspaceConfig {
@@ -20,6 +101,14 @@ impl Default for WorkspaceConfig {
             root_dir: String::new(),
             in_batch_size: 5_000,
             oversample_factor: 3,
+            doc_top_k: default_doc_top_k(),
+            optimize_after_rows: default_optimize_after_rows(),
+            encrypted: false,
+            embed_preset: EmbedPreset::default(),
+            embedding_dimensions: default_embedding_dimensions(),
+            read_only: false,
+            store_text: false,
+            remote_root: None,
         }
     }
 }
@@ -29,6 +118,20 @@ pub struct Workspace {
     pub config: WorkspaceConfig,
 }
 
+/// A workspace as shown by `workspace list`
+#[derive(Debug, Clone)]
+pub struct WorkspaceSummary {
+    pub name: String,
+    pub root_dir: String,
+    /// Documents in the workspace's own embedding space, `None` when its index cannot be
+    /// opened (e.g. encrypted and no passphrase given)
+    pub documents: Option<usize>,
+    /// Bytes on disk of the workspace directory and, when it lives elsewhere, its index
+    pub size_bytes: u64,
+    /// Unix timestamp (seconds) of the last search or write
+    pub last_used: Option<u64>,
+}
+
 impl Workspace {
     pub fn open(workspace_name: Option<&str>) -> Result<Self> {
         let active_workspace = Self::active(workspace_name)?;
@@ -37,20 +140,257 @@ impl Workspace {
             .ok()
             .and_then(|s| serde_json::from_str::<WorkspaceConfig>(&s).ok());
         let mut config = cfg.unwrap_or_default();
-        if config.root_dir.is_empty() {
+        // Workspaces created before the move to the XDG data directory still record their
+        // old root, which has been moved along with them
+        let legacy_root = crate::paths::legacy_dir()?
+            .join("workspaces")
+            .join(&active_workspace);
+        if config.root_dir.is_empty()
+            || (std::path::Path::new(&config.root_dir) == legacy_root && !legacy_root.exists())
+        {
             config.root_dir = Self::root_path(&active_workspace)?;
         }
         if config.name.is_empty() || config.name == "default" {
             config.name = active_workspace;
         }
-        Ok(Self { config })
+        let mut ws = Self { config };
+        match remote_uri_scheme(&ws.config.root_dir).map(str::to_string) {
+            None => {}
+            Some(scheme) if scheme == "file" => {
+                ws.config.root_dir = file_uri_path(&ws.config.root_dir);
+            }
+            #[cfg(feature = "fetch")]
+            Some(scheme) if remote::is_supported(&scheme) => {
+                let url = std::mem::take(&mut ws.config.root_dir);
+                ws.config.root_dir =
+                    remote::open_replica(&ws.config.name, &url, !ws.is_read_only())?;
+                ws.config.remote_root = Some(url);
+            }
+            Some(scheme) => {
+                return Err(WorkspaceError::UnsupportedRoot {
+                    scheme,
+                    name: ws.config.name,
+                    root_dir: ws.config.root_dir,
+                }
+                .into());
+            }
+        }
+        Ok(ws)
+    }
+
+    /// The root as configured: the object-store URI of a remote workspace, else the directory
+    /// of its index
+    pub fn root_uri(&self) -> &str {
+        self.config
+            .remote_root
+            .as_deref()
+            .unwrap_or(&self.config.root_dir)
+    }
+
+    /// Whether a workspace with this name has been created
+    pub fn exists(name: &str) -> Result<bool> {
+        Ok(std::path::Path::new(&Self::config_path_for(name)?).exists())
+    }
+
+    /// Open the index of the embedding space in use (see `space`), creating it if needed
+    pub fn open_store(&self) -> Result<Store> {
+        self.open_space(self.space())
+    }
+
+    /// The embedding space searched and indexed: the one selected with `--embed-preset`, or
+    /// the preset the workspace was created with
+    pub fn space(&self) -> EmbedPreset {
+        SPACE_OVERRIDE
+            .get()
+            .copied()
+            .unwrap_or(self.config.embed_preset)
+    }
+
+    /// Size of the embeddings in `preset`'s space
+    pub fn space_dimensions(&self, preset: EmbedPreset) -> usize {
+        if preset == self.config.embed_preset {
+            self.config.embedding_dimensions
+        } else {
+            preset.dimensions()
+        }
+    }
+
+    /// Index directory of `preset`'s space. The workspace's own preset is indexed in the root
+    /// directory, as it was before workspaces held several spaces.
+    pub fn space_dir(&self, preset: EmbedPreset) -> String {
+        if preset == self.config.embed_preset {
+            self.config.root_dir.clone()
+        } else {
+            std::path::Path::new(&self.config.root_dir)
+                .join(SPACES_DIR)
+                .join(preset.as_str())
+                .to_string_lossy()
+                .to_string()
+        }
+    }
+
+    /// Presets with an index in this workspace, its own preset first
+    pub fn spaces(&self) -> Vec<EmbedPreset> {
+        let mut spaces = vec![self.config.embed_preset];
+        for preset in [
+            EmbedPreset::Fast,
+            EmbedPreset::Balanced,
+            EmbedPreset::Quality,
+        ] {
+            if preset != self.config.embed_preset
+                && std::path::Path::new(&self.space_dir(preset)).exists()
+            {
+                spaces.push(preset);
+            }
+        }
+        spaces
+    }
+
+    /// Open the index of `preset`'s space, creating it if needed. The model each index was
+    /// built with is recorded in it, and opening it for a different model is an error, since
+    /// distances between embeddings of different models are meaningless.
+    pub fn open_space(&self, preset: EmbedPreset) -> Result<Store> {
+        let dir = self.space_dir(preset);
+        if preset != self.config.embed_preset && !std::path::Path::new(&dir).exists() {
+            if self.is_read_only() {
+                return Err(WorkspaceError::SpaceUnavailable {
+                    name: self.config.name.clone(),
+                    space: preset,
+                    default_space: self.config.embed_preset,
+                }
+                .into());
+            }
+            // New spaces of an encrypted workspace are encrypted too
+            if encryption::is_encrypted(std::path::Path::new(&self.config.root_dir)) {
+                encryption::enable_encryption(&dir, &STORE_ITEMS)?;
+            }
+        }
+
+        let expected = EmbeddingSpace {
+            model: preset.model_name().to_string(),
+            dimensions: self.space_dimensions(preset),
+        };
+        let store = Store::open_with_dimensions(&dir, expected.dimensions)?;
+        match store.embedding_space() {
+            Some(recorded) if recorded != expected => {
+                return Err(WorkspaceError::SpaceMismatch {
+                    name: self.config.name.clone(),
+                    space: preset,
+                    dir,
+                    recorded_model: recorded.model,
+                    recorded_dimensions: recorded.dimensions,
+                    model: expected.model,
+                    dimensions: expected.dimensions,
+                }
+                .into());
+            }
+            Some(_) => {}
+            None if !self.is_read_only() => store.save_embedding_space(&expected)?,
+            None => {}
+        }
+        Ok(store)
+    }
+
+    /// Whether the index must not be written to, by its config or `--read-only`
+    pub fn is_read_only(&self) -> bool {
+        self.config.read_only || READ_ONLY_OVERRIDE.get().copied().unwrap_or(false)
+    }
+
+    /// Fail with an explanation when the workspace is read-only and `action` would modify it
+    pub fn ensure_writable(&self, action: &str) -> Result<()> {
+        if self.is_read_only() {
+            return Err(WorkspaceError::ReadOnly {
+                name: self.config.name.clone(),
+                action: action.to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Those of `files` that are gone from disk but whose lines are kept in the index
+    /// (`store_text`), so they can still be searched and shown
+    pub fn files_served_from_index(&self, files: &[String]) -> Result<Vec<String>> {
+        if !self.config.store_text {
+            return Ok(Vec::new());
+        }
+        let missing: Vec<String> = files
+            .iter()
+            .filter(|f| !crate::archive::local_path(f).exists())
+            .cloned()
+            .collect();
+        if missing.is_empty() {
+            return Ok(missing);
+        }
+        let indexed = self.open_store()?.get_existing_docs(&missing)?;
+        Ok(missing
+            .into_iter()
+            .filter(|f| indexed.contains_key(f))
+            .collect())
+    }
+
+    /// Metadata of those of `files` that were indexed with some, to show with results
+    pub fn document_metadata(&self, files: &[String]) -> Result<HashMap<String, DocumentMetadata>> {
+        Ok(self
+            .open_store()?
+            .get_existing_docs(files)?
+            .into_iter()
+            .filter(|(_, meta)| !meta.metadata.is_empty())
+            .map(|(path, meta)| (path, meta.metadata))
+            .collect())
+    }
+
+    /// The preset to embed with for this workspace: the requested one, whose embeddings are
+    /// kept in a space of their own, or the preset the workspace was created with
+    pub fn embed_preset(&self, requested: Option<EmbedPreset>) -> EmbedPreset {
+        requested.unwrap_or(self.config.embed_preset)
+    }
+
+    /// Distance calibration for normalized scores. It is computed from a sample of the index
+    /// and saved, then recomputed once the index has grown or shrunk a lot. `None` for an
+    /// index with fewer than two non-blank lines.
+    pub fn calibration(&self) -> Result<Option<Calibration>> {
+        let store = self.open_store()?;
+        let lines = store.count_line_embeddings()?;
+        if let Some(calibration) = store.calibration()
+            && !calibration.is_stale(lines)
+        {
+            return Ok(Some(calibration));
+        }
+
+        let sample = store.sample_line_embeddings(CALIBRATION_SAMPLE_LINES)?;
+        let calibration = Calibration::from_embeddings(&sample, lines);
+        if let Some(calibration) = &calibration
+            && !self.is_read_only()
+        {
+            store.save_calibration(calibration)?;
+        }
+        Ok(calibration)
+    }
+
+    /// Append one run's usage to the workspace's usage history
+    pub fn record_usage(&self, record: &UsageRecord) -> Result<()> {
+        use std::io::Write;
+
+        let dir = std::path::Path::new(&self.config.root_dir);
+        std::fs::create_dir_all(dir)?;
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(USAGE_FILE))?;
+        writeln!(file, "{}", serde_json::to_string(record)?)?;
+        Ok(())
     }
 
     pub fn save(&self) -> Result<()> {
         let cfg_path = Self::config_path_for(&self.config.name)?;
         let parent = std::path::Path::new(&cfg_path).parent().unwrap();
         std::fs::create_dir_all(parent)?;
-        let s = serde_json::to_string_pretty(&self.config)?;
+        let mut config = self.config.clone();
+        if let Some(remote_root) = config.remote_root.take() {
+            config.root_dir = remote_root;
+        }
+        let s = serde_json::to_string_pretty(&config)?;
         std::fs::write(cfg_path, s)?;
         Ok(())
     }
@@ -61,7 +401,7 @@ impl Workspace {
             Some(a) => a.to_string(),
         };
         if active.is_empty() {
-            bail!("No active workspace. Run: workspace use <name>");
+            return Err(WorkspaceError::NoActiveWorkspace.into());
         }
         Self::root_path(&active)
     }
@@ -72,28 +412,181 @@ impl Workspace {
             Some(a) => a.to_string(),
         };
         if active.is_empty() {
-            bail!("No active workspace. Run: workspace use <name>");
+            return Err(WorkspaceError::NoActiveWorkspace.into());
         }
         Ok(active)
     }
+
+    /// Record that the workspace is being used, for `workspace list`. Read-only workspaces
+    /// are left untouched, and failing to record it never fails the caller.
+    pub fn mark_used(&self) {
+        if self.is_read_only() {
+            return;
+        }
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let _ = std::fs::write(
+            std::path::Path::new(&self.config.root_dir).join(LAST_USED_FILE),
+            secs.to_string(),
+        );
+    }
+
+    /// Name, size, document count and last use of the workspace
+    pub fn summary(&self) -> WorkspaceSummary {
+        let dir = Self::workspaces_dir()
+            .map(|dir| dir.join(&self.config.name))
+            .unwrap_or_default();
+        let root = std::path::Path::new(&self.config.root_dir);
+        let mut size_bytes = dir_size(&dir);
+        if !root.starts_with(&dir) {
+            size_bytes += dir_size(root);
+        }
+        WorkspaceSummary {
+            name: self.config.name.clone(),
+            root_dir: self.root_uri().to_string(),
+            documents: self
+                .open_space(self.config.embed_preset)
+                .and_then(|store| store.get_stats())
+                .map(|stats| stats.total_documents)
+                .ok(),
+            size_bytes,
+            last_used: [dir.as_path(), root]
+                .iter()
+                .filter_map(|dir| newest_mtime(dir))
+                .max(),
+        }
+    }
+
+    /// Summaries of every workspace, sorted by name. Workspaces whose config cannot be read
+    /// are listed by name only.
+    pub fn list() -> Vec<WorkspaceSummary> {
+        Self::list_names()
+            .into_iter()
+            .map(|name| match Self::open(Some(&name)) {
+                Ok(ws) => ws.summary(),
+                Err(_) => WorkspaceSummary {
+                    root_dir: Self::root_path(&name).unwrap_or_default(),
+                    name,
+                    documents: None,
+                    size_bytes: 0,
+                    last_used: None,
+                },
+            })
+            .collect()
+    }
+
+    /// Delete the workspace directory, holding the write lock so no other process is
+    /// writing to it meanwhile. An index kept outside the workspace directory (a custom
+    /// `root_dir`, or an object store) is left in place; its path or URI is returned.
+    pub fn delete(&self) -> Result<Option<String>> {
+        self.ensure_writable("be deleted")?;
+        // Only a directory of the workspaces directory, never a path a name could point at
+        if !Self::list_names().contains(&self.config.name) {
+            anyhow::bail!("Workspace '{}' does not exist", self.config.name);
+        }
+        let dir = Self::workspaces_dir()?.join(&self.config.name);
+        let root = std::path::Path::new(&self.config.root_dir);
+        let lock_dir = if root.exists() { root } else { dir.as_path() };
+        let _lock = lock::WriteLock::acquire_with_timeout(lock_dir, std::time::Duration::ZERO)?;
+        std::fs::remove_dir_all(&dir)?;
+        Ok((!root.starts_with(&dir) && root.exists()).then(|| self.root_uri().to_string()))
+    }
+
+    /// Run index maintenance if the workspace crossed its `optimize_after_rows` threshold.
+    /// Meant to be called after results have been returned so searches never wait on it.
+    pub fn run_scheduled_maintenance(workspace_name: Option<&str>) -> Result<()> {
+        let ws = Self::open(workspace_name)?;
+        if ws.is_read_only() {
+            return Ok(());
+        }
+        let store = ws.open_store()?;
+        if store.needs_optimize(ws.config.optimize_after_rows) {
+            eprintln!("Optimizing workspace '{}'...", ws.config.name);
+            store.optimize()?;
+        }
+        Ok(())
+    }
+}
+
+/// Bytes of the files under `dir` (0 if it cannot be read)
+fn dir_size(dir: &std::path::Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| match entry.metadata() {
+            Ok(meta) if meta.is_dir() => dir_size(&entry.path()),
+            Ok(meta) => meta.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
+
+/// Unix timestamp of the most recently modified entry directly inside `dir`
+fn newest_mtime(dir: &std::path::Path) -> Option<u64> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.metadata().ok()?.modified().ok())
+        .filter_map(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .max()
+}
+
+/// Return the scheme of a URI such as `s3://bucket/prefix`, `gs://bucket` or `file:///srv/ws`
+fn remote_uri_scheme(root_dir: &str) -> Option<&str> {
+    let (scheme, _) = root_dir.split_once("://")?;
+    let is_scheme = !scheme.is_empty()
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+    is_scheme.then_some(scheme)
+}
+
+/// The path of a `file://` URI (`file:///srv/ws`, `file://localhost/srv/ws`, or
+/// `file:///C:/ws` on Windows)
+fn file_uri_path(uri: &str) -> String {
+    let path = uri.trim_start_matches("file://");
+    let path = path.strip_prefix("localhost").unwrap_or(path);
+    match path.as_bytes() {
+        [b'/', drive, b':', ..] if drive.is_ascii_alphabetic() => path[1..].to_string(),
+        _ => path.to_string(),
+    }
 }
 
 impl Workspace {
+    /// Directory holding every workspace, `$XDG_DATA_HOME/semtools/workspaces`
+    fn workspaces_dir() -> Result<std::path::PathBuf> {
+        crate::paths::data_path("workspaces")
+    }
+
     pub fn root_path(name: &str) -> Result<String> {
-        let home = dirs::home_dir().ok_or_else(|| anyhow::anyhow!("No home dir found?"))?;
-        Ok(home
-            .join(".semtools")
-            .join("workspaces")
+        Ok(Self::workspaces_dir()?
             .join(name)
             .to_string_lossy()
             .to_string())
     }
 
+    /// Names of the workspaces under the workspaces directory, sorted
+    pub fn list_names() -> Vec<String> {
+        let Ok(dir) = Self::workspaces_dir() else {
+            return Vec::new();
+        };
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_dir())
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
     fn config_path_for(name: &str) -> Result<String> {
-        let home = dirs::home_dir().ok_or_else(|| anyhow::anyhow!("No home dir found?"))?;
-        Ok(home
-            .join(".semtools")
-            .join("workspaces")
+        Ok(Self::workspaces_dir()?
             .join(name)
             .join("config.json")
             .to_string_lossy()
@@ -114,6 +607,90 @@ mod tests {
         assert_eq!(config.root_dir, "");
         assert_eq!(config.in_batch_size, 5_000);
         assert_eq!(config.oversample_factor, 3);
+        assert_eq!(config.doc_top_k, 100);
+        assert_eq!(config.optimize_after_rows, 100_000);
+    }
+
+    #[test]
+    fn test_workspace_config_missing_optimize_threshold() {
+        // Configs written before `optimize_after_rows` existed should still load
+        let json =
+            r#"{"name":"old","root_dir":"/tmp/old","in_batch_size":10,"oversample_factor":2}"#;
+        let config: WorkspaceConfig = serde_json::from_str(json).expect("Failed to deserialize");
+
+        assert_eq!(config.optimize_after_rows, 100_000);
+        assert_eq!(config.doc_top_k, 100);
+        // Workspaces created before presets existed used the default model
+        assert_eq!(config.embed_preset, EmbedPreset::Quality);
+        assert_eq!(config.embedding_dimensions, 256);
+    }
+
+    #[test]
+    fn test_embedding_spaces() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let ws = Workspace {
+            config: WorkspaceConfig {
+                name: "code".to_string(),
+                root_dir: temp_dir.path().to_string_lossy().to_string(),
+                embed_preset: EmbedPreset::Fast,
+                embedding_dimensions: 128,
+                ..Default::default()
+            },
+        };
+        assert_eq!(ws.embed_preset(None), EmbedPreset::Fast);
+        assert_eq!(
+            ws.embed_preset(Some(EmbedPreset::Quality)),
+            EmbedPreset::Quality
+        );
+
+        // The workspace's own preset lives in the root, others in spaces/<preset>
+        assert_eq!(ws.space_dir(EmbedPreset::Fast), ws.config.root_dir);
+        assert!(
+            ws.space_dir(EmbedPreset::Quality)
+                .ends_with("spaces/quality")
+        );
+        assert_eq!(ws.space_dimensions(EmbedPreset::Quality), 256);
+        assert_eq!(ws.spaces(), vec![EmbedPreset::Fast]);
+
+        let store = ws.open_space(EmbedPreset::Quality).unwrap();
+        assert_eq!(
+            store.embedding_space().unwrap(),
+            EmbeddingSpace {
+                model: EmbedPreset::Quality.model_name().to_string(),
+                dimensions: 256,
+            }
+        );
+        drop(store);
+        assert_eq!(ws.spaces(), vec![EmbedPreset::Fast, EmbedPreset::Quality]);
+
+        // A space recorded for another model is refused rather than searched
+        let store = ws.open_space(EmbedPreset::Fast).unwrap();
+        store
+            .save_embedding_space(&EmbeddingSpace {
+                model: "someone/other-model".to_string(),
+                dimensions: 128,
+            })
+            .unwrap();
+        drop(store);
+        let err = ws.open_space(EmbedPreset::Fast).err().unwrap().to_string();
+        assert!(err.contains("was built with someone/other-model"));
+    }
+
+    #[test]
+    fn test_summary() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let ws = Workspace {
+            config: WorkspaceConfig {
+                name: "semtools-test-summary".to_string(),
+                root_dir: temp_dir.path().to_string_lossy().to_string(),
+                ..Default::default()
+            },
+        };
+        ws.mark_used();
+        let summary = ws.summary();
+        assert_eq!(summary.documents, Some(0));
+        assert!(summary.size_bytes > 0);
+        assert!(summary.last_used.is_some());
     }
 
     #[test]
@@ -123,6 +700,14 @@ mod tests {
             root_dir: "/tmp/test".to_string(),
             in_batch_size: 1000,
             oversample_factor: 2,
+            doc_top_k: 20,
+            optimize_after_rows: 50,
+            encrypted: false,
+            embed_preset: EmbedPreset::Fast,
+            embedding_dimensions: 128,
+            read_only: true,
+            store_text: true,
+            remote_root: None,
         };
 
         // Test serialization
@@ -138,6 +723,40 @@ mod tests {
         assert_eq!(deserialized.root_dir, config.root_dir);
         assert_eq!(deserialized.in_batch_size, config.in_batch_size);
         assert_eq!(deserialized.oversample_factor, config.oversample_factor);
+        assert_eq!(deserialized.optimize_after_rows, config.optimize_after_rows);
+        assert!(deserialized.read_only);
+        assert!(deserialized.store_text);
+
+        // Configs written before the option existed are writable
+        let legacy: WorkspaceConfig = serde_json::from_str(
+            r#"{"name": "old", "root_dir": "/tmp/old", "in_batch_size": 5000, "oversample_factor": 3}"#,
+        )
+        .unwrap();
+        assert!(!legacy.read_only);
+        assert!(!legacy.store_text);
+    }
+
+    #[test]
+    fn test_read_only_workspace_refuses_writes() {
+        let mut ws = Workspace {
+            config: WorkspaceConfig {
+                name: "shared".to_string(),
+                ..Default::default()
+            },
+        };
+        assert!(ws.ensure_writable("sync files").is_ok());
+
+        ws.config.read_only = true;
+        assert!(ws.is_read_only());
+        let err = ws.ensure_writable("sync files").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<WorkspaceError>(),
+            Some(WorkspaceError::ReadOnly { .. })
+        ));
+        assert!(
+            err.to_string()
+                .contains("'shared' is read-only, so it cannot sync files")
+        );
     }
 
     #[test]
@@ -199,11 +818,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_remote_uri_scheme() {
+        assert_eq!(remote_uri_scheme("s3://bucket/ws"), Some("s3"));
+        assert_eq!(remote_uri_scheme("gs://bucket"), Some("gs"));
+        assert_eq!(remote_uri_scheme("/home/me/.semtools/workspaces/ws"), None);
+        assert_eq!(remote_uri_scheme("C:\\Users\\me\\ws"), None);
+        assert_eq!(remote_uri_scheme("file:///srv/ws"), Some("file"));
+
+        assert_eq!(file_uri_path("file:///srv/ws"), "/srv/ws");
+        assert_eq!(file_uri_path("file://localhost/srv/ws"), "/srv/ws");
+        assert_eq!(file_uri_path("file:///C:/ws"), "C:/ws");
+    }
+
     #[test]
     fn test_workspace_root_path() {
         let path = Workspace::root_path("my-workspace").expect("Failed to get root path");
 
-        assert!(path.contains(".semtools"));
+        assert!(path.contains("semtools"));
         assert!(path.contains("workspaces"));
         assert!(path.contains("my-workspace"));
     }
@@ -212,7 +844,7 @@ mod tests {
     fn test_workspace_config_path() {
         let path = Workspace::config_path_for("my-workspace").expect("Failed to get config path");
 
-        assert!(path.contains(".semtools"));
+        assert!(path.contains("semtools"));
         assert!(path.contains("workspaces"));
         assert!(path.contains("my-workspace"));
         assert!(path.ends_with("config.json"));
@@ -236,6 +868,14 @@ mod tests {
                 root_dir: Workspace::root_path(workspace_name).expect("Failed to get root path"),
                 in_batch_size: 456,
                 oversample_factor: 7,
+                doc_top_k: 12,
+                optimize_after_rows: 89,
+                encrypted: false,
+                embed_preset: EmbedPreset::Balanced,
+                embedding_dimensions: 256,
+                read_only: false,
+                store_text: false,
+                remote_root: None,
             },
         };
 
@@ -261,6 +901,7 @@ mod tests {
             loaded_config.oversample_factor,
             workspace.config.oversample_factor
         );
+        assert_eq!(loaded_config.embed_preset, EmbedPreset::Balanced);
 
         // Clean up - remove the test config file
         let _ = fs::remove_file(&config_path);
@@ -0,0 +1,107 @@
+//! Workspaces whose `root_dir` is an `s3://` or `gs://` URI, so a team can share one index.
+//!
+//! The index is memory-mapped, so it is used through a local replica in
+//! `~/.cache/semtools/workspaces/<name>-<hash of the URI>`: a process pulls the replica when it first opens the
+//! workspace, and pushes what it wrote once its command is done (see `crate::s3` for the
+//! transfers). A push that finds the objects changed by another writer since the pull uploads
+//! nothing, and the next pull discards the writes it held.
+use anyhow::Result;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+
+use super::lock::{LOCK_FILE, WriteLock};
+use super::{LAST_USED_FILE, USAGE_FILE};
+
+/// Top-level entries of a replica that belong to this machine and are never uploaded
+const LOCAL_ONLY: [&str; 3] = [LOCK_FILE, LAST_USED_FILE, USAGE_FILE];
+
+/// A replica pulled by this process
+struct Replica {
+    url: String,
+    dir: PathBuf,
+    /// Opened by a workspace that is not read-only, so its writes are pushed
+    writable: bool,
+}
+
+static REPLICAS: Mutex<Vec<Replica>> = Mutex::new(Vec::new());
+
+/// Whether workspaces can be kept in the object store of URI `scheme`
+pub fn is_supported(scheme: &str) -> bool {
+    matches!(scheme, "s3" | "gs")
+}
+
+/// The directory of the replica of workspace `name`, whose root is `url`. It is pulled the
+/// first time this process opens it.
+pub fn open_replica(name: &str, url: &str, writable: bool) -> Result<String> {
+    let dir = replica_dir(name, url)?;
+    let mut replicas = REPLICAS.lock().unwrap_or_else(PoisonError::into_inner);
+    match replicas.iter_mut().find(|replica| replica.dir == dir) {
+        Some(replica) => replica.writable |= writable,
+        None => {
+            let _lock = WriteLock::acquire(&dir)?;
+            block_on(crate::s3::pull_replica(url, &dir, &LOCAL_ONLY))?;
+            replicas.push(Replica {
+                url: url.to_string(),
+                dir: dir.clone(),
+                writable,
+            });
+        }
+    }
+    Ok(dir.to_string_lossy().to_string())
+}
+
+/// `<name>-<hash of url>`, so that workspaces of the same name with different roots, or a
+/// workspace whose root changed, get their own replica
+fn replica_dir(name: &str, url: &str) -> Result<PathBuf> {
+    let hash = url.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    });
+    Ok(crate::paths::cache_path("workspaces")?.join(format!("{name}-{:08x}", hash as u32)))
+}
+
+/// Upload what this process wrote to the replicas it opened. Called once a command is done,
+/// after its stores were dropped; replicas opened afterwards are pulled again.
+pub async fn push_replicas() -> Result<()> {
+    let replicas = std::mem::take(&mut *REPLICAS.lock().unwrap_or_else(PoisonError::into_inner));
+    for replica in replicas.into_iter().filter(|replica| replica.writable) {
+        let _lock = WriteLock::acquire(&replica.dir)?;
+        crate::s3::push_replica(&replica.url, &replica.dir, &LOCAL_ONLY).await?;
+    }
+    Ok(())
+}
+
+/// Run `future` to completion from synchronous code, on a thread of its own so it works
+/// whether or not the caller is inside a runtime
+fn block_on<T: Send>(future: impl Future<Output = Result<T>> + Send) -> Result<T> {
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?
+                    .block_on(future)
+            })
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replica_dir_depends_on_root() {
+        let dir = replica_dir("docs", "s3://lake/team/docs").unwrap();
+        assert_eq!(dir, replica_dir("docs", "s3://lake/team/docs").unwrap());
+        assert_ne!(dir, replica_dir("docs", "gs://lake/team/docs").unwrap());
+        assert_ne!(dir, replica_dir("docs", "s3://lake/other/docs").unwrap());
+        assert!(
+            dir.file_name()
+                .unwrap()
+                .to_string_lossy()
+                .starts_with("docs-")
+        );
+    }
+}
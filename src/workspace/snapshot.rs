@@ -0,0 +1,267 @@
+//! Point-in-time copies of a workspace's index, for rolling back a bad ingestion
+use anyhow::{Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::workspace::encryption::{ENCRYPTION_FILE, SEALED_DIR};
+use crate::workspace::lock::WriteLock;
+use crate::workspace::store::{SPACES_DIR, STORE_ITEMS, Store};
+
+/// Directory (inside the workspace root) holding all snapshots
+const SNAPSHOTS_DIR: &str = "snapshots";
+
+/// Metadata file written alongside each snapshot
+const SNAPSHOT_INFO_FILE: &str = "snapshot.json";
+
+/// Workspace files and directories captured by a snapshot (the plain index, or the sealed
+/// index of an encrypted workspace, and the indexes of its other embedding spaces)
+const SNAPSHOT_ITEMS: [&str; STORE_ITEMS.len() + 3] = {
+    let mut items = [""; STORE_ITEMS.len() + 3];
+    let mut i = 0;
+    while i < STORE_ITEMS.len() {
+        items[i] = STORE_ITEMS[i];
+        i += 1;
+    }
+    items[i] = SPACES_DIR;
+    items[i + 1] = SEALED_DIR;
+    items[i + 2] = ENCRYPTION_FILE;
+    items
+};
+
+/// Directories (inside the workspace root) a restore stages the snapshot's items in, and moves
+/// the replaced items to until the swap is done
+const RESTORE_STAGING_DIR: &str = ".restore.tmp";
+const RESTORE_REPLACED_DIR: &str = ".restore.old";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub name: String,
+    /// Unix timestamp (seconds) of when the snapshot was taken
+    pub created_at: u64,
+    pub total_documents: usize,
+}
+
+fn snapshots_dir(root_dir: &str) -> PathBuf {
+    Path::new(root_dir).join(SNAPSHOTS_DIR)
+}
+
+fn validate_snapshot_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name != "."
+        && name != ".."
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.');
+    if !valid {
+        bail!("Invalid snapshot name '{name}'. Use letters, digits, '-', '_' and '.' only.");
+    }
+    Ok(())
+}
+
+fn copy_recursive(src: &Path, dst: &Path) -> Result<()> {
+    if src.is_dir() {
+        fs::create_dir_all(dst)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &dst.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(src, dst)?;
+    }
+    Ok(())
+}
+
+/// Copy the current index of a workspace into `snapshots/<name>`.
+///
+/// Pending writes are flushed first, and other processes wait to write until the copy is done.
+pub fn create_snapshot(root_dir: &str, name: &str) -> Result<SnapshotInfo> {
+    validate_snapshot_name(name)?;
+
+    let snapshot_dir = snapshots_dir(root_dir).join(name);
+    if snapshot_dir.exists() {
+        bail!("Snapshot '{name}' already exists");
+    }
+
+    let store = Store::open(root_dir)?;
+    let write_lock = store.write_lock()?;
+    store.flush_documents();
+    store.flush_line_embeddings();
+    let total_documents = store.count_documents()?;
+
+    // Copy into a staging directory first so a failed copy never looks like a snapshot
+    let staging_dir = snapshots_dir(root_dir).join(format!(".{name}.tmp"));
+    if staging_dir.exists() {
+        fs::remove_dir_all(&staging_dir)?;
+    }
+    fs::create_dir_all(&staging_dir)?;
+
+    for item in SNAPSHOT_ITEMS {
+        let src = Path::new(root_dir).join(item);
+        if src.exists() {
+            copy_recursive(&src, &staging_dir.join(item))?;
+        }
+    }
+    drop(write_lock);
+    drop(store);
+
+    let info = SnapshotInfo {
+        name: name.to_string(),
+        created_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        total_documents,
+    };
+    fs::write(
+        staging_dir.join(SNAPSHOT_INFO_FILE),
+        serde_json::to_string_pretty(&info)?,
+    )?;
+    fs::rename(&staging_dir, &snapshot_dir)?;
+
+    Ok(info)
+}
+
+/// List the snapshots of a workspace, oldest first
+pub fn list_snapshots(root_dir: &str) -> Result<Vec<SnapshotInfo>> {
+    let dir = snapshots_dir(root_dir);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let info_path = entry?.path().join(SNAPSHOT_INFO_FILE);
+        if let Ok(content) = fs::read_to_string(&info_path)
+            && let Ok(info) = serde_json::from_str::<SnapshotInfo>(&content)
+        {
+            snapshots.push(info);
+        }
+    }
+    snapshots.sort_by(|a, b| (a.created_at, &a.name).cmp(&(b.created_at, &b.name)));
+
+    Ok(snapshots)
+}
+
+/// Replace the current index of a workspace with the contents of a snapshot.
+///
+/// Writers in other processes wait until the restore is done, but the workspace must not be
+/// open for searching in any other process while restoring.
+pub fn restore_snapshot(root_dir: &str, name: &str) -> Result<SnapshotInfo> {
+    validate_snapshot_name(name)?;
+    let _lock = WriteLock::acquire(Path::new(root_dir))?;
+
+    let snapshot_dir = snapshots_dir(root_dir).join(name);
+    let info: SnapshotInfo = fs::read_to_string(snapshot_dir.join(SNAPSHOT_INFO_FILE))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .ok_or_else(|| anyhow!("Snapshot '{name}' not found"))?;
+
+    // Copy into a staging directory first, so a failed copy leaves the index as it was
+    let root = Path::new(root_dir);
+    let staging_dir = root.join(RESTORE_STAGING_DIR);
+    let replaced_dir = root.join(RESTORE_REPLACED_DIR);
+    for dir in [&staging_dir, &replaced_dir] {
+        if dir.exists() {
+            fs::remove_dir_all(dir)?;
+        }
+        fs::create_dir_all(dir)?;
+    }
+    for item in SNAPSHOT_ITEMS {
+        let saved = snapshot_dir.join(item);
+        if saved.exists() {
+            copy_recursive(&saved, &staging_dir.join(item))?;
+        }
+    }
+
+    // Then swap each item in with renames
+    for item in SNAPSHOT_ITEMS {
+        let current = root.join(item);
+        if current.exists() {
+            fs::rename(&current, replaced_dir.join(item))?;
+        }
+        let staged = staging_dir.join(item);
+        if staged.exists() {
+            fs::rename(&staged, &current)?;
+        }
+    }
+    fs::remove_dir_all(&staging_dir)?;
+    fs::remove_dir_all(&replaced_dir)?;
+
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::DocumentMetadata;
+    use crate::search::normalize::Normalization;
+    use crate::workspace::store::{CURRENT_EMBEDDING_VERSION, DocMeta};
+    use tempfile::TempDir;
+
+    fn doc(path: &str) -> DocMeta {
+        DocMeta {
+            path: path.to_string(),
+            size_bytes: 10,
+            mtime: 1000,
+            _version: CURRENT_EMBEDDING_VERSION,
+            tags: Default::default(),
+            metadata: DocumentMetadata::default(),
+            normalization: Normalization::default(),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_create_list_restore() {
+        let temp_dir = TempDir::new().unwrap();
+        let root_dir = temp_dir.path().to_str().unwrap();
+
+        {
+            let store = Store::open(root_dir).unwrap();
+            store
+                .upsert_document_metadata(&[doc("/test/a.txt")])
+                .unwrap();
+        }
+
+        let info = create_snapshot(root_dir, "before-ingest").unwrap();
+        assert_eq!(info.total_documents, 1);
+        assert!(create_snapshot(root_dir, "before-ingest").is_err());
+
+        // A bad ingestion after the snapshot
+        {
+            let store = Store::open(root_dir).unwrap();
+            store
+                .upsert_document_metadata(&[doc("/test/node_modules/b.js"), doc("/test/c.js")])
+                .unwrap();
+            assert_eq!(store.count_documents().unwrap(), 3);
+        }
+
+        let snapshots = list_snapshots(root_dir).unwrap();
+        assert_eq!(snapshots.len(), 1);
+        assert_eq!(snapshots[0].name, "before-ingest");
+
+        restore_snapshot(root_dir, "before-ingest").unwrap();
+        assert!(!temp_dir.path().join(RESTORE_STAGING_DIR).exists());
+        assert!(!temp_dir.path().join(RESTORE_REPLACED_DIR).exists());
+
+        let store = Store::open(root_dir).unwrap();
+        assert_eq!(
+            store.get_all_document_paths().unwrap(),
+            vec!["/test/a.txt".to_string()]
+        );
+        drop(store);
+    }
+
+    #[test]
+    fn test_invalid_snapshot_names() {
+        assert!(validate_snapshot_name("").is_err());
+        assert!(validate_snapshot_name("..").is_err());
+        assert!(validate_snapshot_name("a/b").is_err());
+        assert!(validate_snapshot_name("pre-2024_01.v2").is_ok());
+    }
+
+    #[test]
+    fn test_restore_missing_snapshot() {
+        let temp_dir = TempDir::new().unwrap();
+        let root_dir = temp_dir.path().to_str().unwrap();
+        assert!(restore_snapshot(root_dir, "missing").is_err());
+    }
+}
@@ -0,0 +1,775 @@
+//! Flat-file storage for builds without Qdrant Edge (`workspace-lite`).
+//!
+//! Each table is a single file: a header, every row's vector as little-endian `f32`s, then the
+//! rows' payloads as a JSON array. Files are memory-mapped for reading and searched by brute
+//! force with SIMD cosine distances. A write rewrites the whole table into a temporary file and
+//! renames it over the old one, so readers always see a complete table and a crash never
+//! leaves a torn one. This suits the corpora of constrained environments; large workspaces are
+//! better served by the HNSW index of the `workspace` feature.
+use anyhow::{Result, anyhow, bail};
+use memmap2::Mmap;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use simsimd::SpatialSimilarity;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use super::{
+    DocEmbedding, DocMeta, LINE_EMBEDDING_SIZE, LineEmbedding, MaintenanceState, RankedLine,
+    open_data_dir,
+};
+use crate::metadata::MetadataFilter;
+use crate::search::TagFilter;
+use crate::telemetry::{self, Operation};
+use crate::workspace::encryption::UnsealedWorkspace;
+use crate::workspace::lock::WriteLock;
+
+/// Table files (inside the workspace directory) that make up the index
+pub(super) const INDEX_ITEMS: [&str; 3] = [
+    "documents.flat",
+    "line_embeddings.flat",
+    "doc_embeddings.flat",
+];
+
+/// How `workspace status` describes the index
+pub(super) const INDEX_TYPE: &str = "Flat (brute force)";
+
+const MAGIC: &[u8; 8] = b"SEMFLAT1";
+
+/// Magic, dimensions (u32), reserved (u32), row count (u64) and generation (u64). The length
+/// keeps the vectors that follow 4-byte aligned.
+const HEADER_LEN: usize = 32;
+
+/// A row of a table, identified the same way as the Qdrant points it stands in for
+trait Row: Clone + Serialize + DeserializeOwned {
+    fn row_id(&self) -> u64;
+    fn path(&self) -> &str;
+}
+
+impl Row for DocMeta {
+    fn row_id(&self) -> u64 {
+        self.id()
+    }
+    fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Row for LineEmbedding {
+    fn row_id(&self) -> u64 {
+        self.id()
+    }
+    fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl Row for DocEmbedding {
+    fn row_id(&self) -> u64 {
+        self.id()
+    }
+    fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Header {
+    dimensions: usize,
+    rows: usize,
+    generation: u64,
+}
+
+impl Header {
+    fn parse(bytes: &[u8], path: &Path) -> Result<Self> {
+        if bytes.len() < HEADER_LEN || &bytes[..8] != MAGIC {
+            bail!("{} is not a semtools index file", path.display());
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        Ok(Self {
+            dimensions: u32_at(8) as usize,
+            rows: u64_at(16) as usize,
+            generation: u64_at(24),
+        })
+    }
+
+    fn vectors_len(&self) -> usize {
+        self.rows * self.dimensions * 4
+    }
+}
+
+/// One table file, mapped into memory
+struct Table<R> {
+    path: PathBuf,
+    header: Header,
+    /// `None` until the first write creates the file
+    map: Option<Mmap>,
+    rows: Vec<R>,
+}
+
+impl<R: Row> Table<R> {
+    fn open(path: PathBuf, dimensions: usize) -> Result<Self> {
+        let mut table = Self {
+            path,
+            header: Header {
+                dimensions,
+                rows: 0,
+                generation: 0,
+            },
+            map: None,
+            rows: Vec::new(),
+        };
+        table.load()?;
+        Ok(table)
+    }
+
+    fn load(&mut self) -> Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+        let file = File::open(&self.path)?;
+        // SAFETY: table files are only ever replaced by renaming a new file over them, never
+        // modified in place, so the mapped bytes cannot change underneath us.
+        let map = unsafe { Mmap::map(&file)? };
+        let header = Header::parse(&map, &self.path)?;
+        let payloads = map
+            .get(HEADER_LEN + header.vectors_len()..)
+            .ok_or_else(|| anyhow!("{} is truncated", self.path.display()))?;
+        let rows: Vec<R> = serde_json::from_slice(payloads)
+            .map_err(|e| anyhow!("{} is corrupt: {e}", self.path.display()))?;
+        if rows.len() != header.rows {
+            bail!(
+                "{} is corrupt: {} rows for {} vectors",
+                self.path.display(),
+                rows.len(),
+                header.rows
+            );
+        }
+        self.header = header;
+        self.rows = rows;
+        self.map = Some(map);
+        Ok(())
+    }
+
+    /// Reload the table if another process rewrote it since it was loaded, so a read never
+    /// misses its writes and a write never builds on a stale copy.
+    fn refresh(&mut self) -> Result<()> {
+        let mut bytes = [0u8; HEADER_LEN];
+        match File::open(&self.path) {
+            Ok(mut file) => file.read_exact(&mut bytes)?,
+            Err(_) => return Ok(()),
+        }
+        if Header::parse(&bytes, &self.path)?.generation != self.header.generation {
+            self.load()?;
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    fn vector_bytes(&self, row: usize) -> &[u8] {
+        let len = self.header.dimensions * 4;
+        let start = HEADER_LEN + row * len;
+        self.map
+            .as_ref()
+            .map_or(&[], |map| &map[start..start + len])
+    }
+
+    /// Decode the vector of `row` into `buf`
+    fn read_vector(&self, row: usize, buf: &mut Vec<f32>) {
+        buf.clear();
+        buf.extend(
+            self.vector_bytes(row)
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap())),
+        );
+    }
+
+    fn vector(&self, row: usize) -> Vec<f32> {
+        let mut buf = Vec::with_capacity(self.header.dimensions);
+        self.read_vector(row, &mut buf);
+        buf
+    }
+
+    /// Cosine distance from `query` to each row accepted by `keep`
+    fn distances(&self, query: &[f32], keep: impl Fn(&R) -> bool) -> Vec<(usize, f32)> {
+        let mut buf = Vec::with_capacity(self.header.dimensions);
+        let mut distances = Vec::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            if !keep(row) {
+                continue;
+            }
+            self.read_vector(i, &mut buf);
+            if let Some(distance) = f32::cosine(query, &buf) {
+                distances.push((i, distance as f32));
+            }
+        }
+        distances
+    }
+
+    /// Insert `entries`, replacing rows with the same id, and write the table
+    fn upsert(&mut self, entries: Vec<(R, Vec<f32>)>) -> Result<()> {
+        self.replace(|_| true, entries)
+    }
+
+    /// Drop the rows rejected by `keep`, insert `entries` (replacing rows with the same id)
+    /// and write the table
+    fn replace(&mut self, keep: impl Fn(&R) -> bool, entries: Vec<(R, Vec<f32>)>) -> Result<()> {
+        let mut rows: Vec<_> = self
+            .entries()
+            .into_iter()
+            .filter(|(row, _)| keep(row))
+            .collect();
+        let mut index: HashMap<u64, usize> = rows
+            .iter()
+            .enumerate()
+            .map(|(i, (row, _))| (row.row_id(), i))
+            .collect();
+        for (row, vector) in entries {
+            if vector.len() != self.header.dimensions {
+                bail!(
+                    "Expected {}-dimensional embeddings, got {}",
+                    self.header.dimensions,
+                    vector.len()
+                );
+            }
+            match index.get(&row.row_id()) {
+                Some(&i) => rows[i] = (row, vector),
+                None => {
+                    index.insert(row.row_id(), rows.len());
+                    rows.push((row, vector));
+                }
+            }
+        }
+        self.write(rows)
+    }
+
+    /// Remove the rows rejected by `keep` and write the table. Returns the number removed.
+    fn retain(&mut self, keep: impl Fn(&R) -> bool) -> Result<usize> {
+        let before = self.len();
+        let rows: Vec<_> = self
+            .entries()
+            .into_iter()
+            .filter(|(row, _)| keep(row))
+            .collect();
+        let removed = before - rows.len();
+        if removed > 0 {
+            self.write(rows)?;
+        }
+        Ok(removed)
+    }
+
+    fn entries(&self) -> Vec<(R, Vec<f32>)> {
+        self.rows
+            .iter()
+            .enumerate()
+            .map(|(i, row)| (row.clone(), self.vector(i)))
+            .collect()
+    }
+
+    fn write(&mut self, entries: Vec<(R, Vec<f32>)>) -> Result<()> {
+        let header = Header {
+            dimensions: self.header.dimensions,
+            rows: entries.len(),
+            generation: self.header.generation + 1,
+        };
+        let tmp_path = self.path.with_extension("flat.tmp");
+        {
+            let mut out = BufWriter::new(File::create(&tmp_path)?);
+            out.write_all(MAGIC)?;
+            out.write_all(&(header.dimensions as u32).to_le_bytes())?;
+            out.write_all(&0u32.to_le_bytes())?;
+            out.write_all(&(header.rows as u64).to_le_bytes())?;
+            out.write_all(&header.generation.to_le_bytes())?;
+            for (_, vector) in &entries {
+                for value in vector {
+                    out.write_all(&value.to_le_bytes())?;
+                }
+            }
+            let rows: Vec<&R> = entries.iter().map(|(row, _)| row).collect();
+            serde_json::to_writer(&mut out, &rows)?;
+            out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        fs::rename(&tmp_path, &self.path)?;
+        self.load()
+    }
+}
+
+/// Storage in flat, memory-mapped table files.
+pub struct Store {
+    pub(super) workspace_dir: PathBuf,
+    documents: RwLock<Table<DocMeta>>,
+    line_embeddings: RwLock<Table<LineEmbedding>>,
+    /// Mean line embedding per document, used for first-stage document retrieval
+    doc_embeddings: RwLock<Table<DocEmbedding>>,
+    /// Workspace directory holding the write lock (the encrypted one for encrypted workspaces)
+    pub(super) root_dir: PathBuf,
+    /// Write lock held by this store, and the number of live guards sharing it
+    pub(super) write_lock: Mutex<(usize, Option<WriteLock>)>,
+    /// Decrypted copy of an encrypted workspace. Declared last so the tables are unmapped
+    /// before it is sealed again.
+    pub(super) unsealed: Option<UnsealedWorkspace>,
+}
+
+impl Store {
+    /// Initialize or load storage for a workspace directory, with the default embedding size
+    pub fn open(workspace_dir: &str) -> Result<Self> {
+        Self::open_with_dimensions(workspace_dir, LINE_EMBEDDING_SIZE)
+    }
+
+    /// Initialize or load storage for a workspace directory. `dimensions` is only used when the
+    /// index is created; an existing index keeps the size it was created with.
+    pub fn open_with_dimensions(workspace_dir: &str, dimensions: usize) -> Result<Self> {
+        let (unsealed, data_dir) = open_data_dir(workspace_dir)?;
+        if data_dir.join("documents.qdrant").exists() {
+            bail!(
+                "{workspace_dir} was indexed by a build with the `workspace` feature. Use that \
+                 build, or re-create the workspace"
+            );
+        }
+        fs::create_dir_all(&data_dir)?;
+
+        Ok(Self {
+            documents: RwLock::new(Table::open(data_dir.join(INDEX_ITEMS[0]), 0)?),
+            line_embeddings: RwLock::new(Table::open(data_dir.join(INDEX_ITEMS[1]), dimensions)?),
+            doc_embeddings: RwLock::new(Table::open(data_dir.join(INDEX_ITEMS[2]), dimensions)?),
+            workspace_dir: data_dir,
+            root_dir: Path::new(workspace_dir).to_path_buf(),
+            write_lock: Mutex::new((0, None)),
+            unsealed,
+        })
+    }
+
+    fn documents(&self) -> Result<RwLockReadGuard<'_, Table<DocMeta>>> {
+        Self::readable(&self.documents)
+    }
+
+    fn line_embeddings(&self) -> Result<RwLockReadGuard<'_, Table<LineEmbedding>>> {
+        Self::readable(&self.line_embeddings)
+    }
+
+    fn doc_embeddings(&self) -> Result<RwLockReadGuard<'_, Table<DocEmbedding>>> {
+        Self::readable(&self.doc_embeddings)
+    }
+
+    /// A table to read, brought up to date with writes from other processes
+    fn readable<R: Row>(table: &RwLock<Table<R>>) -> Result<RwLockReadGuard<'_, Table<R>>> {
+        table.write().unwrap().refresh()?;
+        Ok(table.read().unwrap())
+    }
+
+    /// A table to modify, brought up to date with writes from other processes. Only call it
+    /// with the write lock held.
+    fn writable<R: Row>(table: &RwLock<Table<R>>) -> Result<RwLockWriteGuard<'_, Table<R>>> {
+        let mut table = table.write().unwrap();
+        table.refresh()?;
+        Ok(table)
+    }
+
+    pub fn get_existing_docs(&self, paths: &[String]) -> Result<HashMap<String, DocMeta>> {
+        let wanted: HashSet<&str> = paths.iter().map(String::as_str).collect();
+        Ok(self
+            .documents()?
+            .rows
+            .iter()
+            .filter(|meta| wanted.contains(meta.path.as_str()))
+            .map(|meta| (meta.path.clone(), meta.clone()))
+            .collect())
+    }
+
+    /// Delete document metadata by path
+    pub fn delete_document_metadata(&self, paths: &[String]) -> Result<()> {
+        self.delete_rows(&self.documents, paths)
+    }
+
+    /// Delete line embeddings by path
+    pub fn delete_line_embeddings(&self, paths: &[String]) -> Result<()> {
+        self.delete_rows(&self.line_embeddings, paths)
+    }
+
+    /// Delete document-level embeddings by path
+    pub fn delete_doc_embeddings(&self, paths: &[String]) -> Result<()> {
+        self.delete_rows(&self.doc_embeddings, paths)
+    }
+
+    fn delete_rows<R: Row>(&self, table: &RwLock<Table<R>>, paths: &[String]) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.write_lock()?;
+        let paths: HashSet<&str> = paths.iter().map(String::as_str).collect();
+        Self::writable(table)?.retain(|row| !paths.contains(row.path()))?;
+        Ok(())
+    }
+
+    /// Upsert document-level embeddings
+    pub fn upsert_doc_embeddings(&self, doc_embeddings: &[DocEmbedding]) -> Result<()> {
+        if doc_embeddings.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.write_lock()?;
+        Self::writable(&self.doc_embeddings)?.upsert(
+            doc_embeddings
+                .iter()
+                .map(|doc| (doc.clone(), doc.embedding.clone()))
+                .collect(),
+        )
+    }
+
+    /// First-stage retrieval: the `doc_top_k` documents closest to the query, plus any document
+    /// in `subset_paths` that has no document embedding yet (indexed before they existed)
+    pub fn select_candidate_documents(
+        &self,
+        query_vec: &[f32],
+        subset_paths: &[String],
+        doc_top_k: usize,
+    ) -> Result<Vec<String>> {
+        let _timed = telemetry::timed(Operation::StoreQuery, &[("shard", "doc_embeddings")]);
+        let subset: HashSet<&str> = subset_paths.iter().map(String::as_str).collect();
+        let table = self.doc_embeddings()?;
+
+        let mut ranked = table.distances(query_vec, |doc| subset.contains(doc.path.as_str()));
+        // Ties in path order, so the same documents make the cut on every run
+        ranked.sort_by(|a, b| {
+            a.1.partial_cmp(&b.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| table.rows[a.0].path.cmp(&table.rows[b.0].path))
+        });
+        ranked.truncate(doc_top_k);
+
+        let with_embedding: HashSet<&str> =
+            table.rows.iter().map(|doc| doc.path.as_str()).collect();
+        let mut candidates: Vec<String> = ranked
+            .into_iter()
+            .map(|(i, _)| table.rows[i].path.clone())
+            .collect();
+        candidates.extend(
+            subset_paths
+                .iter()
+                .filter(|path| !with_embedding.contains(path.as_str()))
+                .cloned(),
+        );
+
+        Ok(candidates)
+    }
+
+    /// Upsert documents metadata (no embeddings stored)
+    pub fn upsert_document_metadata(&self, metas: &[DocMeta]) -> Result<()> {
+        if metas.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.write_lock()?;
+        Self::writable(&self.documents)?.upsert(
+            metas
+                .iter()
+                .map(|meta| (meta.clone(), Vec::new()))
+                .collect(),
+        )
+    }
+
+    /// The documents among `paths` carrying every tag in `filters` and matching every filter
+    /// in `metadata_filters`, in the order of `paths`
+    pub fn filter_documents(
+        &self,
+        paths: &[String],
+        filters: &[TagFilter],
+        metadata_filters: &[MetadataFilter],
+    ) -> Result<Vec<String>> {
+        let _timed = telemetry::timed(Operation::StoreQuery, &[("shard", "documents")]);
+        let table = self.documents()?;
+        let matching: HashSet<&str> = table
+            .rows
+            .iter()
+            .filter(|meta| {
+                filters
+                    .iter()
+                    .all(|filter| meta.tags.get(&filter.key) == Some(&filter.value))
+                    && metadata_filters
+                        .iter()
+                        .all(|filter| filter.matches(&meta.metadata))
+            })
+            .map(|meta| meta.path.as_str())
+            .collect();
+
+        Ok(paths
+            .iter()
+            .filter(|path| matching.contains(path.as_str()))
+            .cloned()
+            .collect())
+    }
+
+    /// Store `line_embeddings` as the lines of the documents at `paths`, upserted in place and
+    /// keyed on the path and line number. Lines past the new end of each document (i.e. the
+    /// file shrank) are removed in the same write, and so are all the lines of a document in
+    /// `paths` that has none in `line_embeddings` (the file is empty now).
+    pub fn replace_line_embeddings(
+        &self,
+        paths: &[String],
+        line_embeddings: &[LineEmbedding],
+    ) -> Result<()> {
+        if paths.is_empty() && line_embeddings.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.write_lock()?;
+
+        let mut line_counts: HashMap<&str, i32> =
+            paths.iter().map(|path| (path.as_str(), 0)).collect();
+        for line_embedding in line_embeddings {
+            let count = line_counts.entry(line_embedding.path.as_str()).or_default();
+            *count = (*count).max(line_embedding.line_number + 1);
+        }
+
+        Self::writable(&self.line_embeddings)?.replace(
+            |line| {
+                line_counts
+                    .get(line.path.as_str())
+                    .is_none_or(|count| line.line_number < *count)
+            },
+            line_embeddings
+                .iter()
+                .map(|line| (line.clone(), line.embedding.clone()))
+                .collect(),
+        )?;
+
+        self.record_new_rows(line_embeddings.len())?;
+
+        Ok(())
+    }
+
+    /// Get paths for all stored documents
+    pub fn get_all_document_paths(&self) -> Result<Vec<String>> {
+        Ok(self
+            .documents()?
+            .rows
+            .iter()
+            .map(|meta| meta.path.clone())
+            .collect())
+    }
+
+    /// Number of line embeddings stored for each path
+    pub fn line_embedding_counts(&self) -> Result<HashMap<String, usize>> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for line in &self.line_embeddings()?.rows {
+            *counts.entry(line.path.clone()).or_default() += 1;
+        }
+        Ok(counts)
+    }
+
+    /// Paths that have a document-level embedding
+    pub fn doc_embedding_paths(&self) -> Result<HashSet<String>> {
+        Ok(self
+            .doc_embeddings()?
+            .rows
+            .iter()
+            .map(|doc| doc.path.clone())
+            .collect())
+    }
+
+    /// Every stored line embedding of `path`, in no particular order
+    pub fn line_vectors(&self, path: &str) -> Result<Vec<Vec<f32>>> {
+        let table = self.line_embeddings()?;
+        Ok(table
+            .rows
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| line.path == path)
+            .map(|(i, _)| table.vector(i))
+            .collect())
+    }
+
+    /// Like `search_line_embeddings`, optionally returning each line's embedding for re-ranking
+    pub fn search_line_embeddings_with_vectors(
+        &self,
+        query_vec: &[f32],
+        subset_paths: &[String],
+        top_k: usize,
+        max_distance: Option<f32>,
+        with_vectors: bool,
+    ) -> Result<Vec<RankedLine>> {
+        let _timed = telemetry::timed(Operation::StoreQuery, &[("shard", "line_embeddings")]);
+        // Short-circuit on empty subsets
+        if subset_paths.is_empty() || top_k == 0 {
+            return Ok(Vec::new());
+        }
+
+        let subset: HashSet<&str> = subset_paths.iter().map(String::as_str).collect();
+        let table = self.line_embeddings()?;
+        let mut all_results: Vec<RankedLine> = table
+            .distances(query_vec, |line| subset.contains(line.path.as_str()))
+            .into_iter()
+            .filter(|(_, distance)| max_distance.is_none_or(|max| *distance <= max))
+            .map(|(i, distance)| RankedLine {
+                path: table.rows[i].path.clone(),
+                line_number: table.rows[i].cited_line(),
+                distance,
+                score: distance,
+                embedding: if with_vectors {
+                    table.vector(i)
+                } else {
+                    Vec::new()
+                },
+            })
+            .collect();
+
+        // Ties in path and line order, so the same query always returns the same lines
+        all_results.sort_by(|a, b| {
+            a.distance
+                .partial_cmp(&b.distance)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.path.cmp(&b.path))
+                .then_with(|| a.line_number.cmp(&b.line_number))
+        });
+        all_results.truncate(top_k);
+
+        Ok(all_results)
+    }
+
+    /// Up to `limit` stored line embeddings, in id order. Ids are hashes of path and line
+    /// number, so this is a sample spread over the whole index.
+    pub fn sample_line_embeddings(&self, limit: usize) -> Result<Vec<Vec<f32>>> {
+        let table = self.line_embeddings()?;
+        let mut rows: Vec<(u64, usize)> = table
+            .rows
+            .iter()
+            .enumerate()
+            .map(|(i, line)| (line.id(), i))
+            .collect();
+        rows.sort_unstable();
+        Ok(rows
+            .into_iter()
+            .take(limit)
+            .map(|(_, i)| table.vector(i))
+            .filter(|embedding| !embedding.is_empty())
+            .collect())
+    }
+
+    /// Stored text of lines `start..end` of `path`, by line number. Lines indexed without text
+    /// (`store_text` off) are missing from the map.
+    pub fn line_texts(
+        &self,
+        path: &str,
+        start: usize,
+        end: usize,
+    ) -> Result<BTreeMap<usize, String>> {
+        let _timed = telemetry::timed(Operation::StoreQuery, &[("shard", "line_embeddings")]);
+        Ok(self
+            .line_embeddings()?
+            .rows
+            .iter()
+            .filter(|line| line.path == path && (start..end).contains(&(line.line_number as usize)))
+            .filter_map(|line| Some((line.line_number as usize, line.text.clone()?)))
+            .collect())
+    }
+
+    /// Get the number of documents
+    pub fn count_documents(&self) -> Result<usize> {
+        Ok(self.documents()?.len())
+    }
+
+    /// Get the number of stored line embeddings
+    pub fn count_line_embeddings(&self) -> Result<usize> {
+        Ok(self.line_embeddings()?.len())
+    }
+
+    /// Every write is already a complete table file, so there is nothing to index or flush;
+    /// this only resets the maintenance schedule. Returns the number of rows written since the
+    /// previous optimization.
+    pub fn optimize(&self) -> Result<usize> {
+        let _lock = self.write_lock()?;
+        let rows = self.maintenance_state().rows_since_optimize;
+        self.save_maintenance_state(&MaintenanceState::default())?;
+        Ok(rows)
+    }
+
+    /// Writes are durable when they return; kept for parity with the Qdrant store.
+    pub fn flush_documents(&self) {}
+
+    /// Writes are durable when they return; kept for parity with the Qdrant store.
+    pub fn flush_line_embeddings(&self) {}
+
+    /// Writes are durable when they return; kept for parity with the Qdrant store.
+    pub fn flush_doc_embeddings(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn line(path: &str, line_number: i32, embedding: Vec<f32>) -> LineEmbedding {
+        LineEmbedding {
+            path: path.to_string(),
+            line_number,
+            embedding,
+            text: None,
+            table_line: None,
+        }
+    }
+
+    #[test]
+    fn test_tables_persist_across_opens() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace_dir = temp_dir.path().to_str().unwrap();
+        {
+            let store = Store::open_with_dimensions(workspace_dir, 3).unwrap();
+            store
+                .upsert_line_embeddings(&[
+                    line("a.md", 0, vec![1.0, 0.0, 0.0]),
+                    line("a.md", 1, vec![0.0, 1.0, 0.0]),
+                ])
+                .unwrap();
+        }
+
+        let store = Store::open(workspace_dir).unwrap();
+        assert_eq!(store.count_line_embeddings().unwrap(), 2);
+        let results = store
+            .search_line_embeddings(&[0.0, 1.0, 0.0], &["a.md".to_string()], 1, None)
+            .unwrap();
+        assert_eq!(results[0].line_number, 1);
+        assert!(results[0].distance.abs() < 1e-6);
+
+        // The table keeps the size it was created with
+        assert!(
+            store
+                .upsert_line_embeddings(&[line("b.md", 0, vec![1.0; 4])])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_writes_from_another_store_are_kept() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace_dir = temp_dir.path().to_str().unwrap();
+        let first = Store::open_with_dimensions(workspace_dir, 2).unwrap();
+        let second = Store::open_with_dimensions(workspace_dir, 2).unwrap();
+
+        first
+            .upsert_line_embeddings(&[line("a.md", 0, vec![1.0, 0.0])])
+            .unwrap();
+        // `second` loaded the table before that write, and must not overwrite it
+        second
+            .upsert_line_embeddings(&[line("b.md", 0, vec![0.0, 1.0])])
+            .unwrap();
+
+        let store = Store::open(workspace_dir).unwrap();
+        let counts = store.line_embedding_counts().unwrap();
+        assert_eq!(counts.get("a.md"), Some(&1));
+        assert_eq!(counts.get("b.md"), Some(&1));
+    }
+
+    #[test]
+    fn test_corrupt_table_is_reported() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join(INDEX_ITEMS[0]), b"not an index").unwrap();
+        let error = Store::open(temp_dir.path().to_str().unwrap())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("not a semtools index file"));
+    }
+}
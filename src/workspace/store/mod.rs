@@ -0,0 +1,1459 @@
+//! Workspace index storage.
+//!
+//! The index is kept in Qdrant Edge shards with the `workspace` feature. Builds with only
+//! `workspace-lite` keep it in flat files instead (see `flat`), so they do without the
+//! qdrant-edge dependency tree. Both backends expose the same `Store`.
+use anyhow::Result;
+
+use crate::metadata::DocumentMetadata;
+use crate::search::DocumentInfo;
+use crate::search::calibration::Calibration;
+use crate::search::kv::KvEntry;
+use crate::search::normalize::{Normalization, normalization};
+use crate::workspace::encryption::{UnsealedWorkspace, is_encrypted};
+use crate::workspace::lock::WriteLock;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::PoisonError;
+
+#[cfg(not(feature = "workspace"))]
+mod flat;
+#[cfg(feature = "workspace")]
+mod qdrant;
+
+#[cfg(not(feature = "workspace"))]
+pub use flat::Store;
+#[cfg(not(feature = "workspace"))]
+use flat::{INDEX_ITEMS, INDEX_TYPE};
+#[cfg(feature = "workspace")]
+pub use qdrant::Store;
+#[cfg(feature = "workspace")]
+use qdrant::{INDEX_ITEMS, INDEX_TYPE};
+
+/// Current embedding/version number for stored document metadata.
+/// Bump this when the embedding model or preprocessing pipeline changes in a
+/// way that invalidates previously stored line embeddings.
+/// Backwards compatibility: if a workspace DB is missing the `_version` column,
+/// we treat all existing documents as version 1.
+/// Version 3 added the rows of tables (see `crate::search::tables`).
+pub const CURRENT_EMBEDDING_VERSION: u32 = 3;
+
+/// Default embedding size (needed to inform Qdrant collection when it is instantiated)
+pub const LINE_EMBEDDING_SIZE: usize = 256;
+
+/// File (inside the workspace directory) tracking rows written since the last optimization
+pub const MAINTENANCE_FILE: &str = "maintenance.json";
+
+/// File (inside the workspace directory) holding the `ask --memory` history
+pub const MEMORY_FILE: &str = "memory.jsonl";
+
+/// File (inside the workspace directory) holding the key-value pairs of `workspace kv`
+pub const KV_FILE: &str = "kv.jsonl";
+
+/// File (inside the workspace directory) holding the distance calibration for normalized scores
+pub const CALIBRATION_FILE: &str = "calibration.json";
+
+/// File (inside the workspace directory) recording the embedding model of the index
+pub const SPACE_FILE: &str = "space.json";
+
+/// Directory (inside the workspace root) holding the indexes of embedding spaces other than
+/// the workspace's own preset, one per preset
+pub const SPACES_DIR: &str = "spaces";
+
+/// Files and directories (inside the workspace directory) that make up the index
+pub const STORE_ITEMS: [&str; 8] = [
+    INDEX_ITEMS[0],
+    INDEX_ITEMS[1],
+    INDEX_ITEMS[2],
+    MAINTENANCE_FILE,
+    MEMORY_FILE,
+    CALIBRATION_FILE,
+    SPACE_FILE,
+    KV_FILE,
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocMeta {
+    pub path: String,
+    pub size_bytes: u64,
+    pub mtime: i64,
+    pub _version: u32, // used to help manage new embedding models
+    /// Tags attached with `workspace sync --tag`, matched by `search --filter tag:key=value`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Title, author and creation date read from the document when it was indexed, matched by
+    /// `search --filter author:name` and the like
+    #[serde(flatten)]
+    pub metadata: DocumentMetadata,
+    /// Normalization of the text when the document was embedded; it is embedded again once the
+    /// configured normalization differs
+    #[serde(default, skip_serializing_if = "Normalization::is_off")]
+    pub normalization: Normalization,
+}
+
+#[derive(Debug)]
+pub enum DocumentState {
+    Unchanged(String),     // Just the filename, no need to process
+    Changed(DocumentInfo), // Full document info for processing
+    New(DocumentInfo),     // Full document info for processing
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LineEmbedding {
+    pub path: String,
+    pub line_number: i32,
+    #[serde(skip)]
+    pub embedding: Vec<f32>,
+    /// The line itself, stored when the workspace keeps text (`store_text`) so results can be
+    /// served without the original file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// For the row of a table, the line holding the table. Rows are numbered after the last
+    /// line of their document and cite that line in results.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table_line: Option<i32>,
+}
+
+impl DocMeta {
+    pub fn id(&self) -> u64 {
+        // Generate deterministic ID based on path hash for consistent upserts
+        fnv1a_hash(self.path.as_bytes())
+    }
+}
+
+impl LineEmbedding {
+    /// The line results cite: the line itself, or the line of the table a row belongs to
+    pub fn cited_line(&self) -> i32 {
+        self.table_line.unwrap_or(self.line_number)
+    }
+
+    pub fn id(&self) -> u64 {
+        // Generate deterministic ID based on path + line number for consistent upserts
+        let mut bytes = self.path.as_bytes().to_vec();
+        bytes.extend_from_slice(&self.line_number.to_le_bytes());
+        fnv1a_hash(&bytes)
+    }
+}
+
+/// Document-level embedding (mean of the document's line embeddings)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocEmbedding {
+    pub path: String,
+    #[serde(skip)]
+    pub embedding: Vec<f32>,
+}
+
+impl DocEmbedding {
+    pub fn id(&self) -> u64 {
+        // Same id scheme as DocMeta, so a document maps to one point per shard
+        fnv1a_hash(self.path.as_bytes())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RankedLine {
+    pub path: String,
+    pub line_number: i32,
+    pub distance: f32,
+    /// Ranking score: the distance, minus the negative query penalty and recency boost if
+    /// enabled (lower is better)
+    pub score: f32,
+    /// The line's embedding (empty unless requested with `search_line_embeddings_with_vectors`)
+    pub embedding: Vec<f32>,
+}
+
+/// The embedding model an index was built with. Distances between embeddings of different
+/// models are meaningless, so an index is only ever searched with its own model.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EmbeddingSpace {
+    pub model: String,
+    pub dimensions: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceState {
+    pub rows_since_optimize: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkspaceStats {
+    pub total_documents: usize,
+    pub has_index: bool,
+    pub index_type: Option<String>,
+}
+
+/// The directory the index files of `workspace_dir` are in. Encrypted workspaces are
+/// decrypted into a private working directory while open, sealed again when the returned
+/// `UnsealedWorkspace` is dropped.
+fn open_data_dir(workspace_dir: &str) -> Result<(Option<UnsealedWorkspace>, PathBuf)> {
+    let unsealed = if is_encrypted(Path::new(workspace_dir)) {
+        Some(UnsealedWorkspace::open(Path::new(workspace_dir))?)
+    } else {
+        None
+    };
+    let data_dir = match &unsealed {
+        Some(u) => u.work_dir().to_path_buf(),
+        None => Path::new(workspace_dir).to_path_buf(),
+    };
+    Ok((unsealed, data_dir))
+}
+
+impl Store {
+    /// Take the workspace's write lock until the guard is dropped, waiting while another
+    /// process writes. Every write method takes it; take it around a batch of writes to keep
+    /// other processes from interleaving theirs. Reads never lock. Nested guards of one store
+    /// share the lock.
+    pub fn write_lock(&self) -> Result<WriteGuard<'_>> {
+        let mut state = self
+            .write_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if state.0 == 0 {
+            // The decrypted copy of an encrypted workspace keeps the lock until it is sealed
+            let lock = match &self.unsealed {
+                Some(unsealed) => {
+                    unsealed.lock_for_write()?;
+                    None
+                }
+                None => Some(WriteLock::acquire(&self.root_dir)?),
+            };
+            state.1 = lock;
+        }
+        state.0 += 1;
+        Ok(WriteGuard { store: self })
+    }
+
+    /// Store line embeddings, replacing the other lines of the documents they are of (see
+    /// `replace_line_embeddings`)
+    pub fn upsert_line_embeddings(&self, line_embeddings: &[LineEmbedding]) -> Result<()> {
+        let mut paths: Vec<String> = line_embeddings
+            .iter()
+            .map(|line| line.path.clone())
+            .collect();
+        paths.sort();
+        paths.dedup();
+        self.replace_line_embeddings(&paths, line_embeddings)
+    }
+
+    /// Delete documents and all associated line embeddings by path
+    pub fn delete_documents(&self, paths: &[String]) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.write_lock()?;
+
+        // Delete from all tables to maintain synchronization
+        self.delete_document_metadata(paths)?;
+        self.delete_line_embeddings(paths)?;
+        self.delete_doc_embeddings(paths)?;
+
+        Ok(())
+    }
+
+    /// Add `tags` to the stored documents among `paths`, replacing the value of a tag they
+    /// already have. Returns the number of documents tagged; paths not in the workspace are
+    /// skipped.
+    pub fn tag_documents(
+        &self,
+        paths: &[String],
+        tags: &BTreeMap<String, String>,
+    ) -> Result<usize> {
+        if tags.is_empty() {
+            return Ok(0);
+        }
+        let metas: Vec<DocMeta> = self
+            .get_existing_docs(paths)?
+            .into_values()
+            .map(|mut meta| {
+                meta.tags.extend(tags.clone());
+                meta
+            })
+            .collect();
+        self.upsert_document_metadata(&metas)?;
+        Ok(metas.len())
+    }
+
+    /// Search within line embeddings
+    pub fn search_line_embeddings(
+        &self,
+        query_vec: &[f32],
+        subset_paths: &[String],
+        top_k: usize,
+        max_distance: Option<f32>,
+    ) -> Result<Vec<RankedLine>> {
+        self.search_line_embeddings_with_vectors(
+            query_vec,
+            subset_paths,
+            top_k,
+            max_distance,
+            false,
+        )
+    }
+
+    /// Analyze the state of documents within the workspace
+    pub fn analyze_document_states(&self, file_paths: &[String]) -> Result<Vec<DocumentState>> {
+        // Get existing document metadata from workspace
+        let existing_docs = self.get_existing_docs(file_paths)?;
+
+        let mut states = Vec::new();
+
+        for file_path in file_paths {
+            // Files inside archives are read where they were extracted
+            let local_path = crate::archive::local_path(file_path);
+            // Read current file metadata
+            let current_meta = match std::fs::metadata(&local_path) {
+                Ok(metadata) => {
+                    let size_bytes = metadata.len();
+                    let mtime = metadata
+                        .modified()
+                        .ok()
+                        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map(|d| d.as_secs() as i64)
+                        .unwrap_or(0);
+                    DocMeta {
+                        path: file_path.clone(),
+                        size_bytes,
+                        mtime,
+                        _version: CURRENT_EMBEDDING_VERSION,
+                        tags: BTreeMap::new(),
+                        metadata: DocumentMetadata::default(),
+                        normalization: normalization(),
+                    }
+                }
+                Err(_) => {
+                    // File doesn't exist, skip it
+                    continue;
+                }
+            };
+
+            // Check if document exists in workspace and has changed
+            match existing_docs.get(file_path) {
+                Some(existing_meta) => {
+                    if existing_meta.size_bytes != current_meta.size_bytes
+                        || existing_meta.mtime != current_meta.mtime
+                        || existing_meta._version != CURRENT_EMBEDDING_VERSION
+                        || existing_meta.normalization != current_meta.normalization
+                    {
+                        // Document has changed
+                        let content = std::fs::read_to_string(&local_path)?;
+                        // Tags belong to the document, not to a version of its content
+                        states.push(DocumentState::Changed(DocumentInfo {
+                            filename: file_path.clone(),
+                            meta: DocMeta {
+                                tags: existing_meta.tags.clone(),
+                                metadata: crate::metadata::read(file_path, &content),
+                                ..current_meta
+                            },
+                            content,
+                        }));
+                    } else {
+                        // Document unchanged
+                        states.push(DocumentState::Unchanged(file_path.clone()));
+                    }
+                }
+                None => {
+                    // New document
+                    let content = std::fs::read_to_string(&local_path)?;
+                    states.push(DocumentState::New(DocumentInfo {
+                        filename: file_path.clone(),
+                        meta: DocMeta {
+                            metadata: crate::metadata::read(file_path, &content),
+                            ..current_meta
+                        },
+                        content,
+                    }));
+                }
+            }
+        }
+
+        Ok(states)
+    }
+
+    /// Get workspace statistics
+    pub fn get_stats(&self) -> Result<WorkspaceStats> {
+        let total_documents = self.count_documents()?;
+
+        Ok(WorkspaceStats {
+            total_documents,
+            has_index: true,
+            index_type: Some(INDEX_TYPE.to_string()),
+        })
+    }
+
+    /// Directory holding the index files. For an encrypted workspace this is the decrypted
+    /// working copy, which is sealed again when the store is dropped.
+    pub fn data_dir(&self) -> &Path {
+        &self.workspace_dir
+    }
+
+    /// Read the maintenance state, treating a missing or unreadable file as a fresh workspace
+    pub fn maintenance_state(&self) -> MaintenanceState {
+        std::fs::read_to_string(self.workspace_dir.join(MAINTENANCE_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_maintenance_state(&self, state: &MaintenanceState) -> Result<()> {
+        let s = serde_json::to_string_pretty(state)?;
+        std::fs::write(self.workspace_dir.join(MAINTENANCE_FILE), s)?;
+        Ok(())
+    }
+
+    /// Read the saved distance calibration, if the workspace has been calibrated
+    pub fn calibration(&self) -> Option<Calibration> {
+        std::fs::read_to_string(self.workspace_dir.join(CALIBRATION_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+    }
+
+    pub fn save_calibration(&self, calibration: &Calibration) -> Result<()> {
+        let _lock = self.write_lock()?;
+        let s = serde_json::to_string_pretty(calibration)?;
+        std::fs::write(self.workspace_dir.join(CALIBRATION_FILE), s)?;
+        Ok(())
+    }
+
+    /// The pairs of the key-value table, one JSON entry per line. A missing file is an empty
+    /// table.
+    pub fn kv_entries(&self) -> Result<Vec<KvEntry>> {
+        match std::fs::read_to_string(self.workspace_dir.join(KV_FILE)) {
+            Ok(content) => content
+                .lines()
+                .map(|line| Ok(serde_json::from_str(line)?))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save_kv_entries(&self, entries: &[KvEntry]) -> Result<()> {
+        let _lock = self.write_lock()?;
+        let mut s = String::new();
+        for entry in entries {
+            s.push_str(&serde_json::to_string(entry)?);
+            s.push('\n');
+        }
+        std::fs::write(self.workspace_dir.join(KV_FILE), s)?;
+        Ok(())
+    }
+
+    /// The embedding model recorded for the index, if any (indexes built before spaces were
+    /// recorded have none)
+    pub fn embedding_space(&self) -> Option<EmbeddingSpace> {
+        std::fs::read_to_string(self.workspace_dir.join(SPACE_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+    }
+
+    pub fn save_embedding_space(&self, space: &EmbeddingSpace) -> Result<()> {
+        let _lock = self.write_lock()?;
+        let s = serde_json::to_string_pretty(space)?;
+        std::fs::write(self.workspace_dir.join(SPACE_FILE), s)?;
+        Ok(())
+    }
+
+    fn record_new_rows(&self, rows: usize) -> Result<()> {
+        let mut state = self.maintenance_state();
+        state.rows_since_optimize += rows;
+        self.save_maintenance_state(&state)
+    }
+
+    /// Whether enough rows were written since the last optimization to warrant a new one.
+    /// A threshold of 0 disables scheduled optimization.
+    pub fn needs_optimize(&self, optimize_after_rows: usize) -> bool {
+        optimize_after_rows > 0
+            && self.maintenance_state().rows_since_optimize >= optimize_after_rows
+    }
+}
+
+/// A store's hold on the workspace write lock, released with the last guard
+pub struct WriteGuard<'a> {
+    store: &'a Store,
+}
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self
+            .store
+            .write_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        state.0 -= 1;
+        if state.0 == 0 {
+            #[cfg(feature = "workspace")]
+            self.store.close_shards();
+            state.1 = None;
+        }
+    }
+}
+
+/// Generate a stable hash for a byte slice using the FNV-1a algorithm.
+fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::TagFilter;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    // Helper function to create a test store
+    fn create_test_store() -> (Store, TempDir) {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let store = Store::open(temp_dir.path().to_str().unwrap()).expect("Failed to create store");
+        (store, temp_dir)
+    }
+
+    // Helper function to create test documents
+    fn create_test_docs() -> (Vec<DocMeta>, Vec<Vec<f32>>) {
+        let docs = vec![
+            DocMeta {
+                path: "/test/doc1.txt".to_string(),
+                size_bytes: 100,
+                mtime: 1234567890,
+                _version: CURRENT_EMBEDDING_VERSION,
+                tags: BTreeMap::new(),
+                metadata: DocumentMetadata::default(),
+                normalization: Normalization::default(),
+            },
+            DocMeta {
+                path: "/test/doc2.txt".to_string(),
+                size_bytes: 200,
+                mtime: 1234567891,
+                _version: CURRENT_EMBEDDING_VERSION,
+                tags: BTreeMap::new(),
+                metadata: DocumentMetadata::default(),
+                normalization: Normalization::default(),
+            },
+            DocMeta {
+                path: "/test/doc3.txt".to_string(),
+                size_bytes: 150,
+                mtime: 1234567892,
+                _version: CURRENT_EMBEDDING_VERSION,
+                tags: BTreeMap::new(),
+                metadata: DocumentMetadata::default(),
+                normalization: Normalization::default(),
+            },
+        ];
+
+        let embeddings = vec![
+            vec![0.1; 256],  // All 0.1
+            vec![0.5; 256],  // All 0.5
+            vec![0.75; 256], // All 0.5
+        ];
+
+        (docs, embeddings)
+    }
+
+    #[test]
+    fn test_store_creation_and_stats_empty() {
+        let (store, _temp_dir) = create_test_store();
+
+        let stats = store.get_stats().expect("Failed to get stats");
+
+        assert_eq!(stats.total_documents, 0);
+        assert!(stats.has_index);
+        assert_eq!(stats.index_type, Some(INDEX_TYPE.to_string()));
+
+        // explicitly drop store before _temp_dir to avoid
+        // EdgeShard panicking when trying to flush to a non-existing dir
+        // (caused by _temp_dir being dropped before store)
+        drop(store);
+        drop(_temp_dir);
+    }
+
+    #[test]
+    fn test_upsert_documents_and_stats() {
+        let (store, _temp_dir) = create_test_store();
+        let (docs, embeddings) = create_test_docs();
+
+        // Insert documents
+        store
+            .upsert_document_metadata(&docs)
+            .expect("Failed to upsert documents");
+
+        let line_embeddings: Vec<LineEmbedding> = docs
+            .iter()
+            .enumerate()
+            .map(|(i, doc)| LineEmbedding {
+                path: doc.path.clone(),
+                line_number: i as i32,
+                embedding: embeddings[i].clone(),
+                text: None,
+                table_line: None,
+            })
+            .collect();
+
+        store
+            .upsert_line_embeddings(&line_embeddings)
+            .expect("Failed to upsert line embeddings");
+
+        // Check stats
+        let stats = store.get_stats().expect("Failed to get stats");
+
+        assert_eq!(stats.total_documents, 3);
+        assert!(stats.has_index);
+        assert_eq!(stats.index_type, Some(INDEX_TYPE.to_string()));
+
+        drop(store);
+        drop(_temp_dir);
+    }
+
+    #[test]
+    fn test_search_line_embeddings() {
+        let (store, _temp_dir) = create_test_store();
+        let (docs, embeddings) = create_test_docs();
+
+        let line_embeddings: Vec<LineEmbedding> = docs
+            .iter()
+            .enumerate()
+            .map(|(i, doc)| LineEmbedding {
+                path: doc.path.clone(),
+                line_number: i as i32,
+                embedding: embeddings[i].clone(),
+                text: None,
+                table_line: None,
+            })
+            .collect();
+
+        store
+            .upsert_line_embeddings(&line_embeddings)
+            .expect("Failed to upsert line embeddings");
+
+        // Perform search
+        let exact_match_query: Vec<f32> = vec![0.1; 256];
+        let search_results = store
+            .search_line_embeddings(
+                exact_match_query.as_slice(),
+                &["/test/doc1.txt".to_string()],
+                1,
+                Some(0.1_f32),
+            )
+            .expect("Should be able to retrieve search results");
+        assert_eq!(search_results.len(), 1);
+        assert_eq!(search_results[0].line_number, 0);
+        assert_eq!(search_results[0].path, docs[0].path);
+        assert!(search_results[0].distance < 0.1);
+        assert!(search_results[0].embedding.is_empty());
+
+        // Vectors are only returned when requested
+        let with_vectors = store
+            .search_line_embeddings_with_vectors(
+                exact_match_query.as_slice(),
+                &["/test/doc1.txt".to_string()],
+                1,
+                Some(0.1_f32),
+                true,
+            )
+            .expect("Should be able to retrieve search results with vectors");
+        assert_eq!(with_vectors[0].embedding.len(), LINE_EMBEDDING_SIZE);
+
+        drop(store);
+        drop(_temp_dir);
+    }
+
+    #[test]
+    fn test_get_all_document_paths() {
+        let (store, _temp_dir) = create_test_store();
+        let (docs, _embeddings) = create_test_docs();
+
+        // Initially should be empty
+        let paths = store
+            .get_all_document_paths()
+            .expect("Failed to get document paths");
+        assert!(paths.is_empty());
+
+        // Insert documents
+        store
+            .upsert_document_metadata(&docs)
+            .expect("Failed to upsert documents");
+
+        // Should now have paths
+        let paths = store
+            .get_all_document_paths()
+            .expect("Failed to get document paths");
+
+        assert_eq!(paths.len(), 3);
+        assert!(paths.contains(&"/test/doc1.txt".to_string()));
+        assert!(paths.contains(&"/test/doc2.txt".to_string()));
+        assert!(paths.contains(&"/test/doc3.txt".to_string()));
+
+        drop(store);
+        drop(_temp_dir);
+    }
+
+    #[test]
+    fn test_get_existing_docs() {
+        let (store, _temp_dir) = create_test_store();
+        let (docs, _embeddings) = create_test_docs();
+
+        // Insert documents
+        store
+            .upsert_document_metadata(&docs)
+            .expect("Failed to upsert documents");
+
+        // Test getting existing docs
+        let query_paths = vec![
+            "/test/doc1.txt".to_string(),
+            "/test/doc2.txt".to_string(),
+            "/test/nonexistent.txt".to_string(),
+        ];
+
+        let existing = store
+            .get_existing_docs(&query_paths)
+            .expect("Failed to get existing docs");
+
+        assert_eq!(existing.len(), 2);
+        assert!(existing.contains_key("/test/doc1.txt"));
+        assert!(existing.contains_key("/test/doc2.txt"));
+        assert!(!existing.contains_key("/test/nonexistent.txt"));
+
+        // Verify metadata
+        let doc1_meta = existing.get("/test/doc1.txt").unwrap();
+        assert_eq!(doc1_meta.size_bytes, 100);
+        assert_eq!(doc1_meta.mtime, 1234567890);
+
+        drop(store);
+        drop(_temp_dir);
+    }
+
+    #[test]
+    fn test_delete_documents() {
+        let (store, _temp_dir) = create_test_store();
+        let (docs, _embeddings) = create_test_docs();
+
+        // Insert documents
+        store
+            .upsert_document_metadata(&docs)
+            .expect("Failed to upsert documents");
+
+        // Verify all documents exist
+        let all_paths = store
+            .get_all_document_paths()
+            .expect("Failed to get document paths");
+        assert_eq!(all_paths.len(), 3);
+
+        // Delete some documents
+        let to_delete = vec!["/test/doc1.txt".to_string(), "/test/doc3.txt".to_string()];
+        store
+            .delete_documents(&to_delete)
+            .expect("Failed to delete documents");
+
+        // Verify only doc2 remains
+        let remaining_paths = store
+            .get_all_document_paths()
+            .expect("Failed to get document paths");
+        assert_eq!(remaining_paths.len(), 1);
+        assert!(remaining_paths.contains(&"/test/doc2.txt".to_string()));
+
+        drop(store);
+        drop(_temp_dir);
+    }
+
+    #[test]
+    fn test_write_lock_is_shared_by_nested_guards() {
+        let (store, temp_dir) = create_test_store();
+        let (docs, _embeddings) = create_test_docs();
+
+        let outer = store.write_lock().unwrap();
+        // Writes inside the batch reuse the lock instead of waiting on it
+        store.upsert_document_metadata(&docs).unwrap();
+        {
+            let _inner = store.write_lock().unwrap();
+        }
+        // Another writer is kept out until the outer guard is dropped
+        assert!(WriteLock::acquire_with_timeout(temp_dir.path(), Duration::ZERO).is_err());
+        drop(outer);
+        WriteLock::acquire_with_timeout(temp_dir.path(), Duration::ZERO).unwrap();
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_writes_through_two_stores_are_kept() {
+        let (first, temp_dir) = create_test_store();
+        let second = Store::open(temp_dir.path().to_str().unwrap()).unwrap();
+        let (docs, _embeddings) = create_test_docs();
+
+        // Both stores were opened before either wrote; each must catch up with the other's
+        // writes instead of overwriting them with the index it loaded
+        first.upsert_document_metadata(&docs[..1]).unwrap();
+        second.upsert_document_metadata(&docs[1..2]).unwrap();
+        first.upsert_document_metadata(&docs[2..]).unwrap();
+        assert_eq!(second.get_all_document_paths().unwrap().len(), 3);
+        drop(first);
+        drop(second);
+
+        let store = Store::open(temp_dir.path().to_str().unwrap()).unwrap();
+        let mut paths = store.get_all_document_paths().unwrap();
+        paths.sort();
+        assert_eq!(
+            paths,
+            ["/test/doc1.txt", "/test/doc2.txt", "/test/doc3.txt"]
+        );
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_upsert_replaces_existing() {
+        let (store, _temp_dir) = create_test_store();
+
+        // Insert initial document
+        let initial_doc = DocMeta {
+            path: "/test/doc.txt".to_string(),
+            size_bytes: 100,
+            mtime: 1000,
+            _version: CURRENT_EMBEDDING_VERSION,
+            tags: BTreeMap::new(),
+            metadata: DocumentMetadata::default(),
+            normalization: Normalization::default(),
+        };
+        let _initial_embedding = [vec![1.0, 2.0, 3.0, 4.0]];
+
+        store
+            .upsert_document_metadata(&[initial_doc])
+            .expect("Failed to insert initial document");
+
+        // Verify document exists
+        let paths = store.get_all_document_paths().expect("Failed to get paths");
+        assert_eq!(paths.len(), 1);
+
+        // Update the same document
+        // NOTE: this works because the id of the data point depends on the hashing of the path.
+        // same path = same hash -> the update results in a replacement rather than an append
+        let updated_doc = DocMeta {
+            path: "/test/doc.txt".to_string(),
+            size_bytes: 200,
+            mtime: 2000,
+            _version: CURRENT_EMBEDDING_VERSION,
+            tags: BTreeMap::new(),
+            metadata: DocumentMetadata::default(),
+            normalization: Normalization::default(),
+        };
+        let _updated_embedding = [vec![5.0, 6.0, 7.0, 8.0]];
+
+        store
+            .upsert_document_metadata(&[updated_doc])
+            .expect("Failed to update document");
+
+        // Should still have only one document
+        let paths = store.get_all_document_paths().expect("Failed to get paths");
+        assert_eq!(paths.len(), 1);
+
+        // Verify metadata was updated
+        let existing = store
+            .get_existing_docs(&["/test/doc.txt".to_string()])
+            .expect("Failed to get existing docs");
+        let doc_meta = existing.get("/test/doc.txt").unwrap();
+        assert_eq!(doc_meta.size_bytes, 200);
+        assert_eq!(doc_meta.mtime, 2000);
+
+        drop(store);
+        drop(_temp_dir);
+    }
+
+    #[test]
+    fn test_upsert_line_embeddings_trims_shrunk_documents() {
+        let (store, _temp_dir) = create_test_store();
+
+        let lines = |count: i32| -> Vec<LineEmbedding> {
+            (0..count)
+                .map(|line_number| LineEmbedding {
+                    path: "/test/doc.txt".to_string(),
+                    line_number,
+                    embedding: vec![0.1; LINE_EMBEDDING_SIZE],
+                    text: None,
+                    table_line: None,
+                })
+                .collect()
+        };
+
+        store
+            .upsert_line_embeddings(&lines(5))
+            .expect("Failed to insert line embeddings");
+        assert_eq!(store.count_line_embeddings().unwrap(), 5);
+
+        // Re-upserting the same lines overwrites them in place
+        store
+            .upsert_line_embeddings(&lines(5))
+            .expect("Failed to re-upsert line embeddings");
+        assert_eq!(store.count_line_embeddings().unwrap(), 5);
+
+        // The document shrank: lines past the new end are removed
+        store
+            .upsert_line_embeddings(&lines(2))
+            .expect("Failed to upsert shrunk document");
+        assert_eq!(store.count_line_embeddings().unwrap(), 2);
+
+        // The document is empty now: it has no lines to upsert, so it is named explicitly
+        store
+            .replace_line_embeddings(&["/test/doc.txt".to_string()], &[])
+            .expect("Failed to replace the lines of an emptied document");
+        assert_eq!(store.count_line_embeddings().unwrap(), 0);
+
+        drop(store);
+        drop(_temp_dir);
+    }
+
+    #[test]
+    fn test_line_texts() {
+        let (store, _temp_dir) = create_test_store();
+
+        let lines: Vec<LineEmbedding> = ["alpha", "beta", "gamma", "delta"]
+            .iter()
+            .enumerate()
+            .map(|(line_number, text)| LineEmbedding {
+                path: "/test/doc.txt".to_string(),
+                line_number: line_number as i32,
+                embedding: vec![0.1; LINE_EMBEDDING_SIZE],
+                // The last line was indexed without its text
+                text: (line_number < 3).then(|| text.to_string()),
+                table_line: None,
+            })
+            .collect();
+        store
+            .upsert_line_embeddings(&lines)
+            .expect("Failed to insert line embeddings");
+
+        let texts = store.line_texts("/test/doc.txt", 1, 4).unwrap();
+        assert_eq!(
+            texts.into_iter().collect::<Vec<_>>(),
+            vec![(1, "beta".to_string()), (2, "gamma".to_string())]
+        );
+        assert!(
+            store
+                .line_texts("/test/other.txt", 0, 4)
+                .unwrap()
+                .is_empty()
+        );
+
+        drop(store);
+        drop(_temp_dir);
+    }
+
+    #[test]
+    fn test_select_candidate_documents() {
+        let (store, _temp_dir) = create_test_store();
+
+        let mut near = vec![0.0; LINE_EMBEDDING_SIZE];
+        near[0] = 1.0;
+        let mut far = vec![0.0; LINE_EMBEDDING_SIZE];
+        far[1] = 1.0;
+
+        store
+            .upsert_doc_embeddings(&[
+                DocEmbedding {
+                    path: "/test/near.txt".to_string(),
+                    embedding: near.clone(),
+                },
+                DocEmbedding {
+                    path: "/test/far.txt".to_string(),
+                    embedding: far,
+                },
+            ])
+            .expect("Failed to upsert doc embeddings");
+
+        let paths = vec![
+            "/test/near.txt".to_string(),
+            "/test/far.txt".to_string(),
+            "/test/legacy.txt".to_string(),
+        ];
+        let candidates = store
+            .select_candidate_documents(&near, &paths, 1)
+            .expect("Failed to select candidate documents");
+
+        // Closest document, plus the one without a document embedding
+        assert_eq!(
+            candidates,
+            vec!["/test/near.txt".to_string(), "/test/legacy.txt".to_string()]
+        );
+
+        store
+            .delete_documents(&["/test/near.txt".to_string()])
+            .expect("Failed to delete documents");
+        let candidates = store
+            .select_candidate_documents(&near, &paths[..2], 1)
+            .expect("Failed to select candidate documents");
+        assert_eq!(
+            candidates,
+            vec!["/test/far.txt".to_string(), "/test/near.txt".to_string()]
+        );
+
+        drop(store);
+        drop(_temp_dir);
+    }
+
+    #[test]
+    fn test_optimize_scheduling() {
+        let (store, _temp_dir) = create_test_store();
+        let (docs, embeddings) = create_test_docs();
+
+        assert_eq!(store.maintenance_state().rows_since_optimize, 0);
+        assert!(!store.needs_optimize(2));
+
+        let line_embeddings: Vec<LineEmbedding> = docs
+            .iter()
+            .enumerate()
+            .map(|(i, doc)| LineEmbedding {
+                path: doc.path.clone(),
+                line_number: i as i32,
+                embedding: embeddings[i].clone(),
+                text: None,
+                table_line: None,
+            })
+            .collect();
+
+        store
+            .upsert_line_embeddings(&line_embeddings)
+            .expect("Failed to upsert line embeddings");
+
+        assert_eq!(store.maintenance_state().rows_since_optimize, 3);
+        assert!(store.needs_optimize(2));
+        assert!(!store.needs_optimize(10));
+        // A threshold of 0 disables scheduled optimization
+        assert!(!store.needs_optimize(0));
+
+        let rows = store.optimize().expect("Failed to optimize");
+        assert_eq!(rows, 3);
+        assert_eq!(store.maintenance_state().rows_since_optimize, 0);
+
+        // Search still works with the payload index in place
+        let search_results = store
+            .search_line_embeddings(&[0.1; 256], &["/test/doc1.txt".to_string()], 1, None)
+            .expect("Should be able to retrieve search results");
+        assert_eq!(search_results.len(), 1);
+
+        drop(store);
+        drop(_temp_dir);
+    }
+
+    #[test]
+    fn test_open_with_dimensions() {
+        let temp_dir = TempDir::new().expect("Failed to create temp dir");
+        let workspace_dir = temp_dir.path().to_str().unwrap();
+
+        // The size only applies when the index is created
+        {
+            let store = Store::open_with_dimensions(workspace_dir, 128).unwrap();
+            store
+                .upsert_line_embeddings(&[LineEmbedding {
+                    path: "/test/code.rs".to_string(),
+                    line_number: 0,
+                    embedding: vec![0.1; 128],
+                    text: None,
+                    table_line: None,
+                }])
+                .expect("Failed to insert 128-dimensional embedding");
+        }
+
+        let store = Store::open(workspace_dir).unwrap();
+        let results = store
+            .search_line_embeddings(&[0.1; 128], &["/test/code.rs".to_string()], 1, None)
+            .expect("Failed to search 128-dimensional embeddings");
+        assert_eq!(results.len(), 1);
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_doc_meta_id_generation() {
+        let doc1 = DocMeta {
+            path: "test1.txt".to_string(),
+            size_bytes: 100,
+            mtime: 1000,
+            _version: CURRENT_EMBEDDING_VERSION,
+            tags: BTreeMap::new(),
+            metadata: DocumentMetadata::default(),
+            normalization: Normalization::default(),
+        };
+        let doc2 = DocMeta {
+            path: "test2.txt".to_string(),
+            size_bytes: 100,
+            mtime: 1000,
+            _version: CURRENT_EMBEDDING_VERSION,
+            tags: BTreeMap::new(),
+            metadata: DocumentMetadata::default(),
+            normalization: Normalization::default(),
+        };
+
+        let id1 = doc1.id();
+        let id2 = doc2.id();
+
+        // IDs should be different (random generation)
+        assert_ne!(id1, id2);
+    }
+
+    // Helper to create test files for analyze_document_states tests
+    fn create_test_files(temp_dir: &tempfile::TempDir) -> Vec<String> {
+        use std::fs;
+
+        let file1_path = temp_dir.path().join("test1.txt");
+        let file2_path = temp_dir.path().join("test2.txt");
+        let file3_path = temp_dir.path().join("test3.txt");
+
+        fs::write(&file1_path, "This is test file 1\nWith multiple lines").unwrap();
+        fs::write(&file2_path, "This is test file 2\nWith different content").unwrap();
+        fs::write(&file3_path, "This is test file 3\nWith more content").unwrap();
+
+        vec![
+            file1_path.to_string_lossy().to_string(),
+            file2_path.to_string_lossy().to_string(),
+            file3_path.to_string_lossy().to_string(),
+        ]
+    }
+
+    #[test]
+    fn test_analyze_document_states_all_new() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_paths = create_test_files(&temp_dir);
+
+        // Create empty store
+        let store = Store::open(temp_dir.path().to_str().unwrap()).unwrap();
+
+        let states = store.analyze_document_states(&file_paths).unwrap();
+
+        assert_eq!(states.len(), 3);
+
+        // All should be new documents
+        for state in &states {
+            if let DocumentState::New(doc_info) = state {
+                assert!(file_paths.contains(&doc_info.filename));
+                assert!(!doc_info.content.is_empty());
+                assert!(doc_info.meta.size_bytes > 0);
+                assert!(doc_info.meta.mtime > 0);
+            } else {
+                panic!("Expected New document state");
+            }
+        }
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_analyze_document_states_unchanged() {
+        use std::fs;
+        use std::time::UNIX_EPOCH;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_paths = create_test_files(&temp_dir);
+
+        // Create store and add documents
+        let store = Store::open(temp_dir.path().to_str().unwrap()).unwrap();
+
+        // Insert documents with current metadata
+        let mut docs = Vec::new();
+        for path in &file_paths {
+            let metadata = fs::metadata(path).unwrap();
+            let doc_meta = DocMeta {
+                path: path.clone(),
+                size_bytes: metadata.len(),
+                mtime: metadata
+                    .modified()
+                    .unwrap()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as i64,
+                _version: CURRENT_EMBEDDING_VERSION,
+                tags: BTreeMap::new(),
+                metadata: DocumentMetadata::default(),
+                normalization: Normalization::default(),
+            };
+            docs.push(doc_meta);
+        }
+        store.upsert_document_metadata(&docs).unwrap();
+
+        // Analyze states - should all be unchanged
+        let states = store.analyze_document_states(&file_paths).unwrap();
+
+        assert_eq!(states.len(), 3);
+
+        for state in &states {
+            if let DocumentState::Unchanged(filename) = state {
+                assert!(file_paths.contains(filename));
+            } else {
+                panic!("Expected Unchanged document state");
+            }
+        }
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_analyze_document_states_changed() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_paths = create_test_files(&temp_dir);
+
+        // Create store and add documents with old metadata
+        let store = Store::open(temp_dir.path().to_str().unwrap()).unwrap();
+
+        let mut docs = Vec::new();
+        for path in &file_paths {
+            let doc_meta = DocMeta {
+                path: path.clone(),
+                size_bytes: 10, // Different from actual size
+                mtime: 1000,    // Old timestamp
+                _version: 1,    // simulate old version
+                tags: BTreeMap::new(),
+                metadata: DocumentMetadata::default(),
+                normalization: Normalization::default(),
+            };
+            docs.push(doc_meta);
+        }
+        store.upsert_document_metadata(&docs).unwrap();
+
+        // Analyze states - should all be changed
+        let states = store.analyze_document_states(&file_paths).unwrap();
+
+        assert_eq!(states.len(), 3);
+
+        for state in &states {
+            if let DocumentState::Changed(doc_info) = state {
+                assert!(file_paths.contains(&doc_info.filename));
+                assert!(!doc_info.content.is_empty());
+            } else {
+                panic!("Expected Changed document state");
+            }
+        }
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_tag_and_filter_documents() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_paths = create_test_files(&temp_dir);
+        let store = Store::open(temp_dir.path().to_str().unwrap()).unwrap();
+        let docs: Vec<DocMeta> = file_paths
+            .iter()
+            .map(|path| DocMeta {
+                path: path.clone(),
+                size_bytes: 10,
+                mtime: 1000,
+                _version: CURRENT_EMBEDDING_VERSION,
+                tags: BTreeMap::new(),
+                metadata: DocumentMetadata::default(),
+                normalization: Normalization::default(),
+            })
+            .collect();
+        store.upsert_document_metadata(&docs).unwrap();
+
+        let tag = |k: &str, v: &str| BTreeMap::from([(k.to_string(), v.to_string())]);
+        let filter = |k: &str, v: &str| TagFilter {
+            key: k.to_string(),
+            value: v.to_string(),
+        };
+        assert_eq!(
+            store
+                .tag_documents(&file_paths[..2], &tag("project", "alpha"))
+                .unwrap(),
+            2
+        );
+        store
+            .tag_documents(&file_paths[1..], &tag("team", "search"))
+            .unwrap();
+
+        let alpha = store
+            .filter_documents(&file_paths, &[filter("project", "alpha")], &[])
+            .unwrap();
+        assert_eq!(alpha, file_paths[..2].to_vec());
+        let both = store
+            .filter_documents(
+                &file_paths,
+                &[filter("project", "alpha"), filter("team", "search")],
+                &[],
+            )
+            .unwrap();
+        assert_eq!(both, vec![file_paths[1].clone()]);
+        assert!(
+            store
+                .filter_documents(&file_paths, &[filter("project", "beta")], &[])
+                .unwrap()
+                .is_empty()
+        );
+
+        // Metadata filters combine with tag filters
+        let mut authored = store.get_existing_docs(&file_paths).unwrap()[&file_paths[1]].clone();
+        authored.metadata.author = Some("Jane Smith".to_string());
+        store.upsert_document_metadata(&[authored]).unwrap();
+        let by_smith = |tags: &[TagFilter]| {
+            store
+                .filter_documents(&file_paths, tags, &["author:smith".parse().unwrap()])
+                .unwrap()
+        };
+        assert_eq!(by_smith(&[]), vec![file_paths[1].clone()]);
+        assert_eq!(
+            by_smith(&[filter("team", "search")]),
+            vec![file_paths[1].clone()]
+        );
+        assert!(by_smith(&[filter("project", "beta")]).is_empty());
+
+        // Re-indexing a changed document keeps its tags
+        let states = store.analyze_document_states(&file_paths[..1]).unwrap();
+        match &states[0] {
+            DocumentState::Changed(doc_info) => {
+                assert_eq!(doc_info.meta.tags, tag("project", "alpha"))
+            }
+            _ => panic!("Expected Changed document state"),
+        }
+    }
+
+    #[test]
+    fn test_analyze_document_states_mixed() {
+        use std::fs;
+        use std::time::UNIX_EPOCH;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_paths = create_test_files(&temp_dir);
+
+        // Create store and add only the first document
+        let store = Store::open(temp_dir.path().to_str().unwrap()).unwrap();
+
+        let metadata = fs::metadata(&file_paths[0]).unwrap();
+        let doc_meta = DocMeta {
+            path: file_paths[0].clone(),
+            size_bytes: metadata.len(),
+            mtime: metadata
+                .modified()
+                .unwrap()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64,
+            _version: CURRENT_EMBEDDING_VERSION,
+            tags: BTreeMap::new(),
+            metadata: DocumentMetadata::default(),
+            normalization: Normalization::default(),
+        };
+        store.upsert_document_metadata(&[doc_meta]).unwrap();
+
+        // Analyze states
+        let states = store.analyze_document_states(&file_paths).unwrap();
+
+        assert_eq!(states.len(), 3);
+
+        // First should be unchanged, others should be new
+        let mut unchanged_count = 0;
+        let mut new_count = 0;
+
+        for state in &states {
+            match state {
+                DocumentState::Unchanged(filename) => {
+                    assert_eq!(filename, &file_paths[0]);
+                    unchanged_count += 1;
+                }
+                DocumentState::New(doc_info) => {
+                    assert!(file_paths[1..].contains(&doc_info.filename));
+                    new_count += 1;
+                }
+                _ => panic!("Unexpected document state"),
+            }
+        }
+
+        assert_eq!(unchanged_count, 1);
+        assert_eq!(new_count, 2);
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_analyze_document_states_version_mismatch() {
+        use std::fs;
+        use std::time::UNIX_EPOCH;
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_paths = create_test_files(&temp_dir);
+
+        // Create store and add documents with old version but correct size/mtime
+        let store = Store::open(temp_dir.path().to_str().unwrap()).unwrap();
+
+        let mut old_docs = Vec::new();
+        for path in &file_paths {
+            let metadata = fs::metadata(path).unwrap();
+            let doc_meta = DocMeta {
+                path: path.clone(),
+                size_bytes: metadata.len(),
+                mtime: metadata
+                    .modified()
+                    .unwrap()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs() as i64,
+                _version: 1, // older version than CURRENT_EMBEDDING_VERSION (2)
+                tags: BTreeMap::new(),
+                metadata: DocumentMetadata::default(),
+                normalization: Normalization::default(),
+            };
+            old_docs.push(doc_meta);
+        }
+        store.upsert_document_metadata(&old_docs).unwrap();
+
+        let states = store.analyze_document_states(&file_paths).unwrap();
+        assert_eq!(states.len(), 3);
+        for state in &states {
+            match state {
+                DocumentState::Changed(info) => {
+                    assert!(file_paths.contains(&info.filename));
+                }
+                _ => panic!("Expected Changed state due to version mismatch"),
+            }
+        }
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    fn test_analyze_document_states_nonexistent_file() {
+        use tempfile::TempDir;
+
+        let temp_dir = TempDir::new().unwrap();
+        let mut file_paths = create_test_files(&temp_dir);
+
+        // Add a nonexistent file to the list
+        file_paths.push("/nonexistent/file.txt".to_string());
+
+        let store = Store::open(temp_dir.path().to_str().unwrap()).unwrap();
+
+        let states = store.analyze_document_states(&file_paths).unwrap();
+
+        // Should only have states for existing files
+        assert_eq!(states.len(), 3);
+
+        for state in &states {
+            if let DocumentState::New(doc_info) = state {
+                assert_ne!(doc_info.filename, "/nonexistent/file.txt");
+            }
+        }
+
+        drop(store);
+        drop(temp_dir);
+    }
+}
@@ -0,0 +1,1348 @@
+//! Qdrant Edge storage wrapper
+use anyhow::{Result, anyhow};
+
+use super::{
+    CURRENT_EMBEDDING_VERSION, DocEmbedding, DocMeta, LINE_EMBEDDING_SIZE, LineEmbedding,
+    MaintenanceState, RankedLine, fnv1a_hash, open_data_dir,
+};
+use crate::metadata::MetadataFilter;
+use crate::search::TagFilter;
+use crate::telemetry::{self, Operation};
+use crate::workspace::encryption::UnsealedWorkspace;
+use crate::workspace::error::WorkspaceError;
+use crate::workspace::lock::{LOCK_TIMEOUT, RETRY_INTERVAL, WriteLock};
+use ordered_float::OrderedFloat;
+use qdrant_edge::EdgeShard;
+use qdrant_edge::segment::data_types::vectors::NamedQuery;
+use qdrant_edge::segment::data_types::vectors::{VectorInternal, VectorStructInternal};
+use qdrant_edge::segment::json_path::JsonPath;
+use qdrant_edge::segment::types::{
+    AnyVariants, Condition, Distance, ExtendedPointId, FieldCondition, Filter, Match, Payload,
+    PayloadFieldSchema, PayloadSchemaType, PayloadStorageType, Range, SegmentConfig, ValueVariants,
+    VectorDataConfig, VectorStorageType, WithPayloadInterface, WithVector,
+};
+use qdrant_edge::shard::count::CountRequestInternal;
+use qdrant_edge::shard::operations::point_ops::{
+    PointInsertOperationsInternal, PointOperations, PointStructPersisted,
+};
+use qdrant_edge::shard::operations::{
+    CollectionUpdateOperations, CreateIndex, FieldIndexOperations,
+};
+use qdrant_edge::shard::query::query_enum::QueryEnum;
+use qdrant_edge::shard::query::{ScoringQuery, ShardQueryRequest};
+use qdrant_edge::shard::scroll::ScrollRequestInternal;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+/// Shard directories (inside the workspace directory) that make up the index
+pub(super) const INDEX_ITEMS: [&str; 3] = [
+    "documents.qdrant",
+    "line_embeddings.qdrant",
+    "doc_embeddings.qdrant",
+];
+
+/// How `workspace status` describes the index
+pub(super) const INDEX_TYPE: &str = "HNSW";
+
+/// We are not actually storing document-level embeddings,
+/// but Qdrant requires a vector size to be defined for the collection, so we use a dummy size of 1.
+/// This collection is being used for document-level metadata
+pub const DOCUMENT_EMBEDDING_SIZE: usize = 1;
+
+/// Vector name used in the documents shard
+const DOCUMENTS_VECTOR_NAME: &str = "documents";
+
+/// Vector name used in the line embeddings shard
+const LINE_EMBEDDINGS_VECTOR_NAME: &str = "line_embeddings";
+
+/// Vector name used in the document embeddings shard
+const DOC_EMBEDDINGS_VECTOR_NAME: &str = "doc_embeddings";
+
+/// Default limit for Qdrant retrieval
+const DEFAULT_RETRIEVAL_LIMIT: usize = 10000;
+
+/// Load a shard, creating it with a single named vector if it does not exist yet
+fn load_shard(path: &Path, vector_name: &str, size: usize) -> Result<EdgeShard> {
+    let segment_config: Option<SegmentConfig> = if !path.exists() {
+        std::fs::create_dir_all(path)?;
+        let mut vector_data = HashMap::new();
+        vector_data.insert(
+            vector_name.to_string(),
+            VectorDataConfig {
+                size,
+                distance: Distance::Cosine,
+                storage_type: VectorStorageType::ChunkedMmap,
+                index: Default::default(),
+                quantization_config: None,
+                multivector_config: None,
+                datatype: None,
+            },
+        );
+
+        Some(SegmentConfig {
+            vector_data,
+            sparse_vector_data: HashMap::new(),
+            payload_storage_type: PayloadStorageType::Mmap,
+        })
+    } else {
+        None
+    };
+
+    Ok(EdgeShard::load(path, segment_config)?)
+}
+
+/// The shards of the index
+struct Shards {
+    documents: EdgeShard,
+    line_embeddings: EdgeShard,
+    /// Mean line embedding per document, used for first-stage document retrieval
+    doc_embeddings: EdgeShard,
+}
+
+impl Shards {
+    /// Load the shards, waiting while another process has them loaded: Qdrant Edge locks a
+    /// shard's WAL for as long as it is open
+    fn load(data_dir: &Path, dimensions: usize) -> Result<Self> {
+        let started = Instant::now();
+        let mut waiting = false;
+        loop {
+            match Self::try_load(data_dir, dimensions) {
+                Err(e) if format!("{e:#}").contains("WouldBlock") => {
+                    let holder = "another semtools process".to_string();
+                    if started.elapsed() >= LOCK_TIMEOUT {
+                        return Err(WorkspaceError::Locked {
+                            holder,
+                            waited: LOCK_TIMEOUT,
+                        }
+                        .into());
+                    }
+                    if !waiting {
+                        eprintln!("Waiting for {holder} to finish with the workspace index...");
+                        waiting = true;
+                    }
+                    std::thread::sleep(RETRY_INTERVAL);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn try_load(data_dir: &Path, dimensions: usize) -> Result<Self> {
+        Ok(Self {
+            documents: load_shard(
+                &data_dir.join(INDEX_ITEMS[0]),
+                DOCUMENTS_VECTOR_NAME,
+                DOCUMENT_EMBEDDING_SIZE,
+            )?,
+            line_embeddings: load_shard(
+                &data_dir.join(INDEX_ITEMS[1]),
+                LINE_EMBEDDINGS_VECTOR_NAME,
+                dimensions,
+            )?,
+            doc_embeddings: load_shard(
+                &data_dir.join(INDEX_ITEMS[2]),
+                DOC_EMBEDDINGS_VECTOR_NAME,
+                dimensions,
+            )?,
+        })
+    }
+}
+
+/// Shards in use by an operation of a store. The last guard closes them unless the store
+/// holds the write lock, in which case they stay open until it is released.
+struct ShardsGuard<'a> {
+    store: &'a Store,
+    shards: Option<Arc<Shards>>,
+}
+
+impl Deref for ShardsGuard<'_> {
+    type Target = Shards;
+
+    fn deref(&self) -> &Shards {
+        self.shards
+            .as_ref()
+            .expect("shards are held until the guard is dropped")
+    }
+}
+
+impl Drop for ShardsGuard<'_> {
+    fn drop(&mut self) {
+        self.shards = None;
+        let writing = self
+            .store
+            .write_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .0
+            > 0;
+        if !writing {
+            self.store.close_shards();
+        }
+    }
+}
+
+/// Storage wrapper around Qdrant Edge.
+///
+/// Qdrant Edge holds a shard's WAL exclusively while it is loaded, so the shards are only kept
+/// open while an operation uses them, or while the store holds the write lock. Other processes
+/// wait for them in between, and always load what was last written.
+pub struct Store {
+    pub(super) workspace_dir: PathBuf,
+    shards: Mutex<Option<Arc<Shards>>>,
+    /// Embedding size to create missing shards with
+    dimensions: usize,
+    /// Workspace directory holding the write lock (the encrypted one for encrypted workspaces)
+    pub(super) root_dir: PathBuf,
+    /// Write lock held by this store, and the number of live guards sharing it
+    pub(super) write_lock: Mutex<(usize, Option<WriteLock>)>,
+    /// Decrypted copy of an encrypted workspace. Declared last so the shards are flushed and
+    /// dropped before it is sealed again.
+    pub(super) unsealed: Option<UnsealedWorkspace>,
+}
+
+impl Store {
+    /// Initialize or load storage for a workspace directory, with the default embedding size
+    pub fn open(workspace_dir: &str) -> Result<Self> {
+        Self::open_with_dimensions(workspace_dir, LINE_EMBEDDING_SIZE)
+    }
+
+    /// Initialize or load storage for a workspace directory. `dimensions` is only used when the
+    /// index is created; an existing index keeps the size it was created with.
+    pub fn open_with_dimensions(workspace_dir: &str, dimensions: usize) -> Result<Self> {
+        let (unsealed, data_dir) = open_data_dir(workspace_dir)?;
+        if data_dir.join("documents.flat").exists() {
+            anyhow::bail!(
+                "{workspace_dir} was indexed by a build with only the `workspace-lite` feature. \
+                 Use that build, or re-create the workspace"
+            );
+        }
+        // Create the index if it is missing, and check it loads
+        drop(Shards::load(&data_dir, dimensions)?);
+
+        Ok(Self {
+            shards: Mutex::new(None),
+            dimensions,
+            workspace_dir: data_dir,
+            root_dir: Path::new(workspace_dir).to_path_buf(),
+            write_lock: Mutex::new((0, None)),
+            unsealed,
+        })
+    }
+
+    /// The shards, loaded if no operation of this store has them open
+    fn shards(&self) -> Result<ShardsGuard<'_>> {
+        let mut slot = self.shards.lock().unwrap_or_else(PoisonError::into_inner);
+        let shards = match &*slot {
+            Some(shards) => shards.clone(),
+            None => slot
+                .insert(Arc::new(Shards::load(
+                    &self.workspace_dir,
+                    self.dimensions,
+                )?))
+                .clone(),
+        };
+        Ok(ShardsGuard {
+            store: self,
+            shards: Some(shards),
+        })
+    }
+
+    /// Flush and close the shards unless an operation still uses them, so other processes can
+    /// load them. Called when the write lock is released.
+    pub(super) fn close_shards(&self) {
+        let mut slot = self.shards.lock().unwrap_or_else(PoisonError::into_inner);
+        if slot
+            .as_ref()
+            .is_some_and(|shards| Arc::strong_count(shards) == 1)
+        {
+            *slot = None;
+        }
+    }
+
+    /// The shards if an operation of this store has them open
+    fn open_shards(&self) -> Option<Arc<Shards>> {
+        self.shards
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn get_existing_docs(&self, paths: &[String]) -> Result<HashMap<String, DocMeta>> {
+        let shards = self.shards()?;
+        let mut existing = HashMap::new();
+        let docs_count = self.count_documents();
+        let retrieval_limit = match docs_count {
+            Ok(count) => count,
+            Err(_) => DEFAULT_RETRIEVAL_LIMIT,
+        };
+
+        for chunk in paths.chunks(1000) {
+            let scroll_result = shards.documents.scroll(ScrollRequestInternal {
+                offset: None,
+                order_by: None,
+                with_vector: WithVector::Bool(false),
+                with_payload: Some(WithPayloadInterface::Bool(true)),
+                filter: Some(Filter {
+                    must: Some(vec![Condition::Field(FieldCondition::new_match(
+                        JsonPath::from_str("path").map_err(|_| {
+                            anyhow!("An error occurred while creating JSONPath from 'path'")
+                        })?,
+                        Match::from(AnyVariants::Strings(chunk.iter().cloned().collect())),
+                    ))]),
+                    must_not: None,
+                    should: None,
+                    min_should: None,
+                }),
+                limit: Some(retrieval_limit),
+            });
+            let records = match scroll_result {
+                Ok(r) => {
+                    let (recs, _) = r;
+                    recs
+                }
+                Err(e) => return Err(anyhow!(e.to_string())),
+            };
+            for record in records {
+                match record.payload {
+                    None => {}
+                    Some(r) => {
+                        let meta = payload_to_doc_meta(&r)?;
+                        let path = meta.clone().path;
+                        existing.insert(path, meta);
+                    }
+                }
+            }
+        }
+
+        Ok(existing)
+    }
+
+    /// Delete document metadata by path
+    pub fn delete_document_metadata(&self, paths: &[String]) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.write_lock()?;
+
+        let mut point_ids: Vec<ExtendedPointId> = vec![];
+        let docs_count = self.count_documents();
+        let retrieval_limit = match docs_count {
+            Ok(count) => count,
+            Err(_) => DEFAULT_RETRIEVAL_LIMIT,
+        };
+
+        // collect all point IDs to be deleted
+        for chunk in paths.chunks(1000) {
+            let scroll_result = self.shards()?.documents.scroll(ScrollRequestInternal {
+                offset: None,
+                order_by: None,
+                with_vector: WithVector::Bool(false),
+                with_payload: Some(WithPayloadInterface::Bool(true)),
+                filter: Some(Filter {
+                    must: Some(vec![
+                        Condition::Field(FieldCondition::new_match(
+                            JsonPath::from_str("path").map_err(|_| {
+                                anyhow!("An error occurred while creating JSONPath from 'path'")
+                            })?,
+                            Match::from(AnyVariants::Strings(chunk.iter().cloned().collect())),
+                        )),
+                        Condition::Field(FieldCondition::new_match(
+                            JsonPath::from_str("_version").map_err(|_| {
+                                anyhow!("An error occurred while creating JSONPath from '_version'")
+                            })?,
+                            Match::new_value(ValueVariants::Integer(
+                                CURRENT_EMBEDDING_VERSION as i64,
+                            )),
+                        )),
+                    ]),
+                    must_not: None,
+                    should: None,
+                    min_should: None,
+                }),
+                limit: Some(retrieval_limit),
+            });
+            let records = match scroll_result {
+                Ok(r) => {
+                    let (recs, _) = r;
+                    recs
+                }
+                Err(e) => return Err(anyhow!(e.to_string())),
+            };
+            for record in records {
+                point_ids.push(record.id);
+            }
+        }
+
+        let operation = CollectionUpdateOperations::PointOperation(PointOperations::DeletePoints {
+            ids: point_ids,
+        });
+
+        self.shards()?
+            .documents
+            .update(operation)
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        // Flush changes to disk
+        self.flush_documents();
+
+        Ok(())
+    }
+
+    /// Delete line embeddings by path
+    pub fn delete_line_embeddings(&self, paths: &[String]) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.write_lock()?;
+
+        let mut point_ids: Vec<ExtendedPointId> = vec![];
+        let line_embds_count = self.count_line_embeddings();
+        let retrieval_limit = match line_embds_count {
+            Ok(count) => count,
+            Err(_) => DEFAULT_RETRIEVAL_LIMIT,
+        };
+
+        // collect all point IDs to be deleted
+        for chunk in paths.chunks(1000) {
+            let scroll_result = self
+                .shards()?
+                .line_embeddings
+                .scroll(ScrollRequestInternal {
+                    offset: None,
+                    order_by: None,
+                    with_vector: WithVector::Bool(false),
+                    with_payload: Some(WithPayloadInterface::Bool(true)),
+                    filter: Some(Filter::new_must(Condition::Field(
+                        FieldCondition::new_match(
+                            JsonPath::from_str("path").map_err(|_| {
+                                anyhow!("An error occurred while creating JSONPath from 'path'")
+                            })?,
+                            Match::from(AnyVariants::Strings(chunk.iter().cloned().collect())),
+                        ),
+                    ))),
+                    limit: Some(retrieval_limit),
+                });
+            let records = match scroll_result {
+                Ok(r) => {
+                    let (recs, _) = r;
+                    recs
+                }
+                Err(e) => return Err(anyhow!(e.to_string())),
+            };
+            for record in records {
+                point_ids.push(record.id);
+            }
+        }
+
+        let operation = CollectionUpdateOperations::PointOperation(PointOperations::DeletePoints {
+            ids: point_ids,
+        });
+
+        self.shards()?
+            .line_embeddings
+            .update(operation)
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        // flush changes to disk
+        self.flush_line_embeddings();
+
+        Ok(())
+    }
+
+    /// Delete document-level embeddings by path
+    pub fn delete_doc_embeddings(&self, paths: &[String]) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.write_lock()?;
+
+        let point_ids: Vec<ExtendedPointId> = paths
+            .iter()
+            .map(|path| ExtendedPointId::NumId(fnv1a_hash(path.as_bytes())))
+            .collect();
+        let operation = CollectionUpdateOperations::PointOperation(PointOperations::DeletePoints {
+            ids: point_ids,
+        });
+        self.shards()?
+            .doc_embeddings
+            .update(operation)
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Upsert document-level embeddings
+    pub fn upsert_doc_embeddings(&self, doc_embeddings: &[DocEmbedding]) -> Result<()> {
+        if doc_embeddings.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.write_lock()?;
+
+        for chunk in doc_embeddings.chunks(1000) {
+            let mut points: Vec<PointStructPersisted> = vec![];
+            for doc_embedding in chunk {
+                let payload_json =
+                    serde_json::to_value(doc_embedding).map_err(|e| anyhow!(e.to_string()))?;
+                let point = make_point(
+                    doc_embedding.id(),
+                    doc_embedding.embedding.clone(),
+                    payload_json,
+                    DOC_EMBEDDINGS_VECTOR_NAME,
+                );
+                points.push(point);
+            }
+            let operation = CollectionUpdateOperations::PointOperation(
+                PointOperations::UpsertPoints(PointInsertOperationsInternal::PointsList(points)),
+            );
+            self.shards()?
+                .doc_embeddings
+                .update(operation)
+                .map_err(|e| anyhow!(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// First-stage retrieval: the `doc_top_k` documents closest to the query, plus any document
+    /// in `subset_paths` that has no document embedding yet (indexed before they existed)
+    pub fn select_candidate_documents(
+        &self,
+        query_vec: &[f32],
+        subset_paths: &[String],
+        doc_top_k: usize,
+    ) -> Result<Vec<String>> {
+        let _timed = telemetry::timed(Operation::StoreQuery, &[("shard", "doc_embeddings")]);
+        let shards = self.shards()?;
+        let mut ranked: Vec<(String, f32)> = vec![];
+        let mut with_embedding: HashSet<String> = HashSet::new();
+
+        for chunk in subset_paths.chunks(1000) {
+            let path_filter = Filter::new_must(Condition::Field(FieldCondition::new_match(
+                JsonPath::from_str("path").map_err(|_| {
+                    anyhow!("An error occurred while creating JSONPath from 'path'")
+                })?,
+                Match::from(AnyVariants::Strings(chunk.iter().cloned().collect())),
+            )));
+
+            let query: Vec<f32> = query_vec.into();
+            let vector: VectorInternal = query.into();
+            let results = shards
+                .doc_embeddings
+                .query(ShardQueryRequest {
+                    prefetches: vec![],
+                    query: Some(ScoringQuery::Vector(QueryEnum::Nearest(NamedQuery {
+                        query: vector,
+                        using: Some(DOC_EMBEDDINGS_VECTOR_NAME.to_string()),
+                    }))),
+                    filter: Some(path_filter.clone()),
+                    score_threshold: None,
+                    limit: doc_top_k,
+                    offset: 0,
+                    params: None,
+                    with_vector: WithVector::Bool(false),
+                    with_payload: WithPayloadInterface::Bool(true),
+                })
+                .map_err(|e| anyhow!(e.to_string()))?;
+            for result in results {
+                if let Some(path) = result.payload.as_ref().and_then(payload_path) {
+                    ranked.push((path, 1_f32 - result.score));
+                }
+            }
+
+            let (records, _) = shards
+                .doc_embeddings
+                .scroll(ScrollRequestInternal {
+                    offset: None,
+                    order_by: None,
+                    with_vector: WithVector::Bool(false),
+                    with_payload: Some(WithPayloadInterface::Bool(true)),
+                    filter: Some(path_filter),
+                    limit: Some(chunk.len()),
+                })
+                .map_err(|e| anyhow!(e.to_string()))?;
+            with_embedding.extend(
+                records
+                    .into_iter()
+                    .filter_map(|record| record.payload.as_ref().and_then(payload_path)),
+            );
+        }
+
+        // Ties in path order, so the same documents make the cut on every run
+        ranked.sort_by(|a, b| {
+            a.1.partial_cmp(&b.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.0.cmp(&b.0))
+        });
+        ranked.truncate(doc_top_k);
+
+        let mut candidates: Vec<String> = ranked.into_iter().map(|(path, _)| path).collect();
+        candidates.extend(
+            subset_paths
+                .iter()
+                .filter(|path| !with_embedding.contains(*path))
+                .cloned(),
+        );
+
+        Ok(candidates)
+    }
+
+    /// Upsert documents metadata (no embeddings stored)
+    pub fn upsert_document_metadata(&self, metas: &[DocMeta]) -> Result<()> {
+        if metas.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.write_lock()?;
+
+        for chunk in metas.chunks(1000) {
+            let mut points: Vec<PointStructPersisted> = vec![];
+            for meta in chunk {
+                let payload_json =
+                    serde_json::to_value(meta).map_err(|e| anyhow!(e.to_string()))?;
+                let vector: Vec<f32> = vec![1_f32];
+                let point = make_point(meta.id(), vector, payload_json, DOCUMENTS_VECTOR_NAME);
+                points.push(point);
+            }
+            let operation = CollectionUpdateOperations::PointOperation(
+                PointOperations::UpsertPoints(PointInsertOperationsInternal::PointsList(points)),
+            );
+            self.shards()?
+                .documents
+                .update(operation)
+                .map_err(|e| anyhow!(e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// The documents among `paths` carrying every tag in `filters` and matching every filter
+    /// in `metadata_filters`, in the order of `paths`. The tags are matched by a payload
+    /// filter on the documents shard, the metadata on the payloads it returns.
+    pub fn filter_documents(
+        &self,
+        paths: &[String],
+        filters: &[TagFilter],
+        metadata_filters: &[MetadataFilter],
+    ) -> Result<Vec<String>> {
+        let _timed = telemetry::timed(Operation::StoreQuery, &[("shard", "documents")]);
+        let shards = self.shards()?;
+        let mut matching: HashSet<String> = HashSet::new();
+
+        for chunk in paths.chunks(1000) {
+            let mut conditions = vec![Condition::Field(FieldCondition::new_match(
+                JsonPath::from_str("path").map_err(|_| {
+                    anyhow!("An error occurred while creating JSONPath from 'path'")
+                })?,
+                Match::from(AnyVariants::Strings(chunk.iter().cloned().collect())),
+            ))];
+            for filter in filters {
+                let field = format!("tags.{}", filter.key);
+                conditions.push(Condition::Field(FieldCondition::new_match(
+                    JsonPath::from_str(&field).map_err(|_| {
+                        anyhow!("An error occurred while creating JSONPath from '{field}'")
+                    })?,
+                    Match::new_value(ValueVariants::String(filter.value.clone())),
+                )));
+            }
+
+            let (records, _) = shards
+                .documents
+                .scroll(ScrollRequestInternal {
+                    offset: None,
+                    order_by: None,
+                    with_vector: WithVector::Bool(false),
+                    with_payload: Some(WithPayloadInterface::Bool(true)),
+                    filter: Some(Filter {
+                        must: Some(conditions),
+                        must_not: None,
+                        should: None,
+                        min_should: None,
+                    }),
+                    limit: Some(chunk.len()),
+                })
+                .map_err(|e| anyhow!(e.to_string()))?;
+            matching.extend(records.into_iter().filter_map(|record| {
+                let payload = record.payload.as_ref()?;
+                if !metadata_filters.is_empty() {
+                    let meta = payload_to_doc_meta(payload).ok()?;
+                    if !metadata_filters
+                        .iter()
+                        .all(|filter| filter.matches(&meta.metadata))
+                    {
+                        return None;
+                    }
+                }
+                payload_path(payload)
+            }));
+        }
+
+        Ok(paths
+            .iter()
+            .filter(|path| matching.contains(*path))
+            .cloned()
+            .collect())
+    }
+
+    /// Store `line_embeddings` as the lines of the documents at `paths`, upserted in place and
+    /// keyed on the deterministic point id.
+    ///
+    /// Existing lines are overwritten rather than deleted and re-added, so an interrupted
+    /// update never leaves a document without embeddings. Lines past the new end of each
+    /// document (i.e. the file shrank) are removed afterwards, and so are all the lines of a
+    /// document in `paths` that has none in `line_embeddings` (the file is empty now).
+    pub fn replace_line_embeddings(
+        &self,
+        paths: &[String],
+        line_embeddings: &[LineEmbedding],
+    ) -> Result<()> {
+        if paths.is_empty() && line_embeddings.is_empty() {
+            return Ok(());
+        }
+        let _lock = self.write_lock()?;
+
+        let mut line_counts: HashMap<String, i32> =
+            paths.iter().map(|path| (path.clone(), 0)).collect();
+        for line_embedding in line_embeddings {
+            let count = line_counts.entry(line_embedding.path.clone()).or_default();
+            *count = (*count).max(line_embedding.line_number + 1);
+        }
+
+        for chunk in line_embeddings.chunks(1000) {
+            let mut points: Vec<PointStructPersisted> = vec![];
+
+            for line_embedding in chunk {
+                let payload_json =
+                    serde_json::to_value(line_embedding).map_err(|e| anyhow!(e.to_string()))?;
+                let point = make_point(
+                    line_embedding.id(),
+                    line_embedding.embedding.clone(),
+                    payload_json,
+                    LINE_EMBEDDINGS_VECTOR_NAME,
+                );
+                points.push(point);
+            }
+
+            let operation = CollectionUpdateOperations::PointOperation(
+                PointOperations::UpsertPoints(PointInsertOperationsInternal::PointsList(points)),
+            );
+            self.shards()?
+                .line_embeddings
+                .update(operation)
+                .map_err(|e| anyhow!(e.to_string()))?;
+        }
+
+        self.delete_stale_line_embeddings(&line_counts)?;
+
+        // Writes are already durable in the WAL; segment flushing and payload indexing
+        // are deferred to `optimize` so interactive searches are not blocked on them.
+        self.record_new_rows(line_embeddings.len())?;
+
+        Ok(())
+    }
+
+    /// Delete line embeddings at or beyond each document's current line count
+    fn delete_stale_line_embeddings(&self, line_counts: &HashMap<String, i32>) -> Result<usize> {
+        let retrieval_limit = self
+            .count_line_embeddings()
+            .unwrap_or(DEFAULT_RETRIEVAL_LIMIT);
+        let mut point_ids: Vec<ExtendedPointId> = vec![];
+
+        for (path, count) in line_counts {
+            let (records, _) = self
+                .shards()?.line_embeddings
+                .scroll(ScrollRequestInternal {
+                    offset: None,
+                    order_by: None,
+                    with_vector: WithVector::Bool(false),
+                    with_payload: Some(WithPayloadInterface::Bool(false)),
+                    filter: Some(Filter {
+                        must: Some(vec![
+                            Condition::Field(FieldCondition::new_match(
+                                JsonPath::from_str("path").map_err(|_| {
+                                    anyhow!("An error occurred while creating JSONPath from 'path'")
+                                })?,
+                                Match::new_value(ValueVariants::String(path.clone())),
+                            )),
+                            Condition::Field(FieldCondition::new_range(
+                                JsonPath::from_str("line_number").map_err(|_| {
+                                    anyhow!(
+                                        "An error occurred while creating JSONPath from 'line_number'"
+                                    )
+                                })?,
+                                Range {
+                                    gte: Some(OrderedFloat(*count as f64)),
+                                    ..Default::default()
+                                },
+                            )),
+                        ]),
+                        must_not: None,
+                        should: None,
+                        min_should: None,
+                    }),
+                    limit: Some(retrieval_limit),
+                })
+                .map_err(|e| anyhow!(e.to_string()))?;
+            point_ids.extend(records.into_iter().map(|record| record.id));
+        }
+
+        if point_ids.is_empty() {
+            return Ok(0);
+        }
+
+        let deleted = point_ids.len();
+        let operation = CollectionUpdateOperations::PointOperation(PointOperations::DeletePoints {
+            ids: point_ids,
+        });
+        self.shards()?
+            .line_embeddings
+            .update(operation)
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(deleted)
+    }
+
+    /// Get paths for all stored documents
+    pub fn get_all_document_paths(&self) -> Result<Vec<String>> {
+        let docs_count = self.count_documents();
+        let retrieval_limit = match docs_count {
+            Ok(count) => count,
+            Err(_) => DEFAULT_RETRIEVAL_LIMIT,
+        };
+
+        let scroll_result = self
+            .shards()?
+            .documents
+            .scroll(ScrollRequestInternal {
+                offset: None,
+                order_by: None,
+                with_vector: WithVector::Bool(false),
+                with_payload: Some(WithPayloadInterface::Bool(true)),
+                filter: None,
+                limit: Some(retrieval_limit),
+            })
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        let (records, _) = scroll_result;
+        let mut paths: Vec<String> = vec![];
+
+        for record in records {
+            if let Some(p) = record.payload {
+                let doc_meta = payload_to_doc_meta(&p)?;
+                paths.push(doc_meta.path);
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Number of line embeddings stored for each path
+    pub fn line_embedding_counts(&self) -> Result<HashMap<String, usize>> {
+        let retrieval_limit = self
+            .count_line_embeddings()
+            .unwrap_or(DEFAULT_RETRIEVAL_LIMIT);
+        let (records, _) = self
+            .shards()?
+            .line_embeddings
+            .scroll(ScrollRequestInternal {
+                offset: None,
+                order_by: None,
+                with_vector: WithVector::Bool(false),
+                with_payload: Some(WithPayloadInterface::Bool(true)),
+                filter: None,
+                limit: Some(retrieval_limit),
+            })
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for path in records
+            .into_iter()
+            .filter_map(|record| record.payload.as_ref().and_then(payload_path))
+        {
+            *counts.entry(path).or_default() += 1;
+        }
+        Ok(counts)
+    }
+
+    /// Paths that have a document-level embedding
+    pub fn doc_embedding_paths(&self) -> Result<HashSet<String>> {
+        let shards = self.shards()?;
+        let retrieval_limit = shards
+            .doc_embeddings
+            .count(CountRequestInternal {
+                filter: None,
+                exact: true,
+            })
+            .unwrap_or(DEFAULT_RETRIEVAL_LIMIT);
+        let (records, _) = shards
+            .doc_embeddings
+            .scroll(ScrollRequestInternal {
+                offset: None,
+                order_by: None,
+                with_vector: WithVector::Bool(false),
+                with_payload: Some(WithPayloadInterface::Bool(true)),
+                filter: None,
+                limit: Some(retrieval_limit),
+            })
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(records
+            .into_iter()
+            .filter_map(|record| record.payload.as_ref().and_then(payload_path))
+            .collect())
+    }
+
+    /// Every stored line embedding of `path`, in no particular order
+    pub fn line_vectors(&self, path: &str) -> Result<Vec<Vec<f32>>> {
+        let retrieval_limit = self
+            .count_line_embeddings()
+            .unwrap_or(DEFAULT_RETRIEVAL_LIMIT);
+        let (records, _) = self
+            .shards()?
+            .line_embeddings
+            .scroll(ScrollRequestInternal {
+                offset: None,
+                order_by: None,
+                with_vector: WithVector::Bool(true),
+                with_payload: Some(WithPayloadInterface::Bool(false)),
+                filter: Some(Filter::new_must(Condition::Field(
+                    FieldCondition::new_match(
+                        JsonPath::from_str("path").map_err(|_| {
+                            anyhow!("An error occurred while creating JSONPath from 'path'")
+                        })?,
+                        Match::new_value(ValueVariants::String(path.to_string())),
+                    ),
+                ))),
+                limit: Some(retrieval_limit),
+            })
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| line_vector(record.vector))
+            .collect())
+    }
+
+    /// Like `search_line_embeddings`, optionally returning each line's embedding for re-ranking
+    pub fn search_line_embeddings_with_vectors(
+        &self,
+        query_vec: &[f32],
+        subset_paths: &[String],
+        top_k: usize,
+        max_distance: Option<f32>,
+        with_vectors: bool,
+    ) -> Result<Vec<RankedLine>> {
+        let _timed = telemetry::timed(Operation::StoreQuery, &[("shard", "line_embeddings")]);
+        let shards = self.shards()?;
+        // Short-circuit on empty subsets
+        if subset_paths.is_empty() || top_k == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut all_results: Vec<RankedLine> = vec![];
+
+        for chunk in subset_paths.chunks(1000) {
+            let query: Vec<f32> = query_vec.into();
+            let vector: VectorInternal = query.into();
+            let score_threshold: Option<OrderedFloat<f32>> =
+                max_distance.map(|max_dist| OrderedFloat(1_f32 - max_dist));
+            let results = shards
+                .line_embeddings
+                .query(ShardQueryRequest {
+                    prefetches: vec![],
+                    query: Some(ScoringQuery::Vector(QueryEnum::Nearest(NamedQuery {
+                        query: vector,
+                        using: Some(LINE_EMBEDDINGS_VECTOR_NAME.to_string()),
+                    }))),
+                    filter: Some(Filter::new_must(Condition::Field(
+                        FieldCondition::new_match(
+                            JsonPath::from_str("path").map_err(|_| {
+                                anyhow!("An error occurred while creating JSONPath from 'path'")
+                            })?,
+                            Match::from(AnyVariants::Strings(chunk.iter().cloned().collect())),
+                        ),
+                    ))),
+                    score_threshold,
+                    limit: top_k * 2,
+                    offset: 0,
+                    params: None,
+                    with_vector: WithVector::Bool(with_vectors),
+                    with_payload: WithPayloadInterface::Bool(true),
+                })
+                .map_err(|e| anyhow!(e.to_string()))?;
+
+            for result in results {
+                if let Some(p) = result.payload {
+                    let line_embd = payload_to_line_embedding(&p)?;
+                    let ranked_line = RankedLine {
+                        line_number: line_embd.cited_line(),
+                        path: line_embd.path,
+                        distance: 1_f32 - result.score,
+                        score: 1_f32 - result.score,
+                        embedding: line_vector(result.vector),
+                    };
+                    all_results.push(ranked_line);
+                }
+            }
+        }
+
+        // Ties in path and line order, so the same query always returns the same lines
+        all_results.sort_by(|a, b| {
+            a.distance
+                .partial_cmp(&b.distance)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.path.cmp(&b.path))
+                .then_with(|| a.line_number.cmp(&b.line_number))
+        });
+        all_results.truncate(top_k);
+
+        Ok(all_results)
+    }
+
+    /// Up to `limit` stored line embeddings, in point id order. Ids are hashes of path and line
+    /// number, so this is a sample spread over the whole index.
+    pub fn sample_line_embeddings(&self, limit: usize) -> Result<Vec<Vec<f32>>> {
+        let (records, _) = self
+            .shards()?
+            .line_embeddings
+            .scroll(ScrollRequestInternal {
+                offset: None,
+                order_by: None,
+                with_vector: WithVector::Bool(true),
+                with_payload: Some(WithPayloadInterface::Bool(false)),
+                filter: None,
+                limit: Some(limit),
+            })
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(records
+            .into_iter()
+            .map(|record| line_vector(record.vector))
+            .filter(|embedding| !embedding.is_empty())
+            .collect())
+    }
+
+    /// Stored text of lines `start..end` of `path`, by line number. Lines indexed without text
+    /// (`store_text` off) are missing from the map.
+    pub fn line_texts(
+        &self,
+        path: &str,
+        start: usize,
+        end: usize,
+    ) -> Result<BTreeMap<usize, String>> {
+        let _timed = telemetry::timed(Operation::StoreQuery, &[("shard", "line_embeddings")]);
+        if start >= end {
+            return Ok(BTreeMap::new());
+        }
+        let (records, _) = self
+            .shards()?
+            .line_embeddings
+            .scroll(ScrollRequestInternal {
+                offset: None,
+                order_by: None,
+                with_vector: WithVector::Bool(false),
+                with_payload: Some(WithPayloadInterface::Bool(true)),
+                filter: Some(Filter {
+                    must: Some(vec![
+                        Condition::Field(FieldCondition::new_match(
+                            JsonPath::from_str("path").map_err(|_| {
+                                anyhow!("An error occurred while creating JSONPath from 'path'")
+                            })?,
+                            Match::new_value(ValueVariants::String(path.to_string())),
+                        )),
+                        Condition::Field(FieldCondition::new_range(
+                            JsonPath::from_str("line_number").map_err(|_| {
+                                anyhow!(
+                                    "An error occurred while creating JSONPath from 'line_number'"
+                                )
+                            })?,
+                            Range {
+                                gte: Some(OrderedFloat(start as f64)),
+                                lt: Some(OrderedFloat(end as f64)),
+                                ..Default::default()
+                            },
+                        )),
+                    ]),
+                    must_not: None,
+                    should: None,
+                    min_should: None,
+                }),
+                limit: Some(end - start),
+            })
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        let mut texts = BTreeMap::new();
+        for record in records {
+            let Some(payload) = record.payload.as_ref() else {
+                continue;
+            };
+            let line = payload_to_line_embedding(payload)?;
+            if let Some(text) = line.text {
+                texts.insert(line.line_number as usize, text);
+            }
+        }
+        Ok(texts)
+    }
+
+    /// Get the number of indexed points in the documents shard
+    pub fn count_documents(&self) -> Result<usize> {
+        let count = self
+            .shards()?
+            .documents
+            .count(CountRequestInternal {
+                filter: None,
+                exact: true,
+            })
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(count)
+    }
+
+    /// Get the number of indexed points in the documents shard
+    pub fn count_line_embeddings(&self) -> Result<usize> {
+        let count = self
+            .shards()?
+            .line_embeddings
+            .count(CountRequestInternal {
+                filter: None,
+                exact: true,
+            })
+            .map_err(|e| anyhow!(e.to_string()))?;
+
+        Ok(count)
+    }
+
+    /// Build the payload indexes used for path filtering and flush both shards to disk.
+    /// Returns the number of rows written since the previous optimization.
+    pub fn optimize(&self) -> Result<usize> {
+        let _lock = self.write_lock()?;
+        let rows = self.maintenance_state().rows_since_optimize;
+
+        let shards = self.shards()?;
+        for shard in [
+            &shards.documents,
+            &shards.line_embeddings,
+            &shards.doc_embeddings,
+        ] {
+            let operation = CollectionUpdateOperations::FieldIndexOperation(
+                FieldIndexOperations::CreateIndex(CreateIndex {
+                    field_name: JsonPath::from_str("path").map_err(|_| {
+                        anyhow!("An error occurred while creating JSONPath from 'path'")
+                    })?,
+                    field_schema: Some(PayloadFieldSchema::FieldType(PayloadSchemaType::Keyword)),
+                }),
+            );
+            shard
+                .update(operation)
+                .map_err(|e| anyhow!(e.to_string()))?;
+        }
+        drop(shards);
+
+        self.flush_documents();
+        self.flush_line_embeddings();
+        self.flush_doc_embeddings();
+        self.save_maintenance_state(&MaintenanceState::default())?;
+
+        Ok(rows)
+    }
+
+    /// Flush all documents data to disk.
+    pub fn flush_documents(&self) {
+        if let Some(shards) = self.open_shards() {
+            shards.documents.flush();
+        }
+    }
+
+    /// Flush all line embeddings data to disk.
+    pub fn flush_line_embeddings(&self) {
+        if let Some(shards) = self.open_shards() {
+            shards.line_embeddings.flush();
+        }
+    }
+
+    /// Flush all document embeddings data to disk.
+    pub fn flush_doc_embeddings(&self) {
+        if let Some(shards) = self.open_shards() {
+            shards.doc_embeddings.flush();
+        }
+    }
+}
+
+/// Extract the dense line embedding from a returned point vector (empty if not present)
+fn line_vector(vector: Option<VectorStructInternal>) -> Vec<f32> {
+    match vector {
+        Some(VectorStructInternal::Named(mut named)) => {
+            match named.remove(LINE_EMBEDDINGS_VECTOR_NAME) {
+                Some(VectorInternal::Dense(v)) => v,
+                _ => Vec::new(),
+            }
+        }
+        Some(VectorStructInternal::Single(v)) => v,
+        _ => Vec::new(),
+    }
+}
+
+fn payload_path(payload: &Payload) -> Option<String> {
+    payload
+        .0
+        .get("path")
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string())
+}
+
+/// Create a point struct for upserting.
+fn make_point(
+    id: u64,
+    vector: Vec<f32>,
+    payload: Value,
+    vector_name: &str,
+) -> PointStructPersisted {
+    let mut vectors = HashMap::new();
+    vectors.insert(vector_name.to_string(), VectorInternal::from(vector));
+
+    PointStructPersisted {
+        id: ExtendedPointId::NumId(id),
+        vector: VectorStructInternal::Named(vectors).into(),
+        payload: Some(json_to_payload(payload)),
+    }
+}
+
+/// Convert JSON value (DocMeta or LineEmbedding struct) to Qdrant Payload.
+fn json_to_payload(value: Value) -> Payload {
+    if let Value::Object(map) = value {
+        let mut payload = Payload::default();
+        for (k, v) in map {
+            payload.0.insert(k, v);
+        }
+        payload
+    } else {
+        Payload::default()
+    }
+}
+
+/// Convert Qdrant Payload back to DocMeta
+fn payload_to_doc_meta(payload: &Payload) -> Result<DocMeta> {
+    let json_map: serde_json::Map<String, Value> = payload
+        .0
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+
+    let json_value = Value::Object(json_map);
+    serde_json::from_value(json_value).map_err(|e| anyhow!(e.to_string()))
+}
+
+/// Convert Qdrant Payload back to LineEmbedding
+fn payload_to_line_embedding(payload: &Payload) -> Result<LineEmbedding> {
+    let json_map: serde_json::Map<String, Value> = payload
+        .0
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+
+    let json_value = Value::Object(json_map);
+    serde_json::from_value(json_value).map_err(|e| anyhow!(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::DocumentMetadata;
+    use crate::search::normalize::Normalization;
+    use serde_json::json;
+
+    #[test]
+    fn test_json_to_payload_doc_meta() {
+        let doc_meta = DocMeta {
+            path: "hello.txt".to_string(),
+            size_bytes: 1200_u64,
+            mtime: 1234567890,
+            _version: CURRENT_EMBEDDING_VERSION,
+            tags: BTreeMap::new(),
+            metadata: DocumentMetadata::default(),
+            normalization: Normalization::default(),
+        };
+        let doc_meta_json =
+            serde_json::to_value(doc_meta).expect("Should be able to conver DocMeta to JSON Value");
+        let qdrant_payload = json_to_payload(doc_meta_json);
+        assert!(qdrant_payload.contains_key("path"));
+        assert!(qdrant_payload.contains_key("size_bytes"));
+        assert!(qdrant_payload.contains_key("mtime"));
+        assert!(qdrant_payload.contains_key("_version"));
+        for (k, v) in qdrant_payload.0.iter() {
+            match k.as_str() {
+                "path" => assert_eq!(v, &Value::from("hello.txt")),
+                "size_bytes" => assert_eq!(v, &Value::from(1200)),
+                "mtime" => assert_eq!(v, &Value::from(1234567890)),
+                "_version" => assert_eq!(v, &Value::from(CURRENT_EMBEDDING_VERSION)),
+                _ => panic!("Unexpected key: {}", k),
+            }
+        }
+    }
+
+    #[test]
+    fn test_json_to_payload_line_embedding() {
+        let line_embedding = LineEmbedding {
+            path: "hello.txt".to_string(),
+            line_number: 12,
+            embedding: vec![0.1, 0.3, 0.4, 0.5],
+            text: None,
+            table_line: None,
+        };
+        let doc_meta_json = serde_json::to_value(line_embedding)
+            .expect("Should be able to conver LineEmbedding to JSON Value");
+        let qdrant_payload = json_to_payload(doc_meta_json);
+        assert!(qdrant_payload.contains_key("path"));
+        assert!(qdrant_payload.contains_key("line_number"));
+        assert!(!qdrant_payload.contains_key("embedding"));
+        for (k, v) in qdrant_payload.0.iter() {
+            match k.as_str() {
+                "path" => assert_eq!(v, &Value::from("hello.txt")),
+                "line_number" => assert_eq!(v, &Value::from(12)),
+                _ => panic!("Unexpected key: {}", k),
+            }
+        }
+    }
+
+    #[test]
+    fn test_payload_to_doc_meta() {
+        let json_value = json!({
+            "path": "hello.txt",
+            "size_bytes": 1000_u64,
+            "mtime": 1234567890_i64,
+            "_version": CURRENT_EMBEDDING_VERSION,
+        });
+        let map: serde_json::Map<String, Value> = json_value
+            .as_object()
+            .expect("Should be able to convert JSON value to map")
+            .clone();
+        let payload = Payload::from(map);
+        let doc_meta =
+            payload_to_doc_meta(&payload).expect("Should be able to convert Payload to DocMeta");
+        assert_eq!(doc_meta.path, "hello.txt");
+        assert_eq!(doc_meta.size_bytes, 1000_u64);
+        assert_eq!(doc_meta.mtime, 1234567890_i64);
+        assert_eq!(doc_meta._version, CURRENT_EMBEDDING_VERSION);
+    }
+
+    #[test]
+    fn test_payload_to_line_embedding() {
+        let json_value = json!({
+            "path": "hello.txt",
+            "line_number": 12_i32,
+        });
+        let map: serde_json::Map<String, Value> = json_value
+            .as_object()
+            .expect("Should be able to convert JSON value to map")
+            .clone();
+        let payload = Payload::from(map);
+        let line_embedding = payload_to_line_embedding(&payload)
+            .expect("Should be able to convert Payload to DocMeta");
+        assert_eq!(line_embedding.path, "hello.txt");
+        assert_eq!(line_embedding.line_number, 12_i32);
+        assert!(line_embedding.embedding.is_empty());
+    }
+}